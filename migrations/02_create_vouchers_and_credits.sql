@@ -0,0 +1,57 @@
+-- Migration to create tables for gift vouchers, guardian camp credits and quotes
+
+-- Create guardians table
+CREATE TABLE IF NOT EXISTS guardians (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    email TEXT NOT NULL,
+    name TEXT NOT NULL,
+    stripe_customer_id TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (email)
+);
+
+-- Create vouchers table
+-- status: pending (awaiting payment), issued (code usable), redeemed
+CREATE TABLE IF NOT EXISTS vouchers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    code TEXT,
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    purchaser_name TEXT NOT NULL,
+    purchaser_email TEXT NOT NULL,
+    recipient_email TEXT,
+    payment_intent_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    redeemed_by_guardian_id UUID REFERENCES guardians(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    redeemed_at TIMESTAMP,
+    UNIQUE (code),
+    UNIQUE (payment_intent_id)
+);
+
+-- Create camp_credits ledger table
+-- Positive amounts add credit (voucher redemption), negative amounts spend it (quotes)
+CREATE TABLE IF NOT EXISTS camp_credits (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    voucher_id UUID REFERENCES vouchers(id),
+    quote_id UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_camp_credits_guardian_id ON camp_credits(guardian_id);
+
+-- Create quotes table
+CREATE TABLE IF NOT EXISTS quotes (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    guardian_id UUID REFERENCES guardians(id),
+    currency TEXT NOT NULL,
+    subtotal BIGINT NOT NULL,
+    credit_applied BIGINT NOT NULL DEFAULT 0,
+    total BIGINT NOT NULL,
+    line_items JSONB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,53 @@
+-- Migration to create tables for camp sessions and staff scheduling
+
+-- Create camp_sessions table
+CREATE TABLE IF NOT EXISTS camp_sessions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    starts_on DATE NOT NULL,
+    ends_on DATE NOT NULL,
+    capacity INTEGER NOT NULL,
+    price BIGINT NOT NULL,
+    currency TEXT NOT NULL DEFAULT 'usd',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK (ends_on >= starts_on)
+);
+
+-- Create staff table
+CREATE TABLE IF NOT EXISTS staff (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (email)
+);
+
+-- Create staff_certifications table
+CREATE TABLE IF NOT EXISTS staff_certifications (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    staff_id UUID NOT NULL REFERENCES staff(id),
+    certification TEXT NOT NULL,
+    expires_on DATE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (staff_id, certification)
+);
+
+-- Create role_certifications table listing the certifications each staff role requires
+CREATE TABLE IF NOT EXISTS role_certifications (
+    role TEXT NOT NULL,
+    certification TEXT NOT NULL,
+    PRIMARY KEY (role, certification)
+);
+
+-- Create staff_assignments table
+CREATE TABLE IF NOT EXISTS staff_assignments (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    staff_id UUID NOT NULL REFERENCES staff(id),
+    session_id UUID NOT NULL REFERENCES camp_sessions(id),
+    role TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (staff_id, session_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_staff_assignments_staff_id ON staff_assignments(staff_id);
+CREATE INDEX IF NOT EXISTS idx_staff_assignments_session_id ON staff_assignments(session_id);
@@ -0,0 +1,50 @@
+-- Migration to create tables for API tokens, campers, medical records and medical access logging
+
+-- Create api_tokens table; only the SHA-256 hash of each token is stored
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    token_hash TEXT NOT NULL,
+    role TEXT NOT NULL,
+    subject_id UUID,
+    label TEXT NOT NULL,
+    expires_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (token_hash)
+);
+
+-- Create campers table
+CREATE TABLE IF NOT EXISTS campers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    birthdate DATE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_campers_guardian_id ON campers(guardian_id);
+
+-- Create medical_records table
+CREATE TABLE IF NOT EXISTS medical_records (
+    camper_id UUID PRIMARY KEY REFERENCES campers(id),
+    allergies TEXT,
+    medications TEXT,
+    conditions TEXT,
+    notes TEXT,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create medical_access_log table; rows are never updated or deleted
+CREATE TABLE IF NOT EXISTS medical_access_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    camper_id UUID NOT NULL REFERENCES campers(id),
+    reader_token_id UUID,
+    reader_role TEXT NOT NULL,
+    reader_subject_id UUID,
+    reason TEXT,
+    break_glass BOOLEAN NOT NULL DEFAULT FALSE,
+    accessed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_medical_access_log_accessed_at ON medical_access_log(accessed_at);
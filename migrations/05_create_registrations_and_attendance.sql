@@ -0,0 +1,43 @@
+-- Migration to create tables for registrations, attendance and kiosk sync operations
+
+-- Create registrations table
+-- status: pending, confirmed, cancelled
+CREATE TABLE IF NOT EXISTS registrations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    camper_id UUID NOT NULL REFERENCES campers(id),
+    session_id UUID NOT NULL REFERENCES camp_sessions(id),
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_registrations_session_id ON registrations(session_id);
+CREATE INDEX IF NOT EXISTS idx_registrations_guardian_id ON registrations(guardian_id);
+
+-- Create attendance_events table
+-- kind: check_in, check_out
+CREATE TABLE IF NOT EXISTS attendance_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    registration_id UUID NOT NULL REFERENCES registrations(id),
+    kind TEXT NOT NULL,
+    occurred_at TIMESTAMP NOT NULL,
+    source TEXT NOT NULL,
+    operation_id UUID,
+    recorded_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_attendance_events_registration_id ON attendance_events(registration_id);
+
+-- Create kiosk_operations table recording the outcome of every synced offline operation
+CREATE TABLE IF NOT EXISTS kiosk_operations (
+    operation_id UUID PRIMARY KEY,
+    kiosk_id TEXT NOT NULL,
+    operation_type TEXT NOT NULL,
+    registration_id UUID,
+    client_timestamp TIMESTAMP NOT NULL,
+    outcome TEXT NOT NULL,
+    detail TEXT,
+    applied_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
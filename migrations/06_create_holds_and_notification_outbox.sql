@@ -0,0 +1,34 @@
+-- Migration to create tables for registration capacity holds and the notification outbox
+
+-- Create registration_holds table
+-- A hold reserves a seat in a session until it expires or is released
+CREATE TABLE IF NOT EXISTS registration_holds (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    registration_id UUID NOT NULL REFERENCES registrations(id),
+    session_id UUID NOT NULL REFERENCES camp_sessions(id),
+    expires_at TIMESTAMP NOT NULL,
+    released_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_registration_holds_session_id ON registration_holds(session_id);
+CREATE INDEX IF NOT EXISTS idx_registration_holds_registration_id ON registration_holds(registration_id);
+
+-- Create notification_outbox table
+-- Messages are written in the same transaction as the state change that triggers them
+-- status: pending, sent, skipped, failed
+CREATE TABLE IF NOT EXISTS notification_outbox (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    channel TEXT NOT NULL,
+    target TEXT NOT NULL,
+    template TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    registration_id UUID,
+    payment_intent_id TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_outbox_status ON notification_outbox(status);
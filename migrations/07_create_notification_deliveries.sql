@@ -0,0 +1,20 @@
+-- Migration to create the notification_deliveries table recording every delivery attempt
+
+-- outcome: sent, skipped, failed
+CREATE TABLE IF NOT EXISTS notification_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    outbox_id UUID REFERENCES notification_outbox(id),
+    channel TEXT NOT NULL,
+    target TEXT NOT NULL,
+    template TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    error TEXT,
+    latency_ms INTEGER NOT NULL,
+    attempt INTEGER NOT NULL DEFAULT 1,
+    payment_intent_id TEXT,
+    registration_id UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_deliveries_payment_intent_id ON notification_deliveries(payment_intent_id);
+CREATE INDEX IF NOT EXISTS idx_notification_deliveries_registration_id ON notification_deliveries(registration_id);
@@ -0,0 +1,10 @@
+-- Migration to create the exchange_rates table caching one rate per currency pair per day
+
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    rate_date DATE NOT NULL,
+    base_currency TEXT NOT NULL,
+    quote_currency TEXT NOT NULL,
+    rate DOUBLE PRECISION NOT NULL,
+    fetched_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (rate_date, base_currency, quote_currency)
+);
@@ -0,0 +1,17 @@
+-- Migration to create the export_jobs table for asynchronous report exports
+
+-- status: queued, running, completed, failed
+CREATE TABLE IF NOT EXISTS export_jobs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind TEXT NOT NULL,
+    params JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    s3_key TEXT,
+    error TEXT,
+    requested_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMP,
+    completed_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_export_jobs_status ON export_jobs(status);
@@ -0,0 +1,23 @@
+-- Migration to create tables for ad-hoc tags
+
+-- Create tags table; names are stored trimmed and lowercased
+CREATE TABLE IF NOT EXISTS tags (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (name)
+);
+
+-- Create taggings table linking tags to any taggable record
+-- taggable_type: registration
+CREATE TABLE IF NOT EXISTS taggings (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tag_id UUID NOT NULL REFERENCES tags(id),
+    taggable_type TEXT NOT NULL,
+    taggable_id UUID NOT NULL,
+    created_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (tag_id, taggable_type, taggable_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_taggings_taggable ON taggings(taggable_type, taggable_id);
@@ -0,0 +1,7 @@
+-- Migration to track payment intents and expiry notifications on registration holds
+
+ALTER TABLE registration_holds ADD COLUMN IF NOT EXISTS payment_intent_id TEXT;
+ALTER TABLE registration_holds ADD COLUMN IF NOT EXISTS warned_at TIMESTAMP;
+ALTER TABLE registration_holds ADD COLUMN IF NOT EXISTS expiry_notified_at TIMESTAMP;
+
+CREATE INDEX IF NOT EXISTS idx_registration_holds_expires_at ON registration_holds(expires_at) WHERE released_at IS NULL;
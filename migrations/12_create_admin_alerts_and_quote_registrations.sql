@@ -0,0 +1,17 @@
+-- Migration to link quotes to the registrations they price and to record admin alerts
+
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS registration_ids UUID[] NOT NULL DEFAULT '{}';
+
+-- Create admin_alerts table
+CREATE TABLE IF NOT EXISTS admin_alerts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind TEXT NOT NULL,
+    message TEXT NOT NULL,
+    details JSONB NOT NULL,
+    payment_intent_id TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    acknowledged_at TIMESTAMP,
+    acknowledged_by UUID
+);
+
+CREATE INDEX IF NOT EXISTS idx_admin_alerts_created_at ON admin_alerts(created_at);
@@ -0,0 +1,16 @@
+-- Migration to let guardians share a pending registration with someone else to pay for
+
+-- Create delegated_links table
+CREATE TABLE IF NOT EXISTS delegated_links (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    token_hash TEXT NOT NULL UNIQUE,
+    registration_id UUID NOT NULL REFERENCES registrations(id),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    recipient_name TEXT,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    payment_intent_id TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_delegated_links_registration_id ON delegated_links(registration_id);
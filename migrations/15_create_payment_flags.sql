@@ -0,0 +1,19 @@
+-- Migration to record failed refunds and payouts for reconciliation
+
+ALTER TABLE refunds ADD COLUMN IF NOT EXISTS failure_reason TEXT;
+
+-- Create payment_flags table
+CREATE TABLE IF NOT EXISTS payment_flags (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind TEXT NOT NULL,
+    stripe_object_id TEXT NOT NULL,
+    payment_intent_id TEXT,
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    details JSONB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (kind, stripe_object_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_payment_flags_payment_intent_id ON payment_flags(payment_intent_id);
+CREATE INDEX IF NOT EXISTS idx_payment_flags_created_at ON payment_flags(created_at);
@@ -0,0 +1,35 @@
+-- Migration to support cancelling a whole session and refunding its families
+
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS cancelled_at TIMESTAMP;
+
+-- Create session_cancellations table
+CREATE TABLE IF NOT EXISTS session_cancellations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    session_id UUID NOT NULL UNIQUE REFERENCES camp_sessions(id),
+    reason TEXT NOT NULL,
+    requested_by UUID,
+    status TEXT NOT NULL DEFAULT 'in_progress',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMP
+);
+
+-- Create cancellation_refunds table
+CREATE TABLE IF NOT EXISTS cancellation_refunds (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    cancellation_id UUID NOT NULL REFERENCES session_cancellations(id),
+    registration_id UUID NOT NULL UNIQUE REFERENCES registrations(id),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    payment_intent_id TEXT,
+    amount BIGINT NOT NULL,
+    credit_amount BIGINT NOT NULL DEFAULT 0,
+    currency TEXT NOT NULL,
+    status TEXT NOT NULL,
+    stripe_refund_id TEXT,
+    error TEXT,
+    attempts INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_cancellation_refunds_cancellation_id ON cancellation_refunds(cancellation_id);
+CREATE INDEX IF NOT EXISTS idx_cancellation_refunds_status ON cancellation_refunds(status);
@@ -0,0 +1,16 @@
+-- Migration to record request counts per client for usage reporting
+
+-- Create api_usage table
+CREATE TABLE IF NOT EXISTS api_usage (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    hour TIMESTAMP NOT NULL,
+    frontend_id TEXT NOT NULL,
+    api_key TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    client_errors BIGINT NOT NULL DEFAULT 0,
+    server_errors BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (hour, frontend_id, api_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_usage_hour ON api_usage(hour);
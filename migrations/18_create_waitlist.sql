@@ -0,0 +1,29 @@
+-- Migration to add session waitlists and their wait-time estimates
+
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS session_type TEXT NOT NULL DEFAULT 'general';
+
+-- Create waitlist_entries table
+CREATE TABLE IF NOT EXISTS waitlist_entries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    session_id UUID NOT NULL REFERENCES camp_sessions(id),
+    camper_id UUID NOT NULL REFERENCES campers(id),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    status TEXT NOT NULL DEFAULT 'waiting',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    promoted_at TIMESTAMP,
+    UNIQUE (session_id, camper_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_session_id ON waitlist_entries(session_id);
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_guardian_id ON waitlist_entries(guardian_id);
+
+-- Create waitlist_estimates table
+CREATE TABLE IF NOT EXISTS waitlist_estimates (
+    session_type TEXT PRIMARY KEY,
+    sample_sessions INT NOT NULL,
+    joined BIGINT NOT NULL,
+    promoted BIGINT NOT NULL,
+    promotions_per_session JSONB NOT NULL,
+    median_days_to_promotion DOUBLE PRECISION,
+    computed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
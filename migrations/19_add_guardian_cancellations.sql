@@ -0,0 +1,4 @@
+-- Migration to let guardians cancel single registrations through the refund pipeline
+
+ALTER TABLE cancellation_refunds ALTER COLUMN cancellation_id DROP NOT NULL;
+ALTER TABLE cancellation_refunds ADD COLUMN IF NOT EXISTS cause TEXT NOT NULL DEFAULT 'camp';
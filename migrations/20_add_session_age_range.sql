@@ -0,0 +1,4 @@
+-- Migration to restrict sessions to an age range
+
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS min_age INT;
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS max_age INT;
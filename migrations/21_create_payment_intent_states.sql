@@ -0,0 +1,11 @@
+-- Migration to track the latest applied webhook event per payment intent
+
+-- Create payment_intent_states table
+CREATE TABLE IF NOT EXISTS payment_intent_states (
+    payment_intent_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    last_event_id TEXT NOT NULL,
+    last_event_type TEXT NOT NULL,
+    last_event_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
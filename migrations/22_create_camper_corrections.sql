@@ -0,0 +1,26 @@
+-- Migration to let guardians request corrections to camper details
+
+-- Create camper_corrections table
+CREATE TABLE IF NOT EXISTS camper_corrections (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    camper_id UUID NOT NULL REFERENCES campers(id),
+    guardian_id UUID NOT NULL REFERENCES guardians(id),
+    first_name TEXT,
+    last_name TEXT,
+    birthdate DATE,
+    reason TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    previous_first_name TEXT,
+    previous_last_name TEXT,
+    previous_birthdate DATE,
+    reviewed_by UUID,
+    reviewer_role TEXT,
+    review_note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    reviewed_at TIMESTAMP
+);
+
+-- One open request per camper at a time
+CREATE UNIQUE INDEX IF NOT EXISTS idx_camper_corrections_pending
+    ON camper_corrections(camper_id) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_camper_corrections_status ON camper_corrections(status, created_at);
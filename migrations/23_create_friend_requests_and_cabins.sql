@@ -0,0 +1,23 @@
+-- Migration to capture cabinmate requests and record cabin assignments
+
+-- Create friend_requests table
+CREATE TABLE IF NOT EXISTS friend_requests (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    registration_id UUID NOT NULL REFERENCES registrations(id),
+    friend_name TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (registration_id, friend_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_friend_requests_registration_id ON friend_requests(registration_id);
+
+-- Create cabin_assignments table
+CREATE TABLE IF NOT EXISTS cabin_assignments (
+    registration_id UUID PRIMARY KEY REFERENCES registrations(id),
+    session_id UUID NOT NULL REFERENCES camp_sessions(id),
+    cabin TEXT NOT NULL,
+    assigned_by UUID,
+    assigned_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_cabin_assignments_session_id ON cabin_assignments(session_id);
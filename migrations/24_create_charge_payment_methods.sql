@@ -0,0 +1,14 @@
+-- Migration to record the payment method type of each successful charge
+
+-- Create charge_payment_methods table
+CREATE TABLE IF NOT EXISTS charge_payment_methods (
+    charge_id TEXT PRIMARY KEY,
+    payment_intent_id TEXT,
+    method_type TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    succeeded_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_charge_payment_methods_succeeded_at ON charge_payment_methods(succeeded_at);
@@ -0,0 +1,3 @@
+-- Migration to keep guardians' billing addresses for tax receipts
+
+ALTER TABLE guardians ADD COLUMN IF NOT EXISTS billing_address JSONB;
@@ -0,0 +1,5 @@
+-- Migration to cancel sessions that miss a minimum enrollment by a cutoff date
+
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS min_enrollment INT;
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS enrollment_cutoff DATE;
+ALTER TABLE camp_sessions ADD COLUMN IF NOT EXISTS enrollment_flagged_at TIMESTAMP;
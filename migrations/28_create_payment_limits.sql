@@ -0,0 +1,29 @@
+-- Migration to enforce payment amount limits and per-customer velocity checks
+
+-- Create payment_sheet_attempts table, one row per PaymentIntent created for a customer
+CREATE TABLE IF NOT EXISTS payment_sheet_attempts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    customer_email TEXT NOT NULL,
+    payment_intent_id TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_payment_sheet_attempts_customer
+    ON payment_sheet_attempts(customer_email, created_at);
+
+-- Create payment_limit_overrides table for admin-granted exceptions
+CREATE TABLE IF NOT EXISTS payment_limit_overrides (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    customer_email TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    max_amount BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    created_by UUID,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_payment_limit_overrides_customer
+    ON payment_limit_overrides(customer_email, currency, expires_at);
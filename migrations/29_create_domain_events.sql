@@ -0,0 +1,33 @@
+-- Migration to keep one chronological log of domain events across subsystems
+
+-- Create domain_events table; rows are only ever inserted
+CREATE TABLE IF NOT EXISTS domain_events (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    actor_role TEXT NOT NULL,
+    actor_id UUID,
+    camper_id UUID,
+    guardian_id UUID,
+    session_id UUID,
+    registration_id UUID,
+    payment_intent_id TEXT,
+    details JSONB NOT NULL DEFAULT '{}'::jsonb,
+    occurred_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_domain_events_camper ON domain_events(camper_id, id);
+CREATE INDEX IF NOT EXISTS idx_domain_events_guardian ON domain_events(guardian_id, id);
+CREATE INDEX IF NOT EXISTS idx_domain_events_session ON domain_events(session_id, id);
+
+-- Reject updates and deletes so the log stays append-only
+CREATE OR REPLACE FUNCTION reject_domain_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'domain_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS domain_events_append_only ON domain_events;
+CREATE TRIGGER domain_events_append_only
+    BEFORE UPDATE OR DELETE ON domain_events
+    FOR EACH ROW EXECUTE FUNCTION reject_domain_event_changes();
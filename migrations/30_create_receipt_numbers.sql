@@ -0,0 +1,20 @@
+-- Migration to give successful payments gap-free sequential receipt numbers
+
+-- Create receipt_sequences table, the next number per organization
+CREATE TABLE IF NOT EXISTS receipt_sequences (
+    organization TEXT PRIMARY KEY,
+    next_number BIGINT NOT NULL DEFAULT 1,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Create receipt_numbers table, one number per successful payment
+CREATE TABLE IF NOT EXISTS receipt_numbers (
+    payment_intent_id TEXT PRIMARY KEY,
+    organization TEXT NOT NULL,
+    number BIGINT NOT NULL,
+    receipt_number TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    issued_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (organization, number)
+);
@@ -0,0 +1,20 @@
+-- Migration to track date-driven notifications and guardian opt-outs
+
+-- Create notification_rule_sends table; one row per rule, subject and occasion
+-- so the scheduled job sends each message once
+CREATE TABLE IF NOT EXISTS notification_rule_sends (
+    rule TEXT NOT NULL,
+    subject_id UUID NOT NULL,
+    occasion TEXT NOT NULL,
+    outbox_message_id UUID NOT NULL,
+    sent_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (rule, subject_id, occasion)
+);
+
+-- Create notification_opt_outs table, the rules a guardian does not want
+CREATE TABLE IF NOT EXISTS notification_opt_outs (
+    guardian_id UUID NOT NULL REFERENCES guardians(id) ON DELETE CASCADE,
+    rule TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (guardian_id, rule)
+);
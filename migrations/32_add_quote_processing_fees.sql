@@ -0,0 +1,3 @@
+-- Migration to record the processing fee passed on to the payer in each quote
+
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS processing_fee BIGINT NOT NULL DEFAULT 0;
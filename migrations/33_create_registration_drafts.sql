@@ -0,0 +1,19 @@
+-- Migration to keep partially completed registration forms server-side
+
+-- Create registration_drafts table; a draft becomes a registration at checkout
+CREATE TABLE IF NOT EXISTS registration_drafts (
+    id UUID PRIMARY KEY,
+    guardian_id UUID NOT NULL REFERENCES guardians(id) ON DELETE CASCADE,
+    camper_id UUID REFERENCES campers(id) ON DELETE SET NULL,
+    session_id UUID REFERENCES camp_sessions(id) ON DELETE SET NULL,
+    friend_requests TEXT[] NOT NULL DEFAULT '{}',
+    answers JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL,
+    registration_id UUID REFERENCES registrations(id) ON DELETE SET NULL,
+    converted_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_registration_drafts_guardian ON registration_drafts(guardian_id);
+CREATE INDEX IF NOT EXISTS idx_registration_drafts_expires_at ON registration_drafts(expires_at) WHERE converted_at IS NULL;
@@ -0,0 +1,10 @@
+-- Migration to count received Stripe webhook events by type
+
+-- Create webhook_event_counts table; one row per event type ever received
+CREATE TABLE IF NOT EXISTS webhook_event_counts (
+    event_type TEXT PRIMARY KEY,
+    received BIGINT NOT NULL DEFAULT 0,
+    filtered BIGINT NOT NULL DEFAULT 0,
+    first_received_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_received_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,18 @@
+-- Migration to track support tickets opened for families with failing payments
+
+-- Create support_tickets table; at most one ticket per rule and family within the
+-- dedup window, unless opening it failed
+CREATE TABLE IF NOT EXISTS support_tickets (
+    id UUID PRIMARY KEY,
+    rule TEXT NOT NULL,
+    family TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    payment_intent_ids TEXT[] NOT NULL DEFAULT '{}',
+    status TEXT NOT NULL DEFAULT 'pending',
+    reference TEXT,
+    error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_support_tickets_rule_family ON support_tickets(rule, family, created_at);
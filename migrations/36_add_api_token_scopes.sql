@@ -0,0 +1,3 @@
+-- Migration to add scopes that narrow what an API token may do beyond its role
+
+ALTER TABLE api_tokens ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL DEFAULT '{}';
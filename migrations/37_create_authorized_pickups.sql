@@ -0,0 +1,20 @@
+-- Migration to record the adults allowed to pick each camper up, and who did
+
+-- Create authorized_pickups table; removed adults are kept so past check-outs
+-- still name them
+CREATE TABLE IF NOT EXISTS authorized_pickups (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    registration_id UUID NOT NULL REFERENCES registrations(id),
+    name TEXT NOT NULL,
+    relationship TEXT NOT NULL,
+    photo_url TEXT,
+    id_note TEXT,
+    added_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    removed_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_authorized_pickups_registration_id ON authorized_pickups(registration_id);
+
+-- Record the adult each camper was checked out to
+ALTER TABLE attendance_events ADD COLUMN IF NOT EXISTS picked_up_by UUID REFERENCES authorized_pickups(id);
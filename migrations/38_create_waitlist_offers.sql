@@ -0,0 +1,18 @@
+-- Migration to offer freed seats to waitlisted families through payment links
+
+-- Create waitlist_offers table; an offer holds a pending registration's seat until
+-- its hold expires
+CREATE TABLE IF NOT EXISTS waitlist_offers (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    waitlist_entry_id UUID NOT NULL REFERENCES waitlist_entries(id),
+    registration_id UUID NOT NULL REFERENCES registrations(id),
+    hold_id UUID NOT NULL REFERENCES registration_holds(id),
+    token_hash TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL DEFAULT 'open',
+    expires_at TIMESTAMP NOT NULL,
+    payment_intent_id TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_waitlist_offers_open ON waitlist_offers(expires_at) WHERE status = 'open';
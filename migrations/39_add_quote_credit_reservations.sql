@@ -0,0 +1,13 @@
+-- Migration to reserve camp credit on a quote until its payment succeeds, instead of
+-- debiting it when the quote is created
+
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS credit_status TEXT NOT NULL DEFAULT 'none';
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS credit_expires_at TIMESTAMP;
+
+-- Credit on earlier quotes was debited when they were created
+UPDATE quotes SET credit_status = 'redeemed'
+WHERE credit_applied > 0 AND credit_status = 'none';
+
+CREATE INDEX IF NOT EXISTS quotes_reserved_credit_idx
+    ON quotes (guardian_id, credit_expires_at)
+    WHERE credit_status = 'reserved';
@@ -0,0 +1,9 @@
+-- Migration to record which Stripe event each payment event came from, so a redelivered
+-- event is stored once and its side effects are not applied twice
+
+ALTER TABLE payment_events ADD COLUMN IF NOT EXISTS stripe_event_id TEXT;
+
+-- Rows written outside the webhook (PaymentSheet creation) have no event id, and NULLs
+-- do not conflict with each other
+CREATE UNIQUE INDEX IF NOT EXISTS payment_events_stripe_event_id_idx
+    ON payment_events (stripe_event_id);
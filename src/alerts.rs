@@ -0,0 +1,102 @@
+//! Admin alerts.
+//!
+//! Alerts are persisted to `admin_alerts` (so they can be reviewed and acknowledged)
+//! and pushed to Slack through the incoming webhook at `SLACK_WEBHOOK_URL` when set.
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{AdminAlert, NewAdminAlert},
+};
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// Persists an alert. Call inside the transaction that detected the problem.
+pub fn raise(
+    conn: &mut PgConnection,
+    kind: &str,
+    message: String,
+    details: Value,
+    payment_intent_id: Option<String>,
+) -> Result<AdminAlert, diesel::result::Error> {
+    warn!("Admin alert [{kind}]: {message}");
+    diesel::insert_into(crate::database::schema::admin_alerts::table)
+        .values(&NewAdminAlert {
+            id: Uuid::new_v4(),
+            kind: kind.to_string(),
+            message,
+            details,
+            payment_intent_id,
+        })
+        .get_result::<AdminAlert>(conn)
+}
+
+/// Posts an alert to Slack. Best-effort: failures are logged.
+pub async fn notify_slack(alert: &AdminAlert) {
+    let Ok(webhook_url) = std::env::var("SLACK_WEBHOOK_URL") else {
+        return;
+    };
+    let text = format!(":rotating_light: *{}*: {}", alert.kind, alert.message);
+    match reqwest::Client::new()
+        .post(webhook_url)
+        .json(&json!({ "text": text }))
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => error!("Slack notifier returned {}", response.status()),
+        Err(e) => error!("Slack notifier request failed: {e}"),
+    }
+}
+
+/// GET /admin/alerts lists unacknowledged alerts, newest first.
+#[tracing::instrument(skip(state))]
+pub async fn list_alerts_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::admin_alerts::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let alerts = admin_alerts
+        .filter(acknowledged_at.is_null())
+        .order(created_at.desc())
+        .limit(200)
+        .load::<AdminAlert>(&mut conn)
+        .map_err(db_error("Failed to load alerts"))?;
+    Ok(axum::Json(json!({ "alerts": alerts })))
+}
+
+/// POST /admin/alerts/{id}/acknowledge marks an alert as handled.
+#[tracing::instrument(skip(state))]
+pub async fn acknowledge_alert_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(alert_id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    use crate::database::schema::admin_alerts::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let updated = diesel::update(
+        admin_alerts
+            .find(alert_id)
+            .filter(acknowledged_at.is_null()),
+    )
+    .set((
+        acknowledged_at.eq(Some(chrono::Utc::now().naive_utc())),
+        acknowledged_by.eq(actor.subject_id),
+    ))
+    .execute(&mut conn)
+    .map_err(db_error("Failed to acknowledge alert"))?;
+    if updated == 0 {
+        return Err((StatusCode::NOT_FOUND, "Alert not found".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,116 @@
+//! Anonymization rules for the analytics datasets.
+//!
+//! Every rule the anonymized exports apply lives here, so what leaves the database
+//! can be reviewed (and tested) in one place:
+//!
+//! - Ids of people and payments become pseudonyms: a keyed SHA-256 of the id,
+//!   salted with `ANONYMIZATION_KEY`. The same id always maps to the same pseudonym,
+//!   so datasets join across days, but without the key the id cannot be recovered or
+//!   confirmed by hashing guesses. Each kind of id gets its own namespace, so a
+//!   camper and a guardian never share a pseudonym.
+//! - Birthdates become age buckets, computed on the session's first day.
+//! - Timestamps are cut to the day.
+//! - Free text (names, emails, notes, tags, frontend ids and any payment metadata
+//!   not declared in [`crate::payment_metadata`]) is dropped.
+use crate::payment_metadata::PaymentMetadata;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+
+/// Shortest accepted `ANONYMIZATION_KEY`, in bytes.
+const MIN_KEY_LEN: usize = 32;
+
+/// Pseudonym namespaces.
+pub const REGISTRATION: &str = "registration";
+pub const GUARDIAN: &str = "guardian";
+pub const CAMPER: &str = "camper";
+pub const PAYMENT: &str = "payment";
+pub const CUSTOMER: &str = "customer";
+
+/// Upper bounds (inclusive) of the age buckets, with their labels.
+const AGE_BUCKETS: &[(u32, &str)] = &[
+    (5, "under 6"),
+    (8, "6-8"),
+    (11, "9-11"),
+    (14, "12-14"),
+    (17, "15-17"),
+];
+const OLDEST_BUCKET: &str = "18+";
+
+/// Applies the anonymization rules with one key.
+pub struct Anonymizer {
+    key: Vec<u8>,
+}
+
+impl Anonymizer {
+    pub fn new(key: impl Into<Vec<u8>>) -> Result<Self, String> {
+        let key = key.into();
+        if key.len() < MIN_KEY_LEN {
+            return Err(format!(
+                "ANONYMIZATION_KEY must be at least {MIN_KEY_LEN} bytes"
+            ));
+        }
+        Ok(Self { key })
+    }
+
+    /// Loads the key from `ANONYMIZATION_KEY`. Rotating it breaks joins with
+    /// datasets exported under the old key.
+    pub fn from_env() -> Result<Self, String> {
+        let key = env::var("ANONYMIZATION_KEY")
+            .map_err(|_| "ANONYMIZATION_KEY must be set".to_string())?;
+        Self::new(key.trim())
+    }
+
+    /// The stable pseudonym for an id of the given kind.
+    pub fn pseudonym(&self, kind: &str, id: impl Display) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.key);
+        hasher.update([0]);
+        hasher.update(kind.as_bytes());
+        hasher.update([0]);
+        hasher.update(id.to_string().as_bytes());
+        hex::encode(&hasher.finalize()[..16])
+    }
+
+    /// Pseudonyms for the registrations a payment's metadata names, keeping only
+    /// the declared metadata fields that carry no free text.
+    pub fn payment_metadata(&self, metadata: Option<&Value>) -> AnonymizedMetadata {
+        let raw: HashMap<String, String> = metadata
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default();
+        let parsed = PaymentMetadata::parse(&raw).unwrap_or_default();
+        AnonymizedMetadata {
+            purpose: parsed.purpose,
+            registrations: parsed
+                .registration_ids
+                .iter()
+                .map(|id| self.pseudonym(REGISTRATION, id))
+                .collect(),
+        }
+    }
+}
+
+/// What an anonymized dataset keeps of a payment's metadata.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AnonymizedMetadata {
+    pub purpose: Option<String>,
+    pub registrations: Vec<String>,
+}
+
+/// The age bucket for a birthdate on a given day.
+pub fn age_bucket(birthdate: NaiveDate, on: NaiveDate) -> &'static str {
+    let age = on.years_since(birthdate).unwrap_or(0);
+    AGE_BUCKETS
+        .iter()
+        .find(|(upper, _)| age <= *upper)
+        .map(|(_, label)| *label)
+        .unwrap_or(OLDEST_BUCKET)
+}
+
+/// A timestamp cut to its day.
+pub fn day(timestamp: NaiveDateTime) -> NaiveDate {
+    timestamp.date()
+}
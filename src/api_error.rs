@@ -0,0 +1,137 @@
+//! Machine-readable errors for the registration, pricing and payment handlers.
+//!
+//! These handlers answer failures with the same JSON envelope as the webhook:
+//! `{"error": CODE, "message": text}`. The code is an [`ErrorCode`] that frontends
+//! branch on; the message is for people and may change. Codes are stable: rename
+//! one only with a new code alongside it.
+//!
+//! Shared helpers still return `(StatusCode, String)`, which converts into an
+//! [`ApiError`] with the generic code for its status, so `?` works in both
+//! directions while handlers move over.
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use stripe::{ErrorType, StripeError};
+
+/// Reason a request failed, for frontends to branch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The session has no seats left.
+    SessionFull,
+    /// The session was cancelled by the camp.
+    SessionCancelled,
+    /// The camper's age on the first day is outside the session's range.
+    AgeIneligible,
+    /// The registration is not pending, so it cannot be priced or paid.
+    RegistrationNotPayable,
+    /// A voucher (promo code) is past its validity period.
+    PromoExpired,
+    /// A voucher was already redeemed or is not issued yet.
+    PromoUnavailable,
+    /// The currency is not accepted.
+    UnsupportedCurrency,
+    /// The session is priced in a different currency from the checkout.
+    CurrencyMismatch,
+    /// The card was declined by the issuer or failed Stripe's checks.
+    PaymentDeclined,
+    /// Stripe could not be reached or rejected the request.
+    PaymentProviderError,
+    /// A shared payment link was revoked or has expired.
+    LinkExpired,
+    /// The amount is below the currency's minimum payment.
+    AmountBelowMinimum,
+    /// The amount is above the currency's maximum payment.
+    AmountAboveMaximum,
+    /// The customer created too many PaymentIntents in the last hour.
+    TooManyPaymentAttempts,
+    /// The customer's payments in the last 24 hours would exceed the daily limit.
+    DailyLimitExceeded,
+    InvalidRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    InternalError,
+}
+
+impl ErrorCode {
+    /// The generic code for a status, used for errors without a specific code.
+    pub fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            status if status.is_client_error() => ErrorCode::InvalidRequest,
+            _ => ErrorCode::InternalError,
+        }
+    }
+}
+
+/// An error response carrying a status, a code and a message.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Maps a Stripe failure: card errors are declines the payer can fix, anything
+    /// else is a provider error.
+    pub fn from_stripe(context: &str, error: &StripeError) -> Self {
+        match error {
+            StripeError::Stripe(request) if matches!(request.error_type, ErrorType::Card) => {
+                Self::new(
+                    StatusCode::PAYMENT_REQUIRED,
+                    ErrorCode::PaymentDeclined,
+                    request
+                        .message
+                        .clone()
+                        .unwrap_or_else(|| "The card was declined".to_string()),
+                )
+            }
+            _ => Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::PaymentProviderError,
+                format!("{context}: {error:?}"),
+            ),
+        }
+    }
+
+    /// The envelope sent as the response body.
+    pub fn body(&self) -> Value {
+        json!({ "error": self.code, "message": self.message })
+    }
+}
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self::new(status, ErrorCode::for_status(status), message)
+    }
+}
+
+impl From<ApiError> for (StatusCode, String) {
+    fn from(error: ApiError) -> Self {
+        (error.status, error.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
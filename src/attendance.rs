@@ -0,0 +1,179 @@
+use crate::database::models::{AttendanceEvent, NewAttendanceEvent, Registration};
+use chrono::{NaiveDateTime, NaiveTime};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttendanceKind {
+    CheckIn,
+    CheckOut,
+}
+
+impl AttendanceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttendanceKind::CheckIn => "check_in",
+            AttendanceKind::CheckOut => "check_out",
+        }
+    }
+}
+
+/// Why an attendance event could not be recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttendanceRejection {
+    RegistrationNotFound,
+    NotConfirmed(String),
+    AlreadyCheckedIn,
+    NotCheckedIn,
+    /// A later event the same day is of the same kind, so applying this one would
+    /// leave two check-ins or two check-outs in a row.
+    OutOfSequence(AttendanceKind),
+    /// Check-outs must name the adult picking the camper up.
+    PickupRequired,
+    /// The adult is not on the registration's authorized pickup list.
+    PickupNotAuthorized,
+}
+
+impl fmt::Display for AttendanceRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttendanceRejection::RegistrationNotFound => write!(f, "Registration not found"),
+            AttendanceRejection::NotConfirmed(status) => {
+                write!(f, "Registration is {status}, not confirmed")
+            }
+            AttendanceRejection::AlreadyCheckedIn => write!(f, "Camper is already checked in"),
+            AttendanceRejection::NotCheckedIn => write!(f, "Camper is not checked in"),
+            AttendanceRejection::OutOfSequence(kind) => {
+                write!(f, "A later {} was already recorded that day", kind.as_str())
+            }
+            AttendanceRejection::PickupRequired => {
+                write!(f, "Check-out requires an authorized pickup")
+            }
+            AttendanceRejection::PickupNotAuthorized => {
+                write!(f, "Pickup is not authorized for this camper")
+            }
+        }
+    }
+}
+
+/// Where an attendance event came from and who recorded it.
+#[derive(Debug, Clone, Copy)]
+pub struct AttendanceInput<'a> {
+    /// `qr` or `kiosk`.
+    pub source: &'a str,
+    /// The kiosk's client-generated id, for events replayed through `POST /sync`.
+    pub operation_id: Option<Uuid>,
+    pub recorded_by: Option<Uuid>,
+    /// The authorized pickup collecting the camper; required for check-outs.
+    pub picked_up_by: Option<Uuid>,
+}
+
+/// Records a check-in or check-out at `occurred_at`.
+///
+/// The camper's state at `occurred_at` is derived from the latest earlier event on
+/// the same day, so late-arriving (offline) events are validated against what had
+/// happened at the time rather than against the current state. A late event is also
+/// checked against the next event that day: one that would leave two check-ins or
+/// two check-outs in a row is rejected, and the event already recorded stands.
+///
+/// Check-outs must name an adult on the registration's authorized pickup list who had
+/// not been removed by `occurred_at`; the adult is stored with the event as the day's
+/// pickup log.
+pub fn record_attendance(
+    conn: &mut PgConnection,
+    registration: Uuid,
+    event_kind: AttendanceKind,
+    at: NaiveDateTime,
+    input: AttendanceInput,
+) -> Result<Result<AttendanceEvent, AttendanceRejection>, diesel::result::Error> {
+    use crate::database::schema::attendance_events::dsl::*;
+
+    let registration_row = crate::database::schema::registrations::table
+        .find(registration)
+        .for_update()
+        .first::<Registration>(conn)
+        .optional()?;
+    let Some(registration_row) = registration_row else {
+        return Ok(Err(AttendanceRejection::RegistrationNotFound));
+    };
+    if registration_row.status != "confirmed" {
+        return Ok(Err(AttendanceRejection::NotConfirmed(
+            registration_row.status,
+        )));
+    }
+
+    let day_start = at.date().and_time(NaiveTime::MIN);
+    let day_end = day_start + chrono::Duration::days(1);
+    let previous = attendance_events
+        .filter(registration_id.eq(registration))
+        .filter(occurred_at.ge(day_start))
+        .filter(occurred_at.le(at))
+        .order(occurred_at.desc())
+        .select(kind)
+        .first::<String>(conn)
+        .optional()?;
+    let next = attendance_events
+        .filter(registration_id.eq(registration))
+        .filter(occurred_at.gt(at))
+        .filter(occurred_at.lt(day_end))
+        .order(occurred_at.asc())
+        .select(kind)
+        .first::<String>(conn)
+        .optional()?;
+    let checked_in = previous.as_deref() == Some(AttendanceKind::CheckIn.as_str());
+
+    match event_kind {
+        AttendanceKind::CheckIn if checked_in => {
+            return Ok(Err(AttendanceRejection::AlreadyCheckedIn))
+        }
+        AttendanceKind::CheckOut if !checked_in => {
+            return Ok(Err(AttendanceRejection::NotCheckedIn))
+        }
+        _ => {}
+    }
+    if next.as_deref() == Some(event_kind.as_str()) {
+        return Ok(Err(AttendanceRejection::OutOfSequence(event_kind)));
+    }
+
+    let pickup = match event_kind {
+        AttendanceKind::CheckIn => None,
+        AttendanceKind::CheckOut => {
+            use crate::database::schema::authorized_pickups;
+
+            let Some(pickup) = input.picked_up_by else {
+                return Ok(Err(AttendanceRejection::PickupRequired));
+            };
+            let authorized = diesel::select(diesel::dsl::exists(
+                authorized_pickups::table
+                    .filter(authorized_pickups::id.eq(pickup))
+                    .filter(authorized_pickups::registration_id.eq(registration))
+                    .filter(
+                        authorized_pickups::removed_at
+                            .is_null()
+                            .or(authorized_pickups::removed_at.gt(at)),
+                    ),
+            ))
+            .get_result::<bool>(conn)?;
+            if !authorized {
+                return Ok(Err(AttendanceRejection::PickupNotAuthorized));
+            }
+            Some(pickup)
+        }
+    };
+
+    let event = diesel::insert_into(attendance_events)
+        .values(&NewAttendanceEvent {
+            registration_id: registration,
+            kind: event_kind.as_str().to_string(),
+            occurred_at: at,
+            source: input.source.to_string(),
+            operation_id: input.operation_id,
+            recorded_by: input.recorded_by,
+            picked_up_by: pickup,
+        })
+        .get_result::<AttendanceEvent>(conn)?;
+    Ok(Ok(event))
+}
@@ -0,0 +1,384 @@
+use crate::database::{
+    conn_from_state, db_error,
+    models::{ApiToken, NewApiToken},
+};
+use axum::{
+    extract::{Extension, FromRequestParts, Json, Path},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr, sync::Arc};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Roles an API token can carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Director,
+    Nurse,
+    Counselor,
+    Guardian,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Director => "director",
+            Role::Nurse => "nurse",
+            Role::Counselor => "counselor",
+            Role::Guardian => "guardian",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "director" => Ok(Role::Director),
+            "nurse" => Ok(Role::Nurse),
+            "counselor" => Ok(Role::Counselor),
+            "guardian" => Ok(Role::Guardian),
+            other => Err(format!("Unknown role: {other}")),
+        }
+    }
+}
+
+/// Scopes narrowing what a token's role allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Only reads, on every route the role can access.
+    ReadOnly,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadOnly => "read_only",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read_only" => Ok(Scope::ReadOnly),
+            other => Err(format!("Unknown scope: {other}")),
+        }
+    }
+}
+
+/// Whether a read-only token may call the route. Only reads are allowed;
+/// `POST /admin/exports` is refused too, since queueing an export writes a job.
+pub fn is_read_only_route(method: &str, _path: &str) -> bool {
+    matches!(method, "GET" | "HEAD" | "OPTIONS")
+}
+
+/// Auditor tokens expire after this many days unless a shorter time is requested.
+pub const AUDITOR_TOKEN_DEFAULT_DAYS: i64 = 30;
+/// Longest an auditor token may be issued for.
+pub const AUDITOR_TOKEN_MAX_DAYS: i64 = 90;
+
+/// The authenticated caller, resolved from the `Authorization: Bearer` token.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    /// `None` for the bootstrap admin token configured via `ADMIN_API_TOKEN`.
+    pub token_id: Option<Uuid>,
+    pub role: Role,
+    /// Guardian or staff id the token was issued for.
+    pub subject_id: Option<Uuid>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Actor {
+    /// Rejects the request with 403 unless the actor holds one of `roles`.
+    pub fn require_any(&self, roles: &[Role]) -> Result<(), (StatusCode, String)> {
+        if roles.contains(&self.role) {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                format!("Role {} is not permitted", self.role),
+            ))
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.scopes.contains(&Scope::ReadOnly)
+    }
+
+    /// Rejects the request with 403 if the actor's scopes do not allow the route.
+    pub fn require_scope_for(&self, method: &str, path: &str) -> Result<(), (StatusCode, String)> {
+        if self.is_read_only() && !is_read_only_route(method, path) {
+            Err((StatusCode::FORBIDDEN, "Token is read-only".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The guardian id for guardian tokens.
+    pub fn guardian_id(&self) -> Option<Uuid> {
+        match self.role {
+            Role::Guardian => self.subject_id,
+            _ => None,
+        }
+    }
+}
+
+/// Hashes a bearer token for storage and lookup.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub(crate) fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn unauthorized(message: &str) -> Response {
+    (StatusCode::UNAUTHORIZED, message.to_string()).into_response()
+}
+
+impl<S> FromRequestParts<S> for Actor
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Already resolved by the route policy middleware
+        if let Some(actor) = parts.extensions.get::<Actor>() {
+            return Ok(actor.clone());
+        }
+
+        let token = parts
+            .headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| unauthorized("Missing bearer token"))?;
+        let presented_hash = hash_token(token);
+
+        if let Ok(bootstrap) = std::env::var("ADMIN_API_TOKEN") {
+            if !bootstrap.is_empty() && hash_token(&bootstrap) == presented_hash {
+                return Ok(Actor {
+                    token_id: None,
+                    role: Role::Admin,
+                    subject_id: None,
+                    scopes: Vec::new(),
+                });
+            }
+        }
+
+        let app_state = parts
+            .extensions
+            .get::<Arc<Mutex<AppState>>>()
+            .ok_or_else(|| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
+            .clone();
+        let mut conn = conn_from_state(&app_state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        use crate::database::schema::api_tokens::dsl::*;
+        let now = chrono::Utc::now().naive_utc();
+        let api_token = api_tokens
+            .filter(token_hash.eq(&presented_hash))
+            .filter(revoked_at.is_null())
+            .first::<ApiToken>(&mut conn)
+            .optional()
+            .map_err(|e| db_error("Failed to look up API token")(e).into_response())?
+            .ok_or_else(|| unauthorized("Invalid token"))?;
+
+        if api_token.expires_at.is_some_and(|expiry| expiry <= now) {
+            return Err(unauthorized("Token expired"));
+        }
+
+        let actor_role = Role::from_str(&api_token.role).map_err(|e| {
+            warn!("API token {} has invalid role: {e}", api_token.id);
+            unauthorized("Invalid token")
+        })?;
+        // Fail closed: a scope this build does not know must not widen access
+        let actor_scopes = api_token
+            .scopes
+            .iter()
+            .map(|scope| Scope::from_str(scope))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                warn!("API token {} has invalid scope: {e}", api_token.id);
+                unauthorized("Invalid token")
+            })?;
+
+        Ok(Actor {
+            token_id: Some(api_token.id),
+            role: actor_role,
+            subject_id: api_token.subject_id,
+            scopes: actor_scopes,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssueTokenRequest {
+    pub role: Role,
+    pub subject_id: Option<Uuid>,
+    pub label: String,
+    pub expires_in_hours: Option<i64>,
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssueAuditorTokenRequest {
+    pub label: String,
+    /// Defaults to [`AUDITOR_TOKEN_DEFAULT_DAYS`].
+    pub expires_in_days: Option<i64>,
+    /// `director` (the default) or `admin`.
+    pub role: Option<Role>,
+}
+
+fn scope_names(scopes: &[Scope]) -> Vec<String> {
+    let mut names: Vec<String> = scopes.iter().map(Scope::to_string).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+async fn insert_token(
+    state: &Arc<Mutex<AppState>>,
+    new_token: NewApiToken,
+) -> Result<ApiToken, (StatusCode, String)> {
+    let mut conn = conn_from_state(state).await?;
+    diesel::insert_into(crate::database::schema::api_tokens::table)
+        .values(&new_token)
+        .get_result::<ApiToken>(&mut conn)
+        .map_err(db_error("Failed to issue API token"))
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssuedTokenResponse {
+    /// The plaintext bearer token.
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+/// POST /admin/api_tokens issues an API token. The plaintext token is only returned once.
+#[tracing::instrument(skip(state))]
+pub async fn issue_token_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<IssueTokenRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    if payload.role == Role::Guardian && payload.subject_id.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Guardian tokens require a subject_id".to_string(),
+        ));
+    }
+
+    let token = generate_token();
+    let new_token = NewApiToken {
+        id: Uuid::new_v4(),
+        token_hash: hash_token(&token),
+        role: payload.role.to_string(),
+        subject_id: payload.subject_id,
+        label: payload.label,
+        expires_at: payload
+            .expires_in_hours
+            .map(|hours| chrono::Utc::now().naive_utc() + chrono::Duration::hours(hours)),
+        scopes: scope_names(&payload.scopes),
+    };
+
+    let api_token = insert_token(&state, new_token).await?;
+    info!("Issued {} API token {}", api_token.role, api_token.id);
+
+    Ok(axum::Json(json!(IssuedTokenResponse { token, api_token })))
+}
+
+/// POST /admin/api_tokens/auditor issues a read-only staff token that expires
+/// after at most [`AUDITOR_TOKEN_MAX_DAYS`]. The plaintext token is only returned
+/// once.
+#[tracing::instrument(skip(state))]
+pub async fn issue_auditor_token_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<IssueAuditorTokenRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let auditor_role = payload.role.unwrap_or(Role::Director);
+    if !matches!(auditor_role, Role::Admin | Role::Director) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Auditor tokens must have the director or admin role".to_string(),
+        ));
+    }
+    let days = payload
+        .expires_in_days
+        .unwrap_or(AUDITOR_TOKEN_DEFAULT_DAYS);
+    if !(1..=AUDITOR_TOKEN_MAX_DAYS).contains(&days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_days must be between 1 and {AUDITOR_TOKEN_MAX_DAYS}"),
+        ));
+    }
+
+    let token = generate_token();
+    let new_token = NewApiToken {
+        id: Uuid::new_v4(),
+        token_hash: hash_token(&token),
+        role: auditor_role.to_string(),
+        subject_id: None,
+        label: payload.label,
+        expires_at: Some(chrono::Utc::now().naive_utc() + chrono::Duration::days(days)),
+        scopes: scope_names(&[Scope::ReadOnly]),
+    };
+
+    let api_token = insert_token(&state, new_token).await?;
+    info!(
+        "Issued read-only {} auditor token {} until {:?}",
+        api_token.role, api_token.id, api_token.expires_at
+    );
+
+    Ok(axum::Json(json!(IssuedTokenResponse { token, api_token })))
+}
+
+/// DELETE /admin/api_tokens/{id} revokes an API token.
+#[tracing::instrument(skip(state))]
+pub async fn revoke_token_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(token): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    use crate::database::schema::api_tokens::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let updated = diesel::update(api_tokens.find(token).filter(revoked_at.is_null()))
+        .set(revoked_at.eq(Some(chrono::Utc::now().naive_utc())))
+        .execute(&mut conn)
+        .map_err(db_error("Failed to revoke API token"))?;
+    if updated == 0 {
+        return Err((StatusCode::NOT_FOUND, "API token not found".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
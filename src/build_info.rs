@@ -0,0 +1,174 @@
+//! Build and runtime identity of a running instance.
+//!
+//! The version comes from the crate, the git commit and build time from `build.rs`.
+//! At runtime the instance adds which optional features its configuration turns on,
+//! the database host it connects to (masked) and whether its Stripe keys are test
+//! or live keys. The same report is logged as a banner at startup and served by
+//! `GET /admin/info`, so ops can tell which build and config an instance is running.
+use axum::{extract::Extension, http::StatusCode};
+use chrono::{DateTime, Utc};
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_SHA: &str = env!("BUILD_GIT_SHA");
+/// Unix seconds, set by `build.rs`.
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+/// Optional features and the setting that turns each on.
+const FEATURE_SETTINGS: &[(&str, &str)] = &[
+    ("anonymized_exports", "ANONYMIZATION_KEY"),
+    ("qr_check_in", "CHECK_IN_SIGNING_KEY"),
+    ("slack_alerts", "SLACK_WEBHOOK_URL"),
+    ("notification_relay", "NOTIFICATION_RELAY_URL"),
+    ("webhook_shadow", "WEBHOOK_SHADOW_URL"),
+];
+
+/// Which kind of Stripe keys the instance holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StripeMode {
+    Test,
+    Live,
+    Unknown,
+}
+
+impl StripeMode {
+    /// Reads the mode from a secret or restricted key's prefix.
+    pub fn from_secret_key(key: &str) -> Self {
+        if key.starts_with("sk_live_") || key.starts_with("rk_live_") {
+            StripeMode::Live
+        } else if key.starts_with("sk_test_") || key.starts_with("rk_test_") {
+            StripeMode::Test
+        } else {
+            StripeMode::Unknown
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstanceInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub built_at: Option<DateTime<Utc>>,
+    /// `release` or `debug`.
+    pub profile: &'static str,
+    pub features: BTreeMap<&'static str, bool>,
+    pub database_host: Option<String>,
+    pub stripe_mode: StripeMode,
+}
+
+/// The host of a Postgres connection URL with credentials, port and database
+/// dropped and all but the first and last two labels of a hostname replaced by
+/// `*`, or the last two octets of an IPv4 address. `None` if the URL has no host.
+pub fn mask_database_host(database_url: &str) -> Option<String> {
+    let rest = database_url
+        .split_once("://")
+        .map_or(database_url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    let host_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    // IPv6 literals are masked entirely
+    if host_port.starts_with('[') {
+        return Some("[*]".to_string());
+    }
+    let host = host_port.split(':').next().unwrap_or_default();
+    if host.is_empty() {
+        return None;
+    }
+
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() == 4 && labels.iter().all(|l| l.parse::<u8>().is_ok()) {
+        return Some(format!("{}.{}.*.*", labels[0], labels[1]));
+    }
+    if labels.len() <= 3 {
+        return Some(host.to_string());
+    }
+    let masked: Vec<&str> = labels
+        .iter()
+        .enumerate()
+        .map(|(i, label)| {
+            if i == 0 || i >= labels.len() - 2 {
+                *label
+            } else {
+                "*"
+            }
+        })
+        .collect();
+    Some(masked.join("."))
+}
+
+/// The optional features this instance's configuration turns on.
+fn enabled_features() -> BTreeMap<&'static str, bool> {
+    let mut features: BTreeMap<&'static str, bool> = FEATURE_SETTINGS
+        .iter()
+        .map(|(feature, setting)| {
+            let configured = env::var(setting).is_ok_and(|value| !value.trim().is_empty());
+            (*feature, configured)
+        })
+        .collect();
+    features.insert("warm_start", crate::warm_start::enabled());
+    features.insert("dev_fixtures", crate::fixtures::dev_mode());
+    features
+}
+
+/// Collects the instance's build and runtime identity.
+pub fn instance_info(stripe_secret_key: &str) -> InstanceInfo {
+    InstanceInfo {
+        version: VERSION,
+        git_sha: GIT_SHA,
+        built_at: BUILD_TIMESTAMP
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        features: enabled_features(),
+        database_host: env::var("DATABASE_URL")
+            .ok()
+            .and_then(|url| mask_database_host(&url)),
+        stripe_mode: StripeMode::from_secret_key(stripe_secret_key),
+    }
+}
+
+/// Logs the instance's identity once at startup.
+pub fn log_startup_banner(stripe_secret_key: &str) {
+    let info = instance_info(stripe_secret_key);
+    let features: Vec<&str> = info
+        .features
+        .iter()
+        .filter(|(_, enabled)| **enabled)
+        .map(|(feature, _)| *feature)
+        .collect();
+    info!(
+        version = info.version,
+        git_sha = info.git_sha,
+        built_at = ?info.built_at,
+        profile = info.profile,
+        features = ?features,
+        database_host = info.database_host.as_deref().unwrap_or("unset"),
+        stripe_mode = ?info.stripe_mode,
+        "Camp registration backend {} ({})",
+        info.version,
+        info.git_sha
+    );
+}
+
+/// GET /admin/info reports the instance's version, build and runtime configuration.
+#[tracing::instrument(skip(state))]
+pub async fn info_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let secret_key = state.lock().await.stripe_keys.secret_key.clone();
+    Ok(axum::Json(json!(instance_info(&secret_key))))
+}
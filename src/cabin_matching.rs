@@ -0,0 +1,249 @@
+//! Cabin grouping suggestions from friend requests.
+//!
+//! Guardians name friends their camper wants to bunk with. Names are matched
+//! against the other campers in the session: a full name matches first and last
+//! name, a single name matches a first name, ignoring case and spacing. A name that
+//! matches nobody, or more than one camper, cannot be honored.
+//!
+//! [`suggest_cabins`] groups campers greedily. Mutual requests are honored before
+//! one-way ones, and two groups are only merged if the result still fits in a cabin
+//! and keeps every camper's birthdate within the allowed age gap. Groups are then
+//! packed into cabins, oldest first, under the same two constraints. Requests the
+//! suggestion (or any other assignment) leaves split are reported by
+//! [`unmatched_requests`] so staff can resolve them by hand.
+use chrono::{Months, NaiveDate};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// A confirmed camper in the session being grouped.
+#[derive(Debug, Clone)]
+pub struct CabinCamper {
+    pub registration_id: Uuid,
+    pub first_name: String,
+    pub last_name: String,
+    pub birthdate: NaiveDate,
+}
+
+/// A friend named for a registration.
+#[derive(Debug, Clone)]
+pub struct CabinmateRequest {
+    pub registration_id: Uuid,
+    pub friend_name: String,
+}
+
+/// Limits every cabin must respect.
+#[derive(Debug, Clone, Copy)]
+pub struct CabinRules {
+    pub cabin_size: usize,
+    /// Largest gap between the oldest and youngest camper's birthdates, in months.
+    pub max_age_gap_months: u32,
+}
+
+/// Why a friend request is not honored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnmatchedReason {
+    /// No camper in the session has that name.
+    NotRegistered,
+    /// Several campers in the session have that name.
+    Ambiguous,
+    /// The friend is in the session but in a different cabin.
+    DifferentCabin,
+    /// The camper or their friend has no cabin yet.
+    Unassigned,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnmatchedRequest {
+    pub registration_id: Uuid,
+    pub friend_name: String,
+    /// The camper the name resolved to, when it resolved to exactly one.
+    pub friend_registration_id: Option<Uuid>,
+    pub reason: UnmatchedReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SuggestedCabin {
+    pub registration_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CabinPlan {
+    pub cabins: Vec<SuggestedCabin>,
+    pub unmatched_requests: Vec<UnmatchedRequest>,
+}
+
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The camper a friend request names, or why it names none.
+fn resolve(campers: &[CabinCamper], request: &CabinmateRequest) -> Result<Uuid, UnmatchedReason> {
+    let wanted = normalize_name(&request.friend_name);
+    let single_name = !wanted.contains(' ');
+    let matches: Vec<Uuid> = campers
+        .iter()
+        .filter(|c| c.registration_id != request.registration_id)
+        .filter(|c| {
+            if single_name {
+                normalize_name(&c.first_name) == wanted
+            } else {
+                normalize_name(&format!("{} {}", c.first_name, c.last_name)) == wanted
+            }
+        })
+        .map(|c| c.registration_id)
+        .collect();
+    match matches[..] {
+        [friend] => Ok(friend),
+        [] => Err(UnmatchedReason::NotRegistered),
+        _ => Err(UnmatchedReason::Ambiguous),
+    }
+}
+
+/// A set of campers that must share a cabin.
+#[derive(Debug, Clone)]
+struct Group {
+    members: Vec<Uuid>,
+    oldest: NaiveDate,
+    youngest: NaiveDate,
+}
+
+impl Group {
+    fn can_join(&self, other: &Group, rules: &CabinRules) -> bool {
+        let oldest = self.oldest.min(other.oldest);
+        let youngest = self.youngest.max(other.youngest);
+        self.members.len() + other.members.len() <= rules.cabin_size
+            && oldest
+                .checked_add_months(Months::new(rules.max_age_gap_months))
+                .is_some_and(|limit| youngest <= limit)
+    }
+
+    fn absorb(&mut self, other: Group) {
+        self.members.extend(other.members);
+        self.oldest = self.oldest.min(other.oldest);
+        self.youngest = self.youngest.max(other.youngest);
+    }
+}
+
+/// Suggests cabins for the campers, honoring as many friend requests as the rules allow.
+pub fn suggest_cabins(
+    campers: &[CabinCamper],
+    requests: &[CabinmateRequest],
+    rules: CabinRules,
+) -> CabinPlan {
+    let mut ordered: Vec<&CabinCamper> = campers.iter().collect();
+    ordered.sort_by_key(|c| (c.birthdate, c.registration_id));
+
+    // Every camper starts alone; `group_of` maps campers to their group's index
+    let mut groups: Vec<Option<Group>> = ordered
+        .iter()
+        .map(|c| {
+            Some(Group {
+                members: vec![c.registration_id],
+                oldest: c.birthdate,
+                youngest: c.birthdate,
+            })
+        })
+        .collect();
+    let mut group_of: HashMap<Uuid, usize> = ordered
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.registration_id, i))
+        .collect();
+
+    // Pairs to honor, mutual requests first
+    let mut pairs: BTreeMap<(Uuid, Uuid), bool> = BTreeMap::new();
+    let wanted: Vec<(Uuid, Uuid)> = requests
+        .iter()
+        .filter_map(|r| resolve(campers, r).ok().map(|f| (r.registration_id, f)))
+        .filter(|(requester, _)| group_of.contains_key(requester))
+        .collect();
+    for (requester, friend) in &wanted {
+        let key = (*requester.min(friend), *requester.max(friend));
+        let mutual = wanted.contains(&(*friend, *requester));
+        pairs.insert(key, mutual);
+    }
+    let mut pairs: Vec<((Uuid, Uuid), bool)> = pairs.into_iter().collect();
+    pairs.sort_by_key(|(_, mutual)| !mutual);
+
+    for ((a, b), _) in pairs {
+        let (ga, gb) = (group_of[&a], group_of[&b]);
+        if ga == gb {
+            continue;
+        }
+        let (Some(first), Some(second)) = (&groups[ga], &groups[gb]) else {
+            continue;
+        };
+        if !first.can_join(second, &rules) {
+            continue;
+        }
+        let merged = groups[gb].take().expect("group exists");
+        for member in &merged.members {
+            group_of.insert(*member, ga);
+        }
+        groups[ga].as_mut().expect("group exists").absorb(merged);
+    }
+
+    // Pack groups into cabins, oldest first, largest groups placed first on ties
+    let mut remaining: Vec<Group> = groups.into_iter().flatten().collect();
+    remaining.sort_by_key(|g| (g.oldest, std::cmp::Reverse(g.members.len())));
+    let mut cabins: Vec<Group> = Vec::new();
+    for group in remaining {
+        match cabins
+            .iter_mut()
+            .find(|cabin| cabin.can_join(&group, &rules))
+        {
+            Some(cabin) => cabin.absorb(group),
+            None => cabins.push(group),
+        }
+    }
+
+    let cabin_of: HashMap<Uuid, String> = cabins
+        .iter()
+        .enumerate()
+        .flat_map(|(i, cabin)| cabin.members.iter().map(move |m| (*m, i.to_string())))
+        .collect();
+    CabinPlan {
+        unmatched_requests: unmatched_requests(campers, requests, &cabin_of),
+        cabins: cabins
+            .into_iter()
+            .map(|cabin| SuggestedCabin {
+                registration_ids: cabin.members,
+            })
+            .collect(),
+    }
+}
+
+/// Friend requests not honored by an assignment of registrations to cabins.
+pub fn unmatched_requests(
+    campers: &[CabinCamper],
+    requests: &[CabinmateRequest],
+    cabin_of: &HashMap<Uuid, String>,
+) -> Vec<UnmatchedRequest> {
+    requests
+        .iter()
+        .filter_map(|request| {
+            let (friend, reason) = match resolve(campers, request) {
+                Err(reason) => (None, reason),
+                Ok(friend) => match (
+                    cabin_of.get(&request.registration_id),
+                    cabin_of.get(&friend),
+                ) {
+                    (Some(mine), Some(theirs)) if mine == theirs => return None,
+                    (Some(_), Some(_)) => (Some(friend), UnmatchedReason::DifferentCabin),
+                    _ => (Some(friend), UnmatchedReason::Unassigned),
+                },
+            };
+            Some(UnmatchedRequest {
+                registration_id: request.registration_id,
+                friend_name: request.friend_name.clone(),
+                friend_registration_id: friend,
+                reason,
+            })
+        })
+        .collect()
+}
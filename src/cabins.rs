@@ -0,0 +1,270 @@
+//! Cabin assignments for a session.
+//!
+//! `GET /admin/sessions/{id}/cabins` shows the session's saved assignments, the
+//! friend requests they leave unhonored, and a suggested grouping from
+//! [`crate::cabin_matching`]. `PUT` replaces the saved assignments, typically with
+//! the suggestion after staff have adjusted it. Only confirmed registrations are
+//! assigned.
+use crate::auth::Actor;
+use crate::cabin_matching::{
+    suggest_cabins, unmatched_requests, CabinCamper, CabinPlan, CabinRules, CabinmateRequest,
+    UnmatchedRequest,
+};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CabinAssignment, Camper, FriendRequest, Registration},
+};
+use crate::sessions::load_session;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+const DEFAULT_CABIN_SIZE: usize = 8;
+const DEFAULT_MAX_AGE_GAP_MONTHS: u32 = 24;
+
+#[derive(Debug, Deserialize)]
+pub struct CabinsQuery {
+    pub cabin_size: Option<usize>,
+    pub max_age_gap_months: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveCabinsRequest {
+    pub cabins: Vec<Cabin>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cabin {
+    pub name: String,
+    pub registration_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CabinsResponse {
+    pub session_id: Uuid,
+    /// Saved assignments, by cabin name.
+    pub cabins: Vec<Cabin>,
+    /// Confirmed registrations without a cabin.
+    pub unassigned: Vec<Uuid>,
+    /// Friend requests the saved assignments do not honor.
+    pub unmatched_requests: Vec<UnmatchedRequest>,
+    /// Present on `GET`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<CabinPlan>,
+}
+
+/// The session's confirmed campers and their friend requests.
+fn load_cabin_inputs(
+    conn: &mut PgConnection,
+    session: Uuid,
+) -> Result<(Vec<CabinCamper>, Vec<CabinmateRequest>), diesel::result::Error> {
+    use crate::database::schema::{campers, friend_requests, registrations};
+
+    let rows = registrations::table
+        .filter(registrations::session_id.eq(session))
+        .filter(registrations::status.eq("confirmed"))
+        .load::<Registration>(conn)?;
+    let camper_ids: Vec<Uuid> = rows.iter().map(|r| r.camper_id).collect();
+    let camper_rows = campers::table
+        .filter(campers::id.eq_any(&camper_ids))
+        .load::<Camper>(conn)?;
+    let cabin_campers = rows
+        .iter()
+        .filter_map(|registration| {
+            let camper = camper_rows
+                .iter()
+                .find(|c| c.id == registration.camper_id)?;
+            Some(CabinCamper {
+                registration_id: registration.id,
+                first_name: camper.first_name.clone(),
+                last_name: camper.last_name.clone(),
+                birthdate: camper.birthdate,
+            })
+        })
+        .collect();
+
+    let registration_ids: Vec<Uuid> = rows.iter().map(|r| r.id).collect();
+    let requests = friend_requests::table
+        .filter(friend_requests::registration_id.eq_any(&registration_ids))
+        .order(friend_requests::created_at.asc())
+        .load::<FriendRequest>(conn)?
+        .into_iter()
+        .map(|request| CabinmateRequest {
+            registration_id: request.registration_id,
+            friend_name: request.friend_name,
+        })
+        .collect();
+    Ok((cabin_campers, requests))
+}
+
+/// The saved assignments with the requests they leave unmatched.
+fn assignment_report(
+    conn: &mut PgConnection,
+    session: Uuid,
+    campers: &[CabinCamper],
+    requests: &[CabinmateRequest],
+) -> Result<CabinsResponse, diesel::result::Error> {
+    use crate::database::schema::cabin_assignments;
+
+    let saved = cabin_assignments::table
+        .filter(cabin_assignments::session_id.eq(session))
+        .load::<CabinAssignment>(conn)?;
+    let confirmed: HashSet<Uuid> = campers.iter().map(|c| c.registration_id).collect();
+    let cabin_of: HashMap<Uuid, String> = saved
+        .into_iter()
+        .filter(|a| confirmed.contains(&a.registration_id))
+        .map(|a| (a.registration_id, a.cabin))
+        .collect();
+
+    let mut by_cabin: BTreeMap<&str, Vec<Uuid>> = BTreeMap::new();
+    for (registration, cabin) in &cabin_of {
+        by_cabin.entry(cabin).or_default().push(*registration);
+    }
+    let cabins = by_cabin
+        .into_iter()
+        .map(|(name, mut registration_ids)| {
+            registration_ids.sort();
+            Cabin {
+                name: name.to_string(),
+                registration_ids,
+            }
+        })
+        .collect();
+    let unassigned = campers
+        .iter()
+        .map(|c| c.registration_id)
+        .filter(|id| !cabin_of.contains_key(id))
+        .collect();
+
+    Ok(CabinsResponse {
+        session_id: session,
+        cabins,
+        unassigned,
+        unmatched_requests: unmatched_requests(campers, requests, &cabin_of),
+        suggestion: None,
+    })
+}
+
+/// GET /admin/sessions/{id}/cabins?cabin_size=&max_age_gap_months= reports the
+/// session's cabin assignments, unmatched friend requests and a suggested grouping.
+#[tracing::instrument(skip(state))]
+pub async fn session_cabins_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+    Query(query): Query<CabinsQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let rules = CabinRules {
+        cabin_size: query.cabin_size.unwrap_or(DEFAULT_CABIN_SIZE),
+        max_age_gap_months: query
+            .max_age_gap_months
+            .unwrap_or(DEFAULT_MAX_AGE_GAP_MONTHS),
+    };
+    if rules.cabin_size == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "cabin_size must be at least 1".to_string(),
+        ));
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+    let (campers, requests) =
+        load_cabin_inputs(&mut conn, session.id).map_err(db_error("Failed to load campers"))?;
+    let mut report = assignment_report(&mut conn, session.id, &campers, &requests)
+        .map_err(db_error("Failed to load cabin assignments"))?;
+    report.suggestion = Some(suggest_cabins(&campers, &requests, rules));
+
+    Ok(axum::Json(json!(report)))
+}
+
+/// PUT /admin/sessions/{id}/cabins replaces the session's cabin assignments and
+/// reports the friend requests they leave unmatched.
+#[tracing::instrument(skip(state, payload))]
+pub async fn save_cabins_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+    Json(payload): Json<SaveCabinsRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+    let (campers, requests) =
+        load_cabin_inputs(&mut conn, session.id).map_err(db_error("Failed to load campers"))?;
+
+    let confirmed: HashSet<Uuid> = campers.iter().map(|c| c.registration_id).collect();
+    let mut seen = HashSet::new();
+    for cabin in &payload.cabins {
+        if cabin.name.trim().is_empty() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Cabin names must not be empty".to_string(),
+            ));
+        }
+        for registration in &cabin.registration_ids {
+            if !confirmed.contains(registration) {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Registration {registration} is not confirmed for this session"),
+                ));
+            }
+            if !seen.insert(*registration) {
+                return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    format!("Registration {registration} is assigned to more than one cabin"),
+                ));
+            }
+        }
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let rows: Vec<CabinAssignment> = payload
+        .cabins
+        .iter()
+        .flat_map(|cabin| {
+            cabin
+                .registration_ids
+                .iter()
+                .map(|registration| CabinAssignment {
+                    registration_id: *registration,
+                    session_id: session.id,
+                    cabin: cabin.name.trim().to_string(),
+                    assigned_by: actor.subject_id,
+                    assigned_at: now,
+                })
+        })
+        .collect();
+    let report = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            use crate::database::schema::cabin_assignments;
+
+            diesel::delete(
+                cabin_assignments::table.filter(cabin_assignments::session_id.eq(session.id)),
+            )
+            .execute(conn)?;
+            if !rows.is_empty() {
+                diesel::insert_into(cabin_assignments::table)
+                    .values(&rows)
+                    .execute(conn)?;
+            }
+            assignment_report(conn, session.id, &campers, &requests)
+        })
+        .map_err(db_error("Failed to save cabin assignments"))?;
+    info!(
+        "Saved {} cabin assignment(s) for session {}; {} friend request(s) unmatched",
+        rows.len(),
+        session.id,
+        report.unmatched_requests.len()
+    );
+
+    Ok(axum::Json(json!(report)))
+}
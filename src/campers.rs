@@ -0,0 +1,87 @@
+use crate::auth::{Actor, Role};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{Camper, NewCamper},
+};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCamperRequest {
+    /// Ignored for guardian tokens, which always create campers for themselves.
+    pub guardian_id: Option<Uuid>,
+    pub first_name: String,
+    pub last_name: String,
+    pub birthdate: NaiveDate,
+}
+
+/// Loads a camper, mapping a missing row to 404.
+pub fn load_camper(conn: &mut PgConnection, camper: Uuid) -> Result<Camper, (StatusCode, String)> {
+    crate::database::schema::campers::table
+        .find(camper)
+        .first::<Camper>(conn)
+        .optional()
+        .map_err(db_error("Failed to load camper"))?
+        .ok_or((StatusCode::NOT_FOUND, "Camper not found".to_string()))
+}
+
+/// Rejects guardians reaching for someone else's camper. Staff roles pass through.
+pub fn ensure_guardian_owns(actor: &Actor, camper: &Camper) -> Result<(), (StatusCode, String)> {
+    match actor.role {
+        Role::Guardian if actor.guardian_id() != Some(camper.guardian_id) => {
+            Err((StatusCode::NOT_FOUND, "Camper not found".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// POST /campers creates a camper for a guardian.
+#[tracing::instrument(skip(state))]
+pub async fn create_camper_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<CreateCamperRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let guardian_id = actor.guardian_id().or(payload.guardian_id).ok_or((
+        StatusCode::BAD_REQUEST,
+        "guardian_id is required".to_string(),
+    ))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let camper = diesel::insert_into(crate::database::schema::campers::table)
+        .values(&NewCamper {
+            guardian_id,
+            first_name: payload.first_name,
+            last_name: payload.last_name,
+            birthdate: payload.birthdate,
+        })
+        .get_result::<Camper>(&mut conn)
+        .map_err(db_error("Failed to create camper"))?;
+    info!("Created camper {} for guardian {}", camper.id, guardian_id);
+
+    Ok(axum::Json(json!(camper)))
+}
+
+/// GET /campers/{id} returns a camper's profile (without medical data).
+#[tracing::instrument(skip(state))]
+pub async fn get_camper_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(camper_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+    Ok(axum::Json(json!(camper)))
+}
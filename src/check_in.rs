@@ -0,0 +1,206 @@
+//! QR code check-in.
+//!
+//! Each confirmed registration has a check-in code, sent with the confirmation email
+//! for the family to show at the gate as a QR code. The code is the registration id
+//! and an HMAC-SHA256 signature under `CHECK_IN_SIGNING_KEY`, so it cannot be forged
+//! or pointed at another registration, and no table stores it. A code only checks a
+//! camper in while the registration is confirmed, so cancelling a registration
+//! revokes its code. Rotating the key invalidates every code already sent.
+//!
+//! Staff scan codes through `POST /check_in/scan`, which records the check-in and
+//! returns the camper's details so staff can confirm they have the right child.
+use crate::attendance::{record_attendance, AttendanceInput, AttendanceKind, AttendanceRejection};
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{AttendanceEvent, CampSession, Camper, Guardian, Registration},
+};
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Prefix identifying check-in codes, versioned in case the format changes.
+const CODE_PREFIX: &str = "ci1";
+/// Signature bytes kept in a code, to keep the QR code small.
+const SIGNATURE_BYTES: usize = 16;
+
+fn signing_key() -> Result<Vec<u8>, String> {
+    std::env::var("CHECK_IN_SIGNING_KEY")
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+        .map(|key| key.trim().as_bytes().to_vec())
+        .ok_or_else(|| "CHECK_IN_SIGNING_KEY must be set".to_string())
+}
+
+fn signature(key: &[u8], registration: Uuid) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(CODE_PREFIX.as_bytes());
+    mac.update(registration.as_bytes());
+    mac
+}
+
+/// The check-in code for a registration.
+pub fn check_in_code(registration: Uuid) -> Result<String, String> {
+    let mac = signature(&signing_key()?, registration);
+    let tag = mac.finalize().into_bytes();
+    Ok(format!(
+        "{CODE_PREFIX}.{}.{}",
+        registration.simple(),
+        hex::encode(&tag[..SIGNATURE_BYTES])
+    ))
+}
+
+/// The registration a check-in code was issued for, if its signature is valid.
+pub fn verify_check_in_code(code: &str) -> Result<Option<Uuid>, String> {
+    let key = signing_key()?;
+    let mut parts = code.trim().split('.');
+    let (Some(CODE_PREFIX), Some(id), Some(tag), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(None);
+    };
+    let (Ok(registration), Ok(tag)) = (Uuid::parse_str(id), hex::decode(tag)) else {
+        return Ok(None);
+    };
+    // Constant-time comparison of the truncated tag
+    let valid = tag.len() == SIGNATURE_BYTES
+        && signature(&key, registration)
+            .verify_truncated_left(&tag)
+            .is_ok();
+    Ok(valid.then_some(registration))
+}
+
+/// The code and scan URL added to a confirmation email, or nothing when check-in
+/// codes are not configured.
+pub fn check_in_payload(registration: Uuid) -> Option<Value> {
+    match check_in_code(registration) {
+        Ok(code) => {
+            let url = std::env::var("CHECK_IN_BASE_URL")
+                .ok()
+                .filter(|base| !base.is_empty())
+                .map(|base| format!("{}/{code}", base.trim_end_matches('/')));
+            Some(json!({ "code": code, "url": url }))
+        }
+        Err(e) => {
+            warn!("Confirmation for {registration} sent without a check-in code: {e}");
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScanRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScannedCamper {
+    pub id: Uuid,
+    pub first_name: String,
+    pub last_name: String,
+    pub birthdate: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScannedSession {
+    pub id: Uuid,
+    pub name: String,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanResponse {
+    pub registration_id: Uuid,
+    pub camper: ScannedCamper,
+    pub guardian_name: String,
+    pub session: ScannedSession,
+    pub event: AttendanceEvent,
+}
+
+/// POST /check_in/scan checks in the camper a QR code was issued for and returns
+/// their details for staff to verify.
+#[tracing::instrument(skip(state, payload))]
+pub async fn scan_check_in_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<ScanRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{camp_sessions, campers, guardians, registrations};
+
+    let registration_id = verify_check_in_code(&payload.code)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid check-in code".to_string()))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let now = chrono::Utc::now().naive_utc();
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let event = match record_attendance(
+            conn,
+            registration_id,
+            AttendanceKind::CheckIn,
+            now,
+            AttendanceInput {
+                source: "qr",
+                operation_id: None,
+                recorded_by: actor.subject_id,
+                picked_up_by: None,
+            },
+        )? {
+            Ok(event) => event,
+            Err(rejection) => return Ok(Err(rejection)),
+        };
+        let registration = registrations::table
+            .find(registration_id)
+            .first::<Registration>(conn)?;
+        let camper = campers::table
+            .find(registration.camper_id)
+            .first::<Camper>(conn)?;
+        let guardian = guardians::table
+            .find(registration.guardian_id)
+            .first::<Guardian>(conn)?;
+        let session = camp_sessions::table
+            .find(registration.session_id)
+            .first::<CampSession>(conn)?;
+        Ok(Ok(ScanResponse {
+            registration_id,
+            camper: ScannedCamper {
+                id: camper.id,
+                first_name: camper.first_name,
+                last_name: camper.last_name,
+                birthdate: camper.birthdate,
+            },
+            guardian_name: guardian.name,
+            session: ScannedSession {
+                id: session.id,
+                name: session.name,
+                starts_on: session.starts_on,
+                ends_on: session.ends_on,
+            },
+            event,
+        }))
+    });
+    let response = result
+        .map_err(db_error("Failed to check in"))?
+        .map_err(|rejection| match rejection {
+            AttendanceRejection::RegistrationNotFound => {
+                (StatusCode::NOT_FOUND, rejection.to_string())
+            }
+            _ => (StatusCode::CONFLICT, rejection.to_string()),
+        })?;
+
+    info!("Checked in registration {registration_id} by QR code");
+    Ok(axum::Json(json!(response)))
+}
@@ -0,0 +1,277 @@
+//! Configuration profiles.
+//!
+//! The same artifact runs in every environment; `APP_ENV` picks its profile:
+//! `development` (also `dev`, `local`), `staging` (also `stage`) or `production`
+//! (also `prod`). It has no default: an instance without it refuses to start rather
+//! than guess its environment. Each setting resolves in three layers:
+//!
+//! 1. the default built into the module that reads it;
+//! 2. the profile's override, from [`Profile::overrides`];
+//! 3. the process environment, which always wins.
+//!
+//! [`load`] writes the profile overrides into the environment at startup, before the
+//! async runtime starts any threads and before any module reads its settings, so
+//! modules keep reading `env::var`. The effective
+//! configuration is logged once with each value's source and secrets masked. Only the
+//! production profile accepts live Stripe keys; other profiles refuse to start with
+//! one.
+use crate::build_info::{mask_database_host, StripeMode};
+use serde::Serialize;
+use std::env;
+use std::fmt;
+use tracing::info;
+
+/// How a setting's value is shown in the startup dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exposure {
+    Plain,
+    Secret,
+    DatabaseUrl,
+}
+
+/// Every setting read from the environment at runtime.
+const SETTINGS: &[(&str, Exposure)] = &[
+    ("ADMIN_API_TOKEN", Exposure::Secret),
+    ("ANONYMIZATION_KEY", Exposure::Secret),
+    ("CHECK_IN_BASE_URL", Exposure::Plain),
+    ("CHECK_IN_SIGNING_KEY", Exposure::Secret),
+    ("DATABASE_URL", Exposure::DatabaseUrl),
+    ("DELEGATED_LINK_BASE_URL", Exposure::Plain),
+    ("ENROLLMENT_WARNING_DAYS", Exposure::Plain),
+    ("EXCHANGE_RATE_URL", Exposure::Plain),
+    ("EXPORT_BUCKET", Exposure::Plain),
+    ("HOLD_EXPIRY_WARNING_MINUTES", Exposure::Plain),
+    ("HOLD_TTL_MINUTES", Exposure::Plain),
+    ("JIRA_API_TOKEN", Exposure::Secret),
+    ("JIRA_BASE_URL", Exposure::Plain),
+    ("JIRA_EMAIL", Exposure::Plain),
+    ("JIRA_PROJECT_KEY", Exposure::Plain),
+    ("METRICS_NAMESPACE", Exposure::Plain),
+    ("NOTIFICATION_RELAY_URL", Exposure::Plain),
+    ("ORG_ADDRESS", Exposure::Plain),
+    ("ORG_EIN", Exposure::Plain),
+    ("ORG_LEGAL_NAME", Exposure::Plain),
+    ("PAYMENT_AMOUNT_LIMITS", Exposure::Plain),
+    ("PAYMENT_DAILY_LIMITS", Exposure::Plain),
+    ("PAYMENT_MAX_INTENTS_PER_HOUR", Exposure::Plain),
+    ("PROCESSING_FEE_FIXED", Exposure::Plain),
+    ("PROCESSING_FEE_PERCENT", Exposure::Plain),
+    ("PROCESSING_FEE_ROUNDING", Exposure::Plain),
+    ("PUBLIC_AVAILABILITY_CACHE_SECONDS", Exposure::Plain),
+    ("PUBLIC_AVAILABILITY_RATE_LIMIT", Exposure::Plain),
+    ("RECEIPT_NUMBER_PREFIX", Exposure::Plain),
+    ("RECEIPT_ORGANIZATION", Exposure::Plain),
+    ("REFUND_POLICY_TIERS", Exposure::Plain),
+    // The webhook URL is its own credential
+    ("SLACK_WEBHOOK_URL", Exposure::Secret),
+    ("SLO_ALERT_COOLDOWN_MINUTES", Exposure::Plain),
+    ("SLO_BURN_RATE_THRESHOLD", Exposure::Plain),
+    ("SLO_TARGETS", Exposure::Plain),
+    ("STRIPE_API_BASE", Exposure::Plain),
+    ("STRIPE_PAYMENTS_KEY", Exposure::Secret),
+    ("STRIPE_REFUNDS_KEY", Exposure::Secret),
+    ("STRIPE_REPORTING_KEY", Exposure::Secret),
+    ("STRIPE_WEBHOOK_EVENT_ALLOWLIST", Exposure::Plain),
+    ("STRIPE_WEBHOOK_EVENT_DENYLIST", Exposure::Plain),
+    ("TICKETING_PROVIDER", Exposure::Plain),
+    ("TICKETING_WEBHOOK_URL", Exposure::Secret),
+    ("TICKET_DEDUP_HOURS", Exposure::Plain),
+    ("TICKET_RULES", Exposure::Plain),
+    ("USAGE_FLUSH_SECONDS", Exposure::Plain),
+    ("VOUCHER_VALIDITY_DAYS", Exposure::Plain),
+    ("WAITLIST_OFFER_BASE_URL", Exposure::Plain),
+    ("WAITLIST_OFFER_TTL_HOURS", Exposure::Plain),
+    ("WARM_START", Exposure::Plain),
+    ("WEBHOOK_FAILURE_RESPONSES", Exposure::Plain),
+    ("WEBHOOK_ORDERING", Exposure::Plain),
+    ("WEBHOOK_SHADOW_SAMPLE_PERCENT", Exposure::Plain),
+    ("WEBHOOK_SHADOW_TIMEOUT_MS", Exposure::Plain),
+    ("WEBHOOK_SHADOW_URL", Exposure::Plain),
+    ("ZENDESK_API_TOKEN", Exposure::Secret),
+    ("ZENDESK_EMAIL", Exposure::Plain),
+    ("ZENDESK_SUBDOMAIN", Exposure::Plain),
+];
+
+/// Restricted Stripe keys loaded from the environment, checked against the profile.
+const STRIPE_KEY_SETTINGS: &[&str] = &[
+    "STRIPE_PAYMENTS_KEY",
+    "STRIPE_REFUNDS_KEY",
+    "STRIPE_REPORTING_KEY",
+];
+
+/// Longer holds and looser limits for manual testing.
+const DEVELOPMENT_OVERRIDES: &[(&str, &str)] = &[
+    ("HOLD_TTL_MINUTES", "60"),
+    ("PAYMENT_MAX_INTENTS_PER_HOUR", "100"),
+    ("USAGE_FLUSH_SECONDS", "10"),
+    ("WAITLIST_OFFER_TTL_HOURS", "1"),
+];
+
+/// Short waitlist offers so QA can walk a full offer cycle.
+const STAGING_OVERRIDES: &[(&str, &str)] = &[
+    ("PAYMENT_MAX_INTENTS_PER_HOUR", "50"),
+    ("WAITLIST_OFFER_TTL_HOURS", "2"),
+];
+
+/// A named set of setting overrides, selected by `APP_ENV`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    Development,
+    Staging,
+    Production,
+}
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Development => "development",
+            Profile::Staging => "staging",
+            Profile::Production => "production",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        match raw.trim().to_lowercase().as_str() {
+            "dev" | "development" | "local" => Ok(Profile::Development),
+            "stage" | "staging" => Ok(Profile::Staging),
+            "prod" | "production" => Ok(Profile::Production),
+            "" => Err("APP_ENV is empty: expected development, staging or production".to_string()),
+            other => Err(format!(
+                "Invalid APP_ENV '{other}': expected development, staging or production"
+            )),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let raw = env::var("APP_ENV").map_err(|_| {
+            "APP_ENV is not set: expected development, staging or production".to_string()
+        })?;
+        Self::parse(&raw)
+    }
+
+    /// Settings the profile changes from the built-in defaults. Production runs on
+    /// the defaults.
+    pub fn overrides(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Profile::Development => DEVELOPMENT_OVERRIDES,
+            Profile::Staging => STAGING_OVERRIDES,
+            Profile::Production => &[],
+        }
+    }
+
+    /// Rejects live Stripe keys outside production. `setting` names the key's source
+    /// for the error.
+    pub fn check_stripe_key(&self, setting: &str, key: &str) -> Result<(), String> {
+        let live =
+            StripeMode::from_secret_key(key) == StripeMode::Live || key.starts_with("pk_live_");
+        if live && *self != Profile::Production {
+            return Err(format!(
+                "{setting} is a live Stripe key, which the {self} profile does not allow"
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which layer a setting's effective value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Default,
+    Profile,
+    Environment,
+}
+
+/// A setting's effective value, masked for display.
+#[derive(Debug, Serialize)]
+pub struct ConfigValue {
+    pub name: &'static str,
+    pub source: Source,
+    /// `None` when the module's built-in default applies.
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    pub profile: Profile,
+    pub values: Vec<ConfigValue>,
+}
+
+/// Keeps the last four characters of a secret long enough to spare them.
+pub fn mask_secret(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() < 12 {
+        return "****".to_string();
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{tail}")
+}
+
+fn display(exposure: Exposure, value: &str) -> String {
+    match exposure {
+        Exposure::Plain => value.to_string(),
+        Exposure::Secret => mask_secret(value),
+        Exposure::DatabaseUrl => mask_database_host(value).unwrap_or_else(|| "****".to_string()),
+    }
+}
+
+/// Resolves every setting for `profile`, reading the environment through `lookup`.
+pub fn resolve(profile: Profile, lookup: impl Fn(&str) -> Option<String>) -> EffectiveConfig {
+    let values = SETTINGS
+        .iter()
+        .map(|(name, exposure)| {
+            let (source, raw) = match lookup(name) {
+                Some(value) => (Source::Environment, Some(value)),
+                None => match profile.overrides().iter().find(|(key, _)| key == name) {
+                    Some((_, value)) => (Source::Profile, Some(value.to_string())),
+                    None => (Source::Default, None),
+                },
+            };
+            ConfigValue {
+                name,
+                source,
+                value: raw.map(|value| display(*exposure, &value)),
+            }
+        })
+        .collect();
+    EffectiveConfig { profile, values }
+}
+
+impl EffectiveConfig {
+    /// Logs the effective configuration, one line per configured setting.
+    pub fn log(&self) {
+        info!("Configuration profile: {}", self.profile);
+        for value in &self.values {
+            if let Some(shown) = &value.value {
+                info!(source = ?value.source, "{} = {shown}", value.name);
+            }
+        }
+    }
+}
+
+/// Selects the profile from `APP_ENV`, applies its overrides to the environment and
+/// checks the restricted Stripe keys against it. Must run before the async runtime is
+/// built, since changing the environment is only sound while no other thread reads it,
+/// and before anything reads its settings.
+pub fn load() -> Result<EffectiveConfig, String> {
+    let profile = Profile::from_env()?;
+    let config = resolve(profile, |name| env::var(name).ok());
+    for (name, value) in profile.overrides() {
+        if env::var(name).is_err() {
+            env::set_var(name, value);
+        }
+    }
+    for setting in STRIPE_KEY_SETTINGS {
+        if let Ok(key) = env::var(setting) {
+            profile.check_stripe_key(setting, &key)?;
+        }
+    }
+    Ok(config)
+}
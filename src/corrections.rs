@@ -0,0 +1,372 @@
+//! Camper detail corrections requested by guardians.
+//!
+//! Guardians cannot edit a camper once it exists; they ask for a correction with
+//! `POST /campers/{id}/corrections`, proposing a new name or birthdate. Directors
+//! review open requests under `/admin/corrections` and approve or reject them. An
+//! approval applies the change and keeps the replaced values on the request, so the
+//! request history is the camper's audit trail. Either way the guardian is emailed
+//! the outcome.
+use crate::auth::Actor;
+use crate::campers::{ensure_guardian_owns, load_camper};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampSession, Camper, CamperCorrection, Guardian, NewCamperCorrection, Registration},
+};
+use crate::events::{DomainEvent, EventRecorder, CORRECTION_REVIEWED};
+use crate::notifications::{dispatch_pending, enqueue, Channel, Notification};
+use crate::session_cancellations::OPEN_STATUSES;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct CorrectionRequest {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+    /// Why the details are wrong, for the reviewer.
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    /// Shown to the guardian with the outcome.
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CorrectionsQuery {
+    /// `pending` (the default), `approved`, `rejected` or `all`.
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CorrectionReviewedResponse {
+    pub correction: CamperCorrection,
+    pub camper: Camper,
+    /// Open registrations whose session's age range no longer admits the camper.
+    pub age_conflicts: Vec<Uuid>,
+}
+
+fn trimmed(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// POST /campers/{id}/corrections asks staff to correct a camper's details.
+#[tracing::instrument(skip(state))]
+pub async fn request_correction_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(camper_id): Path<Uuid>,
+    Json(payload): Json<CorrectionRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::camper_corrections;
+
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+
+    // Only fields that actually change are kept
+    let first_name = trimmed(payload.first_name).filter(|v| *v != camper.first_name);
+    let last_name = trimmed(payload.last_name).filter(|v| *v != camper.last_name);
+    let birthdate = payload.birthdate.filter(|d| *d != camper.birthdate);
+    if first_name.is_none() && last_name.is_none() && birthdate.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "The correction does not change anything".to_string(),
+        ));
+    }
+    if birthdate.is_some_and(|d| d > chrono::Utc::now().date_naive()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Birthdate must not be in the future".to_string(),
+        ));
+    }
+
+    let correction = diesel::insert_into(camper_corrections::table)
+        .values(&NewCamperCorrection {
+            camper_id: camper.id,
+            guardian_id: camper.guardian_id,
+            first_name,
+            last_name,
+            birthdate,
+            reason: trimmed(payload.reason),
+        })
+        .on_conflict_do_nothing()
+        .get_result::<CamperCorrection>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to request correction"))?
+        .ok_or((
+            StatusCode::CONFLICT,
+            "A correction for this camper is already awaiting review".to_string(),
+        ))?;
+    info!(
+        "Correction {} requested for camper {}",
+        correction.id, camper.id
+    );
+
+    Ok(axum::Json(json!(correction)))
+}
+
+/// GET /campers/{id}/corrections lists a camper's correction requests, newest first.
+#[tracing::instrument(skip(state))]
+pub async fn camper_corrections_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(camper_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::camper_corrections;
+
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+
+    let corrections = camper_corrections::table
+        .filter(camper_corrections::camper_id.eq(camper.id))
+        .order(camper_corrections::created_at.desc())
+        .load::<CamperCorrection>(&mut conn)
+        .map_err(db_error("Failed to load corrections"))?;
+    Ok(axum::Json(json!({ "corrections": corrections })))
+}
+
+/// GET /admin/corrections?status= lists correction requests for review, oldest first.
+#[tracing::instrument(skip(state))]
+pub async fn list_corrections_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(query): Query<CorrectionsQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::camper_corrections;
+
+    let status = query.status.unwrap_or_else(|| "pending".to_string());
+    let mut corrections = camper_corrections::table
+        .order(camper_corrections::created_at.asc())
+        .limit(200)
+        .into_boxed();
+    match status.as_str() {
+        "all" => {}
+        "pending" | "approved" | "rejected" => {
+            corrections = corrections.filter(camper_corrections::status.eq(status.clone()));
+        }
+        other => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown correction status: {other}"),
+            ))
+        }
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let corrections = corrections
+        .load::<CamperCorrection>(&mut conn)
+        .map_err(db_error("Failed to load corrections"))?;
+    Ok(axum::Json(json!({ "corrections": corrections })))
+}
+
+/// Open registrations of the camper whose session does not admit `birthdate`.
+fn age_conflicts(
+    conn: &mut PgConnection,
+    camper_id: Uuid,
+    birthdate: NaiveDate,
+) -> Result<Vec<Uuid>, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, registrations};
+
+    let open = registrations::table
+        .filter(registrations::camper_id.eq(camper_id))
+        .filter(registrations::status.eq_any(OPEN_STATUSES))
+        .load::<Registration>(conn)?;
+    let session_ids: Vec<Uuid> = open.iter().map(|r| r.session_id).collect();
+    let sessions = camp_sessions::table
+        .filter(camp_sessions::id.eq_any(&session_ids))
+        .load::<CampSession>(conn)?;
+    Ok(open
+        .iter()
+        .filter(|registration| {
+            sessions
+                .iter()
+                .find(|s| s.id == registration.session_id)
+                .is_some_and(|session| !session.admits_age(birthdate))
+        })
+        .map(|registration| registration.id)
+        .collect())
+}
+
+/// Approves or rejects a pending correction, applying it on approval, and emails the
+/// guardian the outcome.
+async fn review(
+    actor: Actor,
+    state: Arc<Mutex<AppState>>,
+    correction_id: Uuid,
+    approve: bool,
+    note: Option<String>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{camper_corrections, campers, guardians};
+
+    let note = trimmed(note);
+    let mut conn = conn_from_state(&state).await?;
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let correction = camper_corrections::table
+            .find(correction_id)
+            .for_update()
+            .first::<CamperCorrection>(conn)
+            .optional()?;
+        let Some(correction) = correction else {
+            return Ok(Err((
+                StatusCode::NOT_FOUND,
+                "Correction not found".to_string(),
+            )));
+        };
+        if correction.status != "pending" {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                format!("Correction is already {}", correction.status),
+            )));
+        }
+
+        let camper = campers::table
+            .find(correction.camper_id)
+            .for_update()
+            .first::<Camper>(conn)?;
+        let now = chrono::Utc::now().naive_utc();
+        let (camper, correction) = if approve {
+            // Keep the replaced values on the request for the audit trail
+            let previous_first_name = correction
+                .first_name
+                .as_ref()
+                .map(|_| camper.first_name.clone());
+            let previous_last_name = correction
+                .last_name
+                .as_ref()
+                .map(|_| camper.last_name.clone());
+            let previous_birthdate = correction.birthdate.map(|_| camper.birthdate);
+            let camper = diesel::update(campers::table.find(camper.id))
+                .set((
+                    campers::first_name
+                        .eq(correction.first_name.clone().unwrap_or(camper.first_name)),
+                    campers::last_name.eq(correction.last_name.clone().unwrap_or(camper.last_name)),
+                    campers::birthdate.eq(correction.birthdate.unwrap_or(camper.birthdate)),
+                ))
+                .get_result::<Camper>(conn)?;
+            let correction = diesel::update(camper_corrections::table.find(correction.id))
+                .set((
+                    camper_corrections::status.eq("approved"),
+                    camper_corrections::previous_first_name.eq(previous_first_name),
+                    camper_corrections::previous_last_name.eq(previous_last_name),
+                    camper_corrections::previous_birthdate.eq(previous_birthdate),
+                    camper_corrections::reviewed_by.eq(actor.subject_id),
+                    camper_corrections::reviewer_role.eq(Some(actor.role.as_str())),
+                    camper_corrections::review_note.eq(note.clone()),
+                    camper_corrections::reviewed_at.eq(Some(now)),
+                ))
+                .get_result::<CamperCorrection>(conn)?;
+            (camper, correction)
+        } else {
+            let correction = diesel::update(camper_corrections::table.find(correction.id))
+                .set((
+                    camper_corrections::status.eq("rejected"),
+                    camper_corrections::reviewed_by.eq(actor.subject_id),
+                    camper_corrections::reviewer_role.eq(Some(actor.role.as_str())),
+                    camper_corrections::review_note.eq(note.clone()),
+                    camper_corrections::reviewed_at.eq(Some(now)),
+                ))
+                .get_result::<CamperCorrection>(conn)?;
+            (camper, correction)
+        };
+
+        let conflicts = match (approve, correction.birthdate) {
+            (true, Some(birthdate)) => age_conflicts(conn, camper.id, birthdate)?,
+            _ => Vec::new(),
+        };
+
+        EventRecorder::for_actor(&actor).record(
+            conn,
+            DomainEvent::new(
+                CORRECTION_REVIEWED,
+                format!(
+                    "Correction to {}'s details {}",
+                    camper.first_name, correction.status
+                ),
+            )
+            .camper(camper.id, correction.guardian_id)
+            .details(json!({
+                "correction_id": correction.id,
+                "status": correction.status,
+                "note": correction.review_note,
+            })),
+        )?;
+
+        let guardian = guardians::table
+            .find(correction.guardian_id)
+            .first::<Guardian>(conn)?;
+        let template = if approve {
+            "camper_correction_approved"
+        } else {
+            "camper_correction_rejected"
+        };
+        let notification_id = enqueue(
+            conn,
+            Notification {
+                channel: Channel::Email,
+                target: guardian.email,
+                template: template.to_string(),
+                payload: json!({
+                    "type": template,
+                    "correction_id": correction.id,
+                    "camper_id": camper.id,
+                    "camper_first_name": camper.first_name,
+                    "note": correction.review_note,
+                }),
+                registration_id: None,
+                payment_intent_id: None,
+            },
+        )?;
+        Ok(Ok((correction, camper, conflicts, notification_id)))
+    });
+    let (correction, camper, age_conflicts, notification_id) =
+        result.map_err(db_error("Failed to review correction"))??;
+    drop(conn);
+    info!(
+        "Correction {} for camper {} {} by {}",
+        correction.id, camper.id, correction.status, actor.role
+    );
+
+    dispatch_pending(&state, Some(&[notification_id])).await;
+    Ok(axum::Json(json!(CorrectionReviewedResponse {
+        correction,
+        camper,
+        age_conflicts,
+    })))
+}
+
+/// POST /admin/corrections/{id}/approve applies a pending correction to the camper.
+#[tracing::instrument(skip(state))]
+pub async fn approve_correction_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(correction_id): Path<Uuid>,
+    Json(payload): Json<ReviewRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    review(actor, state, correction_id, true, payload.note).await
+}
+
+/// POST /admin/corrections/{id}/reject closes a pending correction without applying it.
+#[tracing::instrument(skip(state))]
+pub async fn reject_correction_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(correction_id): Path<Uuid>,
+    Json(payload): Json<ReviewRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    review(actor, state, correction_id, false, payload.note).await
+}
@@ -1,8 +1,11 @@
+use axum::http::StatusCode;
 use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, Pool};
 use dotenv::dotenv;
-use lambda_lib::{PgPool, PgPooledConnection};
+use lambda_lib::{AppState, PgPool, PgPooledConnection};
 use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use tracing::{error, info};
 
 pub mod models;
@@ -40,3 +43,32 @@ pub fn get_conn(
         Box::new(e) as Box<dyn std::error::Error + Send + Sync>
     })
 }
+
+/// Checks out a pooled connection from the database client held in `AppState`,
+/// mapping failures to the `(StatusCode, String)` rejection used by handlers.
+pub async fn conn_from_state(
+    state: &Arc<Mutex<AppState>>,
+) -> Result<PgPooledConnection, (StatusCode, String)> {
+    let db_client = state.lock().await.database_client.clone();
+    let db_client = db_client.ok_or_else(|| {
+        error!("Database client not available in AppState");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Database not available".to_string(),
+        )
+    })?;
+    get_conn(&db_client.pool).map_err(|e| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Database connection error: {e}"),
+        )
+    })
+}
+
+/// Maps a diesel error to the `(StatusCode, String)` rejection used by handlers.
+pub fn db_error(context: &str) -> impl Fn(diesel::result::Error) -> (StatusCode, String) + '_ {
+    move |e| {
+        error!("{context}: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("{context}: {e}"))
+    }
+}
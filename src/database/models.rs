@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -57,6 +57,8 @@ pub struct PaymentEvent {
     pub currency: Option<String>,
     pub customer_id: Option<String>,
     pub metadata: Option<Value>,
+    /// The Stripe event this row was recorded from; `None` for rows written outside the webhook.
+    pub stripe_event_id: Option<String>,
 }
 
 #[derive(Insertable, Debug)]
@@ -69,9 +71,16 @@ pub struct NewPaymentEvent {
     pub currency: Option<String>,
     pub customer_id: Option<String>,
     pub metadata: Option<Value>,
+    pub stripe_event_id: Option<String>,
 }
 
 impl PaymentEvent {
+    /// Status recorded for `payment_intent.succeeded`. It comes from the `Display` impl
+    /// of `PaymentIntentStatus`, so queries should match it case-insensitively.
+    pub const SUCCEEDED: &'static str = "succeeded";
+    /// Status recorded when a PaymentSheet is created for the intent, before any webhook.
+    pub const SHEET_CREATED: &'static str = "payment_sheet_created";
+
     pub fn new(
         payment_intent_id: String,
         status: String,
@@ -88,6 +97,1187 @@ impl PaymentEvent {
             currency,
             customer_id,
             metadata,
+            stripe_event_id: None,
+        }
+    }
+}
+
+impl NewPaymentEvent {
+    /// Records which Stripe event this row comes from, so a redelivery of it conflicts.
+    pub fn from_stripe_event(mut self, event_id: String) -> Self {
+        self.stripe_event_id = Some(event_id);
+        self
+    }
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::guardians)]
+pub struct Guardian {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub stripe_customer_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// A `tax::BillingAddress`, once the guardian has entered one.
+    pub billing_address: Option<Value>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::guardians)]
+pub struct NewGuardian {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub stripe_customer_id: Option<String>,
+}
+
+impl Guardian {
+    pub fn new(email: String, name: String, stripe_customer_id: Option<String>) -> NewGuardian {
+        NewGuardian {
+            id: Uuid::new_v4(),
+            email,
+            name,
+            stripe_customer_id,
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::vouchers)]
+pub struct Voucher {
+    pub id: Uuid,
+    pub code: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+    pub purchaser_name: String,
+    pub purchaser_email: String,
+    pub recipient_email: Option<String>,
+    pub payment_intent_id: String,
+    pub status: String,
+    pub redeemed_by_guardian_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub redeemed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::vouchers)]
+pub struct NewVoucher {
+    pub id: Uuid,
+    pub amount: i64,
+    pub currency: String,
+    pub purchaser_name: String,
+    pub purchaser_email: String,
+    pub recipient_email: Option<String>,
+    pub payment_intent_id: String,
+    pub status: String,
+}
+
+impl Voucher {
+    pub fn new(
+        id: Uuid,
+        amount: i64,
+        currency: String,
+        purchaser_name: String,
+        purchaser_email: String,
+        recipient_email: Option<String>,
+        payment_intent_id: String,
+    ) -> NewVoucher {
+        NewVoucher {
+            id,
+            amount,
+            currency,
+            purchaser_name,
+            purchaser_email,
+            recipient_email,
+            payment_intent_id,
+            status: "pending".to_string(),
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::camp_credits)]
+pub struct CampCredit {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub amount: i64,
+    pub currency: String,
+    pub reason: String,
+    pub voucher_id: Option<Uuid>,
+    pub quote_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::camp_credits)]
+pub struct NewCampCredit {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub amount: i64,
+    pub currency: String,
+    pub reason: String,
+    pub voucher_id: Option<Uuid>,
+    pub quote_id: Option<Uuid>,
+}
+
+impl CampCredit {
+    pub fn new(
+        guardian_id: Uuid,
+        amount: i64,
+        currency: String,
+        reason: String,
+        voucher_id: Option<Uuid>,
+        quote_id: Option<Uuid>,
+    ) -> NewCampCredit {
+        NewCampCredit {
+            id: Uuid::new_v4(),
+            guardian_id,
+            amount,
+            currency,
+            reason,
+            voucher_id,
+            quote_id,
         }
     }
 }
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::quotes)]
+pub struct Quote {
+    pub id: Uuid,
+    pub guardian_id: Option<Uuid>,
+    pub currency: String,
+    pub subtotal: i64,
+    pub credit_applied: i64,
+    pub total: i64,
+    pub line_items: Value,
+    pub created_at: NaiveDateTime,
+    pub registration_ids: Vec<Uuid>,
+    pub processing_fee: i64,
+    /// `none`, `reserved` until the payment succeeds or the reservation lapses, then
+    /// `redeemed` or `released`. A released credit that was spent elsewhere before the
+    /// payment succeeded ends up `shortfall`.
+    pub credit_status: String,
+    /// When a `reserved` credit stops counting against the guardian's balance.
+    pub credit_expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::quotes)]
+pub struct NewQuote {
+    pub id: Uuid,
+    pub guardian_id: Option<Uuid>,
+    pub currency: String,
+    pub subtotal: i64,
+    pub credit_applied: i64,
+    pub total: i64,
+    pub line_items: Value,
+    pub registration_ids: Vec<Uuid>,
+    pub processing_fee: i64,
+    pub credit_status: String,
+    pub credit_expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::camp_sessions)]
+pub struct CampSession {
+    pub id: Uuid,
+    pub name: String,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub capacity: i32,
+    pub price: i64,
+    pub currency: String,
+    pub created_at: NaiveDateTime,
+    pub cancelled_at: Option<NaiveDateTime>,
+    pub session_type: String,
+    /// Youngest age, on the first day, a camper may be; unrestricted when unset.
+    pub min_age: Option<i32>,
+    /// Oldest age, on the first day, a camper may be; unrestricted when unset.
+    pub max_age: Option<i32>,
+    /// Paid registrations the session needs by `enrollment_cutoff` to run.
+    pub min_enrollment: Option<i32>,
+    pub enrollment_cutoff: Option<NaiveDate>,
+    /// When the session was flagged as at risk of missing its minimum.
+    pub enrollment_flagged_at: Option<NaiveDateTime>,
+}
+
+impl CampSession {
+    /// Whether the two sessions share at least one day.
+    pub fn overlaps(&self, other: &CampSession) -> bool {
+        self.starts_on <= other.ends_on && other.starts_on <= self.ends_on
+    }
+
+    /// Whether a camper born on `birthdate` is within the age range on the first day.
+    pub fn admits_age(&self, birthdate: NaiveDate) -> bool {
+        let Some(age) = self.starts_on.years_since(birthdate) else {
+            return false;
+        };
+        let age = i64::from(age);
+        self.min_age.is_none_or(|min| age >= i64::from(min))
+            && self.max_age.is_none_or(|max| age <= i64::from(max))
+    }
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[diesel(table_name = crate::database::schema::camp_sessions)]
+pub struct NewCampSession {
+    pub name: String,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub capacity: i32,
+    pub price: i64,
+    pub currency: String,
+    /// Groups sessions for waitlist estimates; `general` when omitted.
+    #[serde(default)]
+    pub session_type: Option<String>,
+    #[serde(default)]
+    pub min_age: Option<i32>,
+    #[serde(default)]
+    pub max_age: Option<i32>,
+    #[serde(default)]
+    pub min_enrollment: Option<i32>,
+    #[serde(default)]
+    pub enrollment_cutoff: Option<NaiveDate>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::staff)]
+pub struct Staff {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[diesel(table_name = crate::database::schema::staff)]
+pub struct NewStaff {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::staff_certifications)]
+pub struct StaffCertification {
+    pub id: Uuid,
+    pub staff_id: Uuid,
+    pub certification: String,
+    pub expires_on: Option<NaiveDate>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::staff_certifications)]
+pub struct NewStaffCertification {
+    pub staff_id: Uuid,
+    pub certification: String,
+    pub expires_on: Option<NaiveDate>,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::role_certifications)]
+pub struct RoleCertification {
+    pub role: String,
+    pub certification: String,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::staff_assignments)]
+pub struct StaffAssignment {
+    pub id: Uuid,
+    pub staff_id: Uuid,
+    pub session_id: Uuid,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::staff_assignments)]
+pub struct NewStaffAssignment {
+    pub staff_id: Uuid,
+    pub session_id: Uuid,
+    pub role: String,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::api_tokens)]
+pub struct ApiToken {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub role: String,
+    pub subject_id: Option<Uuid>,
+    pub label: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    /// Scopes narrowing what the role allows, e.g. `read_only`.
+    pub scopes: Vec<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::api_tokens)]
+pub struct NewApiToken {
+    pub id: Uuid,
+    pub token_hash: String,
+    pub role: String,
+    pub subject_id: Option<Uuid>,
+    pub label: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::campers)]
+pub struct Camper {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub first_name: String,
+    pub last_name: String,
+    pub birthdate: NaiveDate,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::campers)]
+pub struct NewCamper {
+    pub guardian_id: Uuid,
+    pub first_name: String,
+    pub last_name: String,
+    pub birthdate: NaiveDate,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::medical_records, primary_key(camper_id))]
+pub struct MedicalRecord {
+    pub camper_id: Uuid,
+    pub allergies: Option<String>,
+    pub medications: Option<String>,
+    pub conditions: Option<String>,
+    pub notes: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::medical_access_log)]
+pub struct MedicalAccess {
+    pub id: Uuid,
+    pub camper_id: Uuid,
+    pub reader_token_id: Option<Uuid>,
+    pub reader_role: String,
+    pub reader_subject_id: Option<Uuid>,
+    pub reason: Option<String>,
+    pub break_glass: bool,
+    pub accessed_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::medical_access_log)]
+pub struct NewMedicalAccess {
+    pub camper_id: Uuid,
+    pub reader_token_id: Option<Uuid>,
+    pub reader_role: String,
+    pub reader_subject_id: Option<Uuid>,
+    pub reason: Option<String>,
+    pub break_glass: bool,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::registrations)]
+pub struct Registration {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub camper_id: Uuid,
+    pub session_id: Uuid,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::registrations)]
+pub struct NewRegistration {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub camper_id: Uuid,
+    pub session_id: Uuid,
+    pub status: String,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::attendance_events)]
+pub struct AttendanceEvent {
+    pub id: Uuid,
+    pub registration_id: Uuid,
+    pub kind: String,
+    pub occurred_at: NaiveDateTime,
+    pub source: String,
+    pub operation_id: Option<Uuid>,
+    pub recorded_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    /// The authorized adult a camper was checked out to.
+    pub picked_up_by: Option<Uuid>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::attendance_events)]
+pub struct NewAttendanceEvent {
+    pub registration_id: Uuid,
+    pub kind: String,
+    pub occurred_at: NaiveDateTime,
+    pub source: String,
+    pub operation_id: Option<Uuid>,
+    pub recorded_by: Option<Uuid>,
+    pub picked_up_by: Option<Uuid>,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::kiosk_operations)]
+pub struct KioskOperation {
+    pub operation_id: Uuid,
+    pub kiosk_id: String,
+    pub operation_type: String,
+    pub registration_id: Option<Uuid>,
+    pub client_timestamp: NaiveDateTime,
+    pub outcome: String,
+    pub detail: Option<String>,
+    pub applied_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::registration_holds)]
+pub struct RegistrationHold {
+    pub id: Uuid,
+    pub registration_id: Uuid,
+    pub session_id: Uuid,
+    pub expires_at: NaiveDateTime,
+    pub released_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub payment_intent_id: Option<String>,
+    pub warned_at: Option<NaiveDateTime>,
+    pub expiry_notified_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::registration_holds)]
+pub struct NewRegistrationHold {
+    pub registration_id: Uuid,
+    pub session_id: Uuid,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::notification_outbox)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub channel: String,
+    pub target: String,
+    pub template: String,
+    pub payload: Value,
+    pub registration_id: Option<Uuid>,
+    pub payment_intent_id: Option<String>,
+    pub status: String,
+    pub attempts: i32,
+    pub created_at: NaiveDateTime,
+    pub sent_at: Option<NaiveDateTime>,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_error: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::notification_outbox)]
+pub struct NewOutboxMessage {
+    pub id: Uuid,
+    pub channel: String,
+    pub target: String,
+    pub template: String,
+    pub payload: Value,
+    pub registration_id: Option<Uuid>,
+    pub payment_intent_id: Option<String>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::notification_deliveries)]
+pub struct NotificationDelivery {
+    pub id: Uuid,
+    pub outbox_id: Option<Uuid>,
+    pub channel: String,
+    pub target: String,
+    pub template: String,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub latency_ms: i32,
+    pub attempt: i32,
+    pub payment_intent_id: Option<String>,
+    pub registration_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    /// When a failed attempt is retried; unset once a message is final.
+    pub next_attempt_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::notification_deliveries)]
+pub struct NewNotificationDelivery {
+    pub outbox_id: Option<Uuid>,
+    pub channel: String,
+    pub target: String,
+    pub template: String,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub latency_ms: i32,
+    pub attempt: i32,
+    pub payment_intent_id: Option<String>,
+    pub registration_id: Option<Uuid>,
+    pub next_attempt_at: Option<NaiveDateTime>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::exchange_rates)]
+pub struct ExchangeRate {
+    pub rate_date: NaiveDate,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub rate: f64,
+    pub fetched_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::exchange_rates)]
+pub struct NewExchangeRate {
+    pub rate_date: NaiveDate,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub rate: f64,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::export_jobs)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub kind: String,
+    pub params: Value,
+    pub status: String,
+    pub s3_key: Option<String>,
+    pub error: Option<String>,
+    pub requested_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::export_jobs)]
+pub struct NewExportJob {
+    pub id: Uuid,
+    pub kind: String,
+    pub params: Value,
+    pub requested_by: Option<Uuid>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::tags)]
+pub struct Tag {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::taggings)]
+pub struct Tagging {
+    pub id: Uuid,
+    pub tag_id: Uuid,
+    pub taggable_type: String,
+    pub taggable_id: Uuid,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::taggings)]
+pub struct NewTagging {
+    pub tag_id: Uuid,
+    pub taggable_type: String,
+    pub taggable_id: Uuid,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::admin_alerts)]
+pub struct AdminAlert {
+    pub id: Uuid,
+    pub kind: String,
+    pub message: String,
+    pub details: Value,
+    pub payment_intent_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub acknowledged_at: Option<NaiveDateTime>,
+    pub acknowledged_by: Option<Uuid>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::admin_alerts)]
+pub struct NewAdminAlert {
+    pub id: Uuid,
+    pub kind: String,
+    pub message: String,
+    pub details: Value,
+    pub payment_intent_id: Option<String>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::delegated_links)]
+pub struct DelegatedLink {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub registration_id: Uuid,
+    pub guardian_id: Uuid,
+    pub recipient_name: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub payment_intent_id: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::delegated_links)]
+pub struct NewDelegatedLink {
+    pub id: Uuid,
+    pub token_hash: String,
+    pub registration_id: Uuid,
+    pub guardian_id: Uuid,
+    pub recipient_name: Option<String>,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::refunds)]
+pub struct Refund {
+    pub id: Uuid,
+    pub stripe_refund_id: String,
+    pub payment_intent_id: String,
+    pub charge_id: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+    pub status: String,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub failure_reason: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::refunds)]
+pub struct NewRefund {
+    pub stripe_refund_id: String,
+    pub payment_intent_id: String,
+    pub charge_id: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+    pub status: String,
+    pub reason: Option<String>,
+    pub failure_reason: Option<String>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::payment_flags)]
+pub struct PaymentFlag {
+    pub id: Uuid,
+    pub kind: String,
+    pub stripe_object_id: String,
+    pub payment_intent_id: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+    pub details: Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::payment_flags)]
+pub struct NewPaymentFlag {
+    pub kind: String,
+    pub stripe_object_id: String,
+    pub payment_intent_id: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+    pub details: Value,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::session_cancellations)]
+pub struct SessionCancellation {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub reason: String,
+    pub requested_by: Option<Uuid>,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::session_cancellations)]
+pub struct NewSessionCancellation {
+    pub session_id: Uuid,
+    pub reason: String,
+    pub requested_by: Option<Uuid>,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::cancellation_refunds)]
+pub struct CancellationRefund {
+    pub id: Uuid,
+    /// `None` for a registration cancelled on its own rather than with its session.
+    pub cancellation_id: Option<Uuid>,
+    pub registration_id: Uuid,
+    pub guardian_id: Uuid,
+    pub payment_intent_id: Option<String>,
+    pub amount: i64,
+    pub credit_amount: i64,
+    pub currency: String,
+    pub status: String,
+    pub stripe_refund_id: Option<String>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// `camp`, `guardian`, or `late_payment` for payments that landed after their
+    /// registration was cancelled.
+    pub cause: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::cancellation_refunds)]
+pub struct NewCancellationRefund {
+    pub cancellation_id: Option<Uuid>,
+    pub registration_id: Uuid,
+    pub guardian_id: Uuid,
+    pub payment_intent_id: Option<String>,
+    pub amount: i64,
+    pub credit_amount: i64,
+    pub currency: String,
+    pub status: String,
+    pub cause: String,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::api_usage)]
+pub struct ApiUsage {
+    pub id: Uuid,
+    pub hour: NaiveDateTime,
+    pub frontend_id: String,
+    pub api_key: String,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::api_usage)]
+pub struct NewApiUsage {
+    pub hour: NaiveDateTime,
+    pub frontend_id: String,
+    pub api_key: String,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+}
+
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::waitlist_entries)]
+pub struct WaitlistEntry {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub camper_id: Uuid,
+    pub guardian_id: Uuid,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub promoted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::waitlist_entries)]
+pub struct NewWaitlistEntry {
+    pub session_id: Uuid,
+    pub camper_id: Uuid,
+    pub guardian_id: Uuid,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::waitlist_estimates)]
+pub struct WaitlistEstimate {
+    pub session_type: String,
+    pub sample_sessions: i32,
+    pub joined: i64,
+    pub promoted: i64,
+    /// How many families were promoted in each past session of this type.
+    pub promotions_per_session: Value,
+    pub median_days_to_promotion: Option<f64>,
+    pub computed_at: NaiveDateTime,
+}
+
+/// The latest webhook event applied to a payment intent, which later-arriving but
+/// older events must not override.
+#[derive(Queryable, Insertable, AsChangeset, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::payment_intent_states)]
+pub struct PaymentIntentState {
+    pub payment_intent_id: String,
+    pub status: String,
+    pub last_event_id: String,
+    pub last_event_type: String,
+    /// Stripe's `created` timestamp of the event.
+    pub last_event_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A guardian's request to correct a camper's details. Proposed fields left unset
+/// are not changed; the `previous_*` fields record the values an approval replaced.
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::camper_corrections)]
+pub struct CamperCorrection {
+    pub id: Uuid,
+    pub camper_id: Uuid,
+    pub guardian_id: Uuid,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+    pub reason: Option<String>,
+    pub status: String,
+    pub previous_first_name: Option<String>,
+    pub previous_last_name: Option<String>,
+    pub previous_birthdate: Option<NaiveDate>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewer_role: Option<String>,
+    pub review_note: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub reviewed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::camper_corrections)]
+pub struct NewCamperCorrection {
+    pub camper_id: Uuid,
+    pub guardian_id: Uuid,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub birthdate: Option<NaiveDate>,
+    pub reason: Option<String>,
+}
+
+/// A friend a camper wants to share a cabin with, named by their guardian at
+/// registration. Staff match the name against the session's campers.
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::friend_requests)]
+pub struct FriendRequest {
+    pub id: Uuid,
+    pub registration_id: Uuid,
+    pub friend_name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::friend_requests)]
+pub struct NewFriendRequest {
+    pub registration_id: Uuid,
+    pub friend_name: String,
+}
+
+#[derive(Queryable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::cabin_assignments)]
+pub struct CabinAssignment {
+    pub registration_id: Uuid,
+    pub session_id: Uuid,
+    pub cabin: String,
+    pub assigned_by: Option<Uuid>,
+    pub assigned_at: NaiveDateTime,
+}
+
+/// The payment method type of a successful charge, from its `charge.succeeded` event.
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::charge_payment_methods)]
+pub struct ChargePaymentMethod {
+    pub charge_id: String,
+    pub payment_intent_id: Option<String>,
+    /// Stripe's payment method type, e.g. `card` or `us_bank_account`.
+    pub method_type: String,
+    pub amount: i64,
+    pub currency: String,
+    pub succeeded_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::charge_payment_methods)]
+pub struct NewChargePaymentMethod {
+    pub charge_id: String,
+    pub payment_intent_id: Option<String>,
+    pub method_type: String,
+    pub amount: i64,
+    pub currency: String,
+    pub succeeded_at: NaiveDateTime,
+}
+
+/// A PaymentIntent created for a customer, counted by the payment velocity checks.
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::payment_sheet_attempts)]
+pub struct NewPaymentSheetAttempt {
+    /// Lowercased.
+    pub customer_email: String,
+    pub payment_intent_id: String,
+    pub amount: i64,
+    pub currency: String,
+}
+
+/// An admin-granted exception to the payment limits for one customer and currency.
+#[derive(Queryable, Debug, Clone, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::payment_limit_overrides)]
+pub struct PaymentLimitOverride {
+    pub id: Uuid,
+    pub customer_email: String,
+    pub currency: String,
+    pub max_amount: i64,
+    pub reason: String,
+    pub created_by: Option<Uuid>,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::payment_limit_overrides)]
+pub struct NewPaymentLimitOverride {
+    pub customer_email: String,
+    pub currency: String,
+    pub max_amount: i64,
+    pub reason: String,
+    pub created_by: Option<Uuid>,
+    pub expires_at: NaiveDateTime,
+}
+
+/// An entry in the append-only domain event log.
+#[derive(Queryable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::domain_events)]
+pub struct DomainEventRecord {
+    pub id: i64,
+    pub kind: String,
+    pub summary: String,
+    /// The acting role, or `system` for webhooks and jobs.
+    pub actor_role: String,
+    pub actor_id: Option<Uuid>,
+    pub camper_id: Option<Uuid>,
+    pub guardian_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub registration_id: Option<Uuid>,
+    pub payment_intent_id: Option<String>,
+    pub details: Value,
+    pub occurred_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::domain_events)]
+pub struct NewDomainEvent {
+    pub kind: String,
+    pub summary: String,
+    pub actor_role: String,
+    pub actor_id: Option<Uuid>,
+    pub camper_id: Option<Uuid>,
+    pub guardian_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub registration_id: Option<Uuid>,
+    pub payment_intent_id: Option<String>,
+    pub details: Value,
+    pub occurred_at: NaiveDateTime,
+}
+
+/// The receipt number allocated to a successful payment.
+#[derive(Queryable, Debug, Clone, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::receipt_numbers)]
+pub struct ReceiptNumber {
+    pub payment_intent_id: String,
+    pub organization: String,
+    /// Position in the organization's sequence, starting at 1.
+    pub number: i64,
+    /// The number as printed, with the organization's prefix.
+    pub receipt_number: String,
+    pub amount: i64,
+    pub currency: String,
+    pub issued_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::receipt_numbers)]
+pub struct NewReceiptNumber {
+    pub payment_intent_id: String,
+    pub organization: String,
+    pub number: i64,
+    pub receipt_number: String,
+    pub amount: i64,
+    pub currency: String,
+}
+
+/// A date-driven notification that was sent, so it is not sent again.
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::notification_rule_sends)]
+pub struct NewNotificationRuleSend {
+    pub rule: String,
+    /// The camper for birthdays, the registration for session countdowns.
+    pub subject_id: Uuid,
+    /// Which occurrence, e.g. the year of a birthday.
+    pub occasion: String,
+    pub outbox_message_id: Uuid,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::notification_opt_outs)]
+pub struct NewNotificationOptOut {
+    pub guardian_id: Uuid,
+    pub rule: String,
+}
+
+/// A registration form saved part-way through.
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::registration_drafts)]
+pub struct RegistrationDraft {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub camper_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub friend_requests: Vec<String>,
+    /// The rest of the form, as the client sent it.
+    pub answers: Value,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// Drafts left untouched until then are deleted.
+    pub expires_at: NaiveDateTime,
+    /// The registration the draft became at checkout.
+    pub registration_id: Option<Uuid>,
+    pub converted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::registration_drafts)]
+pub struct NewRegistrationDraft {
+    pub id: Uuid,
+    pub guardian_id: Uuid,
+    pub camper_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub friend_requests: Vec<String>,
+    pub answers: Value,
+    pub expires_at: NaiveDateTime,
+}
+
+/// How often Stripe sent an event type.
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::webhook_event_counts)]
+pub struct WebhookEventCount {
+    pub event_type: String,
+    pub received: i64,
+    /// Of those, how many the event filter dropped.
+    pub filtered: i64,
+    pub first_received_at: NaiveDateTime,
+    pub last_received_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::webhook_event_counts)]
+pub struct NewWebhookEventCount {
+    pub event_type: String,
+    pub received: i64,
+    pub filtered: i64,
+}
+
+/// A ticket opened with the support desk for a family's failing payments.
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::support_tickets)]
+pub struct SupportTicketRecord {
+    pub id: Uuid,
+    pub rule: String,
+    /// The payer's email, or the Stripe customer when it is unknown.
+    pub family: String,
+    pub provider: String,
+    pub payment_intent_ids: Vec<String>,
+    /// `pending`, `opened` or `failed`.
+    pub status: String,
+    /// The provider's ticket id or key.
+    pub reference: Option<String>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::support_tickets)]
+pub struct NewSupportTicket {
+    pub id: Uuid,
+    pub rule: String,
+    pub family: String,
+    pub provider: String,
+    pub payment_intent_ids: Vec<String>,
+}
+
+/// An adult allowed to pick a camper up at check-out.
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::authorized_pickups)]
+pub struct AuthorizedPickup {
+    pub id: Uuid,
+    pub registration_id: Uuid,
+    pub name: String,
+    /// e.g. `grandparent` or `neighbor`.
+    pub relationship: String,
+    pub photo_url: Option<String>,
+    /// What staff should check, e.g. "Shows driver's license".
+    pub id_note: Option<String>,
+    pub added_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    /// Removed adults are kept so past check-outs still name them.
+    pub removed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::authorized_pickups)]
+pub struct NewAuthorizedPickup {
+    pub registration_id: Uuid,
+    pub name: String,
+    pub relationship: String,
+    pub photo_url: Option<String>,
+    pub id_note: Option<String>,
+    pub added_by: Option<Uuid>,
+}
+
+/// A freed seat offered to the family first on a waitlist. The offer lasts as long
+/// as the hold on its pending registration.
+#[derive(Queryable, Clone, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::database::schema::waitlist_offers)]
+pub struct WaitlistOffer {
+    pub id: Uuid,
+    pub waitlist_entry_id: Uuid,
+    pub registration_id: Uuid,
+    pub hold_id: Uuid,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    /// `open`, `paid` or `expired`.
+    pub status: String,
+    pub expires_at: NaiveDateTime,
+    pub payment_intent_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+impl WaitlistOffer {
+    /// Whether the offer can still be paid at `now`.
+    pub fn is_open(&self, now: NaiveDateTime) -> bool {
+        self.status == "open" && self.expires_at > now
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::database::schema::waitlist_offers)]
+pub struct NewWaitlistOffer {
+    pub waitlist_entry_id: Uuid,
+    pub registration_id: Uuid,
+    pub hold_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
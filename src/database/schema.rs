@@ -24,5 +24,639 @@ table! {
         currency -> Nullable<Text>,
         customer_id -> Nullable<Text>,
         metadata -> Nullable<Json>,
+        stripe_event_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    guardians (id) {
+        id -> Uuid,
+        email -> Text,
+        name -> Text,
+        stripe_customer_id -> Nullable<Text>,
+        created_at -> Timestamp,
+        billing_address -> Nullable<Jsonb>,
+    }
+}
+
+table! {
+    vouchers (id) {
+        id -> Uuid,
+        code -> Nullable<Text>,
+        amount -> Int8,
+        currency -> Text,
+        purchaser_name -> Text,
+        purchaser_email -> Text,
+        recipient_email -> Nullable<Text>,
+        payment_intent_id -> Text,
+        status -> Text,
+        redeemed_by_guardian_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        redeemed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    camp_credits (id) {
+        id -> Uuid,
+        guardian_id -> Uuid,
+        amount -> Int8,
+        currency -> Text,
+        reason -> Text,
+        voucher_id -> Nullable<Uuid>,
+        quote_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    quotes (id) {
+        id -> Uuid,
+        guardian_id -> Nullable<Uuid>,
+        currency -> Text,
+        subtotal -> Int8,
+        credit_applied -> Int8,
+        total -> Int8,
+        line_items -> Jsonb,
+        created_at -> Timestamp,
+        registration_ids -> Array<Uuid>,
+        processing_fee -> Int8,
+        credit_status -> Text,
+        credit_expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    camp_sessions (id) {
+        id -> Uuid,
+        name -> Text,
+        starts_on -> Date,
+        ends_on -> Date,
+        capacity -> Int4,
+        price -> Int8,
+        currency -> Text,
+        created_at -> Timestamp,
+        cancelled_at -> Nullable<Timestamp>,
+        session_type -> Text,
+        min_age -> Nullable<Int4>,
+        max_age -> Nullable<Int4>,
+        min_enrollment -> Nullable<Int4>,
+        enrollment_cutoff -> Nullable<Date>,
+        enrollment_flagged_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    staff (id) {
+        id -> Uuid,
+        name -> Text,
+        email -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    staff_certifications (id) {
+        id -> Uuid,
+        staff_id -> Uuid,
+        certification -> Text,
+        expires_on -> Nullable<Date>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    role_certifications (role, certification) {
+        role -> Text,
+        certification -> Text,
+    }
+}
+
+table! {
+    staff_assignments (id) {
+        id -> Uuid,
+        staff_id -> Uuid,
+        session_id -> Uuid,
+        role -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    api_tokens (id) {
+        id -> Uuid,
+        token_hash -> Text,
+        role -> Text,
+        subject_id -> Nullable<Uuid>,
+        label -> Text,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        scopes -> Array<Text>,
+    }
+}
+
+table! {
+    campers (id) {
+        id -> Uuid,
+        guardian_id -> Uuid,
+        first_name -> Text,
+        last_name -> Text,
+        birthdate -> Date,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    medical_records (camper_id) {
+        camper_id -> Uuid,
+        allergies -> Nullable<Text>,
+        medications -> Nullable<Text>,
+        conditions -> Nullable<Text>,
+        notes -> Nullable<Text>,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    medical_access_log (id) {
+        id -> Uuid,
+        camper_id -> Uuid,
+        reader_token_id -> Nullable<Uuid>,
+        reader_role -> Text,
+        reader_subject_id -> Nullable<Uuid>,
+        reason -> Nullable<Text>,
+        break_glass -> Bool,
+        accessed_at -> Timestamp,
+    }
+}
+
+table! {
+    registrations (id) {
+        id -> Uuid,
+        guardian_id -> Uuid,
+        camper_id -> Uuid,
+        session_id -> Uuid,
+        status -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    attendance_events (id) {
+        id -> Uuid,
+        registration_id -> Uuid,
+        kind -> Text,
+        occurred_at -> Timestamp,
+        source -> Text,
+        operation_id -> Nullable<Uuid>,
+        recorded_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        picked_up_by -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    kiosk_operations (operation_id) {
+        operation_id -> Uuid,
+        kiosk_id -> Text,
+        operation_type -> Text,
+        registration_id -> Nullable<Uuid>,
+        client_timestamp -> Timestamp,
+        outcome -> Text,
+        detail -> Nullable<Text>,
+        applied_at -> Timestamp,
+    }
+}
+
+table! {
+    registration_holds (id) {
+        id -> Uuid,
+        registration_id -> Uuid,
+        session_id -> Uuid,
+        expires_at -> Timestamp,
+        released_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        payment_intent_id -> Nullable<Text>,
+        warned_at -> Nullable<Timestamp>,
+        expiry_notified_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    notification_outbox (id) {
+        id -> Uuid,
+        channel -> Text,
+        target -> Text,
+        template -> Text,
+        payload -> Jsonb,
+        registration_id -> Nullable<Uuid>,
+        payment_intent_id -> Nullable<Text>,
+        status -> Text,
+        attempts -> Int4,
+        created_at -> Timestamp,
+        sent_at -> Nullable<Timestamp>,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+    }
+}
+
+table! {
+    notification_deliveries (id) {
+        id -> Uuid,
+        outbox_id -> Nullable<Uuid>,
+        channel -> Text,
+        target -> Text,
+        template -> Text,
+        outcome -> Text,
+        error -> Nullable<Text>,
+        latency_ms -> Int4,
+        attempt -> Int4,
+        payment_intent_id -> Nullable<Text>,
+        registration_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        next_attempt_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    exchange_rates (rate_date, base_currency, quote_currency) {
+        rate_date -> Date,
+        base_currency -> Text,
+        quote_currency -> Text,
+        rate -> Float8,
+        fetched_at -> Timestamp,
+    }
+}
+
+table! {
+    export_jobs (id) {
+        id -> Uuid,
+        kind -> Text,
+        params -> Jsonb,
+        status -> Text,
+        s3_key -> Nullable<Text>,
+        error -> Nullable<Text>,
+        requested_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        completed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Uuid,
+        name -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    taggings (id) {
+        id -> Uuid,
+        tag_id -> Uuid,
+        taggable_type -> Text,
+        taggable_id -> Uuid,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    admin_alerts (id) {
+        id -> Uuid,
+        kind -> Text,
+        message -> Text,
+        details -> Jsonb,
+        payment_intent_id -> Nullable<Text>,
+        created_at -> Timestamp,
+        acknowledged_at -> Nullable<Timestamp>,
+        acknowledged_by -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    delegated_links (id) {
+        id -> Uuid,
+        token_hash -> Text,
+        registration_id -> Uuid,
+        guardian_id -> Uuid,
+        recipient_name -> Nullable<Text>,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        payment_intent_id -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    refunds (id) {
+        id -> Uuid,
+        stripe_refund_id -> Text,
+        payment_intent_id -> Text,
+        charge_id -> Nullable<Text>,
+        amount -> Int8,
+        currency -> Text,
+        status -> Text,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        failure_reason -> Nullable<Text>,
+    }
+}
+
+table! {
+    payment_flags (id) {
+        id -> Uuid,
+        kind -> Text,
+        stripe_object_id -> Text,
+        payment_intent_id -> Nullable<Text>,
+        amount -> Int8,
+        currency -> Text,
+        details -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    session_cancellations (id) {
+        id -> Uuid,
+        session_id -> Uuid,
+        reason -> Text,
+        requested_by -> Nullable<Uuid>,
+        status -> Text,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    cancellation_refunds (id) {
+        id -> Uuid,
+        cancellation_id -> Nullable<Uuid>,
+        registration_id -> Uuid,
+        guardian_id -> Uuid,
+        payment_intent_id -> Nullable<Text>,
+        amount -> Int8,
+        credit_amount -> Int8,
+        currency -> Text,
+        status -> Text,
+        stripe_refund_id -> Nullable<Text>,
+        error -> Nullable<Text>,
+        attempts -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        cause -> Text,
+    }
+}
+
+table! {
+    api_usage (id) {
+        id -> Uuid,
+        hour -> Timestamp,
+        frontend_id -> Text,
+        api_key -> Text,
+        requests -> Int8,
+        client_errors -> Int8,
+        server_errors -> Int8,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    waitlist_entries (id) {
+        id -> Uuid,
+        session_id -> Uuid,
+        camper_id -> Uuid,
+        guardian_id -> Uuid,
+        status -> Text,
+        created_at -> Timestamp,
+        promoted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    waitlist_estimates (session_type) {
+        session_type -> Text,
+        sample_sessions -> Int4,
+        joined -> Int8,
+        promoted -> Int8,
+        promotions_per_session -> Jsonb,
+        median_days_to_promotion -> Nullable<Float8>,
+        computed_at -> Timestamp,
+    }
+}
+
+table! {
+    payment_intent_states (payment_intent_id) {
+        payment_intent_id -> Text,
+        status -> Text,
+        last_event_id -> Text,
+        last_event_type -> Text,
+        last_event_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    camper_corrections (id) {
+        id -> Uuid,
+        camper_id -> Uuid,
+        guardian_id -> Uuid,
+        first_name -> Nullable<Text>,
+        last_name -> Nullable<Text>,
+        birthdate -> Nullable<Date>,
+        reason -> Nullable<Text>,
+        status -> Text,
+        previous_first_name -> Nullable<Text>,
+        previous_last_name -> Nullable<Text>,
+        previous_birthdate -> Nullable<Date>,
+        reviewed_by -> Nullable<Uuid>,
+        reviewer_role -> Nullable<Text>,
+        review_note -> Nullable<Text>,
+        created_at -> Timestamp,
+        reviewed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    friend_requests (id) {
+        id -> Uuid,
+        registration_id -> Uuid,
+        friend_name -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    cabin_assignments (registration_id) {
+        registration_id -> Uuid,
+        session_id -> Uuid,
+        cabin -> Text,
+        assigned_by -> Nullable<Uuid>,
+        assigned_at -> Timestamp,
+    }
+}
+
+table! {
+    charge_payment_methods (charge_id) {
+        charge_id -> Text,
+        payment_intent_id -> Nullable<Text>,
+        method_type -> Text,
+        amount -> Int8,
+        currency -> Text,
+        succeeded_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    payment_sheet_attempts (id) {
+        id -> Uuid,
+        customer_email -> Text,
+        payment_intent_id -> Text,
+        amount -> Int8,
+        currency -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    payment_limit_overrides (id) {
+        id -> Uuid,
+        customer_email -> Text,
+        currency -> Text,
+        max_amount -> Int8,
+        reason -> Text,
+        created_by -> Nullable<Uuid>,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    domain_events (id) {
+        id -> Int8,
+        kind -> Text,
+        summary -> Text,
+        actor_role -> Text,
+        actor_id -> Nullable<Uuid>,
+        camper_id -> Nullable<Uuid>,
+        guardian_id -> Nullable<Uuid>,
+        session_id -> Nullable<Uuid>,
+        registration_id -> Nullable<Uuid>,
+        payment_intent_id -> Nullable<Text>,
+        details -> Jsonb,
+        occurred_at -> Timestamp,
+    }
+}
+
+table! {
+    receipt_sequences (organization) {
+        organization -> Text,
+        next_number -> Int8,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    receipt_numbers (payment_intent_id) {
+        payment_intent_id -> Text,
+        organization -> Text,
+        number -> Int8,
+        receipt_number -> Text,
+        amount -> Int8,
+        currency -> Text,
+        issued_at -> Timestamp,
+    }
+}
+
+table! {
+    notification_rule_sends (rule, subject_id, occasion) {
+        rule -> Text,
+        subject_id -> Uuid,
+        occasion -> Text,
+        outbox_message_id -> Uuid,
+        sent_at -> Timestamp,
+    }
+}
+
+table! {
+    notification_opt_outs (guardian_id, rule) {
+        guardian_id -> Uuid,
+        rule -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    registration_drafts (id) {
+        id -> Uuid,
+        guardian_id -> Uuid,
+        camper_id -> Nullable<Uuid>,
+        session_id -> Nullable<Uuid>,
+        friend_requests -> Array<Text>,
+        answers -> Jsonb,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        expires_at -> Timestamp,
+        registration_id -> Nullable<Uuid>,
+        converted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhook_event_counts (event_type) {
+        event_type -> Text,
+        received -> Int8,
+        filtered -> Int8,
+        first_received_at -> Timestamp,
+        last_received_at -> Timestamp,
+    }
+}
+
+table! {
+    support_tickets (id) {
+        id -> Uuid,
+        rule -> Text,
+        family -> Text,
+        provider -> Text,
+        payment_intent_ids -> Array<Text>,
+        status -> Text,
+        reference -> Nullable<Text>,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    authorized_pickups (id) {
+        id -> Uuid,
+        registration_id -> Uuid,
+        name -> Text,
+        relationship -> Text,
+        photo_url -> Nullable<Text>,
+        id_note -> Nullable<Text>,
+        added_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        removed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    waitlist_offers (id) {
+        id -> Uuid,
+        waitlist_entry_id -> Uuid,
+        registration_id -> Uuid,
+        hold_id -> Uuid,
+        token_hash -> Text,
+        status -> Text,
+        expires_at -> Timestamp,
+        payment_intent_id -> Nullable<Text>,
+        created_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
     }
 }
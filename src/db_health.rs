@@ -0,0 +1,199 @@
+//! Degraded mode while the database is unreachable.
+//!
+//! Database failures used to be logged and swallowed, so a Postgres outage lost
+//! webhook events and WebSocket subscriptions without a trace. Code paths that hit a
+//! failure report it to the shared [`DatabaseHealth`], which puts the instance in
+//! degraded mode until a database call succeeds again:
+//!
+//! - Payment webhooks answer 500 so Stripe redelivers the event later, unless
+//!   `WEBHOOK_FAILURE_RESPONSES` says otherwise; see [`crate::webhook_failures`].
+//! - WebSocket subscriptions are buffered in memory (up to
+//!   [`MAX_BUFFERED_REGISTRATIONS`], oldest dropped first) and written once the
+//!   database is back.
+//! - `GET /ready` answers 503 with the degraded state, so load balancers and
+//!   deploys can see it. Each call also probes the database and flushes the buffer
+//!   when it is reachable.
+use crate::database::{conn_from_state, models::NewWebSocketConnection};
+use crate::metrics;
+use axum::{extract::Extension, http::StatusCode};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+/// Most WebSocket subscriptions held while the database is unreachable.
+pub const MAX_BUFFERED_REGISTRATIONS: usize = 1000;
+
+#[derive(Debug, Default)]
+struct HealthState {
+    degraded_since: Option<NaiveDateTime>,
+    last_failure: Option<&'static str>,
+    pending: VecDeque<NewWebSocketConnection>,
+    dropped: u64,
+}
+
+/// Whether the database is reachable, and the writes waiting for it.
+#[derive(Debug, Default)]
+pub struct DatabaseHealth {
+    state: std::sync::Mutex<HealthState>,
+}
+
+/// `ok`, or `degraded` while the database is unreachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseStatus {
+    Ok,
+    Degraded,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub database: DatabaseStatus,
+    pub degraded_since: Option<NaiveDateTime>,
+    /// Where the most recent failure happened, e.g. `webhook` or `websocket`.
+    pub last_failure: Option<&'static str>,
+    pub buffered_ws_registrations: usize,
+    /// Subscriptions dropped because the buffer was full, since startup.
+    pub dropped_ws_registrations: u64,
+}
+
+impl DatabaseHealth {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HealthState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records a failed database call and enters degraded mode.
+    pub fn failed(&self, context: &'static str, e: impl fmt::Display) {
+        let mut state = self.lock();
+        if state.degraded_since.is_none() {
+            warn!("Database unavailable ({context}): {e}; entering degraded mode");
+            state.degraded_since = Some(chrono::Utc::now().naive_utc());
+        } else {
+            error!("Database still unavailable ({context}): {e}");
+        }
+        state.last_failure = Some(context);
+        metrics::increment("database_failures_total", &[("context", context)]);
+    }
+
+    /// Records a successful database call and leaves degraded mode.
+    pub fn recovered(&self) {
+        let mut state = self.lock();
+        if let Some(since) = state.degraded_since.take() {
+            info!("Database reachable again; degraded since {since}");
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.lock().degraded_since.is_some()
+    }
+
+    /// Holds a subscription until it can be written, dropping the oldest one when
+    /// the buffer is full.
+    pub fn buffer_registration(&self, registration: NewWebSocketConnection) {
+        let mut state = self.lock();
+        state.pending.push_back(registration);
+        if state.pending.len() > MAX_BUFFERED_REGISTRATIONS {
+            state.pending.pop_front();
+            state.dropped += 1;
+            metrics::increment("ws_registrations_dropped_total", &[]);
+        }
+    }
+
+    /// Forgets buffered subscriptions of a connection that has closed.
+    pub fn discard_registrations(&self, connection_id: &str) {
+        self.lock()
+            .pending
+            .retain(|registration| registration.connection_id != connection_id);
+    }
+
+    pub fn buffered_registrations(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    /// Writes the buffered subscriptions. They stay buffered if the write fails.
+    pub fn flush_registrations(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        let pending: Vec<NewWebSocketConnection> = self.lock().pending.drain(..).collect();
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        match diesel::insert_into(crate::database::schema::websocket_connections::table)
+            .values(&pending)
+            .execute(conn)
+        {
+            Ok(_) => Ok(pending.len()),
+            Err(e) => {
+                // Put them back ahead of anything buffered meanwhile
+                let mut state = self.lock();
+                for registration in pending.into_iter().rev() {
+                    state.pending.push_front(registration);
+                }
+                while state.pending.len() > MAX_BUFFERED_REGISTRATIONS {
+                    state.pending.pop_front();
+                    state.dropped += 1;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub fn report(&self) -> ReadinessReport {
+        let state = self.lock();
+        ReadinessReport {
+            ready: state.degraded_since.is_none(),
+            database: if state.degraded_since.is_some() {
+                DatabaseStatus::Degraded
+            } else {
+                DatabaseStatus::Ok
+            },
+            degraded_since: state.degraded_since,
+            last_failure: state.last_failure,
+            buffered_ws_registrations: state.pending.len(),
+            dropped_ws_registrations: state.dropped,
+        }
+    }
+}
+
+/// GET /ready probes the database, flushes buffered writes when it is reachable and
+/// reports readiness: 200 when ready, 503 while degraded.
+#[tracing::instrument(skip(state, health))]
+pub async fn readiness_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(health): Extension<Arc<DatabaseHealth>>,
+) -> (StatusCode, axum::Json<Value>) {
+    let probe = match conn_from_state(&state).await {
+        Ok(mut conn) => diesel::sql_query("SELECT 1")
+            .execute(&mut conn)
+            .map(|_| conn)
+            .map_err(|e| e.to_string()),
+        Err((_, e)) => Err(e),
+    };
+    match probe {
+        Ok(mut conn) => {
+            health.recovered();
+            match health.flush_registrations(&mut conn) {
+                Ok(0) => {}
+                Ok(flushed) => info!("Flushed {flushed} buffered WebSocket subscription(s)"),
+                Err(e) => health.failed("readiness", e),
+            }
+        }
+        Err(e) => health.failed("readiness", e),
+    }
+
+    let report = health.report();
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, axum::Json(json!(report)))
+}
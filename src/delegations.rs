@@ -0,0 +1,417 @@
+//! Delegated registration links.
+//!
+//! A guardian can share a pending registration with someone outside the family
+//! account (typically a grandparent) through an expiring link. The link token only
+//! unlocks a summary of that one registration and a PaymentSheet for it; it is not
+//! an API token and grants no access to the guardian's campers, credit or other
+//! registrations.
+//!
+//! Links are for paying, not for filling in the form. A registration draft holds
+//! the family's answers, which the link must not expose, so the guardian converts
+//! the draft with `POST /registrations/draft/{id}/convert` and shares the pending
+//! registration it creates. Once that registration is paid, the link stops working.
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::{generate_token, hash_token, Actor};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{
+        CampSession, Camper, DelegatedLink, NewDelegatedLink, Registration, RegistrationHold,
+    },
+};
+use crate::handlers::{
+    create_checkout, parse_currency, record_sheet_created, PaymentSheetResponse,
+};
+use crate::holds::{link_holds_to_intent, open_hold, place_hold, seats_taken};
+use crate::payment_limits::{record_attempt, PaymentLimits};
+use crate::payment_metadata::PaymentMetadata;
+use crate::processing_fees::ProcessingFees;
+use crate::quotes::{price_registrations, quote_registrations};
+use crate::registrations::load_registration;
+use crate::stripe_keys::{StripeCapability, StripeKeyring};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const DEFAULT_LINK_TTL_HOURS: i64 = 72;
+const MAX_LINK_TTL_HOURS: i64 = 14 * 24;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDelegatedLinkRequest {
+    pub recipient_name: Option<String>,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DelegatedPaymentSheetRequest {
+    pub payer_name: String,
+    pub payer_email: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DelegatedLinkCreatedResponse {
+    /// Only returned here; the link cannot be recovered later.
+    pub token: String,
+    pub url: Option<String>,
+    pub link: DelegatedLink,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DelegatedSessionSummary {
+    pub name: String,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DelegatedRegistrationResponse {
+    pub registration_id: Uuid,
+    pub camper_first_name: String,
+    pub session: DelegatedSessionSummary,
+    /// What the PaymentSheet will charge: the session price with its discounts and
+    /// any processing fee, without the guardian's camp credit.
+    pub amount: i64,
+    pub currency: String,
+    pub recipient_name: Option<String>,
+    pub link_expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DelegatedPaymentSheetResponse {
+    #[serde(flatten)]
+    pub payment_sheet: PaymentSheetResponse,
+    pub quote_id: Uuid,
+    pub amount: i64,
+    pub currency: String,
+    pub hold_expires_at: NaiveDateTime,
+}
+
+/// The shareable URL for a link token, when `DELEGATED_LINK_BASE_URL` is configured.
+fn link_url(token: &str) -> Option<String> {
+    std::env::var("DELEGATED_LINK_BASE_URL")
+        .ok()
+        .filter(|base| !base.is_empty())
+        .map(|base| format!("{}/{token}", base.trim_end_matches('/')))
+}
+
+/// Resolves a link token to its link and registration. Unknown tokens are 404,
+/// revoked or expired links are 410 LINK_EXPIRED, and links for registrations that
+/// are no longer pending are 409 REGISTRATION_NOT_PAYABLE.
+fn load_active_link(
+    conn: &mut PgConnection,
+    token: &str,
+) -> Result<(DelegatedLink, Registration), ApiError> {
+    use crate::database::schema::{delegated_links, registrations};
+
+    let link = delegated_links::table
+        .filter(delegated_links::token_hash.eq(hash_token(token)))
+        .first::<DelegatedLink>(conn)
+        .optional()
+        .map_err(db_error("Failed to load delegated link"))?
+        .ok_or((StatusCode::NOT_FOUND, "Link not found".to_string()))?;
+
+    let now = chrono::Utc::now().naive_utc();
+    if link.revoked_at.is_some() || link.expires_at <= now {
+        return Err(ApiError::new(
+            StatusCode::GONE,
+            ErrorCode::LinkExpired,
+            "Link has expired",
+        ));
+    }
+
+    let registration = registrations::table
+        .find(link.registration_id)
+        .first::<Registration>(conn)
+        .map_err(db_error("Failed to load registration"))?;
+    if registration.status != "pending" {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            ErrorCode::RegistrationNotPayable,
+            format!("Registration is {}", registration.status),
+        ));
+    }
+    Ok((link, registration))
+}
+
+/// POST /registrations/{id}/delegations creates an expiring link that lets someone
+/// else pay for a pending registration. The token is only returned once.
+#[tracing::instrument(skip(state))]
+pub async fn create_delegated_link_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+    Json(payload): Json<CreateDelegatedLinkRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let ttl_hours = payload.expires_in_hours.unwrap_or(DEFAULT_LINK_TTL_HOURS);
+    if !(1..=MAX_LINK_TTL_HOURS).contains(&ttl_hours) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_hours must be between 1 and {MAX_LINK_TTL_HOURS}"),
+        ));
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    if registration.status != "pending" {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Registration is {}", registration.status),
+        ));
+    }
+
+    let token = generate_token();
+    let link = diesel::insert_into(crate::database::schema::delegated_links::table)
+        .values(&NewDelegatedLink {
+            id: Uuid::new_v4(),
+            token_hash: hash_token(&token),
+            registration_id: registration.id,
+            guardian_id: registration.guardian_id,
+            recipient_name: payload.recipient_name,
+            expires_at: chrono::Utc::now().naive_utc() + chrono::Duration::hours(ttl_hours),
+        })
+        .get_result::<DelegatedLink>(&mut conn)
+        .map_err(db_error("Failed to create delegated link"))?;
+    info!(
+        "Created delegated link {} for registration {}",
+        link.id, registration.id
+    );
+
+    Ok(axum::Json(json!(DelegatedLinkCreatedResponse {
+        url: link_url(&token),
+        token,
+        link,
+    })))
+}
+
+/// GET /registrations/{id}/delegations lists the links created for a registration.
+#[tracing::instrument(skip(state))]
+pub async fn list_delegated_links_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::delegated_links;
+
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    let links = delegated_links::table
+        .filter(delegated_links::registration_id.eq(registration.id))
+        .order(delegated_links::created_at.desc())
+        .load::<DelegatedLink>(&mut conn)
+        .map_err(db_error("Failed to load delegated links"))?;
+    Ok(axum::Json(json!(links)))
+}
+
+/// DELETE /registrations/{id}/delegations/{link_id} revokes a link.
+#[tracing::instrument(skip(state))]
+pub async fn revoke_delegated_link_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path((registration_id, link_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    use crate::database::schema::delegated_links;
+
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    let updated = diesel::update(
+        delegated_links::table
+            .find(link_id)
+            .filter(delegated_links::registration_id.eq(registration.id))
+            .filter(delegated_links::revoked_at.is_null()),
+    )
+    .set(delegated_links::revoked_at.eq(Some(chrono::Utc::now().naive_utc())))
+    .execute(&mut conn)
+    .map_err(db_error("Failed to revoke delegated link"))?;
+    if updated == 0 {
+        return Err((StatusCode::NOT_FOUND, "Link not found".to_string()));
+    }
+    info!("Revoked delegated link {link_id}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /delegated/{token} returns what the link holder is being asked to pay for:
+/// the camper's first name, the session and the amount the PaymentSheet will charge.
+/// Nothing else about the family account is exposed.
+#[tracing::instrument(skip(state, fees, token))]
+pub async fn get_delegated_registration_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(fees): Extension<Arc<ProcessingFees>>,
+    Path(token): Path<String>,
+) -> Result<axum::Json<Value>, ApiError> {
+    use crate::database::schema::{camp_sessions, campers};
+
+    let mut conn = conn_from_state(&state).await?;
+    let (link, registration) = load_active_link(&mut conn, &token)?;
+    let camper = campers::table
+        .find(registration.camper_id)
+        .first::<Camper>(&mut conn)
+        .map_err(db_error("Failed to load camper"))?;
+    let session = camp_sessions::table
+        .find(registration.session_id)
+        .first::<CampSession>(&mut conn)
+        .map_err(db_error("Failed to load session"))?;
+    let quote = price_registrations(
+        &mut conn,
+        &fees,
+        registration.guardian_id,
+        &[registration.id],
+        &session.currency,
+    )
+    .map_err(db_error("Failed to price delegated registration"))??;
+
+    Ok(axum::Json(json!(DelegatedRegistrationResponse {
+        registration_id: registration.id,
+        camper_first_name: camper.first_name,
+        session: DelegatedSessionSummary {
+            name: session.name,
+            starts_on: session.starts_on,
+            ends_on: session.ends_on,
+        },
+        amount: quote.total,
+        currency: quote.currency,
+        recipient_name: link.recipient_name,
+        link_expires_at: link.expires_at,
+    })))
+}
+
+/// POST /delegated/{token}/payment_sheet prices the linked registration and creates a
+/// PaymentSheet for the link holder. A seat hold that lapsed while the link was
+/// shared is renewed if the session still has room. The PaymentIntent carries the
+/// quote and registration ids, so the webhook confirms it like any other checkout.
+/// The payer is subject to the same payment limits as any other customer.
+#[tracing::instrument(skip(state, limits, keyring, fees, token))]
+pub async fn create_delegated_payment_sheet_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(limits): Extension<Arc<PaymentLimits>>,
+    Extension(keyring): Extension<Arc<StripeKeyring>>,
+    Extension(fees): Extension<Arc<ProcessingFees>>,
+    Path(token): Path<String>,
+    Json(payload): Json<DelegatedPaymentSheetRequest>,
+) -> Result<axum::Json<Value>, ApiError> {
+    if payload.payer_name.trim().is_empty() || payload.payer_email.trim().is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            "payer_name and payer_email are required",
+        ));
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let (link, registration) = load_active_link(&mut conn, &token)?;
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Lock the session row so a renewed hold cannot oversell it
+        let session = crate::database::schema::camp_sessions::table
+            .find(registration.session_id)
+            .for_update()
+            .first::<CampSession>(conn)?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let hold = match open_hold(conn, registration.id, now)? {
+            Some(hold) => hold,
+            None => {
+                if seats_taken(conn, session.id, now)? >= i64::from(session.capacity) {
+                    return Ok(Err(ApiError::new(
+                        StatusCode::CONFLICT,
+                        ErrorCode::SessionFull,
+                        "Session is full",
+                    )));
+                }
+                place_hold(conn, registration.id, session.id, now)?
+            }
+        };
+
+        let quote = match quote_registrations(
+            conn,
+            &fees,
+            registration.guardian_id,
+            &[registration.id],
+            &session.currency,
+        )? {
+            Ok(quote) => quote,
+            Err(rejection) => return Ok(Err(rejection)),
+        };
+        Ok(Ok((quote, hold)))
+    });
+    let (quote, hold): (_, RegistrationHold) =
+        result.map_err(db_error("Failed to price delegated registration"))??;
+    limits.enforce(
+        Some(&mut conn),
+        payload.payer_email.trim(),
+        &quote.currency,
+        quote.total,
+    )?;
+
+    let state_guard = state.lock().await;
+    let client = keyring.client(StripeCapability::Payments, &state_guard.stripe_keys);
+    let publishable_key = state_guard.stripe_keys.publishable_key.clone();
+    drop(state_guard);
+
+    let metadata = PaymentMetadata {
+        quote_id: Some(quote.id),
+        registration_ids: vec![registration.id],
+        ..Default::default()
+    };
+    let (customer, ephemeral_key, payment_intent) = create_checkout(
+        &client,
+        payload.payer_name.trim(),
+        payload.payer_email.trim(),
+        Some(&format!(
+            "Delegated payment for registration {}",
+            registration.id
+        )),
+        quote.total,
+        parse_currency(&quote.currency)?,
+        metadata.to_metadata(),
+    )
+    .await?;
+
+    record_sheet_created(&mut conn, &payment_intent);
+    record_attempt(
+        &mut conn,
+        payload.payer_email.trim(),
+        payment_intent.id.as_str(),
+        payment_intent.amount,
+        &payment_intent.currency.to_string(),
+    );
+    let linked = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        use crate::database::schema::delegated_links;
+
+        link_holds_to_intent(conn, &[registration.id], payment_intent.id.as_str())?;
+        diesel::update(delegated_links::table.find(link.id))
+            .set(delegated_links::payment_intent_id.eq(Some(payment_intent.id.as_str())))
+            .execute(conn)
+    });
+    if let Err(e) = linked {
+        error!(
+            "Failed to link delegated link {} to payment intent: {e}",
+            link.id
+        );
+    }
+    info!(
+        "Created delegated PaymentIntent {} for registration {} via link {}",
+        payment_intent.id, registration.id, link.id
+    );
+
+    Ok(axum::Json(json!(DelegatedPaymentSheetResponse {
+        payment_sheet: PaymentSheetResponse {
+            customer: customer.id.to_string(),
+            ephemeral_key: ephemeral_key.secret,
+            payment_intent: payment_intent.client_secret,
+            publishable_key,
+        },
+        quote_id: quote.id,
+        amount: quote.total,
+        currency: quote.currency,
+        hold_expires_at: hold.expires_at,
+    })))
+}
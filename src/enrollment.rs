@@ -0,0 +1,297 @@
+//! Minimum enrollment.
+//!
+//! A session can require a minimum number of paid registrations by a cutoff date
+//! and is cancelled if it misses it. The `enrollment` scheduled job flags sessions
+//! below their minimum once the cutoff is within `ENROLLMENT_WARNING_DAYS` (default
+//! 14), raising one admin alert per session. `GET /admin/sessions/at_risk` lists
+//! them. Nothing is cancelled automatically: once the cutoff has passed, a manager
+//! confirms with `POST /admin/sessions/{id}/cancel_under_enrolled`, which runs the
+//! usual session cancellation with its refunds and family emails.
+use crate::alerts::{notify_slack, raise};
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{AdminAlert, CampSession},
+};
+use crate::session_cancellations::{cancel_session, PAID_STATUSES};
+use crate::sessions::load_session;
+use crate::stripe_keys::StripeKeyring;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+pub const UNDER_ENROLLED: &str = "session_under_enrolled";
+
+/// How many days before the cutoff a session below its minimum is flagged, from
+/// `ENROLLMENT_WARNING_DAYS` (default 14).
+pub fn warning_days() -> i64 {
+    env::var("ENROLLMENT_WARNING_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|days| (0..=365).contains(days))
+        .unwrap_or(14)
+}
+
+/// A session below its minimum enrollment with its cutoff near or past.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AtRiskSession {
+    pub session_id: Uuid,
+    pub name: String,
+    pub starts_on: NaiveDate,
+    pub min_enrollment: i32,
+    pub enrollment_cutoff: NaiveDate,
+    pub enrolled: i64,
+    pub shortfall: i64,
+    /// Once the cutoff has passed the session can be cancelled.
+    pub cutoff_passed: bool,
+    pub flagged_at: Option<NaiveDateTime>,
+}
+
+/// Checks a minimum enrollment: set together with its cutoff, within capacity, and
+/// cut off no later than the first day.
+pub fn validate_minimum_enrollment(
+    min_enrollment: Option<i32>,
+    cutoff: Option<NaiveDate>,
+    capacity: i32,
+    starts_on: NaiveDate,
+) -> Result<(), String> {
+    match (min_enrollment, cutoff) {
+        (None, None) => Ok(()),
+        (Some(minimum), Some(cutoff)) => {
+            if minimum < 1 || minimum > capacity {
+                Err(format!(
+                    "min_enrollment must be between 1 and the capacity of {capacity}"
+                ))
+            } else if cutoff > starts_on {
+                Err("enrollment_cutoff must not be after the session starts".to_string())
+            } else {
+                Ok(())
+            }
+        }
+        _ => Err("min_enrollment and enrollment_cutoff must be set together".to_string()),
+    }
+}
+
+/// Whether a session is at risk of missing its minimum on `today`. Sessions that
+/// are cancelled, have started or have no minimum never are.
+pub fn enrollment_risk(
+    session: &CampSession,
+    enrolled: i64,
+    today: NaiveDate,
+    warning_days: i64,
+) -> Option<AtRiskSession> {
+    let (Some(minimum), Some(cutoff)) = (session.min_enrollment, session.enrollment_cutoff) else {
+        return None;
+    };
+    if session.cancelled_at.is_some()
+        || session.starts_on <= today
+        || enrolled >= i64::from(minimum)
+        || today < cutoff - chrono::Duration::days(warning_days)
+    {
+        return None;
+    }
+    Some(AtRiskSession {
+        session_id: session.id,
+        name: session.name.clone(),
+        starts_on: session.starts_on,
+        min_enrollment: minimum,
+        enrollment_cutoff: cutoff,
+        enrolled,
+        shortfall: i64::from(minimum) - enrolled,
+        cutoff_passed: today > cutoff,
+        flagged_at: session.enrollment_flagged_at,
+    })
+}
+
+/// Paid registrations per session.
+fn enrolled_counts(
+    conn: &mut PgConnection,
+    session_ids: &[Uuid],
+) -> Result<HashMap<Uuid, i64>, diesel::result::Error> {
+    use crate::database::schema::registrations::dsl::*;
+
+    Ok(registrations
+        .filter(session_id.eq_any(session_ids))
+        .filter(status.eq_any(PAID_STATUSES))
+        .group_by(session_id)
+        .select((session_id, diesel::dsl::count_star()))
+        .load::<(Uuid, i64)>(conn)?
+        .into_iter()
+        .collect())
+}
+
+/// Sessions at risk of missing their minimum on `today`, earliest cutoff first.
+pub fn at_risk_sessions(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<Vec<AtRiskSession>, diesel::result::Error> {
+    use crate::database::schema::camp_sessions::dsl::*;
+
+    let candidates = camp_sessions
+        .filter(cancelled_at.is_null())
+        .filter(min_enrollment.is_not_null())
+        .filter(starts_on.gt(today))
+        .order(enrollment_cutoff.asc())
+        .load::<CampSession>(conn)?;
+    let ids: Vec<Uuid> = candidates.iter().map(|s| s.id).collect();
+    let counts = enrolled_counts(conn, &ids)?;
+    let lead = warning_days();
+    Ok(candidates
+        .iter()
+        .filter_map(|session| {
+            let enrolled = counts.get(&session.id).copied().unwrap_or_default();
+            enrollment_risk(session, enrolled, today, lead)
+        })
+        .collect())
+}
+
+/// Flags sessions newly at risk, raising one alert each. Returns every at-risk
+/// session and the alerts raised, to post once the transaction commits.
+pub fn flag_at_risk_sessions(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<(Vec<AtRiskSession>, Vec<AdminAlert>), diesel::result::Error> {
+    use crate::database::schema::camp_sessions::dsl::*;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let mut at_risk = at_risk_sessions(conn, today)?;
+        let now = chrono::Utc::now().naive_utc();
+        let mut alerts = Vec::new();
+        for session in at_risk.iter_mut().filter(|s| s.flagged_at.is_none()) {
+            let message = format!(
+                "{} has {} of {} required registrations with enrollment cutoff {}",
+                session.name, session.enrolled, session.min_enrollment, session.enrollment_cutoff
+            );
+            alerts.push(raise(conn, UNDER_ENROLLED, message, json!(session), None)?);
+            diesel::update(camp_sessions.find(session.session_id))
+                .set(enrollment_flagged_at.eq(Some(now)))
+                .execute(conn)?;
+            session.flagged_at = Some(now);
+        }
+        Ok((at_risk, alerts))
+    })
+}
+
+/// Runs the `enrollment` job: flags sessions newly at risk and alerts admins.
+pub async fn check_enrollment(
+    state: &Arc<Mutex<AppState>>,
+    today: NaiveDate,
+) -> Result<Value, (StatusCode, String)> {
+    let mut conn = conn_from_state(state).await?;
+    let (at_risk, alerts) = flag_at_risk_sessions(&mut conn, today)
+        .map_err(db_error("Failed to check session enrollment"))?;
+    drop(conn);
+    for alert in &alerts {
+        notify_slack(alert).await;
+    }
+    Ok(json!({ "at_risk": at_risk.len(), "flagged": alerts.len() }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct AtRiskSessionsResponse {
+    pub warning_days: i64,
+    pub sessions: Vec<AtRiskSession>,
+}
+
+/// GET /admin/sessions/at_risk lists sessions at risk of missing their minimum enrollment.
+#[tracing::instrument(skip(state))]
+pub async fn at_risk_sessions_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let sessions = at_risk_sessions(&mut conn, chrono::Utc::now().date_naive())
+        .map_err(db_error("Failed to load at-risk sessions"))?;
+
+    Ok(axum::Json(json!(AtRiskSessionsResponse {
+        warning_days: warning_days(),
+        sessions,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MinimumEnrollmentRequest {
+    pub min_enrollment: Option<i32>,
+    pub enrollment_cutoff: Option<NaiveDate>,
+}
+
+/// PUT /admin/sessions/{id}/minimum_enrollment sets or clears a session's minimum
+/// enrollment. The session is flagged afresh under the new minimum.
+#[tracing::instrument(skip(state))]
+pub async fn set_minimum_enrollment_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+    Json(payload): Json<MinimumEnrollmentRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::camp_sessions::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+    validate_minimum_enrollment(
+        payload.min_enrollment,
+        payload.enrollment_cutoff,
+        session.capacity,
+        session.starts_on,
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let updated = diesel::update(camp_sessions.find(session.id))
+        .set((
+            min_enrollment.eq(payload.min_enrollment),
+            enrollment_cutoff.eq(payload.enrollment_cutoff),
+            enrollment_flagged_at.eq(None::<NaiveDateTime>),
+        ))
+        .get_result::<CampSession>(&mut conn)
+        .map_err(db_error("Failed to update session"))?;
+
+    Ok(axum::Json(json!(updated)))
+}
+
+/// POST /admin/sessions/{id}/cancel_under_enrolled confirms the cancellation of a
+/// session that missed its minimum enrollment, refunding or crediting every family.
+#[tracing::instrument(skip(state, keyring))]
+pub async fn cancel_under_enrolled_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(keyring): Extension<Arc<StripeKeyring>>,
+    Path(session_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+    let enrolled = enrolled_counts(&mut conn, &[session.id])
+        .map_err(db_error("Failed to count registrations"))?
+        .get(&session.id)
+        .copied()
+        .unwrap_or_default();
+    drop(conn);
+
+    let today = chrono::Utc::now().date_naive();
+    if let Some(cutoff) = session.enrollment_cutoff.filter(|cutoff| today <= *cutoff) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Enrollment cutoff {cutoff} has not passed"),
+        ));
+    }
+    let risk = enrollment_risk(&session, enrolled, today, 0).ok_or((
+        StatusCode::CONFLICT,
+        "Session is not below a minimum enrollment".to_string(),
+    ))?;
+
+    let reason = format!(
+        "Minimum enrollment of {} was not reached by {} ({} registered)",
+        risk.min_enrollment, risk.enrollment_cutoff, risk.enrolled
+    );
+    info!("Cancelling under-enrolled session {}: {reason}", session.id);
+    let response = cancel_session(&state, &keyring, &actor, session.id, &reason).await?;
+    Ok(axum::Json(json!(response)))
+}
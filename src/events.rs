@@ -0,0 +1,280 @@
+//! Unified domain event log.
+//!
+//! Payments, registrations, waitlist moves and staff actions each keep their own
+//! tables; every change worth showing in an activity feed is also appended to
+//! `domain_events` through an [`EventRecorder`], in the same transaction as the
+//! change itself. Each event names the camper, guardian, session, registration and
+//! payment intent it concerns, so one query gives a chronological feed for any of
+//! them. The table is append-only: the recorder only inserts and a trigger rejects
+//! updates and deletes.
+use crate::auth::{Actor, Role};
+use crate::campers::{ensure_guardian_owns, load_camper};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{DomainEventRecord, NewDomainEvent, Registration},
+};
+use crate::sessions::load_session;
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+pub const REGISTRATION_CREATED: &str = "registration.created";
+pub const REGISTRATION_CONFIRMED: &str = "registration.confirmed";
+pub const REGISTRATION_CANCELLED: &str = "registration.cancelled";
+pub const SESSION_CANCELLED: &str = "session.cancelled";
+pub const WAITLIST_JOINED: &str = "waitlist.joined";
+pub const WAITLIST_SEAT_OFFERED: &str = "waitlist.seat_offered";
+pub const WAITLIST_OFFER_EXPIRED: &str = "waitlist.offer_expired";
+pub const STAFF_ASSIGNED: &str = "staff.assigned";
+pub const STAFF_UNASSIGNED: &str = "staff.unassigned";
+pub const CORRECTION_REVIEWED: &str = "camper.correction_reviewed";
+
+const DEFAULT_FEED_LIMIT: i64 = 50;
+const MAX_FEED_LIMIT: i64 = 200;
+
+/// Something that happened, with the records it concerns.
+#[derive(Debug, Clone)]
+pub struct DomainEvent {
+    pub kind: &'static str,
+    pub summary: String,
+    pub camper_id: Option<Uuid>,
+    pub guardian_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub registration_id: Option<Uuid>,
+    pub payment_intent_id: Option<String>,
+    pub details: Value,
+}
+
+impl DomainEvent {
+    pub fn new(kind: &'static str, summary: impl Into<String>) -> Self {
+        Self {
+            kind,
+            summary: summary.into(),
+            camper_id: None,
+            guardian_id: None,
+            session_id: None,
+            registration_id: None,
+            payment_intent_id: None,
+            details: json!({}),
+        }
+    }
+
+    /// Links the event to a registration and its camper, guardian and session.
+    pub fn registration(mut self, registration: &Registration) -> Self {
+        self.registration_id = Some(registration.id);
+        self.camper_id = Some(registration.camper_id);
+        self.guardian_id = Some(registration.guardian_id);
+        self.session_id = Some(registration.session_id);
+        self
+    }
+
+    pub fn camper(mut self, camper_id: Uuid, guardian_id: Uuid) -> Self {
+        self.camper_id = Some(camper_id);
+        self.guardian_id = Some(guardian_id);
+        self
+    }
+
+    pub fn session(mut self, session_id: Uuid) -> Self {
+        self.session_id = Some(session_id);
+        self
+    }
+
+    pub fn payment_intent(mut self, payment_intent_id: &str) -> Self {
+        self.payment_intent_id = Some(payment_intent_id.to_string());
+        self
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Appends events on behalf of whoever is acting: a token holder, or the system for
+/// webhooks and jobs.
+#[derive(Debug, Clone)]
+pub struct EventRecorder {
+    actor_role: &'static str,
+    actor_id: Option<Uuid>,
+}
+
+impl EventRecorder {
+    pub fn for_actor(actor: &Actor) -> Self {
+        Self {
+            actor_role: actor.role.as_str(),
+            actor_id: actor.subject_id,
+        }
+    }
+
+    pub fn system() -> Self {
+        Self {
+            actor_role: "system",
+            actor_id: None,
+        }
+    }
+
+    pub fn to_row(&self, event: DomainEvent, occurred_at: chrono::NaiveDateTime) -> NewDomainEvent {
+        NewDomainEvent {
+            kind: event.kind.to_string(),
+            summary: event.summary,
+            actor_role: self.actor_role.to_string(),
+            actor_id: self.actor_id,
+            camper_id: event.camper_id,
+            guardian_id: event.guardian_id,
+            session_id: event.session_id,
+            registration_id: event.registration_id,
+            payment_intent_id: event.payment_intent_id,
+            details: event.details,
+            occurred_at,
+        }
+    }
+
+    /// Appends one event.
+    pub fn record(
+        &self,
+        conn: &mut PgConnection,
+        event: DomainEvent,
+    ) -> Result<(), diesel::result::Error> {
+        self.record_all(conn, vec![event])
+    }
+
+    /// Appends events in order, sharing one timestamp.
+    pub fn record_all(
+        &self,
+        conn: &mut PgConnection,
+        events: Vec<DomainEvent>,
+    ) -> Result<(), diesel::result::Error> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let rows: Vec<NewDomainEvent> = events
+            .into_iter()
+            .map(|event| self.to_row(event, now))
+            .collect();
+        diesel::insert_into(crate::database::schema::domain_events::table)
+            .values(&rows)
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+/// Whose activity a feed follows.
+#[derive(Debug, Clone, Copy)]
+pub enum FeedSubject {
+    Camper(Uuid),
+    Guardian(Uuid),
+    Session(Uuid),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    /// Return events older than this event id, to page back through the feed.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityFeedResponse {
+    /// Newest first.
+    pub events: Vec<DomainEventRecord>,
+    /// Pass as `before` to fetch the next page; absent on the last page.
+    pub next_before: Option<i64>,
+}
+
+/// Clamps a requested page size to `1..=200`, defaulting to 50.
+pub fn feed_limit(requested: Option<i64>) -> i64 {
+    requested
+        .unwrap_or(DEFAULT_FEED_LIMIT)
+        .clamp(1, MAX_FEED_LIMIT)
+}
+
+/// Loads a page of a subject's events, newest first.
+pub fn activity_feed(
+    conn: &mut PgConnection,
+    subject: FeedSubject,
+    query: &FeedQuery,
+) -> Result<ActivityFeedResponse, diesel::result::Error> {
+    use crate::database::schema::domain_events::dsl::*;
+
+    let limit = feed_limit(query.limit);
+    let mut feed = domain_events.into_boxed();
+    feed = match subject {
+        FeedSubject::Camper(camper) => feed.filter(camper_id.eq(camper)),
+        FeedSubject::Guardian(guardian) => feed.filter(guardian_id.eq(guardian)),
+        FeedSubject::Session(session) => feed.filter(session_id.eq(session)),
+    };
+    if let Some(before) = query.before {
+        feed = feed.filter(id.lt(before));
+    }
+    // One extra row tells whether there is another page
+    let mut events = feed
+        .order(id.desc())
+        .limit(limit + 1)
+        .load::<DomainEventRecord>(conn)?;
+    let next_before = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+    Ok(ActivityFeedResponse {
+        events,
+        next_before,
+    })
+}
+
+/// GET /campers/{id}/activity returns the camper's activity feed.
+#[tracing::instrument(skip(state))]
+pub async fn camper_activity_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(camper_id): Path<Uuid>,
+    Query(query): Query<FeedQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+    let feed = activity_feed(&mut conn, FeedSubject::Camper(camper.id), &query)
+        .map_err(db_error("Failed to load camper activity"))?;
+    Ok(axum::Json(json!(feed)))
+}
+
+/// GET /guardians/{id}/activity returns the guardian's activity feed across campers.
+#[tracing::instrument(skip(state))]
+pub async fn guardian_activity_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(guardian): Path<Uuid>,
+    Query(query): Query<FeedQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    if actor.role == Role::Guardian && actor.guardian_id() != Some(guardian) {
+        return Err((StatusCode::NOT_FOUND, "Guardian not found".to_string()));
+    }
+    let mut conn = conn_from_state(&state).await?;
+    let feed = activity_feed(&mut conn, FeedSubject::Guardian(guardian), &query)
+        .map_err(db_error("Failed to load guardian activity"))?;
+    Ok(axum::Json(json!(feed)))
+}
+
+/// GET /admin/sessions/{id}/activity returns the session's activity feed.
+#[tracing::instrument(skip(state))]
+pub async fn session_activity_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+    Query(query): Query<FeedQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+    let feed = activity_feed(&mut conn, FeedSubject::Session(session.id), &query)
+        .map_err(db_error("Failed to load session activity"))?;
+    Ok(axum::Json(json!(feed)))
+}
@@ -0,0 +1,150 @@
+//! Informational currency conversion.
+//!
+//! Rates are fetched at most once per currency pair per UTC day from the provider at
+//! `EXCHANGE_RATE_URL` (a Frankfurter-compatible API by default) and cached in the
+//! `exchange_rates` table, so cold starts reuse the day's rate. Converted amounts
+//! are for display only; charges are always made in the quoted currency.
+use crate::database::models::{ExchangeRate, NewExchangeRate};
+use crate::handlers::SUPPORTED_CURRENCIES;
+use chrono::{NaiveDate, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{error, info};
+
+const DEFAULT_EXCHANGE_RATE_URL: &str = "https://api.frankfurter.app/latest";
+
+#[derive(Debug, Deserialize)]
+struct RatesResponse {
+    rates: HashMap<String, f64>,
+}
+
+/// An approximate amount in another currency, in that currency's minor units.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertedAmount {
+    pub currency: String,
+    pub amount: i64,
+    pub rate: f64,
+    pub rate_date: NaiveDate,
+    pub informational: bool,
+}
+
+/// Fetches today's rates for `base` from the provider and caches them.
+async fn fetch_rates(
+    conn: &mut PgConnection,
+    base: &str,
+    today: NaiveDate,
+) -> Result<Vec<ExchangeRate>, String> {
+    use crate::database::schema::exchange_rates;
+
+    let url = std::env::var("EXCHANGE_RATE_URL")
+        .unwrap_or_else(|_| DEFAULT_EXCHANGE_RATE_URL.to_string());
+    let response = reqwest::Client::new()
+        .get(&url)
+        .query(&[("from", base.to_uppercase())])
+        .send()
+        .await
+        .map_err(|e| format!("Exchange rate request failed: {e}"))?
+        .error_for_status()
+        .map_err(|e| format!("Exchange rate provider error: {e}"))?
+        .json::<RatesResponse>()
+        .await
+        .map_err(|e| format!("Invalid exchange rate response: {e}"))?;
+
+    let rows: Vec<NewExchangeRate> = response
+        .rates
+        .into_iter()
+        .map(|(quote, rate)| NewExchangeRate {
+            rate_date: today,
+            base_currency: base.to_string(),
+            quote_currency: quote.to_lowercase(),
+            rate,
+        })
+        .filter(|row| SUPPORTED_CURRENCIES.contains(&row.quote_currency.as_str()))
+        .collect();
+
+    diesel::insert_into(exchange_rates::table)
+        .values(&rows)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .map_err(|e| format!("Failed to cache exchange rates: {e}"))?;
+    info!("Cached {} exchange rate(s) for {base}", rows.len());
+
+    exchange_rates::table
+        .filter(exchange_rates::rate_date.eq(today))
+        .filter(exchange_rates::base_currency.eq(base))
+        .load::<ExchangeRate>(conn)
+        .map_err(|e| format!("Failed to load exchange rates: {e}"))
+}
+
+/// Disclaimer shown next to converted amounts.
+pub fn conversion_note(currency: &str) -> String {
+    format!(
+        "Converted amounts are approximate and for information only. You will be charged in {}.",
+        currency.to_uppercase()
+    )
+}
+
+/// Today's cached rates for `base`, fetched from the provider on the day's first use.
+async fn todays_rates(
+    conn: &mut PgConnection,
+    base: &str,
+    today: NaiveDate,
+) -> Result<Vec<ExchangeRate>, String> {
+    use crate::database::schema::exchange_rates::dsl::*;
+
+    let cached = exchange_rates
+        .filter(rate_date.eq(today))
+        .filter(base_currency.eq(base))
+        .load::<ExchangeRate>(conn)
+        .map_err(|e| format!("Failed to load cached exchange rates: {e}"))?;
+    if !cached.is_empty() {
+        return Ok(cached);
+    }
+    fetch_rates(conn, base, today).await
+}
+
+/// Makes sure today's rates are cached for every supported currency, so the first
+/// quote of the day does not wait on the provider. Returns how many bases are cached.
+pub async fn prime_rates(conn: &mut PgConnection) -> usize {
+    let today = Utc::now().date_naive();
+    let mut primed = 0;
+    for base in SUPPORTED_CURRENCIES {
+        match todays_rates(conn, base, today).await {
+            Ok(_) => primed += 1,
+            Err(e) => error!("Failed to prime {base} exchange rates: {e}"),
+        }
+    }
+    primed
+}
+
+/// Returns `amount` (minor units of `currency`) converted into every other supported
+/// currency. Conversion is best-effort: provider or cache failures yield an empty list.
+pub async fn approximate_conversions(
+    conn: &mut PgConnection,
+    amount: i64,
+    currency: &str,
+) -> Vec<ConvertedAmount> {
+    let base = currency.to_lowercase();
+    let rates = match todays_rates(conn, &base, Utc::now().date_naive()).await {
+        Ok(rates) => rates,
+        Err(e) => {
+            error!("{e}");
+            return Vec::new();
+        }
+    };
+
+    SUPPORTED_CURRENCIES
+        .iter()
+        .filter(|target| **target != base)
+        .filter_map(|target| rates.iter().find(|r| r.quote_currency == *target))
+        .map(|r| ConvertedAmount {
+            currency: r.quote_currency.clone(),
+            // All supported currencies use two minor-unit digits
+            amount: (amount as f64 * r.rate).round() as i64,
+            rate: r.rate,
+            rate_date: r.rate_date,
+            informational: true,
+        })
+        .collect()
+}
@@ -0,0 +1,667 @@
+//! Asynchronous report exports.
+//!
+//! `POST /admin/exports` queues a job and returns immediately. The `exports` job
+//! (run on a schedule through `POST /admin/jobs/exports`) builds each queued file,
+//! uploads it to the S3 report archive and marks the job completed. Clients poll
+//! `GET /admin/exports/{id}` until it returns a presigned download URL. A job whose
+//! run died mid-build is failed after 30 minutes in `running`, so polling ends and the
+//! export can be requested again.
+//!
+//! The anonymized registration and payment datasets for analytics are queued every
+//! day by the `analytics` job, one file per dataset per day, and can also be
+//! requested here for any range. [`crate::anonymize`] holds their rules.
+use crate::anonymize::{self, age_bucket, day, Anonymizer};
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error, get_conn,
+    models::{
+        CampSession, Camper, ExportJob, NewExportJob, PaymentEvent, PaymentFlag, Registration,
+    },
+};
+use crate::receipt_numbers::receipt_numbers_for;
+use crate::revenue;
+use crate::roster::load_roster;
+use crate::s3_archive;
+use crate::tags::normalize_tag;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// How long presigned download URLs stay valid.
+const DOWNLOAD_URL_TTL: Duration = Duration::from_secs(15 * 60);
+/// Jobs processed per run, keeping each run well inside the Lambda timeout.
+const JOBS_PER_RUN: i64 = 5;
+/// A job still `running` this long after it was claimed belongs to a run that died
+/// (Lambda timeout, crash) and is failed so clients stop polling it.
+const RUNNING_TIMEOUT: chrono::Duration = chrono::Duration::minutes(30);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportRequest {
+    /// Registrations for a session with camper and guardian details, optionally
+    /// limited to registrations carrying every tag in `tags`.
+    Roster {
+        session_id: Uuid,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    /// Accounting journal of payment events in `[from, to]`, with receipt numbers,
+    /// reconciliation flags for failed refunds and rows for failed payouts.
+    Payments { from: NaiveDate, to: NaiveDate },
+    /// Registrations updated in `[from, to]`, anonymized for analytics.
+    AnonymizedRegistrations { from: NaiveDate, to: NaiveDate },
+    /// Payment events in `[from, to]`, anonymized for analytics.
+    AnonymizedPayments { from: NaiveDate, to: NaiveDate },
+    /// Collected, recognized and deferred revenue per session for every month
+    /// overlapping `[from, to]`.
+    SessionRevenue { from: NaiveDate, to: NaiveDate },
+}
+
+impl ExportRequest {
+    fn kind(&self) -> &'static str {
+        match self {
+            ExportRequest::Roster { .. } => "roster",
+            ExportRequest::Payments { .. } => "payments",
+            ExportRequest::AnonymizedRegistrations { .. } => "anonymized_registrations",
+            ExportRequest::AnonymizedPayments { .. } => "anonymized_payments",
+            ExportRequest::SessionRevenue { .. } => "session_revenue",
+        }
+    }
+}
+
+fn csv_bytes(writer: csv::Writer<Vec<u8>>) -> Result<Vec<u8>, String> {
+    writer
+        .into_inner()
+        .map_err(|e| format!("Failed to finish CSV: {e}"))
+}
+
+fn build_roster(
+    conn: &mut PgConnection,
+    session: Uuid,
+    tags: &[String],
+) -> Result<Vec<u8>, String> {
+    let roster =
+        load_roster(conn, session, tags).map_err(|e| format!("Failed to load roster: {e}"))?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "registration_id",
+            "status",
+            "camper_first_name",
+            "camper_last_name",
+            "birthdate",
+            "guardian_name",
+            "guardian_email",
+            "tags",
+        ])
+        .map_err(|e| e.to_string())?;
+    for entry in roster {
+        writer
+            .write_record([
+                entry.registration_id.to_string(),
+                entry.status,
+                entry.camper_first_name,
+                entry.camper_last_name,
+                entry.birthdate.map(|d| d.to_string()).unwrap_or_default(),
+                entry.guardian_name,
+                entry.guardian_email,
+                entry.tags.join(";"),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    csv_bytes(writer)
+}
+
+/// The half-open timestamp range covering the days `[from, to]`.
+fn day_range(from: NaiveDate, to: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    (
+        from.and_time(NaiveTime::MIN),
+        (to + chrono::Duration::days(1)).and_time(NaiveTime::MIN),
+    )
+}
+
+fn build_payments_journal(
+    conn: &mut PgConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<u8>, String> {
+    use crate::database::schema::{payment_events, payment_flags};
+
+    let (start, end) = day_range(from, to);
+    let events = payment_events::table
+        .filter(payment_events::created_at.ge(start))
+        .filter(payment_events::created_at.lt(end))
+        .filter(payment_events::status.ne(PaymentEvent::SHEET_CREATED))
+        .order(payment_events::created_at.asc())
+        .load::<PaymentEvent>(conn)
+        .map_err(|e| format!("Failed to load payment events: {e}"))?;
+
+    // Every flag on the journal's payments, whenever raised, plus payout failures in range
+    let intent_ids: Vec<&str> = events
+        .iter()
+        .map(|e| e.payment_intent_id.as_str())
+        .collect();
+    let flags = payment_flags::table
+        .filter(
+            payment_flags::payment_intent_id
+                .eq_any(intent_ids.clone())
+                .or(payment_flags::payment_intent_id
+                    .is_null()
+                    .and(payment_flags::created_at.ge(start))
+                    .and(payment_flags::created_at.lt(end))),
+        )
+        .order(payment_flags::created_at.asc())
+        .load::<PaymentFlag>(conn)
+        .map_err(|e| format!("Failed to load payment flags: {e}"))?;
+    let receipt_numbers = receipt_numbers_for(conn, &intent_ids)
+        .map_err(|e| format!("Failed to load receipt numbers: {e}"))?;
+    let mut flags_by_intent: HashMap<&str, Vec<&str>> = HashMap::new();
+    for flag in &flags {
+        if let Some(intent) = &flag.payment_intent_id {
+            let kinds = flags_by_intent.entry(intent.as_str()).or_default();
+            if !kinds.contains(&flag.kind.as_str()) {
+                kinds.push(flag.kind.as_str());
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "created_at",
+            "payment_intent_id",
+            "status",
+            "amount",
+            "currency",
+            "customer_id",
+            "flags",
+            "receipt_number",
+        ])
+        .map_err(|e| e.to_string())?;
+    for event in &events {
+        let event_flags = flags_by_intent
+            .get(event.payment_intent_id.as_str())
+            .map(|kinds| kinds.join(";"))
+            .unwrap_or_default();
+        writer
+            .write_record([
+                event.created_at.to_string(),
+                event.payment_intent_id.clone(),
+                event.status.clone(),
+                event.amount.map(|a| a.to_string()).unwrap_or_default(),
+                event.currency.clone().unwrap_or_default(),
+                event.customer_id.clone().unwrap_or_default(),
+                event_flags,
+                receipt_numbers
+                    .get(&event.payment_intent_id)
+                    .cloned()
+                    .unwrap_or_default(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    // Payout failures are not tied to one payment, so they get rows of their own
+    for flag in flags.iter().filter(|f| f.payment_intent_id.is_none()) {
+        writer
+            .write_record([
+                flag.created_at.to_string(),
+                String::new(),
+                format!("{} {}", flag.kind, flag.stripe_object_id),
+                flag.amount.to_string(),
+                flag.currency.clone(),
+                String::new(),
+                flag.kind.clone(),
+                String::new(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    csv_bytes(writer)
+}
+
+fn build_anonymized_registrations(
+    conn: &mut PgConnection,
+    anonymizer: &Anonymizer,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<u8>, String> {
+    use crate::database::schema::{camp_sessions, campers, registrations};
+
+    let (start, end) = day_range(from, to);
+    let rows = registrations::table
+        .filter(registrations::updated_at.ge(start))
+        .filter(registrations::updated_at.lt(end))
+        .order(registrations::updated_at.asc())
+        .load::<Registration>(conn)
+        .map_err(|e| format!("Failed to load registrations: {e}"))?;
+    let camper_ids: Vec<Uuid> = rows.iter().map(|r| r.camper_id).collect();
+    let session_ids: Vec<Uuid> = rows.iter().map(|r| r.session_id).collect();
+    let camper_rows = campers::table
+        .filter(campers::id.eq_any(&camper_ids))
+        .load::<Camper>(conn)
+        .map_err(|e| format!("Failed to load campers: {e}"))?;
+    let session_rows = camp_sessions::table
+        .filter(camp_sessions::id.eq_any(&session_ids))
+        .load::<CampSession>(conn)
+        .map_err(|e| format!("Failed to load sessions: {e}"))?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "registration",
+            "guardian",
+            "camper",
+            "session_id",
+            "session_type",
+            "session_starts_on",
+            "age_bucket",
+            "status",
+            "created_on",
+            "updated_on",
+        ])
+        .map_err(|e| e.to_string())?;
+    for registration in &rows {
+        let session = session_rows
+            .iter()
+            .find(|s| s.id == registration.session_id);
+        let bucket = camper_rows
+            .iter()
+            .find(|c| c.id == registration.camper_id)
+            .zip(session)
+            .map(|(camper, session)| age_bucket(camper.birthdate, session.starts_on))
+            .unwrap_or_default();
+        writer
+            .write_record([
+                anonymizer.pseudonym(anonymize::REGISTRATION, registration.id),
+                anonymizer.pseudonym(anonymize::GUARDIAN, registration.guardian_id),
+                anonymizer.pseudonym(anonymize::CAMPER, registration.camper_id),
+                registration.session_id.to_string(),
+                session.map(|s| s.session_type.clone()).unwrap_or_default(),
+                session.map(|s| s.starts_on.to_string()).unwrap_or_default(),
+                bucket.to_string(),
+                registration.status.clone(),
+                day(registration.created_at).to_string(),
+                day(registration.updated_at).to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    csv_bytes(writer)
+}
+
+fn build_anonymized_payments(
+    conn: &mut PgConnection,
+    anonymizer: &Anonymizer,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<u8>, String> {
+    use crate::database::schema::payment_events;
+
+    let (start, end) = day_range(from, to);
+    let events = payment_events::table
+        .filter(payment_events::created_at.ge(start))
+        .filter(payment_events::created_at.lt(end))
+        .filter(payment_events::status.ne(PaymentEvent::SHEET_CREATED))
+        .order(payment_events::created_at.asc())
+        .load::<PaymentEvent>(conn)
+        .map_err(|e| format!("Failed to load payment events: {e}"))?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "payment",
+            "customer",
+            "status",
+            "amount",
+            "currency",
+            "purpose",
+            "registrations",
+            "created_on",
+        ])
+        .map_err(|e| e.to_string())?;
+    for event in &events {
+        let metadata = anonymizer.payment_metadata(event.metadata.as_ref());
+        writer
+            .write_record([
+                anonymizer.pseudonym(anonymize::PAYMENT, &event.payment_intent_id),
+                event
+                    .customer_id
+                    .as_ref()
+                    .map(|c| anonymizer.pseudonym(anonymize::CUSTOMER, c))
+                    .unwrap_or_default(),
+                event.status.clone(),
+                event.amount.map(|a| a.to_string()).unwrap_or_default(),
+                event.currency.clone().unwrap_or_default(),
+                metadata.purpose.unwrap_or_default(),
+                metadata.registrations.join(";"),
+                day(event.created_at).to_string(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    csv_bytes(writer)
+}
+
+/// One row per session and month, plus a row per currency for money not tied to
+/// any session.
+fn build_session_revenue(
+    conn: &mut PgConnection,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<u8>, String> {
+    if to < from {
+        return Err("to must not be before from".to_string());
+    }
+    let last = revenue::month_end(to);
+    let ledger = revenue::load_ledger(conn, last)
+        .map_err(|e| format!("Failed to load session revenue: {e}"))?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "month",
+            "session_id",
+            "session_name",
+            "session_starts_on",
+            "currency",
+            "collected_in_month",
+            "collected_to_date",
+            "recognized_in_month",
+            "recognized_to_date",
+            "deferred",
+        ])
+        .map_err(|e| e.to_string())?;
+    let mut month = from.with_day(1).unwrap_or(from);
+    while month <= last {
+        let report = revenue::summarize(month, &ledger);
+        for row in &report.sessions {
+            writer
+                .write_record([
+                    report.month.clone(),
+                    row.session_id.to_string(),
+                    row.session_name.clone(),
+                    row.starts_on.to_string(),
+                    row.currency.clone(),
+                    row.collected_in_month.to_string(),
+                    row.collected_to_date.to_string(),
+                    row.recognized_in_month.to_string(),
+                    row.recognized_to_date.to_string(),
+                    row.deferred.to_string(),
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+        for total in report.totals.iter().filter(|t| t.unallocated_in_month != 0) {
+            writer
+                .write_record([
+                    report.month.clone(),
+                    String::new(),
+                    "unallocated".to_string(),
+                    String::new(),
+                    total.currency.clone(),
+                    total.unallocated_in_month.to_string(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+        month = revenue::month_end(month) + chrono::Duration::days(1);
+    }
+    csv_bytes(writer)
+}
+
+/// Queues the anonymized datasets for `on`, skipping any already queued.
+/// Returns the number of jobs queued.
+pub fn queue_analytics_exports(
+    conn: &mut PgConnection,
+    on: NaiveDate,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::export_jobs::dsl::*;
+
+    let requests = [
+        ExportRequest::AnonymizedRegistrations { from: on, to: on },
+        ExportRequest::AnonymizedPayments { from: on, to: on },
+    ];
+    let mut queued = 0;
+    for request in requests {
+        let job_params = json!(request);
+        let exists = diesel::select(diesel::dsl::exists(
+            export_jobs
+                .filter(kind.eq(request.kind()))
+                .filter(params.eq(&job_params))
+                .filter(status.ne("failed")),
+        ))
+        .get_result::<bool>(conn)?;
+        if exists {
+            continue;
+        }
+        diesel::insert_into(export_jobs)
+            .values(&NewExportJob {
+                id: Uuid::new_v4(),
+                kind: request.kind().to_string(),
+                params: job_params,
+                requested_by: None,
+            })
+            .execute(conn)?;
+        queued += 1;
+    }
+    Ok(queued)
+}
+
+/// Builds and uploads the file for one job, returning its S3 key.
+async fn run_export(conn: &mut PgConnection, job: &ExportJob) -> Result<String, String> {
+    let request: ExportRequest = serde_json::from_value(job.params.clone())
+        .map_err(|e| format!("Invalid export parameters: {e}"))?;
+    let body = match request {
+        ExportRequest::Roster { session_id, tags } => {
+            let tags: Vec<String> = tags.iter().filter_map(|t| normalize_tag(t)).collect();
+            build_roster(conn, session_id, &tags)?
+        }
+        ExportRequest::Payments { from, to } => build_payments_journal(conn, from, to)?,
+        ExportRequest::AnonymizedRegistrations { from, to } => {
+            build_anonymized_registrations(conn, &Anonymizer::from_env()?, from, to)?
+        }
+        ExportRequest::AnonymizedPayments { from, to } => {
+            build_anonymized_payments(conn, &Anonymizer::from_env()?, from, to)?
+        }
+        ExportRequest::SessionRevenue { from, to } => build_session_revenue(conn, from, to)?,
+    };
+
+    let key = format!("exports/{}/{}.csv", job.kind, job.id);
+    s3_archive::upload(&key, body, "text/csv").await?;
+    Ok(key)
+}
+
+/// Fails jobs stuck in `running`, then claims queued export jobs, builds them and
+/// uploads the results. Returns `(completed, failed)` counts, stale jobs included in
+/// `failed`.
+pub async fn process_queued_exports(
+    state: &Arc<Mutex<AppState>>,
+) -> Result<(usize, usize), String> {
+    use crate::database::schema::export_jobs::dsl::*;
+
+    let db_client = state
+        .lock()
+        .await
+        .database_client
+        .clone()
+        .ok_or("Database client not available")?;
+    let mut conn = get_conn(&db_client.pool).map_err(|e| e.to_string())?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let stale = diesel::update(
+        export_jobs
+            .filter(status.eq("running"))
+            .filter(started_at.lt(now - RUNNING_TIMEOUT)),
+    )
+    .set((
+        status.eq("failed"),
+        error.eq(Some("Export did not finish in time; request it again")),
+        completed_at.eq(Some(now)),
+    ))
+    .execute(&mut conn)
+    .map_err(|e| format!("Failed to time out stale export jobs: {e}"))?;
+    if stale > 0 {
+        error!("Failed {stale} export jobs left running by an earlier run");
+    }
+
+    // Claim jobs so concurrent runs never build the same export twice
+    let claimed = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let jobs = export_jobs
+                .filter(status.eq("queued"))
+                .order(created_at.asc())
+                .limit(JOBS_PER_RUN)
+                .for_update()
+                .skip_locked()
+                .load::<ExportJob>(conn)?;
+            let ids: Vec<Uuid> = jobs.iter().map(|j| j.id).collect();
+            diesel::update(export_jobs.filter(id.eq_any(&ids)))
+                .set((status.eq("running"), started_at.eq(Some(now))))
+                .execute(conn)?;
+            Ok(jobs)
+        })
+        .map_err(|e| format!("Failed to claim export jobs: {e}"))?;
+
+    let (mut completed, mut failed) = (0, stale);
+    for job in claimed {
+        let result = run_export(&mut conn, &job).await;
+        let now = Some(chrono::Utc::now().naive_utc());
+        let update = match &result {
+            Ok(key) => {
+                info!("Export {} uploaded to {key}", job.id);
+                completed += 1;
+                diesel::update(export_jobs.find(job.id))
+                    .set((
+                        status.eq("completed"),
+                        s3_key.eq(Some(key.as_str())),
+                        completed_at.eq(now),
+                    ))
+                    .execute(&mut conn)
+            }
+            Err(e) => {
+                error!("Export {} failed: {e}", job.id);
+                failed += 1;
+                diesel::update(export_jobs.find(job.id))
+                    .set((
+                        status.eq("failed"),
+                        error.eq(Some(e.as_str())),
+                        completed_at.eq(now),
+                    ))
+                    .execute(&mut conn)
+            }
+        };
+        if let Err(e) = update {
+            error!("Failed to update export job {}: {e}", job.id);
+        }
+    }
+    Ok((completed, failed))
+}
+
+/// POST /admin/exports queues an export job.
+#[tracing::instrument(skip(state))]
+pub async fn create_export_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<ExportRequest>,
+) -> Result<(StatusCode, axum::Json<Value>), (StatusCode, String)> {
+    let job = NewExportJob {
+        id: Uuid::new_v4(),
+        kind: payload.kind().to_string(),
+        params: json!(payload),
+        requested_by: actor.subject_id,
+    };
+
+    let mut conn = conn_from_state(&state).await?;
+    diesel::insert_into(crate::database::schema::export_jobs::table)
+        .values(&job)
+        .execute(&mut conn)
+        .map_err(db_error("Failed to queue export"))?;
+    info!("Queued {} export {}", job.kind, job.id);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        axum::Json(json!(ExportQueuedResponse {
+            export_id: job.id,
+            status: "queued".to_string(),
+        })),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportQueuedResponse {
+    pub export_id: Uuid,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportStatusResponse {
+    pub export_id: Uuid,
+    pub kind: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+    pub download_url: Option<String>,
+    pub download_url_expires_in_seconds: Option<u64>,
+}
+
+impl ExportStatusResponse {
+    pub fn new(job: ExportJob, download_url: Option<String>) -> Self {
+        Self {
+            export_id: job.id,
+            kind: job.kind,
+            status: job.status,
+            error: job.error,
+            created_at: job.created_at,
+            completed_at: job.completed_at,
+            download_url_expires_in_seconds: download_url
+                .as_ref()
+                .map(|_| DOWNLOAD_URL_TTL.as_secs()),
+            download_url,
+        }
+    }
+}
+
+/// GET /admin/exports/{id} reports an export's status, with a presigned download URL
+/// once it has completed.
+#[tracing::instrument(skip(state))]
+pub async fn export_status_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(export_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let job = crate::database::schema::export_jobs::table
+        .find(export_id)
+        .first::<ExportJob>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to load export"))?
+        .ok_or((StatusCode::NOT_FOUND, "Export not found".to_string()))?;
+
+    let download_url = match (&job.status[..], &job.s3_key) {
+        ("completed", Some(key)) => Some(
+            s3_archive::presigned_url(key, DOWNLOAD_URL_TTL)
+                .await
+                .map_err(|e| {
+                    error!("{e}");
+                    (StatusCode::INTERNAL_SERVER_ERROR, e)
+                })?,
+        ),
+        _ => None,
+    };
+
+    Ok(axum::Json(json!(ExportStatusResponse::new(
+        job,
+        download_url
+    ))))
+}
@@ -0,0 +1,2020 @@
+//! Contract fixtures for client teams.
+//!
+//! Every fixture is built from the same response types the handlers return, filled
+//! with fixed sample data, so a change to a response shape changes the fixtures too.
+//! `tests/contract.rs` snapshots them into `fixtures/contract.json` and checks that
+//! every declared route has a success fixture. `GET /dev/fixtures` serves them when
+//! `APP_ENV` is a development environment.
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::IssuedTokenResponse;
+use crate::cabin_matching::{CabinPlan, SuggestedCabin, UnmatchedReason, UnmatchedRequest};
+use crate::cabins::{Cabin, CabinsResponse};
+use crate::check_in::{ScanResponse, ScannedCamper, ScannedSession};
+use crate::config_profiles::Profile;
+use crate::corrections::CorrectionReviewedResponse;
+use crate::database::models::{
+    AdminAlert, ApiToken, AttendanceEvent, AuthorizedPickup, CampCredit, CampSession, Camper,
+    CamperCorrection, CancellationRefund, DelegatedLink, DomainEventRecord, ExportJob,
+    MedicalAccess, MedicalRecord, NotificationDelivery, PaymentLimitOverride, Registration,
+    RegistrationDraft, RoleCertification, SessionCancellation, Staff, StaffAssignment,
+    StaffCertification, Voucher, WaitlistEntry,
+};
+use crate::db_health::{DatabaseStatus, ReadinessReport};
+use crate::delegations::{
+    DelegatedLinkCreatedResponse, DelegatedPaymentSheetResponse, DelegatedRegistrationResponse,
+    DelegatedSessionSummary,
+};
+use crate::enrollment::{AtRiskSession, AtRiskSessionsResponse};
+use crate::events::ActivityFeedResponse;
+use crate::exchange_rates::{conversion_note, ConvertedAmount};
+use crate::exports::{ExportQueuedResponse, ExportStatusResponse};
+use crate::guardians::GuardianCreditsResponse;
+use crate::handlers::PaymentSheetResponse;
+use crate::kiosk_sync::{SyncResponse, SyncResult};
+use crate::medical::{MedicalAccessReportResponse, MedicalRecordResponse};
+use crate::metrics::Counter;
+use crate::notification_rules::{NotificationPreferencesResponse, RulePreference};
+use crate::notifications::{
+    MessageRetries, PaymentDeliveriesResponse, RegistrationDeliveriesResponse,
+};
+use crate::payment_limits::{AmountRange, PaymentLimits, PaymentLimitsResponse};
+use crate::payment_methods::{MethodCategory, MethodTotal, PaymentMethodsReport};
+use crate::payment_timeline::{PaymentTimelineResponse, TimelineEntry};
+use crate::pickups::{PickupListResponse, PickupLogEntry, PickupLogResponse};
+use crate::public_availability::{
+    Availability, PublicAvailabilityResponse, PublicSessionAvailability,
+};
+use crate::quotes::{LineItem, QuoteResponse};
+use crate::receipts::ReceiptResponse;
+use crate::registration_cancellations::{CancellationPreview, RegistrationCancelledResponse};
+use crate::registration_drafts::DraftResponse;
+use crate::registration_import::{ImportReport, ImportStatus, ImportedRow, RowError};
+use crate::registrations::RegistrationCreatedResponse;
+use crate::revenue::{RevenueTotals, SessionRevenueReport, SessionRevenueRow};
+use crate::roster::{RosterEntry, RosterResponse};
+use crate::route_policy::{Access, ROUTE_POLICIES};
+use crate::session_cancellations::{BatchSummary, CancelSessionResponse, CancellationProgress};
+use crate::staff::{ScheduleEntry, StaffScheduleResponse};
+use crate::stripe_webhook::WebhookError;
+use crate::tags::RegistrationTagsResponse;
+use crate::tax::{
+    BillingAddress, BillingAddressResponse, OrgTaxDetails, TaxReceiptDetails, TaxSummaryResponse,
+    TaxYearPayment, TaxYearRefund, TaxYearTotal,
+};
+use crate::usage::{ClientUsage, UsageReportResponse};
+use crate::vouchers::{
+    VoucherBalanceResponse, VoucherPurchaseResponse, VoucherRedeemedResponse, VoucherStatusResponse,
+};
+use crate::waitlist::{GuardianWaitlistResponse, WaitTimeEstimate, WaitlistPosition};
+use crate::webhook_coverage::{EventTypeCoverage, WebhookCoverageReport};
+use crate::websocket_handler::ClientMessageError;
+use crate::ws_connections::{ConnectionSummary, DisconnectResponse, WsConnectionsResponse};
+use axum::http::StatusCode;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// A sample response for one route.
+#[derive(Debug, Clone, Serialize)]
+pub struct Fixture {
+    pub method: &'static str,
+    /// The route path, in router syntax.
+    pub path: &'static str,
+    pub name: String,
+    pub status: u16,
+    /// `application/json`, `text/plain` for bodies sent as a bare string, or
+    /// `application/pdf` for documents, whose body is not reproduced.
+    pub content_type: &'static str,
+    pub body: Value,
+}
+
+fn ok(method: &'static str, path: &'static str, body: impl Serialize) -> Fixture {
+    Fixture {
+        method,
+        path,
+        name: "success".to_string(),
+        status: StatusCode::OK.as_u16(),
+        content_type: "application/json",
+        body: json!(body),
+    }
+}
+
+fn no_content(method: &'static str, path: &'static str) -> Fixture {
+    Fixture {
+        method,
+        path,
+        name: "success".to_string(),
+        status: StatusCode::NO_CONTENT.as_u16(),
+        content_type: "text/plain",
+        body: Value::Null,
+    }
+}
+
+fn pdf(method: &'static str, path: &'static str) -> Fixture {
+    Fixture {
+        method,
+        path,
+        name: "success".to_string(),
+        status: StatusCode::OK.as_u16(),
+        content_type: "application/pdf",
+        body: Value::Null,
+    }
+}
+
+/// Other handlers report errors as `(StatusCode, String)`, which axum sends as plain text.
+fn error(method: &'static str, path: &'static str, status: StatusCode, message: &str) -> Fixture {
+    Fixture {
+        method,
+        path,
+        name: status
+            .canonical_reason()
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace(' ', "_"),
+        status: status.as_u16(),
+        content_type: "text/plain",
+        body: Value::String(message.to_string()),
+    }
+}
+
+/// Registration, pricing and payment handlers send an [`ApiError`] envelope; the
+/// fixture is named after its code.
+fn coded(method: &'static str, path: &'static str, error: ApiError) -> Fixture {
+    let body = error.body();
+    Fixture {
+        method,
+        path,
+        name: body["error"].as_str().unwrap_or("error").to_lowercase(),
+        status: error.status.as_u16(),
+        content_type: "application/json",
+        body,
+    }
+}
+
+fn named(mut fixture: Fixture, name: &str) -> Fixture {
+    fixture.name = name.to_string();
+    fixture
+}
+
+fn id(n: u128) -> Uuid {
+    Uuid::from_u128(n)
+}
+
+fn date(month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2026, month, day).expect("valid fixture date")
+}
+
+fn at(month: u32, day: u32, hour: u32) -> NaiveDateTime {
+    date(month, day)
+        .and_hms_opt(hour, 0, 0)
+        .expect("valid fixture time")
+}
+
+const GUARDIAN: u128 = 0x1000;
+const CAMPER: u128 = 0x2000;
+const SESSION: u128 = 0x3000;
+const REGISTRATION: u128 = 0x4000;
+const STAFF_MEMBER: u128 = 0x5000;
+const QUOTE: u128 = 0x6000;
+const VOUCHER: u128 = 0x7000;
+const DRAFT: u128 = 0x8000;
+const PAYMENT_INTENT: &str = "pi_3Fixture000000000000000";
+const PUBLISHABLE_KEY: &str = "pk_test_fixture";
+
+fn session() -> CampSession {
+    CampSession {
+        id: id(SESSION),
+        name: "Lakeside Week 1".to_string(),
+        starts_on: date(7, 6),
+        ends_on: date(7, 11),
+        capacity: 40,
+        price: 45_000,
+        currency: "usd".to_string(),
+        created_at: at(1, 15, 9),
+        cancelled_at: None,
+        session_type: "lakeside".to_string(),
+        min_age: Some(8),
+        max_age: Some(14),
+        min_enrollment: Some(12),
+        enrollment_cutoff: Some(date(6, 8)),
+        enrollment_flagged_at: None,
+    }
+}
+
+fn camper() -> Camper {
+    Camper {
+        id: id(CAMPER),
+        guardian_id: id(GUARDIAN),
+        first_name: "Avery".to_string(),
+        last_name: "Lindqvist".to_string(),
+        birthdate: NaiveDate::from_ymd_opt(2015, 4, 12).expect("valid fixture date"),
+        created_at: at(2, 1, 10),
+    }
+}
+
+fn correction(status: &str) -> CamperCorrection {
+    let approved = status == "approved";
+    let pending = status == "pending";
+    CamperCorrection {
+        id: id(CAMPER + 2),
+        camper_id: id(CAMPER),
+        guardian_id: id(GUARDIAN),
+        first_name: None,
+        last_name: None,
+        birthdate: NaiveDate::from_ymd_opt(2015, 4, 21),
+        reason: Some("Birth day and month digits were swapped".to_string()),
+        status: status.to_string(),
+        previous_first_name: None,
+        previous_last_name: None,
+        previous_birthdate: approved.then(|| camper().birthdate),
+        reviewed_by: (!pending).then(|| id(STAFF_MEMBER)),
+        reviewer_role: (!pending).then(|| "director".to_string()),
+        review_note: approved.then(|| "Matches the birth certificate".to_string()),
+        created_at: at(3, 2, 9),
+        reviewed_at: (!pending).then(|| at(3, 3, 11)),
+    }
+}
+
+fn cabins() -> CabinsResponse {
+    CabinsResponse {
+        session_id: id(SESSION),
+        cabins: vec![Cabin {
+            name: "Heron".to_string(),
+            registration_ids: vec![id(REGISTRATION)],
+        }],
+        unassigned: vec![id(REGISTRATION + 1)],
+        unmatched_requests: vec![UnmatchedRequest {
+            registration_id: id(REGISTRATION),
+            friend_name: "Riley Okafor".to_string(),
+            friend_registration_id: Some(id(REGISTRATION + 1)),
+            reason: UnmatchedReason::Unassigned,
+        }],
+        suggestion: None,
+    }
+}
+
+fn registration(status: &str) -> Registration {
+    Registration {
+        id: id(REGISTRATION),
+        guardian_id: id(GUARDIAN),
+        camper_id: id(CAMPER),
+        session_id: id(SESSION),
+        status: status.to_string(),
+        created_at: at(2, 1, 10),
+        updated_at: at(2, 1, 10),
+    }
+}
+
+fn limit_override() -> PaymentLimitOverride {
+    PaymentLimitOverride {
+        id: id(0xC000),
+        customer_email: "morgan@example.com".to_string(),
+        currency: "usd".to_string(),
+        max_amount: 1_800_000,
+        reason: "Three campers for the full summer in one payment".to_string(),
+        created_by: Some(id(STAFF_MEMBER)),
+        expires_at: at(6, 2, 10),
+        created_at: at(6, 1, 10),
+    }
+}
+
+fn activity() -> ActivityFeedResponse {
+    ActivityFeedResponse {
+        events: vec![
+            DomainEventRecord {
+                id: 1042,
+                kind: "registration.confirmed".to_string(),
+                summary: "Registration paid and confirmed".to_string(),
+                actor_role: "system".to_string(),
+                actor_id: None,
+                camper_id: Some(id(CAMPER)),
+                guardian_id: Some(id(GUARDIAN)),
+                session_id: Some(id(SESSION)),
+                registration_id: Some(id(REGISTRATION)),
+                payment_intent_id: Some(PAYMENT_INTENT.to_string()),
+                details: json!({}),
+                occurred_at: at(3, 1, 12),
+            },
+            DomainEventRecord {
+                id: 1017,
+                kind: "registration.created".to_string(),
+                summary: "Avery registered for Lakeside Week 1".to_string(),
+                actor_role: "guardian".to_string(),
+                actor_id: Some(id(GUARDIAN)),
+                camper_id: Some(id(CAMPER)),
+                guardian_id: Some(id(GUARDIAN)),
+                session_id: Some(id(SESSION)),
+                registration_id: Some(id(REGISTRATION)),
+                payment_intent_id: None,
+                details: json!({ "hold_expires_at": at(3, 1, 12) }),
+                occurred_at: at(3, 1, 11),
+            },
+        ],
+        next_before: None,
+    }
+}
+
+fn staff() -> Staff {
+    Staff {
+        id: id(STAFF_MEMBER),
+        name: "Jordan Okafor".to_string(),
+        email: "jordan@example.com".to_string(),
+        created_at: at(1, 20, 9),
+    }
+}
+
+fn certification() -> StaffCertification {
+    StaffCertification {
+        id: id(STAFF_MEMBER + 1),
+        staff_id: id(STAFF_MEMBER),
+        certification: "lifeguard".to_string(),
+        expires_on: Some(date(12, 31)),
+        created_at: at(1, 20, 9),
+    }
+}
+
+fn assignment() -> StaffAssignment {
+    StaffAssignment {
+        id: id(STAFF_MEMBER + 2),
+        staff_id: id(STAFF_MEMBER),
+        session_id: id(SESSION),
+        role: "lifeguard".to_string(),
+        created_at: at(1, 21, 9),
+    }
+}
+
+fn voucher(status: &str) -> Voucher {
+    Voucher {
+        id: id(VOUCHER),
+        code: (status != "pending").then(|| "CAMP-7K2M-Q9XD".to_string()),
+        amount: 10_000,
+        currency: "usd".to_string(),
+        purchaser_name: "Sam Rivera".to_string(),
+        purchaser_email: "sam@example.com".to_string(),
+        recipient_email: Some("family@example.com".to_string()),
+        payment_intent_id: PAYMENT_INTENT.to_string(),
+        status: status.to_string(),
+        redeemed_by_guardian_id: None,
+        created_at: at(3, 1, 12),
+        redeemed_at: None,
+    }
+}
+
+fn delegated_link() -> DelegatedLink {
+    DelegatedLink {
+        id: id(REGISTRATION + 1),
+        token_hash: String::new(),
+        registration_id: id(REGISTRATION),
+        guardian_id: id(GUARDIAN),
+        recipient_name: Some("Grandma".to_string()),
+        expires_at: at(2, 4, 10),
+        revoked_at: None,
+        payment_intent_id: None,
+        created_at: at(2, 1, 10),
+    }
+}
+
+fn medical_record() -> MedicalRecord {
+    MedicalRecord {
+        camper_id: id(CAMPER),
+        allergies: Some("Peanuts".to_string()),
+        medications: None,
+        conditions: None,
+        notes: Some("Carries an EpiPen".to_string()),
+        updated_at: at(2, 1, 10),
+    }
+}
+
+fn delivery() -> NotificationDelivery {
+    NotificationDelivery {
+        id: id(0x8000),
+        outbox_id: Some(id(0x8001)),
+        channel: "email".to_string(),
+        target: "parent@example.com".to_string(),
+        template: "registration_confirmed".to_string(),
+        outcome: "sent".to_string(),
+        error: None,
+        latency_ms: 182,
+        attempt: 1,
+        payment_intent_id: Some(PAYMENT_INTENT.to_string()),
+        registration_id: Some(id(REGISTRATION)),
+        created_at: at(2, 1, 10),
+        next_attempt_at: None,
+    }
+}
+
+fn message_retries() -> MessageRetries {
+    MessageRetries {
+        outbox_id: id(0x8001),
+        channel: "email".to_string(),
+        template: "registration_confirmed".to_string(),
+        status: "sent".to_string(),
+        attempts: 1,
+        max_attempts: Some(6),
+        next_attempt_at: None,
+        last_error: None,
+    }
+}
+
+fn billing_address() -> BillingAddress {
+    BillingAddress {
+        line1: "418 Birch Hollow Rd".to_string(),
+        line2: None,
+        city: "Duluth".to_string(),
+        region: "MN".to_string(),
+        postal_code: "55803".to_string(),
+        country: "US".to_string(),
+    }
+}
+
+fn registration_draft(session_id: Option<Uuid>) -> DraftResponse {
+    let draft = RegistrationDraft {
+        id: id(DRAFT),
+        guardian_id: id(GUARDIAN),
+        camper_id: Some(id(CAMPER)),
+        session_id,
+        friend_requests: vec!["Riley Okafor".to_string()],
+        answers: json!({ "tshirt_size": "youth_m", "swim_level": "intermediate" }),
+        created_at: at(2, 1, 9),
+        updated_at: at(2, 1, 10),
+        expires_at: at(3, 3, 10),
+        registration_id: None,
+        converted_at: None,
+    };
+    let missing = if session_id.is_some() {
+        vec![]
+    } else {
+        vec!["session_id"]
+    };
+    DraftResponse {
+        ready_for_checkout: missing.is_empty(),
+        missing,
+        draft,
+    }
+}
+
+/// An import of two rows: a new family, and a sibling confirmed from a paper form.
+fn registration_import(committed: bool, errors: Vec<RowError>) -> ImportReport {
+    let imported =
+        |row: usize, status: ImportStatus, created_guardian: bool, n: u128| ImportedRow {
+            row,
+            session_id: id(SESSION),
+            status,
+            created_guardian,
+            created_camper: true,
+            guardian_id: committed.then(|| id(GUARDIAN)),
+            camper_id: committed.then(|| id(CAMPER + n)),
+            registration_id: committed.then(|| id(REGISTRATION + n)),
+        };
+    ImportReport {
+        dry_run: false,
+        committed,
+        rows: 2 + errors.len(),
+        guardians_created: 1,
+        campers_created: 2,
+        registrations_created: 2,
+        imported: vec![
+            imported(2, ImportStatus::Pending, true, 0),
+            imported(3, ImportStatus::Confirmed, false, 1),
+        ],
+        errors,
+    }
+}
+
+fn import_error() -> RowError {
+    RowError {
+        row: 4,
+        column: Some("birthdate".to_string()),
+        code: ErrorCode::InvalidRequest,
+        message: "'07/14/2014' is not a YYYY-MM-DD date".to_string(),
+    }
+}
+
+fn authorized_pickup() -> AuthorizedPickup {
+    AuthorizedPickup {
+        id: id(REGISTRATION + 2),
+        registration_id: id(REGISTRATION),
+        name: "Dana Lindqvist".to_string(),
+        relationship: "grandparent".to_string(),
+        photo_url: Some("https://camp.example.com/pickups/dana.jpg".to_string()),
+        id_note: Some("Shows driver's license".to_string()),
+        added_by: Some(id(GUARDIAN)),
+        created_at: at(6, 20, 9),
+        removed_at: None,
+    }
+}
+
+fn notification_preferences(birthday_opted_out: bool) -> NotificationPreferencesResponse {
+    NotificationPreferencesResponse {
+        guardian_id: id(GUARDIAN),
+        rules: vec![
+            RulePreference {
+                rule: "camper_birthday",
+                description: "A birthday greeting for each camper",
+                enabled: !birthday_opted_out,
+            },
+            RulePreference {
+                rule: "session_countdown",
+                description: "A reminder one week before camp starts",
+                enabled: true,
+            },
+        ],
+    }
+}
+
+fn org_tax_details() -> OrgTaxDetails {
+    OrgTaxDetails {
+        legal_name: "Lakeside Youth Camps, Inc.".to_string(),
+        ein: "41-1234567".to_string(),
+        address: "2200 Shoreline Dr, Grand Marais, MN 55604".to_string(),
+    }
+}
+
+fn converted_amounts() -> Vec<ConvertedAmount> {
+    vec![ConvertedAmount {
+        currency: "cad".to_string(),
+        amount: 61_650,
+        rate: 1.37,
+        rate_date: date(2, 1),
+        informational: true,
+    }]
+}
+
+fn line_items() -> Value {
+    json!([LineItem {
+        label: "Avery Lindqvist – Lakeside Week 1".to_string(),
+        amount: 45_000,
+    }])
+}
+
+fn payment_sheet() -> PaymentSheetResponse {
+    PaymentSheetResponse {
+        customer: "cus_Fixture000000".to_string(),
+        ephemeral_key: Some("ek_test_fixture".to_string()),
+        payment_intent: Some(format!("{PAYMENT_INTENT}_secret_fixture")),
+        publishable_key: PUBLISHABLE_KEY.to_string(),
+    }
+}
+
+fn cancellation_progress(pending: usize) -> CancellationProgress {
+    let refunded = 12 - pending;
+    CancellationProgress {
+        cancellation: SessionCancellation {
+            id: id(SESSION + 1),
+            session_id: id(SESSION),
+            reason: "Lake closed for algae bloom".to_string(),
+            requested_by: Some(id(STAFF_MEMBER)),
+            status: if pending == 0 {
+                "completed"
+            } else {
+                "in_progress"
+            }
+            .to_string(),
+            created_at: at(6, 20, 9),
+            completed_at: (pending == 0).then(|| at(6, 20, 10)),
+        },
+        total: 14,
+        pending,
+        refunded,
+        credited: 1,
+        failed: 0,
+        no_payment: 1,
+        refunded_amount: 45_000 * refunded as i64,
+        pending_amount: 45_000 * pending as i64,
+        credited_amount: 45_000,
+        currency: "usd".to_string(),
+    }
+}
+
+fn waitlist_position() -> WaitlistPosition {
+    WaitlistPosition {
+        entry: WaitlistEntry {
+            id: id(CAMPER + 1),
+            session_id: id(SESSION),
+            camper_id: id(CAMPER),
+            guardian_id: id(GUARDIAN),
+            status: "waiting".to_string(),
+            created_at: at(3, 10, 8),
+            promoted_at: None,
+        },
+        session_name: session().name,
+        position: 3,
+        waiting: 9,
+        estimate: Some(WaitTimeEstimate {
+            session_type: session().session_type,
+            chance_percent: 62.5,
+            sample_sessions: 8,
+            promotion_rate: 0.31,
+            median_days_to_promotion: Some(11.5),
+            computed_at: at(3, 10, 4),
+        }),
+    }
+}
+
+fn success_fixtures() -> Vec<Fixture> {
+    let export_job = ExportJob {
+        id: id(0x9000),
+        kind: "registrations".to_string(),
+        params: json!({ "season": 2026 }),
+        status: "completed".to_string(),
+        s3_key: Some("exports/registrations-2026.csv".to_string()),
+        error: None,
+        requested_by: None,
+        created_at: at(2, 2, 8),
+        started_at: Some(at(2, 2, 8)),
+        completed_at: Some(at(2, 2, 9)),
+    };
+    let alert = AdminAlert {
+        id: id(0xA000),
+        kind: "payment_amount_mismatch".to_string(),
+        message: format!("Payment {PAYMENT_INTENT} paid 40000 usd against a 45000 usd quote"),
+        details: json!({ "quote_id": id(QUOTE), "expected": 45_000, "received": 40_000 }),
+        payment_intent_id: Some(PAYMENT_INTENT.to_string()),
+        created_at: at(2, 1, 11),
+        acknowledged_at: None,
+        acknowledged_by: None,
+    };
+    let api_token = ApiToken {
+        id: id(0xB000),
+        token_hash: String::new(),
+        role: "guardian".to_string(),
+        subject_id: Some(id(GUARDIAN)),
+        label: "Parent app".to_string(),
+        expires_at: None,
+        revoked_at: None,
+        created_at: at(1, 10, 9),
+        scopes: Vec::new(),
+    };
+    let medical_access = MedicalAccess {
+        id: id(0xC000),
+        camper_id: id(CAMPER),
+        reader_token_id: None,
+        reader_role: "counselor".to_string(),
+        reader_subject_id: Some(id(STAFF_MEMBER)),
+        reason: Some("Allergic reaction at lunch".to_string()),
+        break_glass: true,
+        accessed_at: at(7, 7, 12),
+    };
+    let role_certification = RoleCertification {
+        role: "lifeguard".to_string(),
+        certification: "lifeguard".to_string(),
+    };
+
+    vec![
+        Fixture {
+            content_type: "text/plain",
+            ..ok("GET", "/hello", "Hello, world!")
+        },
+        ok(
+            "GET",
+            "/ready",
+            ReadinessReport {
+                ready: true,
+                database: DatabaseStatus::Ok,
+                degraded_since: None,
+                last_failure: None,
+                buffered_ws_registrations: 0,
+                dropped_ws_registrations: 0,
+            },
+        ),
+        ok(
+            "GET",
+            "/stripe_key",
+            json!({ "publishable_key": PUBLISHABLE_KEY }),
+        ),
+        ok("POST", "/payment_sheet", payment_sheet()),
+        coded(
+            "POST",
+            "/payment_sheet",
+            ApiError::new(
+                StatusCode::GONE,
+                ErrorCode::LinkExpired,
+                "Offer has expired",
+            ),
+        ),
+        Fixture {
+            content_type: "text/plain",
+            ..ok("POST", "/webhook", "Webhook received")
+        },
+        Fixture {
+            name: "subscription_confirmed".to_string(),
+            status: StatusCode::SWITCHING_PROTOCOLS.as_u16(),
+            ..ok(
+                "GET",
+                "/payment_status",
+                json!({ "type": "subscription_confirmed", "payment_intent_id": PAYMENT_INTENT }),
+            )
+        },
+        ok(
+            "POST",
+            "/quote",
+            QuoteResponse {
+                quote_id: id(QUOTE),
+                registration_ids: vec![id(REGISTRATION)],
+                currency: "usd".to_string(),
+                line_items: line_items(),
+                subtotal: 45_000,
+                credit_applied: 0,
+                processing_fee: 0,
+                total: 45_000,
+                converted_totals: converted_amounts(),
+                conversion_note: conversion_note("usd"),
+            },
+        ),
+        named(
+            ok(
+                "POST",
+                "/quote",
+                QuoteResponse {
+                    quote_id: id(QUOTE),
+                    registration_ids: vec![id(REGISTRATION)],
+                    currency: "usd".to_string(),
+                    line_items: json!([
+                        LineItem {
+                            label: "Avery Lindqvist – Lakeside Week 1".to_string(),
+                            amount: 45_000,
+                        },
+                        LineItem {
+                            label: "Processing fee (2.9% + 0.30 USD)".to_string(),
+                            amount: 1_375,
+                        },
+                    ]),
+                    subtotal: 45_000,
+                    credit_applied: 0,
+                    processing_fee: 1_375,
+                    total: 46_375,
+                    converted_totals: converted_amounts(),
+                    conversion_note: conversion_note("usd"),
+                },
+            ),
+            "with_processing_fee",
+        ),
+        ok(
+            "POST",
+            "/vouchers",
+            VoucherPurchaseResponse {
+                voucher_id: id(VOUCHER),
+                payment_intent: Some(format!("{PAYMENT_INTENT}_secret_fixture")),
+                publishable_key: PUBLISHABLE_KEY.to_string(),
+            },
+        ),
+        ok(
+            "POST",
+            "/vouchers/redeem",
+            VoucherRedeemedResponse {
+                guardian_id: id(GUARDIAN),
+                credited: 10_000,
+                currency: "usd".to_string(),
+            },
+        ),
+        ok(
+            "GET",
+            "/vouchers/purchases/{id}",
+            VoucherStatusResponse::from(voucher("issued")),
+        ),
+        ok(
+            "GET",
+            "/vouchers/{code}",
+            VoucherBalanceResponse::from(voucher("issued")),
+        ),
+        ok(
+            "GET",
+            "/guardians/{id}/credits",
+            GuardianCreditsResponse {
+                guardian_id: id(GUARDIAN),
+                balances: BTreeMap::from([("usd".to_string(), 10_000)]),
+                entries: vec![CampCredit {
+                    id: id(GUARDIAN + 1),
+                    guardian_id: id(GUARDIAN),
+                    amount: 10_000,
+                    currency: "usd".to_string(),
+                    reason: "voucher_redemption".to_string(),
+                    voucher_id: Some(id(VOUCHER)),
+                    quote_id: None,
+                    created_at: at(3, 2, 12),
+                }],
+            },
+        ),
+        ok("GET", "/guardians/{id}/activity", activity()),
+        ok(
+            "GET",
+            "/guardians/{id}/waitlist",
+            GuardianWaitlistResponse {
+                guardian_id: id(GUARDIAN),
+                entries: vec![waitlist_position()],
+            },
+        ),
+        ok("POST", "/waitlist", waitlist_position()),
+        ok("GET", "/sessions", json!({ "sessions": [session()] })),
+        ok("GET", "/sessions/{id}", session()),
+        ok(
+            "GET",
+            "/public/sessions/availability",
+            PublicAvailabilityResponse {
+                sessions: vec![
+                    PublicSessionAvailability {
+                        name: "Lakeside Week 1".to_string(),
+                        availability: Availability::Limited,
+                    },
+                    PublicSessionAvailability {
+                        name: "Lakeside Week 2".to_string(),
+                        availability: Availability::Waitlist,
+                    },
+                ],
+            },
+        ),
+        ok("POST", "/admin/sessions", session()),
+        ok("POST", "/admin/sessions/{id}/staff", assignment()),
+        ok(
+            "POST",
+            "/admin/sessions/{id}/cancel",
+            CancelSessionResponse {
+                progress: cancellation_progress(0),
+                families_notified: 13,
+                first_batch: BatchSummary {
+                    processed: 12,
+                    refunded: 12,
+                    retrying: 0,
+                    failed: 0,
+                },
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/sessions/{id}/cancellation",
+            cancellation_progress(4),
+        ),
+        ok("GET", "/admin/sessions/{id}/activity", activity()),
+        ok(
+            "GET",
+            "/admin/sessions/at_risk",
+            AtRiskSessionsResponse {
+                warning_days: 14,
+                sessions: vec![AtRiskSession {
+                    session_id: id(SESSION),
+                    name: session().name,
+                    starts_on: session().starts_on,
+                    min_enrollment: 12,
+                    enrollment_cutoff: date(6, 8),
+                    enrolled: 9,
+                    shortfall: 3,
+                    cutoff_passed: false,
+                    flagged_at: Some(at(5, 25, 6)),
+                }],
+            },
+        ),
+        ok("PUT", "/admin/sessions/{id}/minimum_enrollment", session()),
+        ok(
+            "POST",
+            "/admin/sessions/{id}/cancel_under_enrolled",
+            CancelSessionResponse {
+                progress: cancellation_progress(0),
+                families_notified: 9,
+                first_batch: BatchSummary {
+                    processed: 9,
+                    refunded: 9,
+                    retrying: 0,
+                    failed: 0,
+                },
+            },
+        ),
+        ok("POST", "/admin/staff", staff()),
+        ok("POST", "/admin/staff/{id}/certifications", certification()),
+        ok(
+            "GET",
+            "/admin/staff/{id}/schedule",
+            StaffScheduleResponse {
+                staff: staff(),
+                certifications: vec![certification()],
+                schedule: vec![ScheduleEntry {
+                    assignment_id: id(STAFF_MEMBER + 2),
+                    role: "lifeguard".to_string(),
+                    session_id: id(SESSION),
+                    session_name: session().name,
+                    starts_on: session().starts_on,
+                    ends_on: session().ends_on,
+                }],
+            },
+        ),
+        no_content("DELETE", "/admin/staff_assignments/{id}"),
+        ok(
+            "GET",
+            "/admin/roles/certifications",
+            json!({ "role_certifications": [&role_certification] }),
+        ),
+        ok(
+            "PUT",
+            "/admin/roles/{role}/certifications",
+            json!({ "role": "lifeguard", "certifications": [&role_certification] }),
+        ),
+        ok("POST", "/campers", camper()),
+        ok("GET", "/campers/{id}", camper()),
+        ok("GET", "/campers/{id}/activity", activity()),
+        ok("POST", "/campers/{id}/corrections", correction("pending")),
+        ok(
+            "GET",
+            "/campers/{id}/corrections",
+            json!({ "corrections": [correction("pending")] }),
+        ),
+        ok(
+            "GET",
+            "/admin/corrections",
+            json!({ "corrections": [correction("pending")] }),
+        ),
+        ok(
+            "POST",
+            "/admin/corrections/{id}/approve",
+            CorrectionReviewedResponse {
+                correction: correction("approved"),
+                camper: Camper {
+                    birthdate: NaiveDate::from_ymd_opt(2015, 4, 21).expect("valid fixture date"),
+                    ..camper()
+                },
+                age_conflicts: Vec::new(),
+            },
+        ),
+        ok(
+            "POST",
+            "/admin/corrections/{id}/reject",
+            CorrectionReviewedResponse {
+                correction: correction("rejected"),
+                camper: camper(),
+                age_conflicts: Vec::new(),
+            },
+        ),
+        ok(
+            "GET",
+            "/campers/{id}/medical",
+            MedicalRecordResponse {
+                camper_id: id(CAMPER),
+                medical_record: Some(medical_record()),
+            },
+        ),
+        no_content("PUT", "/campers/{id}/medical"),
+        ok(
+            "GET",
+            "/admin/compliance/medical_access",
+            MedicalAccessReportResponse {
+                season: 2026,
+                total_accesses: 1,
+                break_glass_accesses: 1,
+                accesses: vec![medical_access],
+            },
+        ),
+        ok(
+            "GET",
+            "/receipts/{payment_intent_id}",
+            ReceiptResponse {
+                payment_intent_id: PAYMENT_INTENT.to_string(),
+                receipt_number: "R-000123".to_string(),
+                paid_at: at(2, 1, 10),
+                amount: 45_000,
+                currency: "usd".to_string(),
+                line_items: Some(line_items()),
+                processing_fee: 0,
+                converted_amounts: converted_amounts(),
+                conversion_note: conversion_note("usd"),
+                tax: Some(TaxReceiptDetails {
+                    organization: Some(org_tax_details()),
+                    payer_name: "Morgan Lindqvist".to_string(),
+                    billing_address: Some(billing_address()),
+                }),
+            },
+        ),
+        pdf("GET", "/receipts/{payment_intent_id}/pdf"),
+        ok(
+            "GET",
+            "/me/billing_address",
+            BillingAddressResponse {
+                guardian_id: id(GUARDIAN),
+                billing_address: Some(billing_address()),
+            },
+        ),
+        ok(
+            "PUT",
+            "/me/billing_address",
+            BillingAddressResponse {
+                guardian_id: id(GUARDIAN),
+                billing_address: Some(billing_address()),
+            },
+        ),
+        ok(
+            "GET",
+            "/me/tax_summary",
+            TaxSummaryResponse {
+                year: 2026,
+                guardian_id: id(GUARDIAN),
+                payer_name: "Morgan Lindqvist".to_string(),
+                billing_address: Some(billing_address()),
+                organization: Some(org_tax_details()),
+                totals: vec![TaxYearTotal {
+                    currency: "usd".to_string(),
+                    paid: 45_000,
+                    refunded: 5_000,
+                    eligible: 40_000,
+                }],
+                payments: vec![TaxYearPayment {
+                    payment_intent_id: PAYMENT_INTENT.to_string(),
+                    paid_at: at(2, 1, 10),
+                    amount: 45_000,
+                    currency: "usd".to_string(),
+                    campers: vec!["Avery Lindqvist".to_string()],
+                }],
+                refunds: vec![TaxYearRefund {
+                    payment_intent_id: PAYMENT_INTENT.to_string(),
+                    refunded_at: at(3, 2, 11),
+                    amount: 5_000,
+                    currency: "usd".to_string(),
+                }],
+            },
+        ),
+        ok(
+            "GET",
+            "/me/notification_preferences",
+            notification_preferences(false),
+        ),
+        ok(
+            "PUT",
+            "/me/notification_preferences",
+            notification_preferences(true),
+        ),
+        ok(
+            "POST",
+            "/registrations",
+            RegistrationCreatedResponse {
+                registration: registration("pending"),
+                hold_expires_at: at(2, 1, 10) + chrono::Duration::minutes(15),
+                friend_requests: vec!["Riley Okafor".to_string()],
+            },
+        ),
+        ok("GET", "/registrations/{id}", registration("confirmed")),
+        ok(
+            "GET",
+            "/registrations/draft",
+            json!({ "drafts": [registration_draft(Some(id(SESSION)))] }),
+        ),
+        ok("POST", "/registrations/draft", registration_draft(None)),
+        ok(
+            "GET",
+            "/registrations/draft/{id}",
+            registration_draft(Some(id(SESSION))),
+        ),
+        ok(
+            "PATCH",
+            "/registrations/draft/{id}",
+            registration_draft(Some(id(SESSION))),
+        ),
+        ok(
+            "POST",
+            "/registrations/draft/{id}/convert",
+            RegistrationCreatedResponse {
+                registration: registration("pending"),
+                hold_expires_at: at(2, 1, 10) + chrono::Duration::minutes(15),
+                friend_requests: vec!["Riley Okafor".to_string()],
+            },
+        ),
+        coded(
+            "POST",
+            "/registrations/draft/{id}/convert",
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::InvalidRequest,
+                "Draft is missing session_id",
+            ),
+        ),
+        ok(
+            "POST",
+            "/admin/registrations/import",
+            registration_import(true, Vec::new()),
+        ),
+        named(
+            ok(
+                "POST",
+                "/admin/registrations/import",
+                ImportReport {
+                    dry_run: true,
+                    ..registration_import(false, vec![import_error()])
+                },
+            ),
+            "dry_run",
+        ),
+        Fixture {
+            status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+            ..named(
+                ok(
+                    "POST",
+                    "/admin/registrations/import",
+                    registration_import(false, vec![import_error()]),
+                ),
+                "unprocessable_entity",
+            )
+        },
+        coded(
+            "POST",
+            "/admin/registrations/import",
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "Missing CSV columns: birthdate",
+            ),
+        ),
+        ok(
+            "POST",
+            "/me/registrations/{id}/cancel_preview",
+            CancellationPreview {
+                registration_id: id(REGISTRATION),
+                session_id: id(SESSION),
+                session_name: session().name,
+                starts_on: session().starts_on,
+                days_before_start: 21,
+                refund_percent: 50,
+                card_refund: 22_500,
+                credit_refund: 0,
+                total_refund: 22_500,
+                currency: "usd".to_string(),
+            },
+        ),
+        ok(
+            "POST",
+            "/me/registrations/{id}/cancel",
+            RegistrationCancelledResponse {
+                registration: Registration {
+                    updated_at: at(6, 15, 18),
+                    ..registration("cancelled")
+                },
+                refund: CancellationRefund {
+                    id: id(REGISTRATION + 1),
+                    cancellation_id: None,
+                    registration_id: id(REGISTRATION),
+                    guardian_id: id(GUARDIAN),
+                    payment_intent_id: Some(PAYMENT_INTENT.to_string()),
+                    amount: 22_500,
+                    credit_amount: 0,
+                    currency: "usd".to_string(),
+                    status: "refunded".to_string(),
+                    stripe_refund_id: Some("re_3Fixture000000000000000".to_string()),
+                    error: None,
+                    attempts: 1,
+                    created_at: at(6, 15, 18),
+                    updated_at: at(6, 15, 18),
+                    cause: "guardian".to_string(),
+                },
+            },
+        ),
+        ok(
+            "POST",
+            "/registrations/{id}/delegations",
+            DelegatedLinkCreatedResponse {
+                token: "3f9c0d2e5b8a4f61a7d2c9e0b4f1a6d83f9c0d2e5b8a4f61".to_string(),
+                url: Some("https://camp.example.com/pay/3f9c0d2e5b8a4f61".to_string()),
+                link: delegated_link(),
+            },
+        ),
+        ok(
+            "GET",
+            "/registrations/{id}/delegations",
+            vec![delegated_link()],
+        ),
+        no_content("DELETE", "/registrations/{id}/delegations/{link_id}"),
+        ok(
+            "GET",
+            "/registrations/{id}/pickups",
+            PickupListResponse {
+                registration_id: id(REGISTRATION),
+                pickups: vec![authorized_pickup()],
+            },
+        ),
+        ok("POST", "/registrations/{id}/pickups", authorized_pickup()),
+        error(
+            "POST",
+            "/registrations/{id}/pickups",
+            StatusCode::CONFLICT,
+            "At most 10 authorized pickups per registration",
+        ),
+        no_content("DELETE", "/registrations/{id}/pickups/{pickup_id}"),
+        ok(
+            "GET",
+            "/delegated/{token}",
+            DelegatedRegistrationResponse {
+                registration_id: id(REGISTRATION),
+                camper_first_name: camper().first_name,
+                session: DelegatedSessionSummary {
+                    name: session().name,
+                    starts_on: session().starts_on,
+                    ends_on: session().ends_on,
+                },
+                amount: 45_000,
+                currency: "usd".to_string(),
+                recipient_name: Some("Grandma".to_string()),
+                link_expires_at: at(2, 4, 10),
+            },
+        ),
+        ok(
+            "POST",
+            "/delegated/{token}/payment_sheet",
+            DelegatedPaymentSheetResponse {
+                payment_sheet: payment_sheet(),
+                quote_id: id(QUOTE),
+                amount: 45_000,
+                currency: "usd".to_string(),
+                hold_expires_at: at(2, 2, 10),
+            },
+        ),
+        ok(
+            "POST",
+            "/sync",
+            SyncResponse {
+                kiosk_id: "dining-hall-1".to_string(),
+                results: vec![SyncResult {
+                    operation_id: id(0xD000),
+                    status: "applied".to_string(),
+                    outcome: "applied".to_string(),
+                    detail: None,
+                }],
+            },
+        ),
+        ok(
+            "POST",
+            "/check_in/scan",
+            ScanResponse {
+                registration_id: id(REGISTRATION),
+                camper: ScannedCamper {
+                    id: id(CAMPER),
+                    first_name: "Avery".to_string(),
+                    last_name: "Lindqvist".to_string(),
+                    birthdate: camper().birthdate,
+                },
+                guardian_name: "Morgan Lindqvist".to_string(),
+                session: ScannedSession {
+                    id: id(SESSION),
+                    name: "Lakeside Week 1".to_string(),
+                    starts_on: date(7, 6),
+                    ends_on: date(7, 11),
+                },
+                event: AttendanceEvent {
+                    id: id(0xD001),
+                    registration_id: id(REGISTRATION),
+                    kind: "check_in".to_string(),
+                    occurred_at: at(7, 6, 8),
+                    source: "qr".to_string(),
+                    operation_id: None,
+                    recorded_by: Some(id(STAFF_MEMBER)),
+                    picked_up_by: None,
+                    created_at: at(7, 6, 8),
+                },
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/payments/{id}/deliveries",
+            PaymentDeliveriesResponse {
+                payment_intent_id: PAYMENT_INTENT.to_string(),
+                deliveries: vec![delivery()],
+                messages: vec![message_retries()],
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/payments/{id}/timeline",
+            PaymentTimelineResponse {
+                payment_intent_id: PAYMENT_INTENT.to_string(),
+                entries: vec![
+                    TimelineEntry {
+                        occurred_at: at(2, 1, 9),
+                        kind: "payment_sheet",
+                        summary: "PaymentSheet created".to_string(),
+                        source_id: id(0x8100),
+                        details: json!({
+                            "status": "payment_sheet_created",
+                            "amount": 45_000,
+                            "currency": "usd",
+                            "customer_id": "cus_Fixture000000",
+                        }),
+                    },
+                    TimelineEntry::from(delivery()),
+                    TimelineEntry {
+                        occurred_at: at(2, 3, 15),
+                        kind: "refund",
+                        summary: "Refund succeeded".to_string(),
+                        source_id: id(0x8200),
+                        details: json!({
+                            "stripe_refund_id": "re_Fixture000000",
+                            "amount": 45_000,
+                            "currency": "usd",
+                            "status": "succeeded",
+                            "reason": "requested_by_customer",
+                            "updated_at": at(2, 3, 15),
+                        }),
+                    },
+                ],
+            },
+        ),
+        error(
+            "GET",
+            "/admin/payments/{id}/timeline",
+            StatusCode::NOT_FOUND,
+            "Payment not found",
+        ),
+        ok(
+            "GET",
+            "/admin/registrations/{id}/deliveries",
+            RegistrationDeliveriesResponse {
+                registration_id: id(REGISTRATION),
+                deliveries: vec![delivery()],
+                messages: vec![message_retries()],
+            },
+        ),
+        ok(
+            "POST",
+            "/admin/api_tokens",
+            IssuedTokenResponse {
+                token: "7b1e4c9a0d3f4e2b8c6a5d1f0e9b7c3a7b1e4c9a0d3f4e2b".to_string(),
+                api_token: api_token.clone(),
+            },
+        ),
+        ok(
+            "POST",
+            "/admin/api_tokens/auditor",
+            IssuedTokenResponse {
+                token: "3c9e1a7f5b2d4c8e9a0f6b1d3e5c7a9b3c9e1a7f5b2d4c8e".to_string(),
+                api_token: ApiToken {
+                    id: id(0xB001),
+                    role: "director".to_string(),
+                    subject_id: None,
+                    label: "Annual audit".to_string(),
+                    expires_at: Some(at(2, 9, 9)),
+                    scopes: vec!["read_only".to_string()],
+                    ..api_token
+                },
+            },
+        ),
+        error(
+            "POST",
+            "/admin/api_tokens/auditor",
+            StatusCode::BAD_REQUEST,
+            "expires_in_days must be between 1 and 90",
+        ),
+        no_content("DELETE", "/admin/api_tokens/{id}"),
+        ok(
+            "GET",
+            "/admin/route_policies",
+            json!({ "route_policies": &ROUTE_POLICIES[..2] }),
+        ),
+        ok(
+            "GET",
+            "/admin/sessions/{id}/roster",
+            RosterResponse {
+                session_id: id(SESSION),
+                session_name: session().name,
+                tags: vec!["bus".to_string()],
+                roster: vec![RosterEntry {
+                    registration_id: id(REGISTRATION),
+                    status: "confirmed".to_string(),
+                    camper_id: id(CAMPER),
+                    camper_first_name: camper().first_name,
+                    camper_last_name: camper().last_name,
+                    birthdate: Some(camper().birthdate),
+                    guardian_name: "Morgan Lindqvist".to_string(),
+                    guardian_email: "parent@example.com".to_string(),
+                    tags: vec!["bus".to_string()],
+                }],
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/sessions/{id}/pickups",
+            PickupLogResponse {
+                session_id: id(SESSION),
+                session_name: session().name,
+                date: date(7, 6),
+                pickups: vec![PickupLogEntry {
+                    registration_id: id(REGISTRATION),
+                    camper_id: id(CAMPER),
+                    camper_first_name: camper().first_name,
+                    camper_last_name: camper().last_name,
+                    checked_out_at: at(7, 6, 16),
+                    picked_up_by: Some(authorized_pickup()),
+                    source: "kiosk".to_string(),
+                    recorded_by: Some(id(STAFF_MEMBER)),
+                }],
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/sessions/{id}/cabins",
+            CabinsResponse {
+                suggestion: Some(CabinPlan {
+                    cabins: vec![SuggestedCabin {
+                        registration_ids: vec![id(REGISTRATION), id(REGISTRATION + 1)],
+                    }],
+                    unmatched_requests: Vec::new(),
+                }),
+                ..cabins()
+            },
+        ),
+        ok("PUT", "/admin/sessions/{id}/cabins", cabins()),
+        ok(
+            "GET",
+            "/admin/tags",
+            json!({ "tags": ["bus", "late-pickup"] }),
+        ),
+        ok(
+            "POST",
+            "/admin/registrations/{id}/tags",
+            RegistrationTagsResponse {
+                registration_id: id(REGISTRATION),
+                tags: vec!["bus".to_string()],
+            },
+        ),
+        no_content("DELETE", "/admin/registrations/{id}/tags/{tag}"),
+        ok("GET", "/admin/alerts", json!({ "alerts": [&alert] })),
+        no_content("POST", "/admin/alerts/{id}/acknowledge"),
+        ok(
+            "POST",
+            "/admin/exports",
+            ExportQueuedResponse {
+                export_id: export_job.id,
+                status: "queued".to_string(),
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/exports/{id}",
+            ExportStatusResponse::new(
+                export_job,
+                Some("https://exports.example.com/registrations-2026.csv".to_string()),
+            ),
+        ),
+        ok(
+            "POST",
+            "/admin/jobs/{name}",
+            json!({ "job": "holds", "summary": { "warned": 2, "expired": 1, "sent": 3 } }),
+        ),
+        ok(
+            "GET",
+            "/admin/info",
+            json!({
+                "version": "0.1.0",
+                "git_sha": "5b63d0e3843ab2570cf4d28dc646a546827de54e",
+                "built_at": "2026-06-01T14:00:00Z",
+                "profile": "release",
+                "features": {
+                    "anonymized_exports": true,
+                    "dev_fixtures": false,
+                    "notification_relay": true,
+                    "qr_check_in": true,
+                    "slack_alerts": true,
+                    "warm_start": true,
+                    "webhook_shadow": false,
+                },
+                "database_host": "camp-prod.*.*.*.amazonaws.com",
+                "stripe_mode": "live",
+            }),
+        ),
+        ok(
+            "GET",
+            "/admin/metrics",
+            json!({ "counters": [Counter {
+                name: "webhook_events_filtered_total",
+                labels: BTreeMap::from([
+                    ("event_type", "charge.succeeded".to_string()),
+                    ("reason", "not_allowlisted".to_string()),
+                ]),
+                value: 4,
+            }] }),
+        ),
+        ok(
+            "GET",
+            "/admin/slo_status",
+            json!({
+                "burn_rate_threshold": 14.4,
+                "routes": [{
+                    "method": "POST",
+                    "path": "/payment_sheet",
+                    "requests": 240,
+                    "slow": 3,
+                    "p50_ms": 500,
+                    "p95_ms": 1000,
+                    "p99_ms": 2500,
+                    "target": {
+                        "method": "POST",
+                        "path": "/payment_sheet",
+                        "threshold_ms": 1000,
+                        "objective": 0.99,
+                    },
+                    "windows": [
+                        { "minutes": 5, "requests": 22, "slow": 0, "burn_rate": 0.0 },
+                        { "minutes": 60, "requests": 240, "slow": 3, "burn_rate": 1.25 },
+                    ],
+                    "last_alert_minutes_ago": null,
+                }],
+            }),
+        ),
+        ok(
+            "GET",
+            "/admin/usage",
+            UsageReportResponse {
+                period: "2026-06".to_string(),
+                from: date(6, 1),
+                to: date(7, 1),
+                total_requests: 18_450,
+                clients: vec![ClientUsage {
+                    frontend_id: "parent-portal".to_string(),
+                    api_key: id(GUARDIAN + 1).to_string(),
+                    requests: 18_450,
+                    client_errors: 369,
+                    server_errors: 0,
+                    error_rate: 0.02,
+                    peak_hour_requests: 412,
+                }],
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/webhook_coverage",
+            WebhookCoverageReport {
+                total_received: 1_284,
+                unhandled_received: 96,
+                unhandled: vec![EventTypeCoverage {
+                    event_type: "customer.updated".to_string(),
+                    handled: false,
+                    received: 96,
+                    filtered: 0,
+                    first_received_at: at(1, 20, 14),
+                    last_received_at: at(6, 30, 9),
+                }],
+                handled: vec![EventTypeCoverage {
+                    event_type: "payment_intent.succeeded".to_string(),
+                    handled: true,
+                    received: 1_188,
+                    filtered: 0,
+                    first_received_at: at(1, 15, 10),
+                    last_received_at: at(6, 30, 11),
+                }],
+                never_received: vec!["payout.failed".to_string()],
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/ws_connections",
+            WsConnectionsResponse {
+                connections: vec![ConnectionSummary {
+                    connection_id: id(0xD000).to_string(),
+                    subscriptions: vec![PAYMENT_INTENT.to_string()],
+                    customer_id: Some("cus_Fixture000000".to_string()),
+                    customer_email: Some("morgan@example.com".to_string()),
+                    opened_at: at(6, 1, 9),
+                    age_seconds: 2_700,
+                    live_here: true,
+                }],
+            },
+        ),
+        ok(
+            "DELETE",
+            "/admin/ws_connections/{connection_id}",
+            DisconnectResponse {
+                connection_id: id(0xD000).to_string(),
+                closed: true,
+                reason: "Disconnected by support".to_string(),
+                deactivated: 1,
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/payment_limits",
+            PaymentLimitsResponse {
+                limits: PaymentLimits {
+                    amounts: [
+                        (
+                            "eur".to_string(),
+                            AmountRange {
+                                min: 50,
+                                max: 1_000_000,
+                            },
+                        ),
+                        (
+                            "usd".to_string(),
+                            AmountRange {
+                                min: 50,
+                                max: 1_000_000,
+                            },
+                        ),
+                    ]
+                    .into(),
+                    daily_totals: [
+                        ("eur".to_string(), 2_500_000),
+                        ("usd".to_string(), 2_500_000),
+                    ]
+                    .into(),
+                    max_intents_per_hour: 10,
+                },
+                overrides: vec![limit_override()],
+            },
+        ),
+        ok("POST", "/admin/payment_limits/overrides", limit_override()),
+        ok(
+            "GET",
+            "/admin/reports/payment_methods",
+            PaymentMethodsReport {
+                from: date(6, 1),
+                to: date(6, 30),
+                methods: vec![
+                    MethodTotal {
+                        category: MethodCategory::Card,
+                        currency: "usd".to_string(),
+                        count: 42,
+                        amount: 1_620_000,
+                        share_of_amount: 0.9,
+                        method_types: [("card".to_string(), 1_620_000)].into(),
+                    },
+                    MethodTotal {
+                        category: MethodCategory::Ach,
+                        currency: "usd".to_string(),
+                        count: 4,
+                        amount: 180_000,
+                        share_of_amount: 0.1,
+                        method_types: [("us_bank_account".to_string(), 180_000)].into(),
+                    },
+                ],
+            },
+        ),
+        ok(
+            "GET",
+            "/admin/reports/session_revenue",
+            SessionRevenueReport {
+                month: "2026-07".to_string(),
+                sessions: vec![
+                    SessionRevenueRow {
+                        session_id: id(SESSION),
+                        session_name: session().name,
+                        starts_on: session().starts_on,
+                        currency: "usd".to_string(),
+                        collected_in_month: 90_000,
+                        collected_to_date: 810_000,
+                        recognized_in_month: 810_000,
+                        recognized_to_date: 810_000,
+                        deferred: 0,
+                    },
+                    SessionRevenueRow {
+                        session_id: id(SESSION + 1),
+                        session_name: "Lakeside Week 2".to_string(),
+                        starts_on: date(8, 3),
+                        currency: "usd".to_string(),
+                        collected_in_month: 135_000,
+                        collected_to_date: 630_000,
+                        recognized_in_month: 0,
+                        recognized_to_date: 0,
+                        deferred: 630_000,
+                    },
+                ],
+                totals: vec![RevenueTotals {
+                    currency: "usd".to_string(),
+                    collected_in_month: 245_000,
+                    recognized_in_month: 810_000,
+                    recognized_to_date: 810_000,
+                    deferred: 630_000,
+                    unallocated_in_month: 20_000,
+                }],
+            },
+        ),
+        error(
+            "GET",
+            "/admin/reports/session_revenue",
+            StatusCode::BAD_REQUEST,
+            "Invalid month '2026-13': expected YYYY-MM",
+        ),
+        ok("GET", "/dev/fixtures", json!({ "fixtures": [] })),
+    ]
+}
+
+fn error_fixtures() -> Vec<Fixture> {
+    let webhook_error = |error: WebhookError| Fixture {
+        method: "POST",
+        path: "/webhook",
+        name: error.code().to_string(),
+        status: error.status().as_u16(),
+        content_type: "application/json",
+        body: json!({ "error": error.code(), "message": error.to_string() }),
+    };
+    // WebSocket errors arrive as messages on the open connection
+    let ws_error = |error: ClientMessageError| Fixture {
+        method: "GET",
+        path: "/payment_status",
+        name: error.code().to_string(),
+        status: StatusCode::SWITCHING_PROTOCOLS.as_u16(),
+        content_type: "application/json",
+        body: serde_json::from_str(&error.to_message()).unwrap_or(Value::Null),
+    };
+
+    vec![
+        webhook_error(WebhookError::MissingSignature),
+        webhook_error(WebhookError::InvalidSignature),
+        webhook_error(WebhookError::DatabaseUnavailable),
+        webhook_error(WebhookError::ProcessingFailed),
+        Fixture {
+            name: "degraded".to_string(),
+            status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+            ..ok(
+                "GET",
+                "/ready",
+                ReadinessReport {
+                    ready: false,
+                    database: DatabaseStatus::Degraded,
+                    degraded_since: Some(at(6, 1, 10)),
+                    last_failure: Some("webhook"),
+                    buffered_ws_registrations: 3,
+                    dropped_ws_registrations: 0,
+                },
+            )
+        },
+        ws_error(ClientMessageError::MissingField("payment_intent_id")),
+        ws_error(ClientMessageError::InvalidJson),
+        coded(
+            "POST",
+            "/quote",
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "Either registration_ids or a non-negative amount is required",
+            ),
+        ),
+        coded(
+            "POST",
+            "/quote",
+            ApiError::new(
+                StatusCode::CONFLICT,
+                ErrorCode::RegistrationNotPayable,
+                format!("Registration {} is confirmed", id(REGISTRATION)),
+            ),
+        ),
+        coded(
+            "POST",
+            "/payment_sheet",
+            ApiError::new(
+                StatusCode::PAYMENT_REQUIRED,
+                ErrorCode::PaymentDeclined,
+                "Your card was declined.",
+            ),
+        ),
+        coded(
+            "POST",
+            "/payment_sheet",
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::AmountAboveMaximum,
+                "Amount is above the maximum of 1000000 usd",
+            ),
+        ),
+        coded(
+            "POST",
+            "/payment_sheet",
+            ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::TooManyPaymentAttempts,
+                "At most 10 payment attempts an hour are allowed; try again later",
+            ),
+        ),
+        coded(
+            "POST",
+            "/vouchers/redeem",
+            ApiError::new(
+                StatusCode::GONE,
+                ErrorCode::PromoExpired,
+                "Voucher has expired",
+            ),
+        ),
+        error(
+            "GET",
+            "/vouchers/{code}",
+            StatusCode::NOT_FOUND,
+            "Voucher not found",
+        ),
+        error(
+            "GET",
+            "/sessions/{id}",
+            StatusCode::NOT_FOUND,
+            "Session not found",
+        ),
+        error(
+            "GET",
+            "/campers/{id}",
+            StatusCode::NOT_FOUND,
+            "Camper not found",
+        ),
+        error(
+            "GET",
+            "/receipts/{payment_intent_id}",
+            StatusCode::NOT_FOUND,
+            "Payment not found",
+        ),
+        error(
+            "GET",
+            "/receipts/{payment_intent_id}/pdf",
+            StatusCode::NOT_FOUND,
+            "Payment not found",
+        ),
+        error(
+            "POST",
+            "/admin/sessions/{id}/cancel_under_enrolled",
+            StatusCode::CONFLICT,
+            "Enrollment cutoff 2026-06-08 has not passed",
+        ),
+        error(
+            "DELETE",
+            "/admin/ws_connections/{connection_id}",
+            StatusCode::NOT_FOUND,
+            "No active WebSocket connection 00000000-0000-0000-0000-00000000d000",
+        ),
+        error(
+            "PUT",
+            "/me/billing_address",
+            StatusCode::BAD_REQUEST,
+            "country must be a two-letter ISO code, got 'USA'",
+        ),
+        error(
+            "PUT",
+            "/me/notification_preferences",
+            StatusCode::BAD_REQUEST,
+            "Unknown notification rule: newsletter",
+        ),
+        error(
+            "GET",
+            "/public/sessions/availability",
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many requests",
+        ),
+        error(
+            "POST",
+            "/check_in/scan",
+            StatusCode::BAD_REQUEST,
+            "Invalid check-in code",
+        ),
+        error(
+            "POST",
+            "/check_in/scan",
+            StatusCode::CONFLICT,
+            "Camper is already checked in",
+        ),
+        error(
+            "POST",
+            "/campers/{id}/corrections",
+            StatusCode::CONFLICT,
+            "A correction for this camper is already awaiting review",
+        ),
+        coded(
+            "POST",
+            "/registrations",
+            ApiError::new(
+                StatusCode::CONFLICT,
+                ErrorCode::SessionFull,
+                "Session is full",
+            ),
+        ),
+        coded(
+            "POST",
+            "/registrations",
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::AgeIneligible,
+                format!(
+                    "{} is outside the age range for {}",
+                    camper().first_name,
+                    session().name
+                ),
+            ),
+        ),
+        coded(
+            "GET",
+            "/registrations/{id}",
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Registration not found",
+            ),
+        ),
+        named(
+            error(
+                "POST",
+                "/me/registrations/{id}/cancel",
+                StatusCode::CONFLICT,
+                "The refund is now 0 usd; preview the cancellation again",
+            ),
+            "refund_changed",
+        ),
+        coded(
+            "GET",
+            "/delegated/{token}",
+            ApiError::new(StatusCode::NOT_FOUND, ErrorCode::NotFound, "Link not found"),
+        ),
+        coded(
+            "GET",
+            "/delegated/{token}",
+            ApiError::new(StatusCode::GONE, ErrorCode::LinkExpired, "Link has expired"),
+        ),
+        named(
+            error(
+                "POST",
+                "/admin/sessions/{id}/staff",
+                StatusCode::CONFLICT,
+                &json!({
+                    "error": "Staff member is double-booked",
+                    "conflicts": [{
+                        "assignment_id": id(STAFF_MEMBER + 2),
+                        "session_id": id(SESSION),
+                        "session_name": session().name,
+                        "starts_on": session().starts_on,
+                        "ends_on": session().ends_on,
+                    }],
+                })
+                .to_string(),
+            ),
+            "double_booked",
+        ),
+        named(
+            error(
+                "POST",
+                "/admin/sessions/{id}/staff",
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &json!({ "error": "Missing required certifications", "missing": ["lifeguard"] })
+                    .to_string(),
+            ),
+            "missing_certifications",
+        ),
+    ]
+}
+
+/// The 401 and 403 responses every route behind a token can return.
+fn auth_fixtures() -> Vec<Fixture> {
+    ROUTE_POLICIES
+        .iter()
+        .flat_map(|p| {
+            let mut fixtures = Vec::new();
+            if !matches!(p.access, Access::Public) {
+                fixtures.push(error(
+                    p.method,
+                    p.path,
+                    StatusCode::UNAUTHORIZED,
+                    "Missing bearer token",
+                ));
+            }
+            if let Access::Roles(roles) = p.access {
+                if !roles.contains(&crate::auth::Role::Guardian) {
+                    fixtures.push(error(
+                        p.method,
+                        p.path,
+                        StatusCode::FORBIDDEN,
+                        "Role guardian is not permitted",
+                    ));
+                }
+            }
+            fixtures
+        })
+        .collect()
+}
+
+/// Every fixture, grouped by route in policy-table order.
+pub fn all() -> Vec<Fixture> {
+    let mut fixtures: Vec<Fixture> = success_fixtures()
+        .into_iter()
+        .chain(error_fixtures())
+        .chain(auth_fixtures())
+        .collect();
+    let position = |f: &Fixture| {
+        ROUTE_POLICIES
+            .iter()
+            .position(|p| p.method == f.method && p.path == f.path)
+            .unwrap_or(usize::MAX)
+    };
+    fixtures.sort_by_key(|f| (position(f), f.status));
+    fixtures
+}
+
+/// Routes in the policy table without a success fixture.
+pub fn routes_missing_fixtures() -> Vec<String> {
+    let fixtures = success_fixtures();
+    ROUTE_POLICIES
+        .iter()
+        .filter(|p| {
+            !fixtures
+                .iter()
+                .any(|f| f.method == p.method && f.path == p.path)
+        })
+        .map(|p| format!("{} {}", p.method, p.path))
+        .collect()
+}
+
+/// Whether `APP_ENV` selects the development profile.
+pub(crate) fn dev_mode() -> bool {
+    Profile::from_env() == Ok(Profile::Development)
+}
+
+/// GET /dev/fixtures returns the contract fixtures. Only served in dev mode.
+#[tracing::instrument]
+pub async fn fixtures_handler() -> Result<axum::Json<Value>, (StatusCode, String)> {
+    if !dev_mode() {
+        return Err((StatusCode::NOT_FOUND, "Not found".to_string()));
+    }
+    Ok(axum::Json(json!({ "fixtures": all() })))
+}
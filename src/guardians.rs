@@ -0,0 +1,119 @@
+use crate::auth::{Actor, Role};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampCredit, Guardian},
+};
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+/// Looks up a guardian by email, creating the record if it does not exist yet.
+pub fn find_or_create_guardian(
+    conn: &mut PgConnection,
+    guardian_email: &str,
+    guardian_name: &str,
+) -> Result<Guardian, diesel::result::Error> {
+    use crate::database::schema::guardians::dsl::*;
+
+    let existing = guardians
+        .filter(email.eq(guardian_email))
+        .first::<Guardian>(conn)
+        .optional()?;
+    if let Some(guardian) = existing {
+        return Ok(guardian);
+    }
+
+    diesel::insert_into(guardians)
+        .values(&Guardian::new(
+            guardian_email.to_string(),
+            guardian_name.to_string(),
+            None,
+        ))
+        .get_result::<Guardian>(conn)
+}
+
+/// Returns the guardian's camp credit balance per currency.
+pub fn credit_balances(
+    conn: &mut PgConnection,
+    guardian: Uuid,
+) -> Result<BTreeMap<String, i64>, diesel::result::Error> {
+    use crate::database::schema::camp_credits::dsl::*;
+
+    let entries = camp_credits
+        .filter(guardian_id.eq(guardian))
+        .load::<CampCredit>(conn)?;
+
+    let mut balances = BTreeMap::new();
+    for entry in entries {
+        *balances.entry(entry.currency).or_insert(0) += entry.amount;
+    }
+    Ok(balances)
+}
+
+/// Credit in `credit_currency` held by the guardian's unpaid quotes at `now`.
+pub fn reserved_credit(
+    conn: &mut PgConnection,
+    guardian: Uuid,
+    credit_currency: &str,
+    now: NaiveDateTime,
+) -> Result<i64, diesel::result::Error> {
+    use crate::database::schema::quotes;
+
+    let reserved = quotes::table
+        .filter(quotes::guardian_id.eq(guardian))
+        .filter(quotes::currency.eq(credit_currency))
+        .filter(quotes::credit_status.eq("reserved"))
+        .filter(quotes::credit_expires_at.gt(now))
+        .select(quotes::credit_applied)
+        .load::<i64>(conn)?;
+    Ok(reserved.iter().sum())
+}
+
+#[derive(Debug, Serialize)]
+pub struct GuardianCreditsResponse {
+    pub guardian_id: Uuid,
+    /// Net balance per currency, in minor units.
+    pub balances: BTreeMap<String, i64>,
+    pub entries: Vec<CampCredit>,
+}
+
+/// GET /guardians/{id}/credits returns the guardian's credit balances and ledger entries.
+#[tracing::instrument(skip(state))]
+pub async fn guardian_credits_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(guardian): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::camp_credits::dsl::*;
+
+    info!("Handling credit balance request for guardian {guardian}");
+    if actor.role == Role::Guardian && actor.guardian_id() != Some(guardian) {
+        return Err((StatusCode::NOT_FOUND, "Guardian not found".to_string()));
+    }
+    let mut conn = conn_from_state(&state).await?;
+
+    let balances =
+        credit_balances(&mut conn, guardian).map_err(db_error("Failed to load credit balance"))?;
+    let entries = camp_credits
+        .filter(guardian_id.eq(guardian))
+        .order(created_at.desc())
+        .load::<CampCredit>(&mut conn)
+        .map_err(db_error("Failed to load credit ledger"))?;
+
+    Ok(axum::Json(json!(GuardianCreditsResponse {
+        guardian_id: guardian,
+        balances,
+        entries,
+    })))
+}
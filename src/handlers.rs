@@ -1,7 +1,18 @@
+use crate::api_error::{ApiError, ErrorCode};
+use crate::database::{conn_from_state, models::PaymentEvent};
+use crate::holds::link_holds_to_intent;
+use crate::payment_limits::{record_attempt, PaymentLimits};
+use crate::payment_metadata::PaymentMetadata;
+use crate::redact::scrub_metadata;
+use crate::stripe_keys::{StripeCapability, StripeKeyring};
+use crate::waitlist_offers::{link_offer_to_intent, load_open_offer, OfferQuery};
 use axum::response::IntoResponse;
 use axum::{http::StatusCode, Extension};
+use diesel::prelude::*;
 use lambda_lib::{AppState, PaymentSheetRequest};
+use serde::Serialize;
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
 use stripe::{
     Client, CreateCustomer, CreateEphemeralKey, CreatePaymentIntent,
@@ -10,28 +21,143 @@ use stripe::{
 use tokio::sync::Mutex;
 use tracing::{error, info};
 
+/// Response of the PaymentSheet endpoints, in the shape the Stripe mobile SDKs expect.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentSheetResponse {
+    pub customer: String,
+    pub ephemeral_key: Option<String>,
+    pub payment_intent: Option<String>,
+    pub publishable_key: String,
+}
+
 /// POST /payment_sheet endpoint creates a Customer, an Ephemeral Key, and a PaymentIntent with automatic payment methods enabled.
-#[tracing::instrument(skip(state))]
+/// The amount and the customer's recent payment attempts are checked against the payment limits first.
+/// With `?offer_token=`, the checkout must pay exactly the offered waitlist registration while the offer lasts.
+#[tracing::instrument(skip(state, limits, keyring, offer))]
 pub async fn create_payment_sheet_handler(
     axum::extract::Extension(state): axum::extract::Extension<Arc<Mutex<AppState>>>,
+    axum::extract::Extension(limits): axum::extract::Extension<Arc<PaymentLimits>>,
+    axum::extract::Extension(keyring): axum::extract::Extension<Arc<StripeKeyring>>,
+    axum::extract::Query(offer): axum::extract::Query<OfferQuery>,
     axum::extract::Json(payload): axum::extract::Json<PaymentSheetRequest>,
-) -> Result<axum::Json<Value>, (StatusCode, String)> {
+) -> Result<axum::Json<Value>, ApiError> {
     info!("Received payment sheet request: {:?}", payload);
 
-    let state = state.lock().await;
-    let secret_key = state.stripe_keys.secret_key.clone();
-    let publishable_key = state.stripe_keys.publishable_key.clone();
-    let client = Client::new(secret_key);
-    drop(state);
+    let state_guard = state.lock().await;
+    let client = keyring.client(StripeCapability::Payments, &state_guard.stripe_keys);
+    let publishable_key = state_guard.stripe_keys.publishable_key.clone();
+    drop(state_guard);
+
+    let currency = parse_currency(&payload.currency)?;
+    let meta_map = PaymentMetadata::metadata_from_json(&payload.metadata).map_err(|e| {
+        error!("Invalid payment metadata: {e}");
+        (StatusCode::BAD_REQUEST, e)
+    })?;
+    let registration_ids = PaymentMetadata::parse(&meta_map)
+        .map(|m| m.registration_ids)
+        .unwrap_or_default();
+
+    let mut conn = match conn_from_state(&state).await {
+        Ok(conn) => Some(conn),
+        Err((_, e)) => {
+            error!("Payment sheet will not be recorded or velocity checked: {e}");
+            None
+        }
+    };
+    let offer = match offer.offer_token.as_deref() {
+        Some(token) => {
+            let conn = conn.as_deref_mut().ok_or((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Database unavailable".to_string(),
+            ))?;
+            let (offer, _) = load_open_offer(conn, token)?;
+            if registration_ids != [offer.registration_id] {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::InvalidRequest,
+                    "registration_ids metadata must only contain the offered registration",
+                ));
+            }
+            Some(offer)
+        }
+        None => None,
+    };
+    limits.enforce(
+        conn.as_deref_mut(),
+        &payload.customer_email,
+        &payload.currency,
+        payload.amount,
+    )?;
+
+    let (customer, ephemeral_key, payment_intent) = create_checkout(
+        &client,
+        &payload.customer_name,
+        &payload.customer_email,
+        payload.customer_description.as_deref(),
+        payload.amount,
+        currency,
+        meta_map,
+    )
+    .await?;
+
+    if let Some(mut conn) = conn {
+        record_sheet_created(&mut conn, &payment_intent);
+        record_attempt(
+            &mut conn,
+            &payload.customer_email,
+            payment_intent.id.as_str(),
+            payment_intent.amount,
+            &payment_intent.currency.to_string(),
+        );
+        // Link the registrations' holds to the intent so expiry warnings reach its WebSocket subscribers
+        if !registration_ids.is_empty() {
+            if let Err(e) =
+                link_holds_to_intent(&mut conn, &registration_ids, payment_intent.id.as_str())
+            {
+                error!("Failed to link holds to payment intent: {e}");
+            }
+        }
+        if let Some(offer) = &offer {
+            if let Err(e) = link_offer_to_intent(&mut conn, offer.id, payment_intent.id.as_str()) {
+                error!(
+                    "Failed to link waitlist offer {} to payment intent: {e}",
+                    offer.id
+                );
+            }
+        }
+    }
 
+    let body = PaymentSheetResponse {
+        customer: customer.id.to_string(),
+        ephemeral_key: ephemeral_key.secret,
+        payment_intent: payment_intent.client_secret,
+        publishable_key,
+    };
+
+    Ok(axum::Json(json!(body)))
+}
+
+/// Creates a Customer, an Ephemeral Key, and a PaymentIntent with automatic payment
+/// methods enabled, which together back a mobile PaymentSheet. Card errors from
+/// Stripe come back as PAYMENT_DECLINED.
+pub(crate) async fn create_checkout(
+    client: &Client,
+    customer_name: &str,
+    customer_email: &str,
+    customer_description: Option<&str>,
+    amount: i64,
+    currency: Currency,
+    metadata: HashMap<String, String>,
+) -> Result<(Customer, EphemeralKey, PaymentIntent), ApiError> {
     // 1. Create a Customer.
     let customer = Customer::create(
-        &client,
+        client,
         CreateCustomer {
-            name: Some(&payload.customer_name),
-            email: Some(&payload.customer_email),
-            description: payload.customer_description.as_deref(),
-            metadata: Some(std::collections::HashMap::from([(
+            name: Some(customer_name),
+            email: Some(customer_email),
+            description: customer_description,
+            metadata: Some(HashMap::from([(
                 "async-stripe".to_string(),
                 "true".to_string(),
             )])),
@@ -41,16 +167,13 @@ pub async fn create_payment_sheet_handler(
     .await
     .map_err(|e| {
         error!("Error creating customer: {e:?}");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Error creating customer: {e:?}"),
-        )
+        ApiError::from_stripe("Error creating customer", &e)
     })?;
     info!("Created customer with id: {}", customer.id);
 
     // 2. Create an Ephemeral Key.
     let ephemeral_key = EphemeralKey::create(
-        &client,
+        client,
         CreateEphemeralKey {
             customer: Some(customer.id.clone()),
             ..Default::default()
@@ -59,59 +182,80 @@ pub async fn create_payment_sheet_handler(
     .await
     .map_err(|e| {
         error!("Error creating ephemeral key: {e:?}");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Error creating ephemeral key: {e:?}"),
-        )
+        ApiError::from_stripe("Error creating ephemeral key", &e)
     })?;
     info!("Created ephemeral key");
 
     // 3. Create a PaymentIntent with automatic payment methods enabled.
-    let currency = match payload.currency.to_lowercase().as_str() {
-        "usd" => Currency::USD,
-        "eur" => Currency::EUR,
-        other => {
-            error!("Unsupported currency: {other}");
-            return Err((
-                StatusCode::BAD_REQUEST,
-                format!("Unsupported currency: {other}"),
-            ));
-        }
-    };
-
-    let mut create_intent = CreatePaymentIntent::new(payload.amount, currency);
+    let mut create_intent = CreatePaymentIntent::new(amount, currency);
     create_intent.customer = Some(customer.id.clone());
     create_intent.automatic_payment_methods = Some(CreatePaymentIntentAutomaticPaymentMethods {
         allow_redirects: None,
         enabled: true,
     });
-    if let Some(meta_obj) = payload.metadata.as_object() {
-        let meta_map = meta_obj
-            .iter()
-            .map(|(k, v)| (k.clone(), v.to_string()))
-            .collect();
-        create_intent.metadata = Some(meta_map);
+    if !metadata.is_empty() {
+        create_intent.metadata = Some(metadata);
     }
 
-    let payment_intent = PaymentIntent::create(&client, create_intent)
+    let payment_intent = PaymentIntent::create(client, create_intent)
         .await
         .map_err(|e| {
             error!("Error creating payment intent: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Error creating payment intent: {e:?}"),
-            )
+            ApiError::from_stripe("Error creating payment intent", &e)
         })?;
     info!("Created PaymentIntent with id: {}", payment_intent.id);
 
-    let body = json!({
-        "customer": customer.id,
-        "ephemeralKey": ephemeral_key.secret,
-        "paymentIntent": payment_intent.client_secret,
-        "publishableKey": publishable_key
-    });
+    Ok((customer, ephemeral_key, payment_intent))
+}
 
-    Ok(axum::Json(body))
+/// Records that a PaymentSheet was created for `payment_intent`, for the payment
+/// timeline. Failures are logged rather than propagated.
+pub(crate) fn record_sheet_created(conn: &mut PgConnection, payment_intent: &PaymentIntent) {
+    let event = PaymentEvent::new(
+        payment_intent.id.to_string(),
+        PaymentEvent::SHEET_CREATED.to_string(),
+        Some(payment_intent.amount),
+        Some(payment_intent.currency.to_string()),
+        payment_intent.customer.as_ref().map(|c| c.id().to_string()),
+        Some(scrub_metadata(&payment_intent.metadata)),
+    );
+    if let Err(e) = diesel::insert_into(crate::database::schema::payment_events::table)
+        .values(&event)
+        .execute(conn)
+    {
+        error!(
+            "Failed to record payment sheet for {}: {e}",
+            payment_intent.id
+        );
+    }
+}
+
+/// Stripe API client. `STRIPE_API_BASE` points it at another host, such as a
+/// stripe-mock server in the integration tests.
+pub(crate) fn stripe_client(secret_key: String) -> Client {
+    match std::env::var("STRIPE_API_BASE") {
+        Ok(base) if !base.is_empty() => Client::from_url(base.as_str(), secret_key),
+        _ => Client::new(secret_key),
+    }
+}
+
+/// Lowercase codes of the currencies accepted by the payment endpoints.
+pub(crate) const SUPPORTED_CURRENCIES: &[&str] = &["usd", "eur"];
+
+/// Parses a currency code accepted by the payment endpoints.
+pub(crate) fn parse_currency(code: &str) -> Result<Currency, ApiError> {
+    match code.to_lowercase().as_str() {
+        "usd" => Ok(Currency::USD),
+        "eur" => Ok(Currency::EUR),
+        other => {
+            error!("Unsupported currency: {other}");
+            Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::UnsupportedCurrency,
+                format!("Unsupported currency: {other}"),
+            ))
+        }
+    }
 }
 
 /// GET /hello endpoint returns a simple text message.
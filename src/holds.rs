@@ -0,0 +1,194 @@
+use crate::database::models::{NewRegistrationHold, RegistrationHold};
+use crate::notifications::{enqueue, Channel, Notification};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde_json::json;
+use std::env;
+use uuid::Uuid;
+
+/// How long a checkout hold reserves a seat, from `HOLD_TTL_MINUTES` (default 15).
+pub fn hold_ttl() -> chrono::Duration {
+    let minutes = env::var("HOLD_TTL_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|m| *m > 0)
+        .unwrap_or(15);
+    chrono::Duration::minutes(minutes)
+}
+
+/// Seats taken in a session: confirmed registrations plus unexpired, unreleased holds.
+pub fn seats_taken(
+    conn: &mut PgConnection,
+    session: Uuid,
+    now: NaiveDateTime,
+) -> Result<i64, diesel::result::Error> {
+    use crate::database::schema::{registration_holds, registrations};
+
+    let confirmed = registrations::table
+        .filter(registrations::session_id.eq(session))
+        .filter(registrations::status.eq("confirmed"))
+        .count()
+        .get_result::<i64>(conn)?;
+    let held = registration_holds::table
+        .filter(registration_holds::session_id.eq(session))
+        .filter(registration_holds::released_at.is_null())
+        .filter(registration_holds::expires_at.gt(now))
+        .count()
+        .get_result::<i64>(conn)?;
+    Ok(confirmed + held)
+}
+
+/// Places a hold for a registration expiring after [`hold_ttl`].
+pub fn place_hold(
+    conn: &mut PgConnection,
+    registration: Uuid,
+    session: Uuid,
+    now: NaiveDateTime,
+) -> Result<RegistrationHold, diesel::result::Error> {
+    place_hold_until(conn, registration, session, now + hold_ttl())
+}
+
+/// Places a hold for a registration expiring at `expires_at`.
+pub fn place_hold_until(
+    conn: &mut PgConnection,
+    registration: Uuid,
+    session: Uuid,
+    expires_at: NaiveDateTime,
+) -> Result<RegistrationHold, diesel::result::Error> {
+    diesel::insert_into(crate::database::schema::registration_holds::table)
+        .values(&NewRegistrationHold {
+            registration_id: registration,
+            session_id: session,
+            expires_at,
+        })
+        .get_result::<RegistrationHold>(conn)
+}
+
+/// The unexpired, unreleased hold for a registration, if any.
+pub fn open_hold(
+    conn: &mut PgConnection,
+    registration: Uuid,
+    now: NaiveDateTime,
+) -> Result<Option<RegistrationHold>, diesel::result::Error> {
+    use crate::database::schema::registration_holds::dsl::*;
+
+    registration_holds
+        .filter(registration_id.eq(registration))
+        .filter(released_at.is_null())
+        .filter(expires_at.gt(now))
+        .first::<RegistrationHold>(conn)
+        .optional()
+}
+
+/// Releases every open hold for the given registrations.
+pub fn release_holds(
+    conn: &mut PgConnection,
+    registrations: &[Uuid],
+    now: NaiveDateTime,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::registration_holds::dsl::*;
+
+    diesel::update(
+        registration_holds
+            .filter(registration_id.eq_any(registrations))
+            .filter(released_at.is_null()),
+    )
+    .set(released_at.eq(Some(now)))
+    .execute(conn)
+}
+
+/// Records the PaymentIntent paying for the registrations on their open holds.
+pub fn link_holds_to_intent(
+    conn: &mut PgConnection,
+    registrations: &[Uuid],
+    intent_id: &str,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::registration_holds::dsl::*;
+
+    diesel::update(
+        registration_holds
+            .filter(registration_id.eq_any(registrations))
+            .filter(released_at.is_null()),
+    )
+    .set(payment_intent_id.eq(Some(intent_id)))
+    .execute(conn)
+}
+
+/// How long before expiry `hold_expiring` is sent, from
+/// `HOLD_EXPIRY_WARNING_MINUTES` (default 5).
+pub fn expiry_warning_lead() -> chrono::Duration {
+    let minutes = env::var("HOLD_EXPIRY_WARNING_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|m| *m > 0)
+        .unwrap_or(5);
+    chrono::Duration::minutes(minutes)
+}
+
+fn hold_message(kind: &str, hold: &RegistrationHold) -> Notification {
+    let intent = hold.payment_intent_id.clone().unwrap_or_default();
+    Notification {
+        channel: Channel::WebSocket,
+        target: intent.clone(),
+        template: kind.to_string(),
+        payload: json!({
+            "type": kind,
+            "registration_id": hold.registration_id,
+            "session_id": hold.session_id,
+            "payment_intent_id": intent,
+            "expires_at": hold.expires_at,
+        }),
+        registration_id: Some(hold.registration_id),
+        payment_intent_id: hold.payment_intent_id.clone(),
+    }
+}
+
+/// Hold sweep: enqueues `hold_expiring` for open holds entering the warning window
+/// and `hold_expired` for holds that lapsed, each at most once per hold. Only holds
+/// linked to a PaymentIntent have WebSocket subscribers to notify.
+/// Returns `(warned, expired, notification_ids)`.
+pub fn sweep_holds(
+    conn: &mut PgConnection,
+    now: NaiveDateTime,
+) -> Result<(usize, usize, Vec<Uuid>), diesel::result::Error> {
+    use crate::database::schema::registration_holds::dsl::*;
+
+    conn.transaction(|conn| {
+        let expiring = registration_holds
+            .filter(released_at.is_null())
+            .filter(payment_intent_id.is_not_null())
+            .filter(warned_at.is_null())
+            .filter(expires_at.gt(now))
+            .filter(expires_at.le(now + expiry_warning_lead()))
+            .for_update()
+            .skip_locked()
+            .load::<RegistrationHold>(conn)?;
+        let expired = registration_holds
+            .filter(released_at.is_null())
+            .filter(payment_intent_id.is_not_null())
+            .filter(expiry_notified_at.is_null())
+            .filter(expires_at.le(now))
+            .for_update()
+            .skip_locked()
+            .load::<RegistrationHold>(conn)?;
+
+        let mut notification_ids = Vec::new();
+        for hold in &expiring {
+            notification_ids.push(enqueue(conn, hold_message("hold_expiring", hold))?);
+        }
+        for hold in &expired {
+            notification_ids.push(enqueue(conn, hold_message("hold_expired", hold))?);
+        }
+
+        let expiring_ids: Vec<Uuid> = expiring.iter().map(|h| h.id).collect();
+        diesel::update(registration_holds.filter(id.eq_any(&expiring_ids)))
+            .set(warned_at.eq(Some(now)))
+            .execute(conn)?;
+        let expired_ids: Vec<Uuid> = expired.iter().map(|h| h.id).collect();
+        diesel::update(registration_holds.filter(id.eq_any(&expired_ids)))
+            .set(expiry_notified_at.eq(Some(now)))
+            .execute(conn)?;
+
+        Ok((expiring.len(), expired.len(), notification_ids))
+    })
+}
@@ -0,0 +1,117 @@
+//! Scheduled jobs.
+//!
+//! Lambda cannot run background loops, so periodic work is triggered by an
+//! EventBridge schedule calling `POST /admin/jobs/{name}` with an admin token.
+//! Each job processes a bounded batch and reports what it did.
+use crate::database::{conn_from_state, db_error};
+use crate::enrollment::check_enrollment;
+use crate::exports::{process_queued_exports, queue_analytics_exports};
+use crate::holds::sweep_holds;
+use crate::notification_rules::evaluate_rules;
+use crate::notifications::dispatch_pending;
+use crate::quotes::release_expired_credit;
+use crate::registration_drafts::expire_drafts;
+use crate::session_cancellations::process_refund_batch;
+use crate::stripe_keys::{StripeCapability, StripeKeyring};
+use crate::waitlist::refresh_waitlists;
+use crate::waitlist_offers::{cancel_expired_intents, sweep_offers};
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+};
+use lambda_lib::AppState;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/// POST /admin/jobs/{name} runs a scheduled job.
+#[tracing::instrument(skip(state, keyring))]
+pub async fn run_job_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(keyring): Extension<Arc<StripeKeyring>>,
+    Path(name): Path<String>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    info!("Running scheduled job {name}");
+
+    let summary = match name.as_str() {
+        // Yesterday's anonymized datasets, built by the next `exports` run
+        "analytics" => {
+            let mut conn = conn_from_state(&state).await?;
+            let yesterday = chrono::Utc::now().date_naive() - chrono::Duration::days(1);
+            let queued = queue_analytics_exports(&mut conn, yesterday)
+                .map_err(db_error("Failed to queue analytics exports"))?;
+            json!({ "day": yesterday, "queued": queued })
+        }
+        "cancellations" => json!(process_refund_batch(&state, &keyring).await?),
+        "enrollment" => check_enrollment(&state, chrono::Utc::now().date_naive()).await?,
+        "exports" => {
+            let (completed, failed) = process_queued_exports(&state).await.map_err(|e| {
+                error!("Export job failed: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e)
+            })?;
+            json!({ "completed": completed, "failed": failed })
+        }
+        "holds" => {
+            let mut conn = conn_from_state(&state).await?;
+            let now = chrono::Utc::now().naive_utc();
+            let (warned, expired, notification_ids) =
+                sweep_holds(&mut conn, now).map_err(db_error("Hold sweep failed"))?;
+            // Credit reserved for checkouts that were never paid goes back to the family
+            let credit_released = release_expired_credit(&mut conn, now)
+                .map_err(db_error("Failed to release reserved credit"))?;
+            drop(conn);
+            let sent = dispatch_pending(&state, Some(&notification_ids)).await;
+            json!({
+                "warned": warned,
+                "expired": expired,
+                "sent": sent,
+                "credit_released": credit_released,
+            })
+        }
+        "notification_rules" => {
+            let mut conn = conn_from_state(&state).await?;
+            let summary = evaluate_rules(&mut conn, chrono::Utc::now().date_naive())
+                .map_err(db_error("Notification rules failed"))?;
+            drop(conn);
+            let sent = dispatch_pending(&state, Some(&summary.notification_ids)).await;
+            json!({ "rules": summary, "dispatched": sent })
+        }
+        "notifications" => {
+            let sent = dispatch_pending(&state, None).await;
+            json!({ "sent": sent })
+        }
+        "registration_drafts" => {
+            let mut conn = conn_from_state(&state).await?;
+            let expired = expire_drafts(&mut conn, chrono::Utc::now().naive_utc())
+                .map_err(db_error("Draft expiry failed"))?;
+            json!({ "expired": expired })
+        }
+        "waitlist" => {
+            let mut conn = conn_from_state(&state).await?;
+            let refreshed = refresh_waitlists(&mut conn, chrono::Utc::now().date_naive())
+                .map_err(db_error("Waitlist refresh failed"))?;
+            json!(refreshed)
+        }
+        "waitlist_offers" => {
+            let mut conn = conn_from_state(&state).await?;
+            let mut sweep = sweep_offers(&mut conn, chrono::Utc::now().naive_utc())
+                .map_err(db_error("Waitlist offer sweep failed"))?;
+            drop(conn);
+            if !sweep.expired_intents.is_empty() {
+                let client =
+                    keyring.client(StripeCapability::Payments, &state.lock().await.stripe_keys);
+                sweep.intents_cancelled =
+                    cancel_expired_intents(&client, &sweep.expired_intents).await;
+            }
+            let sent = dispatch_pending(&state, Some(&sweep.notification_ids)).await;
+            json!({ "offers": sweep, "sent": sent })
+        }
+        other => {
+            return Err((StatusCode::NOT_FOUND, format!("Unknown job: {other}")));
+        }
+    };
+
+    info!("Scheduled job {name} finished: {summary}");
+    Ok(axum::Json(json!({ "job": name, "summary": summary })))
+}
@@ -0,0 +1,192 @@
+//! Offline kiosk sync.
+//!
+//! Kiosks queue operations while offline and replay them through `POST /sync`.
+//! Operations are applied with these rules:
+//!
+//! 1. Each operation carries a client-generated UUID. An id that was already synced
+//!    is not re-applied; its stored outcome is returned with status `duplicate`.
+//! 2. Operations in a batch are applied in client timestamp order, each in its own
+//!    transaction, so one failure never blocks the rest of the batch.
+//! 3. Timestamps more than five minutes in the future are rejected.
+//! 4. A check-in or check-out is validated against the camper's state at the
+//!    operation's timestamp and against the next event that day (see
+//!    [`crate::attendance::record_attendance`]). An operation that contradicts
+//!    either (e.g. two kiosks checking in the same camper, or an earlier check-in
+//!    syncing after a later one) is reported as `conflict` and not applied; the
+//!    event already recorded wins.
+//! 5. A check-out names the adult picking the camper up in `pickup_id`; one who is
+//!    missing or not on the registration's authorized pickup list is `rejected`.
+use crate::attendance::{record_attendance, AttendanceInput, AttendanceKind, AttendanceRejection};
+use crate::auth::Actor;
+use crate::database::{conn_from_state, db_error, models::KioskOperation};
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    pub kiosk_id: String,
+    pub operations: Vec<SyncOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncOperation {
+    pub operation_id: Uuid,
+    #[serde(rename = "type")]
+    pub kind: AttendanceKind,
+    pub registration_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    /// The authorized pickup a camper is checked out to.
+    #[serde(default)]
+    pub pickup_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResult {
+    pub operation_id: Uuid,
+    /// `applied`, `duplicate`, `conflict`, `rejected`, or `error` when the
+    /// operation was not recorded and should be retried.
+    pub status: String,
+    /// Outcome of the original application; differs from `status` for duplicates.
+    pub outcome: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub kiosk_id: String,
+    /// One result per submitted operation, in submission order.
+    pub results: Vec<SyncResult>,
+}
+
+/// Applies a single operation, recording its outcome in `kiosk_operations`.
+fn apply_operation(
+    conn: &mut PgConnection,
+    kiosk: &str,
+    op: &SyncOperation,
+    recorder: Option<Uuid>,
+) -> Result<SyncResult, diesel::result::Error> {
+    use crate::database::schema::kiosk_operations::dsl::*;
+
+    conn.transaction(|conn| {
+        let existing = kiosk_operations
+            .find(op.operation_id)
+            .first::<KioskOperation>(conn)
+            .optional()?;
+        if let Some(existing) = existing {
+            return Ok(SyncResult {
+                operation_id: op.operation_id,
+                status: "duplicate".to_string(),
+                outcome: existing.outcome,
+                detail: existing.detail,
+            });
+        }
+
+        let (result_outcome, result_detail) =
+            if op.timestamp > Utc::now() + chrono::Duration::minutes(5) {
+                ("rejected", Some("Timestamp is in the future".to_string()))
+            } else {
+                match record_attendance(
+                    conn,
+                    op.registration_id,
+                    op.kind,
+                    op.timestamp.naive_utc(),
+                    AttendanceInput {
+                        source: "kiosk",
+                        operation_id: Some(op.operation_id),
+                        recorded_by: recorder,
+                        picked_up_by: op.pickup_id,
+                    },
+                )? {
+                    Ok(_) => ("applied", None),
+                    Err(
+                        rejection @ (AttendanceRejection::AlreadyCheckedIn
+                        | AttendanceRejection::NotCheckedIn
+                        | AttendanceRejection::OutOfSequence(_)),
+                    ) => ("conflict", Some(rejection.to_string())),
+                    Err(rejection) => ("rejected", Some(rejection.to_string())),
+                }
+            };
+
+        diesel::insert_into(kiosk_operations)
+            .values(&KioskOperation {
+                operation_id: op.operation_id,
+                kiosk_id: kiosk.to_string(),
+                operation_type: op.kind.as_str().to_string(),
+                registration_id: Some(op.registration_id),
+                client_timestamp: op.timestamp.naive_utc(),
+                outcome: result_outcome.to_string(),
+                detail: result_detail.clone(),
+                applied_at: Utc::now().naive_utc(),
+            })
+            .execute(conn)?;
+
+        Ok(SyncResult {
+            operation_id: op.operation_id,
+            status: result_outcome.to_string(),
+            outcome: result_outcome.to_string(),
+            detail: result_detail,
+        })
+    })
+}
+
+/// POST /sync applies a batch of offline kiosk operations and returns a result per
+/// operation, in the order they were submitted.
+#[tracing::instrument(skip(state, payload), fields(kiosk_id = %payload.kiosk_id))]
+pub async fn kiosk_sync_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<SyncRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    if payload.operations.len() > MAX_BATCH_SIZE {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {MAX_BATCH_SIZE} operations per sync"),
+        ));
+    }
+    info!(
+        "Syncing {} operation(s) from kiosk {}",
+        payload.operations.len(),
+        payload.kiosk_id
+    );
+
+    let mut conn = conn_from_state(&state).await?;
+
+    let mut order: Vec<usize> = (0..payload.operations.len()).collect();
+    order.sort_by_key(|&i| payload.operations[i].timestamp);
+
+    let mut results: Vec<Option<SyncResult>> =
+        (0..payload.operations.len()).map(|_| None).collect();
+    for i in order {
+        let op = &payload.operations[i];
+        let result = apply_operation(&mut conn, &payload.kiosk_id, op, actor.subject_id)
+            .map_err(db_error("Failed to apply kiosk operation"))
+            .unwrap_or_else(|(_, message)| {
+                error!("Kiosk operation {} failed: {message}", op.operation_id);
+                SyncResult {
+                    operation_id: op.operation_id,
+                    status: "error".to_string(),
+                    outcome: "error".to_string(),
+                    detail: Some("Temporary failure; retry this operation".to_string()),
+                }
+            });
+        results[i] = Some(result);
+    }
+
+    Ok(axum::Json(json!(SyncResponse {
+        kiosk_id: payload.kiosk_id,
+        results: results.into_iter().flatten().collect(),
+    })))
+}
@@ -0,0 +1,563 @@
+#![feature(trivial_bounds)]
+//! Camp registration HTTP backend. [`build_router`] assembles the full router so the
+//! Lambda entry point and the integration tests serve the same application.
+use axum::{
+    middleware,
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
+use lambda_lib::{AppState, PgPool};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::error;
+
+mod handlers;
+use handlers::{create_payment_sheet_handler, hello_handler, stripe_handler};
+pub mod stripe_keys;
+use stripe_keys::StripeKeyring;
+pub mod stripe_webhook;
+use stripe_webhook::webhook_handler;
+pub mod database;
+pub mod db_health;
+use db_health::{readiness_handler, DatabaseHealth};
+pub mod webhook_coverage;
+use webhook_coverage::webhook_coverage_handler;
+pub mod webhook_failures;
+use webhook_failures::WebhookFailureResponses;
+mod webhook_filter;
+use webhook_filter::WebhookEventFilter;
+mod webhook_ordering;
+use webhook_ordering::WebhookOrdering;
+mod webhook_shadow;
+use webhook_shadow::{shadow_webhook, WebhookShadow};
+pub mod websocket_handler;
+use websocket_handler::payment_status_ws_handler;
+mod alerts;
+pub mod ws_connections;
+mod ws_delivery;
+use alerts::{acknowledge_alert_handler, list_alerts_handler};
+use ws_connections::{
+    disconnect_ws_connection_handler, list_ws_connections_handler, LiveConnections,
+};
+pub mod anonymize;
+pub mod api_error;
+mod attendance;
+pub mod auth;
+use auth::{issue_auditor_token_handler, issue_token_handler, revoke_token_handler};
+pub mod build_info;
+use build_info::info_handler;
+pub mod cabin_matching;
+mod cabins;
+use cabins::{save_cabins_handler, session_cabins_handler};
+mod campers;
+use campers::{create_camper_handler, get_camper_handler};
+pub mod check_in;
+pub mod config_profiles;
+use check_in::scan_check_in_handler;
+mod corrections;
+use corrections::{
+    approve_correction_handler, camper_corrections_handler, list_corrections_handler,
+    reject_correction_handler, request_correction_handler,
+};
+mod delegations;
+use delegations::{
+    create_delegated_link_handler, create_delegated_payment_sheet_handler,
+    get_delegated_registration_handler, list_delegated_links_handler,
+    revoke_delegated_link_handler,
+};
+pub mod enrollment;
+use enrollment::{
+    at_risk_sessions_handler, cancel_under_enrolled_handler, set_minimum_enrollment_handler,
+};
+pub mod events;
+use events::{camper_activity_handler, guardian_activity_handler, session_activity_handler};
+mod exchange_rates;
+mod exports;
+use exports::{create_export_handler, export_status_handler};
+pub mod fixtures;
+use fixtures::fixtures_handler;
+mod guardians;
+use guardians::guardian_credits_handler;
+mod holds;
+mod jobs;
+use jobs::run_job_handler;
+mod kiosk_sync;
+use kiosk_sync::kiosk_sync_handler;
+mod medical;
+use medical::{
+    medical_access_report_handler, read_medical_record_handler, update_medical_record_handler,
+};
+mod metrics;
+use metrics::metrics_handler;
+pub mod notification_rules;
+use notification_rules::{
+    get_notification_preferences_handler, put_notification_preferences_handler,
+};
+pub mod notifications;
+use notifications::{payment_deliveries_handler, registration_deliveries_handler};
+mod payment_flags;
+mod payment_guard;
+pub mod payment_limits;
+use payment_limits::{create_limit_override_handler, payment_limits_handler, PaymentLimits};
+mod payment_metadata;
+pub mod payment_methods;
+use payment_methods::payment_methods_report_handler;
+mod payment_timeline;
+pub mod pickups;
+use pickups::{
+    add_pickup_handler, list_pickups_handler, pickup_log_handler, remove_pickup_handler,
+};
+pub mod processing_fees;
+use payment_timeline::payment_timeline_handler;
+use processing_fees::ProcessingFees;
+mod public_availability;
+use public_availability::{public_availability_handler, PublicAvailability};
+mod quotes;
+use quotes::create_quote_handler;
+pub mod receipt_numbers;
+use receipt_numbers::ReceiptNumbering;
+pub mod receipt_pdf;
+mod receipts;
+mod redact;
+mod refund_policy;
+mod refunds;
+use receipts::{receipt_handler, receipt_pdf_handler};
+mod registration_cancellations;
+use registration_cancellations::{cancel_preview_handler, cancel_registration_handler};
+pub mod registration_drafts;
+pub mod registration_import;
+use registration_drafts::{
+    convert_draft_handler, create_draft_handler, get_draft_handler, list_drafts_handler,
+    update_draft_handler,
+};
+use registration_import::import_registrations_handler;
+mod registrations;
+use registrations::{create_registration_handler, get_registration_handler};
+pub mod route_policy;
+use route_policy::{enforce_route_policy, route_policies_handler, RoutePolicyRegistry};
+pub mod revenue;
+use revenue::session_revenue_handler;
+mod roster;
+use roster::roster_handler;
+mod s3_archive;
+mod session_cancellations;
+use session_cancellations::{cancel_session_handler, cancellation_progress_handler};
+mod sessions;
+use sessions::{create_session_handler, get_session_handler, list_sessions_handler};
+mod slo;
+use slo::{slo_status_handler, track_latency, SloTracker};
+mod staff;
+use staff::{
+    add_certification_handler, assign_staff_handler, create_staff_handler,
+    list_role_certifications_handler, remove_assignment_handler, set_role_certifications_handler,
+    staff_schedule_handler,
+};
+pub mod support_tickets;
+use support_tickets::SupportTickets;
+mod tags;
+use tags::{list_tags_handler, tag_registration_handler, untag_registration_handler};
+pub mod tax;
+use tax::{
+    get_billing_address_handler, put_billing_address_handler, tax_summary_handler, TaxIdentity,
+};
+mod usage;
+use usage::{track_usage, usage_report_handler, UsageTracker};
+mod vouchers;
+mod waitlist;
+mod waitlist_offers;
+pub mod warm_start;
+use vouchers::{
+    purchase_voucher_handler, redeem_voucher_handler, voucher_balance_handler,
+    voucher_purchase_status_handler,
+};
+use waitlist::{guardian_waitlist_handler, join_waitlist_handler};
+
+/// Builds the router with every route, the route policy layer and the shared
+/// extensions. Fails if the route policy table, the webhook event filter, ordering
+/// mode, failure responses or shadow settings, the SLO targets, the usage tracking,
+/// the public availability settings, the organization's tax details, the payment
+/// limits, the receipt numbering settings, the per-capability Stripe keys, the
+/// processing fee settings or the support ticket settings are invalid.
+pub fn build_router(
+    state: Arc<Mutex<AppState>>,
+    ws_db_pool: Arc<PgPool>,
+) -> Result<Router, String> {
+    // Load the per-route authorization policy
+    let route_policies = match RoutePolicyRegistry::load() {
+        Ok(registry) => Arc::new(registry),
+        Err(e) => {
+            error!("Invalid route policy table: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the webhook event-type filter
+    let webhook_filter = match WebhookEventFilter::from_env() {
+        Ok(filter) => Arc::new(filter),
+        Err(e) => {
+            error!("Invalid webhook event filter: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the webhook event ordering mode
+    let webhook_ordering = match WebhookOrdering::from_env() {
+        Ok(ordering) => Arc::new(ordering),
+        Err(e) => {
+            error!("Invalid webhook ordering: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load how the webhook answers each class of processing failure
+    let webhook_failure_responses = match WebhookFailureResponses::from_env() {
+        Ok(responses) => Arc::new(responses),
+        Err(e) => {
+            error!("Invalid webhook failure responses: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the webhook shadow replay settings
+    let webhook_shadow = match WebhookShadow::from_env() {
+        Ok(shadow) => Arc::new(shadow),
+        Err(e) => {
+            error!("Invalid webhook shadow configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the latency SLO targets
+    let slo_tracker = match SloTracker::from_env() {
+        Ok(tracker) => Arc::new(tracker),
+        Err(e) => {
+            error!("Invalid SLO configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the API usage flush interval
+    let usage_tracker = match UsageTracker::from_env() {
+        Ok(tracker) => Arc::new(tracker),
+        Err(e) => {
+            error!("Invalid usage tracking configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the public availability cache and rate limit
+    let public_availability = match PublicAvailability::from_env() {
+        Ok(public) => Arc::new(public),
+        Err(e) => {
+            error!("Invalid public availability configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the organization's tax details for receipts
+    let tax_identity = match TaxIdentity::from_env() {
+        Ok(identity) => Arc::new(identity),
+        Err(e) => {
+            error!("Invalid organization tax configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the payment amount limits and velocity checks
+    let payment_limits = match PaymentLimits::from_env() {
+        Ok(limits) => Arc::new(limits),
+        Err(e) => {
+            error!("Invalid payment limits configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the receipt number sequence and prefix
+    let receipt_numbering = match ReceiptNumbering::from_env() {
+        Ok(numbering) => Arc::new(numbering),
+        Err(e) => {
+            error!("Invalid receipt numbering configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the restricted Stripe keys for individual capabilities
+    let stripe_keyring = match StripeKeyring::from_env() {
+        Ok(keyring) => Arc::new(keyring),
+        Err(e) => {
+            error!("Invalid Stripe key configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the processing fees passed on to payers, if any
+    let processing_fees = match ProcessingFees::from_env() {
+        Ok(fees) => Arc::new(fees),
+        Err(e) => {
+            error!("Invalid processing fee configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Load the ticketing provider and the rules that open support tickets
+    let support_tickets = match SupportTickets::from_env() {
+        Ok(tickets) => Arc::new(tickets),
+        Err(e) => {
+            error!("Invalid support ticket configuration: {e}");
+            return Err(e);
+        }
+    };
+
+    // Sockets held by this instance, for admin disconnects
+    let live_connections = Arc::new(LiveConnections::default());
+
+    // Database reachability and the writes buffered while it is down
+    let database_health = Arc::new(DatabaseHealth::default());
+
+    // Configure HTTP routes
+    let app = Router::new()
+        .route("/hello", get(hello_handler))
+        .route("/ready", get(readiness_handler))
+        .route("/stripe_key", get(stripe_handler))
+        .route("/payment_sheet", post(create_payment_sheet_handler))
+        .route(
+            "/webhook",
+            post(webhook_handler).layer(middleware::from_fn(shadow_webhook)),
+        )
+        .route("/payment_status", get(payment_status_ws_handler))
+        .route("/quote", post(create_quote_handler))
+        .route("/vouchers", post(purchase_voucher_handler))
+        .route("/vouchers/redeem", post(redeem_voucher_handler))
+        .route(
+            "/vouchers/purchases/{id}",
+            get(voucher_purchase_status_handler),
+        )
+        .route("/vouchers/{code}", get(voucher_balance_handler))
+        .route("/guardians/{id}/credits", get(guardian_credits_handler))
+        .route("/guardians/{id}/waitlist", get(guardian_waitlist_handler))
+        .route("/guardians/{id}/activity", get(guardian_activity_handler))
+        .route("/waitlist", post(join_waitlist_handler))
+        .route("/sessions", get(list_sessions_handler))
+        .route("/sessions/{id}", get(get_session_handler))
+        .route(
+            "/public/sessions/availability",
+            get(public_availability_handler),
+        )
+        .route("/admin/sessions", post(create_session_handler))
+        .route("/admin/sessions/{id}/staff", post(assign_staff_handler))
+        .route("/admin/sessions/{id}/cancel", post(cancel_session_handler))
+        .route("/admin/sessions/at_risk", get(at_risk_sessions_handler))
+        .route(
+            "/admin/sessions/{id}/minimum_enrollment",
+            put(set_minimum_enrollment_handler),
+        )
+        .route(
+            "/admin/sessions/{id}/cancel_under_enrolled",
+            post(cancel_under_enrolled_handler),
+        )
+        .route(
+            "/admin/sessions/{id}/cancellation",
+            get(cancellation_progress_handler),
+        )
+        .route(
+            "/admin/sessions/{id}/activity",
+            get(session_activity_handler),
+        )
+        .route("/admin/staff", post(create_staff_handler))
+        .route(
+            "/admin/staff/{id}/certifications",
+            post(add_certification_handler),
+        )
+        .route("/admin/staff/{id}/schedule", get(staff_schedule_handler))
+        .route(
+            "/admin/staff_assignments/{id}",
+            delete(remove_assignment_handler),
+        )
+        .route(
+            "/admin/roles/certifications",
+            get(list_role_certifications_handler),
+        )
+        .route(
+            "/admin/roles/{role}/certifications",
+            put(set_role_certifications_handler),
+        )
+        .route("/campers", post(create_camper_handler))
+        .route("/campers/{id}", get(get_camper_handler))
+        .route("/campers/{id}/activity", get(camper_activity_handler))
+        .route(
+            "/campers/{id}/corrections",
+            post(request_correction_handler).get(camper_corrections_handler),
+        )
+        .route("/admin/corrections", get(list_corrections_handler))
+        .route(
+            "/admin/corrections/{id}/approve",
+            post(approve_correction_handler),
+        )
+        .route(
+            "/admin/corrections/{id}/reject",
+            post(reject_correction_handler),
+        )
+        .route(
+            "/campers/{id}/medical",
+            get(read_medical_record_handler).put(update_medical_record_handler),
+        )
+        .route(
+            "/admin/compliance/medical_access",
+            get(medical_access_report_handler),
+        )
+        .route("/receipts/{payment_intent_id}", get(receipt_handler))
+        .route(
+            "/receipts/{payment_intent_id}/pdf",
+            get(receipt_pdf_handler),
+        )
+        .route("/registrations", post(create_registration_handler))
+        .route("/registrations/{id}", get(get_registration_handler))
+        .route(
+            "/registrations/draft",
+            get(list_drafts_handler).post(create_draft_handler),
+        )
+        .route(
+            "/registrations/draft/{id}",
+            get(get_draft_handler).patch(update_draft_handler),
+        )
+        .route(
+            "/registrations/draft/{id}/convert",
+            post(convert_draft_handler),
+        )
+        .route(
+            "/admin/registrations/import",
+            post(import_registrations_handler),
+        )
+        .route(
+            "/me/registrations/{id}/cancel_preview",
+            post(cancel_preview_handler),
+        )
+        .route(
+            "/me/registrations/{id}/cancel",
+            post(cancel_registration_handler),
+        )
+        .route(
+            "/me/billing_address",
+            get(get_billing_address_handler).put(put_billing_address_handler),
+        )
+        .route("/me/tax_summary", get(tax_summary_handler))
+        .route(
+            "/me/notification_preferences",
+            get(get_notification_preferences_handler).put(put_notification_preferences_handler),
+        )
+        .route(
+            "/registrations/{id}/delegations",
+            post(create_delegated_link_handler).get(list_delegated_links_handler),
+        )
+        .route(
+            "/registrations/{id}/delegations/{link_id}",
+            delete(revoke_delegated_link_handler),
+        )
+        .route(
+            "/registrations/{id}/pickups",
+            get(list_pickups_handler).post(add_pickup_handler),
+        )
+        .route(
+            "/registrations/{id}/pickups/{pickup_id}",
+            delete(remove_pickup_handler),
+        )
+        .route(
+            "/delegated/{token}",
+            get(get_delegated_registration_handler),
+        )
+        .route(
+            "/delegated/{token}/payment_sheet",
+            post(create_delegated_payment_sheet_handler),
+        )
+        .route("/sync", post(kiosk_sync_handler))
+        .route("/check_in/scan", post(scan_check_in_handler))
+        .route(
+            "/admin/payments/{id}/deliveries",
+            get(payment_deliveries_handler),
+        )
+        .route(
+            "/admin/payments/{id}/timeline",
+            get(payment_timeline_handler),
+        )
+        .route(
+            "/admin/registrations/{id}/deliveries",
+            get(registration_deliveries_handler),
+        )
+        .route("/admin/api_tokens", post(issue_token_handler))
+        .route(
+            "/admin/api_tokens/auditor",
+            post(issue_auditor_token_handler),
+        )
+        .route("/admin/api_tokens/{id}", delete(revoke_token_handler))
+        .route("/admin/route_policies", get(route_policies_handler))
+        .route("/admin/sessions/{id}/roster", get(roster_handler))
+        .route("/admin/sessions/{id}/pickups", get(pickup_log_handler))
+        .route(
+            "/admin/sessions/{id}/cabins",
+            get(session_cabins_handler).put(save_cabins_handler),
+        )
+        .route("/admin/tags", get(list_tags_handler))
+        .route(
+            "/admin/registrations/{id}/tags",
+            post(tag_registration_handler),
+        )
+        .route(
+            "/admin/registrations/{id}/tags/{tag}",
+            delete(untag_registration_handler),
+        )
+        .route("/admin/alerts", get(list_alerts_handler))
+        .route(
+            "/admin/alerts/{id}/acknowledge",
+            post(acknowledge_alert_handler),
+        )
+        .route("/admin/exports", post(create_export_handler))
+        .route("/admin/exports/{id}", get(export_status_handler))
+        .route("/admin/jobs/{name}", post(run_job_handler))
+        .route("/admin/info", get(info_handler))
+        .route("/admin/metrics", get(metrics_handler))
+        .route("/admin/slo_status", get(slo_status_handler))
+        .route("/admin/usage", get(usage_report_handler))
+        .route("/admin/webhook_coverage", get(webhook_coverage_handler))
+        .route("/admin/ws_connections", get(list_ws_connections_handler))
+        .route(
+            "/admin/ws_connections/{connection_id}",
+            delete(disconnect_ws_connection_handler),
+        )
+        .route("/admin/payment_limits", get(payment_limits_handler))
+        .route(
+            "/admin/payment_limits/overrides",
+            post(create_limit_override_handler),
+        )
+        .route(
+            "/admin/reports/payment_methods",
+            get(payment_methods_report_handler),
+        )
+        .route(
+            "/admin/reports/session_revenue",
+            get(session_revenue_handler),
+        )
+        .route("/dev/fixtures", get(fixtures_handler))
+        .route_layer(middleware::from_fn(enforce_route_policy))
+        .route_layer(middleware::from_fn(track_latency))
+        .route_layer(middleware::from_fn(track_usage))
+        .layer(Extension(usage_tracker))
+        .layer(Extension(public_availability))
+        .layer(Extension(tax_identity))
+        .layer(Extension(payment_limits))
+        .layer(Extension(receipt_numbering))
+        .layer(Extension(stripe_keyring))
+        .layer(Extension(processing_fees))
+        .layer(Extension(support_tickets))
+        .layer(Extension(slo_tracker))
+        .layer(Extension(route_policies))
+        .layer(Extension(webhook_filter))
+        .layer(Extension(webhook_ordering))
+        .layer(Extension(webhook_failure_responses))
+        .layer(Extension(webhook_shadow))
+        .layer(Extension(ws_db_pool))
+        .layer(Extension(live_connections))
+        .layer(Extension(database_health))
+        .layer(Extension(state));
+
+    Ok(app)
+}
@@ -1,7 +1,8 @@
-#![feature(trivial_bounds)]
-use axum::{
-    routing::{get, post},
-    Extension, Router,
+use camp_registration_lambda::{
+    build_info, build_router,
+    config_profiles::{self, EffectiveConfig},
+    database::create_db_pool,
+    warm_start,
 };
 use lambda_http::run;
 use lambda_lib::{get_stripe_keys, structs::WebSocketService, AppState, DatabaseClient};
@@ -10,17 +11,7 @@ use tokio::sync::Mutex;
 use tracing::{error, info};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-mod handlers;
-use handlers::{create_payment_sheet_handler, hello_handler, stripe_handler};
-mod stripe_webhook;
-use stripe_webhook::webhook_handler;
-mod websocket_handler;
-use websocket_handler::payment_status_ws_handler;
-mod database;
-use database::create_db_pool;
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Initialize tracing
     let filter = EnvFilter::from_default_env().add_directive(tracing::Level::TRACE.into());
     let stdout_layer = fmt::layer()
@@ -36,6 +27,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
     info!("Starting HTTP Lambda");
 
+    // Apply the configuration profile while the process is still single-threaded,
+    // before anything reads its settings
+    let config = match config_profiles::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid configuration: {e}");
+            return Err(e.into());
+        }
+    };
+    config.log();
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(serve(config))
+}
+
+async fn serve(config: EffectiveConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let stripe_keys = match get_stripe_keys().await {
         Ok(keys) => keys,
         Err((status, msg)) => {
@@ -44,6 +53,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             return Err(msg.into());
         }
     };
+    let key_check = config
+        .profile
+        .check_stripe_key("Stripe secret key", &stripe_keys.secret_key)
+        .and_then(|()| {
+            config
+                .profile
+                .check_stripe_key("Stripe publishable key", &stripe_keys.publishable_key)
+        });
+    if let Err(e) = key_check {
+        error!("Invalid configuration: {e}");
+        return Err(e.into());
+    }
 
     // Initialize database connection
     let db_pool = match create_db_pool() {
@@ -57,6 +78,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         }
     };
 
+    // The WebSocket handler takes the pool as its own extension
+    let ws_db_pool = Arc::new(db_pool.pool.clone());
+
     // Initialize the WebSocket service
     let websocket_service = WebSocketService::new();
 
@@ -68,14 +92,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     };
     let state_arc = Arc::new(Mutex::new(state));
 
-    // Configure HTTP routes
-    let app = Router::new()
-        .route("/hello", get(hello_handler))
-        .route("/stripe_key", get(stripe_handler))
-        .route("/payment_sheet", post(create_payment_sheet_handler))
-        .route("/webhook", post(webhook_handler))
-        .route("/payment_status", get(payment_status_ws_handler))
-        .layer(Extension(state_arc));
+    let app = match build_router(state_arc.clone(), ws_db_pool) {
+        Ok(app) => app,
+        Err(e) => {
+            error!("Failed to build router: {e}");
+            return Err(e.into());
+        }
+    };
+
+    build_info::log_startup_banner(&state_arc.lock().await.stripe_keys.secret_key);
+
+    // Provisioned instances get ready during init rather than on their first request
+    if warm_start::enabled() {
+        warm_start::warm_up(&state_arc).await;
+    }
 
     match run(app).await {
         Ok(()) => info!("Lambda executed successfully"),
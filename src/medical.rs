@@ -0,0 +1,170 @@
+use crate::auth::{Actor, Role};
+use crate::campers::{ensure_guardian_owns, load_camper};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{MedicalAccess, MedicalRecord, NewMedicalAccess},
+};
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct MedicalReadQuery {
+    /// Required for every role except nurses and the camper's own guardian (break-glass).
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MedicalRecordRequest {
+    pub allergies: Option<String>,
+    pub medications: Option<String>,
+    pub conditions: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComplianceQuery {
+    /// Calendar year of the camp season.
+    pub season: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MedicalRecordResponse {
+    pub camper_id: Uuid,
+    /// `None` until a record has been saved for the camper.
+    pub medical_record: Option<MedicalRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MedicalAccessReportResponse {
+    pub season: i32,
+    pub total_accesses: usize,
+    pub break_glass_accesses: usize,
+    pub accesses: Vec<MedicalAccess>,
+}
+
+/// GET /campers/{id}/medical returns a camper's medical record. Every read is logged
+/// before the record is returned; a read that cannot be logged is refused.
+#[tracing::instrument(skip(state))]
+pub async fn read_medical_record_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(camper_id): Path<Uuid>,
+    Query(query): Query<MedicalReadQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+
+    let reason = query
+        .reason
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty());
+    let break_glass = !matches!(actor.role, Role::Nurse | Role::Guardian);
+    if break_glass && reason.is_none() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "A reason is required to access medical data".to_string(),
+        ));
+    }
+    if break_glass {
+        warn!(
+            "Break-glass medical access to camper {} by {} ({:?})",
+            camper.id, actor.role, actor.subject_id
+        );
+    }
+
+    diesel::insert_into(crate::database::schema::medical_access_log::table)
+        .values(&NewMedicalAccess {
+            camper_id: camper.id,
+            reader_token_id: actor.token_id,
+            reader_role: actor.role.to_string(),
+            reader_subject_id: actor.subject_id,
+            reason,
+            break_glass,
+        })
+        .execute(&mut conn)
+        .map_err(db_error("Failed to log medical data access"))?;
+
+    let record = crate::database::schema::medical_records::table
+        .find(camper.id)
+        .first::<MedicalRecord>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to load medical record"))?;
+
+    Ok(axum::Json(json!(MedicalRecordResponse {
+        camper_id: camper.id,
+        medical_record: record,
+    })))
+}
+
+/// PUT /campers/{id}/medical creates or replaces a camper's medical record.
+#[tracing::instrument(skip(state, payload))]
+pub async fn update_medical_record_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(camper_id): Path<Uuid>,
+    Json(payload): Json<MedicalRecordRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+
+    let record = MedicalRecord {
+        camper_id: camper.id,
+        allergies: payload.allergies,
+        medications: payload.medications,
+        conditions: payload.conditions,
+        notes: payload.notes,
+        updated_at: chrono::Utc::now().naive_utc(),
+    };
+    diesel::insert_into(crate::database::schema::medical_records::table)
+        .values(&record)
+        .on_conflict(crate::database::schema::medical_records::camper_id)
+        .do_update()
+        .set(&record)
+        .execute(&mut conn)
+        .map_err(db_error("Failed to save medical record"))?;
+    info!("Updated medical record for camper {}", camper.id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /admin/compliance/medical_access?season= lists every medical-data access in a season.
+#[tracing::instrument(skip(state))]
+pub async fn medical_access_report_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(query): Query<ComplianceQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::medical_access_log::dsl::*;
+
+    let (from, to) = NaiveDate::from_ymd_opt(query.season, 1, 1)
+        .zip(NaiveDate::from_ymd_opt(query.season + 1, 1, 1))
+        .ok_or((StatusCode::BAD_REQUEST, "Invalid season".to_string()))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let accesses = medical_access_log
+        .filter(accessed_at.ge(from.and_time(NaiveTime::MIN)))
+        .filter(accessed_at.lt(to.and_time(NaiveTime::MIN)))
+        .order(accessed_at.asc())
+        .load::<MedicalAccess>(&mut conn)
+        .map_err(db_error("Failed to load medical access log"))?;
+
+    let break_glass_count = accesses.iter().filter(|a| a.break_glass).count();
+    Ok(axum::Json(json!(MedicalAccessReportResponse {
+        season: query.season,
+        total_accesses: accesses.len(),
+        break_glass_accesses: break_glass_count,
+        accesses,
+    })))
+}
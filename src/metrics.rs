@@ -0,0 +1,80 @@
+//! In-process counters.
+//!
+//! Counters are kept per Lambda instance since its cold start and listed by
+//! `GET /admin/metrics`. When `METRICS_NAMESPACE` is set, every increment is also
+//! written to stdout in CloudWatch Embedded Metric Format so CloudWatch aggregates
+//! the counts across instances.
+use axum::Json;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+type CounterKey = (&'static str, BTreeMap<&'static str, String>);
+
+static COUNTERS: LazyLock<Mutex<BTreeMap<CounterKey, u64>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug, Serialize)]
+pub struct Counter {
+    pub name: &'static str,
+    pub labels: BTreeMap<&'static str, String>,
+    pub value: u64,
+}
+
+/// Increments the counter `name` for the given label values.
+pub fn increment(name: &'static str, labels: &[(&'static str, &str)]) {
+    let labels: BTreeMap<&'static str, String> = labels
+        .iter()
+        .map(|(key, value)| (*key, value.to_string()))
+        .collect();
+
+    if let Ok(namespace) = std::env::var("METRICS_NAMESPACE") {
+        if !namespace.is_empty() {
+            emit_emf(&namespace, name, &labels);
+        }
+    }
+
+    let mut counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+    *counters.entry((name, labels)).or_insert(0) += 1;
+}
+
+/// Current value of every counter.
+pub fn snapshot() -> Vec<Counter> {
+    let counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+    counters
+        .iter()
+        .map(|((name, labels), value)| Counter {
+            name,
+            labels: labels.clone(),
+            value: *value,
+        })
+        .collect()
+}
+
+fn emit_emf(namespace: &str, name: &str, labels: &BTreeMap<&'static str, String>) {
+    let mut line = Map::new();
+    line.insert(
+        "_aws".to_string(),
+        json!({
+            "Timestamp": chrono::Utc::now().timestamp_millis(),
+            "CloudWatchMetrics": [{
+                "Namespace": namespace,
+                "Dimensions": [labels.keys().collect::<Vec<_>>()],
+                "Metrics": [{ "Name": name, "Unit": "Count" }],
+            }],
+        }),
+    );
+    line.insert(name.to_string(), json!(1));
+    for (key, value) in labels {
+        line.insert(key.to_string(), json!(value));
+    }
+    // EMF is read from the raw log line, so bypass the tracing formatter
+    println!("{}", Value::Object(line));
+}
+
+/// GET /admin/metrics lists this instance's counters.
+#[tracing::instrument]
+pub async fn metrics_handler() -> Json<Value> {
+    Json(json!({ "counters": snapshot() }))
+}
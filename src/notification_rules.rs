@@ -0,0 +1,410 @@
+//! Date-driven engagement notifications.
+//!
+//! Each [`NotificationRule`] picks the campers or registrations it applies to on a
+//! given day and renders through its own relay template: a birthday greeting to the
+//! camper's family and a one-week countdown before a confirmed session starts. The
+//! `notification_rules` job evaluates the rules once a day and enqueues the emails
+//! in the outbox. Every send is recorded per rule, subject and occasion, so running
+//! the job twice on the same day sends nothing new.
+//!
+//! Guardians opt out of individual rules through `/me/notification_preferences`.
+//! Guardians have no phone number on file, so the rules only send email.
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{
+        CampSession, Camper, Guardian, NewNotificationOptOut, NewNotificationRuleSend, Registration,
+    },
+};
+use crate::metrics;
+use crate::notifications::{enqueue, Channel, Notification};
+use crate::tax::load_actor_guardian;
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+};
+use chrono::{Datelike, NaiveDate};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+const PREFERENCES_PATH: &str = "/me/notification_preferences";
+
+/// When a rule fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// On the camper's birthday.
+    Birthday,
+    /// This many days before a confirmed registration's session starts.
+    DaysBeforeSession(i64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NotificationRule {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The relay template the message renders with.
+    pub template: &'static str,
+    pub trigger: Trigger,
+}
+
+pub const RULES: &[NotificationRule] = &[
+    NotificationRule {
+        name: "camper_birthday",
+        description: "A birthday greeting for each camper",
+        template: "camper_birthday",
+        trigger: Trigger::Birthday,
+    },
+    NotificationRule {
+        name: "session_countdown",
+        description: "A reminder one week before camp starts",
+        template: "session_countdown_one_week",
+        trigger: Trigger::DaysBeforeSession(7),
+    },
+];
+
+pub fn find_rule(name: &str) -> Option<&'static NotificationRule> {
+    RULES.iter().find(|rule| rule.name == name)
+}
+
+/// The day a birthday falls on in `year`. Birthdays on 29 February are celebrated
+/// on the 28th in common years.
+pub fn birthday_in(birthdate: NaiveDate, year: i32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, birthdate.month(), birthdate.day())
+        .or_else(|| NaiveDate::from_ymd_opt(year, birthdate.month(), birthdate.day() - 1))
+        .unwrap_or(birthdate)
+}
+
+pub fn is_birthday(birthdate: NaiveDate, today: NaiveDate) -> bool {
+    birthdate < today && birthday_in(birthdate, today.year()) == today
+}
+
+/// Age in whole years on `today`.
+pub fn age_on(birthdate: NaiveDate, today: NaiveDate) -> i32 {
+    let years = today.year() - birthdate.year();
+    if birthday_in(birthdate, today.year()) > today {
+        years - 1
+    } else {
+        years
+    }
+}
+
+/// One message a rule wants to send today.
+#[derive(Debug)]
+struct Occasion {
+    rule: &'static NotificationRule,
+    subject_id: Uuid,
+    occasion: String,
+    guardian_id: Uuid,
+    registration_id: Option<Uuid>,
+    payload: Value,
+}
+
+/// Campers with a confirmed registration whose birthday is today.
+fn birthday_occasions(
+    conn: &mut PgConnection,
+    rule: &'static NotificationRule,
+    today: NaiveDate,
+) -> Result<Vec<Occasion>, diesel::result::Error> {
+    use crate::database::schema::{campers, registrations};
+
+    let birthdays: Vec<Camper> = campers::table
+        .load::<Camper>(conn)?
+        .into_iter()
+        .filter(|camper| is_birthday(camper.birthdate, today))
+        .collect();
+    let ids: Vec<Uuid> = birthdays.iter().map(|camper| camper.id).collect();
+    let enrolled: HashSet<Uuid> = registrations::table
+        .filter(registrations::camper_id.eq_any(&ids))
+        .filter(registrations::status.eq("confirmed"))
+        .select(registrations::camper_id)
+        .load::<Uuid>(conn)?
+        .into_iter()
+        .collect();
+
+    Ok(birthdays
+        .into_iter()
+        .filter(|camper| enrolled.contains(&camper.id))
+        .map(|camper| Occasion {
+            rule,
+            subject_id: camper.id,
+            occasion: today.year().to_string(),
+            guardian_id: camper.guardian_id,
+            registration_id: None,
+            payload: json!({
+                "type": rule.template,
+                "camper_id": camper.id,
+                "first_name": camper.first_name,
+                "age": age_on(camper.birthdate, today),
+                "preferences": PREFERENCES_PATH,
+            }),
+        })
+        .collect())
+}
+
+/// Confirmed registrations for sessions starting `days` from today.
+fn countdown_occasions(
+    conn: &mut PgConnection,
+    rule: &'static NotificationRule,
+    days: i64,
+    today: NaiveDate,
+) -> Result<Vec<Occasion>, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, campers, registrations};
+
+    let starts_on = today + chrono::Duration::days(days);
+    let sessions: HashMap<Uuid, CampSession> = camp_sessions::table
+        .filter(camp_sessions::starts_on.eq(starts_on))
+        .filter(camp_sessions::cancelled_at.is_null())
+        .load::<CampSession>(conn)?
+        .into_iter()
+        .map(|session| (session.id, session))
+        .collect();
+    let session_ids: Vec<Uuid> = sessions.keys().copied().collect();
+    let confirmed = registrations::table
+        .filter(registrations::session_id.eq_any(&session_ids))
+        .filter(registrations::status.eq("confirmed"))
+        .load::<Registration>(conn)?;
+    let camper_ids: Vec<Uuid> = confirmed.iter().map(|r| r.camper_id).collect();
+    let names: HashMap<Uuid, String> = campers::table
+        .filter(campers::id.eq_any(&camper_ids))
+        .select((campers::id, campers::first_name))
+        .load::<(Uuid, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    Ok(confirmed
+        .into_iter()
+        .filter_map(|registration| {
+            let session = sessions.get(&registration.session_id)?;
+            Some(Occasion {
+                rule,
+                subject_id: registration.id,
+                occasion: starts_on.to_string(),
+                guardian_id: registration.guardian_id,
+                registration_id: Some(registration.id),
+                payload: json!({
+                    "type": rule.template,
+                    "registration_id": registration.id,
+                    "camper_id": registration.camper_id,
+                    "first_name": names.get(&registration.camper_id),
+                    "session_id": session.id,
+                    "session_name": session.name,
+                    "starts_on": session.starts_on,
+                    "days_until": days,
+                    "preferences": PREFERENCES_PATH,
+                }),
+            })
+        })
+        .collect())
+}
+
+/// What a rules run did.
+#[derive(Debug, Default, Serialize)]
+pub struct RulesRunSummary {
+    /// Messages enqueued per rule.
+    pub sent: BTreeMap<&'static str, usize>,
+    pub opted_out: usize,
+    pub already_sent: usize,
+    #[serde(skip)]
+    pub notification_ids: Vec<Uuid>,
+}
+
+/// Evaluates every rule for `today` and enqueues the messages not sent yet.
+pub fn evaluate_rules(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<RulesRunSummary, diesel::result::Error> {
+    use crate::database::schema::{guardians, notification_opt_outs, notification_rule_sends};
+
+    conn.transaction(|conn| {
+        let mut occasions = Vec::new();
+        for rule in RULES {
+            occasions.extend(match rule.trigger {
+                Trigger::Birthday => birthday_occasions(conn, rule, today)?,
+                Trigger::DaysBeforeSession(days) => countdown_occasions(conn, rule, days, today)?,
+            });
+        }
+
+        let guardian_ids: Vec<Uuid> = occasions.iter().map(|o| o.guardian_id).collect();
+        let opt_outs: HashSet<(Uuid, String)> = notification_opt_outs::table
+            .filter(notification_opt_outs::guardian_id.eq_any(&guardian_ids))
+            .select((
+                notification_opt_outs::guardian_id,
+                notification_opt_outs::rule,
+            ))
+            .load::<(Uuid, String)>(conn)?
+            .into_iter()
+            .collect();
+        let emails: HashMap<Uuid, String> = guardians::table
+            .filter(guardians::id.eq_any(&guardian_ids))
+            .load::<Guardian>(conn)?
+            .into_iter()
+            .map(|guardian| (guardian.id, guardian.email))
+            .collect();
+        let subject_ids: Vec<Uuid> = occasions.iter().map(|o| o.subject_id).collect();
+        let sent: HashSet<(String, Uuid, String)> = notification_rule_sends::table
+            .filter(notification_rule_sends::subject_id.eq_any(&subject_ids))
+            .select((
+                notification_rule_sends::rule,
+                notification_rule_sends::subject_id,
+                notification_rule_sends::occasion,
+            ))
+            .load::<(String, Uuid, String)>(conn)?
+            .into_iter()
+            .collect();
+
+        let mut summary = RulesRunSummary::default();
+        for occasion in occasions {
+            let key = (
+                occasion.rule.name.to_string(),
+                occasion.subject_id,
+                occasion.occasion.clone(),
+            );
+            if sent.contains(&key) {
+                summary.already_sent += 1;
+                continue;
+            }
+            if opt_outs.contains(&(occasion.guardian_id, occasion.rule.name.to_string())) {
+                summary.opted_out += 1;
+                continue;
+            }
+            let Some(email) = emails.get(&occasion.guardian_id) else {
+                continue;
+            };
+
+            let message_id = enqueue(
+                conn,
+                Notification {
+                    channel: Channel::Email,
+                    target: email.clone(),
+                    template: occasion.rule.template.to_string(),
+                    payload: occasion.payload,
+                    registration_id: occasion.registration_id,
+                    payment_intent_id: None,
+                },
+            )?;
+            diesel::insert_into(notification_rule_sends::table)
+                .values(&NewNotificationRuleSend {
+                    rule: occasion.rule.name.to_string(),
+                    subject_id: occasion.subject_id,
+                    occasion: occasion.occasion,
+                    outbox_message_id: message_id,
+                })
+                .execute(conn)?;
+            *summary.sent.entry(occasion.rule.name).or_default() += 1;
+            summary.notification_ids.push(message_id);
+            metrics::increment(
+                "notification_rules_sent_total",
+                &[("rule", occasion.rule.name)],
+            );
+        }
+        Ok(summary)
+    })
+}
+
+/// Whether a guardian receives one rule's messages.
+#[derive(Debug, Serialize)]
+pub struct RulePreference {
+    pub rule: &'static str,
+    pub description: &'static str,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationPreferencesResponse {
+    pub guardian_id: Uuid,
+    pub rules: Vec<RulePreference>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    /// The rules the guardian does not want; every other rule is enabled.
+    pub opted_out: Vec<String>,
+}
+
+fn preferences(guardian_id: Uuid, opted_out: &HashSet<String>) -> NotificationPreferencesResponse {
+    NotificationPreferencesResponse {
+        guardian_id,
+        rules: RULES
+            .iter()
+            .map(|rule| RulePreference {
+                rule: rule.name,
+                description: rule.description,
+                enabled: !opted_out.contains(rule.name),
+            })
+            .collect(),
+    }
+}
+
+/// GET /me/notification_preferences lists the notification rules and whether the
+/// guardian receives each.
+#[tracing::instrument(skip(state))]
+pub async fn get_notification_preferences_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::notification_opt_outs::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let guardian = load_actor_guardian(&mut conn, &actor)?;
+    let opted_out: HashSet<String> = notification_opt_outs
+        .filter(guardian_id.eq(guardian.id))
+        .select(rule)
+        .load::<String>(&mut conn)
+        .map_err(db_error("Failed to load notification preferences"))?
+        .into_iter()
+        .collect();
+
+    Ok(axum::Json(json!(preferences(guardian.id, &opted_out))))
+}
+
+/// PUT /me/notification_preferences replaces the rules the guardian has opted out of.
+#[tracing::instrument(skip(state))]
+pub async fn put_notification_preferences_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<UpdatePreferencesRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::notification_opt_outs::dsl::*;
+
+    let opted_out: HashSet<String> = payload.opted_out.into_iter().collect();
+    if let Some(unknown) = opted_out.iter().find(|name| find_rule(name).is_none()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unknown notification rule: {unknown}"),
+        ));
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let guardian = load_actor_guardian(&mut conn, &actor)?;
+    let rows: Vec<NewNotificationOptOut> = opted_out
+        .iter()
+        .map(|name| NewNotificationOptOut {
+            guardian_id: guardian.id,
+            rule: name.clone(),
+        })
+        .collect();
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(notification_opt_outs.filter(guardian_id.eq(guardian.id))).execute(conn)?;
+        if !rows.is_empty() {
+            diesel::insert_into(notification_opt_outs)
+                .values(&rows)
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+    .map_err(db_error("Failed to save notification preferences"))?;
+    info!(
+        "Guardian {} opted out of {} notification rule(s)",
+        guardian.id,
+        rows.len()
+    );
+
+    Ok(axum::Json(json!(preferences(guardian.id, &opted_out))))
+}
@@ -0,0 +1,497 @@
+//! Notification outbox.
+//!
+//! State changes enqueue notifications inside their own database transaction, so a
+//! notification exists if and only if the change committed. [`dispatch_pending`]
+//! then delivers queued messages: WebSocket messages go to the connections
+//! subscribed to the message's payment intent, and email, SMS and push messages are
+//! handed to the relay service configured by `NOTIFICATION_RELAY_URL`.
+//!
+//! A failed send is retried under its channel's [`RetryPolicy`]: the message stays
+//! pending with a `next_attempt_at` pushed back exponentially, with jitter so a
+//! burst of failures does not retry in lockstep, and the `notifications` job picks
+//! it up once it is due. A message that exhausts its attempts, or that the relay
+//! rejects outright, is moved to `dead_letter` and counted in
+//! `notifications_dead_lettered_total{channel}`.
+use crate::database::{
+    conn_from_state, db_error, get_conn,
+    models::{
+        NewNotificationDelivery, NewOutboxMessage, NotificationDelivery, OutboxMessage,
+        WebSocketConnection,
+    },
+};
+use crate::metrics;
+use crate::ws_delivery::send_to_connections;
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    WebSocket,
+    Email,
+    Sms,
+    Push,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::WebSocket => "websocket",
+            Channel::Email => "email",
+            Channel::Sms => "sms",
+            Channel::Push => "push",
+        }
+    }
+
+    pub fn parse(channel: &str) -> Option<Self> {
+        match channel {
+            "websocket" => Some(Channel::WebSocket),
+            "email" => Some(Channel::Email),
+            "sms" => Some(Channel::Sms),
+            "push" => Some(Channel::Push),
+            _ => None,
+        }
+    }
+
+    /// WebSocket messages are only useful while the payer is watching, so they
+    /// give up quickly; email can wait hours for a mail provider to recover.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let (max_attempts, base_secs, max_secs) = match self {
+            Channel::WebSocket => (3, 5, 30),
+            Channel::Email => (6, 60, 3600),
+            Channel::Sms => (4, 60, 1800),
+            Channel::Push => (4, 30, 900),
+        };
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_secs(base_secs),
+            max_delay: Duration::from_secs(max_secs),
+        }
+    }
+}
+
+/// How many times a channel's failed sends are attempted and how long to wait
+/// between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first.
+    pub max_attempts: i32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The delay after failed attempt number `attempt` (from 1): the base delay
+    /// doubled per earlier attempt and capped at `max_delay`, of which the upper
+    /// half is scaled by `jitter` (from 0 to 1).
+    pub fn backoff(&self, attempt: i32, jitter: f64) -> Duration {
+        let doublings = u32::try_from(attempt.saturating_sub(1))
+            .unwrap_or_default()
+            .min(20);
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay);
+        let half = ceiling / 2;
+        half + half.mul_f64(jitter.clamp(0.0, 1.0))
+    }
+
+    /// Whether a message that failed attempt number `attempt` may be tried again.
+    pub fn retries_after(&self, attempt: i32) -> bool {
+        attempt < self.max_attempts
+    }
+}
+
+/// A uniformly random jitter factor from 0 to 1.
+fn jitter() -> f64 {
+    let bits = Uuid::new_v4().as_u128() as u64 & ((1 << 53) - 1);
+    bits as f64 / (1u64 << 53) as f64
+}
+
+/// Why a send failed.
+#[derive(Debug)]
+enum DeliveryFailure {
+    /// May succeed if tried again.
+    Transient(String),
+    /// Will fail the same way every time, such as a relay rejecting the request.
+    Permanent(String),
+}
+
+/// A notification to enqueue. `target` is the payment intent id for WebSocket
+/// messages, and the address, phone number or device token otherwise.
+#[derive(Debug)]
+pub struct Notification {
+    pub channel: Channel,
+    pub target: String,
+    pub template: String,
+    pub payload: Value,
+    pub registration_id: Option<Uuid>,
+    pub payment_intent_id: Option<String>,
+}
+
+/// Writes a notification to the outbox. Call inside the transaction that makes the
+/// state change being announced.
+pub fn enqueue(
+    conn: &mut PgConnection,
+    notification: Notification,
+) -> Result<Uuid, diesel::result::Error> {
+    let message = NewOutboxMessage {
+        id: Uuid::new_v4(),
+        channel: notification.channel.as_str().to_string(),
+        target: notification.target,
+        template: notification.template,
+        payload: notification.payload,
+        registration_id: notification.registration_id,
+        payment_intent_id: notification.payment_intent_id,
+    };
+    diesel::insert_into(crate::database::schema::notification_outbox::table)
+        .values(&message)
+        .execute(conn)?;
+    Ok(message.id)
+}
+
+/// Sends a message to the active WebSocket connections for a payment intent. Fails
+/// only when no connection could be reached.
+async fn deliver_websocket(
+    state: &Arc<Mutex<AppState>>,
+    conn: &mut PgConnection,
+    message: &OutboxMessage,
+) -> Result<&'static str, DeliveryFailure> {
+    use crate::database::schema::websocket_connections::dsl::*;
+
+    let connection_ids: Vec<String> = websocket_connections
+        .filter(payment_intent_id.eq(&message.target))
+        .filter(status.eq("active"))
+        .load::<WebSocketConnection>(conn)
+        .map_err(|e| {
+            DeliveryFailure::Transient(format!("Failed to fetch active connections: {e}"))
+        })?
+        .into_iter()
+        .map(|c| c.connection_id)
+        .collect();
+    if connection_ids.is_empty() {
+        return Ok("skipped");
+    }
+
+    let summary = send_to_connections(
+        state,
+        conn,
+        &message.target,
+        &message.payload.to_string(),
+        &connection_ids,
+    )
+    .await;
+    if summary.sent() == 0 {
+        let errors: Vec<String> = summary
+            .results
+            .iter()
+            .filter_map(|r| {
+                r.error
+                    .as_ref()
+                    .map(|e| format!("{}: {e}", r.connection_id))
+            })
+            .collect();
+        return Err(DeliveryFailure::Transient(format!(
+            "Failed to send message to connections: {}",
+            errors.join("; ")
+        )));
+    }
+    Ok("sent")
+}
+
+/// Hands an email, SMS or push message to the notification relay. A 4xx response
+/// other than a timeout or rate limit means the relay will never accept the message.
+async fn deliver_relay(message: &OutboxMessage) -> Result<&'static str, DeliveryFailure> {
+    let Ok(relay_url) = std::env::var("NOTIFICATION_RELAY_URL") else {
+        warn!(
+            "NOTIFICATION_RELAY_URL not set; skipping {} notification {}",
+            message.channel, message.id
+        );
+        return Ok("skipped");
+    };
+
+    let response = reqwest::Client::new()
+        .post(relay_url)
+        .json(&json!({
+            "id": message.id,
+            "channel": message.channel,
+            "target": message.target,
+            "template": message.template,
+            "payload": message.payload,
+        }))
+        .send()
+        .await
+        .map_err(|e| DeliveryFailure::Transient(format!("Relay request failed: {e}")))?;
+    let relay_status = response.status();
+    if !relay_status.is_success() {
+        let error = format!("Relay returned {relay_status}");
+        let permanent = relay_status.is_client_error()
+            && relay_status != StatusCode::REQUEST_TIMEOUT
+            && relay_status != StatusCode::TOO_MANY_REQUESTS;
+        return Err(if permanent {
+            DeliveryFailure::Permanent(error)
+        } else {
+            DeliveryFailure::Transient(error)
+        });
+    }
+    Ok("sent")
+}
+
+/// Converts an elapsed duration to whole milliseconds for `notification_deliveries`.
+pub fn latency_ms(elapsed: Duration) -> i32 {
+    i32::try_from(elapsed.as_millis()).unwrap_or(i32::MAX)
+}
+
+/// Records a delivery attempt. Failures are logged rather than propagated so that
+/// bookkeeping never blocks delivery.
+pub fn record_delivery(conn: &mut PgConnection, delivery: NewNotificationDelivery) {
+    if let Err(e) = diesel::insert_into(crate::database::schema::notification_deliveries::table)
+        .values(&delivery)
+        .execute(conn)
+    {
+        error!("Failed to record notification delivery: {e}");
+    }
+}
+
+/// Delivers pending outbox messages that are due, optionally restricted to `only`.
+/// Returns the number of messages sent.
+pub async fn dispatch_pending(state: &Arc<Mutex<AppState>>, only: Option<&[Uuid]>) -> usize {
+    use crate::database::schema::notification_outbox::dsl::*;
+
+    let db_client = state.lock().await.database_client.clone();
+    let Some(db_client) = db_client else {
+        error!("Database client not available for notification dispatch");
+        return 0;
+    };
+    let mut conn = match get_conn(&db_client.pool) {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to get database connection for notification dispatch: {e}");
+            return 0;
+        }
+    };
+
+    let mut query = notification_outbox
+        .filter(status.eq("pending"))
+        .filter(next_attempt_at.le(diesel::dsl::now))
+        .order(created_at.asc())
+        .limit(100)
+        .into_boxed();
+    if let Some(ids) = only {
+        query = query.filter(id.eq_any(ids.to_vec()));
+    }
+    let pending = match query.load::<OutboxMessage>(&mut conn) {
+        Ok(pending) => pending,
+        Err(e) => {
+            error!("Failed to load pending notifications: {e}");
+            return 0;
+        }
+    };
+
+    let mut sent = 0;
+    for message in pending {
+        let attempt = message.attempts + 1;
+        let started = Instant::now();
+        let result = if message.channel == Channel::WebSocket.as_str() {
+            deliver_websocket(state, &mut conn, &message).await
+        } else {
+            deliver_relay(&message).await
+        };
+        let latency = started.elapsed();
+
+        let mut delivered_at = None;
+        let mut retry_at: Option<NaiveDateTime> = None;
+        let mut delivery_error = None;
+        let new_status = match result {
+            Ok(outcome) => {
+                info!("Notification {} {}", message.id, outcome);
+                if outcome == "sent" {
+                    sent += 1;
+                }
+                delivered_at = Some(chrono::Utc::now().naive_utc());
+                outcome
+            }
+            Err(failure) => {
+                let policy = Channel::parse(&message.channel).map(|c| c.retry_policy());
+                let (e, retryable) = match failure {
+                    DeliveryFailure::Transient(e) => (e, true),
+                    DeliveryFailure::Permanent(e) => (e, false),
+                };
+                let new_status = match policy {
+                    Some(policy) if retryable && policy.retries_after(attempt) => {
+                        let delay = policy.backoff(attempt, jitter());
+                        retry_at = Some(
+                            chrono::Utc::now().naive_utc()
+                                + chrono::Duration::from_std(delay).unwrap_or_default(),
+                        );
+                        warn!(
+                            "Notification {} attempt {attempt} failed, retrying in {}s: {e}",
+                            message.id,
+                            delay.as_secs()
+                        );
+                        "pending"
+                    }
+                    _ => {
+                        error!(
+                            "Notification {} dead-lettered after attempt {attempt}: {e}",
+                            message.id
+                        );
+                        metrics::increment(
+                            "notifications_dead_lettered_total",
+                            &[("channel", &message.channel)],
+                        );
+                        "dead_letter"
+                    }
+                };
+                delivery_error = Some(e);
+                new_status
+            }
+        };
+
+        record_delivery(
+            &mut conn,
+            NewNotificationDelivery {
+                outbox_id: Some(message.id),
+                channel: message.channel.clone(),
+                target: message.target.clone(),
+                template: message.template.clone(),
+                outcome: match new_status {
+                    "pending" => "failed".to_string(),
+                    other => other.to_string(),
+                },
+                error: delivery_error.clone(),
+                latency_ms: latency_ms(latency),
+                attempt,
+                payment_intent_id: message.payment_intent_id.clone(),
+                registration_id: message.registration_id,
+                next_attempt_at: retry_at,
+            },
+        );
+
+        if let Err(e) = diesel::update(notification_outbox.find(message.id))
+            .set((
+                status.eq(new_status),
+                attempts.eq(attempt),
+                sent_at.eq(delivered_at),
+                next_attempt_at.eq(retry_at.unwrap_or(message.next_attempt_at)),
+                last_error.eq(delivery_error),
+            ))
+            .execute(&mut conn)
+        {
+            error!("Failed to update notification {}: {e}", message.id);
+        }
+    }
+    sent
+}
+
+/// Where an outbox message stands against its channel's retry policy.
+#[derive(Debug, Serialize)]
+pub struct MessageRetries {
+    pub outbox_id: Uuid,
+    pub channel: String,
+    pub template: String,
+    /// `pending`, `sent`, `skipped` or `dead_letter`.
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: Option<i32>,
+    /// When a pending message is next tried.
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+}
+
+impl From<OutboxMessage> for MessageRetries {
+    fn from(message: OutboxMessage) -> Self {
+        let pending = message.status == "pending";
+        Self {
+            outbox_id: message.id,
+            max_attempts: Channel::parse(&message.channel).map(|c| c.retry_policy().max_attempts),
+            channel: message.channel,
+            template: message.template,
+            status: message.status,
+            attempts: message.attempts,
+            next_attempt_at: pending.then_some(message.next_attempt_at),
+            last_error: message.last_error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentDeliveriesResponse {
+    pub payment_intent_id: String,
+    pub deliveries: Vec<NotificationDelivery>,
+    pub messages: Vec<MessageRetries>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistrationDeliveriesResponse {
+    pub registration_id: Uuid,
+    pub deliveries: Vec<NotificationDelivery>,
+    pub messages: Vec<MessageRetries>,
+}
+
+/// GET /admin/payments/{id}/deliveries lists every delivery attempt for a payment
+/// intent and the retry state of its outbox messages.
+#[tracing::instrument(skip(state))]
+pub async fn payment_deliveries_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(intent_id): Path<String>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{notification_deliveries, notification_outbox};
+
+    let mut conn = conn_from_state(&state).await?;
+    let deliveries = notification_deliveries::table
+        .filter(notification_deliveries::payment_intent_id.eq(&intent_id))
+        .order(notification_deliveries::created_at.asc())
+        .load::<NotificationDelivery>(&mut conn)
+        .map_err(db_error("Failed to load notification deliveries"))?;
+    let messages = notification_outbox::table
+        .filter(notification_outbox::payment_intent_id.eq(&intent_id))
+        .order(notification_outbox::created_at.asc())
+        .load::<OutboxMessage>(&mut conn)
+        .map_err(db_error("Failed to load notifications"))?;
+
+    Ok(axum::Json(json!(PaymentDeliveriesResponse {
+        payment_intent_id: intent_id,
+        deliveries,
+        messages: messages.into_iter().map(MessageRetries::from).collect(),
+    })))
+}
+
+/// GET /admin/registrations/{id}/deliveries lists every delivery attempt for a
+/// registration and the retry state of its outbox messages.
+#[tracing::instrument(skip(state))]
+pub async fn registration_deliveries_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{notification_deliveries, notification_outbox};
+
+    let mut conn = conn_from_state(&state).await?;
+    let deliveries = notification_deliveries::table
+        .filter(notification_deliveries::registration_id.eq(registration))
+        .order(notification_deliveries::created_at.asc())
+        .load::<NotificationDelivery>(&mut conn)
+        .map_err(db_error("Failed to load notification deliveries"))?;
+    let messages = notification_outbox::table
+        .filter(notification_outbox::registration_id.eq(registration))
+        .order(notification_outbox::created_at.asc())
+        .load::<OutboxMessage>(&mut conn)
+        .map_err(db_error("Failed to load notifications"))?;
+
+    Ok(axum::Json(json!(RegistrationDeliveriesResponse {
+        registration_id: registration,
+        deliveries,
+        messages: messages.into_iter().map(MessageRetries::from).collect(),
+    })))
+}
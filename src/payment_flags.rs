@@ -0,0 +1,116 @@
+//! Reconciliation flags for money that did not move as expected.
+//!
+//! A refund that Stripe later reports as `failed` or `canceled` leaves the family
+//! without their money, and a failed payout leaves it out of the camp's bank account.
+//! Both are recorded in `payment_flags`, raise an admin alert and show up in the
+//! `flags` column of the payments journal export. Flags are keyed by the Stripe
+//! object, so webhook replays neither duplicate them nor alert twice.
+use crate::alerts;
+use crate::database::models::{AdminAlert, NewPaymentFlag};
+use diesel::prelude::*;
+use serde_json::json;
+use stripe::{Payout, Refund};
+
+pub const REFUND_FAILED: &str = "refund_failed";
+pub const PAYOUT_FAILED: &str = "payout_failed";
+
+/// Refund statuses that mean the money did not reach the customer.
+const FAILED_REFUND_STATUSES: &[&str] = &["failed", "canceled"];
+
+/// Inserts a flag, returning `false` if the same object was already flagged.
+fn insert_flag(
+    conn: &mut PgConnection,
+    flag: &NewPaymentFlag,
+) -> Result<bool, diesel::result::Error> {
+    let inserted = diesel::insert_into(crate::database::schema::payment_flags::table)
+        .values(flag)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(inserted > 0)
+}
+
+/// Flags a refund that failed or was canceled. Returns the alert raised, if any,
+/// for the caller to post once the transaction commits.
+pub fn flag_failed_refund(
+    conn: &mut PgConnection,
+    refund: &Refund,
+    payment_intent_id: &str,
+) -> Result<Option<AdminAlert>, diesel::result::Error> {
+    let status = refund.status.as_deref().unwrap_or_default();
+    if !FAILED_REFUND_STATUSES.contains(&status) {
+        return Ok(None);
+    }
+
+    let details = json!({
+        "refund_id": refund.id.to_string(),
+        "status": status,
+        "failure_reason": refund.failure_reason,
+    });
+    let flag = NewPaymentFlag {
+        kind: REFUND_FAILED.to_string(),
+        stripe_object_id: refund.id.to_string(),
+        payment_intent_id: Some(payment_intent_id.to_string()),
+        amount: refund.amount,
+        currency: refund.currency.to_string(),
+        details: details.clone(),
+    };
+    if !insert_flag(conn, &flag)? {
+        return Ok(None);
+    }
+
+    let alert = alerts::raise(
+        conn,
+        REFUND_FAILED,
+        format!(
+            "Refund {} of {} {} for payment {payment_intent_id} is {status}",
+            refund.id, refund.amount, refund.currency
+        ),
+        details,
+        Some(payment_intent_id.to_string()),
+    )?;
+    Ok(Some(alert))
+}
+
+/// Flags a failed payout. Returns the alert raised, if any, for the caller to post
+/// once the transaction commits.
+pub fn flag_failed_payout(
+    conn: &mut PgConnection,
+    payout: &Payout,
+) -> Result<Option<AdminAlert>, diesel::result::Error> {
+    let details = json!({
+        "payout_id": payout.id.to_string(),
+        "status": payout.status,
+        "failure_code": payout.failure_code,
+        "failure_message": payout.failure_message,
+        "arrival_date": payout.arrival_date,
+    });
+    let flag = NewPaymentFlag {
+        kind: PAYOUT_FAILED.to_string(),
+        stripe_object_id: payout.id.to_string(),
+        payment_intent_id: None,
+        amount: payout.amount,
+        currency: payout.currency.to_string(),
+        details: details.clone(),
+    };
+    if !insert_flag(conn, &flag)? {
+        return Ok(None);
+    }
+
+    let alert = alerts::raise(
+        conn,
+        PAYOUT_FAILED,
+        format!(
+            "Payout {} of {} {} failed: {}",
+            payout.id,
+            payout.amount,
+            payout.currency,
+            payout
+                .failure_message
+                .as_deref()
+                .unwrap_or("no reason given")
+        ),
+        details,
+        None,
+    )?;
+    Ok(Some(alert))
+}
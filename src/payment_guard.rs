@@ -0,0 +1,193 @@
+//! Amount mismatch guard.
+//!
+//! A PaymentIntent may only auto-confirm registrations if its amount and currency
+//! equal the server-side quote referenced by its metadata, and that quote covers
+//! every registration the intent claims to pay for. Anything else (a missing quote,
+//! an amount lowered through the Stripe dashboard or API, a swapped currency) puts
+//! the registrations into `payment_review` and raises an admin alert instead.
+//!
+//! A payment can also land after its registrations were cancelled, e.g. when a
+//! waitlist offer expired while the family was paying. Such a payment is refunded
+//! through the cancellation refund batches and flagged with an admin alert.
+use crate::alerts;
+use crate::database::models::{AdminAlert, NewCancellationRefund, Quote, Registration};
+use crate::payment_metadata::PaymentMetadata;
+use diesel::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Registration status for registrations held because their payment did not match.
+pub const PAYMENT_REVIEW: &str = "payment_review";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AmountCheck {
+    Matches,
+    Mismatch(String),
+}
+
+/// Compares a PaymentIntent's amount and currency against its stored quote.
+pub fn check_amount_against_quote(
+    conn: &mut PgConnection,
+    metadata: &PaymentMetadata,
+    amount: i64,
+    currency: &str,
+) -> Result<AmountCheck, diesel::result::Error> {
+    let Some(quote_id) = metadata.quote_id else {
+        return Ok(AmountCheck::Mismatch(
+            "Payment intent does not reference a quote".to_string(),
+        ));
+    };
+    let quote = crate::database::schema::quotes::table
+        .find(quote_id)
+        .first::<Quote>(conn)
+        .optional()?;
+    let Some(quote) = quote else {
+        return Ok(AmountCheck::Mismatch(format!("Quote {quote_id} not found")));
+    };
+
+    if quote.total != amount || !quote.currency.eq_ignore_ascii_case(currency) {
+        return Ok(AmountCheck::Mismatch(format!(
+            "Paid {amount} {currency} but quote {quote_id} totals {} {}",
+            quote.total, quote.currency
+        )));
+    }
+    if let Some(unquoted) = metadata
+        .registration_ids
+        .iter()
+        .find(|id| !quote.registration_ids.contains(id))
+    {
+        return Ok(AmountCheck::Mismatch(format!(
+            "Registration {unquoted} is not covered by quote {quote_id}"
+        )));
+    }
+    Ok(AmountCheck::Matches)
+}
+
+/// Holds the intent's pending registrations for review and raises an admin alert,
+/// in one transaction.
+pub fn flag_payment_mismatch(
+    conn: &mut PgConnection,
+    metadata: &PaymentMetadata,
+    intent_id: &str,
+    amount: i64,
+    currency: &str,
+    reason: String,
+) -> Result<AdminAlert, diesel::result::Error> {
+    use crate::database::schema::registrations;
+
+    conn.transaction(|conn| {
+        diesel::update(
+            registrations::table
+                .filter(registrations::id.eq_any(&metadata.registration_ids))
+                .filter(registrations::status.eq("pending")),
+        )
+        .set((
+            registrations::status.eq(PAYMENT_REVIEW),
+            registrations::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+
+        alerts::raise(
+            conn,
+            "payment_amount_mismatch",
+            format!("Payment {intent_id} held for review: {reason}"),
+            json!({
+                "payment_intent_id": intent_id,
+                "amount": amount,
+                "currency": currency,
+                "quote_id": metadata.quote_id,
+                "registration_ids": metadata.registration_ids,
+                "reason": reason,
+            }),
+            Some(intent_id.to_string()),
+        )
+    })
+}
+
+/// Plans a full refund of a payment whose registrations were all cancelled before it
+/// succeeded, and raises an admin alert. When only some were cancelled, the alert
+/// asks for a manual refund instead. Returns `None` when no registration was
+/// cancelled without a refund of its own, or the payment was already flagged.
+pub fn refund_late_payment(
+    conn: &mut PgConnection,
+    metadata: &PaymentMetadata,
+    intent_id: &str,
+    amount: i64,
+    currency: &str,
+) -> Result<Option<AdminAlert>, diesel::result::Error> {
+    use crate::database::schema::{admin_alerts, cancellation_refunds, registrations};
+
+    conn.transaction(|conn| {
+        let flagged = diesel::select(diesel::dsl::exists(
+            admin_alerts::table
+                .filter(admin_alerts::kind.eq("late_payment"))
+                .filter(admin_alerts::payment_intent_id.eq(intent_id)),
+        ))
+        .get_result::<bool>(conn)?;
+        if flagged {
+            return Ok(None);
+        }
+
+        let paid_for = registrations::table
+            .filter(registrations::id.eq_any(&metadata.registration_ids))
+            .load::<Registration>(conn)?;
+        // Registrations cancelled with a planned refund were paid before cancelling
+        let refunding = cancellation_refunds::table
+            .filter(cancellation_refunds::registration_id.eq_any(&metadata.registration_ids))
+            .select(cancellation_refunds::registration_id)
+            .load::<Uuid>(conn)?;
+        let cancelled: Vec<&Registration> = paid_for
+            .iter()
+            .filter(|registration| registration.status == "cancelled")
+            .filter(|registration| !refunding.contains(&registration.id))
+            .collect();
+        let Some(first) = cancelled.first() else {
+            return Ok(None);
+        };
+
+        // A refund row is keyed by registration, so only a payment for nothing but
+        // cancelled registrations is refunded in full automatically
+        let refund_planned = cancelled.len() == paid_for.len();
+        if refund_planned {
+            diesel::insert_into(cancellation_refunds::table)
+                .values(&NewCancellationRefund {
+                    cancellation_id: None,
+                    registration_id: first.id,
+                    guardian_id: first.guardian_id,
+                    payment_intent_id: Some(intent_id.to_string()),
+                    amount,
+                    credit_amount: 0,
+                    currency: currency.to_lowercase(),
+                    status: "pending".to_string(),
+                    cause: "late_payment".to_string(),
+                })
+                .execute(conn)?;
+        }
+
+        let cancelled_ids: Vec<_> = cancelled.iter().map(|r| r.id).collect();
+        let message = if refund_planned {
+            format!(
+                "Payment {intent_id} arrived after its registrations were cancelled; refunding it"
+            )
+        } else {
+            format!(
+                "Payment {intent_id} covers {} cancelled registration(s); refund them manually",
+                cancelled_ids.len()
+            )
+        };
+        alerts::raise(
+            conn,
+            "late_payment",
+            message,
+            json!({
+                "payment_intent_id": intent_id,
+                "amount": amount,
+                "currency": currency,
+                "cancelled_registration_ids": cancelled_ids,
+                "refund_planned": refund_planned,
+            }),
+            Some(intent_id.to_string()),
+        )
+        .map(Some)
+    })
+}
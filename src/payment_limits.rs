@@ -0,0 +1,446 @@
+//! Payment amount limits and per-customer velocity checks.
+//!
+//! Every PaymentSheet is checked before its PaymentIntent is created:
+//!
+//! - the amount must fall within the currency's bounds, from
+//!   `PAYMENT_AMOUNT_LIMITS` (`usd:50-1000000,eur:50-1000000` by default, in minor
+//!   units);
+//! - a customer, by email, may create at most `PAYMENT_MAX_INTENTS_PER_HOUR`
+//!   (default 10) PaymentIntents an hour;
+//! - and at most `PAYMENT_DAILY_LIMITS` (`usd:2500000,eur:2500000` by default) in
+//!   one currency over the last 24 hours.
+//!
+//! An admin can grant a customer a temporary override for one currency, for a large
+//! family paying for a whole summer at once: while it is active the per-payment
+//! maximum rises to the override's amount and the hourly and daily checks are
+//! lifted. If the database cannot be read only the amount bounds are enforced, so
+//! an outage does not block checkout.
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{NewPaymentLimitOverride, NewPaymentSheetAttempt, PaymentLimitOverride},
+};
+use crate::metrics;
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+};
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+const DEFAULT_AMOUNT_LIMITS: &str = "usd:50-1000000,eur:50-1000000";
+const DEFAULT_DAILY_LIMITS: &str = "usd:2500000,eur:2500000";
+const DEFAULT_MAX_INTENTS_PER_HOUR: i64 = 10;
+const DEFAULT_OVERRIDE_HOURS: i64 = 24;
+const MAX_OVERRIDE_HOURS: i64 = 30 * 24;
+
+/// Smallest and largest amount of one payment, in minor units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AmountRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+/// A customer's PaymentIntents over the velocity windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecentActivity {
+    /// PaymentIntents created in the last hour, in any currency.
+    pub intents_last_hour: i64,
+    /// Total of the PaymentIntents created in the last 24 hours in the checkout's
+    /// currency.
+    pub amount_last_day: i64,
+}
+
+/// Why a checkout was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitViolation {
+    BelowMinimum { currency: String, min: i64 },
+    AboveMaximum { currency: String, max: i64 },
+    TooManyAttempts { limit: i64 },
+    DailyLimitExceeded { currency: String, limit: i64 },
+}
+
+impl LimitViolation {
+    fn reason(&self) -> &'static str {
+        match self {
+            LimitViolation::BelowMinimum { .. } => "below_minimum",
+            LimitViolation::AboveMaximum { .. } => "above_maximum",
+            LimitViolation::TooManyAttempts { .. } => "too_many_attempts",
+            LimitViolation::DailyLimitExceeded { .. } => "daily_limit",
+        }
+    }
+}
+
+impl From<LimitViolation> for ApiError {
+    fn from(violation: LimitViolation) -> Self {
+        match violation {
+            LimitViolation::BelowMinimum { currency, min } => ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::AmountBelowMinimum,
+                format!("Amount is below the minimum of {min} {currency}"),
+            ),
+            LimitViolation::AboveMaximum { currency, max } => ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::AmountAboveMaximum,
+                format!("Amount is above the maximum of {max} {currency}"),
+            ),
+            LimitViolation::TooManyAttempts { limit } => ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::TooManyPaymentAttempts,
+                format!("At most {limit} payment attempts an hour are allowed; try again later"),
+            ),
+            LimitViolation::DailyLimitExceeded { currency, limit } => ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::DailyLimitExceeded,
+                format!("Payments are limited to {limit} {currency} in 24 hours"),
+            ),
+        }
+    }
+}
+
+/// The configured limits, loaded at startup.
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentLimits {
+    pub amounts: BTreeMap<String, AmountRange>,
+    pub daily_totals: BTreeMap<String, i64>,
+    pub max_intents_per_hour: i64,
+}
+
+/// Parses `currency:value` entries separated by commas.
+pub(crate) fn parse_entries<T>(
+    name: &str,
+    raw: &str,
+    parse_value: impl Fn(&str) -> Option<T>,
+) -> Result<BTreeMap<String, T>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (currency, value) = entry.split_once(':').ok_or_else(|| {
+                format!("Invalid {name} entry '{entry}': expected currency:value")
+            })?;
+            let value = parse_value(value.trim())
+                .ok_or_else(|| format!("Invalid {name} entry '{entry}'"))?;
+            Ok((currency.trim().to_lowercase(), value))
+        })
+        .collect()
+}
+
+impl PaymentLimits {
+    /// Loads the limits from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_settings(
+            env::var("PAYMENT_AMOUNT_LIMITS").ok().as_deref(),
+            env::var("PAYMENT_DAILY_LIMITS").ok().as_deref(),
+            env::var("PAYMENT_MAX_INTENTS_PER_HOUR").ok().as_deref(),
+        )
+    }
+
+    /// Parses the limits from their settings, using the defaults for unset ones.
+    pub fn from_settings(
+        amount_limits: Option<&str>,
+        daily_limits: Option<&str>,
+        max_intents_per_hour: Option<&str>,
+    ) -> Result<Self, String> {
+        let amounts = parse_entries(
+            "PAYMENT_AMOUNT_LIMITS",
+            amount_limits.unwrap_or(DEFAULT_AMOUNT_LIMITS),
+            |value| {
+                let (min, max) = value.split_once('-')?;
+                let range = AmountRange {
+                    min: min.trim().parse().ok()?,
+                    max: max.trim().parse().ok()?,
+                };
+                (range.min > 0 && range.min <= range.max).then_some(range)
+            },
+        )?;
+        let daily_totals = parse_entries(
+            "PAYMENT_DAILY_LIMITS",
+            daily_limits.unwrap_or(DEFAULT_DAILY_LIMITS),
+            |value| value.parse::<i64>().ok().filter(|limit| *limit > 0),
+        )?;
+        for (currency, daily) in &daily_totals {
+            if let Some(range) = amounts.get(currency).filter(|range| range.max > *daily) {
+                return Err(format!(
+                    "PAYMENT_DAILY_LIMITS for {currency} is below its maximum payment of {}",
+                    range.max
+                ));
+            }
+        }
+        let max_intents_per_hour = match max_intents_per_hour {
+            Some(raw) => raw
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|limit| *limit > 0)
+                .ok_or_else(|| format!("Invalid PAYMENT_MAX_INTENTS_PER_HOUR: {raw}"))?,
+            None => DEFAULT_MAX_INTENTS_PER_HOUR,
+        };
+        Ok(Self {
+            amounts,
+            daily_totals,
+            max_intents_per_hour,
+        })
+    }
+
+    /// Checks a checkout of `amount` against the limits, given the customer's recent
+    /// activity and active override. Currencies without configured limits are only
+    /// subject to the hourly count.
+    pub fn check(
+        &self,
+        currency: &str,
+        amount: i64,
+        recent: &RecentActivity,
+        active_override: Option<&PaymentLimitOverride>,
+    ) -> Result<(), LimitViolation> {
+        let currency = currency.to_lowercase();
+        let range = self.amounts.get(&currency);
+        if let Some(range) = range.filter(|range| amount < range.min) {
+            return Err(LimitViolation::BelowMinimum {
+                currency,
+                min: range.min,
+            });
+        }
+        let max = match (range, active_override) {
+            (Some(range), Some(o)) => Some(range.max.max(o.max_amount)),
+            (Some(range), None) => Some(range.max),
+            (None, Some(o)) => Some(o.max_amount),
+            (None, None) => None,
+        };
+        if let Some(max) = max.filter(|max| amount > *max) {
+            return Err(LimitViolation::AboveMaximum { currency, max });
+        }
+        if active_override.is_some() {
+            return Ok(());
+        }
+
+        if recent.intents_last_hour >= self.max_intents_per_hour {
+            return Err(LimitViolation::TooManyAttempts {
+                limit: self.max_intents_per_hour,
+            });
+        }
+        if let Some(limit) = self.daily_totals.get(&currency) {
+            if recent.amount_last_day + amount > *limit {
+                return Err(LimitViolation::DailyLimitExceeded {
+                    currency,
+                    limit: *limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a checkout before its PaymentIntent is created. Without a connection,
+    /// or if the customer's history cannot be read, only the amount bounds apply.
+    pub(crate) fn enforce(
+        &self,
+        conn: Option<&mut PgConnection>,
+        customer_email: &str,
+        currency: &str,
+        amount: i64,
+    ) -> Result<(), ApiError> {
+        let email = customer_email.trim().to_lowercase();
+        let currency = currency.to_lowercase();
+        let history = conn.map(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            Ok::<_, diesel::result::Error>((
+                recent_activity(conn, &email, &currency, now)?,
+                active_override(conn, &email, &currency, now)?,
+            ))
+        });
+        let (recent, active) = match history {
+            Some(Ok(history)) => history,
+            Some(Err(e)) => {
+                error!("Failed to load payment history; checking amount bounds only: {e}");
+                (RecentActivity::default(), None)
+            }
+            None => (RecentActivity::default(), None),
+        };
+
+        // With no history the daily total is just this amount, always within the
+        // daily limit, so only the amount bounds apply
+        self.check(&currency, amount, &recent, active.as_ref())
+            .map_err(|violation| {
+                warn!("Refused {amount} {currency} checkout: {violation:?}");
+                metrics::increment(
+                    "payment_limit_rejections_total",
+                    &[("reason", violation.reason())],
+                );
+                ApiError::from(violation)
+            })
+    }
+}
+
+/// The customer's PaymentIntents in the last hour and the last 24 hours.
+fn recent_activity(
+    conn: &mut PgConnection,
+    email: &str,
+    checkout_currency: &str,
+    now: NaiveDateTime,
+) -> Result<RecentActivity, diesel::result::Error> {
+    use crate::database::schema::payment_sheet_attempts::dsl::*;
+
+    let attempts = payment_sheet_attempts
+        .filter(customer_email.eq(email))
+        .filter(created_at.gt(now - Duration::hours(24)))
+        .select((amount, currency, created_at))
+        .load::<(i64, String, NaiveDateTime)>(conn)?;
+    let hour_ago = now - Duration::hours(1);
+    Ok(RecentActivity {
+        intents_last_hour: attempts.iter().filter(|(_, _, at)| *at > hour_ago).count() as i64,
+        amount_last_day: attempts
+            .iter()
+            .filter(|(_, c, _)| c == checkout_currency)
+            .map(|(a, _, _)| a)
+            .sum(),
+    })
+}
+
+/// The customer's override for the currency with the highest amount, if any is active.
+fn active_override(
+    conn: &mut PgConnection,
+    email: &str,
+    checkout_currency: &str,
+    now: NaiveDateTime,
+) -> Result<Option<PaymentLimitOverride>, diesel::result::Error> {
+    use crate::database::schema::payment_limit_overrides::dsl::*;
+
+    payment_limit_overrides
+        .filter(customer_email.eq(email))
+        .filter(currency.eq(checkout_currency))
+        .filter(expires_at.gt(now))
+        .order(max_amount.desc())
+        .first::<PaymentLimitOverride>(conn)
+        .optional()
+}
+
+/// Records a created PaymentIntent for the velocity checks. Failures are logged:
+/// the PaymentSheet has already been created.
+pub(crate) fn record_attempt(
+    conn: &mut PgConnection,
+    customer_email: &str,
+    payment_intent_id: &str,
+    amount: i64,
+    currency: &str,
+) {
+    use crate::database::schema::payment_sheet_attempts;
+
+    let attempt = NewPaymentSheetAttempt {
+        customer_email: customer_email.trim().to_lowercase(),
+        payment_intent_id: payment_intent_id.to_string(),
+        amount,
+        currency: currency.to_lowercase(),
+    };
+    if let Err(e) = diesel::insert_into(payment_sheet_attempts::table)
+        .values(&attempt)
+        .execute(conn)
+    {
+        error!("Failed to record payment attempt for {payment_intent_id}: {e}");
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentLimitsResponse {
+    pub limits: PaymentLimits,
+    pub overrides: Vec<PaymentLimitOverride>,
+}
+
+/// GET /admin/payment_limits returns the configured limits and the active overrides.
+#[tracing::instrument(skip(state, limits))]
+pub async fn payment_limits_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(limits): Extension<Arc<PaymentLimits>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::payment_limit_overrides::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let overrides = payment_limit_overrides
+        .filter(expires_at.gt(chrono::Utc::now().naive_utc()))
+        .order(created_at.desc())
+        .load::<PaymentLimitOverride>(&mut conn)
+        .map_err(db_error("Failed to load payment limit overrides"))?;
+
+    Ok(axum::Json(json!(PaymentLimitsResponse {
+        limits: limits.as_ref().clone(),
+        overrides,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLimitOverrideRequest {
+    pub customer_email: String,
+    pub currency: String,
+    pub max_amount: i64,
+    pub reason: String,
+    pub expires_in_hours: Option<i64>,
+}
+
+/// POST /admin/payment_limits/overrides lets a customer pay up to `max_amount` in
+/// one currency, without the hourly and daily checks, until the override expires.
+#[tracing::instrument(skip(state))]
+pub async fn create_limit_override_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<CreateLimitOverrideRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::payment_limit_overrides;
+
+    let email = payload.customer_email.trim().to_lowercase();
+    if email.is_empty() || !email.contains('@') {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "customer_email must be an email address".to_string(),
+        ));
+    }
+    if payload.reason.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "reason is required".to_string()));
+    }
+    if payload.max_amount <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "max_amount must be positive".to_string(),
+        ));
+    }
+    let currency = crate::handlers::parse_currency(&payload.currency)
+        .map_err(<(StatusCode, String)>::from)?
+        .to_string();
+    let hours = payload.expires_in_hours.unwrap_or(DEFAULT_OVERRIDE_HOURS);
+    if !(1..=MAX_OVERRIDE_HOURS).contains(&hours) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_hours must be between 1 and {MAX_OVERRIDE_HOURS}"),
+        ));
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let created = diesel::insert_into(payment_limit_overrides::table)
+        .values(&NewPaymentLimitOverride {
+            customer_email: email,
+            currency,
+            max_amount: payload.max_amount,
+            reason: payload.reason.trim().to_string(),
+            created_by: actor.subject_id,
+            expires_at: chrono::Utc::now().naive_utc() + Duration::hours(hours),
+        })
+        .get_result::<PaymentLimitOverride>(&mut conn)
+        .map_err(db_error("Failed to create payment limit override"))?;
+    info!(
+        "Payment limit override {} for {} up to {} {} until {}",
+        created.id,
+        created.customer_email,
+        created.max_amount,
+        created.currency,
+        created.expires_at
+    );
+
+    Ok(axum::Json(json!(created)))
+}
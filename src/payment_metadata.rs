@@ -0,0 +1,117 @@
+//! Typed contract for the metadata attached to Stripe PaymentIntents.
+//!
+//! Stripe stores metadata as string key/value pairs. Every key the backend reads
+//! back from a PaymentIntent is declared here so the payment sheet, the voucher
+//! flow and the webhook agree on names and encodings.
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const PURPOSE: &str = "purpose";
+pub const FRONTEND_ID: &str = "frontend_id";
+pub const VOUCHER_ID: &str = "voucher_id";
+pub const QUOTE_ID: &str = "quote_id";
+/// Comma-separated registration UUIDs paid for by the intent.
+pub const REGISTRATION_IDS: &str = "registration_ids";
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PaymentMetadata {
+    pub purpose: Option<String>,
+    pub frontend_id: Option<String>,
+    pub voucher_id: Option<Uuid>,
+    pub quote_id: Option<Uuid>,
+    pub registration_ids: Vec<Uuid>,
+}
+
+fn parse_uuid(key: &str, value: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(value.trim()).map_err(|e| format!("Invalid {key} metadata '{value}': {e}"))
+}
+
+impl PaymentMetadata {
+    /// Parses the typed fields out of PaymentIntent metadata. Unknown keys are ignored.
+    pub fn parse(metadata: &HashMap<String, String>) -> Result<Self, String> {
+        let non_empty = |key: &str| {
+            metadata
+                .get(key)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+
+        Ok(Self {
+            purpose: non_empty(PURPOSE).map(String::from),
+            frontend_id: non_empty(FRONTEND_ID).map(String::from),
+            voucher_id: non_empty(VOUCHER_ID)
+                .map(|v| parse_uuid(VOUCHER_ID, v))
+                .transpose()?,
+            quote_id: non_empty(QUOTE_ID)
+                .map(|v| parse_uuid(QUOTE_ID, v))
+                .transpose()?,
+            registration_ids: non_empty(REGISTRATION_IDS)
+                .map(|v| {
+                    v.split(',')
+                        .filter(|id| !id.trim().is_empty())
+                        .map(|id| parse_uuid(REGISTRATION_IDS, id))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Converts client-supplied JSON metadata into Stripe metadata. String values are
+    /// passed through unquoted, `registration_ids` may be given as a JSON array, and
+    /// the typed fields are validated.
+    pub fn metadata_from_json(value: &Value) -> Result<HashMap<String, String>, String> {
+        let Some(object) = value.as_object() else {
+            return Ok(HashMap::new());
+        };
+
+        let metadata: HashMap<String, String> = object
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| {
+                let encoded = match v {
+                    Value::String(s) => s.clone(),
+                    Value::Array(items) if k == REGISTRATION_IDS => items
+                        .iter()
+                        .map(|item| item.as_str().map(String::from).unwrap_or(item.to_string()))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    other => other.to_string(),
+                };
+                (k.clone(), encoded)
+            })
+            .collect();
+
+        Self::parse(&metadata)?;
+        Ok(metadata)
+    }
+
+    /// Encodes the typed fields as Stripe metadata.
+    pub fn to_metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::new();
+        if let Some(purpose) = &self.purpose {
+            metadata.insert(PURPOSE.to_string(), purpose.clone());
+        }
+        if let Some(frontend_id) = &self.frontend_id {
+            metadata.insert(FRONTEND_ID.to_string(), frontend_id.clone());
+        }
+        if let Some(voucher_id) = self.voucher_id {
+            metadata.insert(VOUCHER_ID.to_string(), voucher_id.to_string());
+        }
+        if let Some(quote_id) = self.quote_id {
+            metadata.insert(QUOTE_ID.to_string(), quote_id.to_string());
+        }
+        if !self.registration_ids.is_empty() {
+            metadata.insert(
+                REGISTRATION_IDS.to_string(),
+                self.registration_ids
+                    .iter()
+                    .map(Uuid::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+        }
+        metadata
+    }
+}
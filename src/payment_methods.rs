@@ -0,0 +1,171 @@
+//! Payment method mix.
+//!
+//! Every `charge.succeeded` event records the charge's payment method type (from
+//! `payment_method_details.type`) with its amount. `GET /admin/reports/payment_methods`
+//! totals the charges that succeeded in a date range by category: cards (including
+//! wallets such as Apple Pay, which Stripe reports as cards), ACH and other bank
+//! debits, buy-now-pay-later, offline methods paid in cash or by bank transfer,
+//! and anything else. Amounts are gross of refunds and totalled per currency.
+use crate::database::{
+    conn_from_state, db_error,
+    models::{ChargePaymentMethod, NewChargePaymentMethod},
+};
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use stripe::Charge;
+use tokio::sync::Mutex;
+
+/// Reporting category of a Stripe payment method type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MethodCategory {
+    Card,
+    Ach,
+    Bnpl,
+    Offline,
+    Other,
+}
+
+impl MethodCategory {
+    pub fn for_type(method_type: &str) -> Self {
+        match method_type {
+            "card" | "card_present" | "interac_present" => MethodCategory::Card,
+            "us_bank_account" | "ach_debit" | "ach_credit_transfer" | "acss_debit" => {
+                MethodCategory::Ach
+            }
+            "affirm" | "afterpay_clearpay" | "klarna" | "zip" => MethodCategory::Bnpl,
+            "customer_balance" | "paper_check" | "oxxo" | "boleto" | "konbini" | "multibanco" => {
+                MethodCategory::Offline
+            }
+            _ => MethodCategory::Other,
+        }
+    }
+}
+
+/// Records the payment method of a successful charge. Returns whether a row was
+/// added; redelivered events add nothing.
+pub fn record_charge_method(
+    conn: &mut PgConnection,
+    charge: &Charge,
+) -> Result<bool, diesel::result::Error> {
+    let Some(details) = charge.payment_method_details.as_ref() else {
+        return Ok(false);
+    };
+    let succeeded_at = chrono::DateTime::from_timestamp(charge.created, 0)
+        .map(|at| at.naive_utc())
+        .unwrap_or_else(|| chrono::Utc::now().naive_utc());
+    let inserted = diesel::insert_into(crate::database::schema::charge_payment_methods::table)
+        .values(&NewChargePaymentMethod {
+            charge_id: charge.id.to_string(),
+            payment_intent_id: charge.payment_intent.as_ref().map(|p| p.id().to_string()),
+            method_type: details.type_.clone(),
+            amount: charge.amount,
+            currency: charge.currency.to_string(),
+            succeeded_at,
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(inserted > 0)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaymentMethodsQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MethodTotal {
+    pub category: MethodCategory,
+    pub currency: String,
+    pub count: i64,
+    pub amount: i64,
+    /// Share of the currency's total amount, from 0 to 1.
+    pub share_of_amount: f64,
+    /// Stripe payment method types in the category, with their amounts.
+    pub method_types: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentMethodsReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub methods: Vec<MethodTotal>,
+}
+
+/// Totals charges by category and currency, in category order.
+pub fn summarize(charges: &[ChargePaymentMethod]) -> Vec<MethodTotal> {
+    let mut totals: BTreeMap<(MethodCategory, &str), MethodTotal> = BTreeMap::new();
+    let mut currency_totals: BTreeMap<&str, i64> = BTreeMap::new();
+    for charge in charges {
+        let category = MethodCategory::for_type(&charge.method_type);
+        let total = totals
+            .entry((category, charge.currency.as_str()))
+            .or_insert_with(|| MethodTotal {
+                category,
+                currency: charge.currency.clone(),
+                count: 0,
+                amount: 0,
+                share_of_amount: 0.0,
+                method_types: BTreeMap::new(),
+            });
+        total.count += 1;
+        total.amount += charge.amount;
+        *total
+            .method_types
+            .entry(charge.method_type.clone())
+            .or_default() += charge.amount;
+        *currency_totals.entry(charge.currency.as_str()).or_default() += charge.amount;
+    }
+    totals
+        .into_values()
+        .map(|mut total| {
+            let currency_total = currency_totals[total.currency.as_str()];
+            if currency_total > 0 {
+                total.share_of_amount = total.amount as f64 / currency_total as f64;
+            }
+            total
+        })
+        .collect()
+}
+
+/// GET /admin/reports/payment_methods?from=&to= totals successful charges in
+/// `[from, to]` by payment method category.
+#[tracing::instrument(skip(state))]
+pub async fn payment_methods_report_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(query): Query<PaymentMethodsQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::charge_payment_methods::dsl::*;
+
+    if query.to < query.from {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "to must not be before from".to_string(),
+        ));
+    }
+    let start = query.from.and_time(NaiveTime::MIN);
+    let end = (query.to + chrono::Duration::days(1)).and_time(NaiveTime::MIN);
+
+    let mut conn = conn_from_state(&state).await?;
+    let charges = charge_payment_methods
+        .filter(succeeded_at.ge(start))
+        .filter(succeeded_at.lt(end))
+        .load::<ChargePaymentMethod>(&mut conn)
+        .map_err(db_error("Failed to load payment methods"))?;
+
+    Ok(axum::Json(json!(PaymentMethodsReport {
+        from: query.from,
+        to: query.to,
+        methods: summarize(&charges),
+    })))
+}
@@ -0,0 +1,141 @@
+//! Chronological view of everything that happened to one payment intent, for support.
+use crate::database::{
+    conn_from_state, db_error,
+    models::{NotificationDelivery, PaymentEvent, Refund},
+};
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// One step in a payment's history.
+#[derive(Debug, Serialize)]
+pub struct TimelineEntry {
+    pub occurred_at: NaiveDateTime,
+    /// `payment_sheet`, `webhook_event`, `notification` or `refund`.
+    pub kind: &'static str,
+    pub summary: String,
+    /// The id of the underlying `payment_events`, `notification_deliveries` or `refunds` row.
+    pub source_id: Uuid,
+    pub details: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentTimelineResponse {
+    pub payment_intent_id: String,
+    /// Oldest first.
+    pub entries: Vec<TimelineEntry>,
+}
+
+impl From<PaymentEvent> for TimelineEntry {
+    fn from(event: PaymentEvent) -> Self {
+        let (kind, summary) = if event.status == PaymentEvent::SHEET_CREATED {
+            ("payment_sheet", "PaymentSheet created".to_string())
+        } else {
+            ("webhook_event", format!("Payment intent {}", event.status))
+        };
+        Self {
+            occurred_at: event.created_at,
+            kind,
+            summary,
+            source_id: event.id,
+            details: json!({
+                "status": event.status,
+                "amount": event.amount,
+                "currency": event.currency,
+                "customer_id": event.customer_id,
+            }),
+        }
+    }
+}
+
+impl From<NotificationDelivery> for TimelineEntry {
+    fn from(delivery: NotificationDelivery) -> Self {
+        Self {
+            occurred_at: delivery.created_at,
+            kind: "notification",
+            summary: format!(
+                "{} {} {}",
+                delivery.channel, delivery.template, delivery.outcome
+            ),
+            source_id: delivery.id,
+            details: json!({
+                "channel": delivery.channel,
+                "target": delivery.target,
+                "template": delivery.template,
+                "outcome": delivery.outcome,
+                "error": delivery.error,
+                "attempt": delivery.attempt,
+                "latency_ms": delivery.latency_ms,
+            }),
+        }
+    }
+}
+
+impl From<Refund> for TimelineEntry {
+    fn from(refund: Refund) -> Self {
+        Self {
+            occurred_at: refund.created_at,
+            kind: "refund",
+            summary: format!("Refund {}", refund.status),
+            source_id: refund.id,
+            details: json!({
+                "stripe_refund_id": refund.stripe_refund_id,
+                "amount": refund.amount,
+                "currency": refund.currency,
+                "status": refund.status,
+                "reason": refund.reason,
+                "updated_at": refund.updated_at,
+            }),
+        }
+    }
+}
+
+/// GET /admin/payments/{id}/timeline merges a payment intent's events, notification
+/// deliveries and refunds into one ordered timeline.
+#[tracing::instrument(skip(state))]
+pub async fn payment_timeline_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(intent_id): Path<String>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{notification_deliveries, payment_events, refunds};
+
+    let mut conn = conn_from_state(&state).await?;
+    let events = payment_events::table
+        .filter(payment_events::payment_intent_id.eq(&intent_id))
+        .load::<PaymentEvent>(&mut conn)
+        .map_err(db_error("Failed to load payment events"))?;
+    let deliveries = notification_deliveries::table
+        .filter(notification_deliveries::payment_intent_id.eq(&intent_id))
+        .load::<NotificationDelivery>(&mut conn)
+        .map_err(db_error("Failed to load notification deliveries"))?;
+    let refund_rows = refunds::table
+        .filter(refunds::payment_intent_id.eq(&intent_id))
+        .load::<Refund>(&mut conn)
+        .map_err(db_error("Failed to load refunds"))?;
+
+    let mut entries: Vec<TimelineEntry> = events
+        .into_iter()
+        .map(TimelineEntry::from)
+        .chain(deliveries.into_iter().map(TimelineEntry::from))
+        .chain(refund_rows.into_iter().map(TimelineEntry::from))
+        .collect();
+    if entries.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Payment not found".to_string()));
+    }
+    // Stable, so rows sharing a timestamp keep their source order
+    entries.sort_by_key(|entry| entry.occurred_at);
+
+    Ok(axum::Json(json!(PaymentTimelineResponse {
+        payment_intent_id: intent_id,
+        entries,
+    })))
+}
@@ -0,0 +1,307 @@
+//! Authorized pickups.
+//!
+//! Families list the adults allowed to pick a camper up, per registration. A
+//! check-out must name one of them (see [`crate::attendance::record_attendance`]),
+//! and the adult is stored with the check-out event, so the session pickup log shows
+//! who collected each camper each day. Removing an adult only stops future
+//! check-outs; past events still name them.
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{AttendanceEvent, AuthorizedPickup, Camper, NewAuthorizedPickup, Registration},
+};
+use crate::registrations::load_registration;
+use crate::sessions::load_session;
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+pub const MAX_PICKUPS_PER_REGISTRATION: usize = 10;
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_RELATIONSHIP_LENGTH: usize = 50;
+const MAX_ID_NOTE_LENGTH: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct AddPickupRequest {
+    pub name: String,
+    pub relationship: String,
+    pub photo_url: Option<String>,
+    pub id_note: Option<String>,
+}
+
+impl AddPickupRequest {
+    /// Trims the request, dropping blank optional fields, and checks each field.
+    pub fn validate(self) -> Result<AddPickupRequest, String> {
+        let name = self.name.trim().to_string();
+        let relationship = self.relationship.trim().to_string();
+        let photo_url = self
+            .photo_url
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty());
+        let id_note = self
+            .id_note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty());
+
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "name must be between 1 and {MAX_NAME_LENGTH} characters"
+            ));
+        }
+        if relationship.is_empty() || relationship.chars().count() > MAX_RELATIONSHIP_LENGTH {
+            return Err(format!(
+                "relationship must be between 1 and {MAX_RELATIONSHIP_LENGTH} characters"
+            ));
+        }
+        if let Some(url) = &photo_url {
+            if !url.starts_with("https://") || url.len() <= "https://".len() {
+                return Err("photo_url must be an https URL".to_string());
+            }
+        }
+        if id_note
+            .as_ref()
+            .is_some_and(|note| note.chars().count() > MAX_ID_NOTE_LENGTH)
+        {
+            return Err(format!(
+                "id_note must be at most {MAX_ID_NOTE_LENGTH} characters"
+            ));
+        }
+        Ok(AddPickupRequest {
+            name,
+            relationship,
+            photo_url,
+            id_note,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PickupListResponse {
+    pub registration_id: Uuid,
+    pub pickups: Vec<AuthorizedPickup>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PickupLogQuery {
+    /// Defaults to today (UTC).
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PickupLogEntry {
+    pub registration_id: Uuid,
+    pub camper_id: Uuid,
+    pub camper_first_name: String,
+    pub camper_last_name: String,
+    pub checked_out_at: NaiveDateTime,
+    /// Absent for check-outs recorded before pickups were required.
+    pub picked_up_by: Option<AuthorizedPickup>,
+    pub source: String,
+    pub recorded_by: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PickupLogResponse {
+    pub session_id: Uuid,
+    pub session_name: String,
+    pub date: NaiveDate,
+    /// Check-outs in time order.
+    pub pickups: Vec<PickupLogEntry>,
+}
+
+/// Active pickups for a registration, oldest first.
+fn active_pickups(
+    conn: &mut PgConnection,
+    registration: Uuid,
+) -> Result<Vec<AuthorizedPickup>, diesel::result::Error> {
+    use crate::database::schema::authorized_pickups;
+
+    authorized_pickups::table
+        .filter(authorized_pickups::registration_id.eq(registration))
+        .filter(authorized_pickups::removed_at.is_null())
+        .order(authorized_pickups::created_at.asc())
+        .load::<AuthorizedPickup>(conn)
+}
+
+/// GET /registrations/{id}/pickups lists the adults allowed to pick the camper up.
+#[tracing::instrument(skip(state))]
+pub async fn list_pickups_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    let pickups = active_pickups(&mut conn, registration.id)
+        .map_err(db_error("Failed to load authorized pickups"))?;
+    Ok(axum::Json(json!(PickupListResponse {
+        registration_id: registration.id,
+        pickups,
+    })))
+}
+
+/// POST /registrations/{id}/pickups adds an adult to the authorized pickup list.
+#[tracing::instrument(skip(state, payload))]
+pub async fn add_pickup_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+    Json(payload): Json<AddPickupRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let payload = payload
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    if registration.status == "cancelled" {
+        return Err((
+            StatusCode::CONFLICT,
+            "Registration is cancelled".to_string(),
+        ));
+    }
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Lock the registration so concurrent adds cannot exceed the limit
+        crate::database::schema::registrations::table
+            .find(registration.id)
+            .for_update()
+            .first::<Registration>(conn)?;
+        if active_pickups(conn, registration.id)?.len() >= MAX_PICKUPS_PER_REGISTRATION {
+            return Ok(None);
+        }
+        diesel::insert_into(crate::database::schema::authorized_pickups::table)
+            .values(&NewAuthorizedPickup {
+                registration_id: registration.id,
+                name: payload.name,
+                relationship: payload.relationship,
+                photo_url: payload.photo_url,
+                id_note: payload.id_note,
+                added_by: actor.subject_id,
+            })
+            .get_result::<AuthorizedPickup>(conn)
+            .map(Some)
+    });
+    let pickup = result
+        .map_err(db_error("Failed to add authorized pickup"))?
+        .ok_or((
+            StatusCode::CONFLICT,
+            format!("At most {MAX_PICKUPS_PER_REGISTRATION} authorized pickups per registration"),
+        ))?;
+    info!(
+        "Added authorized pickup {} to registration {}",
+        pickup.id, registration.id
+    );
+    Ok(axum::Json(json!(pickup)))
+}
+
+/// DELETE /registrations/{id}/pickups/{pickup_id} removes an adult from the list.
+#[tracing::instrument(skip(state))]
+pub async fn remove_pickup_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path((registration_id, pickup_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    use crate::database::schema::authorized_pickups;
+
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    let updated = diesel::update(
+        authorized_pickups::table
+            .find(pickup_id)
+            .filter(authorized_pickups::registration_id.eq(registration.id))
+            .filter(authorized_pickups::removed_at.is_null()),
+    )
+    .set(authorized_pickups::removed_at.eq(Some(chrono::Utc::now().naive_utc())))
+    .execute(&mut conn)
+    .map_err(db_error("Failed to remove authorized pickup"))?;
+    if updated == 0 {
+        return Err((StatusCode::NOT_FOUND, "Pickup not found".to_string()));
+    }
+    info!("Removed authorized pickup {pickup_id}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /admin/sessions/{id}/pickups?date= lists who picked up each camper in a
+/// session on a day.
+#[tracing::instrument(skip(state))]
+pub async fn pickup_log_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+    Query(query): Query<PickupLogQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{attendance_events, authorized_pickups, campers, registrations};
+
+    let day = query
+        .date
+        .unwrap_or_else(|| chrono::Utc::now().date_naive());
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+
+    let registration_rows = registrations::table
+        .filter(registrations::session_id.eq(session.id))
+        .load::<Registration>(&mut conn)
+        .map_err(db_error("Failed to load registrations"))?;
+    let registration_ids: Vec<Uuid> = registration_rows.iter().map(|r| r.id).collect();
+    let events = attendance_events::table
+        .filter(attendance_events::registration_id.eq_any(&registration_ids))
+        .filter(attendance_events::kind.eq("check_out"))
+        .filter(attendance_events::occurred_at.ge(day.and_time(NaiveTime::MIN)))
+        .filter(
+            attendance_events::occurred_at
+                .lt(day.succ_opt().unwrap_or(day).and_time(NaiveTime::MIN)),
+        )
+        .order(attendance_events::occurred_at.asc())
+        .load::<AttendanceEvent>(&mut conn)
+        .map_err(db_error("Failed to load check-outs"))?;
+
+    let pickup_ids: Vec<Uuid> = events.iter().filter_map(|e| e.picked_up_by).collect();
+    let pickup_rows = authorized_pickups::table
+        .filter(authorized_pickups::id.eq_any(&pickup_ids))
+        .load::<AuthorizedPickup>(&mut conn)
+        .map_err(db_error("Failed to load authorized pickups"))?;
+    let camper_ids: Vec<Uuid> = registration_rows.iter().map(|r| r.camper_id).collect();
+    let camper_rows = campers::table
+        .filter(campers::id.eq_any(&camper_ids))
+        .load::<Camper>(&mut conn)
+        .map_err(db_error("Failed to load campers"))?;
+
+    let pickups = events
+        .into_iter()
+        .filter_map(|event| {
+            let registration = registration_rows
+                .iter()
+                .find(|r| r.id == event.registration_id)?;
+            let camper = camper_rows.iter().find(|c| c.id == registration.camper_id);
+            Some(PickupLogEntry {
+                registration_id: registration.id,
+                camper_id: registration.camper_id,
+                camper_first_name: camper.map(|c| c.first_name.clone()).unwrap_or_default(),
+                camper_last_name: camper.map(|c| c.last_name.clone()).unwrap_or_default(),
+                checked_out_at: event.occurred_at,
+                picked_up_by: event
+                    .picked_up_by
+                    .and_then(|pickup| pickup_rows.iter().find(|p| p.id == pickup).cloned()),
+                source: event.source,
+                recorded_by: event.recorded_by,
+            })
+        })
+        .collect();
+
+    Ok(axum::Json(json!(PickupLogResponse {
+        session_id: session.id,
+        session_name: session.name,
+        date: day,
+        pickups,
+    })))
+}
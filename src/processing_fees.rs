@@ -0,0 +1,216 @@
+//! Processing fee pass-through.
+//!
+//! Camps that pass card processing fees on to families set
+//! `PROCESSING_FEE_PERCENT` (e.g. `2.9`, up to two decimals and at most 10) and/or
+//! `PROCESSING_FEE_FIXED` (`usd:30,eur:25`, in minor units). Quotes then carry a
+//! labelled "Processing fee" line item, and the quote and its receipt show the fee
+//! on its own. The fee grosses up the amount due so that, after Stripe takes its
+//! percentage and fixed fee from the total, the camp receives what it charged:
+//! `total = (due + fixed) / (1 - percent)`.
+//!
+//! Fees are rounded to the currency's minor units: cents for most currencies,
+//! whole units for zero-decimal currencies such as JPY, and tens of the minor unit
+//! for three-decimal currencies, which Stripe requires. `PROCESSING_FEE_ROUNDING`
+//! is `up` (the default, so the camp never falls short) or `nearest`.
+use crate::payment_limits::parse_entries;
+use crate::quotes::LineItem;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+
+/// Largest percentage accepted, in basis points.
+const MAX_PERCENT_BASIS_POINTS: i64 = 1000;
+
+/// Currencies Stripe charges in whole units.
+const ZERO_DECIMAL_CURRENCIES: [&str; 16] = [
+    "bif", "clp", "djf", "gnf", "jpy", "kmf", "krw", "mga", "pyg", "rwf", "ugx", "vnd", "vuv",
+    "xaf", "xof", "xpf",
+];
+/// Currencies with three minor-unit digits, charged in multiples of ten.
+const THREE_DECIMAL_CURRENCIES: [&str; 5] = ["bhd", "jod", "kwd", "omr", "tnd"];
+
+/// Number of digits after the decimal point in a currency's amounts.
+pub fn minor_unit_digits(currency: &str) -> u32 {
+    let currency = currency.to_lowercase();
+    if ZERO_DECIMAL_CURRENCIES.contains(&currency.as_str()) {
+        0
+    } else if THREE_DECIMAL_CURRENCIES.contains(&currency.as_str()) {
+        3
+    } else {
+        2
+    }
+}
+
+/// The smallest step a charge in the currency can take, in minor units.
+pub fn rounding_increment(currency: &str) -> i64 {
+    if minor_unit_digits(currency) == 3 {
+        10
+    } else {
+        1
+    }
+}
+
+/// Prints an amount in minor units as a decimal with the currency code, e.g.
+/// `0.30 USD`.
+pub fn format_minor(amount: i64, currency: &str) -> String {
+    let code = currency.to_uppercase();
+    let digits = minor_unit_digits(currency);
+    if digits == 0 {
+        return format!("{amount} {code}");
+    }
+    let scale = 10_i64.pow(digits);
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.abs();
+    format!(
+        "{sign}{}.{:0width$} {code}",
+        amount / scale,
+        amount % scale,
+        width = digits as usize
+    )
+}
+
+/// How fees are rounded to the currency's increment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRounding {
+    #[default]
+    Up,
+    Nearest,
+}
+
+/// The configured pass-through, loaded at startup. Disabled when neither a
+/// percentage nor a fixed fee is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProcessingFees {
+    /// Percentage in basis points, e.g. 290 for 2.9%.
+    pub percent_basis_points: i64,
+    /// Fixed fee per currency, in minor units.
+    pub fixed: BTreeMap<String, i64>,
+    pub rounding: FeeRounding,
+}
+
+/// Parses a percentage with up to two decimals into basis points.
+fn parse_percent(raw: &str) -> Option<i64> {
+    let (whole, fraction) = raw.split_once('.').unwrap_or((raw, ""));
+    if whole.is_empty() || fraction.len() > 2 {
+        return None;
+    }
+    if !(whole.chars().all(|c| c.is_ascii_digit()) && fraction.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+    let fraction = format!("{fraction:0<2}");
+    Some(whole.parse::<i64>().ok()? * 100 + fraction.parse::<i64>().ok()?)
+}
+
+/// Prints basis points as a percentage without trailing zeros, e.g. `2.9`.
+fn format_percent(basis_points: i64) -> String {
+    let fraction = format!("{:02}", basis_points % 100);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}", basis_points / 100)
+    } else {
+        format!("{}.{fraction}", basis_points / 100)
+    }
+}
+
+impl ProcessingFees {
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_settings(
+            env::var("PROCESSING_FEE_PERCENT").ok().as_deref(),
+            env::var("PROCESSING_FEE_FIXED").ok().as_deref(),
+            env::var("PROCESSING_FEE_ROUNDING").ok().as_deref(),
+        )
+    }
+
+    pub fn from_settings(
+        percent: Option<&str>,
+        fixed: Option<&str>,
+        rounding: Option<&str>,
+    ) -> Result<Self, String> {
+        let percent_basis_points = match percent.map(str::trim).filter(|p| !p.is_empty()) {
+            Some(raw) => parse_percent(raw)
+                .filter(|bps| *bps <= MAX_PERCENT_BASIS_POINTS)
+                .ok_or_else(|| {
+                    format!("Invalid PROCESSING_FEE_PERCENT '{raw}': expected 0 to 10 with up to two decimals")
+                })?,
+            None => 0,
+        };
+        let fixed = parse_entries("PROCESSING_FEE_FIXED", fixed.unwrap_or_default(), |value| {
+            value.parse::<i64>().ok().filter(|fee| *fee >= 0)
+        })?;
+        for (currency, fee) in &fixed {
+            if fee % rounding_increment(currency) != 0 {
+                return Err(format!(
+                    "PROCESSING_FEE_FIXED for {currency} must be a multiple of {}",
+                    rounding_increment(currency)
+                ));
+            }
+        }
+        let rounding = match rounding.map(|r| r.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("up") => FeeRounding::Up,
+            Some("nearest") => FeeRounding::Nearest,
+            Some(other) => {
+                return Err(format!(
+                    "Invalid PROCESSING_FEE_ROUNDING '{other}': expected up or nearest"
+                ))
+            }
+        };
+        Ok(Self {
+            percent_basis_points,
+            fixed,
+            rounding,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.percent_basis_points > 0 || self.fixed.values().any(|fee| *fee > 0)
+    }
+
+    fn fixed_fee(&self, currency: &str) -> i64 {
+        self.fixed
+            .get(&currency.to_lowercase())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The fee added to `due` so the camp receives `due` after processing fees.
+    /// Nothing is added when nothing is due.
+    pub fn fee_for(&self, due: i64, currency: &str) -> i64 {
+        if due <= 0 || !self.is_enabled() {
+            return 0;
+        }
+        let increment = rounding_increment(currency) as i128;
+        let numerator = (due + self.fixed_fee(currency)) as i128 * 10_000;
+        let denominator = (10_000 - self.percent_basis_points) as i128 * increment;
+        let steps = match self.rounding {
+            FeeRounding::Up => (numerator + denominator - 1) / denominator,
+            FeeRounding::Nearest => (2 * numerator + denominator) / (2 * denominator),
+        };
+        ((steps * increment) as i64 - due).max(0)
+    }
+
+    /// The line item's label, naming the percentage and fixed fee charged.
+    pub fn label(&self, currency: &str) -> String {
+        let fixed = self.fixed_fee(currency);
+        let terms = match (self.percent_basis_points > 0, fixed > 0) {
+            (true, true) => format!(
+                "{}% + {}",
+                format_percent(self.percent_basis_points),
+                format_minor(fixed, currency)
+            ),
+            (true, false) => format!("{}%", format_percent(self.percent_basis_points)),
+            (false, _) => format_minor(fixed, currency),
+        };
+        format!("Processing fee ({terms})")
+    }
+
+    /// The fee line item for a checkout with `due` outstanding, if any fee applies.
+    pub fn line_item(&self, due: i64, currency: &str) -> Option<LineItem> {
+        let amount = self.fee_for(due, currency);
+        (amount > 0).then(|| LineItem {
+            label: self.label(currency),
+            amount,
+        })
+    }
+}
@@ -0,0 +1,238 @@
+//! Public session availability for the marketing site's "spots left" badge.
+//!
+//! `GET /public/sessions/availability` needs no token and is safe to embed from any
+//! origin: it returns only session names and a coarse availability bucket, never
+//! seat counts. The response is cached per Lambda instance for
+//! `PUBLIC_AVAILABILITY_CACHE_SECONDS` (default 60) and marked cacheable for
+//! browsers and CDNs for as long. Each client address may call it
+//! `PUBLIC_AVAILABILITY_RATE_LIMIT` times per minute (default 30) per instance;
+//! further calls get 429 with `Retry-After`.
+use crate::database::{conn_from_state, db_error, models::CampSession};
+use crate::holds::seats_taken;
+use axum::{
+    extract::Extension,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const DEFAULT_CACHE_SECONDS: u64 = 60;
+const DEFAULT_RATE_LIMIT: u32 = 30;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Sessions with this share of seats or fewer left are `limited`.
+const LIMITED_SHARE: f64 = 0.2;
+/// Sessions with this many seats or fewer left are `limited`, however large.
+const LIMITED_SEATS: i64 = 5;
+
+/// Coarse availability shown publicly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    Open,
+    Limited,
+    /// No seats, and the session has started so its waitlist is closed.
+    Full,
+    /// No seats, but families can join the waitlist.
+    Waitlist,
+}
+
+impl Availability {
+    fn for_session(session: &CampSession, taken: i64, today: NaiveDate) -> Self {
+        let capacity = i64::from(session.capacity);
+        let left = capacity - taken;
+        if left <= 0 {
+            if session.starts_on > today {
+                Availability::Waitlist
+            } else {
+                Availability::Full
+            }
+        } else if left <= LIMITED_SEATS || (left as f64) <= capacity as f64 * LIMITED_SHARE {
+            Availability::Limited
+        } else {
+            Availability::Open
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicSessionAvailability {
+    pub name: String,
+    pub availability: Availability,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicAvailabilityResponse {
+    pub sessions: Vec<PublicSessionAvailability>,
+}
+
+#[derive(Debug)]
+struct RateWindow {
+    started: Instant,
+    counts: HashMap<String, u32>,
+}
+
+/// The response cache and per-client rate limits, shared across requests.
+#[derive(Debug)]
+pub struct PublicAvailability {
+    cache_ttl: Duration,
+    rate_limit: u32,
+    cached: Mutex<Option<(Instant, Value)>>,
+    window: Mutex<RateWindow>,
+}
+
+fn env_number<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(raw) => raw
+            .trim()
+            .parse::<T>()
+            .map_err(|_| format!("Invalid {name}: {raw}")),
+        Err(_) => Ok(default),
+    }
+}
+
+impl PublicAvailability {
+    /// Loads the cache lifetime and rate limit from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        let cache_seconds = env_number("PUBLIC_AVAILABILITY_CACHE_SECONDS", DEFAULT_CACHE_SECONDS)?;
+        let rate_limit = env_number("PUBLIC_AVAILABILITY_RATE_LIMIT", DEFAULT_RATE_LIMIT)?;
+        if rate_limit == 0 {
+            return Err("PUBLIC_AVAILABILITY_RATE_LIMIT must be at least 1".to_string());
+        }
+        Ok(Self {
+            cache_ttl: Duration::from_secs(cache_seconds),
+            rate_limit,
+            cached: Mutex::new(None),
+            window: Mutex::new(RateWindow {
+                started: Instant::now(),
+                counts: HashMap::new(),
+            }),
+        })
+    }
+
+    /// Counts a call from `client`. Returns the seconds until the window resets when
+    /// the client is over its limit.
+    fn check_rate(&self, client: &str) -> Result<(), u64> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let elapsed = window.started.elapsed();
+        if elapsed >= RATE_WINDOW {
+            window.started = Instant::now();
+            window.counts.clear();
+        }
+        let count = window.counts.entry(client.to_string()).or_default();
+        if *count >= self.rate_limit {
+            return Err(RATE_WINDOW.saturating_sub(elapsed).as_secs().max(1));
+        }
+        *count += 1;
+        Ok(())
+    }
+
+    fn cached(&self) -> Option<Value> {
+        let cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        cached
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < self.cache_ttl)
+            .map(|(_, body)| body.clone())
+    }
+
+    fn store(&self, body: Value) {
+        *self.cached.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), body));
+    }
+
+    fn headers(&self) -> [(header::HeaderName, HeaderValue); 2] {
+        let cache_control = format!("public, max-age={}", self.cache_ttl.as_secs());
+        [
+            (
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            ),
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_str(&cache_control)
+                    .unwrap_or_else(|_| HeaderValue::from_static("no-store")),
+            ),
+        ]
+    }
+}
+
+/// The caller's address as reported by API Gateway, or `unknown`.
+///
+/// API Gateway appends the address it saw to any `X-Forwarded-For` the client sent, so only
+/// the last hop is trustworthy; earlier entries are client-controlled and would let a caller
+/// pick a fresh rate-limit bucket per request.
+fn client_address(headers: &HeaderMap) -> String {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .map(|address| address.trim().to_string())
+        .filter(|address| !address.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Availability of every session that has not ended and is not cancelled.
+fn load_availability(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<PublicAvailabilityResponse, diesel::result::Error> {
+    use crate::database::schema::camp_sessions;
+
+    let sessions = camp_sessions::table
+        .filter(camp_sessions::cancelled_at.is_null())
+        .filter(camp_sessions::ends_on.ge(today))
+        .order((camp_sessions::starts_on.asc(), camp_sessions::name.asc()))
+        .load::<CampSession>(conn)?;
+    let now = chrono::Utc::now().naive_utc();
+    let mut entries = Vec::with_capacity(sessions.len());
+    for session in sessions {
+        let taken = seats_taken(conn, session.id, now)?;
+        entries.push(PublicSessionAvailability {
+            availability: Availability::for_session(&session, taken, today),
+            name: session.name,
+        });
+    }
+    Ok(PublicAvailabilityResponse { sessions: entries })
+}
+
+/// GET /public/sessions/availability returns session names with coarse availability
+/// buckets, for embedding on other sites.
+#[tracing::instrument(skip(state, public, headers))]
+pub async fn public_availability_handler(
+    Extension(state): Extension<Arc<tokio::sync::Mutex<AppState>>>,
+    Extension(public): Extension<Arc<PublicAvailability>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let client = client_address(&headers);
+    if let Err(retry_after) = public.check_rate(&client) {
+        warn!("Rate limited public availability for {client}");
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            public.headers(),
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            "Too many requests".to_string(),
+        )
+            .into_response());
+    }
+
+    let body = match public.cached() {
+        Some(body) => body,
+        None => {
+            let mut conn = conn_from_state(&state).await?;
+            let availability = load_availability(&mut conn, chrono::Utc::now().date_naive())
+                .map_err(db_error("Failed to load session availability"))?;
+            let body = json!(availability);
+            public.store(body.clone());
+            body
+        }
+    };
+    Ok((public.headers(), Json(body)).into_response())
+}
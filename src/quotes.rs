@@ -0,0 +1,437 @@
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::{Actor, Role};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampCredit, CampSession, NewQuote, Quote, Registration},
+};
+use crate::exchange_rates::{approximate_conversions, conversion_note, ConvertedAmount};
+use crate::guardians::{credit_balances, reserved_credit};
+use crate::handlers::parse_currency;
+use crate::holds::hold_ttl;
+use crate::processing_fees::ProcessingFees;
+use crate::waitlist_offers::load_open_offer;
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteRequest {
+    pub guardian_id: Option<Uuid>,
+    /// Pending registrations to price from their sessions' prices.
+    #[serde(default)]
+    pub registration_ids: Vec<Uuid>,
+    /// Free-form amount for checkouts that are not tied to registrations.
+    pub amount: Option<i64>,
+    pub currency: String,
+    #[serde(default)]
+    pub apply_credit: bool,
+    /// A waitlist offer's token; quotes the offered registration while the offer lasts.
+    pub offer_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LineItem {
+    pub label: String,
+    pub amount: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteResponse {
+    pub quote_id: Uuid,
+    pub registration_ids: Vec<Uuid>,
+    pub currency: String,
+    pub line_items: Value,
+    pub subtotal: i64,
+    pub credit_applied: i64,
+    /// Processing fee passed on to the payer, included in `total`.
+    pub processing_fee: i64,
+    /// The amount the PaymentIntent must be created for.
+    pub total: i64,
+    pub converted_totals: Vec<ConvertedAmount>,
+    pub conversion_note: String,
+}
+
+/// Prices the requested registrations (or the free-form amount) as line items.
+/// When `owner` is set, registrations belonging to other guardians are not found.
+fn price_line_items(
+    conn: &mut PgConnection,
+    owner: Option<Uuid>,
+    registration_ids: &[Uuid],
+    amount: Option<i64>,
+    quote_currency: &str,
+) -> Result<Result<Vec<LineItem>, ApiError>, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, registrations};
+
+    if registration_ids.is_empty() {
+        return Ok(match amount {
+            Some(amount) if amount >= 0 => Ok(vec![LineItem {
+                label: "Camp registration".to_string(),
+                amount,
+            }]),
+            _ => Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "Either registration_ids or a non-negative amount is required",
+            )),
+        });
+    }
+
+    let rows = registrations::table
+        .filter(registrations::id.eq_any(registration_ids))
+        .load::<Registration>(conn)?;
+    let session_ids: Vec<Uuid> = rows.iter().map(|r| r.session_id).collect();
+    let sessions = camp_sessions::table
+        .filter(camp_sessions::id.eq_any(&session_ids))
+        .load::<CampSession>(conn)?;
+
+    let mut line_items = Vec::new();
+    for registration_id in registration_ids {
+        let registration = rows
+            .iter()
+            .find(|r| r.id == *registration_id && owner.is_none_or(|g| g == r.guardian_id));
+        let Some(registration) = registration else {
+            return Ok(Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                format!("Registration {registration_id} not found"),
+            )));
+        };
+        if registration.status != "pending" {
+            return Ok(Err(ApiError::new(
+                StatusCode::CONFLICT,
+                ErrorCode::RegistrationNotPayable,
+                format!("Registration {registration_id} is {}", registration.status),
+            )));
+        }
+        let Some(session) = sessions.iter().find(|s| s.id == registration.session_id) else {
+            return Ok(Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                format!("Session for registration {registration_id} not found"),
+            )));
+        };
+        if session.currency != quote_currency {
+            return Ok(Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::CurrencyMismatch,
+                format!("Session {} is priced in {}", session.name, session.currency),
+            )));
+        }
+        line_items.push(LineItem {
+            label: format!("Camp registration: {}", session.name),
+            amount: session.price,
+        });
+    }
+    Ok(Ok(line_items))
+}
+
+/// POST /quote prices a checkout, applying the guardian's camp credit when requested.
+/// Registrations are priced server-side from their sessions; the returned `total` is
+/// the amount the PaymentIntent must be created for, with the quote id in its metadata,
+/// and includes the processing fee when fees are passed on.
+///
+/// Applied credit is only reserved: it is debited by [`redeem_quote_credit`] when the
+/// payment succeeds, and stops counting against the balance after a hold's lifetime,
+/// when [`release_expired_credit`] returns it.
+#[tracing::instrument(skip(state, fees))]
+pub async fn create_quote_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(fees): Extension<Arc<ProcessingFees>>,
+    Json(mut payload): Json<QuoteRequest>,
+) -> Result<axum::Json<Value>, ApiError> {
+    info!("Received quote request: {:?}", payload);
+
+    // Guardians always quote their own registrations against their own credit
+    let owner = match actor.role {
+        Role::Guardian => Some(actor.guardian_id().ok_or((
+            StatusCode::FORBIDDEN,
+            "Guardian token has no guardian".to_string(),
+        ))?),
+        _ => None,
+    };
+    if owner.is_some() {
+        payload.guardian_id = owner;
+    }
+
+    parse_currency(&payload.currency)?;
+    let quote_currency = payload.currency.to_lowercase();
+
+    if payload.apply_credit && payload.guardian_id.is_none() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            "guardian_id is required to apply camp credit",
+        ));
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+
+    if let Some(token) = payload.offer_token.as_deref() {
+        let (offer, registration) = load_open_offer(&mut conn, token)?;
+        if owner.is_some_and(|guardian| guardian != registration.guardian_id) {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Offer not found",
+            ));
+        }
+        if payload.registration_ids.is_empty() {
+            payload.registration_ids = vec![offer.registration_id];
+        } else if payload.registration_ids != [offer.registration_id] {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                "registration_ids must only contain the offered registration",
+            ));
+        }
+        payload.guardian_id.get_or_insert(registration.guardian_id);
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let mut line_items = match price_line_items(
+            conn,
+            owner,
+            &payload.registration_ids,
+            payload.amount,
+            &quote_currency,
+        )? {
+            Ok(line_items) => line_items,
+            Err(rejection) => return Ok(Err(rejection)),
+        };
+        let subtotal: i64 = line_items.iter().map(|item| item.amount).sum();
+
+        let mut credit_applied = 0;
+        if let (true, Some(guardian)) = (payload.apply_credit, payload.guardian_id) {
+            // Lock the guardian row so concurrent quotes cannot reserve the same credit
+            crate::database::schema::guardians::table
+                .find(guardian)
+                .for_update()
+                .select(crate::database::schema::guardians::id)
+                .first::<Uuid>(conn)?;
+
+            let balance = credit_balances(conn, guardian)?
+                .get(&quote_currency)
+                .copied()
+                .unwrap_or(0);
+            let available = balance - reserved_credit(conn, guardian, &quote_currency, now)?;
+            credit_applied = available.clamp(0, subtotal);
+            if credit_applied > 0 {
+                line_items.push(LineItem {
+                    label: "Camp credit".to_string(),
+                    amount: -credit_applied,
+                });
+            }
+        }
+
+        // The fee covers processing of what is left to pay after credit
+        let processing_fee = fees.fee_for(subtotal - credit_applied, &quote_currency);
+        line_items.extend(fees.line_item(subtotal - credit_applied, &quote_currency));
+
+        let quote = NewQuote {
+            id: Uuid::new_v4(),
+            guardian_id: payload.guardian_id,
+            currency: quote_currency.clone(),
+            subtotal,
+            credit_applied,
+            total: subtotal - credit_applied + processing_fee,
+            line_items: json!(line_items),
+            registration_ids: payload.registration_ids.clone(),
+            processing_fee,
+            credit_status: if credit_applied > 0 {
+                "reserved"
+            } else {
+                "none"
+            }
+            .to_string(),
+            credit_expires_at: (credit_applied > 0).then(|| now + hold_ttl()),
+        };
+        diesel::insert_into(crate::database::schema::quotes::table)
+            .values(&quote)
+            .execute(conn)?;
+
+        Ok(Ok(quote))
+    });
+    let quote = result.map_err(db_error("Failed to create quote"))??;
+
+    info!(
+        "Created quote {} with total {} {}",
+        quote.id, quote.total, quote.currency
+    );
+
+    let converted_totals = approximate_conversions(&mut conn, quote.total, &quote.currency).await;
+
+    Ok(axum::Json(json!(QuoteResponse {
+        quote_id: quote.id,
+        conversion_note: conversion_note(&quote.currency),
+        registration_ids: quote.registration_ids,
+        currency: quote.currency,
+        line_items: quote.line_items,
+        subtotal: quote.subtotal,
+        credit_applied: quote.credit_applied,
+        processing_fee: quote.processing_fee,
+        total: quote.total,
+        converted_totals,
+    })))
+}
+
+/// Prices a guardian's pending registrations without spending camp credit, for
+/// checkouts paid by someone other than the guardian. Nothing is stored.
+pub fn price_registrations(
+    conn: &mut PgConnection,
+    fees: &ProcessingFees,
+    guardian: Uuid,
+    registration_ids: &[Uuid],
+    quote_currency: &str,
+) -> Result<Result<NewQuote, ApiError>, diesel::result::Error> {
+    let mut line_items =
+        match price_line_items(conn, Some(guardian), registration_ids, None, quote_currency)? {
+            Ok(line_items) => line_items,
+            Err(rejection) => return Ok(Err(rejection)),
+        };
+    let subtotal: i64 = line_items.iter().map(|item| item.amount).sum();
+    let processing_fee = fees.fee_for(subtotal, quote_currency);
+    line_items.extend(fees.line_item(subtotal, quote_currency));
+
+    let quote = NewQuote {
+        id: Uuid::new_v4(),
+        guardian_id: Some(guardian),
+        currency: quote_currency.to_string(),
+        subtotal,
+        credit_applied: 0,
+        total: subtotal + processing_fee,
+        line_items: json!(line_items),
+        registration_ids: registration_ids.to_vec(),
+        processing_fee,
+        credit_status: "none".to_string(),
+        credit_expires_at: None,
+    };
+    Ok(Ok(quote))
+}
+
+/// [`price_registrations`], storing the quote for the checkout.
+pub fn quote_registrations(
+    conn: &mut PgConnection,
+    fees: &ProcessingFees,
+    guardian: Uuid,
+    registration_ids: &[Uuid],
+    quote_currency: &str,
+) -> Result<Result<NewQuote, ApiError>, diesel::result::Error> {
+    let quote = match price_registrations(conn, fees, guardian, registration_ids, quote_currency)? {
+        Ok(quote) => quote,
+        Err(rejection) => return Ok(Err(rejection)),
+    };
+    diesel::insert_into(crate::database::schema::quotes::table)
+        .values(&quote)
+        .execute(conn)?;
+    Ok(Ok(quote))
+}
+
+/// The outcome of redeeming a quote's camp credit once its payment succeeded.
+#[derive(Debug)]
+pub enum CreditRedemption {
+    /// The quote applied no credit, or it was already redeemed.
+    Nothing,
+    /// The debit written for the credit.
+    Redeemed(CampCredit),
+    /// The reservation had lapsed and the guardian no longer has the credit, so
+    /// nothing was debited and the payment needs review.
+    Shortfall { applied: i64, available: i64 },
+}
+
+/// Debits the credit a quote reserved, once its payment has succeeded. A reservation
+/// that already lapsed is debited only if the guardian's available balance still
+/// covers it, since the credit may have gone to another quote in the meantime;
+/// otherwise the quote is marked `shortfall` and nothing is debited.
+pub fn redeem_quote_credit(
+    conn: &mut PgConnection,
+    quote_id: Uuid,
+) -> Result<CreditRedemption, diesel::result::Error> {
+    use crate::database::schema::{camp_credits, guardians, quotes};
+
+    conn.transaction(|conn| {
+        let quote = quotes::table
+            .find(quote_id)
+            .for_update()
+            .first::<Quote>(conn)
+            .optional()?;
+        let Some(quote) = quote else {
+            return Ok(CreditRedemption::Nothing);
+        };
+        let Some(guardian) = quote.guardian_id else {
+            return Ok(CreditRedemption::Nothing);
+        };
+        if quote.credit_applied <= 0
+            || !["reserved", "released"].contains(&quote.credit_status.as_str())
+        {
+            return Ok(CreditRedemption::Nothing);
+        }
+
+        // A released credit counts towards the balance again, so check it is still there
+        if quote.credit_status == "released" {
+            guardians::table
+                .find(guardian)
+                .for_update()
+                .select(guardians::id)
+                .first::<Uuid>(conn)?;
+            let now = chrono::Utc::now().naive_utc();
+            let balance = credit_balances(conn, guardian)?
+                .get(&quote.currency)
+                .copied()
+                .unwrap_or(0);
+            let available = balance - reserved_credit(conn, guardian, &quote.currency, now)?;
+            if available < quote.credit_applied {
+                diesel::update(quotes::table.find(quote_id))
+                    .set(quotes::credit_status.eq("shortfall"))
+                    .execute(conn)?;
+                return Ok(CreditRedemption::Shortfall {
+                    applied: quote.credit_applied,
+                    available: available.max(0),
+                });
+            }
+        }
+
+        diesel::update(quotes::table.find(quote_id))
+            .set(quotes::credit_status.eq("redeemed"))
+            .execute(conn)?;
+        diesel::insert_into(camp_credits::table)
+            .values(&CampCredit::new(
+                guardian,
+                -quote.credit_applied,
+                quote.currency,
+                "quote_redemption".to_string(),
+                None,
+                Some(quote_id),
+            ))
+            .get_result::<CampCredit>(conn)
+            .map(CreditRedemption::Redeemed)
+    })
+}
+
+/// Releases credit reserved by quotes that were not paid in time, returning it to the
+/// guardians' available balance. Returns the number of quotes released.
+pub fn release_expired_credit(
+    conn: &mut PgConnection,
+    now: NaiveDateTime,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::quotes;
+
+    diesel::update(
+        quotes::table
+            .filter(quotes::credit_status.eq("reserved"))
+            .filter(quotes::credit_expires_at.le(now)),
+    )
+    .set(quotes::credit_status.eq("released"))
+    .execute(conn)
+}
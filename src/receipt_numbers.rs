@@ -0,0 +1,137 @@
+//! Sequential receipt numbers.
+//!
+//! Auditors expect every successful payment to carry a receipt number from an
+//! unbroken sequence per organization. A Postgres sequence would leave gaps when a
+//! transaction rolls back, so the next number lives in a `receipt_sequences` row
+//! that is locked, read and advanced in the same transaction that records the
+//! number: a number is only consumed if the payment's receipt is committed.
+//! Allocation is idempotent per PaymentIntent, so webhook retries reuse the number.
+//! Only the webhook allocates: reading a receipt that has no number yet is a 404.
+//!
+//! `RECEIPT_ORGANIZATION` (default `default`) names the sequence and
+//! `RECEIPT_NUMBER_PREFIX` (default `R`) is printed before the zero-padded number,
+//! e.g. `R-000042`. The printed form is stored with the number, so changing the
+//! prefix later does not rewrite issued receipts.
+use crate::database::models::{NewReceiptNumber, ReceiptNumber};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::env;
+
+const DEFAULT_ORGANIZATION: &str = "default";
+const DEFAULT_PREFIX: &str = "R";
+const MAX_SETTING_LEN: usize = 32;
+
+/// Which sequence receipts are numbered from and how numbers are printed.
+#[derive(Debug, Clone)]
+pub struct ReceiptNumbering {
+    organization: String,
+    prefix: String,
+}
+
+fn setting(name: &str, raw: Option<&str>, default: &str) -> Result<String, String> {
+    let value = raw
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .unwrap_or(default);
+    if value.len() > MAX_SETTING_LEN
+        || !value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid {name} '{value}': expected up to {MAX_SETTING_LEN} letters, digits, '-' or '_'"
+        ));
+    }
+    Ok(value.to_string())
+}
+
+impl ReceiptNumbering {
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_settings(
+            env::var("RECEIPT_ORGANIZATION").ok().as_deref(),
+            env::var("RECEIPT_NUMBER_PREFIX").ok().as_deref(),
+        )
+    }
+
+    pub fn from_settings(organization: Option<&str>, prefix: Option<&str>) -> Result<Self, String> {
+        Ok(Self {
+            organization: setting("RECEIPT_ORGANIZATION", organization, DEFAULT_ORGANIZATION)?,
+            prefix: setting("RECEIPT_NUMBER_PREFIX", prefix, DEFAULT_PREFIX)?,
+        })
+    }
+
+    pub fn organization(&self) -> &str {
+        &self.organization
+    }
+
+    /// The printed form of a number: the prefix and at least six digits.
+    pub fn format(&self, number: i64) -> String {
+        format!("{}-{number:06}", self.prefix)
+    }
+
+    /// Returns the payment's receipt number, allocating the next one in the
+    /// organization's sequence if it has none yet.
+    pub fn allocate(
+        &self,
+        conn: &mut PgConnection,
+        intent_id: &str,
+        paid_amount: i64,
+        paid_currency: &str,
+    ) -> Result<ReceiptNumber, diesel::result::Error> {
+        use crate::database::schema::{receipt_numbers, receipt_sequences};
+
+        conn.transaction(|conn| {
+            diesel::insert_into(receipt_sequences::table)
+                .values(receipt_sequences::organization.eq(&self.organization))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            // Holding the sequence row serializes allocations, including retries of
+            // the same payment
+            let next = receipt_sequences::table
+                .find(&self.organization)
+                .select(receipt_sequences::next_number)
+                .for_update()
+                .first::<i64>(conn)?;
+            let existing = receipt_numbers::table
+                .find(intent_id)
+                .first::<ReceiptNumber>(conn)
+                .optional()?;
+            if let Some(existing) = existing {
+                return Ok(existing);
+            }
+
+            let receipt = diesel::insert_into(receipt_numbers::table)
+                .values(&NewReceiptNumber {
+                    payment_intent_id: intent_id.to_string(),
+                    organization: self.organization.clone(),
+                    number: next,
+                    receipt_number: self.format(next),
+                    amount: paid_amount,
+                    currency: paid_currency.to_lowercase(),
+                })
+                .get_result::<ReceiptNumber>(conn)?;
+            diesel::update(receipt_sequences::table.find(&self.organization))
+                .set((
+                    receipt_sequences::next_number.eq(next + 1),
+                    receipt_sequences::updated_at.eq(chrono::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+            Ok(receipt)
+        })
+    }
+}
+
+/// The receipt numbers of the given payments, keyed by PaymentIntent id.
+pub fn receipt_numbers_for(
+    conn: &mut PgConnection,
+    intent_ids: &[&str],
+) -> Result<HashMap<String, String>, diesel::result::Error> {
+    use crate::database::schema::receipt_numbers::dsl::*;
+
+    Ok(receipt_numbers
+        .filter(payment_intent_id.eq_any(intent_ids.to_vec()))
+        .select((payment_intent_id, receipt_number))
+        .load::<(String, String)>(conn)?
+        .into_iter()
+        .collect())
+}
@@ -0,0 +1,258 @@
+use crate::auth::{Actor, Role};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{Guardian, PaymentEvent, Quote},
+};
+use crate::exchange_rates::{approximate_conversions, conversion_note, ConvertedAmount};
+use crate::payment_metadata::{PaymentMetadata, QUOTE_ID};
+use crate::processing_fees::format_minor;
+use crate::quotes::LineItem;
+use crate::receipt_numbers::receipt_numbers_for;
+use crate::receipt_pdf;
+use crate::tax::{TaxIdentity, TaxReceiptDetails};
+use axum::{
+    extract::{Extension, Path},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Loads the succeeded payment event for a PaymentIntent, mapping a missing row to 404.
+pub fn load_succeeded_payment(
+    conn: &mut PgConnection,
+    intent_id: &str,
+) -> Result<PaymentEvent, (StatusCode, String)> {
+    use crate::database::schema::payment_events::dsl::*;
+
+    payment_events
+        .filter(payment_intent_id.eq(intent_id))
+        .filter(status.ilike(PaymentEvent::SUCCEEDED))
+        .order(created_at.desc())
+        .first::<PaymentEvent>(conn)
+        .optional()
+        .map_err(db_error("Failed to load payment"))?
+        .ok_or((StatusCode::NOT_FOUND, "Payment not found".to_string()))
+}
+
+/// Loads the quote a payment was created from, if its metadata references one.
+pub fn load_payment_quote(
+    conn: &mut PgConnection,
+    payment: &PaymentEvent,
+) -> Result<Option<Quote>, (StatusCode, String)> {
+    let quote_id = payment
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get(QUOTE_ID))
+        .and_then(Value::as_str)
+        .and_then(|id| Uuid::parse_str(id).ok());
+    let Some(quote_id) = quote_id else {
+        return Ok(None);
+    };
+    crate::database::schema::quotes::table
+        .find(quote_id)
+        .first::<Quote>(conn)
+        .optional()
+        .map_err(db_error("Failed to load quote"))
+}
+
+/// The guardian who made a payment: the quote's guardian, or the guardian of the
+/// first registration it paid for.
+pub fn load_payment_guardian(
+    conn: &mut PgConnection,
+    payment: &PaymentEvent,
+    quote: Option<&Quote>,
+) -> Result<Option<Guardian>, (StatusCode, String)> {
+    use crate::database::schema::{guardians, registrations};
+
+    let guardian_id = match quote.and_then(|q| q.guardian_id) {
+        Some(guardian_id) => Some(guardian_id),
+        None => {
+            let raw: HashMap<String, String> = payment
+                .metadata
+                .as_ref()
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+                .unwrap_or_default();
+            let registration_ids = PaymentMetadata::parse(&raw)
+                .unwrap_or_default()
+                .registration_ids;
+            registrations::table
+                .filter(registrations::id.eq_any(&registration_ids))
+                .select(registrations::guardian_id)
+                .first::<Uuid>(conn)
+                .optional()
+                .map_err(db_error("Failed to load registration"))?
+        }
+    };
+    let Some(guardian_id) = guardian_id else {
+        return Ok(None);
+    };
+    guardians::table
+        .find(guardian_id)
+        .first::<Guardian>(conn)
+        .optional()
+        .map_err(db_error("Failed to load guardian"))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceiptResponse {
+    pub payment_intent_id: String,
+    /// Sequential receipt number, e.g. `R-000042`.
+    pub receipt_number: String,
+    pub paid_at: NaiveDateTime,
+    pub amount: i64,
+    pub currency: String,
+    /// The quote's line items, when the payment was made against a quote.
+    pub line_items: Option<Value>,
+    /// Processing fee passed on to the payer, included in `amount`.
+    pub processing_fee: i64,
+    pub converted_amounts: Vec<ConvertedAmount>,
+    pub conversion_note: String,
+    /// Payer and provider details for dependent-care claims, when the payer is known.
+    pub tax: Option<TaxReceiptDetails>,
+}
+
+impl ReceiptResponse {
+    /// The receipt as printed: provider and payer details, then the items and total.
+    pub fn printed_lines(&self) -> Vec<String> {
+        let money = |amount: i64| format_minor(amount, &self.currency);
+        let mut lines = vec![
+            format!("Receipt {}", self.receipt_number),
+            format!("Paid {}", self.paid_at.format("%Y-%m-%d %H:%M UTC")),
+            format!("Payment {}", self.payment_intent_id),
+        ];
+        if let Some(tax) = &self.tax {
+            if let Some(organization) = &tax.organization {
+                lines.push(String::new());
+                lines.push("Provider".to_string());
+                lines.push(organization.legal_name.clone());
+                lines.push(format!("EIN {}", organization.ein));
+                lines.push(organization.address.clone());
+            }
+            lines.push(String::new());
+            lines.push("Paid by".to_string());
+            lines.push(tax.payer_name.clone());
+            if let Some(address) = &tax.billing_address {
+                lines.push(address.line1.clone());
+                lines.extend(address.line2.clone());
+                lines.push(format!(
+                    "{}, {} {}",
+                    address.city, address.region, address.postal_code
+                ));
+                lines.push(address.country.clone());
+            }
+        }
+        let items: Vec<LineItem> = self
+            .line_items
+            .as_ref()
+            .and_then(|items| serde_json::from_value(items.clone()).ok())
+            .unwrap_or_default();
+        if !items.is_empty() {
+            lines.push(String::new());
+            lines.extend(
+                items
+                    .iter()
+                    .map(|item| format!("{}: {}", item.label, money(item.amount))),
+            );
+        }
+        if self.processing_fee > 0 {
+            lines.push(format!("Processing fee: {}", money(self.processing_fee)));
+        }
+        lines.push(String::new());
+        lines.push(format!("Total paid: {}", money(self.amount)));
+        if !self.converted_amounts.is_empty() {
+            lines.extend(self.converted_amounts.iter().map(|converted| {
+                format!(
+                    "Approximately {}",
+                    format_minor(converted.amount, &converted.currency)
+                )
+            }));
+            lines.push(self.conversion_note.clone());
+        }
+        lines
+    }
+}
+
+/// Loads the receipt for a successful payment, once the webhook has numbered it.
+async fn load_receipt(
+    actor: &Actor,
+    state: &Arc<Mutex<AppState>>,
+    identity: &TaxIdentity,
+    intent_id: &str,
+) -> Result<ReceiptResponse, (StatusCode, String)> {
+    let mut conn = conn_from_state(state).await?;
+    let payment = load_succeeded_payment(&mut conn, intent_id)?;
+    let quote = load_payment_quote(&mut conn, &payment)?;
+    let guardian = load_payment_guardian(&mut conn, &payment, quote.as_ref())?;
+
+    // Guardians may only see receipts for their own payments
+    if actor.role == Role::Guardian && guardian.as_ref().map(|g| g.id) != actor.guardian_id() {
+        return Err((StatusCode::NOT_FOUND, "Payment not found".to_string()));
+    }
+
+    // Numbers are only allocated by the webhook, so reading a receipt never consumes one
+    let receipt_number = receipt_numbers_for(&mut conn, &[payment.payment_intent_id.as_str()])
+        .map_err(db_error("Failed to load receipt number"))?
+        .remove(&payment.payment_intent_id)
+        .ok_or((StatusCode::NOT_FOUND, "Receipt not issued yet".to_string()))?;
+
+    let amount = payment.amount.unwrap_or_default();
+    let currency = payment.currency.clone().unwrap_or_default().to_lowercase();
+    let converted_amounts = approximate_conversions(&mut conn, amount, &currency).await;
+
+    Ok(ReceiptResponse {
+        payment_intent_id: payment.payment_intent_id,
+        receipt_number,
+        paid_at: payment.created_at,
+        amount,
+        processing_fee: quote.as_ref().map_or(0, |q| q.processing_fee),
+        line_items: quote.map(|q| q.line_items),
+        converted_amounts,
+        conversion_note: conversion_note(&currency),
+        currency,
+        tax: guardian.map(|g| TaxReceiptDetails::new(identity, &g)),
+    })
+}
+
+/// GET /receipts/{payment_intent_id} returns an itemized receipt for a successful payment,
+/// once the webhook has numbered it.
+#[tracing::instrument(skip(state, identity))]
+pub async fn receipt_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(identity): Extension<Arc<TaxIdentity>>,
+    Path(intent_id): Path<String>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let receipt = load_receipt(&actor, &state, &identity, &intent_id).await?;
+    Ok(axum::Json(json!(receipt)))
+}
+
+/// GET /receipts/{payment_intent_id}/pdf returns the same receipt as a printable PDF.
+#[tracing::instrument(skip(state, identity))]
+pub async fn receipt_pdf_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(identity): Extension<Arc<TaxIdentity>>,
+    Path(intent_id): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let receipt = load_receipt(&actor, &state, &identity, &intent_id).await?;
+    let disposition = format!(
+        "inline; filename=\"receipt-{}.pdf\"",
+        receipt.receipt_number
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        receipt_pdf::render(&receipt.printed_lines()),
+    )
+        .into_response())
+}
@@ -0,0 +1,149 @@
+//! Redaction of Stripe data before it is logged or persisted.
+//!
+//! Stripe objects can carry payment method details (card brand, last four digits,
+//! expiry, billing details, wallet data), which must not reach logs. Wrap them in
+//! [`Redacted`] wherever they are logged: its `Debug`, `Display` and `Serialize`
+//! output keep only an allowlist of fields (ids, status, amounts) and drop
+//! everything else. Free-form metadata is persisted through [`scrub_metadata`], which
+//! masks anything that looks like a card number.
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Fields kept from Stripe objects. Nested objects under these keys are redacted too.
+const ALLOWED_FIELDS: &[&str] = &[
+    "id",
+    "object",
+    "type",
+    "status",
+    "amount",
+    "amount_capturable",
+    "amount_received",
+    "amount_refunded",
+    "currency",
+    "created",
+    "livemode",
+    "customer",
+    "payment_intent",
+    "charge",
+    "data",
+];
+
+const MASK: &str = "[REDACTED]";
+
+/// Keeps only [`ALLOWED_FIELDS`], recursing into allowed nested objects such as an
+/// event's `data.object`.
+pub fn redact_value(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| ALLOWED_FIELDS.contains(&key.as_str()))
+                .map(|(key, child)| (key.clone(), redact_value(child)))
+                .collect::<Map<String, Value>>(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact_value).collect()),
+        other => other.clone(),
+    }
+}
+
+/// A Stripe object (or any serializable value) reduced to its allowlisted fields.
+pub struct Redacted<'a, T: Serialize>(pub &'a T);
+
+impl<T: Serialize> Redacted<'_, T> {
+    fn value(&self) -> Value {
+        serde_json::to_value(self.0)
+            .map(|v| redact_value(&v))
+            .unwrap_or_else(|_| Value::String(MASK.to_string()))
+    }
+}
+
+impl<T: Serialize> fmt::Display for Redacted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+impl<T: Serialize> fmt::Debug for Redacted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl<T: Serialize> Serialize for Redacted<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value().serialize(serializer)
+    }
+}
+
+/// A raw webhook payload reduced to its allowlisted fields, for logging. Payloads
+/// that are not JSON are described by their length only.
+pub fn redact_payload(payload: &[u8]) -> String {
+    match serde_json::from_slice::<Value>(payload) {
+        Ok(value) => redact_value(&value).to_string(),
+        Err(_) => format!("<{} bytes, not JSON>", payload.len()),
+    }
+}
+
+/// Whether `digits` passes the Luhn check used by card numbers.
+fn luhn_valid(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                *d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Whether `text` contains a 13 to 19 digit run (ignoring spaces and dashes) that
+/// passes the Luhn check.
+fn contains_card_number(text: &str) -> bool {
+    let mut digits: Vec<u32> = Vec::new();
+    let check = |digits: &mut Vec<u32>| {
+        let found = (13..=19).contains(&digits.len()) && luhn_valid(digits);
+        digits.clear();
+        found
+    };
+    for c in text.chars() {
+        match c {
+            '0'..='9' => digits.push(c.to_digit(10).unwrap_or_default()),
+            ' ' | '-' => {}
+            _ => {
+                if check(&mut digits) {
+                    return true;
+                }
+            }
+        }
+    }
+    check(&mut digits)
+}
+
+/// PaymentIntent metadata as JSON for persisting, with values that look like card
+/// numbers masked.
+pub fn scrub_metadata(metadata: &HashMap<String, String>) -> Value {
+    Value::Object(
+        metadata
+            .iter()
+            .map(|(key, value)| {
+                let value = if contains_card_number(value) {
+                    MASK.to_string()
+                } else {
+                    value.clone()
+                };
+                (key.clone(), Value::String(value))
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,128 @@
+//! How much of a registration's price is refunded when it is cancelled.
+//!
+//! When the camp cancels, families get everything back. When a guardian cancels,
+//! the refund depends on how many days remain before the session starts, per the
+//! tiers in `REFUND_POLICY_TIERS`: comma-separated `DAYS:PERCENT` entries, where a
+//! cancellation at least `DAYS` days before the start is refunded `PERCENT` percent.
+//! Without it, 30 or more days out refunds 100%, 14 or more refunds 50%, and later
+//! cancellations refund nothing.
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::env;
+
+const DEFAULT_TIERS: &str = "30:100,14:50,0:0";
+
+/// Who cancelled the registration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancellationCause {
+    /// The camp cancelled the session.
+    Camp,
+    /// The guardian withdrew the registration.
+    Guardian,
+}
+
+impl CancellationCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CancellationCause::Camp => "camp",
+            CancellationCause::Guardian => "guardian",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct RefundTier {
+    min_days_before: i64,
+    percent: i64,
+}
+
+/// The refund owed for one cancelled registration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RefundDecision {
+    pub cause: CancellationCause,
+    pub days_before_start: i64,
+    pub percent: i64,
+    /// In the smallest unit of the paid currency, rounded down.
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefundPolicy {
+    /// Sorted by `min_days_before`, longest notice first.
+    tiers: Vec<RefundTier>,
+}
+
+impl RefundPolicy {
+    /// Parses `DAYS:PERCENT` tiers, such as `30:100,14:50,0:0`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut tiers = spec
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let invalid = || format!("Invalid refund tier '{entry}': expected DAYS:PERCENT");
+                let (days, percent) = entry.trim().split_once(':').ok_or_else(invalid)?;
+                let min_days_before = days
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|d| *d >= 0)
+                    .ok_or_else(invalid)?;
+                let percent = percent
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|p| (0..=100).contains(p))
+                    .ok_or_else(invalid)?;
+                Ok(RefundTier {
+                    min_days_before,
+                    percent,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if tiers.is_empty() {
+            return Err("Refund policy has no tiers".to_string());
+        }
+        tiers.sort_by_key(|tier| std::cmp::Reverse(tier.min_days_before));
+        Ok(Self { tiers })
+    }
+
+    /// The policy configured in `REFUND_POLICY_TIERS`, or the default tiers.
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("REFUND_POLICY_TIERS") {
+            Ok(spec) if !spec.trim().is_empty() => Self::parse(&spec),
+            _ => Self::parse(DEFAULT_TIERS),
+        }
+    }
+
+    /// Percent refunded to a guardian cancelling `days_before_start` days out.
+    fn guardian_percent(&self, days_before_start: i64) -> i64 {
+        self.tiers
+            .iter()
+            .find(|tier| days_before_start >= tier.min_days_before)
+            .map(|tier| tier.percent)
+            .unwrap_or(0)
+    }
+
+    /// The refund owed on `paid` for a registration in a session starting on
+    /// `starts_on`, cancelled on `today`.
+    pub fn decide(
+        &self,
+        cause: CancellationCause,
+        paid: i64,
+        starts_on: NaiveDate,
+        today: NaiveDate,
+    ) -> RefundDecision {
+        let days_before_start = (starts_on - today).num_days();
+        let percent = match cause {
+            CancellationCause::Camp => 100,
+            CancellationCause::Guardian => self.guardian_percent(days_before_start),
+        };
+        RefundDecision {
+            cause,
+            days_before_start,
+            percent,
+            amount: paid.max(0) * percent / 100,
+        }
+    }
+}
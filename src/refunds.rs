@@ -0,0 +1,60 @@
+//! Refund bookkeeping.
+//!
+//! Refunds are issued in the Stripe dashboard or by the API, and reported back
+//! through `charge.refunded` and `charge.refund.updated` webhooks. Each one is
+//! recorded in `refunds`, keyed by its Stripe id, so replays and later status changes
+//! update the same row.
+use crate::database::models::NewRefund;
+use diesel::prelude::*;
+use stripe::{Charge, Refund};
+
+/// Records a refund, or updates the status of one already recorded.
+pub fn record_refund(
+    conn: &mut PgConnection,
+    refund: &Refund,
+    payment_intent_id: &str,
+) -> Result<(), diesel::result::Error> {
+    use crate::database::schema::refunds;
+
+    let new_refund = NewRefund {
+        stripe_refund_id: refund.id.to_string(),
+        payment_intent_id: payment_intent_id.to_string(),
+        charge_id: refund.charge.as_ref().map(|c| c.id().to_string()),
+        amount: refund.amount,
+        currency: refund.currency.to_string(),
+        status: refund
+            .status
+            .clone()
+            .unwrap_or_else(|| "pending".to_string()),
+        reason: refund.reason.map(|r| r.to_string()),
+        failure_reason: refund.failure_reason.clone(),
+    };
+    diesel::insert_into(refunds::table)
+        .values(&new_refund)
+        .on_conflict(refunds::stripe_refund_id)
+        .do_update()
+        .set((
+            refunds::status.eq(&new_refund.status),
+            refunds::failure_reason.eq(&new_refund.failure_reason),
+            refunds::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Records every refund listed on a refunded charge. Returns how many were recorded.
+pub fn record_charge_refunds(
+    conn: &mut PgConnection,
+    charge: &Charge,
+) -> Result<usize, diesel::result::Error> {
+    let Some(payment_intent_id) = charge.payment_intent.as_ref().map(|p| p.id().to_string()) else {
+        return Ok(0);
+    };
+    let listed = charge.refunds.as_ref().map(|l| l.data.as_slice());
+    let mut recorded = 0;
+    for refund in listed.unwrap_or_default() {
+        record_refund(conn, refund, &payment_intent_id)?;
+        recorded += 1;
+    }
+    Ok(recorded)
+}
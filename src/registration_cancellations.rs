@@ -0,0 +1,285 @@
+//! Guardian self-service cancellation.
+//!
+//! A guardian first asks for `POST /me/registrations/{id}/cancel_preview`, which
+//! shows the refund the [`RefundPolicy`] gives for cancelling today, then confirms
+//! with `POST /me/registrations/{id}/cancel`, echoing the previewed refund. If the
+//! refund has changed in between (the date moved into another tier, or a refund went
+//! out elsewhere) the cancel is refused so the guardian can preview again. Confirmed
+//! cancellations free the seat, tell the family first on the waitlist, and go
+//! through the same refund pipeline as session cancellations.
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampSession, CancellationRefund, Guardian, Registration},
+};
+use crate::events::{DomainEvent, EventRecorder, REGISTRATION_CANCELLED};
+use crate::holds::release_holds;
+use crate::notifications::{dispatch_pending, enqueue, Channel, Notification};
+use crate::refund_policy::{CancellationCause, RefundPolicy};
+use crate::session_cancellations::{
+    plan_refund, process_refund, quote_refund, RefundQuote, OPEN_STATUSES,
+};
+use crate::stripe_keys::StripeKeyring;
+use crate::waitlist::notify_next_in_line;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmCancellationRequest {
+    /// `total_refund` from the preview the guardian accepted.
+    pub expected_refund: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancellationPreview {
+    pub registration_id: Uuid,
+    pub session_id: Uuid,
+    pub session_name: String,
+    pub starts_on: NaiveDate,
+    pub days_before_start: i64,
+    pub refund_percent: i64,
+    /// Refunded to the card used at checkout.
+    pub card_refund: i64,
+    /// Returned as camp credit where the card cannot take it.
+    pub credit_refund: i64,
+    pub total_refund: i64,
+    pub currency: String,
+}
+
+impl CancellationPreview {
+    fn new(registration: &Registration, session: &CampSession, quote: &RefundQuote) -> Self {
+        Self {
+            registration_id: registration.id,
+            session_id: session.id,
+            session_name: session.name.clone(),
+            starts_on: session.starts_on,
+            days_before_start: quote.decision.days_before_start,
+            refund_percent: quote.decision.percent,
+            card_refund: quote.card_amount,
+            credit_refund: quote.credit_amount,
+            total_refund: quote.total(),
+            currency: session.currency.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistrationCancelledResponse {
+    pub registration: Registration,
+    pub refund: CancellationRefund,
+}
+
+fn load_policy() -> Result<RefundPolicy, (StatusCode, String)> {
+    RefundPolicy::from_env().map_err(|e| {
+        error!("Invalid refund policy: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, e)
+    })
+}
+
+/// A registration the actor may cancel and its session, or why it cannot be cancelled.
+type Cancellable = Result<(Registration, CampSession), (StatusCode, String)>;
+
+/// Loads a registration the actor may cancel, and its session, rejecting anything
+/// that can no longer be cancelled. Other families' registrations read as missing.
+fn load_cancellable(
+    conn: &mut PgConnection,
+    actor: &Actor,
+    registration_id: Uuid,
+    today: NaiveDate,
+) -> Result<Cancellable, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, registrations};
+
+    let registration = registrations::table
+        .find(registration_id)
+        .for_update()
+        .first::<Registration>(conn)
+        .optional()?
+        .filter(|r| actor.guardian_id() == Some(r.guardian_id));
+    let Some(registration) = registration else {
+        return Ok(Err((
+            StatusCode::NOT_FOUND,
+            "Registration not found".to_string(),
+        )));
+    };
+    if !OPEN_STATUSES.contains(&registration.status.as_str()) {
+        return Ok(Err((
+            StatusCode::CONFLICT,
+            format!("Registration is {}", registration.status),
+        )));
+    }
+    let session = camp_sessions::table
+        .find(registration.session_id)
+        .first::<CampSession>(conn)?;
+    if session.starts_on <= today {
+        return Ok(Err((
+            StatusCode::CONFLICT,
+            "Session has already started".to_string(),
+        )));
+    }
+    Ok(Ok((registration, session)))
+}
+
+/// POST /me/registrations/{id}/cancel_preview shows the refund for cancelling today.
+#[tracing::instrument(skip(state))]
+pub async fn cancel_preview_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let policy = load_policy()?;
+    let today = chrono::Utc::now().date_naive();
+
+    let mut conn = conn_from_state(&state).await?;
+    // Read-only, but the row lock keeps the preview consistent with a concurrent cancel
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let (registration, session) = match load_cancellable(conn, &actor, registration_id, today)?
+        {
+            Ok(loaded) => loaded,
+            Err(rejection) => return Ok(Err(rejection)),
+        };
+        let quote = quote_refund(
+            conn,
+            &policy,
+            CancellationCause::Guardian,
+            &session,
+            &registration,
+            today,
+        )?;
+        Ok(Ok(CancellationPreview::new(
+            &registration,
+            &session,
+            &quote,
+        )))
+    });
+    let preview = result.map_err(db_error("Failed to preview cancellation"))??;
+    Ok(axum::Json(json!(preview)))
+}
+
+/// POST /me/registrations/{id}/cancel cancels the registration for the previewed refund.
+#[tracing::instrument(skip(state, keyring))]
+pub async fn cancel_registration_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(keyring): Extension<Arc<StripeKeyring>>,
+    Path(registration_id): Path<Uuid>,
+    Json(payload): Json<ConfirmCancellationRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{guardians, registrations};
+
+    let policy = load_policy()?;
+    let now = chrono::Utc::now().naive_utc();
+
+    let mut conn = conn_from_state(&state).await?;
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let (registration, session) =
+            match load_cancellable(conn, &actor, registration_id, now.date())? {
+                Ok(loaded) => loaded,
+                Err(rejection) => return Ok(Err(rejection)),
+            };
+        let quote = quote_refund(
+            conn,
+            &policy,
+            CancellationCause::Guardian,
+            &session,
+            &registration,
+            now.date(),
+        )?;
+        if quote.total() != payload.expected_refund {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                format!(
+                    "The refund is now {} {}; preview the cancellation again",
+                    quote.total(),
+                    session.currency
+                ),
+            )));
+        }
+
+        let registration = diesel::update(registrations::table.find(registration.id))
+            .set((
+                registrations::status.eq("cancelled"),
+                registrations::updated_at.eq(now),
+            ))
+            .get_result::<Registration>(conn)?;
+        release_holds(conn, &[registration.id], now)?;
+        let refund = plan_refund(conn, None, &session, &registration, quote)?;
+        EventRecorder::for_actor(&actor).record(
+            conn,
+            DomainEvent::new(
+                REGISTRATION_CANCELLED,
+                format!("Registration for {} cancelled by the family", session.name),
+            )
+            .registration(&registration)
+            .details(json!({
+                "refund_amount": refund.amount,
+                "credit_amount": refund.credit_amount,
+                "currency": refund.currency,
+            })),
+        )?;
+
+        let guardian = guardians::table
+            .find(registration.guardian_id)
+            .first::<Guardian>(conn)?;
+        let mut notification_ids = vec![enqueue(
+            conn,
+            Notification {
+                channel: Channel::Email,
+                target: guardian.email,
+                template: "registration_cancelled".to_string(),
+                payload: json!({
+                    "type": "registration_cancelled",
+                    "registration_id": registration.id,
+                    "camper_id": registration.camper_id,
+                    "session_id": session.id,
+                    "session_name": session.name,
+                    "refund_amount": refund.amount,
+                    "credit_amount": refund.credit_amount,
+                    "currency": refund.currency,
+                }),
+                registration_id: Some(registration.id),
+                payment_intent_id: refund.payment_intent_id.clone(),
+            },
+        )?];
+        notification_ids.extend(notify_next_in_line(conn, &session)?);
+        Ok(Ok((registration, refund, notification_ids)))
+    });
+    let (registration, refund, notification_ids) =
+        result.map_err(db_error("Failed to cancel registration"))??;
+    drop(conn);
+    info!(
+        "Guardian cancelled registration {} with a refund of {} and credit of {} {}",
+        registration.id, refund.amount, refund.credit_amount, refund.currency
+    );
+
+    dispatch_pending(&state, Some(&notification_ids)).await;
+    // The cancellation is committed; a refund that fails here is retried by the job
+    if refund.status == "pending" {
+        if let Err((_, e)) = process_refund(&state, &keyring, refund.id).await {
+            error!(
+                "Refund for cancelled registration {} failed: {e}",
+                registration.id
+            );
+        }
+    }
+
+    let mut conn = conn_from_state(&state).await?;
+    let refund = crate::database::schema::cancellation_refunds::table
+        .find(refund.id)
+        .first::<CancellationRefund>(&mut conn)
+        .map_err(db_error("Failed to load refund"))?;
+    Ok(axum::Json(json!(RegistrationCancelledResponse {
+        registration,
+        refund,
+    })))
+}
@@ -0,0 +1,435 @@
+//! Registration drafts.
+//!
+//! The registration form is long, so guardians can save it part-way through with
+//! `POST /registrations/draft` and continue with `PATCH /registrations/draft/{id}`.
+//! Each save validates only what has been filled in: the camper must be the
+//! guardian's, the session must exist and be open, and, once both are chosen, the
+//! camper must be old enough for the session. Free-form answers for the rest of the
+//! form are kept as a JSON object and merged key by key; a `null` removes an answer.
+//! Seats are not held for drafts.
+//!
+//! `POST /registrations/draft/{id}/convert` turns a complete draft into a pending
+//! registration at checkout, with the same capacity and eligibility checks as
+//! `POST /registrations`. Drafts not saved for [`DRAFT_TTL_DAYS`] days are deleted
+//! by the `registration_drafts` job.
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::Actor;
+use crate::campers::load_camper;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampSession, NewRegistrationDraft, RegistrationDraft},
+};
+use crate::registrations::{
+    normalize_friend_requests, register_camper, RegistrationCreatedResponse,
+};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+/// Days a draft is kept after it was last saved.
+pub const DRAFT_TTL_DAYS: i64 = 30;
+/// Largest answers object a draft can hold, serialized.
+pub const MAX_ANSWERS_BYTES: usize = 32 * 1024;
+/// Most open drafts a guardian can keep.
+const MAX_OPEN_DRAFTS: i64 = 20;
+
+/// Fields of a draft, all optional. Fields left out of a `PATCH` are unchanged.
+#[derive(Debug, Default, Deserialize)]
+pub struct DraftRequest {
+    pub camper_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    /// Replaces the draft's friend requests.
+    pub friend_requests: Option<Vec<String>>,
+    /// Merged into the draft's answers; a `null` value removes the answer.
+    pub answers: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DraftResponse {
+    pub draft: RegistrationDraft,
+    /// Fields still needed before the draft can be converted.
+    pub missing: Vec<&'static str>,
+    pub ready_for_checkout: bool,
+}
+
+impl DraftResponse {
+    fn new(draft: RegistrationDraft) -> Self {
+        let missing = missing_fields(draft.camper_id, draft.session_id);
+        Self {
+            ready_for_checkout: missing.is_empty() && draft.converted_at.is_none(),
+            missing,
+            draft,
+        }
+    }
+}
+
+/// The fields a registration needs that the draft does not have yet.
+pub fn missing_fields(camper_id: Option<Uuid>, session_id: Option<Uuid>) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+    if camper_id.is_none() {
+        missing.push("camper_id");
+    }
+    if session_id.is_none() {
+        missing.push("session_id");
+    }
+    missing
+}
+
+/// Merges `changes` into the answers object, removing answers set to `null`.
+/// Rejects answers that would grow past [`MAX_ANSWERS_BYTES`].
+pub fn merge_answers(answers: &Value, changes: Map<String, Value>) -> Result<Value, String> {
+    let mut merged = answers.as_object().cloned().unwrap_or_default();
+    for (key, value) in changes {
+        if value.is_null() {
+            merged.remove(&key);
+        } else {
+            merged.insert(key, value);
+        }
+    }
+    let merged = Value::Object(merged);
+    if merged.to_string().len() > MAX_ANSWERS_BYTES {
+        return Err(format!(
+            "Draft answers must be at most {MAX_ANSWERS_BYTES} bytes"
+        ));
+    }
+    Ok(merged)
+}
+
+fn invalid(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, message)
+}
+
+fn actor_guardian(actor: &Actor) -> Result<Uuid, ApiError> {
+    actor.guardian_id().ok_or_else(|| {
+        ApiError::from((
+            StatusCode::FORBIDDEN,
+            "Guardian token has no guardian".to_string(),
+        ))
+    })
+}
+
+/// Checks the parts of the draft that are filled in.
+fn validate(
+    conn: &mut PgConnection,
+    guardian: Uuid,
+    camper_id: Option<Uuid>,
+    session_id: Option<Uuid>,
+) -> Result<(), ApiError> {
+    let camper = camper_id
+        .map(|id| load_camper(conn, id))
+        .transpose()?
+        .map(|camper| {
+            if camper.guardian_id == guardian {
+                Ok(camper)
+            } else {
+                Err(ApiError::new(
+                    StatusCode::NOT_FOUND,
+                    ErrorCode::NotFound,
+                    "Camper not found",
+                ))
+            }
+        })
+        .transpose()?;
+    let Some(session_id) = session_id else {
+        return Ok(());
+    };
+    let session = crate::database::schema::camp_sessions::table
+        .find(session_id)
+        .first::<CampSession>(conn)
+        .optional()
+        .map_err(db_error("Failed to load session"))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Session not found",
+            )
+        })?;
+    if session.cancelled_at.is_some() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            ErrorCode::SessionCancelled,
+            "Session is cancelled",
+        ));
+    }
+    if let Some(camper) = camper.filter(|c| !session.admits_age(c.birthdate)) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::AgeIneligible,
+            format!(
+                "{} is outside the age range for {}",
+                camper.first_name, session.name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Loads one of the guardian's drafts, mapping a missing, expired or someone
+/// else's draft to 404.
+fn load_draft(
+    conn: &mut PgConnection,
+    guardian: Uuid,
+    draft_id: Uuid,
+) -> Result<RegistrationDraft, ApiError> {
+    use crate::database::schema::registration_drafts::dsl::*;
+
+    let now = chrono::Utc::now().naive_utc();
+    registration_drafts
+        .find(draft_id)
+        .filter(guardian_id.eq(guardian))
+        .filter(expires_at.gt(now).or(converted_at.is_not_null()))
+        .first::<RegistrationDraft>(conn)
+        .optional()
+        .map_err(db_error("Failed to load draft"))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Draft not found",
+            )
+        })
+}
+
+fn already_converted() -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        ErrorCode::Conflict,
+        "Draft was already converted into a registration",
+    )
+}
+
+/// POST /registrations/draft saves a new draft with whatever has been filled in.
+#[tracing::instrument(skip(state))]
+pub async fn create_draft_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<DraftRequest>,
+) -> Result<axum::Json<Value>, ApiError> {
+    use crate::database::schema::registration_drafts::dsl::*;
+
+    let guardian = actor_guardian(&actor)?;
+    let friends = normalize_friend_requests(&payload.friend_requests.unwrap_or_default())?;
+    let form_answers =
+        merge_answers(&json!({}), payload.answers.unwrap_or_default()).map_err(invalid)?;
+
+    let mut conn = conn_from_state(&state).await?;
+    validate(&mut conn, guardian, payload.camper_id, payload.session_id)?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let open = registration_drafts
+        .filter(guardian_id.eq(guardian))
+        .filter(converted_at.is_null())
+        .filter(expires_at.gt(now))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(db_error("Failed to count drafts"))?;
+    if open >= MAX_OPEN_DRAFTS {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            ErrorCode::Conflict,
+            format!("At most {MAX_OPEN_DRAFTS} drafts can be open at once"),
+        ));
+    }
+
+    let draft = diesel::insert_into(registration_drafts)
+        .values(&NewRegistrationDraft {
+            id: Uuid::new_v4(),
+            guardian_id: guardian,
+            camper_id: payload.camper_id,
+            session_id: payload.session_id,
+            friend_requests: friends,
+            answers: form_answers,
+            expires_at: now + Duration::days(DRAFT_TTL_DAYS),
+        })
+        .get_result::<RegistrationDraft>(&mut conn)
+        .map_err(db_error("Failed to save draft"))?;
+    info!(
+        "Saved registration draft {} for guardian {guardian}",
+        draft.id
+    );
+
+    Ok(axum::Json(json!(DraftResponse::new(draft))))
+}
+
+/// GET /registrations/draft lists the guardian's drafts that have not expired or
+/// been converted, most recently saved first.
+#[tracing::instrument(skip(state))]
+pub async fn list_drafts_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, ApiError> {
+    use crate::database::schema::registration_drafts::dsl::*;
+
+    let guardian = actor_guardian(&actor)?;
+    let mut conn = conn_from_state(&state).await?;
+    let drafts: Vec<DraftResponse> = registration_drafts
+        .filter(guardian_id.eq(guardian))
+        .filter(converted_at.is_null())
+        .filter(expires_at.gt(chrono::Utc::now().naive_utc()))
+        .order(updated_at.desc())
+        .load::<RegistrationDraft>(&mut conn)
+        .map_err(db_error("Failed to load drafts"))?
+        .into_iter()
+        .map(DraftResponse::new)
+        .collect();
+
+    Ok(axum::Json(json!({ "drafts": drafts })))
+}
+
+/// GET /registrations/draft/{id} returns a draft.
+#[tracing::instrument(skip(state))]
+pub async fn get_draft_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(draft_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, ApiError> {
+    let guardian = actor_guardian(&actor)?;
+    let mut conn = conn_from_state(&state).await?;
+    let draft = load_draft(&mut conn, guardian, draft_id)?;
+    Ok(axum::Json(json!(DraftResponse::new(draft))))
+}
+
+/// PATCH /registrations/draft/{id} saves changes to a draft and extends its expiry.
+#[tracing::instrument(skip(state))]
+pub async fn update_draft_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(draft_id): Path<Uuid>,
+    Json(payload): Json<DraftRequest>,
+) -> Result<axum::Json<Value>, ApiError> {
+    use crate::database::schema::registration_drafts::dsl::*;
+
+    let guardian = actor_guardian(&actor)?;
+    let mut conn = conn_from_state(&state).await?;
+    let draft = load_draft(&mut conn, guardian, draft_id)?;
+    if draft.converted_at.is_some() {
+        return Err(already_converted());
+    }
+
+    let new_camper = payload.camper_id.or(draft.camper_id);
+    let new_session = payload.session_id.or(draft.session_id);
+    let friends = match &payload.friend_requests {
+        Some(names) => normalize_friend_requests(names)?,
+        None => draft.friend_requests.clone(),
+    };
+    let form_answers = match payload.answers {
+        Some(changes) => merge_answers(&draft.answers, changes).map_err(invalid)?,
+        None => draft.answers.clone(),
+    };
+    validate(&mut conn, guardian, new_camper, new_session)?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let draft = diesel::update(registration_drafts.find(draft.id))
+        .set((
+            camper_id.eq(new_camper),
+            session_id.eq(new_session),
+            friend_requests.eq(friends),
+            answers.eq(form_answers),
+            updated_at.eq(now),
+            expires_at.eq(now + Duration::days(DRAFT_TTL_DAYS)),
+        ))
+        .get_result::<RegistrationDraft>(&mut conn)
+        .map_err(db_error("Failed to save draft"))?;
+
+    Ok(axum::Json(json!(DraftResponse::new(draft))))
+}
+
+/// POST /registrations/draft/{id}/convert creates the pending registration and its
+/// seat hold from a complete draft, ready for checkout.
+#[tracing::instrument(skip(state))]
+pub async fn convert_draft_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(draft_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, ApiError> {
+    use crate::database::schema::registration_drafts::dsl::*;
+
+    let guardian = actor_guardian(&actor)?;
+    let mut conn = conn_from_state(&state).await?;
+    let draft = load_draft(&mut conn, guardian, draft_id)?;
+    if draft.converted_at.is_some() {
+        return Err(already_converted());
+    }
+    let (Some(draft_camper), Some(draft_session)) = (draft.camper_id, draft.session_id) else {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::InvalidRequest,
+            format!(
+                "Draft is missing {}",
+                missing_fields(draft.camper_id, draft.session_id).join(" and ")
+            ),
+        ));
+    };
+    let camper = load_camper(&mut conn, draft_camper)?;
+    if camper.guardian_id != guardian {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            ErrorCode::NotFound,
+            "Camper not found",
+        ));
+    }
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let created =
+            match register_camper(conn, &actor, &camper, draft_session, &draft.friend_requests)? {
+                Ok(created) => created,
+                Err(rejection) => return Ok(Err(rejection)),
+            };
+        // Only the first conversion wins if the draft is submitted twice at once
+        let converted = diesel::update(
+            registration_drafts
+                .find(draft.id)
+                .filter(converted_at.is_null()),
+        )
+        .set((
+            registration_id.eq(created.0.id),
+            converted_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+        if converted == 0 {
+            return Err(diesel::result::Error::RollbackTransaction);
+        }
+        Ok(Ok(created))
+    });
+    let (registration, hold) = match result {
+        Ok(created) => created?,
+        Err(diesel::result::Error::RollbackTransaction) => return Err(already_converted()),
+        Err(e) => return Err(db_error("Failed to convert draft")(e).into()),
+    };
+    info!(
+        "Converted draft {} into registration {}",
+        draft.id, registration.id
+    );
+
+    Ok(axum::Json(json!(RegistrationCreatedResponse {
+        hold_expires_at: hold.expires_at,
+        registration,
+        friend_requests: draft.friend_requests,
+    })))
+}
+
+/// Deletes drafts that expired without being converted. Returns how many.
+pub fn expire_drafts(
+    conn: &mut PgConnection,
+    now: NaiveDateTime,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::registration_drafts::dsl::*;
+
+    diesel::delete(
+        registration_drafts
+            .filter(converted_at.is_null())
+            .filter(expires_at.le(now)),
+    )
+    .execute(conn)
+}
@@ -0,0 +1,472 @@
+//! Bulk import of registrations from CSV, for paper registrations and legacy
+//! spreadsheets.
+//!
+//! `POST /admin/registrations/import` takes a CSV body whose header row names the
+//! columns `guardian_email`, `guardian_name`, `camper_first_name`,
+//! `camper_last_name`, `birthdate` (`YYYY-MM-DD`) and `session_id`, and optionally
+//! `friend_requests` (names separated by `;`) and `status`: `pending` (the default)
+//! holds the seat like a registration made through the API, and `confirmed` is for
+//! registrations already paid outside Stripe. Guardians are matched by email and
+//! campers by guardian, name and birthdate; missing ones are created.
+//!
+//! Rows are checked against the same rules as `POST /registrations`: the session
+//! must exist, be open, admit the camper's age and have a seat, counting the seats
+//! taken by earlier rows. The whole file is imported in one transaction, so any
+//! failing row imports nothing; the report lists every row's errors. With
+//! `?dry_run=true` the import is checked and rolled back. Imported registrations
+//! send no notifications.
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{Camper, Guardian, NewCamper, Registration},
+};
+use crate::events::{DomainEvent, EventRecorder, REGISTRATION_CONFIRMED};
+use crate::guardians::find_or_create_guardian;
+use crate::holds::release_holds;
+use crate::registrations::{normalize_friend_requests, register_camper};
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+/// Most data rows one import may contain.
+pub const MAX_IMPORT_ROWS: usize = 2000;
+
+const REQUIRED_COLUMNS: [&str; 6] = [
+    "guardian_email",
+    "guardian_name",
+    "camper_first_name",
+    "camper_last_name",
+    "birthdate",
+    "session_id",
+];
+
+/// The status an imported registration is created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Pending,
+    Confirmed,
+}
+
+/// A row that passed the column checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportRow {
+    /// Row number, counting the header as row 1.
+    pub row: usize,
+    pub guardian_email: String,
+    pub guardian_name: String,
+    pub camper_first_name: String,
+    pub camper_last_name: String,
+    pub birthdate: NaiveDate,
+    pub session_id: Uuid,
+    pub friend_requests: Vec<String>,
+    pub status: ImportStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowError {
+    pub row: usize,
+    /// The column at fault, when the error is about one value.
+    pub column: Option<String>,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RowError {
+    fn invalid(row: usize, column: &str, message: impl Into<String>) -> Self {
+        Self {
+            row,
+            column: Some(column.to_string()),
+            code: ErrorCode::InvalidRequest,
+            message: message.into(),
+        }
+    }
+}
+
+/// What an imported row created, or would create in a dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedRow {
+    pub row: usize,
+    pub session_id: Uuid,
+    pub status: ImportStatus,
+    pub created_guardian: bool,
+    pub created_camper: bool,
+    /// Set only once the import is committed.
+    pub guardian_id: Option<Uuid>,
+    pub camper_id: Option<Uuid>,
+    pub registration_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Whether the registrations were saved.
+    pub committed: bool,
+    pub rows: usize,
+    pub guardians_created: usize,
+    pub campers_created: usize,
+    pub registrations_created: usize,
+    pub imported: Vec<ImportedRow>,
+    pub errors: Vec<RowError>,
+}
+
+/// The data rows of an import file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedImport {
+    pub total_rows: usize,
+    /// Rows that passed the column checks.
+    pub rows: Vec<ImportRow>,
+    /// Errors of the rows that did not.
+    pub errors: Vec<RowError>,
+}
+
+fn field<'a>(record: &'a csv::StringRecord, index: &HashMap<&str, usize>, column: &str) -> &'a str {
+    index
+        .get(column)
+        .and_then(|i| record.get(*i))
+        .unwrap_or_default()
+}
+
+/// Reads the CSV and checks each row's values. Fails if the file itself is
+/// unusable.
+pub fn parse_rows(csv_body: &str) -> Result<ParsedImport, ApiError> {
+    let bad_file = |message: String| {
+        ApiError::new(StatusCode::BAD_REQUEST, ErrorCode::InvalidRequest, message)
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv_body.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| bad_file(format!("Invalid CSV header: {e}")))?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    let missing: Vec<&str> = REQUIRED_COLUMNS
+        .iter()
+        .copied()
+        .filter(|column| !headers.iter().any(|h| h == column))
+        .collect();
+    if !missing.is_empty() {
+        return Err(bad_file(format!(
+            "Missing CSV columns: {}",
+            missing.join(", ")
+        )));
+    }
+    let index: HashMap<&str, usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| (h.as_str(), i))
+        .collect();
+
+    let mut parsed = ParsedImport {
+        total_rows: 0,
+        rows: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (i, record) in reader.records().enumerate() {
+        let row = i + 2;
+        if i >= MAX_IMPORT_ROWS {
+            return Err(bad_file(format!(
+                "Imports are limited to {MAX_IMPORT_ROWS} rows"
+            )));
+        }
+        parsed.total_rows += 1;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.errors.push(RowError {
+                    row,
+                    column: None,
+                    code: ErrorCode::InvalidRequest,
+                    message: format!("Unreadable row: {e}"),
+                });
+                continue;
+            }
+        };
+        let value = |column: &str| field(&record, &index, column);
+
+        let mut row_errors = Vec::new();
+        let mut required = |column: &str| {
+            let v = value(column);
+            if v.is_empty() {
+                row_errors.push(RowError::invalid(
+                    row,
+                    column,
+                    format!("{column} is required"),
+                ));
+            }
+            v.to_string()
+        };
+        let guardian_email = required("guardian_email");
+        let guardian_name = required("guardian_name");
+        let camper_first_name = required("camper_first_name");
+        let camper_last_name = required("camper_last_name");
+        let birthdate_raw = required("birthdate");
+        let session_raw = required("session_id");
+
+        if !guardian_email.is_empty() && !guardian_email.contains('@') {
+            row_errors.push(RowError::invalid(
+                row,
+                "guardian_email",
+                format!("'{guardian_email}' is not an email address"),
+            ));
+        }
+        let birthdate = NaiveDate::parse_from_str(&birthdate_raw, "%Y-%m-%d").ok();
+        if birthdate.is_none() && !birthdate_raw.is_empty() {
+            row_errors.push(RowError::invalid(
+                row,
+                "birthdate",
+                format!("'{birthdate_raw}' is not a YYYY-MM-DD date"),
+            ));
+        }
+        let session_id = Uuid::parse_str(&session_raw).ok();
+        if session_id.is_none() && !session_raw.is_empty() {
+            row_errors.push(RowError::invalid(
+                row,
+                "session_id",
+                format!("'{session_raw}' is not a session id"),
+            ));
+        }
+        let status = match value("status").to_lowercase().as_str() {
+            "" | "pending" => Some(ImportStatus::Pending),
+            "confirmed" => Some(ImportStatus::Confirmed),
+            other => {
+                row_errors.push(RowError::invalid(
+                    row,
+                    "status",
+                    format!("'{other}' is not pending or confirmed"),
+                ));
+                None
+            }
+        };
+        let friend_names: Vec<String> = value("friend_requests")
+            .split(';')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+        let friend_requests = match normalize_friend_requests(&friend_names) {
+            Ok(friends) => friends,
+            Err(e) => {
+                row_errors.push(RowError::invalid(row, "friend_requests", e.message));
+                Vec::new()
+            }
+        };
+
+        match (birthdate, session_id, status) {
+            (Some(birthdate), Some(session_id), Some(status)) if row_errors.is_empty() => {
+                parsed.rows.push(ImportRow {
+                    row,
+                    guardian_email,
+                    guardian_name,
+                    camper_first_name,
+                    camper_last_name,
+                    birthdate,
+                    session_id,
+                    friend_requests,
+                    status,
+                })
+            }
+            _ => parsed.errors.extend(row_errors),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Creates the guardians, campers and registrations for the rows, recording row
+/// errors in the report. Runs inside the import transaction.
+fn apply_rows(
+    conn: &mut PgConnection,
+    actor: &Actor,
+    rows: &[ImportRow],
+    report: &mut ImportReport,
+) -> Result<(), diesel::result::Error> {
+    use crate::database::schema::{campers, guardians, registrations};
+
+    let now = chrono::Utc::now().naive_utc();
+    for row in rows {
+        let existing_guardian = guardians::table
+            .filter(guardians::email.eq(&row.guardian_email))
+            .first::<Guardian>(conn)
+            .optional()?;
+        let created_guardian = existing_guardian.is_none();
+        let guardian = match existing_guardian {
+            Some(guardian) => guardian,
+            None => find_or_create_guardian(conn, &row.guardian_email, &row.guardian_name)?,
+        };
+
+        let existing_camper = campers::table
+            .filter(campers::guardian_id.eq(guardian.id))
+            .filter(campers::first_name.eq(&row.camper_first_name))
+            .filter(campers::last_name.eq(&row.camper_last_name))
+            .filter(campers::birthdate.eq(row.birthdate))
+            .first::<Camper>(conn)
+            .optional()?;
+        let created_camper = existing_camper.is_none();
+        let camper = match existing_camper {
+            Some(camper) => camper,
+            None => diesel::insert_into(campers::table)
+                .values(&NewCamper {
+                    guardian_id: guardian.id,
+                    first_name: row.camper_first_name.clone(),
+                    last_name: row.camper_last_name.clone(),
+                    birthdate: row.birthdate,
+                })
+                .get_result::<Camper>(conn)?,
+        };
+
+        let already_registered = diesel::select(diesel::dsl::exists(
+            registrations::table
+                .filter(registrations::camper_id.eq(camper.id))
+                .filter(registrations::session_id.eq(row.session_id))
+                .filter(registrations::status.ne("cancelled")),
+        ))
+        .get_result::<bool>(conn)?;
+        if already_registered {
+            report.errors.push(RowError {
+                row: row.row,
+                column: None,
+                code: ErrorCode::Conflict,
+                message: format!(
+                    "{} {} is already registered for this session",
+                    camper.first_name, camper.last_name
+                ),
+            });
+            continue;
+        }
+
+        let registration =
+            match register_camper(conn, actor, &camper, row.session_id, &row.friend_requests)? {
+                Ok((registration, _)) => registration,
+                Err(e) => {
+                    report.errors.push(RowError {
+                        row: row.row,
+                        column: Some("session_id".to_string()),
+                        code: e.code,
+                        message: e.message,
+                    });
+                    continue;
+                }
+            };
+        let registration = match row.status {
+            ImportStatus::Pending => registration,
+            ImportStatus::Confirmed => {
+                let confirmed = diesel::update(registrations::table.find(registration.id))
+                    .set((
+                        registrations::status.eq("confirmed"),
+                        registrations::updated_at.eq(now),
+                    ))
+                    .get_result::<Registration>(conn)?;
+                release_holds(conn, &[confirmed.id], now)?;
+                EventRecorder::for_actor(actor).record(
+                    conn,
+                    DomainEvent::new(REGISTRATION_CONFIRMED, "Registration imported as paid")
+                        .registration(&confirmed),
+                )?;
+                confirmed
+            }
+        };
+
+        report.guardians_created += usize::from(created_guardian);
+        report.campers_created += usize::from(created_camper);
+        report.registrations_created += 1;
+        report.imported.push(ImportedRow {
+            row: row.row,
+            session_id: row.session_id,
+            status: row.status,
+            created_guardian,
+            created_camper,
+            guardian_id: Some(guardian.id),
+            camper_id: Some(camper.id),
+            registration_id: Some(registration.id),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// POST /admin/registrations/import?dry_run= imports registrations from a CSV
+/// body, all or nothing, and reports per-row errors. Answers 422 with the report
+/// when rows fail outside a dry run.
+#[tracing::instrument(skip(state, body))]
+pub async fn import_registrations_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<(StatusCode, axum::Json<Value>), ApiError> {
+    let ParsedImport {
+        total_rows,
+        rows,
+        errors,
+    } = parse_rows(&body)?;
+    let mut report = ImportReport {
+        dry_run: query.dry_run,
+        committed: false,
+        rows: total_rows,
+        guardians_created: 0,
+        campers_created: 0,
+        registrations_created: 0,
+        imported: Vec::new(),
+        errors,
+    };
+
+    // Outside a dry run there is no point checking the database once a row failed
+    if report.errors.is_empty() || query.dry_run {
+        let mut conn = conn_from_state(&state).await?;
+        let outcome = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            apply_rows(conn, &actor, &rows, &mut report)?;
+            if query.dry_run || !report.errors.is_empty() {
+                return Err(diesel::result::Error::RollbackTransaction);
+            }
+            Ok(())
+        });
+        match outcome {
+            Ok(()) => report.committed = true,
+            Err(diesel::result::Error::RollbackTransaction) => {}
+            Err(e) => return Err(db_error("Failed to import registrations")(e).into()),
+        }
+    }
+
+    report.errors.sort_by_key(|e| e.row);
+    if !report.committed {
+        // Nothing was saved, so the ids refer to nothing
+        for imported in &mut report.imported {
+            imported.guardian_id = None;
+            imported.camper_id = None;
+            imported.registration_id = None;
+        }
+    }
+    info!(
+        "Registration import of {} row(s): {} registration(s), {} error(s), committed={}",
+        report.rows,
+        report.registrations_created,
+        report.errors.len(),
+        report.committed
+    );
+
+    let status = if report.errors.is_empty() || query.dry_run {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    Ok((status, axum::Json(json!(report))))
+}
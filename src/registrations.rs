@@ -0,0 +1,298 @@
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::{Actor, Role};
+use crate::campers::{ensure_guardian_owns, load_camper};
+use crate::check_in::check_in_payload;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{
+        CampSession, Camper, Guardian, NewFriendRequest, NewRegistration, Registration,
+        RegistrationHold,
+    },
+};
+use crate::events::{DomainEvent, EventRecorder, REGISTRATION_CONFIRMED, REGISTRATION_CREATED};
+use crate::holds::{place_hold, release_holds, seats_taken};
+use crate::notifications::{enqueue, Channel, Notification};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRegistrationRequest {
+    pub camper_id: Uuid,
+    pub session_id: Uuid,
+    /// Friends the camper would like to share a cabin with, by name.
+    #[serde(default)]
+    pub friend_requests: Vec<String>,
+}
+
+/// Most friends a registration can name.
+const MAX_FRIEND_REQUESTS: usize = 3;
+const MAX_FRIEND_NAME_LEN: usize = 100;
+
+/// Trims and deduplicates friend names, rejecting blank, overlong or too many names.
+pub(crate) fn normalize_friend_requests(names: &[String]) -> Result<Vec<String>, ApiError> {
+    let mut friends: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.is_empty() || name.chars().count() > MAX_FRIEND_NAME_LEN {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                format!("Friend names must be 1 to {MAX_FRIEND_NAME_LEN} characters"),
+            ));
+        }
+        if !friends.iter().any(|f| f.eq_ignore_ascii_case(&name)) {
+            friends.push(name);
+        }
+    }
+    if friends.len() > MAX_FRIEND_REQUESTS {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidRequest,
+            format!("At most {MAX_FRIEND_REQUESTS} friend requests per registration"),
+        ));
+    }
+    Ok(friends)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistrationCreatedResponse {
+    pub registration: Registration,
+    /// When the seat is released unless the registration has been paid for.
+    pub hold_expires_at: NaiveDateTime,
+    /// Friend names recorded for cabin matching.
+    pub friend_requests: Vec<String>,
+}
+
+/// Loads a registration, mapping a missing row (or another guardian's registration) to 404.
+pub fn load_registration(
+    conn: &mut PgConnection,
+    actor: &Actor,
+    registration: Uuid,
+) -> Result<Registration, (StatusCode, String)> {
+    let registration = crate::database::schema::registrations::table
+        .find(registration)
+        .first::<Registration>(conn)
+        .optional()
+        .map_err(db_error("Failed to load registration"))?
+        .filter(|r| actor.role != Role::Guardian || actor.guardian_id() == Some(r.guardian_id))
+        .ok_or((StatusCode::NOT_FOUND, "Registration not found".to_string()))?;
+    Ok(registration)
+}
+
+/// Creates a pending registration for the camper and places a capacity hold, after
+/// checking the session is open, has room and admits the camper's age.
+pub fn register_camper(
+    conn: &mut PgConnection,
+    actor: &Actor,
+    camper: &Camper,
+    session_id: Uuid,
+    friends: &[String],
+) -> Result<Result<(Registration, RegistrationHold), ApiError>, diesel::result::Error> {
+    conn.transaction(|conn| {
+        // Lock the session row so concurrent checkouts cannot oversell it
+        let session = crate::database::schema::camp_sessions::table
+            .find(session_id)
+            .for_update()
+            .first::<CampSession>(conn)
+            .optional()?;
+        let Some(session) = session else {
+            return Ok(Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Session not found",
+            )));
+        };
+
+        if session.cancelled_at.is_some() {
+            return Ok(Err(ApiError::new(
+                StatusCode::CONFLICT,
+                ErrorCode::SessionCancelled,
+                "Session is cancelled",
+            )));
+        }
+        if !session.admits_age(camper.birthdate) {
+            return Ok(Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::AgeIneligible,
+                format!(
+                    "{} is outside the age range for {}",
+                    camper.first_name, session.name
+                ),
+            )));
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        if seats_taken(conn, session.id, now)? >= i64::from(session.capacity) {
+            return Ok(Err(ApiError::new(
+                StatusCode::CONFLICT,
+                ErrorCode::SessionFull,
+                "Session is full",
+            )));
+        }
+
+        let registration = diesel::insert_into(crate::database::schema::registrations::table)
+            .values(&NewRegistration {
+                id: Uuid::new_v4(),
+                guardian_id: camper.guardian_id,
+                camper_id: camper.id,
+                session_id: session.id,
+                status: "pending".to_string(),
+            })
+            .get_result::<Registration>(conn)?;
+        if !friends.is_empty() {
+            let friend_rows: Vec<NewFriendRequest> = friends
+                .iter()
+                .map(|name| NewFriendRequest {
+                    registration_id: registration.id,
+                    friend_name: name.clone(),
+                })
+                .collect();
+            diesel::insert_into(crate::database::schema::friend_requests::table)
+                .values(&friend_rows)
+                .execute(conn)?;
+        }
+        let hold = place_hold(conn, registration.id, session.id, now)?;
+        EventRecorder::for_actor(actor).record(
+            conn,
+            DomainEvent::new(
+                REGISTRATION_CREATED,
+                format!("{} registered for {}", camper.first_name, session.name),
+            )
+            .registration(&registration)
+            .details(json!({ "hold_expires_at": hold.expires_at })),
+        )?;
+        Ok(Ok((registration, hold)))
+    })
+}
+
+/// POST /registrations creates a pending registration and places a capacity hold
+/// that keeps the seat while the family pays. Rejections carry SESSION_FULL,
+/// SESSION_CANCELLED or AGE_INELIGIBLE codes.
+#[tracing::instrument(skip(state))]
+pub async fn create_registration_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<CreateRegistrationRequest>,
+) -> Result<axum::Json<Value>, ApiError> {
+    let friends = normalize_friend_requests(&payload.friend_requests)?;
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, payload.camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+
+    let (registration, hold): (Registration, RegistrationHold) =
+        register_camper(&mut conn, &actor, &camper, payload.session_id, &friends)
+            .map_err(db_error("Failed to create registration"))??;
+    info!(
+        "Created registration {} with hold until {}",
+        registration.id, hold.expires_at
+    );
+
+    Ok(axum::Json(json!(RegistrationCreatedResponse {
+        hold_expires_at: hold.expires_at,
+        registration,
+        friend_requests: friends,
+    })))
+}
+
+/// GET /registrations/{id} returns a registration.
+#[tracing::instrument(skip(state))]
+pub async fn get_registration_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, ApiError> {
+    let mut conn = conn_from_state(&state).await?;
+    let registration = load_registration(&mut conn, &actor, registration_id)?;
+    Ok(axum::Json(json!(registration)))
+}
+
+/// Confirms the pending registrations paid for by a PaymentIntent, releases their
+/// holds and enqueues confirmation notifications, all in one transaction.
+/// Returns the confirmed registrations and the ids of the enqueued notifications.
+pub fn confirm_paid_registrations(
+    conn: &mut PgConnection,
+    registration_ids: &[Uuid],
+    intent_id: &str,
+) -> Result<(Vec<Registration>, Vec<Uuid>), diesel::result::Error> {
+    use crate::database::schema::{guardians, registrations};
+
+    conn.transaction(|conn| {
+        let now = chrono::Utc::now().naive_utc();
+        let confirmed = diesel::update(
+            registrations::table
+                .filter(registrations::id.eq_any(registration_ids))
+                .filter(registrations::status.eq("pending")),
+        )
+        .set((
+            registrations::status.eq("confirmed"),
+            registrations::updated_at.eq(now),
+        ))
+        .get_results::<Registration>(conn)?;
+
+        release_holds(conn, registration_ids, now)?;
+        EventRecorder::system().record_all(
+            conn,
+            confirmed
+                .iter()
+                .map(|registration| {
+                    DomainEvent::new(REGISTRATION_CONFIRMED, "Registration paid and confirmed")
+                        .registration(registration)
+                        .payment_intent(intent_id)
+                })
+                .collect(),
+        )?;
+
+        let mut notification_ids = Vec::new();
+        for registration in &confirmed {
+            let guardian = guardians::table
+                .find(registration.guardian_id)
+                .first::<Guardian>(conn)?;
+            let payload = json!({
+                "type": "registration_confirmed",
+                "registration_id": registration.id,
+                "camper_id": registration.camper_id,
+                "session_id": registration.session_id,
+                "payment_intent_id": intent_id,
+            });
+            // The email carries the QR check-in code for the gate
+            let mut email_payload = payload.clone();
+            if let Some(check_in) = check_in_payload(registration.id) {
+                email_payload["check_in"] = check_in;
+            }
+            notification_ids.push(enqueue(
+                conn,
+                Notification {
+                    channel: Channel::Email,
+                    target: guardian.email,
+                    template: "registration_confirmed".to_string(),
+                    payload: email_payload,
+                    registration_id: Some(registration.id),
+                    payment_intent_id: Some(intent_id.to_string()),
+                },
+            )?);
+            notification_ids.push(enqueue(
+                conn,
+                Notification {
+                    channel: Channel::WebSocket,
+                    target: intent_id.to_string(),
+                    template: "registration_confirmed".to_string(),
+                    payload,
+                    registration_id: Some(registration.id),
+                    payment_intent_id: Some(intent_id.to_string()),
+                },
+            )?);
+        }
+        Ok((confirmed, notification_ids))
+    })
+}
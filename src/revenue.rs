@@ -0,0 +1,434 @@
+//! Session-level revenue recognition.
+//!
+//! Bookkeeping recognizes camp revenue when the session is delivered rather than
+//! when the family pays. Every successful payment is allocated to the sessions of
+//! the registrations it paid for, directly or through a quote, in proportion to the
+//! sessions' prices; the rounding remainder goes to the last session. A refund
+//! issued for a cancelled registration reduces that registration's session, and any
+//! other refund is allocated like the payment it returns.
+//!
+//! Money collected for a session stays deferred until the session starts; on its
+//! start date everything collected so far is recognized, and later movements are
+//! recognized as they happen. `GET /admin/reports/session_revenue?month=` reports
+//! one month, and the `session_revenue` export writes every month of a range for
+//! the accounting system. Payments that cannot be tied to a session, such as
+//! voucher purchases, are totalled as unallocated.
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampSession, CancellationRefund, PaymentEvent, Quote, Refund, Registration},
+};
+use crate::payment_metadata::PaymentMetadata;
+use crate::vouchers::VOUCHER_PURPOSE;
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+};
+use chrono::{Datelike, Months, NaiveDate, NaiveTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// A session revenue is recognized for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionRef {
+    pub id: Uuid,
+    pub name: String,
+    pub starts_on: NaiveDate,
+}
+
+/// Money moving for a session on a day: positive for payments, negative for
+/// refunds. `session_id` is `None` when the money is not for any session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevenueEntry {
+    pub session_id: Option<Uuid>,
+    pub on: NaiveDate,
+    pub amount: i64,
+    pub currency: String,
+}
+
+/// Sessions and the money allocated to them.
+#[derive(Debug, Default)]
+pub struct RevenueLedger {
+    pub sessions: Vec<SessionRef>,
+    pub entries: Vec<RevenueEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionRevenueRow {
+    pub session_id: Uuid,
+    pub session_name: String,
+    pub starts_on: NaiveDate,
+    pub currency: String,
+    /// Payments less refunds in the month.
+    pub collected_in_month: i64,
+    /// Payments less refunds up to the end of the month.
+    pub collected_to_date: i64,
+    pub recognized_in_month: i64,
+    pub recognized_to_date: i64,
+    /// Collected but not yet recognized at the end of the month.
+    pub deferred: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RevenueTotals {
+    pub currency: String,
+    pub collected_in_month: i64,
+    pub recognized_in_month: i64,
+    pub recognized_to_date: i64,
+    pub deferred: i64,
+    /// Money collected in the month that is not for any session.
+    pub unallocated_in_month: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionRevenueReport {
+    /// `YYYY-MM`.
+    pub month: String,
+    pub sessions: Vec<SessionRevenueRow>,
+    pub totals: Vec<RevenueTotals>,
+}
+
+/// Parses `YYYY-MM` into the first day of the month.
+pub fn parse_month(raw: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", raw.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid month '{raw}': expected YYYY-MM"))
+}
+
+/// The last day of the month starting on `first`.
+pub fn month_end(first: NaiveDate) -> NaiveDate {
+    let first = first.with_day(1).unwrap_or(first);
+    first
+        .checked_add_months(Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .unwrap_or(first)
+}
+
+/// Splits `amount` across sessions in proportion to their weights, evenly when no
+/// weight is positive. The shares always add up to `amount`; the rounding
+/// remainder goes to the last session.
+pub fn allocate(amount: i64, weights: &[(Uuid, i64)]) -> Vec<(Uuid, i64)> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let total_weight: i64 = weights.iter().map(|(_, w)| (*w).max(0)).sum();
+    let mut shares: Vec<(Uuid, i64)> = weights
+        .iter()
+        .map(|(session, weight)| {
+            let share = if total_weight > 0 {
+                (amount as i128 * (*weight).max(0) as i128 / total_weight as i128) as i64
+            } else {
+                amount / weights.len() as i64
+            };
+            (*session, share)
+        })
+        .collect();
+    let allocated: i64 = shares.iter().map(|(_, share)| share).sum();
+    if let Some(last) = shares.last_mut() {
+        last.1 += amount - allocated;
+    }
+    shares
+}
+
+/// Revenue per session for the month starting on `first`, as of its last day.
+/// Sessions without any money in or before the month are left out.
+pub fn summarize(first: NaiveDate, ledger: &RevenueLedger) -> SessionRevenueReport {
+    let first = first.with_day(1).unwrap_or(first);
+    let last = month_end(first);
+    let sessions: HashMap<Uuid, &SessionRef> = ledger.sessions.iter().map(|s| (s.id, s)).collect();
+
+    let mut rows: BTreeMap<(NaiveDate, &str, Uuid, &str), SessionRevenueRow> = BTreeMap::new();
+    let mut unallocated: BTreeMap<&str, i64> = BTreeMap::new();
+    for entry in ledger.entries.iter().filter(|e| e.on <= last) {
+        let Some(session) = entry.session_id.and_then(|id| sessions.get(&id)) else {
+            if entry.on >= first {
+                *unallocated.entry(entry.currency.as_str()).or_default() += entry.amount;
+            }
+            continue;
+        };
+        let row = rows
+            .entry((
+                session.starts_on,
+                session.name.as_str(),
+                session.id,
+                entry.currency.as_str(),
+            ))
+            .or_insert_with(|| SessionRevenueRow {
+                session_id: session.id,
+                session_name: session.name.clone(),
+                starts_on: session.starts_on,
+                currency: entry.currency.clone(),
+                collected_in_month: 0,
+                collected_to_date: 0,
+                recognized_in_month: 0,
+                recognized_to_date: 0,
+                deferred: 0,
+            });
+        row.collected_to_date += entry.amount;
+        if entry.on >= first {
+            row.collected_in_month += entry.amount;
+        }
+        if session.starts_on <= last {
+            row.recognized_to_date += entry.amount;
+            // Recognized this month if the session started this month or the money
+            // moved this month after it started
+            if session.starts_on >= first || entry.on >= first {
+                row.recognized_in_month += entry.amount;
+            }
+        }
+    }
+
+    let mut totals: BTreeMap<&str, RevenueTotals> = BTreeMap::new();
+    let total_for = |currency: &str| RevenueTotals {
+        currency: currency.to_string(),
+        collected_in_month: 0,
+        recognized_in_month: 0,
+        recognized_to_date: 0,
+        deferred: 0,
+        unallocated_in_month: 0,
+    };
+    let sessions: Vec<SessionRevenueRow> = rows
+        .into_values()
+        .map(|mut row| {
+            row.deferred = row.collected_to_date - row.recognized_to_date;
+            row
+        })
+        .filter(|row| {
+            row.collected_to_date != 0
+                || row.collected_in_month != 0
+                || row.recognized_in_month != 0
+        })
+        .collect();
+    for row in &sessions {
+        let total = totals
+            .entry(row.currency.as_str())
+            .or_insert_with(|| total_for(&row.currency));
+        total.collected_in_month += row.collected_in_month;
+        total.recognized_in_month += row.recognized_in_month;
+        total.recognized_to_date += row.recognized_to_date;
+        total.deferred += row.deferred;
+    }
+    for (currency, amount) in unallocated {
+        let total = totals
+            .entry(currency)
+            .or_insert_with(|| total_for(currency));
+        total.collected_in_month += amount;
+        total.unallocated_in_month += amount;
+    }
+
+    SessionRevenueReport {
+        month: first.format("%Y-%m").to_string(),
+        totals: totals.into_values().collect(),
+        sessions,
+    }
+}
+
+/// Loads every successful payment and refund up to `until`, allocated to sessions.
+pub fn load_ledger(
+    conn: &mut PgConnection,
+    until: NaiveDate,
+) -> Result<RevenueLedger, diesel::result::Error> {
+    use crate::database::schema::{
+        camp_sessions, cancellation_refunds, payment_events, quotes, refunds, registrations,
+    };
+
+    let end = (until + chrono::Duration::days(1)).and_time(NaiveTime::MIN);
+    let mut seen = HashSet::new();
+    let payments: Vec<PaymentEvent> = payment_events::table
+        .filter(payment_events::status.ilike(PaymentEvent::SUCCEEDED))
+        .filter(payment_events::created_at.lt(end))
+        .order(payment_events::created_at.asc())
+        .load::<PaymentEvent>(conn)?
+        .into_iter()
+        .filter(|p| seen.insert(p.payment_intent_id.clone()))
+        .collect();
+    let paid_refunds = refunds::table
+        .filter(refunds::status.eq("succeeded"))
+        .filter(refunds::created_at.lt(end))
+        .order(refunds::created_at.asc())
+        .load::<Refund>(conn)?;
+
+    // The registrations each payment paid for, from its metadata and its quote
+    let metadata: Vec<PaymentMetadata> = payments
+        .iter()
+        .map(|payment| {
+            let raw: HashMap<String, String> = payment
+                .metadata
+                .as_ref()
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+                .unwrap_or_default();
+            PaymentMetadata::parse(&raw).unwrap_or_default()
+        })
+        .collect();
+    let quote_ids: Vec<Uuid> = metadata.iter().filter_map(|m| m.quote_id).collect();
+    let quote_registrations: HashMap<Uuid, Vec<Uuid>> = if quote_ids.is_empty() {
+        HashMap::new()
+    } else {
+        quotes::table
+            .filter(quotes::id.eq_any(&quote_ids))
+            .load::<Quote>(conn)?
+            .into_iter()
+            .map(|q| (q.id, q.registration_ids))
+            .collect()
+    };
+    let paid_for: Vec<Vec<Uuid>> = metadata
+        .iter()
+        .map(|m| {
+            if m.purpose.as_deref() == Some(VOUCHER_PURPOSE) {
+                return Vec::new();
+            }
+            let mut ids = m.registration_ids.clone();
+            if let Some(quoted) = m.quote_id.and_then(|q| quote_registrations.get(&q)) {
+                for id in quoted {
+                    if !ids.contains(id) {
+                        ids.push(*id);
+                    }
+                }
+            }
+            ids
+        })
+        .collect();
+
+    let stripe_refund_ids: Vec<&str> = paid_refunds
+        .iter()
+        .map(|r| r.stripe_refund_id.as_str())
+        .collect();
+    let refunded_registration: HashMap<String, Uuid> = if stripe_refund_ids.is_empty() {
+        HashMap::new()
+    } else {
+        cancellation_refunds::table
+            .filter(cancellation_refunds::stripe_refund_id.eq_any(stripe_refund_ids))
+            .load::<CancellationRefund>(conn)?
+            .into_iter()
+            .filter_map(|c| Some((c.stripe_refund_id?, c.registration_id)))
+            .collect()
+    };
+
+    let registration_ids: Vec<Uuid> = paid_for
+        .iter()
+        .flatten()
+        .chain(refunded_registration.values())
+        .copied()
+        .collect();
+    let registration_sessions: HashMap<Uuid, Uuid> = if registration_ids.is_empty() {
+        HashMap::new()
+    } else {
+        registrations::table
+            .filter(registrations::id.eq_any(&registration_ids))
+            .load::<Registration>(conn)?
+            .into_iter()
+            .map(|r| (r.id, r.session_id))
+            .collect()
+    };
+    let session_ids: Vec<Uuid> = registration_sessions.values().copied().collect();
+    let session_rows = if session_ids.is_empty() {
+        Vec::new()
+    } else {
+        camp_sessions::table
+            .filter(camp_sessions::id.eq_any(&session_ids))
+            .load::<CampSession>(conn)?
+    };
+    let prices: HashMap<Uuid, i64> = session_rows.iter().map(|s| (s.id, s.price)).collect();
+
+    // Each payment's split, reused for refunds not tied to a registration
+    let mut weights_by_intent: HashMap<&str, Vec<(Uuid, i64)>> = HashMap::new();
+    let mut entries = Vec::new();
+    for (payment, registrations) in payments.iter().zip(&paid_for) {
+        let weights: Vec<(Uuid, i64)> = registrations
+            .iter()
+            .filter_map(|r| registration_sessions.get(r))
+            .filter_map(|session| Some((*session, *prices.get(session)?)))
+            .collect();
+        let amount = payment.amount.unwrap_or_default();
+        let currency = payment.currency.clone().unwrap_or_default().to_lowercase();
+        let on = payment.created_at.date();
+        if weights.is_empty() {
+            entries.push(RevenueEntry {
+                session_id: None,
+                on,
+                amount,
+                currency,
+            });
+        } else {
+            for (session, share) in allocate(amount, &weights) {
+                entries.push(RevenueEntry {
+                    session_id: Some(session),
+                    on,
+                    amount: share,
+                    currency: currency.clone(),
+                });
+            }
+        }
+        weights_by_intent.insert(payment.payment_intent_id.as_str(), weights);
+    }
+    for refund in &paid_refunds {
+        let currency = refund.currency.to_lowercase();
+        let on = refund.created_at.date();
+        let session = refunded_registration
+            .get(&refund.stripe_refund_id)
+            .and_then(|r| registration_sessions.get(r));
+        let shares = match (
+            session,
+            weights_by_intent.get(refund.payment_intent_id.as_str()),
+        ) {
+            (Some(session), _) => vec![(*session, refund.amount)],
+            (None, Some(weights)) => allocate(refund.amount, weights),
+            (None, None) => Vec::new(),
+        };
+        if shares.is_empty() {
+            entries.push(RevenueEntry {
+                session_id: None,
+                on,
+                amount: -refund.amount,
+                currency,
+            });
+            continue;
+        }
+        for (session, share) in shares {
+            entries.push(RevenueEntry {
+                session_id: Some(session),
+                on,
+                amount: -share,
+                currency: currency.clone(),
+            });
+        }
+    }
+
+    Ok(RevenueLedger {
+        sessions: session_rows
+            .into_iter()
+            .map(|s| SessionRef {
+                id: s.id,
+                name: s.name,
+                starts_on: s.starts_on,
+            })
+            .collect(),
+        entries,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionRevenueQuery {
+    /// `YYYY-MM`.
+    pub month: String,
+}
+
+/// GET /admin/reports/session_revenue?month= reports collected, recognized and
+/// deferred revenue per session for a month.
+#[tracing::instrument(skip(state))]
+pub async fn session_revenue_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Query(query): Query<SessionRevenueQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let first = parse_month(&query.month).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let ledger = load_ledger(&mut conn, month_end(first))
+        .map_err(db_error("Failed to load session revenue"))?;
+
+    Ok(axum::Json(json!(summarize(first, &ledger))))
+}
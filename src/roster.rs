@@ -0,0 +1,119 @@
+use crate::database::{
+    conn_from_state, db_error,
+    models::{Camper, Guardian, Registration},
+};
+use crate::sessions::load_session;
+use crate::tags::{ids_with_all_tags, parse_tag_filter, tags_for, REGISTRATION};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct RosterQuery {
+    /// Comma-separated tags; only registrations carrying all of them are returned.
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RosterEntry {
+    pub registration_id: Uuid,
+    pub status: String,
+    pub camper_id: Uuid,
+    pub camper_first_name: String,
+    pub camper_last_name: String,
+    pub birthdate: Option<NaiveDate>,
+    pub guardian_name: String,
+    pub guardian_email: String,
+    pub tags: Vec<String>,
+}
+
+/// Loads a session's registrations with camper and guardian details, keeping only
+/// registrations that carry every tag in `tag_filter`.
+pub fn load_roster(
+    conn: &mut PgConnection,
+    session: Uuid,
+    tag_filter: &[String],
+) -> Result<Vec<RosterEntry>, diesel::result::Error> {
+    use crate::database::schema::{campers, guardians, registrations};
+
+    let mut query = registrations::table
+        .filter(registrations::session_id.eq(session))
+        .order(registrations::created_at.asc())
+        .into_boxed();
+    if !tag_filter.is_empty() {
+        let tagged = ids_with_all_tags(conn, REGISTRATION, tag_filter)?;
+        query = query.filter(registrations::id.eq_any(tagged));
+    }
+    let rows = query.load::<Registration>(conn)?;
+
+    let registration_ids: Vec<Uuid> = rows.iter().map(|r| r.id).collect();
+    let camper_ids: Vec<Uuid> = rows.iter().map(|r| r.camper_id).collect();
+    let guardian_ids: Vec<Uuid> = rows.iter().map(|r| r.guardian_id).collect();
+    let camper_rows = campers::table
+        .filter(campers::id.eq_any(&camper_ids))
+        .load::<Camper>(conn)?;
+    let guardian_rows = guardians::table
+        .filter(guardians::id.eq_any(&guardian_ids))
+        .load::<Guardian>(conn)?;
+    let mut tag_map = tags_for(conn, REGISTRATION, &registration_ids)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|registration| {
+            let camper = camper_rows.iter().find(|c| c.id == registration.camper_id);
+            let guardian = guardian_rows
+                .iter()
+                .find(|g| g.id == registration.guardian_id);
+            RosterEntry {
+                registration_id: registration.id,
+                status: registration.status,
+                camper_id: registration.camper_id,
+                camper_first_name: camper.map(|c| c.first_name.clone()).unwrap_or_default(),
+                camper_last_name: camper.map(|c| c.last_name.clone()).unwrap_or_default(),
+                birthdate: camper.map(|c| c.birthdate),
+                guardian_name: guardian.map(|g| g.name.clone()).unwrap_or_default(),
+                guardian_email: guardian.map(|g| g.email.clone()).unwrap_or_default(),
+                tags: tag_map.remove(&registration.id).unwrap_or_default(),
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct RosterResponse {
+    pub session_id: Uuid,
+    pub session_name: String,
+    /// The tag filter applied; entries carry every tag requested.
+    pub tags: Vec<String>,
+    pub roster: Vec<RosterEntry>,
+}
+
+/// GET /admin/sessions/{id}/roster?tags= lists a session's registrations.
+#[tracing::instrument(skip(state))]
+pub async fn roster_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+    Query(query): Query<RosterQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let session = load_session(&mut conn, session_id)?;
+    let tag_filter = parse_tag_filter(query.tags.as_deref());
+    let roster = load_roster(&mut conn, session.id, &tag_filter)
+        .map_err(db_error("Failed to load roster"))?;
+
+    Ok(axum::Json(json!(RosterResponse {
+        session_id: session.id,
+        session_name: session.name,
+        tags: tag_filter,
+        roster,
+    })))
+}
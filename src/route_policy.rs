@@ -0,0 +1,438 @@
+//! Central authorization policy for every HTTP route.
+//!
+//! Each route the router serves is declared in [`ROUTE_POLICIES`] with the access it
+//! requires. [`enforce_route_policy`] runs as a route layer, resolves the caller's
+//! [`Actor`] and rejects requests the policy or the token's scopes do not allow
+//! (read-only tokens may only call reads), so handlers only perform data-level
+//! checks (such as a guardian owning a camper). Routes without a declared policy
+//! are refused. axum answers `HEAD` with the `GET` handler, so `HEAD` requests are
+//! held to the route's `GET` policy.
+use crate::auth::{Actor, Role};
+use axum::{
+    extract::{Extension, FromRequestParts, MatchedPath, Request},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{error, warn};
+
+const STAFF: &[Role] = &[Role::Admin, Role::Director, Role::Counselor, Role::Nurse];
+const MANAGERS: &[Role] = &[Role::Admin, Role::Director];
+const ADMINS: &[Role] = &[Role::Admin];
+const FAMILY_AND_MANAGERS: &[Role] = &[Role::Admin, Role::Director, Role::Guardian];
+const GUARDIANS: &[Role] = &[Role::Guardian];
+
+/// Access a route requires.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "roles")]
+pub enum Access {
+    /// No token required.
+    Public,
+    /// Any valid token.
+    Authenticated,
+    /// A token carrying one of the listed roles.
+    Roles(&'static [Role]),
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RoutePolicy {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub access: Access,
+}
+
+const fn policy(method: &'static str, path: &'static str, access: Access) -> RoutePolicy {
+    RoutePolicy {
+        method,
+        path,
+        access,
+    }
+}
+
+/// The policy table. Paths use the same syntax as the router.
+pub const ROUTE_POLICIES: &[RoutePolicy] = &[
+    policy("GET", "/hello", Access::Public),
+    policy("GET", "/ready", Access::Public),
+    policy("GET", "/stripe_key", Access::Public),
+    policy("POST", "/payment_sheet", Access::Public),
+    policy("POST", "/webhook", Access::Public),
+    policy("GET", "/payment_status", Access::Public),
+    policy("POST", "/quote", Access::Roles(FAMILY_AND_MANAGERS)),
+    policy("POST", "/vouchers", Access::Public),
+    policy("POST", "/vouchers/redeem", Access::Public),
+    policy("GET", "/vouchers/purchases/{id}", Access::Public),
+    policy("GET", "/vouchers/{code}", Access::Public),
+    policy(
+        "GET",
+        "/guardians/{id}/credits",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/guardians/{id}/waitlist",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/guardians/{id}/activity",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy("POST", "/waitlist", Access::Roles(FAMILY_AND_MANAGERS)),
+    policy("GET", "/sessions", Access::Public),
+    policy("GET", "/sessions/{id}", Access::Public),
+    policy("GET", "/public/sessions/availability", Access::Public),
+    policy("POST", "/admin/sessions", Access::Roles(MANAGERS)),
+    policy(
+        "POST",
+        "/admin/sessions/{id}/staff",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "POST",
+        "/admin/sessions/{id}/cancel",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/admin/sessions/{id}/cancellation",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/admin/sessions/{id}/activity",
+        Access::Roles(MANAGERS),
+    ),
+    policy("GET", "/admin/sessions/at_risk", Access::Roles(STAFF)),
+    policy(
+        "PUT",
+        "/admin/sessions/{id}/minimum_enrollment",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "POST",
+        "/admin/sessions/{id}/cancel_under_enrolled",
+        Access::Roles(MANAGERS),
+    ),
+    policy("POST", "/admin/staff", Access::Roles(MANAGERS)),
+    policy(
+        "POST",
+        "/admin/staff/{id}/certifications",
+        Access::Roles(MANAGERS),
+    ),
+    policy("GET", "/admin/staff/{id}/schedule", Access::Roles(STAFF)),
+    policy(
+        "DELETE",
+        "/admin/staff_assignments/{id}",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/admin/roles/certifications",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "PUT",
+        "/admin/roles/{role}/certifications",
+        Access::Roles(MANAGERS),
+    ),
+    policy("POST", "/campers", Access::Roles(FAMILY_AND_MANAGERS)),
+    policy("GET", "/campers/{id}", Access::Authenticated),
+    policy(
+        "GET",
+        "/campers/{id}/activity",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy(
+        "POST",
+        "/campers/{id}/corrections",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy("GET", "/campers/{id}/corrections", Access::Authenticated),
+    policy("GET", "/admin/corrections", Access::Roles(MANAGERS)),
+    policy(
+        "POST",
+        "/admin/corrections/{id}/approve",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "POST",
+        "/admin/corrections/{id}/reject",
+        Access::Roles(MANAGERS),
+    ),
+    // Non-nurse staff must supply a break-glass reason, enforced by the handler
+    policy("GET", "/campers/{id}/medical", Access::Authenticated),
+    policy(
+        "PUT",
+        "/campers/{id}/medical",
+        Access::Roles(&[Role::Admin, Role::Nurse, Role::Guardian]),
+    ),
+    policy(
+        "GET",
+        "/admin/compliance/medical_access",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/receipts/{payment_intent_id}",
+        Access::Authenticated,
+    ),
+    policy(
+        "GET",
+        "/receipts/{payment_intent_id}/pdf",
+        Access::Authenticated,
+    ),
+    policy("POST", "/registrations", Access::Roles(FAMILY_AND_MANAGERS)),
+    policy("GET", "/registrations/{id}", Access::Authenticated),
+    policy("GET", "/registrations/draft", Access::Roles(GUARDIANS)),
+    policy("POST", "/registrations/draft", Access::Roles(GUARDIANS)),
+    policy("GET", "/registrations/draft/{id}", Access::Roles(GUARDIANS)),
+    policy(
+        "PATCH",
+        "/registrations/draft/{id}",
+        Access::Roles(GUARDIANS),
+    ),
+    policy(
+        "POST",
+        "/registrations/draft/{id}/convert",
+        Access::Roles(GUARDIANS),
+    ),
+    policy(
+        "POST",
+        "/admin/registrations/import",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "POST",
+        "/me/registrations/{id}/cancel_preview",
+        Access::Roles(GUARDIANS),
+    ),
+    policy(
+        "POST",
+        "/me/registrations/{id}/cancel",
+        Access::Roles(GUARDIANS),
+    ),
+    policy("GET", "/me/billing_address", Access::Roles(GUARDIANS)),
+    policy("PUT", "/me/billing_address", Access::Roles(GUARDIANS)),
+    policy("GET", "/me/tax_summary", Access::Roles(GUARDIANS)),
+    policy(
+        "GET",
+        "/me/notification_preferences",
+        Access::Roles(GUARDIANS),
+    ),
+    policy(
+        "PUT",
+        "/me/notification_preferences",
+        Access::Roles(GUARDIANS),
+    ),
+    policy(
+        "POST",
+        "/registrations/{id}/delegations",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/registrations/{id}/delegations",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy(
+        "DELETE",
+        "/registrations/{id}/delegations/{link_id}",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    // Staff read the pickup list at check-out; guardians only see their own
+    policy("GET", "/registrations/{id}/pickups", Access::Authenticated),
+    policy(
+        "POST",
+        "/registrations/{id}/pickups",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    policy(
+        "DELETE",
+        "/registrations/{id}/pickups/{pickup_id}",
+        Access::Roles(FAMILY_AND_MANAGERS),
+    ),
+    // Delegated link holders authenticate with the link token in the path
+    policy("GET", "/delegated/{token}", Access::Public),
+    policy("POST", "/delegated/{token}/payment_sheet", Access::Public),
+    policy("POST", "/sync", Access::Roles(STAFF)),
+    policy("POST", "/check_in/scan", Access::Roles(STAFF)),
+    policy(
+        "GET",
+        "/admin/payments/{id}/deliveries",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/admin/payments/{id}/timeline",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/admin/registrations/{id}/deliveries",
+        Access::Roles(MANAGERS),
+    ),
+    policy("POST", "/admin/api_tokens", Access::Roles(ADMINS)),
+    policy("POST", "/admin/api_tokens/auditor", Access::Roles(ADMINS)),
+    policy("DELETE", "/admin/api_tokens/{id}", Access::Roles(ADMINS)),
+    policy("GET", "/admin/route_policies", Access::Roles(MANAGERS)),
+    policy("GET", "/admin/sessions/{id}/roster", Access::Roles(STAFF)),
+    policy("GET", "/admin/sessions/{id}/pickups", Access::Roles(STAFF)),
+    policy("GET", "/admin/sessions/{id}/cabins", Access::Roles(STAFF)),
+    policy(
+        "PUT",
+        "/admin/sessions/{id}/cabins",
+        Access::Roles(MANAGERS),
+    ),
+    policy("GET", "/admin/tags", Access::Roles(STAFF)),
+    policy(
+        "POST",
+        "/admin/registrations/{id}/tags",
+        Access::Roles(STAFF),
+    ),
+    policy(
+        "DELETE",
+        "/admin/registrations/{id}/tags/{tag}",
+        Access::Roles(STAFF),
+    ),
+    policy("GET", "/admin/alerts", Access::Roles(MANAGERS)),
+    policy(
+        "POST",
+        "/admin/alerts/{id}/acknowledge",
+        Access::Roles(MANAGERS),
+    ),
+    policy("POST", "/admin/exports", Access::Roles(MANAGERS)),
+    policy("GET", "/admin/exports/{id}", Access::Roles(MANAGERS)),
+    policy("POST", "/admin/jobs/{name}", Access::Roles(ADMINS)),
+    policy("GET", "/admin/info", Access::Roles(ADMINS)),
+    policy("GET", "/admin/metrics", Access::Roles(ADMINS)),
+    policy("GET", "/admin/slo_status", Access::Roles(ADMINS)),
+    policy("GET", "/admin/usage", Access::Roles(ADMINS)),
+    policy("GET", "/admin/webhook_coverage", Access::Roles(ADMINS)),
+    policy("GET", "/admin/ws_connections", Access::Roles(ADMINS)),
+    policy(
+        "DELETE",
+        "/admin/ws_connections/{connection_id}",
+        Access::Roles(ADMINS),
+    ),
+    policy("GET", "/admin/payment_limits", Access::Roles(ADMINS)),
+    policy(
+        "POST",
+        "/admin/payment_limits/overrides",
+        Access::Roles(ADMINS),
+    ),
+    policy(
+        "GET",
+        "/admin/reports/payment_methods",
+        Access::Roles(MANAGERS),
+    ),
+    policy(
+        "GET",
+        "/admin/reports/session_revenue",
+        Access::Roles(MANAGERS),
+    ),
+    // Served only when APP_ENV is a development environment
+    policy("GET", "/dev/fixtures", Access::Public),
+];
+
+/// Route policies loaded at router construction, shared with the middleware.
+#[derive(Debug)]
+pub struct RoutePolicyRegistry {
+    policies: Vec<RoutePolicy>,
+}
+
+impl RoutePolicyRegistry {
+    /// Loads the policy table, rejecting duplicate declarations.
+    pub fn load() -> Result<Self, String> {
+        let policies = ROUTE_POLICIES.to_vec();
+        for (i, p) in policies.iter().enumerate() {
+            if policies[..i]
+                .iter()
+                .any(|q| q.method == p.method && q.path == p.path)
+            {
+                return Err(format!(
+                    "Duplicate route policy for {} {}",
+                    p.method, p.path
+                ));
+            }
+        }
+        Ok(Self { policies })
+    }
+
+    pub fn lookup(&self, method: &str, path: &str) -> Option<&RoutePolicy> {
+        self.policies
+            .iter()
+            .find(|p| p.method == method && p.path == path)
+    }
+
+    pub fn policies(&self) -> &[RoutePolicy] {
+        &self.policies
+    }
+}
+
+/// Middleware enforcing the declared policy for the matched route. Must be added
+/// with `route_layer` so the matched path is available.
+pub async fn enforce_route_policy(
+    Extension(registry): Extension<Arc<RoutePolicyRegistry>>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = match request.method() {
+        &Method::HEAD => Method::GET.as_str().to_string(),
+        method => method.as_str().to_string(),
+    };
+    let Some(path) = matched_path.as_ref().map(MatchedPath::as_str) else {
+        return next.run(request).await;
+    };
+
+    let Some(route_policy) = registry.lookup(&method, path) else {
+        error!("No route policy declared for {method} {path}; refusing request");
+        return (StatusCode::FORBIDDEN, "Route is not permitted".to_string()).into_response();
+    };
+
+    if let Access::Public = route_policy.access {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let actor = match Actor::from_request_parts(&mut parts, &()).await {
+        Ok(actor) => actor,
+        Err(rejection) => return rejection,
+    };
+
+    if let Access::Roles(roles) = route_policy.access {
+        if let Err(rejection) = actor.require_any(roles) {
+            warn!(
+                "Denied {method} {path} for role {} ({:?})",
+                actor.role, actor.subject_id
+            );
+            return rejection.into_response();
+        }
+    }
+
+    if let Err(rejection) = actor.require_scope_for(&method, path) {
+        warn!(
+            "Denied {method} {path} for read-only token {:?}",
+            actor.token_id
+        );
+        return rejection.into_response();
+    }
+
+    // Handlers extracting `Actor` reuse this resolution instead of repeating the lookup
+    parts.extensions.insert(actor.clone());
+    let mut response = next.run(Request::from_parts(parts, body)).await;
+    // Outer layers, such as usage tracking, attribute the request to the actor
+    response.extensions_mut().insert(actor);
+    response
+}
+
+/// GET /admin/route_policies lists the declared policy for every route.
+#[tracing::instrument(skip(registry))]
+pub async fn route_policies_handler(
+    Extension(registry): Extension<Arc<RoutePolicyRegistry>>,
+) -> axum::Json<Value> {
+    axum::Json(json!({ "route_policies": registry.policies() }))
+}
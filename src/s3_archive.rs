@@ -0,0 +1,55 @@
+//! Report archive in S3. Objects are written to the bucket named by `EXPORT_BUCKET`
+//! and handed to clients through short-lived presigned URLs.
+use aws_sdk_s3::{presigning::PresigningConfig, primitives::ByteStream, Client};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+static S3_CLIENT: OnceCell<Client> = OnceCell::const_new();
+
+async fn client() -> &'static Client {
+    S3_CLIENT
+        .get_or_init(|| async {
+            let config = aws_config::load_from_env().await;
+            Client::new(&config)
+        })
+        .await
+}
+
+/// Builds the S3 client ahead of the first export, loading the AWS configuration.
+pub async fn warm_up() {
+    client().await;
+}
+
+fn bucket() -> Result<String, String> {
+    std::env::var("EXPORT_BUCKET").map_err(|_| "EXPORT_BUCKET must be set".to_string())
+}
+
+/// Uploads an object to the export bucket.
+pub async fn upload(key: &str, body: Vec<u8>, content_type: &str) -> Result<(), String> {
+    client()
+        .await
+        .put_object()
+        .bucket(bucket()?)
+        .key(key)
+        .content_type(content_type)
+        .body(ByteStream::from(body))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to upload {key} to S3: {e}"))?;
+    Ok(())
+}
+
+/// Returns a presigned GET URL for an object in the export bucket.
+pub async fn presigned_url(key: &str, expires_in: Duration) -> Result<String, String> {
+    let presigning = PresigningConfig::expires_in(expires_in)
+        .map_err(|e| format!("Invalid presigning config: {e}"))?;
+    let request = client()
+        .await
+        .get_object()
+        .bucket(bucket()?)
+        .key(key)
+        .presigned(presigning)
+        .await
+        .map_err(|e| format!("Failed to presign {key}: {e}"))?;
+    Ok(request.uri().to_string())
+}
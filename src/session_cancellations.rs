@@ -0,0 +1,721 @@
+//! Cancelling a whole camp session.
+//!
+//! `POST /admin/sessions/{id}/cancel` marks the session cancelled, cancels every
+//! open registration, releases their holds and plans one `cancellation_refunds` row
+//! per registration, all in one transaction. Refund amounts come from the
+//! [`RefundPolicy`] (a camp cancellation refunds the full price). The card refund
+//! for a registration is capped at what is left refundable on the PaymentIntent
+//! that paid for it; anything beyond that, and anything paid without a card, is
+//! returned as camp credit instead. Every affected family is emailed.
+//!
+//! Card refunds are then issued through Stripe in batches of [`BATCH_SIZE`]: the
+//! first batch inline, the rest by the `cancellations` scheduled job. A refund that
+//! fails [`MAX_REFUND_ATTEMPTS`] times is marked failed and raises an admin alert.
+//! `GET /admin/sessions/{id}/cancellation` reports progress.
+use crate::alerts::{notify_slack, raise};
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{
+        CampCredit, CampSession, CancellationRefund, Guardian, NewCancellationRefund,
+        NewSessionCancellation, PaymentEvent, Registration, SessionCancellation,
+    },
+};
+use crate::events::{DomainEvent, EventRecorder, REGISTRATION_CANCELLED, SESSION_CANCELLED};
+use crate::holds::release_holds;
+use crate::notifications::{dispatch_pending, enqueue, Channel, Notification};
+use crate::payment_guard::PAYMENT_REVIEW;
+use crate::refund_policy::{CancellationCause, RefundDecision, RefundPolicy};
+use crate::refunds::record_refund;
+use crate::stripe_keys::{StripeCapability, StripeKeyring};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use stripe::{CreateRefund, PaymentIntentId, Refund, RefundReasonFilter, RequestStrategy};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Refunds issued per batch.
+pub const BATCH_SIZE: i64 = 25;
+/// Attempts per refund before it is marked failed.
+pub const MAX_REFUND_ATTEMPTS: i32 = 3;
+
+/// Registration statuses cancelled along with the session.
+pub(crate) const OPEN_STATUSES: &[&str] = &["pending", "confirmed", PAYMENT_REVIEW];
+/// Registration statuses that mean the family has paid.
+pub(crate) const PAID_STATUSES: &[&str] = &["confirmed", PAYMENT_REVIEW];
+
+/// Refund statuses that do not return money, so leave the amount refundable.
+const UNREFUNDED_STATUSES: &[&str] = &["failed", "canceled"];
+
+#[derive(Debug, Deserialize)]
+pub struct CancelSessionRequest {
+    pub reason: String,
+}
+
+/// Counts and amounts of a cancellation's refunds by status.
+#[derive(Debug, Serialize)]
+pub struct CancellationProgress {
+    pub cancellation: SessionCancellation,
+    pub total: usize,
+    pub pending: usize,
+    pub refunded: usize,
+    pub credited: usize,
+    pub failed: usize,
+    pub no_payment: usize,
+    pub refunded_amount: i64,
+    pub pending_amount: i64,
+    pub credited_amount: i64,
+    pub currency: String,
+}
+
+/// What one batch of refunds did.
+#[derive(Debug, Default, Serialize)]
+pub struct BatchSummary {
+    pub processed: usize,
+    pub refunded: usize,
+    pub retrying: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelSessionResponse {
+    pub progress: CancellationProgress,
+    pub families_notified: usize,
+    pub first_batch: BatchSummary,
+}
+
+/// The PaymentIntent that paid for a registration, recorded on its hold at checkout.
+fn paying_intent(
+    conn: &mut PgConnection,
+    registration: Uuid,
+) -> Result<Option<String>, diesel::result::Error> {
+    use crate::database::schema::registration_holds::dsl::*;
+
+    registration_holds
+        .filter(registration_id.eq(registration))
+        .filter(payment_intent_id.is_not_null())
+        .order(created_at.desc())
+        .select(payment_intent_id)
+        .first::<Option<String>>(conn)
+        .optional()
+        .map(Option::flatten)
+}
+
+/// Amount still refundable on a PaymentIntent in `currency`: what it collected, less
+/// refunds that went through and card refunds already planned by cancellations.
+fn refundable_on_intent(
+    conn: &mut PgConnection,
+    intent_id: &str,
+    currency: &str,
+) -> Result<i64, diesel::result::Error> {
+    use crate::database::schema::{cancellation_refunds, payment_events, refunds};
+
+    let payment = payment_events::table
+        .filter(payment_events::payment_intent_id.eq(intent_id))
+        .filter(payment_events::status.ilike(PaymentEvent::SUCCEEDED))
+        .order(payment_events::created_at.desc())
+        .first::<PaymentEvent>(conn)
+        .optional()?;
+    let Some(payment) = payment else {
+        return Ok(0);
+    };
+    if !payment
+        .currency
+        .as_deref()
+        .is_some_and(|c| c.eq_ignore_ascii_case(currency))
+    {
+        return Ok(0);
+    }
+
+    let refunded: i64 = refunds::table
+        .filter(refunds::payment_intent_id.eq(intent_id))
+        .filter(refunds::status.ne_all(UNREFUNDED_STATUSES))
+        .select(refunds::amount)
+        .load::<i64>(conn)?
+        .into_iter()
+        .sum();
+    // Issued cancellation refunds are already counted through `refunds`
+    let planned: i64 = cancellation_refunds::table
+        .filter(cancellation_refunds::payment_intent_id.eq(intent_id))
+        .filter(cancellation_refunds::status.eq("pending"))
+        .select(cancellation_refunds::amount)
+        .load::<i64>(conn)?
+        .into_iter()
+        .sum();
+    Ok((payment.amount.unwrap_or_default() - refunded - planned).max(0))
+}
+
+/// What a cancelled registration gets back, before anything is written.
+#[derive(Debug, Clone)]
+pub(crate) struct RefundQuote {
+    pub decision: RefundDecision,
+    pub payment_intent_id: Option<String>,
+    /// Refunded to the card.
+    pub card_amount: i64,
+    /// Returned as camp credit because the card cannot take it.
+    pub credit_amount: i64,
+}
+
+impl RefundQuote {
+    pub fn total(&self) -> i64 {
+        self.card_amount + self.credit_amount
+    }
+}
+
+/// Works out the refund owed for cancelling a registration: the policy amount on
+/// the session price, refunded to the card up to what is left refundable on the
+/// paying PaymentIntent, with the rest as credit. Unpaid registrations owe nothing.
+pub(crate) fn quote_refund(
+    conn: &mut PgConnection,
+    policy: &RefundPolicy,
+    cause: CancellationCause,
+    session: &CampSession,
+    registration: &Registration,
+    today: NaiveDate,
+) -> Result<RefundQuote, diesel::result::Error> {
+    let paid = PAID_STATUSES.contains(&registration.status.as_str());
+    let decision = policy.decide(
+        cause,
+        if paid { session.price } else { 0 },
+        session.starts_on,
+        today,
+    );
+    let payment_intent_id = if paid {
+        paying_intent(conn, registration.id)?
+    } else {
+        None
+    };
+    let card_amount = match &payment_intent_id {
+        Some(intent_id) => {
+            decision
+                .amount
+                .min(refundable_on_intent(conn, intent_id, &session.currency)?)
+        }
+        None => 0,
+    };
+    Ok(RefundQuote {
+        decision,
+        payment_intent_id,
+        card_amount,
+        credit_amount: decision.amount - card_amount,
+    })
+}
+
+/// Records a quoted refund for a cancelled registration: credits the credit part
+/// and plans the card part for the refund batches.
+pub(crate) fn plan_refund(
+    conn: &mut PgConnection,
+    cancellation_id: Option<Uuid>,
+    session: &CampSession,
+    registration: &Registration,
+    quote: RefundQuote,
+) -> Result<CancellationRefund, diesel::result::Error> {
+    let cause = quote.decision.cause.as_str();
+    if quote.credit_amount > 0 {
+        diesel::insert_into(crate::database::schema::camp_credits::table)
+            .values(&CampCredit::new(
+                registration.guardian_id,
+                quote.credit_amount,
+                session.currency.clone(),
+                format!("{cause}_cancellation"),
+                None,
+                None,
+            ))
+            .execute(conn)?;
+    }
+
+    let status = if quote.card_amount > 0 {
+        "pending"
+    } else if quote.credit_amount > 0 {
+        "credited"
+    } else {
+        "no_payment"
+    };
+    diesel::insert_into(crate::database::schema::cancellation_refunds::table)
+        .values(&NewCancellationRefund {
+            cancellation_id,
+            registration_id: registration.id,
+            guardian_id: registration.guardian_id,
+            payment_intent_id: quote.payment_intent_id,
+            amount: quote.card_amount,
+            credit_amount: quote.credit_amount,
+            currency: session.currency.clone(),
+            status: status.to_string(),
+            cause: cause.to_string(),
+        })
+        .get_result::<CancellationRefund>(conn)
+}
+
+/// Enqueues one `session_cancelled` email per family, listing their registrations
+/// and what they will get back.
+fn notify_families(
+    conn: &mut PgConnection,
+    session: &CampSession,
+    reason: &str,
+    planned: &[CancellationRefund],
+) -> Result<Vec<Uuid>, diesel::result::Error> {
+    use crate::database::schema::guardians;
+
+    let mut by_guardian: BTreeMap<Uuid, Vec<&CancellationRefund>> = BTreeMap::new();
+    for refund in planned {
+        by_guardian
+            .entry(refund.guardian_id)
+            .or_default()
+            .push(refund);
+    }
+
+    let mut notification_ids = Vec::new();
+    for (guardian_id, refunds) in by_guardian {
+        let guardian = guardians::table.find(guardian_id).first::<Guardian>(conn)?;
+        let payload = json!({
+            "type": "session_cancelled",
+            "session_id": session.id,
+            "session_name": session.name,
+            "reason": reason,
+            "registration_ids": refunds.iter().map(|r| r.registration_id).collect::<Vec<_>>(),
+            "refund_amount": refunds.iter().map(|r| r.amount).sum::<i64>(),
+            "credit_amount": refunds.iter().map(|r| r.credit_amount).sum::<i64>(),
+            "currency": session.currency,
+        });
+        notification_ids.push(enqueue(
+            conn,
+            Notification {
+                channel: Channel::Email,
+                target: guardian.email,
+                template: "session_cancelled".to_string(),
+                payload,
+                registration_id: refunds.first().map(|r| r.registration_id),
+                payment_intent_id: None,
+            },
+        )?);
+    }
+    Ok(notification_ids)
+}
+
+/// Marks in-progress cancellations with no pending refunds as completed.
+fn complete_finished(conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::{cancellation_refunds, session_cancellations};
+
+    let in_progress = session_cancellations::table
+        .filter(session_cancellations::status.eq("in_progress"))
+        .select(session_cancellations::id)
+        .load::<Uuid>(conn)?;
+    let refunding = cancellation_refunds::table
+        .filter(cancellation_refunds::cancellation_id.eq_any(&in_progress))
+        .filter(cancellation_refunds::status.eq("pending"))
+        .select(cancellation_refunds::cancellation_id)
+        .load::<Option<Uuid>>(conn)?;
+    let finished: Vec<Uuid> = in_progress
+        .into_iter()
+        .filter(|id| !refunding.contains(&Some(*id)))
+        .collect();
+    if finished.is_empty() {
+        return Ok(0);
+    }
+    let completed =
+        session_cancellations::table.filter(session_cancellations::id.eq_any(&finished));
+    diesel::update(completed)
+        .set((
+            session_cancellations::status.eq("completed"),
+            session_cancellations::completed_at.eq(Some(chrono::Utc::now().naive_utc())),
+        ))
+        .execute(conn)
+}
+
+fn load_progress(
+    conn: &mut PgConnection,
+    session: &CampSession,
+) -> Result<CancellationProgress, (StatusCode, String)> {
+    use crate::database::schema::{cancellation_refunds, session_cancellations};
+
+    let cancellation = session_cancellations::table
+        .filter(session_cancellations::session_id.eq(session.id))
+        .first::<SessionCancellation>(conn)
+        .optional()
+        .map_err(db_error("Failed to load cancellation"))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "Session has not been cancelled".to_string(),
+        ))?;
+    let refunds = cancellation_refunds::table
+        .filter(cancellation_refunds::cancellation_id.eq(cancellation.id))
+        .load::<CancellationRefund>(conn)
+        .map_err(db_error("Failed to load cancellation refunds"))?;
+
+    let count = |status: &str| refunds.iter().filter(|r| r.status == status).count();
+    let amount = |status: &str| {
+        refunds
+            .iter()
+            .filter(|r| r.status == status)
+            .map(|r| r.amount)
+            .sum::<i64>()
+    };
+    Ok(CancellationProgress {
+        total: refunds.len(),
+        pending: count("pending"),
+        refunded: count("refunded"),
+        credited: count("credited"),
+        failed: count("failed"),
+        no_payment: count("no_payment"),
+        refunded_amount: amount("refunded"),
+        pending_amount: amount("pending"),
+        credited_amount: refunds.iter().map(|r| r.credit_amount).sum(),
+        currency: session.currency.clone(),
+        cancellation,
+    })
+}
+
+/// Issues one planned refund through Stripe. The idempotency key is derived from
+/// the registration, so a retry after a timeout cannot refund twice.
+async fn issue_refund(
+    client: &stripe::Client,
+    planned: &CancellationRefund,
+) -> Result<Refund, String> {
+    let intent_id = planned
+        .payment_intent_id
+        .as_deref()
+        .ok_or("Refund has no payment intent")?
+        .parse::<PaymentIntentId>()
+        .map_err(|e| format!("Invalid payment intent id: {e}"))?;
+    let mut params = CreateRefund::new();
+    params.payment_intent = Some(intent_id);
+    params.amount = Some(planned.amount);
+    params.reason = Some(RefundReasonFilter::RequestedByCustomer);
+    params.metadata = Some(
+        [
+            (
+                "registration_id".to_string(),
+                planned.registration_id.to_string(),
+            ),
+            ("cause".to_string(), planned.cause.clone()),
+        ]
+        .into_iter()
+        .chain(
+            planned
+                .cancellation_id
+                .map(|id| ("cancellation_id".to_string(), id.to_string())),
+        )
+        .collect(),
+    );
+
+    let client = client
+        .clone()
+        .with_strategy(RequestStrategy::Idempotent(format!(
+            "cancellation-refund-{}",
+            planned.registration_id
+        )));
+    Refund::create(&client, params)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Issues up to [`BATCH_SIZE`] pending cancellation refunds, oldest first.
+pub async fn process_refund_batch(
+    state: &Arc<Mutex<AppState>>,
+    keyring: &StripeKeyring,
+) -> Result<BatchSummary, (StatusCode, String)> {
+    use crate::database::schema::cancellation_refunds::dsl::*;
+
+    let mut conn = conn_from_state(state).await?;
+    let batch = cancellation_refunds
+        .filter(status.eq("pending"))
+        .order(created_at.asc())
+        .limit(BATCH_SIZE)
+        .load::<CancellationRefund>(&mut conn)
+        .map_err(db_error("Failed to load pending refunds"))?;
+    issue_planned(state, keyring, &mut conn, batch).await
+}
+
+/// Issues one pending cancellation refund right away, rather than waiting for the
+/// next batch.
+pub(crate) async fn process_refund(
+    state: &Arc<Mutex<AppState>>,
+    keyring: &StripeKeyring,
+    refund_id: Uuid,
+) -> Result<BatchSummary, (StatusCode, String)> {
+    use crate::database::schema::cancellation_refunds::dsl::*;
+
+    let mut conn = conn_from_state(state).await?;
+    let batch = cancellation_refunds
+        .filter(id.eq(refund_id))
+        .filter(status.eq("pending"))
+        .load::<CancellationRefund>(&mut conn)
+        .map_err(db_error("Failed to load pending refund"))?;
+    issue_planned(state, keyring, &mut conn, batch).await
+}
+
+/// Issues the planned refunds, retrying failures on later runs and alerting once a
+/// refund runs out of attempts.
+async fn issue_planned(
+    state: &Arc<Mutex<AppState>>,
+    keyring: &StripeKeyring,
+    conn: &mut PgConnection,
+    batch: Vec<CancellationRefund>,
+) -> Result<BatchSummary, (StatusCode, String)> {
+    use crate::database::schema::cancellation_refunds::dsl::*;
+
+    let client = keyring.client(StripeCapability::Refunds, &state.lock().await.stripe_keys);
+
+    let mut summary = BatchSummary::default();
+    for planned in batch {
+        summary.processed += 1;
+        let now = chrono::Utc::now().naive_utc();
+        let attempt = planned.attempts + 1;
+        match issue_refund(&client, &planned).await {
+            Ok(refund) => {
+                let intent_id = planned.payment_intent_id.clone().unwrap_or_default();
+                let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    record_refund(conn, &refund, &intent_id)?;
+                    diesel::update(cancellation_refunds.find(planned.id))
+                        .set((
+                            status.eq("refunded"),
+                            stripe_refund_id.eq(Some(refund.id.to_string())),
+                            error.eq(None::<String>),
+                            attempts.eq(attempt),
+                            updated_at.eq(now),
+                        ))
+                        .execute(conn)
+                });
+                if let Err(e) = result {
+                    // Stripe has the refund; the idempotency key makes the next batch
+                    // pick up the same refund rather than issue another
+                    error!(
+                        "Failed to record refund {} for registration {}: {e}",
+                        refund.id, planned.registration_id
+                    );
+                    summary.retrying += 1;
+                    continue;
+                }
+                summary.refunded += 1;
+            }
+            Err(e) if attempt < MAX_REFUND_ATTEMPTS => {
+                warn!(
+                    "Refund for registration {} failed (attempt {attempt}): {e}",
+                    planned.registration_id
+                );
+                diesel::update(cancellation_refunds.find(planned.id))
+                    .set((
+                        error.eq(Some(e.clone())),
+                        attempts.eq(attempt),
+                        updated_at.eq(now),
+                    ))
+                    .execute(conn)
+                    .map_err(db_error("Failed to update cancellation refund"))?;
+                summary.retrying += 1;
+            }
+            Err(e) => {
+                let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    diesel::update(cancellation_refunds.find(planned.id))
+                        .set((
+                            status.eq("failed"),
+                            error.eq(Some(e.clone())),
+                            attempts.eq(attempt),
+                            updated_at.eq(now),
+                        ))
+                        .execute(conn)?;
+                    raise(
+                        conn,
+                        "cancellation_refund_failed",
+                        format!(
+                            "Refund of {} {} for registration {} failed after {attempt} attempts: {e}",
+                            planned.amount, planned.currency, planned.registration_id
+                        ),
+                        json!({
+                            "cancellation_id": planned.cancellation_id,
+                            "registration_id": planned.registration_id,
+                            "amount": planned.amount,
+                            "currency": planned.currency,
+                            "error": e,
+                        }),
+                        planned.payment_intent_id.clone(),
+                    )
+                });
+                let alert = result.map_err(db_error("Failed to record refund failure"))?;
+                notify_slack(&alert).await;
+                summary.failed += 1;
+            }
+        }
+    }
+
+    complete_finished(conn).map_err(db_error("Failed to complete cancellations"))?;
+    info!(
+        "Cancellation refund batch: {} processed, {} refunded, {} retrying, {} failed",
+        summary.processed, summary.refunded, summary.retrying, summary.failed
+    );
+    Ok(summary)
+}
+
+/// POST /admin/sessions/{id}/cancel cancels a session, its registrations, and
+/// refunds or credits every family.
+#[tracing::instrument(skip(state, keyring))]
+pub async fn cancel_session_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(keyring): Extension<Arc<StripeKeyring>>,
+    Path(session_id): Path<Uuid>,
+    Json(payload): Json<CancelSessionRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    if payload.reason.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "A cancellation reason is required".to_string(),
+        ));
+    }
+    let response =
+        cancel_session(&state, &keyring, &actor, session_id, payload.reason.trim()).await?;
+    Ok(axum::Json(json!(response)))
+}
+
+/// Cancels a session, its registrations, and refunds or credits every family.
+pub(crate) async fn cancel_session(
+    state: &Arc<Mutex<AppState>>,
+    keyring: &StripeKeyring,
+    actor: &Actor,
+    session_id: Uuid,
+    reason: &str,
+) -> Result<CancelSessionResponse, (StatusCode, String)> {
+    let policy = RefundPolicy::from_env().map_err(|e| {
+        error!("Invalid refund policy: {e}");
+        (StatusCode::INTERNAL_SERVER_ERROR, e)
+    })?;
+
+    let mut conn = conn_from_state(state).await?;
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        use crate::database::schema::{camp_sessions, registrations};
+
+        let session = camp_sessions::table
+            .find(session_id)
+            .for_update()
+            .first::<CampSession>(conn)
+            .optional()?;
+        let Some(session) = session else {
+            return Ok(Err((
+                StatusCode::NOT_FOUND,
+                "Session not found".to_string(),
+            )));
+        };
+        if session.cancelled_at.is_some() {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                "Session is already cancelled".to_string(),
+            )));
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        diesel::update(camp_sessions::table.find(session.id))
+            .set(camp_sessions::cancelled_at.eq(Some(now)))
+            .execute(conn)?;
+        let cancellation =
+            diesel::insert_into(crate::database::schema::session_cancellations::table)
+                .values(&NewSessionCancellation {
+                    session_id: session.id,
+                    reason: reason.to_string(),
+                    requested_by: actor.subject_id,
+                })
+                .get_result::<SessionCancellation>(conn)?;
+
+        let open = registrations::table
+            .filter(registrations::session_id.eq(session.id))
+            .filter(registrations::status.eq_any(OPEN_STATUSES))
+            .for_update()
+            .load::<Registration>(conn)?;
+        let ids: Vec<Uuid> = open.iter().map(|r| r.id).collect();
+        diesel::update(registrations::table.filter(registrations::id.eq_any(&ids)))
+            .set((
+                registrations::status.eq("cancelled"),
+                registrations::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        release_holds(conn, &ids, now)?;
+
+        let today = now.date();
+        let mut planned = Vec::with_capacity(open.len());
+        for registration in &open {
+            let quote = quote_refund(
+                conn,
+                &policy,
+                CancellationCause::Camp,
+                &session,
+                registration,
+                today,
+            )?;
+            planned.push(plan_refund(
+                conn,
+                Some(cancellation.id),
+                &session,
+                registration,
+                quote,
+            )?);
+        }
+        let mut events = vec![DomainEvent::new(
+            SESSION_CANCELLED,
+            format!("{} cancelled: {}", session.name, cancellation.reason),
+        )
+        .session(session.id)
+        .details(json!({ "registrations": open.len() }))];
+        events.extend(open.iter().zip(&planned).map(|(registration, refund)| {
+            DomainEvent::new(
+                REGISTRATION_CANCELLED,
+                format!(
+                    "Registration cancelled because {} was cancelled",
+                    session.name
+                ),
+            )
+            .registration(registration)
+            .details(json!({
+                "refund_amount": refund.amount,
+                "credit_amount": refund.credit_amount,
+                "currency": refund.currency,
+            }))
+        }));
+        EventRecorder::for_actor(actor).record_all(conn, events)?;
+        let notification_ids = notify_families(conn, &session, &cancellation.reason, &planned)?;
+        complete_finished(conn)?;
+        Ok(Ok((session, planned.len(), notification_ids)))
+    });
+    let (session, cancelled, notification_ids) =
+        result.map_err(db_error("Failed to cancel session"))??;
+    drop(conn);
+    info!(
+        "Cancelled session {} with {cancelled} registration(s), notifying {} families",
+        session.id,
+        notification_ids.len()
+    );
+
+    dispatch_pending(state, Some(&notification_ids)).await;
+    // The cancellation is committed; refunds left pending go out with the next job run
+    let first_batch = process_refund_batch(state, keyring)
+        .await
+        .unwrap_or_else(|(_, e)| {
+            error!("First cancellation refund batch failed: {e}");
+            BatchSummary::default()
+        });
+
+    let mut conn = conn_from_state(state).await?;
+    Ok(CancelSessionResponse {
+        progress: load_progress(&mut conn, &session)?,
+        families_notified: notification_ids.len(),
+        first_batch,
+    })
+}
+
+/// GET /admin/sessions/{id}/cancellation reports refund progress for a cancelled session.
+#[tracing::instrument(skip(state))]
+pub async fn cancellation_progress_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let session = crate::sessions::load_session(&mut conn, session_id)?;
+    Ok(axum::Json(json!(load_progress(&mut conn, &session)?)))
+}
@@ -0,0 +1,105 @@
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampSession, NewCampSession},
+};
+use crate::enrollment::validate_minimum_enrollment;
+use crate::handlers::parse_currency;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+/// Loads a camp session, mapping a missing row to 404.
+pub fn load_session(
+    conn: &mut PgConnection,
+    session: Uuid,
+) -> Result<CampSession, (StatusCode, String)> {
+    crate::database::schema::camp_sessions::table
+        .find(session)
+        .first::<CampSession>(conn)
+        .optional()
+        .map_err(db_error("Failed to load session"))?
+        .ok_or((StatusCode::NOT_FOUND, "Session not found".to_string()))
+}
+
+/// POST /admin/sessions creates a camp session.
+#[tracing::instrument(skip(state))]
+pub async fn create_session_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(mut payload): Json<NewCampSession>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    info!("Received create session request: {:?}", payload);
+
+    if payload.ends_on < payload.starts_on {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Session must not end before it starts".to_string(),
+        ));
+    }
+    if payload.capacity < 0 || payload.price < 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Capacity and price must not be negative".to_string(),
+        ));
+    }
+    if payload.min_age.is_some_and(|age| age < 0)
+        || payload.max_age.is_some_and(|age| age < 0)
+        || matches!((payload.min_age, payload.max_age), (Some(min), Some(max)) if min > max)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Age range must be non-negative with min_age not above max_age".to_string(),
+        ));
+    }
+    if let Err(e) = validate_minimum_enrollment(
+        payload.min_enrollment,
+        payload.enrollment_cutoff,
+        payload.capacity,
+        payload.starts_on,
+    ) {
+        return Err((StatusCode::BAD_REQUEST, e));
+    }
+    parse_currency(&payload.currency)?;
+    payload.currency = payload.currency.to_lowercase();
+
+    let mut conn = conn_from_state(&state).await?;
+    let session = diesel::insert_into(crate::database::schema::camp_sessions::table)
+        .values(&payload)
+        .get_result::<CampSession>(&mut conn)
+        .map_err(db_error("Failed to create session"))?;
+
+    Ok(axum::Json(json!(session)))
+}
+
+/// GET /sessions lists camp sessions ordered by start date.
+#[tracing::instrument(skip(state))]
+pub async fn list_sessions_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::camp_sessions::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let sessions = camp_sessions
+        .order(starts_on.asc())
+        .load::<CampSession>(&mut conn)
+        .map_err(db_error("Failed to load sessions"))?;
+
+    Ok(axum::Json(json!({ "sessions": sessions })))
+}
+
+/// GET /sessions/{id} returns a single camp session.
+#[tracing::instrument(skip(state))]
+pub async fn get_session_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    Ok(axum::Json(json!(load_session(&mut conn, session)?)))
+}
@@ -0,0 +1,377 @@
+//! Per-route latency tracking and SLO burn-rate alerts.
+//!
+//! Every request's latency is recorded into a rolling one-hour histogram for its
+//! route, kept in one-minute buckets per Lambda instance. Routes with a latency SLO
+//! in `SLO_TARGETS` also count the requests slower than their threshold. The burn
+//! rate is the share of slow requests divided by the error budget
+//! (`1 - objective`); when it exceeds
+//! `SLO_BURN_RATE_THRESHOLD` (default 14.4) over both the last 5 minutes and the
+//! last hour, an admin alert is raised and pushed to Slack, at most once per
+//! `SLO_ALERT_COOLDOWN_MINUTES` (default 30) per route.
+//!
+//! `SLO_TARGETS` holds comma-separated `METHOD /path=THRESHOLDms@OBJECTIVE` entries,
+//! for example `POST /payment_sheet=1000ms@99,GET /sessions=300ms@99.5`. Without it,
+//! `POST /payment_sheet` is held to 1000ms for 99% of requests.
+use crate::alerts::{notify_slack, raise};
+use crate::database::get_conn;
+use crate::metrics;
+use axum::{
+    extract::{Extension, MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{error, warn};
+
+/// Upper bounds of the histogram buckets, in milliseconds. A final bucket holds the rest.
+const BUCKET_BOUNDS_MS: &[u64] = &[25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+/// Minutes of history kept per route.
+const WINDOW_MINUTES: i64 = 60;
+const SHORT_WINDOW_MINUTES: i64 = 5;
+/// Requests needed in the short window before it can trigger an alert.
+const MIN_REQUESTS: u64 = 20;
+
+const DEFAULT_TARGETS: &str = "POST /payment_sheet=1000ms@99";
+const DEFAULT_BURN_RATE_THRESHOLD: f64 = 14.4;
+const DEFAULT_ALERT_COOLDOWN_MINUTES: i64 = 30;
+
+/// A latency objective for one route.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SloTarget {
+    pub method: String,
+    pub path: String,
+    pub threshold_ms: u64,
+    /// Fraction of requests that must finish within the threshold, e.g. `0.99`.
+    pub objective: f64,
+}
+
+impl SloTarget {
+    fn parse(raw: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid SLO target '{raw}': expected METHOD /path=NNNms@PP");
+        let (route, spec) = raw.split_once('=').ok_or_else(invalid)?;
+        let (method, path) = route.trim().split_once(' ').ok_or_else(invalid)?;
+        let (threshold, objective) = spec.trim().split_once('@').ok_or_else(invalid)?;
+        let threshold_ms = threshold
+            .trim()
+            .strip_suffix("ms")
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .filter(|ms| *ms > 0)
+            .ok_or_else(invalid)?;
+        let percent = objective
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|p| *p > 0.0 && *p < 100.0)
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            method: method.trim().to_uppercase(),
+            path: path.trim().to_string(),
+            threshold_ms,
+            objective: percent / 100.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    minute: i64,
+    counts: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    total: u64,
+    slow: u64,
+}
+
+#[derive(Debug, Default)]
+struct RouteWindow {
+    buckets: Vec<Bucket>,
+    last_alert_minute: Option<i64>,
+}
+
+impl RouteWindow {
+    fn record(&mut self, minute: i64, latency_ms: u64, slow: bool) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![Bucket::default(); WINDOW_MINUTES as usize];
+        }
+        let bucket = &mut self.buckets[minute.rem_euclid(WINDOW_MINUTES) as usize];
+        if bucket.minute != minute {
+            *bucket = Bucket {
+                minute,
+                ..Bucket::default()
+            };
+        }
+        let index = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        bucket.counts[index] += 1;
+        bucket.total += 1;
+        if slow {
+            bucket.slow += 1;
+        }
+    }
+
+    /// Buckets recorded within the last `minutes` minutes.
+    fn recent(&self, now: i64, minutes: i64) -> impl Iterator<Item = &Bucket> {
+        self.buckets
+            .iter()
+            .filter(move |b| b.total > 0 && b.minute > now - minutes && b.minute <= now)
+    }
+
+    fn totals(&self, now: i64, minutes: i64) -> (u64, u64) {
+        self.recent(now, minutes)
+            .fold((0, 0), |(total, slow), b| (total + b.total, slow + b.slow))
+    }
+
+    /// Upper bound of the bucket holding the `quantile` request, or `None` past the last bound.
+    fn quantile_ms(&self, now: i64, quantile: f64) -> Option<u64> {
+        let mut counts = [0u64; BUCKET_BOUNDS_MS.len() + 1];
+        for bucket in self.recent(now, WINDOW_MINUTES) {
+            for (sum, count) in counts.iter_mut().zip(bucket.counts) {
+                *sum += count;
+            }
+        }
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = (quantile * total as f64).ceil() as u64;
+        let mut seen = 0;
+        for (i, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return BUCKET_BOUNDS_MS.get(i).copied();
+            }
+        }
+        None
+    }
+}
+
+fn burn_rate(total: u64, slow: u64, objective: f64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (slow as f64 / total as f64) / (1.0 - objective)
+}
+
+/// Rolling latency histograms for every route, shared by the middleware and the
+/// status endpoint.
+#[derive(Debug)]
+pub struct SloTracker {
+    targets: Vec<SloTarget>,
+    burn_rate_threshold: f64,
+    alert_cooldown_minutes: i64,
+    routes: Mutex<HashMap<(String, String), RouteWindow>>,
+}
+
+/// A burn-rate breach the middleware should alert on.
+#[derive(Debug)]
+struct Breach {
+    target: SloTarget,
+    short_burn_rate: f64,
+    long_burn_rate: f64,
+}
+
+impl SloTracker {
+    /// Loads the SLO targets and alerting thresholds from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        let raw_targets = env::var("SLO_TARGETS").unwrap_or_else(|_| DEFAULT_TARGETS.to_string());
+        let targets = raw_targets
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(SloTarget::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let burn_rate_threshold = match env::var("SLO_BURN_RATE_THRESHOLD") {
+            Ok(raw) => raw
+                .parse::<f64>()
+                .ok()
+                .filter(|t| *t > 0.0)
+                .ok_or_else(|| format!("Invalid SLO_BURN_RATE_THRESHOLD: {raw}"))?,
+            Err(_) => DEFAULT_BURN_RATE_THRESHOLD,
+        };
+        let alert_cooldown_minutes = match env::var("SLO_ALERT_COOLDOWN_MINUTES") {
+            Ok(raw) => raw
+                .parse::<i64>()
+                .ok()
+                .filter(|m| *m >= 0)
+                .ok_or_else(|| format!("Invalid SLO_ALERT_COOLDOWN_MINUTES: {raw}"))?,
+            Err(_) => DEFAULT_ALERT_COOLDOWN_MINUTES,
+        };
+        Ok(Self {
+            targets,
+            burn_rate_threshold,
+            alert_cooldown_minutes,
+            routes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn target(&self, method: &str, path: &str) -> Option<&SloTarget> {
+        self.targets
+            .iter()
+            .find(|t| t.method == method && t.path == path)
+    }
+
+    /// Records a request and returns a breach when it pushes the route over its
+    /// burn-rate threshold outside the alert cooldown.
+    fn record(&self, method: &str, path: &str, latency_ms: u64, minute: i64) -> Option<Breach> {
+        let target = self.target(method, path);
+        let slow = target.is_some_and(|t| latency_ms > t.threshold_ms);
+
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let window = routes
+            .entry((method.to_string(), path.to_string()))
+            .or_default();
+        window.record(minute, latency_ms, slow);
+
+        let target = target?;
+        let (short_total, short_slow) = window.totals(minute, SHORT_WINDOW_MINUTES);
+        let (long_total, long_slow) = window.totals(minute, WINDOW_MINUTES);
+        let short_burn_rate = burn_rate(short_total, short_slow, target.objective);
+        let long_burn_rate = burn_rate(long_total, long_slow, target.objective);
+        if short_total < MIN_REQUESTS
+            || short_burn_rate < self.burn_rate_threshold
+            || long_burn_rate < self.burn_rate_threshold
+        {
+            return None;
+        }
+        if window
+            .last_alert_minute
+            .is_some_and(|last| minute - last < self.alert_cooldown_minutes)
+        {
+            return None;
+        }
+        window.last_alert_minute = Some(minute);
+        Some(Breach {
+            target: target.clone(),
+            short_burn_rate,
+            long_burn_rate,
+        })
+    }
+
+    fn status(&self, now: i64) -> Vec<Value> {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut keys: Vec<&(String, String)> = routes.keys().collect();
+        keys.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        keys.into_iter()
+            .map(|key| {
+                let window = &routes[key];
+                let target = self.target(&key.0, &key.1);
+                let (requests, slow) = window.totals(now, WINDOW_MINUTES);
+                let windows: Vec<Value> = [SHORT_WINDOW_MINUTES, WINDOW_MINUTES]
+                    .into_iter()
+                    .map(|minutes| {
+                        let (total, slow) = window.totals(now, minutes);
+                        json!({
+                            "minutes": minutes,
+                            "requests": total,
+                            "slow": slow,
+                            "burn_rate": target.map(|t| burn_rate(total, slow, t.objective)),
+                        })
+                    })
+                    .collect();
+                json!({
+                    "method": key.0,
+                    "path": key.1,
+                    "requests": requests,
+                    "slow": target.map(|_| slow),
+                    "p50_ms": window.quantile_ms(now, 0.50),
+                    "p95_ms": window.quantile_ms(now, 0.95),
+                    "p99_ms": window.quantile_ms(now, 0.99),
+                    "target": target,
+                    "windows": windows,
+                    "last_alert_minutes_ago": window.last_alert_minute.map(|m| now - m),
+                })
+            })
+            .collect()
+    }
+}
+
+fn current_minute() -> i64 {
+    chrono::Utc::now().timestamp().div_euclid(60)
+}
+
+/// Persists and posts a burn-rate alert.
+async fn alert_breach(state: Option<Arc<tokio::sync::Mutex<AppState>>>, breach: Breach) {
+    let route = format!("{} {}", breach.target.method, breach.target.path);
+    metrics::increment("slo_burn_alerts_total", &[("route", &route)]);
+    let message = format!(
+        "{route} is burning its latency error budget at {:.1}x over 5 minutes and {:.1}x over the last hour (SLO: {}ms for {}% of requests)",
+        breach.short_burn_rate,
+        breach.long_burn_rate,
+        breach.target.threshold_ms,
+        breach.target.objective * 100.0
+    );
+    warn!("{message}");
+
+    let db_client = match state {
+        Some(state) => state.lock().await.database_client.clone(),
+        None => None,
+    };
+    let Some(db_client) = db_client else {
+        error!("Database client not available to record SLO alert for {route}");
+        return;
+    };
+    let alert = get_conn(&db_client.pool)
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| {
+            raise(
+                &mut conn,
+                "slo_burn_rate",
+                message,
+                json!({
+                    "target": breach.target,
+                    "short_window_burn_rate": breach.short_burn_rate,
+                    "long_window_burn_rate": breach.long_burn_rate,
+                }),
+                None,
+            )
+            .map_err(|e| e.to_string())
+        });
+    match alert {
+        Ok(alert) => notify_slack(&alert).await,
+        Err(e) => error!("Failed to record SLO alert for {route}: {e}"),
+    }
+}
+
+/// Middleware recording each request's latency against its matched route. Must be
+/// added with `route_layer` so the matched path is available.
+pub async fn track_latency(
+    Extension(tracker): Extension<Arc<SloTracker>>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(path) = matched_path.map(|p| p.as_str().to_string()) else {
+        return next.run(request).await;
+    };
+    let method = request.method().as_str().to_string();
+    let state = request
+        .extensions()
+        .get::<Arc<tokio::sync::Mutex<AppState>>>()
+        .cloned();
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let latency_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+    if let Some(breach) = tracker.record(&method, &path, latency_ms, current_minute()) {
+        // Awaited rather than spawned: Lambda may freeze before a detached task runs
+        alert_breach(state, breach).await;
+    }
+    response
+}
+
+/// GET /admin/slo_status lists this instance's latency histograms and SLO burn rates.
+#[tracing::instrument(skip(tracker))]
+pub async fn slo_status_handler(Extension(tracker): Extension<Arc<SloTracker>>) -> Json<Value> {
+    Json(json!({
+        "burn_rate_threshold": tracker.burn_rate_threshold,
+        "routes": tracker.status(current_minute()),
+    }))
+}
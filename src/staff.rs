@@ -0,0 +1,364 @@
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{
+        CampSession, NewStaff, NewStaffAssignment, NewStaffCertification, RoleCertification, Staff,
+        StaffAssignment, StaffCertification,
+    },
+};
+use crate::events::{DomainEvent, EventRecorder, STAFF_ASSIGNED, STAFF_UNASSIGNED};
+use crate::sessions::load_session;
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct CertificationRequest {
+    pub certification: String,
+    pub expires_on: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleCertificationsRequest {
+    pub certifications: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignmentRequest {
+    pub staff_id: Uuid,
+    pub role: String,
+}
+
+/// Loads a staff member, mapping a missing row to 404.
+fn load_staff(conn: &mut PgConnection, staff: Uuid) -> Result<Staff, (StatusCode, String)> {
+    crate::database::schema::staff::table
+        .find(staff)
+        .first::<Staff>(conn)
+        .optional()
+        .map_err(db_error("Failed to load staff member"))?
+        .ok_or((StatusCode::NOT_FOUND, "Staff member not found".to_string()))
+}
+
+/// Loads the sessions a staff member is assigned to, paired with the assignment.
+fn load_schedule(
+    conn: &mut PgConnection,
+    staff: Uuid,
+) -> Result<Vec<(StaffAssignment, CampSession)>, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, staff_assignments};
+
+    let assignments = staff_assignments::table
+        .filter(staff_assignments::staff_id.eq(staff))
+        .load::<StaffAssignment>(conn)?;
+    let session_ids: Vec<Uuid> = assignments.iter().map(|a| a.session_id).collect();
+    let sessions = camp_sessions::table
+        .filter(camp_sessions::id.eq_any(&session_ids))
+        .load::<CampSession>(conn)?;
+
+    let mut schedule: Vec<(StaffAssignment, CampSession)> = assignments
+        .into_iter()
+        .filter_map(|assignment| {
+            let session = sessions.iter().find(|s| s.id == assignment.session_id)?;
+            Some((assignment, session.clone()))
+        })
+        .collect();
+    schedule.sort_by_key(|(_, session)| session.starts_on);
+    Ok(schedule)
+}
+
+/// Returns the required certifications for `role` that the staff member lacks or
+/// whose expiry falls before the session ends.
+fn missing_certifications(
+    required: &[String],
+    held: &[StaffCertification],
+    session: &CampSession,
+) -> Vec<String> {
+    required
+        .iter()
+        .filter(|cert| {
+            !held.iter().any(|h| {
+                &h.certification == *cert
+                    && h.expires_on.is_none_or(|expiry| expiry >= session.ends_on)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+/// POST /admin/staff creates a staff member.
+#[tracing::instrument(skip(state))]
+pub async fn create_staff_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<NewStaff>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let staff = diesel::insert_into(crate::database::schema::staff::table)
+        .values(&payload)
+        .get_result::<Staff>(&mut conn)
+        .map_err(db_error("Failed to create staff member"))?;
+    info!("Created staff member {}", staff.id);
+    Ok(axum::Json(json!(staff)))
+}
+
+/// POST /admin/staff/{id}/certifications records (or renews) a certification held by a staff member.
+#[tracing::instrument(skip(state))]
+pub async fn add_certification_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(staff): Path<Uuid>,
+    Json(payload): Json<CertificationRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::staff_certifications::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    load_staff(&mut conn, staff)?;
+
+    let cert = diesel::insert_into(staff_certifications)
+        .values(&NewStaffCertification {
+            staff_id: staff,
+            certification: payload.certification,
+            expires_on: payload.expires_on,
+        })
+        .on_conflict((staff_id, certification))
+        .do_update()
+        .set(expires_on.eq(payload.expires_on))
+        .get_result::<StaffCertification>(&mut conn)
+        .map_err(db_error("Failed to save certification"))?;
+
+    Ok(axum::Json(json!(cert)))
+}
+
+/// PUT /admin/roles/{role}/certifications replaces the certifications required for a role.
+#[tracing::instrument(skip(state))]
+pub async fn set_role_certifications_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(staff_role): Path<String>,
+    Json(payload): Json<RoleCertificationsRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::role_certifications::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let rows: Vec<RoleCertification> = payload
+        .certifications
+        .into_iter()
+        .map(|cert| RoleCertification {
+            role: staff_role.clone(),
+            certification: cert,
+        })
+        .collect();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(role_certifications.filter(role.eq(&staff_role))).execute(conn)?;
+        diesel::insert_into(role_certifications)
+            .values(&rows)
+            .execute(conn)?;
+        Ok(())
+    })
+    .map_err(db_error("Failed to save role certifications"))?;
+
+    Ok(axum::Json(
+        json!({ "role": staff_role, "certifications": rows }),
+    ))
+}
+
+/// GET /admin/roles/certifications lists the certifications required per role.
+#[tracing::instrument(skip(state))]
+pub async fn list_role_certifications_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let rows = crate::database::schema::role_certifications::table
+        .load::<RoleCertification>(&mut conn)
+        .map_err(db_error("Failed to load role certifications"))?;
+    Ok(axum::Json(json!({ "role_certifications": rows })))
+}
+
+/// POST /admin/sessions/{id}/staff assigns a staff member to a session, rejecting
+/// double-bookings across overlapping sessions and missing role certifications.
+#[tracing::instrument(skip(state))]
+pub async fn assign_staff_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(session): Path<Uuid>,
+    Json(payload): Json<AssignmentRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::{role_certifications, staff, staff_certifications};
+
+    let mut conn = conn_from_state(&state).await?;
+    let target = load_session(&mut conn, session)?;
+    let member = load_staff(&mut conn, payload.staff_id)?;
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Lock the staff row so concurrent assignments are checked one at a time
+        staff::table
+            .find(payload.staff_id)
+            .for_update()
+            .select(staff::id)
+            .first::<Uuid>(conn)?;
+
+        let conflicts: Vec<Value> = load_schedule(conn, payload.staff_id)?
+            .into_iter()
+            .filter(|(_, other)| other.overlaps(&target))
+            .map(|(assignment, other)| {
+                json!({
+                    "assignment_id": assignment.id,
+                    "session_id": other.id,
+                    "session_name": other.name,
+                    "starts_on": other.starts_on,
+                    "ends_on": other.ends_on,
+                })
+            })
+            .collect();
+        if !conflicts.is_empty() {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                json!({ "error": "Staff member is double-booked", "conflicts": conflicts })
+                    .to_string(),
+            )));
+        }
+
+        let required = role_certifications::table
+            .filter(role_certifications::role.eq(&payload.role))
+            .select(role_certifications::certification)
+            .load::<String>(conn)?;
+        let held = staff_certifications::table
+            .filter(staff_certifications::staff_id.eq(payload.staff_id))
+            .load::<StaffCertification>(conn)?;
+        let missing = missing_certifications(&required, &held, &target);
+        if !missing.is_empty() {
+            return Ok(Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                json!({ "error": "Missing required certifications", "missing": missing })
+                    .to_string(),
+            )));
+        }
+
+        let assignment = diesel::insert_into(crate::database::schema::staff_assignments::table)
+            .values(&NewStaffAssignment {
+                staff_id: payload.staff_id,
+                session_id: session,
+                role: payload.role.clone(),
+            })
+            .get_result::<StaffAssignment>(conn)?;
+        EventRecorder::for_actor(&actor).record(
+            conn,
+            DomainEvent::new(
+                STAFF_ASSIGNED,
+                format!(
+                    "{} assigned to {} as {}",
+                    member.name, target.name, assignment.role
+                ),
+            )
+            .session(target.id)
+            .details(json!({
+                "assignment_id": assignment.id,
+                "staff_id": assignment.staff_id,
+                "role": assignment.role,
+            })),
+        )?;
+        Ok(Ok(assignment))
+    });
+
+    let assignment = result.map_err(db_error("Failed to assign staff"))??;
+    info!(
+        "Assigned staff {} to session {} as {}",
+        assignment.staff_id, assignment.session_id, assignment.role
+    );
+    Ok(axum::Json(json!(assignment)))
+}
+
+/// DELETE /admin/staff_assignments/{id} removes a staff assignment.
+#[tracing::instrument(skip(state))]
+pub async fn remove_assignment_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(assignment): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let deleted = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let deleted =
+                diesel::delete(crate::database::schema::staff_assignments::table.find(assignment))
+                    .get_result::<StaffAssignment>(conn)
+                    .optional()?;
+            if let Some(deleted) = &deleted {
+                EventRecorder::for_actor(&actor).record(
+                    conn,
+                    DomainEvent::new(
+                        STAFF_UNASSIGNED,
+                        format!("Staff removed as {}", deleted.role),
+                    )
+                    .session(deleted.session_id)
+                    .details(json!({
+                        "assignment_id": deleted.id,
+                        "staff_id": deleted.staff_id,
+                        "role": deleted.role,
+                    })),
+                )?;
+            }
+            Ok(deleted)
+        })
+        .map_err(db_error("Failed to remove assignment"))?;
+    if deleted.is_none() {
+        return Err((StatusCode::NOT_FOUND, "Assignment not found".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleEntry {
+    pub assignment_id: Uuid,
+    pub role: String,
+    pub session_id: Uuid,
+    pub session_name: String,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaffScheduleResponse {
+    pub staff: Staff,
+    pub certifications: Vec<StaffCertification>,
+    /// Assignments in session start order.
+    pub schedule: Vec<ScheduleEntry>,
+}
+
+/// GET /admin/staff/{id}/schedule returns a staff member's assignments in date order.
+#[tracing::instrument(skip(state))]
+pub async fn staff_schedule_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(staff): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let member = load_staff(&mut conn, staff)?;
+    let certifications = crate::database::schema::staff_certifications::table
+        .filter(crate::database::schema::staff_certifications::staff_id.eq(staff))
+        .load::<StaffCertification>(&mut conn)
+        .map_err(db_error("Failed to load certifications"))?;
+    let schedule: Vec<ScheduleEntry> = load_schedule(&mut conn, staff)
+        .map_err(db_error("Failed to load schedule"))?
+        .into_iter()
+        .map(|(assignment, session)| ScheduleEntry {
+            assignment_id: assignment.id,
+            role: assignment.role,
+            session_id: session.id,
+            session_name: session.name,
+            starts_on: session.starts_on,
+            ends_on: session.ends_on,
+        })
+        .collect();
+
+    Ok(axum::Json(json!(StaffScheduleResponse {
+        staff: member,
+        certifications,
+        schedule,
+    })))
+}
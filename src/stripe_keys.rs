@@ -0,0 +1,119 @@
+//! Stripe API keys per capability.
+//!
+//! `get_stripe_keys` loads one secret key with every permission. Restricted keys let
+//! each part of the app hold only what it needs, so a [`StripeKeyring`] maps each
+//! [`StripeCapability`] to a key and callers ask for the narrowest one:
+//!
+//! - `STRIPE_PAYMENTS_KEY` creates customers, ephemeral keys and PaymentIntents.
+//! - `STRIPE_REFUNDS_KEY` issues refunds.
+//! - `STRIPE_REPORTING_KEY` is a read-only key for reporting and reconciliation.
+//!
+//! A capability without its own key falls back to the secret key, so restricted
+//! keys can be introduced one at a time. All configured keys must be for the same
+//! mode, test or live.
+use crate::build_info::StripeMode;
+use crate::handlers::stripe_client;
+use lambda_lib::structs::StripeKeys;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+
+/// What a Stripe key is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StripeCapability {
+    Payments,
+    Refunds,
+    Reporting,
+}
+
+impl StripeCapability {
+    pub const ALL: [StripeCapability; 3] = [
+        StripeCapability::Payments,
+        StripeCapability::Refunds,
+        StripeCapability::Reporting,
+    ];
+
+    /// The environment variable holding the capability's key.
+    pub fn setting(self) -> &'static str {
+        match self {
+            StripeCapability::Payments => "STRIPE_PAYMENTS_KEY",
+            StripeCapability::Refunds => "STRIPE_REFUNDS_KEY",
+            StripeCapability::Reporting => "STRIPE_REPORTING_KEY",
+        }
+    }
+}
+
+/// The keys configured for individual capabilities. Never prints the keys.
+#[derive(Clone, Default)]
+pub struct StripeKeyring {
+    keys: BTreeMap<StripeCapability, String>,
+}
+
+impl fmt::Debug for StripeKeyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StripeKeyring")
+            .field("capabilities", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl StripeKeyring {
+    pub fn from_env() -> Result<Self, String> {
+        let setting = |capability: StripeCapability| env::var(capability.setting()).ok();
+        Self::from_settings(
+            setting(StripeCapability::Payments).as_deref(),
+            setting(StripeCapability::Refunds).as_deref(),
+            setting(StripeCapability::Reporting).as_deref(),
+        )
+    }
+
+    pub fn from_settings(
+        payments: Option<&str>,
+        refunds: Option<&str>,
+        reporting: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut keys = BTreeMap::new();
+        let mut mode = None;
+        for (capability, raw) in StripeCapability::ALL
+            .into_iter()
+            .zip([payments, refunds, reporting])
+        {
+            let Some(key) = raw.map(str::trim).filter(|k| !k.is_empty()) else {
+                continue;
+            };
+            let key_mode = StripeMode::from_secret_key(key);
+            if key_mode == StripeMode::Unknown {
+                return Err(format!(
+                    "Invalid {}: expected a Stripe secret (sk_) or restricted (rk_) key",
+                    capability.setting()
+                ));
+            }
+            if mode.is_some_and(|mode| mode != key_mode) {
+                return Err("Stripe capability keys mix test and live modes".to_string());
+            }
+            mode = Some(key_mode);
+            keys.insert(capability, key.to_string());
+        }
+        Ok(Self { keys })
+    }
+
+    /// Whether the capability has its own key rather than the secret key.
+    pub fn has_own_key(&self, capability: StripeCapability) -> bool {
+        self.keys.contains_key(&capability)
+    }
+
+    /// The key to use for a capability: its own key, or the secret key.
+    pub fn key(&self, capability: StripeCapability, fallback: &StripeKeys) -> String {
+        self.keys
+            .get(&capability)
+            .cloned()
+            .unwrap_or_else(|| fallback.secret_key.clone())
+    }
+
+    /// A Stripe client holding the capability's key.
+    pub fn client(&self, capability: StripeCapability, fallback: &StripeKeys) -> stripe::Client {
+        stripe_client(self.key(capability, fallback))
+    }
+}
@@ -1,4 +1,27 @@
-use crate::database::{get_conn, models::PaymentEvent};
+use crate::alerts::notify_slack;
+use crate::database::get_conn;
+use crate::database::models::{AdminAlert, PaymentEvent};
+use crate::db_health::DatabaseHealth;
+use crate::metrics;
+use crate::notifications::{dispatch_pending, enqueue, Channel, Notification};
+use crate::payment_flags::{flag_failed_payout, flag_failed_refund};
+use crate::payment_guard::{
+    check_amount_against_quote, flag_payment_mismatch, refund_late_payment, AmountCheck,
+};
+use crate::payment_metadata::PaymentMetadata;
+use crate::payment_methods::record_charge_method;
+use crate::quotes::{redeem_quote_credit, CreditRedemption};
+use crate::receipt_numbers::ReceiptNumbering;
+use crate::redact::{redact_payload, scrub_metadata, Redacted};
+use crate::refunds::{record_charge_refunds, record_refund};
+use crate::registrations::confirm_paid_registrations;
+use crate::support_tickets::SupportTickets;
+use crate::vouchers::{issue_voucher, VOUCHER_PURPOSE};
+use crate::webhook_coverage;
+use crate::webhook_failures::{FailureClass, FailureResponse, Failures, WebhookFailureResponses};
+use crate::webhook_filter::WebhookEventFilter;
+use crate::webhook_ordering::{Claim, WebhookOrdering};
+use crate::ws_delivery::{record_connection_deliveries, send_to_connections};
 use axum::{
     body::Body,
     extract::{Extension, FromRequest, FromRequestParts, Request},
@@ -9,10 +32,146 @@ use diesel::prelude::*;
 use hyper::StatusCode;
 use lambda_lib::structs::{AppState, PaymentIntentStatus};
 use serde_json::json;
+use std::fmt;
 use std::sync::Arc;
 use stripe::{Event, EventObject, EventType, Webhook};
 use tokio::sync::Mutex;
-use tracing::{error, info, trace};
+use tracing::{debug, error, info, trace};
+use uuid::Uuid;
+
+/// Why a webhook request was rejected. The response body carries a stable `code`
+/// for each class of malformed input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    MissingSignature,
+    MalformedSignatureHeader,
+    InvalidSignature,
+    StaleTimestamp,
+    UnreadableBody,
+    InvalidEncoding,
+    MalformedPayload(String),
+    UnexpectedObject { event_type: String },
+    Unavailable,
+    DatabaseUnavailable,
+    ProcessingFailed,
+}
+
+impl WebhookError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            WebhookError::MissingSignature => "missing_signature",
+            WebhookError::MalformedSignatureHeader => "malformed_signature_header",
+            WebhookError::InvalidSignature => "invalid_signature",
+            WebhookError::StaleTimestamp => "stale_timestamp",
+            WebhookError::UnreadableBody => "unreadable_body",
+            WebhookError::InvalidEncoding => "invalid_encoding",
+            WebhookError::MalformedPayload(_) => "malformed_payload",
+            WebhookError::UnexpectedObject { .. } => "unexpected_object",
+            WebhookError::Unavailable => "unavailable",
+            WebhookError::DatabaseUnavailable => "database_unavailable",
+            WebhookError::ProcessingFailed => "processing_failed",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            WebhookError::Unavailable
+            | WebhookError::DatabaseUnavailable
+            | WebhookError::ProcessingFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::MissingSignature => f.write_str("Missing Stripe-Signature header"),
+            WebhookError::MalformedSignatureHeader => {
+                f.write_str("Stripe-Signature header is malformed")
+            }
+            WebhookError::InvalidSignature => f.write_str("Signature does not match the payload"),
+            WebhookError::StaleTimestamp => {
+                f.write_str("Signature timestamp is outside the tolerance")
+            }
+            WebhookError::UnreadableBody => f.write_str("Request body could not be read"),
+            WebhookError::InvalidEncoding => f.write_str("Payload is not valid UTF-8"),
+            WebhookError::MalformedPayload(e) => write!(f, "Payload is not a Stripe event: {e}"),
+            WebhookError::UnexpectedObject { event_type } => {
+                write!(f, "{event_type} event does not carry the expected object")
+            }
+            WebhookError::Unavailable => f.write_str("Webhook processing is unavailable"),
+            WebhookError::DatabaseUnavailable => {
+                f.write_str("Database is unavailable; the event will be retried")
+            }
+            WebhookError::ProcessingFailed => {
+                f.write_str("Processing the event failed; it will be retried")
+            }
+        }
+    }
+}
+
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        (
+            self.status(),
+            axum::Json(json!({ "error": self.code(), "message": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+/// Checks that a `Stripe-Signature` header has a numeric `t=` timestamp and at least
+/// one non-empty hex `v1=` signature before it is handed to the Stripe library.
+fn check_signature_header(header: &str) -> Result<(), WebhookError> {
+    let mut has_timestamp = false;
+    let mut has_signature = false;
+    for part in header.split(',') {
+        let Some((key, value)) = part.trim().split_once('=') else {
+            return Err(WebhookError::MalformedSignatureHeader);
+        };
+        match key {
+            "t" => {
+                has_timestamp = value.parse::<i64>().is_ok();
+                if !has_timestamp {
+                    return Err(WebhookError::MalformedSignatureHeader);
+                }
+            }
+            "v1" => {
+                if value.is_empty() || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(WebhookError::MalformedSignatureHeader);
+                }
+                has_signature = true;
+            }
+            _ => {}
+        }
+    }
+    if has_timestamp && has_signature {
+        Ok(())
+    } else {
+        Err(WebhookError::MalformedSignatureHeader)
+    }
+}
+
+/// Verifies a webhook payload against its `Stripe-Signature` header and parses the event.
+pub fn verify_event(
+    payload: &[u8],
+    signature: Option<&str>,
+    webhook_secret: &str,
+) -> Result<Event, WebhookError> {
+    let signature = signature.ok_or(WebhookError::MissingSignature)?;
+    check_signature_header(signature)?;
+    let payload = std::str::from_utf8(payload).map_err(|_| WebhookError::InvalidEncoding)?;
+
+    Webhook::construct_event(payload, signature, webhook_secret).map_err(|e| match e {
+        stripe::WebhookError::BadTimestamp(_) => WebhookError::StaleTimestamp,
+        stripe::WebhookError::BadParse(e) => WebhookError::MalformedPayload(e.to_string()),
+        stripe::WebhookError::BadHeader(_) => WebhookError::MalformedSignatureHeader,
+        stripe::WebhookError::BadKey | stripe::WebhookError::BadSignature => {
+            WebhookError::InvalidSignature
+        }
+    })
+}
 
 /// Custom extractor for Stripe webhook events.
 pub struct StripeEvent(pub Event);
@@ -30,28 +189,24 @@ where
         let app_state = parts
             .extensions
             .get::<Arc<Mutex<AppState>>>()
-            .ok_or_else(|| StatusCode::BAD_REQUEST.into_response())?
+            .ok_or_else(|| WebhookError::Unavailable.into_response())?
             .clone();
 
         let state_guard = app_state.lock().await;
         trace!("Got App State for Stripe keys");
 
         let webhook_secret = state_guard.stripe_keys.webhook_secret.clone();
+        let db_client = state_guard.database_client.clone();
         drop(state_guard);
 
-        let signature = if let Some(sig) = parts.headers.get("stripe-signature") {
-            sig.to_owned()
-        } else {
-            return Err(StatusCode::BAD_REQUEST.into_response());
-        }
-        .to_str()
-        .map_err(|e| {
-            error!("Error converting payload to string: {e}");
-            StatusCode::BAD_REQUEST.into_response()
-        })?
-        .to_string();
+        let signature = parts
+            .headers
+            .get("stripe-signature")
+            .map(|sig| sig.to_str().map(str::to_string))
+            .transpose()
+            .map_err(|_| WebhookError::MalformedSignatureHeader.into_response())?;
 
-        trace!("Signature: {signature}");
+        trace!("Signature: {signature:?}");
 
         // Extract the payload from the request
         let payload = axum::body::Bytes::from_request(
@@ -61,24 +216,48 @@ where
         .await
         .map_err(|e| {
             error!("Error reading payload: {e:?}");
-            StatusCode::BAD_REQUEST.into_response()
+            WebhookError::UnreadableBody.into_response()
         })?;
 
-        let payload_str = String::from_utf8(payload.to_vec()).map_err(|e| {
-            error!("Error converting payload bytes to string: {e}");
-            StatusCode::BAD_REQUEST.into_response()
+        // Construct and verify the event.
+        let event = verify_event(&payload, signature.as_deref(), &webhook_secret).map_err(|e| {
+            error!("Rejected webhook ({}): {e}", e.code());
+            e.into_response()
         })?;
 
-        trace!("Payload: {payload_str}");
+        let event_type = event.type_.to_string();
+        let filtered = parts
+            .extensions
+            .get::<Arc<WebhookEventFilter>>()
+            .and_then(|filter| filter.check(&event_type));
+
+        // Count every verified event by type, filtered or not; a failure here must
+        // not lose the event
+        webhook_coverage::observe(&event_type, filtered.is_some());
+        if let Some(client) = db_client {
+            let counted = get_conn(&client.pool)
+                .map_err(|e| e.to_string())
+                .and_then(|mut conn| {
+                    webhook_coverage::record_received(&mut conn, &event_type, filtered.is_some())
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = counted {
+                debug!("Could not count {event_type} webhook event: {e}");
+            }
+        }
 
-        // Construct and verify the event.
-        let event =
-            Webhook::construct_event(&payload_str, &signature, &webhook_secret).map_err(|e| {
-                error!("Error constructing event: {e:?}");
-                StatusCode::BAD_REQUEST.into_response()
-            })?;
+        // Drop filtered event types before they are logged or processed
+        if let Some(reason) = filtered {
+            debug!("Ignoring {event_type} webhook event ({})", reason.as_str());
+            metrics::increment(
+                "webhook_events_filtered_total",
+                &[("event_type", &event_type), ("reason", reason.as_str())],
+            );
+            return Err((StatusCode::OK, "Webhook ignored".to_string()).into_response());
+        }
 
-        trace!("Event: {event:?}");
+        trace!("Payload: {}", redact_payload(&payload));
+        trace!("Event: {}", Redacted(&event));
         Ok(Self(event))
     }
 }
@@ -99,7 +278,7 @@ where
             Ok(bytes) => bytes,
             Err(e) => {
                 error!("Error reading request body: {e}");
-                return Err(StatusCode::BAD_REQUEST.into_response());
+                return Err(WebhookError::UnreadableBody.into_response());
             }
         };
 
@@ -112,22 +291,196 @@ where
     }
 }
 
-/// Webhook handler that processes Stripe events.
-#[tracing::instrument(skip(state))]
+/// Answers an event once processing is done: 200, unless a failure's class is
+/// configured to be retried.
+fn respond(responses: &WebhookFailureResponses, event_id: &str, failures: &Failures) -> Response {
+    match responses.resolve(event_id, failures) {
+        Some(FailureResponse::Retry)
+            if failures
+                .classes()
+                .contains(&FailureClass::DatabaseUnavailable)
+                && responses.response_for(FailureClass::DatabaseUnavailable)
+                    == FailureResponse::Retry =>
+        {
+            WebhookError::DatabaseUnavailable.into_response()
+        }
+        Some(FailureResponse::Retry) => WebhookError::ProcessingFailed.into_response(),
+        _ => (StatusCode::OK, "Webhook received".to_string()).into_response(),
+    }
+}
+
+/// Answers an event the database could not be reached for, by default with a 500
+/// so Stripe redelivers it once the database is back.
+fn database_unavailable(
+    health: &DatabaseHealth,
+    responses: &WebhookFailureResponses,
+    event_id: &str,
+    e: impl fmt::Display,
+) -> Response {
+    health.failed("webhook", &e);
+    let mut failures = Failures::default();
+    failures.record(
+        FailureClass::DatabaseUnavailable,
+        &format!("Could not record webhook event {event_id}"),
+        e,
+    );
+    respond(responses, event_id, &failures)
+}
+
+/// What recording a succeeded payment changed, acted on once it commits.
+#[derive(Debug, Default)]
+struct RecordedPayment {
+    alerts: Vec<AdminAlert>,
+    notification_ids: Vec<Uuid>,
+}
+
+/// The step of recording a succeeded payment that failed.
+#[derive(Debug)]
+struct PaymentStepFailed {
+    context: &'static str,
+    error: diesel::result::Error,
+}
+
+impl PaymentStepFailed {
+    fn at(context: &'static str) -> impl FnOnce(diesel::result::Error) -> Self {
+        move |error| Self { context, error }
+    }
+}
+
+impl From<diesel::result::Error> for PaymentStepFailed {
+    fn from(error: diesel::result::Error) -> Self {
+        Self {
+            context: "Failed to record payment",
+            error,
+        }
+    }
+}
+
+/// Applies a succeeded payment: numbers its receipt, issues a purchased voucher,
+/// redeems its quote's camp credit and confirms the registrations it paid for. A
+/// payment whose amount does not match its quote, or whose lapsed credit was spent
+/// elsewhere, is flagged instead and keeps the registrations on hold. Run it in a transaction, so a
+/// failed step leaves none of the others behind.
+fn record_succeeded_payment(
+    conn: &mut PgConnection,
+    receipt_numbering: &ReceiptNumbering,
+    metadata: &PaymentMetadata,
+    intent_id: &str,
+    amount: i64,
+    currency: &str,
+) -> Result<RecordedPayment, PaymentStepFailed> {
+    let mut recorded = RecordedPayment::default();
+
+    // Number the receipt for this payment
+    let receipt = receipt_numbering
+        .allocate(conn, intent_id, amount, currency)
+        .map_err(PaymentStepFailed::at("Failed to allocate receipt number"))?;
+    info!(
+        "Receipt {} for payment intent {intent_id}",
+        receipt.receipt_number
+    );
+
+    // Issue the voucher code for voucher purchases
+    if metadata.purpose.as_deref() == Some(VOUCHER_PURPOSE) {
+        match issue_voucher(conn, intent_id)
+            .map_err(PaymentStepFailed::at("Failed to issue voucher"))?
+        {
+            Some(voucher) => info!("Issued voucher {}", voucher.id),
+            None => info!("Voucher already issued for {intent_id}"),
+        }
+    }
+
+    // Redeem the quote's camp credit and confirm the registrations paid for by this
+    // intent, unless the amount does not match the server-side quote
+    if metadata.quote_id.is_none() && metadata.registration_ids.is_empty() {
+        return Ok(recorded);
+    }
+    match check_amount_against_quote(conn, metadata, amount, currency)
+        .map_err(PaymentStepFailed::at("Failed to check payment amount"))?
+    {
+        AmountCheck::Matches => {
+            if let Some(quote_id) = metadata.quote_id {
+                match redeem_quote_credit(conn, quote_id)
+                    .map_err(PaymentStepFailed::at("Failed to redeem camp credit"))?
+                {
+                    CreditRedemption::Nothing => {}
+                    CreditRedemption::Redeemed(debit) => info!(
+                        "Redeemed {} {} of camp credit for quote {quote_id}",
+                        -debit.amount, debit.currency
+                    ),
+                    // The payer was charged less than the registrations cost
+                    CreditRedemption::Shortfall { applied, available } => {
+                        let reason = format!(
+                            "Quote {quote_id} applied {applied} {currency} of camp credit after its reservation lapsed, and only {available} is still available"
+                        );
+                        error!("Credit shortfall for payment intent {intent_id}: {reason}");
+                        let alert = flag_payment_mismatch(
+                            conn, metadata, intent_id, amount, currency, reason,
+                        )
+                        .map_err(PaymentStepFailed::at("Failed to flag credit shortfall"))?;
+                        recorded.alerts.push(alert);
+                        return Ok(recorded);
+                    }
+                }
+            }
+            if metadata.registration_ids.is_empty() {
+                return Ok(recorded);
+            }
+            let (confirmed, ids) =
+                confirm_paid_registrations(conn, &metadata.registration_ids, intent_id)
+                    .map_err(PaymentStepFailed::at("Failed to confirm registrations"))?;
+            info!(
+                "Confirmed {} registration(s) for payment intent {intent_id}",
+                confirmed.len()
+            );
+            recorded.notification_ids = ids;
+            if let Some(alert) = refund_late_payment(conn, metadata, intent_id, amount, currency)
+                .map_err(PaymentStepFailed::at("Failed to refund late payment"))?
+            {
+                recorded.alerts.push(alert);
+            }
+        }
+        AmountCheck::Mismatch(reason) => {
+            error!("Amount mismatch for payment intent {intent_id}: {reason}");
+            let alert = flag_payment_mismatch(conn, metadata, intent_id, amount, currency, reason)
+                .map_err(PaymentStepFailed::at("Failed to flag payment mismatch"))?;
+            recorded.alerts.push(alert);
+        }
+    }
+    Ok(recorded)
+}
+
+/// Webhook handler that processes Stripe events. Payment intent events older than
+/// the last one applied to their intent are acknowledged and ignored; see
+/// [`crate::webhook_ordering`]. A redelivered event that was already recorded is
+/// acknowledged without being processed again. Failures are answered with a 500 or
+/// a 200 by class; see [`crate::webhook_failures`] and [`crate::db_health`].
+/// Repeated payment failures open support tickets; see [`crate::support_tickets`].
+#[tracing::instrument(skip_all, fields(event_id = %stripe_event.id, event_type = %stripe_event.type_))]
 #[axum::debug_handler]
 pub async fn webhook_handler(
     StripeEvent(stripe_event): StripeEvent,
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-) -> impl IntoResponse {
-    trace!("Processing webhook event: {stripe_event:?}");
-
-    // Extract payment intent status from event type
-    let status = match PaymentIntentStatus::try_from(stripe_event.type_) {
-        Ok(status) => status.to_string(),
-        Err(_) => {
-            info!("Non-payment-intent event type: {}", stripe_event.type_);
-            return (StatusCode::OK, "Webhook received".to_string());
-        }
+    Extension(ordering): Extension<Arc<WebhookOrdering>>,
+    Extension(receipt_numbering): Extension<Arc<ReceiptNumbering>>,
+    Extension(health): Extension<Arc<DatabaseHealth>>,
+    Extension(support_tickets): Extension<Arc<SupportTickets>>,
+    Extension(failure_responses): Extension<Arc<WebhookFailureResponses>>,
+) -> Response {
+    trace!("Processing webhook event: {}", Redacted(&stripe_event));
+    let mut failures = Failures::default();
+
+    let event_type = stripe_event.type_;
+    let unexpected_object = || {
+        let e = WebhookError::UnexpectedObject {
+            event_type: event_type.to_string(),
+        };
+        error!(
+            "Rejected webhook event {} ({}): {e}",
+            stripe_event.id,
+            e.code()
+        );
+        e.into_response()
     };
 
     match stripe_event.type_ {
@@ -140,151 +493,474 @@ pub async fn webhook_handler(
         | EventType::PaymentIntentAmountCapturableUpdated
         | EventType::PaymentIntentCreated
         | EventType::PaymentIntentProcessing => {
-            if let EventObject::PaymentIntent(payment_intent) = stripe_event.data.object {
-                info!(
-                    "Payment intent event: id={}, status={}",
-                    payment_intent.id, status
-                );
-
-                // Get currency as string if available
-                let currency = payment_intent.currency.to_string();
-
-                // Get customer ID if available
-                let customer_id = payment_intent.customer.as_ref().map(|c| c.id().to_string());
-
-                // Extract metadata to identify specific frontends that initiated this payment
-                let frontend_id = payment_intent
-                    .metadata
-                    .get("frontend_id")
-                    .and_then(|v| Some(v.as_str()))
-                    .map(|s| s.to_string());
-
-                // Save payment event to database
-                let payment_event = PaymentEvent::new(
-                    payment_intent.id.to_string(),
-                    status.clone(),
-                    Some(payment_intent.amount),
-                    Some(currency.clone()),
-                    customer_id.clone(),
-                    Some(json!(payment_intent.metadata)),
-                );
+            let EventObject::PaymentIntent(payment_intent) = stripe_event.data.object else {
+                return unexpected_object();
+            };
+
+            // Extract payment intent status from event type
+            let status = match PaymentIntentStatus::try_from(event_type) {
+                Ok(status) => status.to_string(),
+                Err(_) => {
+                    info!("No payment status for event type: {event_type}");
+                    return (StatusCode::OK, "Webhook received".to_string()).into_response();
+                }
+            };
+            info!(
+                "Payment intent event: id={}, status={}",
+                payment_intent.id, status
+            );
+
+            // Drop events older than the last one applied to this intent
+            let event_at = chrono::DateTime::from_timestamp(stripe_event.created, 0)
+                .map(|at| at.naive_utc())
+                .unwrap_or_else(|| chrono::Utc::now().naive_utc());
+            let db_client = state.lock().await.database_client.clone();
+            if let Some(db_client) = db_client {
+                match get_conn(&db_client.pool)
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut conn| {
+                        ordering
+                            .claim(
+                                &mut conn,
+                                payment_intent.id.as_str(),
+                                &status,
+                                stripe_event.id.as_str(),
+                                &event_type.to_string(),
+                                event_at,
+                            )
+                            .map_err(|e| e.to_string())
+                    }) {
+                    Ok(Claim::Applied) => {}
+                    Ok(Claim::Stale {
+                        last_event_id,
+                        last_event_at,
+                    }) => {
+                        info!(
+                            "Ignoring {event_type} event {} for payment intent {}: {last_event_id} from {last_event_at} was already applied",
+                            stripe_event.id, payment_intent.id
+                        );
+                        metrics::increment(
+                            "webhook_events_out_of_order_total",
+                            &[("event_type", &event_type.to_string())],
+                        );
+                        return (StatusCode::OK, "Webhook ignored".to_string()).into_response();
+                    }
+                    // Processing is idempotent, so an unordered event is safer than a lost one
+                    Err(e) => error!(
+                        "Failed to order event {} for payment intent {}: {e}",
+                        stripe_event.id, payment_intent.id
+                    ),
+                }
+            }
 
-                let db_client = state.lock().await.database_client.clone();
-                if let Some(db_client) = db_client {
-                    if let Ok(mut conn) = get_conn(&db_client.pool) {
-                        match diesel::insert_into(crate::database::schema::payment_events::table)
+            // Get currency as string if available
+            let currency = payment_intent.currency.to_string();
+
+            // Get customer ID if available
+            let customer_id = payment_intent.customer.as_ref().map(|c| c.id().to_string());
+
+            // Parse the typed metadata contract (frontend, vouchers, registrations)
+            let payment_metadata =
+                PaymentMetadata::parse(&payment_intent.metadata).unwrap_or_else(|e| {
+                    error!(
+                        "Invalid metadata on payment intent {}: {e}",
+                        payment_intent.id
+                    );
+                    PaymentMetadata::default()
+                });
+            let frontend_id = payment_metadata.frontend_id.clone();
+            let is_succeeded = matches!(stripe_event.type_, EventType::PaymentIntentSucceeded);
+            let mut notification_ids = Vec::new();
+
+            // Save payment event to database
+            let payment_event = PaymentEvent::new(
+                payment_intent.id.to_string(),
+                status.clone(),
+                Some(payment_intent.amount),
+                Some(currency.clone()),
+                customer_id.clone(),
+                Some(scrub_metadata(&payment_intent.metadata)),
+            )
+            .from_stripe_event(stripe_event.id.to_string());
+
+            let db_client = state.lock().await.database_client.clone();
+            match db_client.map(|client| get_conn(&client.pool)) {
+                Some(Ok(mut conn)) => {
+                    // Record the event and everything a succeeded payment changes in
+                    // one transaction, once per Stripe event
+                    let recorded = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                        use crate::database::schema::payment_events;
+
+                        let inserted = diesel::insert_into(payment_events::table)
                             .values(&payment_event)
-                            .execute(&mut conn)
-                        {
-                            Ok(_) => info!("Saved payment event to database"),
-                            Err(e) => error!("Failed to save payment event to database: {}", e),
+                            .on_conflict(payment_events::stripe_event_id)
+                            .do_nothing()
+                            .execute(conn)?;
+                        if inserted == 0 {
+                            return Ok(None);
+                        }
+                        if !is_succeeded {
+                            return Ok(Some(RecordedPayment::default()));
+                        }
+                        match conn.transaction(|conn| {
+                            record_succeeded_payment(
+                                conn,
+                                &receipt_numbering,
+                                &payment_metadata,
+                                payment_intent.id.as_str(),
+                                payment_intent.amount,
+                                &currency,
+                            )
+                        }) {
+                            Ok(recorded) => Ok(Some(recorded)),
+                            Err(failed) => {
+                                failures.record(
+                                    FailureClass::Persistence,
+                                    failed.context,
+                                    failed.error,
+                                );
+                                // Keep nothing of an event Stripe will redeliver, so the
+                                // redelivery records it in full
+                                if failure_responses.response_for(FailureClass::Persistence)
+                                    == FailureResponse::Retry
+                                {
+                                    Err(diesel::result::Error::RollbackTransaction)
+                                } else {
+                                    Ok(Some(RecordedPayment::default()))
+                                }
+                            }
+                        }
+                    });
+                    match recorded {
+                        Ok(None) => {
+                            health.recovered();
+                            info!(
+                                "Ignoring redelivered {event_type} event {} for payment intent {}: it was already recorded",
+                                stripe_event.id, payment_intent.id
+                            );
+                            metrics::increment(
+                                "webhook_events_redelivered_total",
+                                &[("event_type", &event_type.to_string())],
+                            );
+                            return (StatusCode::OK, "Webhook ignored".to_string()).into_response();
+                        }
+                        Ok(Some(recorded)) => {
+                            info!("Saved payment event to database");
+                            health.recovered();
+                            for alert in &recorded.alerts {
+                                notify_slack(alert).await;
+                            }
+                            notification_ids = recorded.notification_ids;
+                        }
+                        Err(e) => {
+                            if failures.is_empty() {
+                                health.failed("webhook", &e);
+                                failures.record(
+                                    FailureClass::Persistence,
+                                    "Failed to save payment event",
+                                    e,
+                                );
+                            }
+                            return respond(
+                                &failure_responses,
+                                stripe_event.id.as_str(),
+                                &failures,
+                            );
+                        }
+                    }
+
+                    // Open a support ticket when this family's payments keep failing
+                    if support_tickets.applies_to(&status) {
+                        match support_tickets.claim(
+                            &mut conn,
+                            payment_intent.id.as_str(),
+                            &status,
+                            customer_id.as_deref(),
+                        ) {
+                            Ok(claimed) => {
+                                for (record, ticket) in &claimed {
+                                    support_tickets.open(&mut conn, record, ticket).await;
+                                }
+                            }
+                            Err(e) => failures.record(
+                                FailureClass::Notification,
+                                "Failed to evaluate support ticket rules",
+                                e,
+                            ),
                         }
-                    } else {
-                        error!("Failed to get database connection from pool");
                     }
                 }
+                Some(Err(e)) => {
+                    return database_unavailable(
+                        &health,
+                        &failure_responses,
+                        stripe_event.id.as_str(),
+                        e,
+                    )
+                }
+                None => error!(
+                    "No database connection to record payment event {}",
+                    payment_intent.id
+                ),
+            }
 
-                // Create the notification message
-                let message = json!({
-                    "type": "payment_update",
-                    "payment_intent_id": payment_intent.id.to_string(),
-                    "status": status,
-                    "amount": payment_intent.amount,
-                    "currency": currency,
-                    "transaction_id": payment_intent.id.to_string(),
-                    "timestamp": chrono::Utc::now().to_rfc3339(),
-                    "customer_id": customer_id,
-                    "frontend_id": frontend_id,
-                })
-                .to_string();
-
-                // Find and notify relevant WebSocket connections
-                let db_client = &state.lock().await.database_client;
-                if let Some(db_client) = db_client {
-                    if let Ok(mut conn) = get_conn(&db_client.pool) {
-                        use crate::database::schema::websocket_connections::dsl::*;
-
-                        // Build a query that filters by payment_intent_id and active status
-                        let mut query = websocket_connections
-                            .filter(payment_intent_id.eq(payment_intent.id.to_string()))
-                            .filter(status.eq("active"))
-                            .into_boxed();
-
-                        // If we have a frontend_id in metadata, only send to connections from that frontend
-                        if let Some(frontend_identifier) = &frontend_id {
+            // Create the notification message
+            let update = json!({
+                "type": "payment_update",
+                "payment_intent_id": payment_intent.id.to_string(),
+                "status": status,
+                "amount": payment_intent.amount,
+                "currency": currency,
+                "transaction_id": payment_intent.id.to_string(),
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "customer_id": customer_id,
+                "frontend_id": frontend_id,
+            });
+            let message = update.to_string();
+
+            // Find and notify relevant WebSocket connections
+            let db_client = state.lock().await.database_client.clone();
+            if let Some(db_client) = db_client {
+                if let Ok(mut conn) = get_conn(&db_client.pool) {
+                    use crate::database::schema::websocket_connections::dsl::*;
+
+                    // Build a query that filters by payment_intent_id and active status
+                    let mut query = websocket_connections
+                        .filter(payment_intent_id.eq(payment_intent.id.to_string()))
+                        .filter(status.eq("active"))
+                        .into_boxed();
+
+                    // If we have a frontend_id in metadata, only send to connections from that frontend
+                    if let Some(frontend_identifier) = &frontend_id {
+                        info!(
+                            "Targeting WebSocket connections for frontend_id: {}",
+                            frontend_identifier
+                        );
+                        // This assumes you store the frontend_id in the customer_id or metadata field
+                        // You might need to adjust this based on your actual data model
+                        query = query.filter(customer_id.eq(frontend_identifier));
+                    }
+
+                    match query
+                        .select(crate::database::schema::websocket_connections::all_columns)
+                        .load::<crate::database::models::WebSocketConnection>(&mut conn)
+                    {
+                        Ok(connections) => {
                             info!(
-                                "Targeting WebSocket connections for frontend_id: {}",
-                                frontend_identifier
+                                "Found {} active connection(s) for payment intent {}",
+                                connections.len(),
+                                payment_intent.id
                             );
-                            // This assumes you store the frontend_id in the customer_id or metadata field
-                            // You might need to adjust this based on your actual data model
-                            query = query.filter(customer_id.eq(frontend_identifier));
-                        }
 
-                        match query
-                            .select(crate::database::schema::websocket_connections::all_columns)
-                            .load::<crate::database::models::WebSocketConnection>(&mut conn)
-                        {
-                            Ok(connections) => {
+                            // Send message to specific connections
+                            if !connections.is_empty() {
                                 info!(
-                                    "Found {} active connection(s) for payment intent {}",
+                                    "Sending payment update to {} connection(s) for payment intent {}",
                                     connections.len(),
                                     payment_intent.id
                                 );
 
-                                // Send message to specific connections
-                                if !connections.is_empty() {
-                                    info!(
-                                        "Sending payment update to {} connection(s) for payment intent {}",
-                                        connections.len(),
-                                        payment_intent.id
-                                    );
-
-                                    // Extract connection IDs for targeting
-                                    let connection_ids: Vec<String> = connections
-                                        .iter()
-                                        .map(|conn| conn.connection_id.clone())
-                                        .collect();
-
-                                    // Use the WebSocketService to send to specific clients
-                                    if let Some(ws_service) = &state.lock().await.websocket_service
-                                    {
-                                        if let Err(e) = ws_service
-                                            .send_message_to_clients(
-                                                &payment_intent.id.to_string(),
-                                                &message,
-                                                &connection_ids,
-                                            )
-                                            .await
-                                        {
-                                            error!("Failed to send message to connections: {}", e);
-                                        }
-                                    } else {
-                                        error!("WebSocket service not available in AppState");
-                                    }
-                                } else {
-                                    info!(
-                                        "No active connections found for payment intent {}",
-                                        payment_intent.id
-                                    );
-                                }
+                                // Extract connection IDs for targeting
+                                let connection_ids: Vec<String> = connections
+                                    .iter()
+                                    .map(|conn| conn.connection_id.clone())
+                                    .collect();
+
+                                let summary = send_to_connections(
+                                    &state,
+                                    &mut conn,
+                                    payment_intent.id.as_str(),
+                                    &message,
+                                    &connection_ids,
+                                )
+                                .await;
+                                record_connection_deliveries(
+                                    &mut conn,
+                                    &summary,
+                                    "payment_update",
+                                    payment_intent.id.as_str(),
+                                );
+                            } else {
+                                info!(
+                                    "No active connections found for payment intent {}",
+                                    payment_intent.id
+                                );
                             }
-                            Err(e) => {
-                                error!("Failed to fetch active connections: {}", e);
+                        }
+                        Err(e) => {
+                            failures.record(
+                                FailureClass::Notification,
+                                "Failed to fetch active connections",
+                                e,
+                            );
+                            // Leave the update in the outbox for the retry job
+                            match enqueue(
+                                &mut conn,
+                                Notification {
+                                    channel: Channel::WebSocket,
+                                    target: payment_intent.id.to_string(),
+                                    template: "payment_update".to_string(),
+                                    payload: update.clone(),
+                                    registration_id: None,
+                                    payment_intent_id: Some(payment_intent.id.to_string()),
+                                },
+                            ) {
+                                Ok(queued) => info!("Queued payment update {queued} for retry"),
+                                Err(e) => error!("Failed to queue payment update: {e}"),
                             }
                         }
                     }
                 }
             }
+
+            // Deliver the confirmation notifications enqueued above
+            if !notification_ids.is_empty() {
+                dispatch_pending(&state, Some(&notification_ids)).await;
+            }
         }
         EventType::PaymentMethodAttached => {
-            if let EventObject::PaymentMethod(payment_method) = stripe_event.data.object {
-                info!("PaymentMethod attached: id={}", payment_method.id);
-            }
+            let EventObject::PaymentMethod(payment_method) = stripe_event.data.object else {
+                return unexpected_object();
+            };
+            info!("PaymentMethod attached: id={}", payment_method.id);
         }
         EventType::ChargeSucceeded | EventType::ChargeUpdated => {
-            if let EventObject::Charge(charge) = stripe_event.data.object {
-                info!("Charge event: id={}, status={}", charge.id, charge.status);
+            let EventObject::Charge(charge) = stripe_event.data.object else {
+                return unexpected_object();
+            };
+            info!("Charge event: id={}, status={}", charge.id, charge.status);
+
+            // Record how the charge was paid, for the payment method mix report
+            if matches!(stripe_event.type_, EventType::ChargeSucceeded) {
+                let db_client = state.lock().await.database_client.clone();
+                match db_client.map(|client| get_conn(&client.pool)) {
+                    Some(Ok(mut conn)) => {
+                        if let Err(e) = record_charge_method(&mut conn, &charge) {
+                            failures.record(
+                                FailureClass::Persistence,
+                                &format!(
+                                    "Failed to record payment method for charge {}",
+                                    charge.id
+                                ),
+                                e,
+                            );
+                        }
+                    }
+                    Some(Err(e)) => {
+                        return database_unavailable(
+                            &health,
+                            &failure_responses,
+                            stripe_event.id.as_str(),
+                            e,
+                        )
+                    }
+                    None => error!(
+                        "No database connection to record payment method for charge {}",
+                        charge.id
+                    ),
+                }
+            }
+        }
+        EventType::ChargeRefunded => {
+            let EventObject::Charge(charge) = stripe_event.data.object else {
+                return unexpected_object();
+            };
+            info!("Charge refunded: id={}", charge.id);
+
+            let db_client = state.lock().await.database_client.clone();
+            match db_client.map(|client| get_conn(&client.pool)) {
+                Some(Ok(mut conn)) => match record_charge_refunds(&mut conn, &charge) {
+                    Ok(recorded) => info!("Recorded {recorded} refunds for charge {}", charge.id),
+                    Err(e) => failures.record(
+                        FailureClass::Persistence,
+                        &format!("Failed to record refunds for charge {}", charge.id),
+                        e,
+                    ),
+                },
+                Some(Err(e)) => {
+                    return database_unavailable(
+                        &health,
+                        &failure_responses,
+                        stripe_event.id.as_str(),
+                        e,
+                    )
+                }
+                None => error!(
+                    "No database connection to record refunds for charge {}",
+                    charge.id
+                ),
+            }
+        }
+        EventType::ChargeRefundUpdated => {
+            let EventObject::Refund(refund) = stripe_event.data.object else {
+                return unexpected_object();
+            };
+            info!(
+                "Refund updated: id={}, status={:?}",
+                refund.id, refund.status
+            );
+            let Some(intent_id) = refund.payment_intent.as_ref().map(|p| p.id().to_string()) else {
+                info!("Refund {} has no payment intent; not recorded", refund.id);
+                return (StatusCode::OK, "Webhook received".to_string()).into_response();
+            };
+
+            let db_client = state.lock().await.database_client.clone();
+            match db_client.map(|client| get_conn(&client.pool)) {
+                Some(Ok(mut conn)) => {
+                    let flagged = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                        record_refund(conn, &refund, &intent_id)?;
+                        flag_failed_refund(conn, &refund, &intent_id)
+                    });
+                    match flagged {
+                        Ok(Some(alert)) => notify_slack(&alert).await,
+                        Ok(None) => {}
+                        Err(e) => failures.record(
+                            FailureClass::Persistence,
+                            &format!("Failed to record refund {}", refund.id),
+                            e,
+                        ),
+                    }
+                }
+                Some(Err(e)) => {
+                    return database_unavailable(
+                        &health,
+                        &failure_responses,
+                        stripe_event.id.as_str(),
+                        e,
+                    )
+                }
+                None => error!("No database connection to record refund {}", refund.id),
+            }
+        }
+        EventType::PayoutFailed => {
+            let EventObject::Payout(payout) = stripe_event.data.object else {
+                return unexpected_object();
+            };
+            error!(
+                "Payout failed: id={}, failure_code={:?}",
+                payout.id, payout.failure_code
+            );
+
+            let db_client = state.lock().await.database_client.clone();
+            match db_client.map(|client| get_conn(&client.pool)) {
+                Some(Ok(mut conn)) => match flag_failed_payout(&mut conn, &payout) {
+                    Ok(Some(alert)) => notify_slack(&alert).await,
+                    Ok(None) => info!("Payout {} was already flagged", payout.id),
+                    Err(e) => failures.record(
+                        FailureClass::Persistence,
+                        &format!("Failed to flag payout {}", payout.id),
+                        e,
+                    ),
+                },
+                Some(Err(e)) => {
+                    return database_unavailable(
+                        &health,
+                        &failure_responses,
+                        stripe_event.id.as_str(),
+                        e,
+                    )
+                }
+                None => error!("No database connection to flag payout {}", payout.id),
             }
         }
         _ => {
@@ -292,5 +968,5 @@ pub async fn webhook_handler(
         }
     }
 
-    (StatusCode::OK, "Webhook received".to_string())
+    respond(&failure_responses, stripe_event.id.as_str(), &failures)
 }
@@ -0,0 +1,534 @@
+//! Support tickets for families whose payments keep failing.
+//!
+//! After each payment intent event is recorded, the rules in `TICKET_RULES` are
+//! checked against the payer's recent payment events. A rule is
+//! `name:status|status:threshold:window_hours`; the default,
+//! `repeated_payment_failures:payment_failed:2:168`, opens a ticket when a family's
+//! payments fail twice within a week. Statuses are compared ignoring case and
+//! underscores. A family is the payer's email from their PaymentSheet attempts, or
+//! the Stripe customer when the email is unknown.
+//!
+//! Tickets go to the [`Ticketing`] provider named by `TICKETING_PROVIDER`:
+//!
+//! - `webhook` posts the ticket as JSON to `TICKETING_WEBHOOK_URL`;
+//! - `jira` creates an issue in `JIRA_PROJECT_KEY` at `JIRA_BASE_URL`, as
+//!   `JIRA_EMAIL` with `JIRA_API_TOKEN`;
+//! - `zendesk` creates a ticket in `ZENDESK_SUBDOMAIN`, as `ZENDESK_EMAIL` with
+//!   `ZENDESK_API_TOKEN`, with the family as requester.
+//!
+//! Without a provider no tickets are opened. Each ticket is recorded in
+//! `support_tickets` before it is sent, and a family gets at most one ticket per
+//! rule every `TICKET_DEDUP_HOURS` (default 720); a ticket the provider rejected
+//! does not count, so the next failure tries again.
+use crate::database::models::{NewSupportTicket, SupportTicketRecord};
+use crate::metrics;
+use chrono::Duration;
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::env;
+use std::fmt;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const DEFAULT_RULES: &str = "repeated_payment_failures:payment_failed:2:168";
+const DEFAULT_DEDUP_HOURS: i64 = 30 * 24;
+const MAX_WINDOW_HOURS: i64 = 365 * 24;
+const PROVIDER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Lowercases a status and drops underscores and dashes, so `PaymentFailed` and
+/// `payment_failed` compare equal.
+pub fn normalize_status(status: &str) -> String {
+    status
+        .chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Opens a ticket when a family has `threshold` payment events with one of
+/// `statuses` within `window_hours`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TicketRule {
+    pub name: String,
+    pub statuses: Vec<String>,
+    pub threshold: i64,
+    pub window_hours: i64,
+}
+
+impl TicketRule {
+    pub fn matches(&self, status: &str) -> bool {
+        let status = normalize_status(status);
+        self.statuses.iter().any(|s| normalize_status(s) == status)
+    }
+}
+
+/// Parses comma-separated `name:status|status:threshold:window_hours` rules.
+pub fn parse_rules(raw: &str) -> Result<Vec<TicketRule>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let invalid = || {
+                format!(
+                    "Invalid TICKET_RULES entry '{entry}': expected name:status|status:threshold:window_hours"
+                )
+            };
+            let parts: Vec<&str> = entry.split(':').map(str::trim).collect();
+            let [name, statuses, threshold, window_hours] = parts[..] else {
+                return Err(invalid());
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(invalid());
+            }
+            let statuses: Vec<String> = statuses
+                .split('|')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect();
+            let threshold = threshold.parse::<i64>().ok().filter(|t| *t >= 1);
+            let window_hours = window_hours
+                .parse::<i64>()
+                .ok()
+                .filter(|w| (1..=MAX_WINDOW_HOURS).contains(w));
+            match (statuses.is_empty(), threshold, window_hours) {
+                (false, Some(threshold), Some(window_hours)) => Ok(TicketRule {
+                    name: name.to_string(),
+                    statuses,
+                    threshold,
+                    window_hours,
+                }),
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+/// What a provider is asked to open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SupportTicket {
+    pub rule: String,
+    pub family: String,
+    pub subject: String,
+    pub description: String,
+    pub payment_intent_ids: Vec<String>,
+    pub matching_events: i64,
+}
+
+impl SupportTicket {
+    pub fn new(
+        rule: &TicketRule,
+        family: &str,
+        payment_intent_ids: Vec<String>,
+        matching_events: i64,
+    ) -> Self {
+        Self {
+            rule: rule.name.clone(),
+            family: family.to_string(),
+            subject: format!("Payments keep failing for {family}"),
+            description: format!(
+                "{matching_events} payment event(s) with status {} in the last {} hours \
+                 (rule {}). Payment intents: {}.",
+                rule.statuses.join(" or "),
+                rule.window_hours,
+                rule.name,
+                payment_intent_ids.join(", ")
+            ),
+            payment_intent_ids,
+            matching_events,
+        }
+    }
+}
+
+/// A support desk tickets can be opened in.
+pub trait Ticketing: fmt::Debug + Send + Sync {
+    /// Name recorded with each ticket, e.g. `jira`.
+    fn provider(&self) -> &'static str;
+    /// Builds the request that opens the ticket.
+    fn request(&self, client: &reqwest::Client, ticket: &SupportTicket) -> reqwest::RequestBuilder;
+    /// The provider's id or key for the new ticket, from its response body.
+    fn reference(&self, response: &Value) -> Option<String>;
+}
+
+/// A credential that is never printed.
+#[derive(Clone)]
+struct Secret(String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+fn id_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Posts the ticket as JSON to a URL.
+#[derive(Debug, Clone)]
+pub struct WebhookTicketing {
+    url: String,
+}
+
+impl WebhookTicketing {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+        }
+    }
+}
+
+impl Ticketing for WebhookTicketing {
+    fn provider(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn request(&self, client: &reqwest::Client, ticket: &SupportTicket) -> reqwest::RequestBuilder {
+        client.post(&self.url).json(ticket)
+    }
+
+    fn reference(&self, response: &Value) -> Option<String> {
+        id_string(&response["id"])
+    }
+}
+
+/// Creates Jira issues through the REST API.
+#[derive(Debug, Clone)]
+pub struct JiraTicketing {
+    base_url: String,
+    email: String,
+    api_token: Secret,
+    project_key: String,
+}
+
+impl JiraTicketing {
+    pub fn new(base_url: &str, email: &str, api_token: &str, project_key: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            email: email.to_string(),
+            api_token: Secret(api_token.to_string()),
+            project_key: project_key.to_string(),
+        }
+    }
+}
+
+impl Ticketing for JiraTicketing {
+    fn provider(&self) -> &'static str {
+        "jira"
+    }
+
+    fn request(&self, client: &reqwest::Client, ticket: &SupportTicket) -> reqwest::RequestBuilder {
+        client
+            .post(format!("{}/rest/api/2/issue", self.base_url))
+            .basic_auth(&self.email, Some(&self.api_token.0))
+            .json(&json!({
+                "fields": {
+                    "project": { "key": self.project_key },
+                    "issuetype": { "name": "Task" },
+                    "summary": ticket.subject,
+                    "description": ticket.description,
+                    "labels": ["payment-failure", ticket.rule],
+                }
+            }))
+    }
+
+    fn reference(&self, response: &Value) -> Option<String> {
+        id_string(&response["key"])
+    }
+}
+
+/// Creates Zendesk tickets with the family as requester.
+#[derive(Debug, Clone)]
+pub struct ZendeskTicketing {
+    subdomain: String,
+    email: String,
+    api_token: Secret,
+}
+
+impl ZendeskTicketing {
+    pub fn new(subdomain: &str, email: &str, api_token: &str) -> Self {
+        Self {
+            subdomain: subdomain.to_string(),
+            email: email.to_string(),
+            api_token: Secret(api_token.to_string()),
+        }
+    }
+}
+
+impl Ticketing for ZendeskTicketing {
+    fn provider(&self) -> &'static str {
+        "zendesk"
+    }
+
+    fn request(&self, client: &reqwest::Client, ticket: &SupportTicket) -> reqwest::RequestBuilder {
+        let mut body = json!({
+            "ticket": {
+                "subject": ticket.subject,
+                "comment": { "body": ticket.description },
+                "tags": ["payment_failure", ticket.rule],
+            }
+        });
+        // Only an email can be a requester; Stripe customer ids cannot
+        if ticket.family.contains('@') {
+            body["ticket"]["requester"] = json!({ "email": ticket.family });
+        }
+        client
+            .post(format!(
+                "https://{}.zendesk.com/api/v2/tickets.json",
+                self.subdomain
+            ))
+            .basic_auth(format!("{}/token", self.email), Some(&self.api_token.0))
+            .json(&body)
+    }
+
+    fn reference(&self, response: &Value) -> Option<String> {
+        id_string(&response["ticket"]["id"])
+    }
+}
+
+/// The ticketing provider and rules, loaded at startup.
+#[derive(Debug)]
+pub struct SupportTickets {
+    provider: Option<Box<dyn Ticketing>>,
+    rules: Vec<TicketRule>,
+    dedup_hours: i64,
+}
+
+fn required(name: &str) -> Result<String, String> {
+    env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| format!("{name} is required by TICKETING_PROVIDER"))
+}
+
+impl SupportTickets {
+    pub fn from_env() -> Result<Self, String> {
+        let provider: Option<Box<dyn Ticketing>> = match env::var("TICKETING_PROVIDER")
+            .ok()
+            .map(|p| p.trim().to_lowercase())
+            .as_deref()
+        {
+            None | Some("") => None,
+            Some("webhook") => Some(Box::new(WebhookTicketing::new(&required(
+                "TICKETING_WEBHOOK_URL",
+            )?))),
+            Some("jira") => Some(Box::new(JiraTicketing::new(
+                &required("JIRA_BASE_URL")?,
+                &required("JIRA_EMAIL")?,
+                &required("JIRA_API_TOKEN")?,
+                &required("JIRA_PROJECT_KEY")?,
+            ))),
+            Some("zendesk") => Some(Box::new(ZendeskTicketing::new(
+                &required("ZENDESK_SUBDOMAIN")?,
+                &required("ZENDESK_EMAIL")?,
+                &required("ZENDESK_API_TOKEN")?,
+            ))),
+            Some(other) => {
+                return Err(format!(
+                    "Invalid TICKETING_PROVIDER '{other}': expected webhook, jira or zendesk"
+                ))
+            }
+        };
+        let rules = parse_rules(
+            env::var("TICKET_RULES")
+                .ok()
+                .as_deref()
+                .unwrap_or(DEFAULT_RULES),
+        )?;
+        let dedup_hours = match env::var("TICKET_DEDUP_HOURS") {
+            Ok(raw) => raw
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|h| (1..=MAX_WINDOW_HOURS).contains(h))
+                .ok_or_else(|| format!("Invalid TICKET_DEDUP_HOURS: {raw}"))?,
+            Err(_) => DEFAULT_DEDUP_HOURS,
+        };
+        Ok(Self::new(provider, rules, dedup_hours))
+    }
+
+    pub fn new(
+        provider: Option<Box<dyn Ticketing>>,
+        rules: Vec<TicketRule>,
+        dedup_hours: i64,
+    ) -> Self {
+        Self {
+            provider,
+            rules,
+            dedup_hours,
+        }
+    }
+
+    /// Whether a payment event with this status could open a ticket.
+    pub fn applies_to(&self, status: &str) -> bool {
+        self.provider.is_some() && self.rules.iter().any(|rule| rule.matches(status))
+    }
+
+    /// Records the tickets a payment event calls for, skipping families that
+    /// already have one for the rule within the dedup window. Send them with
+    /// [`SupportTickets::open`].
+    pub fn claim(
+        &self,
+        conn: &mut PgConnection,
+        intent_id: &str,
+        status: &str,
+        customer_id: Option<&str>,
+    ) -> Result<Vec<(SupportTicketRecord, SupportTicket)>, diesel::result::Error> {
+        use crate::database::schema::{payment_events, payment_sheet_attempts, support_tickets};
+
+        let Some(provider) = &self.provider else {
+            return Ok(Vec::new());
+        };
+        let email = payment_sheet_attempts::table
+            .filter(payment_sheet_attempts::payment_intent_id.eq(intent_id))
+            .select(payment_sheet_attempts::customer_email)
+            .first::<String>(conn)
+            .optional()?;
+        let family_intents: Vec<String> = match (&email, customer_id) {
+            (Some(email), _) => payment_sheet_attempts::table
+                .filter(payment_sheet_attempts::customer_email.eq(email))
+                .select(payment_sheet_attempts::payment_intent_id)
+                .load::<String>(conn)?,
+            (None, Some(customer)) => payment_events::table
+                .filter(payment_events::customer_id.eq(customer))
+                .select(payment_events::payment_intent_id)
+                .distinct()
+                .load::<String>(conn)?,
+            (None, None) => return Ok(Vec::new()),
+        };
+        let Some(family) = email.or(customer_id.map(String::from)) else {
+            return Ok(Vec::new());
+        };
+
+        let now = chrono::Utc::now().naive_utc();
+        let mut claimed = Vec::new();
+        for rule in self.rules.iter().filter(|rule| rule.matches(status)) {
+            let since = now - Duration::hours(rule.window_hours);
+            let events = payment_events::table
+                .filter(payment_events::payment_intent_id.eq_any(&family_intents))
+                .filter(payment_events::created_at.ge(since))
+                .select((payment_events::payment_intent_id, payment_events::status))
+                .load::<(String, String)>(conn)?;
+            let mut intents = Vec::new();
+            let mut matching = 0;
+            for (intent, event_status) in events {
+                if rule.matches(&event_status) {
+                    matching += 1;
+                    if !intents.contains(&intent) {
+                        intents.push(intent);
+                    }
+                }
+            }
+            if matching < rule.threshold {
+                continue;
+            }
+
+            let record = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                // Serialize claims for the same rule and family
+                diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+                    .bind::<diesel::sql_types::Text, _>(format!("{}:{family}", rule.name))
+                    .execute(conn)?;
+                let dedup_since = now - Duration::hours(self.dedup_hours);
+                let recent = diesel::select(diesel::dsl::exists(
+                    support_tickets::table
+                        .filter(support_tickets::rule.eq(&rule.name))
+                        .filter(support_tickets::family.eq(&family))
+                        .filter(support_tickets::status.ne("failed"))
+                        .filter(support_tickets::created_at.ge(dedup_since)),
+                ))
+                .get_result::<bool>(conn)?;
+                if recent {
+                    return Ok(None);
+                }
+                diesel::insert_into(support_tickets::table)
+                    .values(&NewSupportTicket {
+                        id: Uuid::new_v4(),
+                        rule: rule.name.clone(),
+                        family: family.clone(),
+                        provider: provider.provider().to_string(),
+                        payment_intent_ids: intents.clone(),
+                    })
+                    .get_result::<SupportTicketRecord>(conn)
+                    .map(Some)
+            })?;
+            if let Some(record) = record {
+                claimed.push((record, SupportTicket::new(rule, &family, intents, matching)));
+            }
+        }
+        Ok(claimed)
+    }
+
+    /// Sends a claimed ticket to the provider and records the outcome.
+    pub async fn open(
+        &self,
+        conn: &mut PgConnection,
+        record: &SupportTicketRecord,
+        ticket: &SupportTicket,
+    ) {
+        use crate::database::schema::support_tickets::dsl::*;
+
+        let Some(ticketing) = &self.provider else {
+            return;
+        };
+        let outcome = send(ticketing.as_ref(), ticket).await;
+        let now = chrono::Utc::now().naive_utc();
+        let updated = match &outcome {
+            Ok(provider_reference) => {
+                info!(
+                    "Opened {} ticket {} for {}",
+                    ticketing.provider(),
+                    provider_reference.as_deref().unwrap_or("(no reference)"),
+                    ticket.family
+                );
+                diesel::update(support_tickets.find(record.id))
+                    .set((
+                        status.eq("opened"),
+                        reference.eq(provider_reference),
+                        updated_at.eq(now),
+                    ))
+                    .execute(conn)
+            }
+            Err(e) => {
+                error!(
+                    "Failed to open {} ticket for {}: {e}",
+                    ticketing.provider(),
+                    ticket.family
+                );
+                diesel::update(support_tickets.find(record.id))
+                    .set((status.eq("failed"), error.eq(e), updated_at.eq(now)))
+                    .execute(conn)
+            }
+        };
+        if let Err(e) = updated {
+            error!("Failed to record support ticket {}: {e}", record.id);
+        }
+        metrics::increment(
+            "support_tickets_total",
+            &[
+                ("provider", ticketing.provider()),
+                ("outcome", if outcome.is_ok() { "opened" } else { "failed" }),
+            ],
+        );
+    }
+}
+
+async fn send(ticketing: &dyn Ticketing, ticket: &SupportTicket) -> Result<Option<String>, String> {
+    let response = ticketing
+        .request(&reqwest::Client::new(), ticket)
+        .timeout(PROVIDER_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("request failed: {e}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("{} returned {status}", ticketing.provider()));
+    }
+    let body = response.json::<Value>().await.unwrap_or(Value::Null);
+    Ok(ticketing.reference(&body))
+}
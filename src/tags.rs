@@ -0,0 +1,218 @@
+//! Ad-hoc tags ("bus B", "swim-test pending") attachable to any record through the
+//! polymorphic `taggings` table. Registrations are currently the only taggable type.
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{NewTagging, Registration, Tag, Tagging},
+};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+/// `taggable_type` for registrations.
+pub const REGISTRATION: &str = "registration";
+
+#[derive(Debug, Deserialize)]
+pub struct TagRequest {
+    pub tag: String,
+}
+
+/// Normalizes a tag name: trimmed, lowercased, non-empty.
+pub fn normalize_tag(name: &str) -> Option<String> {
+    let name = name.trim().to_lowercase();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Parses a comma-separated `tags` query parameter.
+pub fn parse_tag_filter(tags: Option<&str>) -> Vec<String> {
+    tags.map(|t| t.split(',').filter_map(normalize_tag).collect())
+        .unwrap_or_default()
+}
+
+fn find_or_create_tag(
+    conn: &mut PgConnection,
+    tag_name: &str,
+) -> Result<Tag, diesel::result::Error> {
+    use crate::database::schema::tags::dsl::*;
+
+    diesel::insert_into(tags)
+        .values(name.eq(tag_name))
+        .on_conflict(name)
+        .do_nothing()
+        .execute(conn)?;
+    tags.filter(name.eq(tag_name)).first::<Tag>(conn)
+}
+
+/// Returns the tag names attached to each of `ids`.
+pub fn tags_for(
+    conn: &mut PgConnection,
+    kind: &str,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, Vec<String>>, diesel::result::Error> {
+    use crate::database::schema::{taggings, tags};
+
+    let rows = taggings::table
+        .filter(taggings::taggable_type.eq(kind))
+        .filter(taggings::taggable_id.eq_any(ids))
+        .load::<Tagging>(conn)?;
+    let tag_ids: Vec<Uuid> = rows.iter().map(|t| t.tag_id).collect();
+    let names: HashMap<Uuid, String> = tags::table
+        .filter(tags::id.eq_any(&tag_ids))
+        .load::<Tag>(conn)?
+        .into_iter()
+        .map(|t| (t.id, t.name))
+        .collect();
+
+    let mut result: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for row in rows {
+        if let Some(tag_name) = names.get(&row.tag_id) {
+            result
+                .entry(row.taggable_id)
+                .or_default()
+                .push(tag_name.clone());
+        }
+    }
+    for tag_names in result.values_mut() {
+        tag_names.sort();
+    }
+    Ok(result)
+}
+
+/// Returns the ids of records of `kind` carrying every tag in `tag_names`.
+pub fn ids_with_all_tags(
+    conn: &mut PgConnection,
+    kind: &str,
+    tag_names: &[String],
+) -> Result<Vec<Uuid>, diesel::result::Error> {
+    use crate::database::schema::{taggings, tags};
+
+    let tag_ids = tags::table
+        .filter(tags::name.eq_any(tag_names))
+        .select(tags::id)
+        .load::<Uuid>(conn)?;
+    if tag_ids.len() < tag_names.len() {
+        return Ok(Vec::new());
+    }
+
+    let mut counts: HashMap<Uuid, usize> = HashMap::new();
+    for taggable in taggings::table
+        .filter(taggings::taggable_type.eq(kind))
+        .filter(taggings::tag_id.eq_any(&tag_ids))
+        .select(taggings::taggable_id)
+        .load::<Uuid>(conn)?
+    {
+        *counts.entry(taggable).or_default() += 1;
+    }
+    Ok(counts
+        .into_iter()
+        .filter(|(_, count)| *count == tag_ids.len())
+        .map(|(taggable, _)| taggable)
+        .collect())
+}
+
+/// GET /admin/tags lists every tag.
+#[tracing::instrument(skip(state))]
+pub async fn list_tags_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::tags::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let all_tags = tags
+        .order(name.asc())
+        .load::<Tag>(&mut conn)
+        .map_err(db_error("Failed to load tags"))?;
+    Ok(axum::Json(json!({ "tags": all_tags })))
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistrationTagsResponse {
+    pub registration_id: Uuid,
+    pub tags: Vec<String>,
+}
+
+/// POST /admin/registrations/{id}/tags tags a registration.
+#[tracing::instrument(skip(state))]
+pub async fn tag_registration_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(registration_id): Path<Uuid>,
+    Json(payload): Json<TagRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let tag_name = normalize_tag(&payload.tag)
+        .ok_or((StatusCode::BAD_REQUEST, "Tag must not be empty".to_string()))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    crate::database::schema::registrations::table
+        .find(registration_id)
+        .first::<Registration>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to load registration"))?
+        .ok_or((StatusCode::NOT_FOUND, "Registration not found".to_string()))?;
+
+    let tag = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let tag = find_or_create_tag(conn, &tag_name)?;
+            diesel::insert_into(crate::database::schema::taggings::table)
+                .values(&NewTagging {
+                    tag_id: tag.id,
+                    taggable_type: REGISTRATION.to_string(),
+                    taggable_id: registration_id,
+                    created_by: actor.subject_id,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            Ok(tag)
+        })
+        .map_err(db_error("Failed to tag registration"))?;
+    info!("Tagged registration {registration_id} with '{}'", tag.name);
+
+    let current = tags_for(&mut conn, REGISTRATION, &[registration_id])
+        .map_err(db_error("Failed to load tags"))?;
+    Ok(axum::Json(json!(RegistrationTagsResponse {
+        registration_id,
+        tags: current.get(&registration_id).cloned().unwrap_or_default(),
+    })))
+}
+
+/// DELETE /admin/registrations/{id}/tags/{tag} removes a tag from a registration.
+#[tracing::instrument(skip(state))]
+pub async fn untag_registration_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path((registration_id, tag)): Path<(Uuid, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    use crate::database::schema::{taggings, tags};
+
+    let tag_name = normalize_tag(&tag)
+        .ok_or((StatusCode::BAD_REQUEST, "Tag must not be empty".to_string()))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let tag_id = tags::table
+        .filter(tags::name.eq(&tag_name))
+        .select(tags::id)
+        .first::<Uuid>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to load tag"))?
+        .ok_or((StatusCode::NOT_FOUND, "Tag not found".to_string()))?;
+
+    diesel::delete(
+        taggings::table
+            .filter(taggings::tag_id.eq(tag_id))
+            .filter(taggings::taggable_type.eq(REGISTRATION))
+            .filter(taggings::taggable_id.eq(registration_id)),
+    )
+    .execute(&mut conn)
+    .map_err(db_error("Failed to untag registration"))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,483 @@
+//! Dependent-care tax receipts.
+//!
+//! Families claiming camp as childcare need the camp's legal name, EIN and address
+//! alongside their own billing address. The camp's details come from
+//! `ORG_LEGAL_NAME`, `ORG_EIN` and `ORG_ADDRESS`, all or none; guardians keep their
+//! billing address on their profile through `/me/billing_address`. Receipts carry
+//! both, in JSON and in the printable `GET /receipts/{payment_intent_id}/pdf`, and
+//! `GET /me/tax_summary?year=` totals the year's eligible payments.
+//!
+//! A payment is eligible when it paid for the guardian's registrations, directly or
+//! through a quote. Voucher purchases are not childcare and are left out. Refunds
+//! issued in the year are subtracted from the year they are issued in.
+use crate::auth::Actor;
+use crate::database::schema::payment_events;
+use crate::database::{
+    conn_from_state, db_error,
+    models::{Camper, Guardian, PaymentEvent, Quote, Refund, Registration},
+};
+use crate::payment_metadata::{PaymentMetadata, QUOTE_ID, REGISTRATION_IDS};
+use crate::vouchers::VOUCHER_PURPOSE;
+use axum::{
+    extract::{Extension, Json, Query},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use diesel::dsl::sql;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Bool, Text};
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+/// The camp's identity as a care provider.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OrgTaxDetails {
+    pub legal_name: String,
+    /// Employer Identification Number, as `12-3456789`.
+    pub ein: String,
+    pub address: String,
+}
+
+/// The provider details loaded at startup. Receipts omit them when unset.
+#[derive(Debug)]
+pub struct TaxIdentity {
+    pub organization: Option<OrgTaxDetails>,
+}
+
+/// Formats nine digits, with or without the dash, as an EIN.
+pub fn normalize_ein(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let digits = match raw.split_once('-') {
+        Some((prefix, rest)) if prefix.len() == 2 => format!("{prefix}{rest}"),
+        Some(_) => return None,
+        None => raw.to_string(),
+    };
+    (digits.len() == 9 && digits.chars().all(|c| c.is_ascii_digit()))
+        .then(|| format!("{}-{}", &digits[..2], &digits[2..]))
+}
+
+impl TaxIdentity {
+    pub fn from_env() -> Result<Self, String> {
+        let setting = |name: &str| {
+            env::var(name)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let organization = match (
+            setting("ORG_LEGAL_NAME"),
+            setting("ORG_EIN"),
+            setting("ORG_ADDRESS"),
+        ) {
+            (None, None, None) => None,
+            (Some(legal_name), Some(ein), Some(address)) => Some(OrgTaxDetails {
+                ein: normalize_ein(&ein).ok_or_else(|| format!("Invalid ORG_EIN: {ein}"))?,
+                legal_name,
+                address,
+            }),
+            _ => {
+                return Err(
+                    "ORG_LEGAL_NAME, ORG_EIN and ORG_ADDRESS must be set together".to_string(),
+                )
+            }
+        };
+        Ok(Self { organization })
+    }
+}
+
+/// A guardian's billing address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BillingAddress {
+    pub line1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line2: Option<String>,
+    pub city: String,
+    /// State or province.
+    pub region: String,
+    pub postal_code: String,
+    /// ISO 3166-1 alpha-2 code.
+    pub country: String,
+}
+
+impl BillingAddress {
+    /// Trims every field and upper-cases the country, rejecting missing fields.
+    pub fn normalized(self) -> Result<Self, String> {
+        let required = |field: &str, value: String| {
+            let value = value.trim().to_string();
+            if value.is_empty() {
+                Err(format!("{field} is required"))
+            } else {
+                Ok(value)
+            }
+        };
+        let country = required("country", self.country)?.to_uppercase();
+        if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!(
+                "country must be a two-letter ISO code, got '{country}'"
+            ));
+        }
+        Ok(Self {
+            line1: required("line1", self.line1)?,
+            line2: self
+                .line2
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty()),
+            city: required("city", self.city)?,
+            region: required("region", self.region)?,
+            postal_code: required("postal_code", self.postal_code)?,
+            country,
+        })
+    }
+
+    /// The address stored on a guardian, if any.
+    pub fn of(guardian: &Guardian) -> Option<Self> {
+        guardian
+            .billing_address
+            .as_ref()
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+}
+
+/// The payer and provider details a receipt needs for a tax claim.
+#[derive(Debug, Serialize)]
+pub struct TaxReceiptDetails {
+    pub organization: Option<OrgTaxDetails>,
+    pub payer_name: String,
+    pub billing_address: Option<BillingAddress>,
+}
+
+impl TaxReceiptDetails {
+    pub fn new(identity: &TaxIdentity, guardian: &Guardian) -> Self {
+        Self {
+            organization: identity.organization.clone(),
+            payer_name: guardian.name.clone(),
+            billing_address: BillingAddress::of(guardian),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BillingAddressResponse {
+    pub guardian_id: Uuid,
+    pub billing_address: Option<BillingAddress>,
+}
+
+pub(crate) fn load_actor_guardian(
+    conn: &mut PgConnection,
+    actor: &Actor,
+) -> Result<Guardian, (StatusCode, String)> {
+    let guardian = actor
+        .guardian_id()
+        .ok_or((StatusCode::FORBIDDEN, "Guardian token required".to_string()))?;
+    crate::database::schema::guardians::table
+        .find(guardian)
+        .first::<Guardian>(conn)
+        .optional()
+        .map_err(db_error("Failed to load guardian"))?
+        .ok_or((StatusCode::NOT_FOUND, "Guardian not found".to_string()))
+}
+
+/// GET /me/billing_address returns the guardian's billing address.
+#[tracing::instrument(skip(state))]
+pub async fn get_billing_address_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let guardian = load_actor_guardian(&mut conn, &actor)?;
+
+    Ok(axum::Json(json!(BillingAddressResponse {
+        guardian_id: guardian.id,
+        billing_address: BillingAddress::of(&guardian),
+    })))
+}
+
+/// PUT /me/billing_address sets the guardian's billing address.
+#[tracing::instrument(skip(state, payload))]
+pub async fn put_billing_address_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<BillingAddress>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::guardians::dsl::*;
+
+    let address = payload
+        .normalized()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let mut conn = conn_from_state(&state).await?;
+    let guardian = load_actor_guardian(&mut conn, &actor)?;
+    diesel::update(guardians.find(guardian.id))
+        .set(billing_address.eq(Some(json!(address))))
+        .execute(&mut conn)
+        .map_err(db_error("Failed to save billing address"))?;
+    info!("Updated billing address for guardian {}", guardian.id);
+
+    Ok(axum::Json(json!(BillingAddressResponse {
+        guardian_id: guardian.id,
+        billing_address: Some(address),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaxSummaryQuery {
+    pub year: i32,
+}
+
+/// An eligible payment, or a refund of one, in the tax year.
+#[derive(Debug, Serialize)]
+pub struct TaxYearPayment {
+    pub payment_intent_id: String,
+    pub paid_at: NaiveDateTime,
+    pub amount: i64,
+    pub currency: String,
+    pub campers: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxYearRefund {
+    pub payment_intent_id: String,
+    pub refunded_at: NaiveDateTime,
+    pub amount: i64,
+    pub currency: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxYearTotal {
+    pub currency: String,
+    pub paid: i64,
+    pub refunded: i64,
+    /// `paid - refunded`, in minor units.
+    pub eligible: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaxSummaryResponse {
+    pub year: i32,
+    pub guardian_id: Uuid,
+    pub payer_name: String,
+    pub billing_address: Option<BillingAddress>,
+    pub organization: Option<OrgTaxDetails>,
+    pub totals: Vec<TaxYearTotal>,
+    pub payments: Vec<TaxYearPayment>,
+    pub refunds: Vec<TaxYearRefund>,
+}
+
+/// Totals payments and refunds per currency.
+pub fn tax_year_totals(
+    payments: &[TaxYearPayment],
+    refunds: &[TaxYearRefund],
+) -> Vec<TaxYearTotal> {
+    let mut totals: BTreeMap<&str, (i64, i64)> = BTreeMap::new();
+    for payment in payments {
+        totals.entry(&payment.currency).or_default().0 += payment.amount;
+    }
+    for refund in refunds {
+        totals.entry(&refund.currency).or_default().1 += refund.amount;
+    }
+    totals
+        .into_iter()
+        .map(|(currency, (paid, refunded))| TaxYearTotal {
+            currency: currency.to_string(),
+            paid,
+            refunded,
+            eligible: paid - refunded,
+        })
+        .collect()
+}
+
+/// What a guardian paid for: their registrations' campers and their quotes.
+struct Ownership {
+    camper_of: HashMap<Uuid, String>,
+    quote_registrations: HashMap<Uuid, Vec<Uuid>>,
+}
+
+impl Ownership {
+    fn load(conn: &mut PgConnection, guardian: Uuid) -> Result<Self, diesel::result::Error> {
+        use crate::database::schema::{campers, quotes, registrations};
+
+        let own_registrations = registrations::table
+            .filter(registrations::guardian_id.eq(guardian))
+            .load::<Registration>(conn)?;
+        let camper_ids: Vec<Uuid> = own_registrations.iter().map(|r| r.camper_id).collect();
+        let camper_names: HashMap<Uuid, String> = campers::table
+            .filter(campers::id.eq_any(&camper_ids))
+            .load::<Camper>(conn)?
+            .into_iter()
+            .map(|c| (c.id, format!("{} {}", c.first_name, c.last_name)))
+            .collect();
+        let quote_registrations = quotes::table
+            .filter(quotes::guardian_id.eq(guardian))
+            .load::<Quote>(conn)?
+            .into_iter()
+            .map(|q| (q.id, q.registration_ids))
+            .collect();
+
+        Ok(Self {
+            camper_of: own_registrations
+                .iter()
+                .filter_map(|r| Some((r.id, camper_names.get(&r.camper_id)?.clone())))
+                .collect(),
+            quote_registrations,
+        })
+    }
+
+    /// Succeeded payments whose metadata names one of this guardian's quotes or
+    /// registrations, so other families' payments are never loaded. [`Self::eligible`]
+    /// still decides which of them count.
+    fn succeeded_payments(&self) -> payment_events::BoxedQuery<'static, Pg> {
+        let quote_ids: Vec<String> = self
+            .quote_registrations
+            .keys()
+            .map(Uuid::to_string)
+            .collect();
+        let registration_ids: Vec<String> = self.camper_of.keys().map(Uuid::to_string).collect();
+        payment_events::table
+            .filter(payment_events::status.ilike(PaymentEvent::SUCCEEDED))
+            .filter(
+                sql::<Bool>(&format!("((metadata->>'{QUOTE_ID}') = ANY("))
+                    .bind::<Array<Text>, _>(quote_ids)
+                    .sql(&format!(
+                        ") OR regexp_split_to_array(metadata->>'{REGISTRATION_IDS}', '\\s*,\\s*') && "
+                    ))
+                    .bind::<Array<Text>, _>(registration_ids)
+                    .sql(")"),
+            )
+            .into_boxed()
+    }
+
+    /// The payments made for this guardian's registrations or quotes, excluding
+    /// voucher purchases, once per payment intent.
+    fn eligible(&self, succeeded: Vec<PaymentEvent>) -> Vec<TaxYearPayment> {
+        let mut seen = HashSet::new();
+        let mut payments = Vec::new();
+        for payment in succeeded {
+            let raw: HashMap<String, String> = payment
+                .metadata
+                .as_ref()
+                .and_then(|value| serde_json::from_value(value.clone()).ok())
+                .unwrap_or_default();
+            let metadata = PaymentMetadata::parse(&raw).unwrap_or_default();
+            if metadata.purpose.as_deref() == Some(VOUCHER_PURPOSE) {
+                continue;
+            }
+            let quote = metadata
+                .quote_id
+                .and_then(|q| self.quote_registrations.get(&q));
+            let mut registration_ids = metadata.registration_ids.clone();
+            if let Some(quoted) = quote {
+                registration_ids.extend(quoted.iter().copied());
+            }
+            registration_ids.sort();
+            registration_ids.dedup();
+            let campers: Vec<String> = registration_ids
+                .iter()
+                .filter_map(|id| self.camper_of.get(id).cloned())
+                .collect();
+            if campers.is_empty() && quote.is_none() {
+                continue;
+            }
+            // Redelivered webhooks can record the same success twice
+            if !seen.insert(payment.payment_intent_id.clone()) {
+                continue;
+            }
+            payments.push(TaxYearPayment {
+                payment_intent_id: payment.payment_intent_id,
+                paid_at: payment.created_at,
+                amount: payment.amount.unwrap_or_default(),
+                currency: payment.currency.unwrap_or_default().to_lowercase(),
+                campers,
+            });
+        }
+        payments
+    }
+}
+
+/// GET /me/tax_summary?year= totals the guardian's childcare payments for a year,
+/// net of refunds, with the details a dependent-care claim needs.
+#[tracing::instrument(skip(state, identity))]
+pub async fn tax_summary_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(identity): Extension<Arc<TaxIdentity>>,
+    Query(query): Query<TaxSummaryQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::refunds;
+
+    let (Some(first_day), Some(next_year)) = (
+        NaiveDate::from_ymd_opt(query.year, 1, 1),
+        NaiveDate::from_ymd_opt(query.year + 1, 1, 1),
+    ) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid year: {}", query.year),
+        ));
+    };
+    let start = first_day.and_time(NaiveTime::MIN);
+    let end = next_year.and_time(NaiveTime::MIN);
+
+    let mut conn = conn_from_state(&state).await?;
+    let guardian = load_actor_guardian(&mut conn, &actor)?;
+    let ownership = Ownership::load(&mut conn, guardian.id)
+        .map_err(db_error("Failed to load registrations"))?;
+
+    let paid = ownership
+        .succeeded_payments()
+        .filter(payment_events::created_at.ge(start))
+        .filter(payment_events::created_at.lt(end))
+        .order(payment_events::created_at.asc())
+        .load::<PaymentEvent>(&mut conn)
+        .map_err(db_error("Failed to load payments"))?;
+    let payments = ownership.eligible(paid);
+
+    // Refunds count in the year they are issued, whenever the payment was made
+    let year_refunds = refunds::table
+        .filter(refunds::status.eq("succeeded"))
+        .filter(refunds::created_at.ge(start))
+        .filter(refunds::created_at.lt(end))
+        .order(refunds::created_at.asc())
+        .load::<Refund>(&mut conn)
+        .map_err(db_error("Failed to load refunds"))?;
+    let refunded_intents: Vec<&str> = year_refunds
+        .iter()
+        .map(|r| r.payment_intent_id.as_str())
+        .collect();
+    let refunded_payments = ownership
+        .succeeded_payments()
+        .filter(payment_events::payment_intent_id.eq_any(&refunded_intents))
+        .load::<PaymentEvent>(&mut conn)
+        .map_err(db_error("Failed to load refunded payments"))?;
+    let eligible_intents: HashSet<String> = ownership
+        .eligible(refunded_payments)
+        .into_iter()
+        .map(|p| p.payment_intent_id)
+        .collect();
+    let refunds: Vec<TaxYearRefund> = year_refunds
+        .into_iter()
+        .filter(|r| eligible_intents.contains(&r.payment_intent_id))
+        .map(|refund| TaxYearRefund {
+            payment_intent_id: refund.payment_intent_id,
+            refunded_at: refund.created_at,
+            amount: refund.amount,
+            currency: refund.currency.to_lowercase(),
+        })
+        .collect();
+
+    Ok(axum::Json(json!(TaxSummaryResponse {
+        year: query.year,
+        guardian_id: guardian.id,
+        payer_name: guardian.name.clone(),
+        billing_address: BillingAddress::of(&guardian),
+        organization: identity.organization.clone(),
+        totals: tax_year_totals(&payments, &refunds),
+        payments,
+        refunds,
+    })))
+}
@@ -0,0 +1,347 @@
+//! API usage per client, for monthly reporting and abuse detection.
+//!
+//! Every routed request is counted against its client: the frontend named in the
+//! `X-Frontend-Id` header and the API key (token id) that authenticated it. Counts
+//! are aggregated in memory per Lambda instance and flushed to `api_usage`, in
+//! hourly rows, on the first request after `USAGE_FLUSH_SECONDS` (default 60) have
+//! passed. `GET /admin/usage?period=YYYY-MM` reports a month.
+use crate::auth::Actor;
+use crate::database::{
+    conn_from_state, db_error, get_conn,
+    models::{ApiUsage, NewApiUsage},
+};
+use axum::{
+    extract::{Extension, Query, Request},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info};
+
+/// Header naming the calling frontend.
+pub const FRONTEND_HEADER: &str = "x-frontend-id";
+/// Label for requests without a frontend header.
+const UNKNOWN_FRONTEND: &str = "unknown";
+/// Label for requests that presented no valid token.
+const ANONYMOUS_KEY: &str = "anonymous";
+/// Label for the bootstrap `ADMIN_API_TOKEN`, which has no token id.
+const BOOTSTRAP_KEY: &str = "bootstrap_admin";
+/// Longer frontend ids are cut, so a misbehaving client cannot bloat the table.
+const MAX_FRONTEND_ID_LEN: usize = 64;
+
+const DEFAULT_FLUSH_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UsageKey {
+    hour: NaiveDateTime,
+    frontend_id: String,
+    api_key: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct UsageCounts {
+    requests: i64,
+    client_errors: i64,
+    server_errors: i64,
+}
+
+#[derive(Debug)]
+struct Pending {
+    counts: HashMap<UsageKey, UsageCounts>,
+    last_flush: NaiveDateTime,
+}
+
+/// Request counts not yet flushed to the database.
+#[derive(Debug)]
+pub struct UsageTracker {
+    flush_interval: chrono::Duration,
+    pending: Mutex<Pending>,
+}
+
+impl UsageTracker {
+    /// Loads the flush interval from the environment.
+    pub fn from_env() -> Result<Self, String> {
+        let flush_seconds = match env::var("USAGE_FLUSH_SECONDS") {
+            Ok(raw) => raw
+                .parse::<i64>()
+                .ok()
+                .filter(|s| *s >= 0)
+                .ok_or_else(|| format!("Invalid USAGE_FLUSH_SECONDS: {raw}"))?,
+            Err(_) => DEFAULT_FLUSH_SECONDS,
+        };
+        Ok(Self {
+            flush_interval: chrono::Duration::seconds(flush_seconds),
+            pending: Mutex::new(Pending {
+                counts: HashMap::new(),
+                last_flush: chrono::Utc::now().naive_utc(),
+            }),
+        })
+    }
+
+    /// Counts one request. Returns the counts to flush if the interval has passed.
+    fn record(
+        &self,
+        frontend_id: String,
+        api_key: String,
+        status: StatusCode,
+        now: NaiveDateTime,
+    ) -> Option<HashMap<UsageKey, UsageCounts>> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let key = UsageKey {
+            hour: truncate_to_hour(now),
+            frontend_id,
+            api_key,
+        };
+        let counts = pending.counts.entry(key).or_default();
+        counts.requests += 1;
+        if status.is_client_error() {
+            counts.client_errors += 1;
+        } else if status.is_server_error() {
+            counts.server_errors += 1;
+        }
+
+        if now - pending.last_flush < self.flush_interval {
+            return None;
+        }
+        pending.last_flush = now;
+        Some(std::mem::take(&mut pending.counts))
+    }
+
+    /// Puts back counts that could not be flushed, to go out with the next flush.
+    fn restore(&self, counts: HashMap<UsageKey, UsageCounts>) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, restored) in counts {
+            let entry = pending.counts.entry(key).or_default();
+            entry.requests += restored.requests;
+            entry.client_errors += restored.client_errors;
+            entry.server_errors += restored.server_errors;
+        }
+    }
+}
+
+fn truncate_to_hour(at: NaiveDateTime) -> NaiveDateTime {
+    at.date().and_hms_opt(at.hour(), 0, 0).unwrap_or(at)
+}
+
+/// Adds the counts to their hourly rows.
+fn flush(
+    conn: &mut PgConnection,
+    counts: &HashMap<UsageKey, UsageCounts>,
+) -> Result<(), diesel::result::Error> {
+    use crate::database::schema::api_usage::dsl::*;
+
+    let rows: Vec<NewApiUsage> = counts
+        .iter()
+        .map(|(key, counted)| NewApiUsage {
+            hour: key.hour,
+            frontend_id: key.frontend_id.clone(),
+            api_key: key.api_key.clone(),
+            requests: counted.requests,
+            client_errors: counted.client_errors,
+            server_errors: counted.server_errors,
+        })
+        .collect();
+    diesel::insert_into(api_usage)
+        .values(&rows)
+        .on_conflict((hour, frontend_id, api_key))
+        .do_update()
+        .set((
+            requests.eq(requests + excluded(requests)),
+            client_errors.eq(client_errors + excluded(client_errors)),
+            server_errors.eq(server_errors + excluded(server_errors)),
+            updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+async fn flush_counts(
+    state: Option<Arc<tokio::sync::Mutex<AppState>>>,
+    tracker: &UsageTracker,
+    counts: HashMap<UsageKey, UsageCounts>,
+) {
+    let db_client = match state {
+        Some(state) => state.lock().await.database_client.clone(),
+        None => None,
+    };
+    let result = match db_client {
+        Some(db_client) => get_conn(&db_client.pool)
+            .map_err(|e| e.to_string())
+            .and_then(|mut conn| flush(&mut conn, &counts).map_err(|e| e.to_string())),
+        None => Err("Database client not available".to_string()),
+    };
+    match result {
+        Ok(()) => info!("Flushed API usage for {} client-hour(s)", counts.len()),
+        Err(e) => {
+            error!("Failed to flush API usage: {e}");
+            tracker.restore(counts);
+        }
+    }
+}
+
+/// Counts each routed request against its frontend and API key.
+pub async fn track_usage(
+    Extension(tracker): Extension<Arc<UsageTracker>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let frontend_id = request
+        .headers()
+        .get(FRONTEND_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .unwrap_or(UNKNOWN_FRONTEND)
+        .chars()
+        .take(MAX_FRONTEND_ID_LEN)
+        .collect::<String>();
+    let state = request
+        .extensions()
+        .get::<Arc<tokio::sync::Mutex<AppState>>>()
+        .cloned();
+
+    let response = next.run(request).await;
+    // The route policy layer hands back the actor it resolved on the response
+    let api_key = match response.extensions().get::<Actor>() {
+        Some(Actor {
+            token_id: Some(token_id),
+            ..
+        }) => token_id.to_string(),
+        Some(_) => BOOTSTRAP_KEY.to_string(),
+        None => ANONYMOUS_KEY.to_string(),
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    if let Some(counts) = tracker.record(frontend_id, api_key, response.status(), now) {
+        // Awaited rather than spawned: Lambda may freeze before a detached task runs
+        flush_counts(state, &tracker, counts).await;
+    }
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// Calendar month as `YYYY-MM`; the current month when omitted.
+    pub period: Option<String>,
+}
+
+/// One client's usage over a period.
+#[derive(Debug, Serialize)]
+pub struct ClientUsage {
+    pub frontend_id: String,
+    pub api_key: String,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    /// Share of requests answered with a 4xx or 5xx status.
+    pub error_rate: f64,
+    /// The most requests the client made in a single hour.
+    pub peak_hour_requests: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReportResponse {
+    pub period: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub total_requests: i64,
+    /// Busiest clients first.
+    pub clients: Vec<ClientUsage>,
+}
+
+/// The first day of the month named by `period`, and of the month after it.
+fn month_bounds(period: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (year, month) = period.split_once('-')?;
+    let year = year.parse::<i32>().ok()?;
+    let month = month.parse::<u32>().ok()?;
+    let from = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let to = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)?
+    };
+    Some((from, to))
+}
+
+/// Sums hourly rows into one entry per client, busiest first.
+fn summarize(rows: &[ApiUsage]) -> Vec<ClientUsage> {
+    let mut clients: HashMap<(&str, &str), ClientUsage> = HashMap::new();
+    for row in rows {
+        let client = clients
+            .entry((row.frontend_id.as_str(), row.api_key.as_str()))
+            .or_insert_with(|| ClientUsage {
+                frontend_id: row.frontend_id.clone(),
+                api_key: row.api_key.clone(),
+                requests: 0,
+                client_errors: 0,
+                server_errors: 0,
+                error_rate: 0.0,
+                peak_hour_requests: 0,
+            });
+        client.requests += row.requests;
+        client.client_errors += row.client_errors;
+        client.server_errors += row.server_errors;
+        client.peak_hour_requests = client.peak_hour_requests.max(row.requests);
+    }
+
+    let mut clients: Vec<ClientUsage> = clients
+        .into_values()
+        .map(|mut client| {
+            if client.requests > 0 {
+                client.error_rate =
+                    (client.client_errors + client.server_errors) as f64 / client.requests as f64;
+            }
+            client
+        })
+        .collect();
+    clients.sort_by(|a, b| {
+        b.requests
+            .cmp(&a.requests)
+            .then_with(|| a.frontend_id.cmp(&b.frontend_id))
+            .then_with(|| a.api_key.cmp(&b.api_key))
+    });
+    clients
+}
+
+/// GET /admin/usage?period= reports request counts and error rates per client for a month.
+#[tracing::instrument(skip(state))]
+pub async fn usage_report_handler(
+    Extension(state): Extension<Arc<tokio::sync::Mutex<AppState>>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::api_usage::dsl::*;
+
+    let period = query.period.unwrap_or_else(|| {
+        let today = chrono::Utc::now().date_naive();
+        format!("{:04}-{:02}", today.year(), today.month())
+    });
+    let (from, to) = month_bounds(&period).ok_or((
+        StatusCode::BAD_REQUEST,
+        "Invalid period: expected YYYY-MM".to_string(),
+    ))?;
+
+    let mut conn = conn_from_state(&state).await?;
+    let rows = api_usage
+        .filter(hour.ge(from.and_time(NaiveTime::MIN)))
+        .filter(hour.lt(to.and_time(NaiveTime::MIN)))
+        .load::<ApiUsage>(&mut conn)
+        .map_err(db_error("Failed to load API usage"))?;
+
+    let clients = summarize(&rows);
+    Ok(axum::Json(json!(UsageReportResponse {
+        total_requests: clients.iter().map(|c| c.requests).sum(),
+        period,
+        from,
+        to,
+        clients,
+    })))
+}
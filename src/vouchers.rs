@@ -0,0 +1,344 @@
+use crate::api_error::{ApiError, ErrorCode};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampCredit, Voucher},
+};
+use crate::guardians::find_or_create_guardian;
+use crate::handlers::parse_currency;
+use crate::payment_metadata::PaymentMetadata;
+use crate::stripe_keys::{StripeCapability, StripeKeyring};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use stripe::{CreatePaymentIntent, CreatePaymentIntentAutomaticPaymentMethods, PaymentIntent};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// PaymentIntent metadata value marking an intent as a voucher purchase.
+pub const VOUCHER_PURPOSE: &str = "voucher";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoucherPurchaseResponse {
+    #[serde(rename = "voucher_id")]
+    pub voucher_id: Uuid,
+    pub payment_intent: Option<String>,
+    pub publishable_key: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoucherStatusResponse {
+    pub voucher_id: Uuid,
+    pub status: String,
+    /// Set once the purchase has been paid for.
+    pub code: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+    pub recipient_email: Option<String>,
+}
+
+impl From<Voucher> for VoucherStatusResponse {
+    fn from(voucher: Voucher) -> Self {
+        Self {
+            voucher_id: voucher.id,
+            status: voucher.status,
+            code: voucher.code,
+            amount: voucher.amount,
+            currency: voucher.currency,
+            recipient_email: voucher.recipient_email,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoucherBalanceResponse {
+    pub status: String,
+    pub amount: i64,
+    pub remaining: i64,
+    pub currency: String,
+}
+
+impl From<Voucher> for VoucherBalanceResponse {
+    fn from(voucher: Voucher) -> Self {
+        let remaining = if voucher.status == "issued" {
+            voucher.amount
+        } else {
+            0
+        };
+        Self {
+            status: voucher.status,
+            amount: voucher.amount,
+            remaining,
+            currency: voucher.currency,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoucherRedeemedResponse {
+    pub guardian_id: Uuid,
+    pub credited: i64,
+    pub currency: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoucherPurchaseRequest {
+    pub purchaser_name: String,
+    pub purchaser_email: String,
+    pub recipient_email: Option<String>,
+    pub amount: i64,
+    pub currency: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoucherRedeemRequest {
+    pub code: String,
+    pub guardian_email: String,
+    pub guardian_name: String,
+}
+
+/// Generates a human-friendly voucher code such as `CAMP-1A2B-3C4D-5E6F`.
+fn generate_voucher_code() -> String {
+    let raw = Uuid::new_v4().simple().to_string().to_uppercase();
+    format!("CAMP-{}-{}-{}", &raw[0..4], &raw[4..8], &raw[8..12])
+}
+
+/// POST /vouchers creates a pending voucher and the PaymentIntent that pays for it.
+/// The voucher code is only issued once the webhook reports the payment succeeded.
+#[tracing::instrument(skip(state, keyring))]
+pub async fn purchase_voucher_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(keyring): Extension<Arc<StripeKeyring>>,
+    Json(payload): Json<VoucherPurchaseRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    info!("Received voucher purchase request: {:?}", payload);
+
+    if payload.amount <= 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Voucher amount must be positive".to_string(),
+        ));
+    }
+    let currency = parse_currency(&payload.currency)?;
+
+    let state_guard = state.lock().await;
+    let client = keyring.client(StripeCapability::Payments, &state_guard.stripe_keys);
+    let publishable_key = state_guard.stripe_keys.publishable_key.clone();
+    drop(state_guard);
+
+    let voucher_id = Uuid::new_v4();
+    let mut create_intent = CreatePaymentIntent::new(payload.amount, currency);
+    create_intent.receipt_email = Some(&payload.purchaser_email);
+    create_intent.automatic_payment_methods = Some(CreatePaymentIntentAutomaticPaymentMethods {
+        allow_redirects: None,
+        enabled: true,
+    });
+    create_intent.metadata = Some(
+        PaymentMetadata {
+            purpose: Some(VOUCHER_PURPOSE.to_string()),
+            voucher_id: Some(voucher_id),
+            ..Default::default()
+        }
+        .to_metadata(),
+    );
+
+    let payment_intent = PaymentIntent::create(&client, create_intent)
+        .await
+        .map_err(|e| {
+            error!("Error creating voucher payment intent: {e:?}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error creating payment intent: {e:?}"),
+            )
+        })?;
+    info!(
+        "Created voucher PaymentIntent with id: {}",
+        payment_intent.id
+    );
+
+    let mut conn = conn_from_state(&state).await?;
+    diesel::insert_into(crate::database::schema::vouchers::table)
+        .values(&Voucher::new(
+            voucher_id,
+            payload.amount,
+            payload.currency.to_lowercase(),
+            payload.purchaser_name,
+            payload.purchaser_email,
+            payload.recipient_email,
+            payment_intent.id.to_string(),
+        ))
+        .execute(&mut conn)
+        .map_err(db_error("Failed to save voucher"))?;
+
+    Ok(axum::Json(json!(VoucherPurchaseResponse {
+        voucher_id,
+        payment_intent: payment_intent.client_secret,
+        publishable_key,
+    })))
+}
+
+/// Issues the voucher code for a voucher whose PaymentIntent succeeded.
+/// Called by the webhook; issuing twice is a no-op.
+pub fn issue_voucher(
+    conn: &mut PgConnection,
+    intent_id: &str,
+) -> Result<Option<Voucher>, diesel::result::Error> {
+    use crate::database::schema::vouchers::dsl::*;
+
+    diesel::update(
+        vouchers
+            .filter(payment_intent_id.eq(intent_id))
+            .filter(status.eq("pending")),
+    )
+    .set((code.eq(generate_voucher_code()), status.eq("issued")))
+    .get_result::<Voucher>(conn)
+    .optional()
+}
+
+/// GET /vouchers/purchases/{id} lets the purchaser retrieve the voucher code once issued.
+#[tracing::instrument(skip(state))]
+pub async fn voucher_purchase_status_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(voucher_id): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::vouchers::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let voucher = vouchers
+        .find(voucher_id)
+        .first::<Voucher>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to load voucher"))?
+        .ok_or((StatusCode::NOT_FOUND, "Voucher not found".to_string()))?;
+
+    Ok(axum::Json(json!(VoucherStatusResponse::from(voucher))))
+}
+
+/// GET /vouchers/{code} returns the value and status of a voucher code.
+#[tracing::instrument(skip(state, voucher_code))]
+pub async fn voucher_balance_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(voucher_code): Path<String>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::vouchers::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let voucher = vouchers
+        .filter(code.eq(voucher_code.trim().to_uppercase()))
+        .first::<Voucher>(&mut conn)
+        .optional()
+        .map_err(db_error("Failed to load voucher"))?
+        .ok_or((StatusCode::NOT_FOUND, "Voucher not found".to_string()))?;
+
+    Ok(axum::Json(json!(VoucherBalanceResponse::from(voucher))))
+}
+
+/// How long a voucher can be redeemed after purchase, from `VOUCHER_VALIDITY_DAYS`.
+/// Vouchers do not expire when it is unset.
+fn voucher_validity() -> Result<Option<chrono::Duration>, ApiError> {
+    match std::env::var("VOUCHER_VALIDITY_DAYS") {
+        Ok(raw) if !raw.trim().is_empty() => raw
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|days| *days > 0)
+            .map(|days| Some(chrono::Duration::days(days)))
+            .ok_or_else(|| {
+                error!("Invalid VOUCHER_VALIDITY_DAYS: {raw}");
+                ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::InternalError,
+                    format!("Invalid VOUCHER_VALIDITY_DAYS: {raw}"),
+                )
+            }),
+        _ => Ok(None),
+    }
+}
+
+/// POST /vouchers/redeem moves the full value of an issued voucher onto the
+/// guardian's camp credit ledger, where it is spent at quote time. Expired vouchers
+/// are rejected with PROMO_EXPIRED, spent ones with PROMO_UNAVAILABLE.
+#[tracing::instrument(skip(state))]
+pub async fn redeem_voucher_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<VoucherRedeemRequest>,
+) -> Result<axum::Json<Value>, ApiError> {
+    use crate::database::schema::vouchers::dsl::*;
+
+    let validity = voucher_validity()?;
+    let mut conn = conn_from_state(&state).await?;
+    let voucher_code = payload.code.trim().to_uppercase();
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let voucher = vouchers
+            .filter(code.eq(&voucher_code))
+            .for_update()
+            .first::<Voucher>(conn)
+            .optional()?;
+        let Some(voucher) = voucher else {
+            return Ok(Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Voucher not found",
+            )));
+        };
+        if voucher.status != "issued" {
+            return Ok(Err(ApiError::new(
+                StatusCode::CONFLICT,
+                ErrorCode::PromoUnavailable,
+                format!("Voucher is {}", voucher.status),
+            )));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        if validity.is_some_and(|validity| voucher.created_at + validity <= now) {
+            return Ok(Err(ApiError::new(
+                StatusCode::GONE,
+                ErrorCode::PromoExpired,
+                "Voucher has expired",
+            )));
+        }
+
+        let guardian =
+            find_or_create_guardian(conn, &payload.guardian_email, &payload.guardian_name)?;
+
+        diesel::insert_into(crate::database::schema::camp_credits::table)
+            .values(&CampCredit::new(
+                guardian.id,
+                voucher.amount,
+                voucher.currency.clone(),
+                "voucher_redemption".to_string(),
+                Some(voucher.id),
+                None,
+            ))
+            .execute(conn)?;
+
+        diesel::update(vouchers.find(voucher.id))
+            .set((
+                status.eq("redeemed"),
+                redeemed_by_guardian_id.eq(Some(guardian.id)),
+                redeemed_at.eq(Some(now)),
+            ))
+            .execute(conn)?;
+
+        Ok(Ok((guardian, voucher)))
+    });
+
+    let (guardian, voucher) = result.map_err(db_error("Failed to redeem voucher"))??;
+    info!(
+        "Redeemed voucher {} for guardian {}",
+        voucher.id, guardian.id
+    );
+
+    Ok(axum::Json(json!(VoucherRedeemedResponse {
+        guardian_id: guardian.id,
+        credited: voucher.amount,
+        currency: voucher.currency,
+    })))
+}
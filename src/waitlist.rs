@@ -0,0 +1,470 @@
+//! Session waitlists and wait-time estimates.
+//!
+//! When a session is full, a family can put a camper on its waitlist with
+//! `POST /waitlist`. `GET /guardians/{id}/waitlist` tells them where they stand and
+//! how likely they are to get in, based on how far down the waitlist past sessions
+//! of the same `session_type` reached: a family in position 3 has the chance that a
+//! past session promoted at least 3 families. The estimates are recomputed by the
+//! `waitlist` scheduled job, which also marks entries promoted once the camper holds
+//! a confirmed registration for the session, and expires entries for sessions that
+//! have started. When a seat frees up, the family first in line is emailed a
+//! payment link that holds the seat for them (see [`crate::waitlist_offers`]).
+use crate::auth::{Actor, Role};
+use crate::campers::{ensure_guardian_owns, load_camper};
+use crate::database::{
+    conn_from_state, db_error,
+    models::{CampSession, Guardian, NewWaitlistEntry, WaitlistEntry, WaitlistEstimate},
+};
+use crate::events::{DomainEvent, EventRecorder, WAITLIST_JOINED, WAITLIST_SEAT_OFFERED};
+use crate::holds::seats_taken;
+use crate::notifications::{enqueue, Channel, Notification};
+use crate::waitlist_offers::{create_offer, offer_url};
+use axum::{
+    extract::{Extension, Json, Path},
+    http::StatusCode,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct JoinWaitlistRequest {
+    pub camper_id: Uuid,
+    pub session_id: Uuid,
+}
+
+/// How likely a waitlisted family is to get a seat.
+#[derive(Debug, Serialize)]
+pub struct WaitTimeEstimate {
+    pub session_type: String,
+    /// Share of past sessions of this type that promoted at least this many families.
+    pub chance_percent: f64,
+    pub sample_sessions: i32,
+    /// Share of all past waitlisted families that were promoted.
+    pub promotion_rate: f64,
+    pub median_days_to_promotion: Option<f64>,
+    pub computed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitlistPosition {
+    pub entry: WaitlistEntry,
+    pub session_name: String,
+    /// 1 for the family next in line.
+    pub position: i64,
+    /// Families currently waiting for the session.
+    pub waiting: i64,
+    /// `None` until there is history for this session type.
+    pub estimate: Option<WaitTimeEstimate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GuardianWaitlistResponse {
+    pub guardian_id: Uuid,
+    pub entries: Vec<WaitlistPosition>,
+}
+
+/// What a run of the `waitlist` job did.
+#[derive(Debug, Default, Serialize)]
+pub struct WaitlistRefresh {
+    pub promoted: usize,
+    pub expired: usize,
+    pub session_types: usize,
+}
+
+fn estimate_for(estimate: &WaitlistEstimate, position: i64) -> Option<WaitTimeEstimate> {
+    let promotions: Vec<i64> =
+        serde_json::from_value(estimate.promotions_per_session.clone()).ok()?;
+    if promotions.is_empty() {
+        return None;
+    }
+    let reached = promotions.iter().filter(|p| **p >= position).count();
+    Some(WaitTimeEstimate {
+        session_type: estimate.session_type.clone(),
+        chance_percent: (reached as f64 * 1000.0 / promotions.len() as f64).round() / 10.0,
+        sample_sessions: estimate.sample_sessions,
+        promotion_rate: if estimate.joined > 0 {
+            estimate.promoted as f64 / estimate.joined as f64
+        } else {
+            0.0
+        },
+        median_days_to_promotion: estimate.median_days_to_promotion,
+        computed_at: estimate.computed_at,
+    })
+}
+
+/// The entry's place in line and its estimate.
+fn position_of(
+    conn: &mut PgConnection,
+    entry: WaitlistEntry,
+) -> Result<WaitlistPosition, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, waitlist_entries, waitlist_estimates};
+
+    let session = camp_sessions::table
+        .find(entry.session_id)
+        .first::<CampSession>(conn)?;
+    let waiting = waitlist_entries::table
+        .filter(waitlist_entries::session_id.eq(entry.session_id))
+        .filter(waitlist_entries::status.eq("waiting"))
+        .count()
+        .get_result::<i64>(conn)?;
+    let ahead = waitlist_entries::table
+        .filter(waitlist_entries::session_id.eq(entry.session_id))
+        .filter(waitlist_entries::status.eq("waiting"))
+        .filter(waitlist_entries::created_at.lt(entry.created_at))
+        .count()
+        .get_result::<i64>(conn)?;
+    let position = ahead + 1;
+    let estimate = waitlist_estimates::table
+        .find(&session.session_type)
+        .first::<WaitlistEstimate>(conn)
+        .optional()?
+        .and_then(|estimate| estimate_for(&estimate, position));
+
+    Ok(WaitlistPosition {
+        entry,
+        session_name: session.name,
+        position,
+        waiting,
+        estimate,
+    })
+}
+
+/// Offers the family first in line the open seat in the session: their camper gets
+/// a pending registration holding the seat, and they are emailed a payment link.
+/// Returns the enqueued notification, if anyone is waiting and the seat is free.
+pub fn notify_next_in_line(
+    conn: &mut PgConnection,
+    session: &CampSession,
+) -> Result<Option<Uuid>, diesel::result::Error> {
+    use crate::database::schema::{guardians, waitlist_entries};
+
+    let next = waitlist_entries::table
+        .filter(waitlist_entries::session_id.eq(session.id))
+        .filter(waitlist_entries::status.eq("waiting"))
+        .order(waitlist_entries::created_at.asc())
+        .first::<WaitlistEntry>(conn)
+        .optional()?;
+    let Some(next) = next else {
+        return Ok(None);
+    };
+    let now = chrono::Utc::now().naive_utc();
+    if seats_taken(conn, session.id, now)? >= i64::from(session.capacity) {
+        return Ok(None);
+    }
+    let (offer, registration, token) = create_offer(conn, &next, now)?;
+    let guardian = guardians::table
+        .find(next.guardian_id)
+        .first::<Guardian>(conn)?;
+    let notification_id = enqueue(
+        conn,
+        Notification {
+            channel: Channel::Email,
+            target: guardian.email,
+            template: "waitlist_seat_available".to_string(),
+            payload: json!({
+                "type": "waitlist_seat_available",
+                "waitlist_entry_id": next.id,
+                "camper_id": next.camper_id,
+                "session_id": session.id,
+                "session_name": session.name,
+                "registration_id": registration.id,
+                "offer_token": token,
+                "offer_url": offer_url(&token),
+                "offer_expires_at": offer.expires_at,
+            }),
+            registration_id: Some(registration.id),
+            payment_intent_id: None,
+        },
+    )?;
+    EventRecorder::system().record(
+        conn,
+        DomainEvent::new(
+            WAITLIST_SEAT_OFFERED,
+            format!("A seat opened in {}", session.name),
+        )
+        .registration(&registration)
+        .details(json!({
+            "waitlist_entry_id": next.id,
+            "waitlist_offer_id": offer.id,
+            "offer_expires_at": offer.expires_at,
+        })),
+    )?;
+    info!(
+        "Offered waitlist entry {} a seat in session {} until {}",
+        next.id, session.id, offer.expires_at
+    );
+    Ok(Some(notification_id))
+}
+
+/// Marks entries promoted once their camper holds a confirmed registration for the
+/// session, whether or not they were offered a seat. Returns how many were promoted.
+fn mark_promotions(conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::{registrations, waitlist_entries};
+
+    let waiting = waitlist_entries::table
+        .filter(waitlist_entries::status.eq_any(["waiting", "offered"]))
+        .load::<WaitlistEntry>(conn)?;
+    let mut promoted = 0;
+    for entry in waiting {
+        let confirmed_at = registrations::table
+            .filter(registrations::session_id.eq(entry.session_id))
+            .filter(registrations::camper_id.eq(entry.camper_id))
+            .filter(registrations::status.eq("confirmed"))
+            .select(registrations::updated_at)
+            .first::<NaiveDateTime>(conn)
+            .optional()?;
+        if let Some(confirmed_at) = confirmed_at {
+            diesel::update(waitlist_entries::table.find(entry.id))
+                .set((
+                    waitlist_entries::status.eq("promoted"),
+                    waitlist_entries::promoted_at.eq(Some(confirmed_at)),
+                ))
+                .execute(conn)?;
+            promoted += 1;
+        }
+    }
+    Ok(promoted)
+}
+
+/// Expires the entries still waiting for sessions that have started.
+fn expire_started(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, waitlist_entries};
+
+    let started = camp_sessions::table
+        .filter(camp_sessions::starts_on.le(today))
+        .select(camp_sessions::id)
+        .load::<Uuid>(conn)?;
+    if started.is_empty() {
+        return Ok(0);
+    }
+    diesel::update(
+        waitlist_entries::table
+            .filter(waitlist_entries::status.eq("waiting"))
+            .filter(waitlist_entries::session_id.eq_any(&started)),
+    )
+    .set(waitlist_entries::status.eq("expired"))
+    .execute(conn)
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    })
+}
+
+/// Recomputes the estimate for every session type from the waitlists of sessions
+/// that have started. Returns how many session types were estimated.
+fn recompute_estimates(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::{camp_sessions, waitlist_entries, waitlist_estimates};
+
+    let past_sessions = camp_sessions::table
+        .filter(camp_sessions::starts_on.le(today))
+        .filter(camp_sessions::cancelled_at.is_null())
+        .load::<CampSession>(conn)?;
+    let session_ids: Vec<Uuid> = past_sessions.iter().map(|s| s.id).collect();
+    let entries = waitlist_entries::table
+        .filter(waitlist_entries::session_id.eq_any(&session_ids))
+        .load::<WaitlistEntry>(conn)?;
+
+    let mut by_type: BTreeMap<&str, Vec<&CampSession>> = BTreeMap::new();
+    for session in &past_sessions {
+        by_type
+            .entry(&session.session_type)
+            .or_default()
+            .push(session);
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    for (session_type, sessions) in &by_type {
+        let mut promotions = Vec::with_capacity(sessions.len());
+        let mut joined = 0;
+        let mut days_to_promotion = Vec::new();
+        for session in sessions {
+            let session_entries: Vec<&WaitlistEntry> = entries
+                .iter()
+                .filter(|e| e.session_id == session.id)
+                .collect();
+            joined += session_entries.len() as i64;
+            let promoted: Vec<&&WaitlistEntry> = session_entries
+                .iter()
+                .filter(|e| e.status == "promoted")
+                .collect();
+            promotions.push(promoted.len() as i64);
+            days_to_promotion.extend(promoted.iter().filter_map(|e| {
+                e.promoted_at
+                    .map(|at| (at - e.created_at).num_minutes() as f64 / (24.0 * 60.0))
+            }));
+        }
+
+        let estimate = WaitlistEstimate {
+            session_type: session_type.to_string(),
+            sample_sessions: i32::try_from(sessions.len()).unwrap_or(i32::MAX),
+            joined,
+            promoted: promotions.iter().sum(),
+            promotions_per_session: json!(promotions),
+            median_days_to_promotion: median(days_to_promotion),
+            computed_at: now,
+        };
+        diesel::insert_into(waitlist_estimates::table)
+            .values(&estimate)
+            .on_conflict(waitlist_estimates::session_type)
+            .do_update()
+            .set((
+                waitlist_estimates::sample_sessions.eq(estimate.sample_sessions),
+                waitlist_estimates::joined.eq(estimate.joined),
+                waitlist_estimates::promoted.eq(estimate.promoted),
+                waitlist_estimates::promotions_per_session.eq(&estimate.promotions_per_session),
+                waitlist_estimates::median_days_to_promotion.eq(estimate.median_days_to_promotion),
+                waitlist_estimates::computed_at.eq(now),
+            ))
+            .execute(conn)?;
+    }
+    Ok(by_type.len())
+}
+
+/// Updates promotions and expiries, then recomputes the estimates.
+pub fn refresh_waitlists(
+    conn: &mut PgConnection,
+    today: NaiveDate,
+) -> Result<WaitlistRefresh, diesel::result::Error> {
+    conn.transaction(|conn| {
+        let promoted = mark_promotions(conn)?;
+        let expired = expire_started(conn, today)?;
+        let session_types = recompute_estimates(conn, today)?;
+        Ok(WaitlistRefresh {
+            promoted,
+            expired,
+            session_types,
+        })
+    })
+}
+
+/// POST /waitlist puts a camper on the waitlist of a full session.
+#[tracing::instrument(skip(state))]
+pub async fn join_waitlist_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Json(payload): Json<JoinWaitlistRequest>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    let mut conn = conn_from_state(&state).await?;
+    let camper = load_camper(&mut conn, payload.camper_id)?;
+    ensure_guardian_owns(&actor, &camper)?;
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let session = crate::database::schema::camp_sessions::table
+            .find(payload.session_id)
+            .for_update()
+            .first::<CampSession>(conn)
+            .optional()?;
+        let Some(session) = session else {
+            return Ok(Err((
+                StatusCode::NOT_FOUND,
+                "Session not found".to_string(),
+            )));
+        };
+        if session.cancelled_at.is_some() {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                "Session is cancelled".to_string(),
+            )));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        if seats_taken(conn, session.id, now)? < i64::from(session.capacity) {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                "Session has open seats; register instead".to_string(),
+            )));
+        }
+
+        let entry = diesel::insert_into(crate::database::schema::waitlist_entries::table)
+            .values(&NewWaitlistEntry {
+                session_id: session.id,
+                camper_id: camper.id,
+                guardian_id: camper.guardian_id,
+            })
+            .on_conflict_do_nothing()
+            .get_result::<WaitlistEntry>(conn)
+            .optional()?;
+        let Some(entry) = entry else {
+            return Ok(Err((
+                StatusCode::CONFLICT,
+                "Camper is already on the waitlist".to_string(),
+            )));
+        };
+        let position = position_of(conn, entry)?;
+        EventRecorder::for_actor(&actor).record(
+            conn,
+            DomainEvent::new(
+                WAITLIST_JOINED,
+                format!(
+                    "{} joined the waitlist for {} at position {}",
+                    camper.first_name, session.name, position.position
+                ),
+            )
+            .camper(camper.id, camper.guardian_id)
+            .session(session.id)
+            .details(json!({ "waitlist_entry_id": position.entry.id })),
+        )?;
+        Ok(Ok(position))
+    });
+    let position = result.map_err(db_error("Failed to join waitlist"))??;
+
+    info!(
+        "Camper {} joined the waitlist for session {} at position {}",
+        camper.id, position.entry.session_id, position.position
+    );
+    Ok(axum::Json(json!(position)))
+}
+
+/// GET /guardians/{id}/waitlist lists the guardian's waitlist positions and estimates.
+#[tracing::instrument(skip(state))]
+pub async fn guardian_waitlist_handler(
+    actor: Actor,
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(guardian): Path<Uuid>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::waitlist_entries::dsl::*;
+
+    if actor.role == Role::Guardian && actor.guardian_id() != Some(guardian) {
+        return Err((StatusCode::NOT_FOUND, "Guardian not found".to_string()));
+    }
+    let mut conn = conn_from_state(&state).await?;
+
+    let waiting = waitlist_entries
+        .filter(guardian_id.eq(guardian))
+        .filter(status.eq("waiting"))
+        .order(created_at.asc())
+        .load::<WaitlistEntry>(&mut conn)
+        .map_err(db_error("Failed to load waitlist"))?;
+    let entries = waiting
+        .into_iter()
+        .map(|entry| position_of(&mut conn, entry))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(db_error("Failed to load waitlist positions"))?;
+
+    Ok(axum::Json(json!(GuardianWaitlistResponse {
+        guardian_id: guardian,
+        entries,
+    })))
+}
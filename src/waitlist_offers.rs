@@ -0,0 +1,297 @@
+//! Hold-my-spot offers for waitlisted families.
+//!
+//! When a seat frees up, the family first in line gets a pending registration with a
+//! hold lasting `WAITLIST_OFFER_TTL_HOURS` (default 24) and an email carrying a
+//! payment link. The link holds an offer token that is only good for that
+//! registration while its hold lasts: `POST /quote` accepts it as `offer_token` and
+//! `POST /payment_sheet` as the `offer_token` query parameter, refusing it with 410
+//! LINK_EXPIRED once the offer has lapsed. The `waitlist_offers` job marks paid
+//! offers and expires unpaid ones, cancelling their registration and offering the
+//! seat to the next family. An offer whose PaymentSheet was opened gets
+//! [`PAYMENT_GRACE_MINUTES`] past expiry for the payment to settle, after which its
+//! PaymentIntent is cancelled in Stripe. A payment that still lands on the cancelled
+//! registration is refunded (see [`crate::payment_guard::refund_late_payment`]).
+use crate::api_error::{ApiError, ErrorCode};
+use crate::auth::{generate_token, hash_token};
+use crate::database::{
+    db_error,
+    models::{
+        CampSession, NewRegistration, NewWaitlistOffer, Registration, WaitlistEntry, WaitlistOffer,
+    },
+};
+use crate::events::{DomainEvent, EventRecorder, WAITLIST_OFFER_EXPIRED};
+use crate::holds::{place_hold_until, release_holds};
+use crate::session_cancellations::PAID_STATUSES;
+use crate::waitlist::notify_next_in_line;
+use axum::http::StatusCode;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::env;
+use stripe::{
+    CancelPaymentIntent, PaymentIntent, PaymentIntentCancellationReason, PaymentIntentId,
+};
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const PAYMENT_GRACE_MINUTES: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+pub struct OfferQuery {
+    pub offer_token: Option<String>,
+}
+
+/// What a run of the `waitlist_offers` job did.
+#[derive(Debug, Default, Serialize)]
+pub struct OfferSweep {
+    pub paid: usize,
+    pub expired: usize,
+    /// Seats offered to the next family after an offer expired.
+    pub offered: usize,
+    /// PaymentIntents of expired offers cancelled in Stripe.
+    pub intents_cancelled: usize,
+    #[serde(skip)]
+    pub notification_ids: Vec<Uuid>,
+    /// PaymentIntents of expired offers, to cancel once the sweep is committed.
+    #[serde(skip)]
+    pub expired_intents: Vec<String>,
+}
+
+/// How long an offer holds the seat, from `WAITLIST_OFFER_TTL_HOURS` (default 24).
+pub fn offer_ttl() -> chrono::Duration {
+    let hours = env::var("WAITLIST_OFFER_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|h| *h > 0)
+        .unwrap_or(24);
+    chrono::Duration::hours(hours)
+}
+
+/// The payment link for an offer token, when `WAITLIST_OFFER_BASE_URL` is configured.
+pub fn offer_url(token: &str) -> Option<String> {
+    env::var("WAITLIST_OFFER_BASE_URL")
+        .ok()
+        .filter(|base| !base.is_empty())
+        .map(|base| format!("{}?offer_token={token}", base.trim_end_matches('/')))
+}
+
+/// Creates a pending registration for a waitlist entry, holds its seat for
+/// [`offer_ttl`] and marks the entry `offered`. Returns the offer, its registration
+/// and the token, which is only available here.
+pub fn create_offer(
+    conn: &mut PgConnection,
+    entry: &WaitlistEntry,
+    now: NaiveDateTime,
+) -> Result<(WaitlistOffer, Registration, String), diesel::result::Error> {
+    use crate::database::schema::{registrations, waitlist_entries, waitlist_offers};
+
+    let registration = diesel::insert_into(registrations::table)
+        .values(&NewRegistration {
+            id: Uuid::new_v4(),
+            guardian_id: entry.guardian_id,
+            camper_id: entry.camper_id,
+            session_id: entry.session_id,
+            status: "pending".to_string(),
+        })
+        .get_result::<Registration>(conn)?;
+    let expires_at = now + offer_ttl();
+    let hold = place_hold_until(conn, registration.id, entry.session_id, expires_at)?;
+
+    let token = generate_token();
+    let offer = diesel::insert_into(waitlist_offers::table)
+        .values(&NewWaitlistOffer {
+            waitlist_entry_id: entry.id,
+            registration_id: registration.id,
+            hold_id: hold.id,
+            token_hash: hash_token(&token),
+            expires_at,
+        })
+        .get_result::<WaitlistOffer>(conn)?;
+    diesel::update(waitlist_entries::table.find(entry.id))
+        .set(waitlist_entries::status.eq("offered"))
+        .execute(conn)?;
+    Ok((offer, registration, token))
+}
+
+/// Resolves an offer token to its offer and registration. Unknown tokens are 404,
+/// lapsed offers are 410 LINK_EXPIRED, and offers whose registration is no longer
+/// pending are 409 REGISTRATION_NOT_PAYABLE.
+pub fn load_open_offer(
+    conn: &mut PgConnection,
+    token: &str,
+) -> Result<(WaitlistOffer, Registration), ApiError> {
+    use crate::database::schema::{registrations, waitlist_offers};
+
+    let offer = waitlist_offers::table
+        .filter(waitlist_offers::token_hash.eq(hash_token(token)))
+        .first::<WaitlistOffer>(conn)
+        .optional()
+        .map_err(db_error("Failed to load waitlist offer"))?
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                "Offer not found",
+            )
+        })?;
+    if !offer.is_open(chrono::Utc::now().naive_utc()) {
+        return Err(ApiError::new(
+            StatusCode::GONE,
+            ErrorCode::LinkExpired,
+            "Offer has expired",
+        ));
+    }
+
+    let registration = registrations::table
+        .find(offer.registration_id)
+        .first::<Registration>(conn)
+        .map_err(db_error("Failed to load registration"))?;
+    if registration.status != "pending" {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            ErrorCode::RegistrationNotPayable,
+            format!("Registration is {}", registration.status),
+        ));
+    }
+    Ok((offer, registration))
+}
+
+/// Records the PaymentIntent created through an offer's link.
+pub fn link_offer_to_intent(
+    conn: &mut PgConnection,
+    offer: Uuid,
+    intent_id: &str,
+) -> Result<usize, diesel::result::Error> {
+    use crate::database::schema::waitlist_offers::dsl::*;
+
+    diesel::update(waitlist_offers.find(offer))
+        .set(payment_intent_id.eq(Some(intent_id)))
+        .execute(conn)
+}
+
+/// Marks offers whose registration was paid, and expires the unpaid ones that
+/// lapsed: their registration is cancelled, the seat released and offered to the
+/// next family in line.
+pub fn sweep_offers(
+    conn: &mut PgConnection,
+    now: NaiveDateTime,
+) -> Result<OfferSweep, diesel::result::Error> {
+    use crate::database::schema::{
+        camp_sessions, registrations, waitlist_entries, waitlist_offers,
+    };
+
+    conn.transaction(|conn| {
+        let open = waitlist_offers::table
+            .filter(waitlist_offers::status.eq("open"))
+            .for_update()
+            .skip_locked()
+            .load::<WaitlistOffer>(conn)?;
+
+        let mut sweep = OfferSweep::default();
+        for offer in open {
+            let registration = registrations::table
+                .find(offer.registration_id)
+                .for_update()
+                .first::<Registration>(conn)?;
+            if PAID_STATUSES.contains(&registration.status.as_str()) {
+                diesel::update(waitlist_offers::table.find(offer.id))
+                    .set((
+                        waitlist_offers::status.eq("paid"),
+                        waitlist_offers::resolved_at.eq(Some(now)),
+                    ))
+                    .execute(conn)?;
+                sweep.paid += 1;
+                continue;
+            }
+
+            let deadline = if offer.payment_intent_id.is_some() {
+                offer.expires_at + chrono::Duration::minutes(PAYMENT_GRACE_MINUTES)
+            } else {
+                offer.expires_at
+            };
+            // A registration cancelled some other way already freed its seat
+            let pending = registration.status == "pending";
+            if pending && deadline > now {
+                continue;
+            }
+
+            diesel::update(waitlist_offers::table.find(offer.id))
+                .set((
+                    waitlist_offers::status.eq("expired"),
+                    waitlist_offers::resolved_at.eq(Some(now)),
+                ))
+                .execute(conn)?;
+            diesel::update(waitlist_entries::table.find(offer.waitlist_entry_id))
+                .set(waitlist_entries::status.eq("expired"))
+                .execute(conn)?;
+            sweep.expired += 1;
+            sweep
+                .expired_intents
+                .extend(offer.payment_intent_id.clone());
+            if !pending {
+                continue;
+            }
+            diesel::update(registrations::table.find(registration.id))
+                .set((
+                    registrations::status.eq("cancelled"),
+                    registrations::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+            release_holds(conn, &[registration.id], now)?;
+            EventRecorder::system().record(
+                conn,
+                DomainEvent::new(WAITLIST_OFFER_EXPIRED, "Waitlist offer expired unpaid")
+                    .registration(&registration)
+                    .details(json!({ "waitlist_offer_id": offer.id })),
+            )?;
+            info!(
+                "Expired waitlist offer {} for registration {}",
+                offer.id, registration.id
+            );
+
+            // Lock the session so the freed seat is offered only once
+            let session = camp_sessions::table
+                .find(registration.session_id)
+                .for_update()
+                .first::<CampSession>(conn)?;
+            if session.cancelled_at.is_none() {
+                if let Some(notification_id) = notify_next_in_line(conn, &session)? {
+                    sweep.notification_ids.push(notification_id);
+                    sweep.offered += 1;
+                }
+            }
+        }
+        Ok(sweep)
+    })
+}
+
+/// Cancels the PaymentIntents of expired offers so the family can no longer pay for a
+/// seat that was given away. Best-effort: an intent that already succeeded cannot be
+/// cancelled, and its payment is refunded when the webhook arrives. Returns how many
+/// were cancelled.
+pub async fn cancel_expired_intents(client: &stripe::Client, intent_ids: &[String]) -> usize {
+    let mut cancelled = 0;
+    for intent_id in intent_ids {
+        let result = match intent_id.parse::<PaymentIntentId>() {
+            Ok(id) => PaymentIntent::cancel(
+                client,
+                &id,
+                CancelPaymentIntent {
+                    cancellation_reason: Some(PaymentIntentCancellationReason::Abandoned),
+                },
+            )
+            .await
+            .map_err(|e| e.to_string()),
+            Err(e) => Err(format!("Invalid payment intent id: {e}")),
+        };
+        match result {
+            Ok(_) => {
+                info!("Cancelled payment intent {intent_id} of an expired waitlist offer");
+                cancelled += 1;
+            }
+            Err(e) => error!("Failed to cancel payment intent {intent_id}: {e}"),
+        }
+    }
+    cancelled
+}
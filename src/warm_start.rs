@@ -0,0 +1,95 @@
+//! Init-phase warm-up for provisioned concurrency.
+//!
+//! With `WARM_START=1` the Lambda does the work a cold first request would otherwise
+//! pay for before it starts serving: every pooled database connection runs a query,
+//! the Stripe keys fetched at init are checked, the S3 client loads its AWS
+//! configuration, and today's exchange rates are cached. Provisioned instances run
+//! this during their init phase, outside any request. Failures are logged and never
+//! stop the Lambda from starting; the request path recovers as it always has.
+use crate::database::get_conn;
+use crate::exchange_rates::prime_rates;
+use crate::s3_archive;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use std::env;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/// Whether `WARM_START=1` asks for the warm-up.
+pub fn enabled() -> bool {
+    env::var("WARM_START").is_ok_and(|value| value.trim() == "1")
+}
+
+/// What the warm-up got ready.
+#[derive(Debug, Default)]
+pub struct WarmStartReport {
+    /// Pooled connections that answered a query.
+    pub connections: u32,
+    /// Whether the Stripe secret, publishable and webhook keys are all present.
+    pub stripe_keys: bool,
+    /// Currencies with today's exchange rates cached.
+    pub exchange_rate_bases: usize,
+    pub elapsed_ms: u128,
+}
+
+/// Runs the warm-up. Best-effort: each step logs its own failure.
+pub async fn warm_up(state: &Arc<Mutex<AppState>>) -> WarmStartReport {
+    let started = Instant::now();
+    let mut report = WarmStartReport::default();
+
+    let db_client = {
+        let state_guard = state.lock().await;
+        let keys = &state_guard.stripe_keys;
+        report.stripe_keys = !keys.secret_key.is_empty()
+            && !keys.publishable_key.is_empty()
+            && !keys.webhook_secret.is_empty();
+        state_guard.database_client.clone()
+    };
+    if !report.stripe_keys {
+        error!("Warm start found missing Stripe keys");
+    }
+
+    s3_archive::warm_up().await;
+
+    match db_client {
+        Some(db_client) => {
+            // Hold every connection at once so each pooled connection is exercised
+            let mut held = Vec::new();
+            for _ in 0..db_client.pool.max_size() {
+                match get_conn(&db_client.pool) {
+                    Ok(mut conn) => {
+                        match diesel::sql_query("SELECT 1").execute(&mut conn) {
+                            Ok(_) => report.connections += 1,
+                            Err(e) => error!("Warm start query failed: {e}"),
+                        }
+                        held.push(conn);
+                    }
+                    Err(e) => {
+                        error!("Warm start could not open a connection: {e}");
+                        break;
+                    }
+                }
+            }
+            if let Some(conn) = held.first_mut() {
+                report.exchange_rate_bases = prime_rates(conn).await;
+            }
+        }
+        None => error!("Warm start skipped the database: client not available"),
+    }
+
+    report.elapsed_ms = started.elapsed().as_millis();
+    info!(
+        "Warm start ready in {} ms: {} connection(s), Stripe keys {}, {} exchange rate base(s)",
+        report.elapsed_ms,
+        report.connections,
+        if report.stripe_keys {
+            "present"
+        } else {
+            "missing"
+        },
+        report.exchange_rate_bases
+    );
+    report
+}
@@ -0,0 +1,159 @@
+//! Stripe event type coverage.
+//!
+//! Every webhook event that passes signature verification is counted by type in
+//! `webhook_event_counts`, including events the event filter drops and types the
+//! handler ignores, and in the `webhook_events_received_total` metric.
+//! `GET /admin/webhook_coverage` compares the received types with
+//! [`HANDLED_EVENT_TYPES`], listing unhandled types by volume so new handlers can be
+//! prioritized, and the handled types Stripe has never sent, which usually means
+//! the endpoint is not subscribed to them.
+use crate::database::{
+    conn_from_state, db_error,
+    models::{NewWebhookEventCount, WebhookEventCount},
+};
+use crate::metrics;
+use axum::{extract::Extension, http::StatusCode};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use lambda_lib::AppState;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Event types `webhook_handler` acts on. Keep in step with its `match`.
+pub const HANDLED_EVENT_TYPES: &[&str] = &[
+    "charge.refund.updated",
+    "charge.refunded",
+    "charge.succeeded",
+    "charge.updated",
+    "payment_intent.amount_capturable_updated",
+    "payment_intent.canceled",
+    "payment_intent.created",
+    "payment_intent.partially_funded",
+    "payment_intent.payment_failed",
+    "payment_intent.processing",
+    "payment_intent.requires_action",
+    "payment_intent.requires_capture",
+    "payment_intent.succeeded",
+    "payment_method.attached",
+    "payout.failed",
+];
+
+pub fn is_handled(event_type: &str) -> bool {
+    HANDLED_EVENT_TYPES.contains(&event_type)
+}
+
+/// Counts a received event, and whether the event filter dropped it.
+pub fn record_received(
+    conn: &mut PgConnection,
+    received_type: &str,
+    was_filtered: bool,
+) -> Result<(), diesel::result::Error> {
+    use crate::database::schema::webhook_event_counts::dsl::*;
+
+    diesel::insert_into(webhook_event_counts)
+        .values(&NewWebhookEventCount {
+            event_type: received_type.to_string(),
+            received: 1,
+            filtered: i64::from(was_filtered),
+        })
+        .on_conflict(event_type)
+        .do_update()
+        .set((
+            received.eq(received + 1),
+            filtered.eq(filtered + excluded(filtered)),
+            last_received_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Records the metric for a received event.
+pub fn observe(event_type: &str, was_filtered: bool) {
+    let handled = if was_filtered {
+        "filtered"
+    } else if is_handled(event_type) {
+        "true"
+    } else {
+        "false"
+    };
+    metrics::increment(
+        "webhook_events_received_total",
+        &[("event_type", event_type), ("handled", handled)],
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EventTypeCoverage {
+    pub event_type: String,
+    pub handled: bool,
+    pub received: i64,
+    pub filtered: i64,
+    pub first_received_at: NaiveDateTime,
+    pub last_received_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WebhookCoverageReport {
+    pub total_received: i64,
+    /// Received events of types the handler ignores.
+    pub unhandled_received: i64,
+    /// Received types the handler ignores, most received first.
+    pub unhandled: Vec<EventTypeCoverage>,
+    /// Received types the handler acts on, most received first.
+    pub handled: Vec<EventTypeCoverage>,
+    /// Types the handler acts on that have never been received.
+    pub never_received: Vec<String>,
+}
+
+/// Splits the counts into handled and unhandled types.
+pub fn coverage(counts: Vec<WebhookEventCount>) -> WebhookCoverageReport {
+    let mut entries: Vec<EventTypeCoverage> = counts
+        .into_iter()
+        .map(|count| EventTypeCoverage {
+            handled: is_handled(&count.event_type),
+            event_type: count.event_type,
+            received: count.received,
+            filtered: count.filtered,
+            first_received_at: count.first_received_at,
+            last_received_at: count.last_received_at,
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.received
+            .cmp(&a.received)
+            .then_with(|| a.event_type.cmp(&b.event_type))
+    });
+    let never_received = HANDLED_EVENT_TYPES
+        .iter()
+        .filter(|handled| !entries.iter().any(|e| e.event_type == **handled))
+        .map(|handled| handled.to_string())
+        .collect();
+    let (handled, unhandled): (Vec<_>, Vec<_>) = entries.into_iter().partition(|e| e.handled);
+
+    WebhookCoverageReport {
+        total_received: handled.iter().chain(&unhandled).map(|e| e.received).sum(),
+        unhandled_received: unhandled.iter().map(|e| e.received).sum(),
+        unhandled,
+        handled,
+        never_received,
+    }
+}
+
+/// GET /admin/webhook_coverage compares the event types received with those the
+/// webhook handles.
+#[tracing::instrument(skip(state))]
+pub async fn webhook_coverage_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::webhook_event_counts::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let counts = webhook_event_counts
+        .load::<WebhookEventCount>(&mut conn)
+        .map_err(db_error("Failed to load webhook event counts"))?;
+
+    Ok(axum::Json(json!(coverage(counts))))
+}
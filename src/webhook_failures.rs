@@ -0,0 +1,174 @@
+//! How the webhook answers when processing an event fails.
+//!
+//! Failures fall into classes:
+//!
+//! - `database_unavailable`: no database connection could be made;
+//! - `persistence`: recording the event or its effects failed (the payment event,
+//!   receipt number, voucher, registration confirmation, refund, payment method or
+//!   payout flag);
+//! - `notification`: telling someone about the event failed (WebSocket lookups,
+//!   support tickets).
+//!
+//! `WEBHOOK_FAILURE_RESPONSES` sets the response per class as comma-separated
+//! `class:response` pairs, e.g. `persistence:acknowledge`. `retry` answers 500 so
+//! Stripe redelivers the event; a payment intent event is recorded in one
+//! transaction that a retried failure rolls back, so its redelivery is processed in
+//! full. `acknowledge` answers 200 and leaves the failure to logs and metrics. Database failures are
+//! retried by default and notification failures acknowledged, since notifications
+//! wait in the outbox for the retry job. When several failures happen, any `retry`
+//! wins. Each failure is counted in `webhook_failures_total` by class and response,
+//! and each answer in `webhook_responses_total` by outcome.
+use crate::metrics;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use tracing::{error, warn};
+
+/// What went wrong while processing an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureClass {
+    DatabaseUnavailable,
+    Persistence,
+    Notification,
+}
+
+impl FailureClass {
+    pub const ALL: [FailureClass; 3] = [
+        FailureClass::DatabaseUnavailable,
+        FailureClass::Persistence,
+        FailureClass::Notification,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::DatabaseUnavailable => "database_unavailable",
+            FailureClass::Persistence => "persistence",
+            FailureClass::Notification => "notification",
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|class| class.as_str() == raw)
+    }
+}
+
+/// How the webhook answers a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureResponse {
+    /// 500, so Stripe redelivers the event.
+    Retry,
+    /// 200; the failure is only logged and counted.
+    Acknowledge,
+}
+
+impl FailureResponse {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureResponse::Retry => "retry",
+            FailureResponse::Acknowledge => "acknowledge",
+        }
+    }
+}
+
+/// The failures met while processing one event.
+#[derive(Debug, Default)]
+pub struct Failures {
+    classes: Vec<FailureClass>,
+}
+
+impl Failures {
+    /// Logs and remembers a failure.
+    pub fn record(&mut self, class: FailureClass, context: &str, e: impl fmt::Display) {
+        error!("{context}: {e}");
+        if !self.classes.contains(&class) {
+            self.classes.push(class);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    pub fn classes(&self) -> &[FailureClass] {
+        &self.classes
+    }
+}
+
+/// The response per failure class, loaded at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookFailureResponses {
+    responses: BTreeMap<FailureClass, FailureResponse>,
+}
+
+impl Default for WebhookFailureResponses {
+    fn default() -> Self {
+        Self {
+            responses: BTreeMap::from([
+                (FailureClass::DatabaseUnavailable, FailureResponse::Retry),
+                (FailureClass::Persistence, FailureResponse::Retry),
+                (FailureClass::Notification, FailureResponse::Acknowledge),
+            ]),
+        }
+    }
+}
+
+impl WebhookFailureResponses {
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&env::var("WEBHOOK_FAILURE_RESPONSES").unwrap_or_default())
+    }
+
+    /// Parses `class:response` pairs over the defaults.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut configured = Self::default();
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || {
+                format!(
+                    "Invalid WEBHOOK_FAILURE_RESPONSES entry '{entry}': expected \
+                     database_unavailable, persistence or notification, then :retry or :acknowledge"
+                )
+            };
+            let (class, response) = entry.split_once(':').ok_or_else(invalid)?;
+            let class = FailureClass::parse(&class.trim().to_lowercase()).ok_or_else(invalid)?;
+            let response = match response.trim().to_lowercase().as_str() {
+                "retry" => FailureResponse::Retry,
+                "acknowledge" => FailureResponse::Acknowledge,
+                _ => return Err(invalid()),
+            };
+            configured.responses.insert(class, response);
+        }
+        Ok(configured)
+    }
+
+    pub fn response_for(&self, class: FailureClass) -> FailureResponse {
+        self.responses
+            .get(&class)
+            .copied()
+            .unwrap_or(FailureResponse::Retry)
+    }
+
+    /// The response to an event's failures, counting each failure and the outcome:
+    /// `processed`, `acknowledged` despite failures, or `retry`.
+    pub fn resolve(&self, event_id: &str, failures: &Failures) -> Option<FailureResponse> {
+        let mut resolved = None;
+        for class in failures.classes() {
+            let response = self.response_for(*class);
+            metrics::increment(
+                "webhook_failures_total",
+                &[("class", class.as_str()), ("response", response.as_str())],
+            );
+            if resolved != Some(FailureResponse::Retry) {
+                resolved = Some(response);
+            }
+        }
+        let outcome = match resolved {
+            None => "processed",
+            Some(FailureResponse::Acknowledge) => "acknowledged",
+            Some(FailureResponse::Retry) => {
+                warn!("Asking Stripe to retry webhook event {event_id}");
+                "retry"
+            }
+        };
+        metrics::increment("webhook_responses_total", &[("outcome", outcome)]);
+        resolved
+    }
+}
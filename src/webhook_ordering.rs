@@ -0,0 +1,146 @@
+//! Ordering of payment intent webhook events.
+//!
+//! Stripe does not deliver events in order, and retries make it worse: a
+//! `payment_intent.succeeded` can arrive before the intent's `created` event. Each
+//! payment intent has a row in `payment_intent_states` recording the last event
+//! applied to it. With `WEBHOOK_ORDERING=created` (the default) an event whose Stripe
+//! `created` timestamp is older than that one is acknowledged and dropped, so state
+//! never moves backwards. Timestamps only have second precision, so among events
+//! from the same second a terminal event (`succeeded`, `canceled`) wins over the
+//! others. `WEBHOOK_ORDERING=arrival` applies events as they arrive.
+//!
+//! Deliveries run concurrently, on one instance or several. Each claims its intent's
+//! row under a row lock before anything else happens, so deliveries for the same
+//! intent are ordered at the claim, and deliveries for different intents do not
+//! wait on each other. A redelivery of the applied event is claimed again; the
+//! webhook then records each Stripe event id in `payment_events` once, so the
+//! redelivery of an event that was recorded is acknowledged without repeating its
+//! side effects. A delivery that failed is rolled back whole before Stripe is asked
+//! to retry it, so its redelivery is processed from scratch.
+use crate::database::models::PaymentIntentState;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use std::env;
+
+/// How webhook events for the same payment intent are ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderingMode {
+    /// Drop events older than the last applied one.
+    Created,
+    /// Apply events in arrival order.
+    Arrival,
+}
+
+/// The ordering mode loaded at startup.
+#[derive(Debug)]
+pub struct WebhookOrdering {
+    mode: OrderingMode,
+}
+
+/// The outcome of claiming an event for its payment intent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim {
+    /// The event is the newest seen and should be processed.
+    Applied,
+    /// A newer event was already applied; this one should be ignored.
+    Stale {
+        last_event_id: String,
+        last_event_at: NaiveDateTime,
+    },
+}
+
+/// Breaks ties between events created in the same second.
+fn rank(event_type: &str) -> u8 {
+    match event_type {
+        "payment_intent.created" => 0,
+        "payment_intent.succeeded" | "payment_intent.canceled" => 2,
+        _ => 1,
+    }
+}
+
+impl WebhookOrdering {
+    /// Loads `WEBHOOK_ORDERING` (`created` or `arrival`).
+    pub fn from_env() -> Result<Self, String> {
+        let mode = match env::var("WEBHOOK_ORDERING") {
+            Ok(raw) => match raw.trim().to_lowercase().as_str() {
+                "" | "created" => OrderingMode::Created,
+                "arrival" => OrderingMode::Arrival,
+                other => {
+                    return Err(format!(
+                        "Invalid WEBHOOK_ORDERING '{other}': expected created or arrival"
+                    ))
+                }
+            },
+            Err(_) => OrderingMode::Created,
+        };
+        Ok(Self { mode })
+    }
+
+    /// Whether an event is older than the last applied event for its intent.
+    fn is_stale(
+        &self,
+        last: &PaymentIntentState,
+        event_type: &str,
+        event_at: NaiveDateTime,
+    ) -> bool {
+        match self.mode {
+            OrderingMode::Arrival => false,
+            OrderingMode::Created => {
+                event_at < last.last_event_at
+                    || (event_at == last.last_event_at
+                        && rank(event_type) < rank(&last.last_event_type))
+            }
+        }
+    }
+
+    /// Records the event as the intent's latest unless a newer one was applied first.
+    pub fn claim(
+        &self,
+        conn: &mut PgConnection,
+        intent_id: &str,
+        status: &str,
+        event_id: &str,
+        event_type: &str,
+        event_at: NaiveDateTime,
+    ) -> Result<Claim, diesel::result::Error> {
+        use crate::database::schema::payment_intent_states::dsl;
+
+        conn.transaction(|conn| {
+            let now = chrono::Utc::now().naive_utc();
+            let state = PaymentIntentState {
+                payment_intent_id: intent_id.to_string(),
+                status: status.to_string(),
+                last_event_id: event_id.to_string(),
+                last_event_type: event_type.to_string(),
+                last_event_at: event_at,
+                updated_at: now,
+            };
+            let inserted = diesel::insert_into(dsl::payment_intent_states)
+                .values(&state)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            if inserted > 0 {
+                return Ok(Claim::Applied);
+            }
+
+            // Serializes concurrent deliveries for the same intent
+            let last = dsl::payment_intent_states
+                .find(intent_id)
+                .for_update()
+                .first::<PaymentIntentState>(conn)?;
+            if last.last_event_id == event_id {
+                return Ok(Claim::Applied);
+            }
+            if self.is_stale(&last, event_type, event_at) {
+                return Ok(Claim::Stale {
+                    last_event_id: last.last_event_id,
+                    last_event_at: last.last_event_at,
+                });
+            }
+            diesel::update(dsl::payment_intent_states.find(intent_id))
+                .set(&state)
+                .execute(conn)?;
+            Ok(Claim::Applied)
+        })
+    }
+}
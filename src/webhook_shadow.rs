@@ -0,0 +1,201 @@
+//! Shadow replay of webhook deliveries.
+//!
+//! With `WEBHOOK_SHADOW_URL` set, every webhook request is also sent to that
+//! endpoint, a deployment of the candidate implementation with its own database.
+//! The shadow gets the same body and `Stripe-Signature` header, so it verifies and
+//! processes the event exactly as this handler did. The copy is sent from a
+//! background task after this handler has responded: the shadow can never delay,
+//! fail or change the response Stripe sees. Its status and body are compared with
+//! ours; mismatches are logged with the event id and counted in
+//! `webhook_shadow_total{outcome}` (`match`, `mismatch` or `error`).
+//!
+//! `WEBHOOK_SHADOW_SAMPLE_PERCENT` (default 100) replays a share of deliveries and
+//! `WEBHOOK_SHADOW_TIMEOUT_MS` (default 5000) bounds each shadow request. A task
+//! still running when Lambda freezes the instance resumes on its next invocation.
+use crate::metrics;
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Extension, Request},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Largest body buffered for replay: Lambda's request payload limit, so any
+/// delivery that reaches the handler fits.
+const MAX_BODY_BYTES: usize = 6 * 1024 * 1024;
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Shadow replay settings loaded at startup. Disabled without a URL.
+#[derive(Debug)]
+pub struct WebhookShadow {
+    url: Option<String>,
+    sample_percent: u8,
+    client: reqwest::Client,
+}
+
+impl WebhookShadow {
+    pub fn from_env() -> Result<Self, String> {
+        let url = env::var("WEBHOOK_SHADOW_URL")
+            .ok()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty());
+        if let Some(url) = &url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(format!(
+                    "Invalid WEBHOOK_SHADOW_URL '{url}': expected an http(s) URL"
+                ));
+            }
+        }
+        let sample_percent = match env::var("WEBHOOK_SHADOW_SAMPLE_PERCENT") {
+            Ok(raw) => raw
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= 100)
+                .ok_or_else(|| format!("Invalid WEBHOOK_SHADOW_SAMPLE_PERCENT: {raw}"))?,
+            Err(_) => 100,
+        };
+        let timeout_ms = match env::var("WEBHOOK_SHADOW_TIMEOUT_MS") {
+            Ok(raw) => raw
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|ms| *ms > 0)
+                .ok_or_else(|| format!("Invalid WEBHOOK_SHADOW_TIMEOUT_MS: {raw}"))?,
+            Err(_) => DEFAULT_TIMEOUT_MS,
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(timeout_ms))
+            .build()
+            .map_err(|e| format!("Failed to build shadow HTTP client: {e}"))?;
+        if let Some(url) = &url {
+            info!("Shadowing {sample_percent}% of webhook deliveries to {url}");
+        }
+        Ok(Self {
+            url,
+            sample_percent,
+            client,
+        })
+    }
+
+    /// Whether this delivery should be replayed, sampling by a hash of the body so
+    /// Stripe's retries of one event are sampled alike.
+    fn samples(&self, body: &[u8]) -> bool {
+        if self.sample_percent >= 100 {
+            return true;
+        }
+        let bucket = body.iter().fold(0u32, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(u32::from(*byte))
+        });
+        bucket % 100 < u32::from(self.sample_percent)
+    }
+}
+
+/// The event id and type from a webhook body, for logs.
+fn describe(body: &[u8]) -> String {
+    let event: serde_json::Value = serde_json::from_slice(body).unwrap_or_default();
+    format!(
+        "{} ({})",
+        event["id"].as_str().unwrap_or("unknown event"),
+        event["type"].as_str().unwrap_or("unknown type")
+    )
+}
+
+/// Sends the delivery to the shadow endpoint and compares its response with ours.
+async fn replay(
+    shadow: Arc<WebhookShadow>,
+    url: String,
+    signature: Option<String>,
+    body: Bytes,
+    primary_status: StatusCode,
+    primary_body: Bytes,
+) {
+    let event = describe(&body);
+    let mut request = shadow
+        .client
+        .post(&url)
+        .header("content-type", "application/json")
+        .body(body);
+    if let Some(signature) = signature {
+        request = request.header("stripe-signature", signature);
+    }
+
+    let outcome = match request.send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let shadow_body = response.text().await.unwrap_or_default();
+            let primary_text = String::from_utf8_lossy(&primary_body);
+            if status == primary_status.as_u16() && shadow_body.trim() == primary_text.trim() {
+                "match"
+            } else {
+                warn!(
+                    "Shadow webhook mismatch for {event}: primary {} {:?}, shadow {status} {:?}",
+                    primary_status.as_u16(),
+                    primary_text.trim(),
+                    shadow_body.trim()
+                );
+                "mismatch"
+            }
+        }
+        Err(e) => {
+            error!("Shadow webhook request for {event} failed: {e}");
+            "error"
+        }
+    };
+    metrics::increment("webhook_shadow_total", &[("outcome", outcome)]);
+}
+
+/// Replays webhook deliveries to the shadow endpoint once the primary response is
+/// ready. A pass-through when shadowing is disabled.
+pub async fn shadow_webhook(
+    Extension(shadow): Extension<Arc<WebhookShadow>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(url) = shadow.url.clone() else {
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Webhook body not shadowed: {e}");
+            return next.run(Request::from_parts(parts, Body::empty())).await;
+        }
+    };
+    let signature = parts
+        .headers
+        .get("stripe-signature")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let response = next
+        .run(Request::from_parts(parts, Body::from(body.clone())))
+        .await;
+    if !shadow.samples(&body) {
+        return response;
+    }
+
+    let (response_parts, response_body) = response.into_parts();
+    let response_body = match to_bytes(response_body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to buffer webhook response for shadowing: {e}");
+            return Response::from_parts(response_parts, Body::empty());
+        }
+    };
+    tokio::spawn(replay(
+        shadow.clone(),
+        url,
+        signature,
+        body,
+        response_parts.status,
+        response_body.clone(),
+    ));
+    Response::from_parts(response_parts, Body::from(response_body))
+}
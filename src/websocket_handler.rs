@@ -1,7 +1,9 @@
 use crate::database::get_conn;
+use crate::db_health::DatabaseHealth;
+use crate::ws_connections::LiveConnections;
 use axum::{
     extract::{
-        ws::{Message, Utf8Bytes, WebSocket},
+        ws::{close_code, CloseFrame, Message, Utf8Bytes, WebSocket},
         WebSocketUpgrade,
     },
     response::IntoResponse,
@@ -11,121 +13,303 @@ use diesel::prelude::*;
 use futures::{SinkExt, StreamExt};
 use lambda_lib::AppState;
 use lambda_lib::PgPool;
-use serde_json::json;
+use serde_json::{json, Value};
+use std::fmt;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Largest text frame a client may send.
+pub const MAX_CLIENT_MESSAGE_BYTES: usize = 4096;
+/// Longest accepted identifier or email in a client message.
+const MAX_FIELD_LEN: usize = 255;
+
+/// A message a WebSocket client may send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessage {
+    Subscribe {
+        payment_intent_id: String,
+        customer_id: Option<String>,
+        customer_email: Option<String>,
+    },
+}
+
+/// Why a client message was rejected. Each variant is reported to the client as an
+/// `error` message carrying [`ClientMessageError::code`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMessageError {
+    TooLarge {
+        len: usize,
+    },
+    BinaryFrame,
+    InvalidJson,
+    NotAnObject,
+    MissingType,
+    UnknownType(String),
+    MissingField(&'static str),
+    InvalidField {
+        field: &'static str,
+        reason: &'static str,
+    },
+}
+
+impl ClientMessageError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ClientMessageError::TooLarge { .. } => "message_too_large",
+            ClientMessageError::BinaryFrame => "binary_not_supported",
+            ClientMessageError::InvalidJson => "invalid_json",
+            ClientMessageError::NotAnObject => "not_an_object",
+            ClientMessageError::MissingType => "missing_type",
+            ClientMessageError::UnknownType(_) => "unknown_type",
+            ClientMessageError::MissingField(_) => "missing_field",
+            ClientMessageError::InvalidField { .. } => "invalid_field",
+        }
+    }
+
+    /// The `error` message sent back to the client.
+    pub fn to_message(&self) -> String {
+        let field = match self {
+            ClientMessageError::MissingField(field)
+            | ClientMessageError::InvalidField { field, .. } => Some(*field),
+            _ => None,
+        };
+        json!({
+            "type": "error",
+            "code": self.code(),
+            "message": self.to_string(),
+            "field": field,
+        })
+        .to_string()
+    }
+}
+
+impl fmt::Display for ClientMessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientMessageError::TooLarge { len } => write!(
+                f,
+                "Message is {len} bytes; the limit is {MAX_CLIENT_MESSAGE_BYTES}"
+            ),
+            ClientMessageError::BinaryFrame => f.write_str("Binary messages are not supported"),
+            ClientMessageError::InvalidJson => f.write_str("Message is not valid JSON"),
+            ClientMessageError::NotAnObject => f.write_str("Message must be a JSON object"),
+            ClientMessageError::MissingType => f.write_str("Message has no string 'type'"),
+            ClientMessageError::UnknownType(kind) => write!(f, "Unknown message type '{kind}'"),
+            ClientMessageError::MissingField(field) => write!(f, "Missing field '{field}'"),
+            ClientMessageError::InvalidField { field, reason } => {
+                write!(f, "Invalid field '{field}': {reason}")
+            }
+        }
+    }
+}
+
+fn optional_string(
+    object: &serde_json::Map<String, Value>,
+    field: &'static str,
+) -> Result<Option<String>, ClientMessageError> {
+    match object.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) if value.len() > MAX_FIELD_LEN => {
+            Err(ClientMessageError::InvalidField {
+                field,
+                reason: "too long",
+            })
+        }
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(ClientMessageError::InvalidField {
+            field,
+            reason: "must be a string",
+        }),
+    }
+}
+
+/// Parses a text frame from a WebSocket client.
+pub fn parse_client_message(text: &str) -> Result<ClientMessage, ClientMessageError> {
+    if text.len() > MAX_CLIENT_MESSAGE_BYTES {
+        return Err(ClientMessageError::TooLarge { len: text.len() });
+    }
+    let value: Value = serde_json::from_str(text).map_err(|_| ClientMessageError::InvalidJson)?;
+    let object = value.as_object().ok_or(ClientMessageError::NotAnObject)?;
+    let kind = object
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or(ClientMessageError::MissingType)?;
+
+    match kind {
+        "subscribe" => {
+            let payment_intent_id = optional_string(object, "payment_intent_id")?
+                .ok_or(ClientMessageError::MissingField("payment_intent_id"))?;
+            let well_formed = payment_intent_id.len() > "pi_".len()
+                && payment_intent_id.starts_with("pi_")
+                && payment_intent_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !well_formed {
+                return Err(ClientMessageError::InvalidField {
+                    field: "payment_intent_id",
+                    reason: "must be a PaymentIntent id",
+                });
+            }
+            Ok(ClientMessage::Subscribe {
+                payment_intent_id,
+                customer_id: optional_string(object, "customer_id")?,
+                customer_email: optional_string(object, "customer_email")?,
+            })
+        }
+        other => Err(ClientMessageError::UnknownType(
+            other.chars().take(MAX_FIELD_LEN).collect(),
+        )),
+    }
+}
+
 /// WebSocket handler for payment status updates
 pub async fn payment_status_ws_handler(
     ws: WebSocketUpgrade,
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Extension(db_pool): Extension<Arc<PgPool>>,
+    Extension(live): Extension<Arc<LiveConnections>>,
+    Extension(health): Extension<Arc<DatabaseHealth>>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state, db_pool))
+    ws.on_upgrade(|socket| handle_socket(socket, state, db_pool, live, health))
 }
 
 /// Handles an individual WebSocket connection
-async fn handle_socket(socket: WebSocket, state: Arc<Mutex<AppState>>, db_pool: Arc<PgPool>) {
+async fn handle_socket(
+    socket: WebSocket,
+    state: Arc<Mutex<AppState>>,
+    db_pool: Arc<PgPool>,
+    live: Arc<LiveConnections>,
+    health: Arc<DatabaseHealth>,
+) {
     let (mut sender, mut receiver) = socket.split();
     let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
-    // Task that forwards messages from the channel to the WebSocket
+    // Generate a unique connection ID
+    let connection_id = uuid::Uuid::new_v4().to_string();
+    let mut close_rx = live.open(&connection_id);
+
+    // Task that forwards messages from the channel to the WebSocket until an admin
+    // disconnects it
     let mut send_task = tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            if sender
-                .send(Message::Text(Utf8Bytes::try_from(message).unwrap()))
-                .await
-                .is_err()
-            {
-                break;
+        loop {
+            tokio::select! {
+                message = rx.recv() => {
+                    let Some(message) = message else { break };
+                    if sender
+                        .send(Message::Text(Utf8Bytes::from(message)))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                reason = &mut close_rx => {
+                    // The registry only drops the sender once the socket is closing
+                    let Ok(reason) = reason else { break };
+                    let frame = CloseFrame {
+                        code: close_code::RESTART,
+                        reason: Utf8Bytes::from(reason),
+                    };
+                    if let Err(e) = sender.send(Message::Close(Some(frame))).await {
+                        warn!("Failed to send close frame: {e}");
+                    }
+                    break;
+                }
             }
         }
     });
 
-    // Generate a unique connection ID
-    let connection_id = uuid::Uuid::new_v4().to_string();
-
     // Process incoming messages from the WebSocket
     let state_clone = state.clone();
     let db_pool_clone = db_pool.clone();
     let connection_id_clone = connection_id.clone();
+    let health_clone = health.clone();
 
     let mut receive_task = tokio::spawn(async move {
         while let Some(Ok(message)) = receiver.next().await {
-            if let Message::Text(text) = message {
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                    // Handle subscription request
-                    if let Some(msg_type) = json.get("type").and_then(|t| t.as_str()) {
-                        if msg_type == "subscribe" {
-                            if let Some(payment_intent_id) =
-                                json.get("payment_intent_id").and_then(|id| id.as_str())
-                            {
-                                info!(
-                                    "Client subscribed to payment updates for: {}",
-                                    payment_intent_id
-                                );
-
-                                // Get access to websocket service from state
-                                let state = state_clone.lock().await;
-                                if let Some(ws_service) = &state.websocket_service {
-                                    ws_service
-                                        .register_client(payment_intent_id.to_string(), tx.clone())
-                                        .await;
-                                }
-                                drop(state);
-
-                                // Store connection in database
-                                let customer_id = json
-                                    .get("customer_id")
-                                    .and_then(|id| id.as_str())
-                                    .map(String::from);
-                                let customer_email = json
-                                    .get("customer_email")
-                                    .and_then(|email| email.as_str())
-                                    .map(String::from);
-
-                                // Create a new WebSocketConnection record
-                                let ws_conn = crate::database::models::WebSocketConnection::new(
-                                    payment_intent_id.to_string(),
-                                    connection_id_clone.clone(),
-                                    customer_id.clone(),
-                                    customer_email.clone(),
-                                );
-
-                                // Save to database
-                                if let Ok(mut conn) = get_conn(&db_pool_clone) {
-                                    match diesel::insert_into(
-                                        crate::database::schema::websocket_connections::table,
-                                    )
-                                    .values(&ws_conn)
-                                    .execute(&mut conn)
-                                    {
-                                        Ok(_) => info!("Saved WebSocket connection to database"),
-                                        Err(e) => error!(
-                                            "Failed to save WebSocket connection to database: {}",
-                                            e
-                                        ),
-                                    }
-                                } else {
-                                    error!("Failed to get database connection from pool");
-                                }
+            let parsed = match message {
+                Message::Text(text) => parse_client_message(&text),
+                Message::Binary(_) => Err(ClientMessageError::BinaryFrame),
+                _ => continue,
+            };
+            let (payment_intent_id, customer_id, customer_email) = match parsed {
+                Ok(ClientMessage::Subscribe {
+                    payment_intent_id,
+                    customer_id,
+                    customer_email,
+                }) => (payment_intent_id, customer_id, customer_email),
+                Err(e) => {
+                    warn!("Rejected WebSocket message on {connection_id_clone}: {e}");
+                    if tx.send(e.to_message()).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            // Handle subscription request
+            info!(
+                "Client subscribed to payment updates for: {}",
+                payment_intent_id
+            );
+
+            // Get access to websocket service from state
+            let state = state_clone.lock().await;
+            if let Some(ws_service) = &state.websocket_service {
+                ws_service
+                    .register_client(payment_intent_id.clone(), tx.clone())
+                    .await;
+            }
+            drop(state);
 
-                                // Send confirmation to client
-                                let confirmation = json!({
-                                    "type": "subscription_confirmed",
-                                    "payment_intent_id": payment_intent_id
-                                })
-                                .to_string();
+            // Create a new WebSocketConnection record
+            let ws_conn = crate::database::models::WebSocketConnection::new(
+                payment_intent_id.clone(),
+                connection_id_clone.clone(),
+                customer_id,
+                customer_email,
+            );
 
-                                if tx.send(confirmation).is_err() {
-                                    break;
+            // Save to database, or hold the subscription until it is reachable again
+            match get_conn(&db_pool_clone) {
+                Ok(mut conn) => {
+                    match diesel::insert_into(crate::database::schema::websocket_connections::table)
+                        .values(&ws_conn)
+                        .execute(&mut conn)
+                    {
+                        Ok(_) => {
+                            info!("Saved WebSocket connection to database");
+                            health_clone.recovered();
+                            match health_clone.flush_registrations(&mut conn) {
+                                Ok(0) => {}
+                                Ok(flushed) => {
+                                    info!("Flushed {flushed} buffered WebSocket subscription(s)")
                                 }
+                                Err(e) => error!("Failed to flush buffered subscriptions: {}", e),
                             }
                         }
+                        Err(e) => {
+                            health_clone.failed("websocket", e);
+                            health_clone.buffer_registration(ws_conn);
+                        }
                     }
-                } else {
-                    error!("Failed to parse WebSocket message as JSON: {}", text);
                 }
+                Err(e) => {
+                    health_clone.failed("websocket", e);
+                    health_clone.buffer_registration(ws_conn);
+                }
+            }
+
+            // Send confirmation to client
+            let confirmation = json!({
+                "type": "subscription_confirmed",
+                "payment_intent_id": payment_intent_id
+            })
+            .to_string();
+
+            if tx.send(confirmation).is_err() {
+                break;
             }
         }
     });
@@ -138,13 +322,18 @@ async fn handle_socket(socket: WebSocket, state: Arc<Mutex<AppState>>, db_pool:
 
     // Clean up when connection is closed
     info!("WebSocket connection closed: {}", connection_id);
+    live.closed(&connection_id);
+    health.discard_registrations(&connection_id);
 
     // Update connection status in database to inactive
     if let Ok(mut conn) = get_conn(&db_pool) {
         use crate::database::schema::websocket_connections::dsl::*;
 
         match diesel::update(websocket_connections.filter(connection_id.eq(connection_id.clone())))
-            .set(status.eq("inactive"))
+            .set((
+                status.eq("inactive"),
+                updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
             .execute(&mut conn)
         {
             Ok(_) => info!("Updated WebSocket connection status to inactive"),
@@ -0,0 +1,223 @@
+//! Inspecting and disconnecting payment status WebSockets.
+//!
+//! Each subscription a client makes is a `websocket_connections` row, so one
+//! connection has a row per payment intent it follows. `GET /admin/ws_connections`
+//! lists the active connections with their subscriptions and age. Support can kick a
+//! stuck client with `DELETE /admin/ws_connections/{connection_id}`: if this
+//! instance holds the socket it is closed with a reason frame, and the rows are
+//! marked inactive either way so deliveries stop targeting it. The close code is
+//! 1012 (service restart), which clients treat as a cue to reconnect.
+use crate::database::{conn_from_state, db_error, models::WebSocketConnection};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::{oneshot, Mutex};
+use tracing::{info, warn};
+
+const DEFAULT_REASON: &str = "Disconnected by support";
+/// Close frame reasons must fit in a control frame with the code.
+const MAX_REASON_BYTES: usize = 123;
+
+/// The sockets this instance holds, each with a channel to close it.
+#[derive(Debug, Default)]
+pub struct LiveConnections {
+    sockets: std::sync::Mutex<HashMap<String, oneshot::Sender<String>>>,
+}
+
+impl LiveConnections {
+    /// Registers a socket and returns the channel its close reason arrives on.
+    pub fn open(&self, connection_id: &str) -> oneshot::Receiver<String> {
+        let (close_tx, close_rx) = oneshot::channel();
+        self.sockets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(connection_id.to_string(), close_tx);
+        close_rx
+    }
+
+    /// Forgets a socket once it has closed.
+    pub fn closed(&self, connection_id: &str) {
+        self.sockets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(connection_id);
+    }
+
+    /// Asks a socket held by this instance to close. False if it is not held here.
+    pub fn disconnect(&self, connection_id: &str, reason: &str) -> bool {
+        let close_tx = self
+            .sockets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(connection_id);
+        close_tx.is_some_and(|tx| tx.send(reason.to_string()).is_ok())
+    }
+
+    pub fn is_live(&self, connection_id: &str) -> bool {
+        self.sockets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(connection_id)
+    }
+}
+
+/// Truncates a close reason to fit a close frame, on a character boundary.
+pub fn close_reason(reason: Option<&str>) -> String {
+    let reason = reason
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .unwrap_or(DEFAULT_REASON);
+    let mut end = reason.len().min(MAX_REASON_BYTES);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    reason[..end].to_string()
+}
+
+/// An active connection and the payment intents it is subscribed to.
+#[derive(Debug, Serialize)]
+pub struct ConnectionSummary {
+    pub connection_id: String,
+    pub subscriptions: Vec<String>,
+    pub customer_id: Option<String>,
+    pub customer_email: Option<String>,
+    pub opened_at: NaiveDateTime,
+    pub age_seconds: i64,
+    /// Whether this instance holds the socket.
+    pub live_here: bool,
+}
+
+/// Groups active subscription rows by connection, oldest connection first.
+pub fn summarize_connections(
+    rows: Vec<WebSocketConnection>,
+    now: NaiveDateTime,
+    live: &LiveConnections,
+) -> Vec<ConnectionSummary> {
+    let mut by_connection: BTreeMap<String, ConnectionSummary> = BTreeMap::new();
+    for row in rows {
+        let summary = by_connection
+            .entry(row.connection_id.clone())
+            .or_insert_with(|| ConnectionSummary {
+                connection_id: row.connection_id.clone(),
+                subscriptions: Vec::new(),
+                customer_id: None,
+                customer_email: None,
+                opened_at: row.created_at,
+                age_seconds: 0,
+                live_here: live.is_live(&row.connection_id),
+            });
+        summary.opened_at = summary.opened_at.min(row.created_at);
+        summary.customer_id = summary.customer_id.take().or(row.customer_id);
+        summary.customer_email = summary.customer_email.take().or(row.customer_email);
+        summary.subscriptions.push(row.payment_intent_id);
+    }
+    let mut connections: Vec<ConnectionSummary> = by_connection
+        .into_values()
+        .map(|mut summary| {
+            summary.subscriptions.sort();
+            summary.subscriptions.dedup();
+            summary.age_seconds = (now - summary.opened_at).num_seconds().max(0);
+            summary
+        })
+        .collect();
+    connections.sort_by_key(|c| c.opened_at);
+    connections
+}
+
+#[derive(Debug, Serialize)]
+pub struct WsConnectionsResponse {
+    pub connections: Vec<ConnectionSummary>,
+}
+
+/// GET /admin/ws_connections lists active WebSocket connections with their
+/// subscriptions and age.
+#[tracing::instrument(skip(state, live))]
+pub async fn list_ws_connections_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(live): Extension<Arc<LiveConnections>>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::websocket_connections::dsl::*;
+
+    let mut conn = conn_from_state(&state).await?;
+    let rows = websocket_connections
+        .filter(status.eq("active"))
+        .order(created_at.asc())
+        .load::<WebSocketConnection>(&mut conn)
+        .map_err(db_error("Failed to load WebSocket connections"))?;
+
+    let now = chrono::Utc::now().naive_utc();
+    Ok(axum::Json(json!(WsConnectionsResponse {
+        connections: summarize_connections(rows, now, &live),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisconnectQuery {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DisconnectResponse {
+    pub connection_id: String,
+    /// Whether this instance held the socket and closed it. A socket held by another
+    /// instance stays open but no longer receives updates.
+    pub closed: bool,
+    pub reason: String,
+    /// Subscription rows marked inactive.
+    pub deactivated: usize,
+}
+
+/// DELETE /admin/ws_connections/{connection_id} closes a connection with a reason
+/// frame and marks its subscriptions inactive.
+#[tracing::instrument(skip(state, live))]
+pub async fn disconnect_ws_connection_handler(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Extension(live): Extension<Arc<LiveConnections>>,
+    Path(target): Path<String>,
+    Query(query): Query<DisconnectQuery>,
+) -> Result<axum::Json<Value>, (StatusCode, String)> {
+    use crate::database::schema::websocket_connections::dsl::*;
+
+    let reason = close_reason(query.reason.as_deref());
+    let closed = live.disconnect(&target, &reason);
+
+    let mut conn = conn_from_state(&state).await?;
+    let deactivated = diesel::update(
+        websocket_connections
+            .filter(connection_id.eq(&target))
+            .filter(status.eq("active")),
+    )
+    .set((
+        status.eq("inactive"),
+        updated_at.eq(chrono::Utc::now().naive_utc()),
+    ))
+    .execute(&mut conn)
+    .map_err(db_error("Failed to deactivate WebSocket connection"))?;
+
+    if !closed && deactivated == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No active WebSocket connection {target}"),
+        ));
+    }
+    if closed {
+        info!("Disconnected WebSocket connection {target}: {reason}");
+    } else {
+        warn!("WebSocket connection {target} is not held by this instance; marked inactive only");
+    }
+
+    Ok(axum::Json(json!(DisconnectResponse {
+        connection_id: target,
+        closed,
+        reason,
+        deactivated,
+    })))
+}
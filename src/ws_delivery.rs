@@ -0,0 +1,170 @@
+//! WebSocket fan-out with per-connection results.
+//!
+//! The WebSocket service sends a message to a batch of connections and fails the
+//! whole batch on the first error. [`send_to_connections`] instead sends to each
+//! connection on its own, retries failures with exponential backoff, and marks
+//! connections that still fail after [`MAX_ATTEMPTS`] as inactive so later messages
+//! skip them. The caller gets a [`DeliverySummary`] covering every connection.
+use crate::database::models::NewNotificationDelivery;
+use crate::notifications::{latency_ms, record_delivery, Channel};
+use diesel::prelude::*;
+use lambda_lib::AppState;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+/// Attempts per connection before it is considered permanently failed.
+pub const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry; doubled for each further retry.
+const BASE_BACKOFF: Duration = Duration::from_millis(50);
+
+/// The outcome of sending to one connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionResult {
+    pub connection_id: String,
+    pub attempts: u32,
+    /// `None` when the message was delivered.
+    pub error: Option<String>,
+    pub latency_ms: i32,
+}
+
+/// Per-connection results of a fan-out.
+#[derive(Debug, Default, Serialize)]
+pub struct DeliverySummary {
+    pub results: Vec<ConnectionResult>,
+    /// Connections marked inactive after exhausting their retries.
+    pub deactivated: Vec<String>,
+}
+
+impl DeliverySummary {
+    pub fn sent(&self) -> usize {
+        self.results.iter().filter(|r| r.error.is_none()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.sent()
+    }
+}
+
+/// Sends `message` to one connection, retrying failures with backoff.
+async fn send_with_retry(
+    state: &Arc<Mutex<AppState>>,
+    payment_intent_id: &str,
+    message: &str,
+    connection_id: &str,
+) -> ConnectionResult {
+    let started = Instant::now();
+    let targets = vec![connection_id.to_string()];
+    let mut attempts = 0;
+    let mut last_error = None;
+    while attempts < MAX_ATTEMPTS {
+        if attempts > 0 {
+            tokio::time::sleep(BASE_BACKOFF * 2u32.pow(attempts - 1)).await;
+        }
+        attempts += 1;
+
+        // Hold the state lock only for the send, not across the backoff
+        let result = match &state.lock().await.websocket_service {
+            Some(ws_service) => ws_service
+                .send_message_to_clients(payment_intent_id, message, &targets)
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("WebSocket service not available in AppState".to_string()),
+        };
+        match result {
+            Ok(_) => {
+                last_error = None;
+                break;
+            }
+            Err(e) => {
+                warn!("Send to connection {connection_id} failed (attempt {attempts}): {e}");
+                last_error = Some(e);
+            }
+        }
+    }
+    ConnectionResult {
+        connection_id: connection_id.to_string(),
+        attempts,
+        error: last_error,
+        latency_ms: latency_ms(started.elapsed()),
+    }
+}
+
+/// Sends `message` to each connection and deactivates connections that could not be
+/// reached.
+pub async fn send_to_connections(
+    state: &Arc<Mutex<AppState>>,
+    conn: &mut PgConnection,
+    payment_intent_id: &str,
+    message: &str,
+    connection_ids: &[String],
+) -> DeliverySummary {
+    let mut summary = DeliverySummary::default();
+    for connection_id in connection_ids {
+        let result = send_with_retry(state, payment_intent_id, message, connection_id).await;
+        if result.error.is_some() {
+            summary.deactivated.push(connection_id.clone());
+        }
+        summary.results.push(result);
+    }
+
+    if !summary.deactivated.is_empty() {
+        use crate::database::schema::websocket_connections;
+
+        match diesel::update(
+            websocket_connections::table
+                .filter(websocket_connections::connection_id.eq_any(&summary.deactivated)),
+        )
+        .set((
+            websocket_connections::status.eq("inactive"),
+            websocket_connections::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+        {
+            Ok(count) => warn!(
+                "Marked {count} unreachable connection(s) inactive for payment intent {payment_intent_id}"
+            ),
+            Err(e) => error!("Failed to deactivate unreachable connections: {e}"),
+        }
+    }
+
+    info!(
+        "WebSocket delivery for payment intent {payment_intent_id}: {} sent, {} failed",
+        summary.sent(),
+        summary.failed()
+    );
+    summary
+}
+
+/// Records one `notification_deliveries` row per connection in `summary`.
+pub fn record_connection_deliveries(
+    conn: &mut PgConnection,
+    summary: &DeliverySummary,
+    template: &str,
+    payment_intent_id: &str,
+) {
+    for result in &summary.results {
+        record_delivery(
+            conn,
+            NewNotificationDelivery {
+                outbox_id: None,
+                channel: Channel::WebSocket.as_str().to_string(),
+                target: result.connection_id.clone(),
+                template: template.to_string(),
+                outcome: if result.error.is_none() {
+                    "sent".to_string()
+                } else {
+                    "failed".to_string()
+                },
+                error: result.error.clone(),
+                latency_ms: result.latency_ms,
+                attempt: i32::try_from(result.attempts).unwrap_or(i32::MAX),
+                payment_intent_id: Some(payment_intent_id.to_string()),
+                registration_id: None,
+                next_attempt_at: None,
+            },
+        );
+    }
+}
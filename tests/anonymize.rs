@@ -0,0 +1,131 @@
+//! Tests for the anonymization rules applied to the analytics datasets, and for the
+//! daily `analytics` job that queues them against Postgres.
+mod common;
+
+use camp_registration_lambda::anonymize::{
+    age_bucket, day, AnonymizedMetadata, Anonymizer, CAMPER, GUARDIAN, REGISTRATION,
+};
+use camp_registration_lambda::database::schema::export_jobs;
+use chrono::NaiveDate;
+use common::TestApp;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const KEY: &str = "test-anonymization-key-0123456789abcdef";
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn pseudonyms_are_stable_and_keyed() {
+    let id = Uuid::new_v4();
+    let anonymizer = Anonymizer::new(KEY).unwrap();
+    let pseudonym = anonymizer.pseudonym(CAMPER, id);
+
+    assert_eq!(
+        pseudonym,
+        Anonymizer::new(KEY).unwrap().pseudonym(CAMPER, id)
+    );
+    assert_eq!(pseudonym.len(), 32);
+    assert!(!pseudonym.contains(&id.simple().to_string()));
+
+    let rotated = Anonymizer::new(format!("{KEY}-rotated")).unwrap();
+    assert_ne!(pseudonym, rotated.pseudonym(CAMPER, id));
+}
+
+#[test]
+fn pseudonym_namespaces_do_not_collide() {
+    let id = Uuid::new_v4();
+    let anonymizer = Anonymizer::new(KEY).unwrap();
+    assert_ne!(
+        anonymizer.pseudonym(CAMPER, id),
+        anonymizer.pseudonym(GUARDIAN, id)
+    );
+}
+
+#[test]
+fn short_keys_are_rejected() {
+    assert!(Anonymizer::new("too-short").is_err());
+}
+
+#[test]
+fn ages_are_bucketed_on_the_given_day() {
+    let birthdate = date(2015, 6, 15);
+    assert_eq!(age_bucket(birthdate, date(2021, 6, 14)), "under 6");
+    assert_eq!(age_bucket(birthdate, date(2021, 6, 15)), "6-8");
+    assert_eq!(age_bucket(birthdate, date(2027, 6, 14)), "9-11");
+    assert_eq!(age_bucket(birthdate, date(2027, 6, 15)), "12-14");
+    assert_eq!(age_bucket(birthdate, date(2032, 7, 1)), "15-17");
+    assert_eq!(age_bucket(birthdate, date(2033, 6, 15)), "18+");
+}
+
+#[test]
+fn timestamps_are_cut_to_the_day() {
+    let timestamp = date(2026, 7, 4).and_hms_opt(15, 42, 7).unwrap();
+    assert_eq!(day(timestamp), date(2026, 7, 4));
+}
+
+#[test]
+fn payment_metadata_keeps_only_declared_fields_without_free_text() {
+    let registration = Uuid::new_v4();
+    let anonymizer = Anonymizer::new(KEY).unwrap();
+    let metadata = json!({
+        "purpose": "registration",
+        "frontend_id": "ios-jane-phone",
+        "registration_ids": registration.to_string(),
+        "note": "Pay for Jane Doe, allergic to peanuts",
+    });
+
+    assert_eq!(
+        anonymizer.payment_metadata(Some(&metadata)),
+        AnonymizedMetadata {
+            purpose: Some("registration".to_string()),
+            registrations: vec![anonymizer.pseudonym(REGISTRATION, registration)],
+        }
+    );
+    assert_eq!(
+        anonymizer.payment_metadata(None),
+        AnonymizedMetadata::default()
+    );
+}
+
+async fn run_analytics(app: &TestApp) -> Value {
+    let response = app
+        .admin(Method::POST, "/admin/jobs/analytics")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["summary"].clone()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn analytics_job_queues_each_dataset_once_per_day() {
+    let app = TestApp::spawn().await;
+
+    let first = run_analytics(&app).await;
+    assert_eq!(first["queued"], 2);
+    let day = first["day"].as_str().unwrap().to_string();
+    let mut kinds: Vec<(String, Value)> = export_jobs::table
+        .select((export_jobs::kind, export_jobs::params))
+        .load(&mut app.conn())
+        .unwrap();
+    kinds.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(kinds[0].0, "anonymized_payments");
+    assert_eq!(kinds[1].0, "anonymized_registrations");
+    assert_eq!(kinds[1].1["from"], day.as_str());
+    assert_eq!(kinds[1].1["to"], day.as_str());
+
+    // A rerun the same day adds nothing, but a failed dataset is queued again
+    assert_eq!(run_analytics(&app).await["queued"], 0);
+    diesel::update(export_jobs::table.filter(export_jobs::kind.eq("anonymized_payments")))
+        .set(export_jobs::status.eq("failed"))
+        .execute(&mut app.conn())
+        .unwrap();
+    assert_eq!(run_analytics(&app).await["queued"], 1);
+}
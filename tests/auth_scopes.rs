@@ -0,0 +1,150 @@
+//! Tests for token scopes and read-only enforcement, and for auditor tokens issued
+//! and used against Postgres.
+mod common;
+
+use axum::http::StatusCode;
+use camp_registration_lambda::auth::{is_read_only_route, Actor, Role, Scope};
+use common::{seed_confirmed_registration, TestApp};
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use uuid::Uuid;
+
+fn actor(scopes: Vec<Scope>) -> Actor {
+    Actor {
+        token_id: Some(Uuid::new_v4()),
+        role: Role::Director,
+        subject_id: None,
+        scopes,
+    }
+}
+
+#[test]
+fn scopes_round_trip() {
+    assert_eq!(Scope::from_str("read_only"), Ok(Scope::ReadOnly));
+    assert_eq!(Scope::ReadOnly.to_string(), "read_only");
+    assert!(Scope::from_str("write_all").is_err());
+    assert_eq!(
+        serde_json::from_str::<Vec<Scope>>(r#"["read_only"]"#).unwrap(),
+        vec![Scope::ReadOnly]
+    );
+}
+
+#[test]
+fn read_only_routes_are_reads() {
+    assert!(is_read_only_route("GET", "/admin/reports/session_revenue"));
+    assert!(is_read_only_route("HEAD", "/sessions"));
+    assert!(!is_read_only_route("POST", "/admin/exports"));
+    assert!(!is_read_only_route("POST", "/admin/sessions"));
+    assert!(!is_read_only_route("PUT", "/me/billing_address"));
+    assert!(!is_read_only_route("DELETE", "/admin/api_tokens/{id}"));
+}
+
+#[test]
+fn read_only_tokens_cannot_mutate() {
+    let auditor = actor(vec![Scope::ReadOnly]);
+    assert!(auditor.is_read_only());
+    assert!(auditor
+        .require_scope_for("GET", "/admin/payments/{id}/timeline")
+        .is_ok());
+    assert!(auditor.require_scope_for("POST", "/admin/exports").is_err());
+    let (status, message) = auditor
+        .require_scope_for("POST", "/admin/sessions/{id}/cancel")
+        .unwrap_err();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(message, "Token is read-only");
+
+    // The role still applies; unscoped tokens are unaffected
+    let director = actor(Vec::new());
+    assert!(!director.is_read_only());
+    assert!(director
+        .require_scope_for("POST", "/admin/sessions/{id}/cancel")
+        .is_ok());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn auditor_tokens_read_but_never_write() {
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+
+    let too_long = app
+        .admin(Method::POST, "/admin/api_tokens/auditor")
+        .json(&json!({ "label": "Year-end audit", "expires_in_days": 365 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(too_long.status(), StatusCode::BAD_REQUEST);
+    let issued: Value = app
+        .admin(Method::POST, "/admin/api_tokens/auditor")
+        .json(&json!({ "label": "Year-end audit" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issued["api_token"]["role"], "director");
+    assert_eq!(issued["api_token"]["scopes"], json!(["read_only"]));
+    assert!(issued["api_token"]["expires_at"].is_string());
+    let auditor = issued["token"].as_str().unwrap().to_string();
+    let as_auditor = |method: Method, path: &str| {
+        app.http
+            .request(method, format!("{}{path}", app.base_url))
+            .bearer_auth(&auditor)
+    };
+
+    let report = as_auditor(Method::GET, "/admin/reports/session_revenue?month=2026-06")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(report.status(), StatusCode::OK);
+    let cancel = as_auditor(
+        Method::POST,
+        &format!("/admin/sessions/{}/cancel", seed.session_id),
+    )
+    .json(&json!({ "reason": "Audit" }))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(cancel.status(), StatusCode::FORBIDDEN);
+    assert_eq!(cancel.text().await.unwrap(), "Token is read-only");
+    let export = as_auditor(Method::POST, "/admin/exports")
+        .json(&json!({ "kind": "payments", "from": "2026-01-01", "to": "2026-06-30" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(export.status(), StatusCode::FORBIDDEN);
+
+    // Scopes narrow any role, even admin
+    let read_only_admin = app.issue_token("admin", None, &["read_only"]);
+    let revoke = app
+        .http
+        .delete(format!(
+            "{}/admin/api_tokens/{}",
+            app.base_url,
+            issued["api_token"]["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&read_only_admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(revoke.status(), StatusCode::FORBIDDEN);
+    let revoke = app
+        .admin(
+            Method::DELETE,
+            &format!(
+                "/admin/api_tokens/{}",
+                issued["api_token"]["id"].as_str().unwrap()
+            ),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert!(revoke.status().is_success());
+    let revoked = as_auditor(Method::GET, "/admin/reports/session_revenue?month=2026-06")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(revoked.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,310 @@
+//! Tests for cabin suggestions from friend requests, and for the session cabin
+//! endpoints against Postgres.
+mod common;
+
+use camp_registration_lambda::cabin_matching::{
+    suggest_cabins, unmatched_requests, CabinCamper, CabinRules, CabinmateRequest, UnmatchedReason,
+};
+use chrono::NaiveDate;
+use common::TestApp;
+use diesel::connection::SimpleConnection;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+fn camper(n: u128, name: &str, birthdate: (i32, u32, u32)) -> CabinCamper {
+    let (first_name, last_name) = name.split_once(' ').unwrap();
+    CabinCamper {
+        registration_id: Uuid::from_u128(n),
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        birthdate: NaiveDate::from_ymd_opt(birthdate.0, birthdate.1, birthdate.2).unwrap(),
+    }
+}
+
+fn request(n: u128, friend: &str) -> CabinmateRequest {
+    CabinmateRequest {
+        registration_id: Uuid::from_u128(n),
+        friend_name: friend.to_string(),
+    }
+}
+
+fn cabin_of(plan_cabins: &[Vec<Uuid>]) -> HashMap<Uuid, usize> {
+    plan_cabins
+        .iter()
+        .enumerate()
+        .flat_map(|(i, cabin)| cabin.iter().map(move |id| (*id, i)))
+        .collect()
+}
+
+const RULES: CabinRules = CabinRules {
+    cabin_size: 2,
+    max_age_gap_months: 24,
+};
+
+#[test]
+fn mutual_requests_win_over_one_way_requests() {
+    let campers = [
+        camper(1, "Avery Lindqvist", (2015, 4, 12)),
+        camper(2, "Riley Okafor", (2015, 6, 1)),
+        camper(3, "Sam Patel", (2015, 8, 20)),
+    ];
+    // Sam wants Avery, but Avery and Riley asked for each other
+    let requests = [
+        request(3, "Avery Lindqvist"),
+        request(1, "riley  okafor"),
+        request(2, "Avery"),
+    ];
+    let plan = suggest_cabins(&campers, &requests, RULES);
+    let cabins: Vec<Vec<Uuid>> = plan
+        .cabins
+        .iter()
+        .map(|c| c.registration_ids.clone())
+        .collect();
+    let cabin = cabin_of(&cabins);
+
+    assert_eq!(cabin[&Uuid::from_u128(1)], cabin[&Uuid::from_u128(2)]);
+    assert_ne!(cabin[&Uuid::from_u128(1)], cabin[&Uuid::from_u128(3)]);
+    assert_eq!(plan.unmatched_requests.len(), 1);
+    assert_eq!(
+        plan.unmatched_requests[0].registration_id,
+        Uuid::from_u128(3)
+    );
+    assert_eq!(
+        plan.unmatched_requests[0].reason,
+        UnmatchedReason::DifferentCabin
+    );
+}
+
+#[test]
+fn age_gap_keeps_friends_apart() {
+    let campers = [
+        camper(1, "Avery Lindqvist", (2012, 1, 1)),
+        camper(2, "Riley Okafor", (2016, 1, 1)),
+    ];
+    let requests = [request(1, "Riley Okafor"), request(2, "Avery Lindqvist")];
+    let plan = suggest_cabins(&campers, &requests, RULES);
+
+    assert_eq!(plan.cabins.len(), 2);
+    assert_eq!(plan.unmatched_requests.len(), 2);
+}
+
+#[test]
+fn every_camper_is_placed_within_the_cabin_size() {
+    let campers: Vec<CabinCamper> = (1..=7)
+        .map(|n| camper(n, &format!("Camper{n} Test"), (2015, 1, n as u32)))
+        .collect();
+    let requests: Vec<CabinmateRequest> = (1..7)
+        .map(|n| request(n, &format!("Camper{} Test", n + 1)))
+        .collect();
+    let rules = CabinRules {
+        cabin_size: 3,
+        max_age_gap_months: 24,
+    };
+    let plan = suggest_cabins(&campers, &requests, rules);
+
+    let placed: usize = plan.cabins.iter().map(|c| c.registration_ids.len()).sum();
+    assert_eq!(placed, campers.len());
+    assert!(plan.cabins.iter().all(|c| c.registration_ids.len() <= 3));
+}
+
+#[test]
+fn unresolvable_names_are_reported() {
+    let campers = [
+        camper(1, "Avery Lindqvist", (2015, 4, 12)),
+        camper(2, "Sam Patel", (2015, 6, 1)),
+        camper(3, "Sam Rivera", (2015, 7, 1)),
+    ];
+    let requests = [request(1, "Jordan Blake"), request(1, "Sam")];
+    let reasons: Vec<UnmatchedReason> = unmatched_requests(&campers, &requests, &HashMap::new())
+        .into_iter()
+        .map(|u| u.reason)
+        .collect();
+
+    assert_eq!(
+        reasons,
+        [UnmatchedReason::NotRegistered, UnmatchedReason::Ambiguous]
+    );
+}
+
+#[test]
+fn requests_without_cabins_are_unassigned() {
+    let campers = [
+        camper(1, "Avery Lindqvist", (2015, 4, 12)),
+        camper(2, "Riley Okafor", (2015, 6, 1)),
+    ];
+    let requests = [request(1, "Riley Okafor")];
+    let saved = HashMap::from([(Uuid::from_u128(1), "Heron".to_string())]);
+    let unmatched = unmatched_requests(&campers, &requests, &saved);
+
+    assert_eq!(unmatched.len(), 1);
+    assert_eq!(unmatched[0].reason, UnmatchedReason::Unassigned);
+    assert_eq!(
+        unmatched[0].friend_registration_id,
+        Some(Uuid::from_u128(2))
+    );
+}
+
+/// A session with three campers from one family: Avery and Riley are confirmed and
+/// Sam is not yet registered.
+struct CabinSession {
+    guardian_id: Uuid,
+    session_id: Uuid,
+    avery: Uuid,
+    riley: Uuid,
+    sam_camper: Uuid,
+}
+
+fn seed_cabin_session(app: &TestApp) -> CabinSession {
+    let (guardian, session, avery, riley) = (
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+    );
+    let (avery_camper, riley_camper, sam_camper) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    app.conn()
+        .batch_execute(&format!(
+            r#"
+            INSERT INTO guardians (id, email, name)
+                VALUES ('{guardian}', '{guardian}@example.com', 'Test Guardian');
+            INSERT INTO campers (id, guardian_id, first_name, last_name, birthdate)
+                VALUES ('{avery_camper}', '{guardian}', 'Avery', 'Lindqvist', '2015-04-12'),
+                       ('{riley_camper}', '{guardian}', 'Riley', 'Okafor', '2015-06-01'),
+                       ('{sam_camper}', '{guardian}', 'Sam', 'Patel', '2015-08-20');
+            INSERT INTO camp_sessions (id, name, starts_on, ends_on, capacity, price, currency)
+                VALUES ('{session}', 'Week 1', '2027-07-05', '2027-07-09', 10, 45000, 'usd');
+            INSERT INTO registrations (id, guardian_id, camper_id, session_id, status)
+                VALUES ('{avery}', '{guardian}', '{avery_camper}', '{session}', 'confirmed'),
+                       ('{riley}', '{guardian}', '{riley_camper}', '{session}', 'confirmed');
+            INSERT INTO friend_requests (registration_id, friend_name)
+                VALUES ('{avery}', 'Riley Okafor'), ('{riley}', 'Avery');
+            "#
+        ))
+        .expect("failed to seed cabin session");
+    CabinSession {
+        guardian_id: guardian,
+        session_id: session,
+        avery,
+        riley,
+        sam_camper,
+    }
+}
+
+fn cabins(app: &TestApp, method: Method, seed: &CabinSession) -> reqwest::RequestBuilder {
+    app.admin(
+        method,
+        &format!("/admin/sessions/{}/cabins", seed.session_id),
+    )
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn friend_requests_are_captured_and_reported_against_saved_cabins() {
+    let app = TestApp::spawn().await;
+    let seed = seed_cabin_session(&app);
+
+    // Sam registers asking for Avery; the request is stored as named
+    let response = app
+        .guardian(seed.guardian_id, Method::POST, "/registrations")
+        .json(&json!({
+            "camper_id": seed.sam_camper,
+            "session_id": seed.session_id,
+            "friend_requests": [" Avery Lindqvist ", "avery lindqvist"],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let created: Value = response.json().await.unwrap();
+    assert_eq!(created["friend_requests"], json!(["Avery Lindqvist"]));
+    let sam: Uuid = created["registration"]["id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    // A pending registration is not placed in a cabin
+    let pending = cabins(&app, Method::PUT, &seed)
+        .json(&json!({ "cabins": [{ "name": "Heron", "registration_ids": [sam] }] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(pending.status(), 422);
+
+    app.conn()
+        .batch_execute(&format!(
+            "UPDATE registrations SET status = 'confirmed' WHERE id = '{sam}';"
+        ))
+        .unwrap();
+
+    // Before any cabins are saved, every request is unassigned and the suggestion
+    // pairs the mutual friends
+    let report: Value = cabins(&app, Method::GET, &seed)
+        .query(&[("cabin_size", "2")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["unassigned"].as_array().unwrap().len(), 3);
+    let unmatched = report["unmatched_requests"].as_array().unwrap();
+    assert_eq!(unmatched.len(), 3);
+    assert!(unmatched.iter().all(|u| u["reason"] == "unassigned"));
+    let suggestion = &report["suggestion"];
+    let paired = json!([seed.avery, seed.riley]);
+    let paired_reversed = json!([seed.riley, seed.avery]);
+    assert!(suggestion["cabins"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|c| c["registration_ids"] == paired || c["registration_ids"] == paired_reversed));
+    assert_eq!(
+        suggestion["unmatched_requests"][0]["registration_id"],
+        sam.to_string()
+    );
+
+    // Saving cabins that split Avery and Riley reports both of their requests
+    let saved = cabins(&app, Method::PUT, &seed)
+        .json(&json!({ "cabins": [
+            { "name": "Heron", "registration_ids": [seed.avery, sam] },
+            { "name": "Osprey", "registration_ids": [seed.riley] },
+        ] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(saved.status(), 200);
+    let saved: Value = saved.json().await.unwrap();
+    let mut split: Vec<(String, String)> = saved["unmatched_requests"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| {
+            (
+                u["registration_id"].as_str().unwrap().to_string(),
+                u["reason"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    split.sort();
+    let mut expected = vec![
+        (seed.avery.to_string(), "different_cabin".to_string()),
+        (seed.riley.to_string(), "different_cabin".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(split, expected);
+
+    let report: Value = cabins(&app, Method::GET, &seed)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["cabins"][0]["name"], "Heron");
+    assert_eq!(report["cabins"][1]["name"], "Osprey");
+    assert_eq!(report["unassigned"], json!([]));
+    assert_eq!(report["unmatched_requests"].as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,208 @@
+//! Camp credit applied to quotes against Postgres: the credit is reserved by the quote,
+//! debited only when its payment succeeds and matches the quote, and returned when the
+//! reservation lapses.
+mod common;
+
+use camp_registration_lambda::database::schema::{camp_credits, quotes, registrations};
+use common::{payment_intent_event, seed_pending_registration, PendingRegistration, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// A pending registration whose guardian holds `credit` of camp credit.
+fn seed_with_credit(app: &TestApp, price: i64, credit: i64) -> PendingRegistration {
+    let mut conn = app.conn();
+    let seed = seed_pending_registration(&mut conn, price);
+    conn.batch_execute(&format!(
+        "INSERT INTO camp_credits (id, guardian_id, amount, currency, reason)
+         VALUES ('{}', '{}', {credit}, 'usd', 'voucher_redemption');",
+        Uuid::new_v4(),
+        seed.guardian_id
+    ))
+    .unwrap();
+    seed
+}
+
+async fn quote_with_credit(app: &TestApp, seed: &PendingRegistration) -> Value {
+    let response = app
+        .admin(Method::POST, "/quote")
+        .json(&json!({
+            "guardian_id": seed.guardian_id,
+            "registration_ids": [seed.registration_id],
+            "currency": "usd",
+            "apply_credit": true,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+fn ledger(app: &TestApp, seed: &PendingRegistration) -> Vec<(i64, String)> {
+    camp_credits::table
+        .filter(camp_credits::guardian_id.eq(seed.guardian_id))
+        .order(camp_credits::created_at.asc())
+        .select((camp_credits::amount, camp_credits::reason))
+        .load(&mut app.conn())
+        .unwrap()
+}
+
+fn credit_status(app: &TestApp, quote_id: &str) -> String {
+    quotes::table
+        .find(quote_id.parse::<Uuid>().unwrap())
+        .select(quotes::credit_status)
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn credit_is_reserved_by_the_quote_and_debited_on_payment() {
+    let app = TestApp::spawn().await;
+    let seed = seed_with_credit(&app, 45_000, 10_000);
+
+    let quote = quote_with_credit(&app, &seed).await;
+    assert_eq!(quote["credit_applied"], 10_000);
+    let quote_id = quote["quote_id"].as_str().unwrap();
+    assert_eq!(credit_status(&app, quote_id), "reserved");
+    // Nothing leaves the ledger until the payment succeeds
+    assert_eq!(ledger(&app, &seed).len(), 1);
+
+    // A second checkout cannot spend the reserved credit
+    let again = quote_with_credit(&app, &seed).await;
+    assert_eq!(again["credit_applied"], 0);
+
+    let total = quote["total"].as_i64().unwrap();
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &format!("pi_{}", Uuid::new_v4().simple()),
+        total,
+        "usd",
+        json!({
+            "quote_id": quote_id,
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+    // Redelivery does not debit twice
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    assert_eq!(credit_status(&app, quote_id), "redeemed");
+    assert_eq!(
+        ledger(&app, &seed),
+        vec![
+            (10_000, "voucher_redemption".to_string()),
+            (-10_000, "quote_redemption".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn lapsed_reservations_are_released_by_the_holds_job() {
+    let app = TestApp::spawn().await;
+    let seed = seed_with_credit(&app, 45_000, 10_000);
+
+    let quote = quote_with_credit(&app, &seed).await;
+    let quote_id = quote["quote_id"].as_str().unwrap();
+    diesel::update(quotes::table.find(quote_id.parse::<Uuid>().unwrap()))
+        .set(quotes::credit_expires_at.eq(Some(
+            chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1),
+        )))
+        .execute(&mut app.conn())
+        .unwrap();
+
+    let response = app
+        .admin(Method::POST, "/admin/jobs/holds")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["summary"]["credit_released"], 1);
+    assert_eq!(credit_status(&app, quote_id), "released");
+
+    // The credit is available again and was never debited
+    let retry = quote_with_credit(&app, &seed).await;
+    assert_eq!(retry["credit_applied"], 10_000);
+    assert_eq!(ledger(&app, &seed).len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn mismatched_payments_keep_the_credit_reserved() {
+    let app = TestApp::spawn().await;
+    let seed = seed_with_credit(&app, 45_000, 10_000);
+
+    let quote = quote_with_credit(&app, &seed).await;
+    let quote_id = quote["quote_id"].as_str().unwrap();
+    // Paid the full price, as if the credit had not been applied
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &format!("pi_{}", Uuid::new_v4().simple()),
+        seed.price,
+        "usd",
+        json!({
+            "quote_id": quote_id,
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    assert_eq!(credit_status(&app, quote_id), "reserved");
+    assert_eq!(ledger(&app, &seed).len(), 1);
+    let status: String = registrations::table
+        .find(seed.registration_id)
+        .select(registrations::status)
+        .first(&mut app.conn())
+        .unwrap();
+    assert_eq!(status, "payment_review");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn lapsed_credit_spent_elsewhere_is_not_debited_twice() {
+    let app = TestApp::spawn().await;
+    let seed = seed_with_credit(&app, 45_000, 10_000);
+
+    let quote = quote_with_credit(&app, &seed).await;
+    let quote_id = quote["quote_id"].as_str().unwrap();
+    diesel::update(quotes::table.find(quote_id.parse::<Uuid>().unwrap()))
+        .set(quotes::credit_expires_at.eq(Some(
+            chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1),
+        )))
+        .execute(&mut app.conn())
+        .unwrap();
+    app.admin(Method::POST, "/admin/jobs/holds")
+        .send()
+        .await
+        .unwrap();
+    // A second checkout reserves the released credit
+    let retry = quote_with_credit(&app, &seed).await;
+    assert_eq!(retry["credit_applied"], 10_000);
+
+    // The first quote's payment still arrives, charged the reduced total
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &format!("pi_{}", Uuid::new_v4().simple()),
+        quote["total"].as_i64().unwrap(),
+        "usd",
+        json!({
+            "quote_id": quote_id,
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    assert_eq!(credit_status(&app, quote_id), "shortfall");
+    assert_eq!(ledger(&app, &seed).len(), 1);
+    let status: String = registrations::table
+        .find(seed.registration_id)
+        .select(registrations::status)
+        .first(&mut app.conn())
+        .unwrap();
+    assert_eq!(status, "payment_review");
+}
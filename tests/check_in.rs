@@ -0,0 +1,81 @@
+//! QR code check-in against Postgres: a signed code checks its confirmed camper in
+//! once, and forged or revoked codes are refused.
+mod common;
+
+use camp_registration_lambda::check_in::{check_in_code, verify_check_in_code};
+use camp_registration_lambda::database::schema::attendance_events;
+use common::{seed_confirmed_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const SIGNING_KEY: &str = "integration-test-check-in-key";
+
+async fn scan(app: &TestApp, code: &str) -> reqwest::Response {
+    app.admin(Method::POST, "/check_in/scan")
+        .json(&json!({ "code": code }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn scanning_a_code_checks_the_camper_in_once() {
+    std::env::set_var("CHECK_IN_SIGNING_KEY", SIGNING_KEY);
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+    let code = check_in_code(seed.registration_id).unwrap();
+
+    let response = scan(&app, &code).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["registration_id"], seed.registration_id.to_string());
+    assert_eq!(body["camper"]["first_name"], "Sam");
+    assert_eq!(body["guardian_name"], "Test Guardian");
+    assert_eq!(body["event"]["kind"], "check_in");
+    assert_eq!(body["event"]["source"], "qr");
+
+    // The camper is already in
+    assert_eq!(scan(&app, &code).await.status(), 409);
+    let events: i64 = attendance_events::table
+        .filter(attendance_events::registration_id.eq(seed.registration_id))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap();
+    assert_eq!(events, 1);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn forged_and_revoked_codes_are_refused() {
+    std::env::set_var("CHECK_IN_SIGNING_KEY", SIGNING_KEY);
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+    let code = check_in_code(seed.registration_id).unwrap();
+
+    // Another registration's id under this code's signature
+    let (_, signature) = code.rsplit_once('.').unwrap();
+    let forged = format!("ci1.{}.{signature}", Uuid::new_v4().simple());
+    assert_eq!(scan(&app, &forged).await.status(), 400);
+
+    // Cancelling the registration revokes its code
+    app.conn()
+        .batch_execute(&format!(
+            "UPDATE registrations SET status = 'cancelled' WHERE id = '{}';",
+            seed.registration_id
+        ))
+        .unwrap();
+    assert_eq!(scan(&app, &code).await.status(), 409);
+}
+
+#[test]
+fn codes_name_their_registration() {
+    std::env::set_var("CHECK_IN_SIGNING_KEY", SIGNING_KEY);
+    let registration = Uuid::new_v4();
+    let code = check_in_code(registration).unwrap();
+    assert_eq!(verify_check_in_code(&code), Ok(Some(registration)));
+    assert_eq!(verify_check_in_code("ci1.not-a-code"), Ok(None));
+}
@@ -0,0 +1,168 @@
+//! Contract tests for the published response fixtures.
+//!
+//! `fixtures/contract.json` is the snapshot client teams build against. A change to a
+//! response type changes the generated fixtures and fails `fixtures_match_snapshot`
+//! until the snapshot is regenerated with `UPDATE_FIXTURES=1 cargo test --test contract`
+//! and committed alongside the change.
+mod common;
+
+use camp_registration_lambda::fixtures;
+use common::TestApp;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+fn snapshot_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/contract.json")
+}
+
+fn generated() -> String {
+    let mut rendered = serde_json::to_string_pretty(&fixtures::all()).unwrap();
+    rendered.push('\n');
+    rendered
+}
+
+#[test]
+fn every_route_has_a_success_fixture() {
+    let missing = fixtures::routes_missing_fixtures();
+    assert!(missing.is_empty(), "routes without fixtures: {missing:?}");
+}
+
+#[test]
+fn fixtures_match_snapshot() {
+    let path = snapshot_path();
+    let rendered = generated();
+    if std::env::var("UPDATE_FIXTURES").is_ok_and(|v| v == "1") {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &rendered).unwrap();
+        return;
+    }
+
+    let snapshot = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "cannot read {}: {e}; generate it with UPDATE_FIXTURES=1 and commit it",
+            path.display()
+        )
+    });
+    assert!(
+        snapshot == rendered,
+        "response fixtures changed; review the diff and rerun with UPDATE_FIXTURES=1 to accept it"
+    );
+}
+
+#[test]
+fn error_fixtures_use_declared_statuses() {
+    let all = fixtures::all();
+    // Named variants of a success response (e.g. a quote with a processing fee) share its status
+    let success_variant = |fixture: &fixtures::Fixture| {
+        all.iter().any(|other| {
+            other.name == "success"
+                && other.method == fixture.method
+                && other.path == fixture.path
+                && other.status == fixture.status
+        })
+    };
+    for fixture in &all {
+        if fixture.name == "success" {
+            continue;
+        }
+        assert!(
+            fixture.status >= 400 || fixture.path == "/payment_status" || success_variant(fixture),
+            "{} {} fixture {} has status {}",
+            fixture.method,
+            fixture.path,
+            fixture.name,
+            fixture.status
+        );
+    }
+}
+
+/// Object keys at every level, with array elements collapsed into `[]`.
+fn shape(value: &Value, prefix: &str, keys: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let path = format!("{prefix}.{key}");
+                keys.insert(path.clone());
+                shape(child, &path, keys);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                shape(item, &format!("{prefix}[]"), keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn assert_same_shape(method: &str, path: &str, live: &Value) {
+    let fixture = fixtures::all()
+        .into_iter()
+        .find(|f| f.method == method && f.path == path && f.name == "success")
+        .unwrap_or_else(|| panic!("no success fixture for {method} {path}"));
+    let (mut expected, mut actual) = (BTreeSet::new(), BTreeSet::new());
+    shape(&fixture.body, "", &mut expected);
+    shape(live, "", &mut actual);
+    assert_eq!(expected, actual, "{method} {path} drifted from its fixture");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn live_responses_match_fixture_shapes() {
+    std::env::set_var("APP_ENV", "dev");
+    let app = TestApp::spawn().await;
+
+    let created: Value = app
+        .admin(Method::POST, "/admin/sessions")
+        .json(&json!({
+            "name": "Contract Week",
+            "starts_on": "2026-07-06",
+            "ends_on": "2026-07-11",
+            "capacity": 10,
+            "price": 45000,
+            "currency": "usd",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_same_shape("POST", "/admin/sessions", &created);
+
+    let session_id = created["id"].as_str().unwrap();
+    for (path, url) in [
+        ("/sessions", format!("{}/sessions", app.base_url)),
+        (
+            "/sessions/{id}",
+            format!("{}/sessions/{session_id}", app.base_url),
+        ),
+    ] {
+        let live: Value = app
+            .http
+            .get(url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_same_shape("GET", path, &live);
+    }
+
+    let served: Value = app
+        .http
+        .get(format!("{}/dev/fixtures", app.base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        served["fixtures"],
+        serde_json::to_value(fixtures::all()).unwrap()
+    );
+}
@@ -0,0 +1,137 @@
+//! Tests for database degraded mode, including a real outage of the test harness's
+//! Postgres.
+mod common;
+
+use axum::http::StatusCode;
+use camp_registration_lambda::database::models::WebSocketConnection;
+use camp_registration_lambda::database::schema::{payment_events, websocket_connections};
+use camp_registration_lambda::db_health::{
+    DatabaseHealth, DatabaseStatus, MAX_BUFFERED_REGISTRATIONS,
+};
+use camp_registration_lambda::stripe_webhook::WebhookError;
+use common::{payment_intent_event, TestApp};
+use diesel::prelude::*;
+use serde_json::{json, Value};
+
+fn subscribe(health: &DatabaseHealth, connection_id: &str, intent: &str) {
+    health.buffer_registration(WebSocketConnection::new(
+        intent.to_string(),
+        connection_id.to_string(),
+        None,
+        None,
+    ));
+}
+
+#[test]
+fn failures_degrade_until_a_call_succeeds() {
+    let health = DatabaseHealth::default();
+    let report = health.report();
+    assert!(report.ready);
+    assert_eq!(report.database, DatabaseStatus::Ok);
+
+    health.failed("webhook", "connection refused");
+    let report = health.report();
+    assert!(!report.ready);
+    assert_eq!(report.database, DatabaseStatus::Degraded);
+    assert_eq!(report.last_failure, Some("webhook"));
+    let since = report.degraded_since.unwrap();
+
+    // Later failures keep the original start of the outage
+    health.failed("websocket", "connection refused");
+    assert_eq!(health.report().degraded_since, Some(since));
+    assert_eq!(health.report().last_failure, Some("websocket"));
+
+    health.recovered();
+    assert!(!health.is_degraded());
+    assert!(health.report().ready);
+}
+
+#[test]
+fn buffered_subscriptions_are_capped_and_discarded_on_close() {
+    let health = DatabaseHealth::default();
+    subscribe(&health, "conn-1", "pi_1");
+    subscribe(&health, "conn-1", "pi_2");
+    subscribe(&health, "conn-2", "pi_3");
+    assert_eq!(health.buffered_registrations(), 3);
+
+    health.discard_registrations("conn-1");
+    assert_eq!(health.buffered_registrations(), 1);
+
+    for n in 0..MAX_BUFFERED_REGISTRATIONS {
+        subscribe(&health, "conn-3", &format!("pi_{n}"));
+    }
+    let report = health.report();
+    assert_eq!(report.buffered_ws_registrations, MAX_BUFFERED_REGISTRATIONS);
+    assert_eq!(report.dropped_ws_registrations, 1);
+}
+
+#[test]
+fn database_outages_ask_stripe_to_retry() {
+    let error = WebhookError::DatabaseUnavailable;
+    assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(error.code(), "database_unavailable");
+    assert_eq!(
+        WebhookError::InvalidSignature.status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+async fn readiness(app: &TestApp) -> (u16, Value) {
+    let response = app
+        .http
+        .get(format!("{}/ready", app.base_url))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn outage_is_retried_buffered_and_recovered() {
+    let app = TestApp::spawn().await;
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_during_outage",
+        45_000,
+        "usd",
+        json!({}),
+    );
+    app.take_database_offline();
+
+    // Stripe is asked to redeliver rather than the event being dropped
+    let response = app.post_webhook(&payload).await;
+    assert_eq!(response.status(), 500);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "database_unavailable");
+
+    // Subscriptions are still confirmed, and held until the database is back
+    let _client = app.subscribe("pi_during_outage").await;
+    let (status, report) = readiness(&app).await;
+    assert_eq!(status, 503);
+    assert_eq!(report["database"], "degraded");
+    assert_eq!(report["buffered_ws_registrations"], 1);
+
+    app.restore_database();
+    let (status, report) = readiness(&app).await;
+    assert_eq!(status, 200);
+    assert_eq!(report["database"], "ok");
+    assert_eq!(report["buffered_ws_registrations"], 0);
+    let subscribed: i64 = websocket_connections::table
+        .filter(websocket_connections::payment_intent_id.eq("pi_during_outage"))
+        .filter(websocket_connections::status.eq("active"))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap();
+    assert_eq!(subscribed, 1);
+
+    // Stripe's redelivery is recorded
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+    let recorded: i64 = payment_events::table
+        .filter(payment_events::payment_intent_id.eq("pi_during_outage"))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap();
+    assert_eq!(recorded, 1);
+}
@@ -0,0 +1,207 @@
+//! Tests for delegated registration links against Postgres and stripe-mock: the
+//! summary shows what the PaymentSheet charges, links expire, are revoked and stop
+//! working once paid, and the token unlocks nothing beyond its registration.
+mod common;
+
+use common::{payment_intent_event, seed_pending_registration, PendingRegistration, TestApp};
+use diesel::connection::SimpleConnection;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// Creates a link for the seeded registration, returning its token and id.
+async fn create_link(app: &TestApp, seed: &PendingRegistration) -> (String, String) {
+    let response = app
+        .guardian(
+            seed.guardian_id,
+            Method::POST,
+            &format!("/registrations/{}/delegations", seed.registration_id),
+        )
+        .json(&json!({ "recipient_name": "Grandma Jo" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let created: Value = response.json().await.unwrap();
+    (
+        created["token"].as_str().unwrap().to_string(),
+        created["link"]["id"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn summary(app: &TestApp, token: &str) -> reqwest::Response {
+    app.http
+        .get(format!("{}/delegated/{token}", app.base_url))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn payment_sheet(app: &TestApp, token: &str) -> reqwest::Response {
+    app.http
+        .post(format!("{}/delegated/{token}/payment_sheet", app.base_url))
+        .json(&json!({ "payer_name": "Jo Lindqvist", "payer_email": "jo@example.com" }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn error_code(response: reqwest::Response) -> Value {
+    let body: Value = response.json().await.unwrap();
+    body["error"].clone()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn summary_shows_the_amount_the_payment_sheet_charges() {
+    std::env::set_var("PROCESSING_FEE_PERCENT", "2.9");
+    std::env::set_var("PROCESSING_FEE_FIXED", "usd:30");
+    let app = TestApp::spawn().await;
+    std::env::remove_var("PROCESSING_FEE_PERCENT");
+    std::env::remove_var("PROCESSING_FEE_FIXED");
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let (token, _) = create_link(&app, &seed).await;
+
+    let response = summary(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let shown: Value = response.json().await.unwrap();
+    assert_eq!(shown["amount"], 46_375);
+
+    let response = payment_sheet(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let sheet: Value = response.json().await.unwrap();
+    assert_eq!(sheet["amount"], shown["amount"]);
+    assert_eq!(sheet["currency"], shown["currency"]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn expired_and_revoked_links_are_gone() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+
+    let (expired, expired_id) = create_link(&app, &seed).await;
+    app.conn()
+        .batch_execute(&format!(
+            "UPDATE delegated_links SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = '{expired_id}';"
+        ))
+        .unwrap();
+    let response = summary(&app, &expired).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert_eq!(error_code(response).await, "LINK_EXPIRED");
+    assert_eq!(
+        payment_sheet(&app, &expired).await.status(),
+        StatusCode::GONE
+    );
+
+    let (revoked, revoked_id) = create_link(&app, &seed).await;
+    assert_eq!(summary(&app, &revoked).await.status(), StatusCode::OK);
+    let response = app
+        .guardian(
+            seed.guardian_id,
+            Method::DELETE,
+            &format!(
+                "/registrations/{}/delegations/{revoked_id}",
+                seed.registration_id
+            ),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = summary(&app, &revoked).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert_eq!(error_code(response).await, "LINK_EXPIRED");
+
+    let response = summary(&app, "not-a-link").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn links_stop_working_once_the_registration_is_paid() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let (token, _) = create_link(&app, &seed).await;
+
+    let response = payment_sheet(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let sheet: Value = response.json().await.unwrap();
+    let succeeded = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_delegated",
+        sheet["amount"].as_i64().unwrap(),
+        "usd",
+        json!({
+            "quote_id": sheet["quote_id"],
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&succeeded).await.status(), 200);
+
+    let response = summary(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(error_code(response).await, "REGISTRATION_NOT_PAYABLE");
+    let response = payment_sheet(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(error_code(response).await, "REGISTRATION_NOT_PAYABLE");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn link_tokens_unlock_only_their_registration() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let (token, _) = create_link(&app, &seed).await;
+
+    // The summary names the camper and session and nothing else about the family
+    let shown: Value = summary(&app, &token).await.json().await.unwrap();
+    let fields: BTreeSet<&str> = shown
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        fields,
+        BTreeSet::from([
+            "amount",
+            "camper_first_name",
+            "currency",
+            "link_expires_at",
+            "recipient_name",
+            "registration_id",
+            "session",
+        ])
+    );
+    assert_eq!(shown["camper_first_name"], "Sam");
+
+    // The token is not an API token
+    for path in [
+        format!("/registrations/{}", seed.registration_id),
+        format!("/registrations/{}/delegations", seed.registration_id),
+        "/me/billing_address".to_string(),
+    ] {
+        let response = app
+            .http
+            .get(format!("{}{path}", app.base_url))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{path}");
+    }
+
+    // Another family can neither share nor list links for the registration
+    let other = seed_pending_registration(&mut app.conn(), 30_000);
+    let path = format!("/registrations/{}/delegations", seed.registration_id);
+    for method in [Method::POST, Method::GET] {
+        let response = app
+            .guardian(other.guardian_id, method.clone(), &path)
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{method} {path}");
+    }
+}
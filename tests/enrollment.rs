@@ -0,0 +1,180 @@
+//! Tests for minimum enrollment checks, and for flagging and cancelling
+//! under-enrolled sessions against Postgres.
+mod common;
+
+use camp_registration_lambda::database::models::CampSession;
+use camp_registration_lambda::database::schema::{
+    admin_alerts, camp_sessions, registrations, session_cancellations,
+};
+use camp_registration_lambda::enrollment::{
+    enrollment_risk, validate_minimum_enrollment, UNDER_ENROLLED,
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use common::{seed_confirmed_registration, TestApp};
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+fn date(month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+}
+
+fn session() -> CampSession {
+    CampSession {
+        id: Uuid::from_u128(1),
+        name: "Lakeside Week 1".to_string(),
+        starts_on: date(7, 6),
+        ends_on: date(7, 11),
+        capacity: 40,
+        price: 45_000,
+        currency: "usd".to_string(),
+        created_at: date(1, 15).and_hms_opt(9, 0, 0).unwrap(),
+        cancelled_at: None,
+        session_type: "lakeside".to_string(),
+        min_age: None,
+        max_age: None,
+        min_enrollment: Some(12),
+        enrollment_cutoff: Some(date(6, 8)),
+        enrollment_flagged_at: None,
+    }
+}
+
+#[test]
+fn sessions_are_flagged_within_the_warning_window() {
+    assert_eq!(enrollment_risk(&session(), 9, date(5, 24), 14), None);
+
+    let risk = enrollment_risk(&session(), 9, date(5, 25), 14).unwrap();
+    assert_eq!(risk.shortfall, 3);
+    assert!(!risk.cutoff_passed);
+
+    let risk = enrollment_risk(&session(), 9, date(6, 9), 14).unwrap();
+    assert!(risk.cutoff_passed);
+}
+
+#[test]
+fn full_enough_started_or_cancelled_sessions_are_not_at_risk() {
+    assert_eq!(enrollment_risk(&session(), 12, date(6, 1), 14), None);
+    assert_eq!(enrollment_risk(&session(), 0, date(7, 6), 14), None);
+
+    let mut cancelled = session();
+    cancelled.cancelled_at = Some(date(6, 1).and_hms_opt(9, 0, 0).unwrap());
+    assert_eq!(enrollment_risk(&cancelled, 0, date(6, 1), 14), None);
+
+    let mut no_minimum = session();
+    no_minimum.min_enrollment = None;
+    no_minimum.enrollment_cutoff = None;
+    assert_eq!(enrollment_risk(&no_minimum, 0, date(6, 1), 14), None);
+}
+
+#[test]
+fn minimum_enrollment_settings_are_validated() {
+    let starts_on = date(7, 6);
+    assert!(validate_minimum_enrollment(None, None, 40, starts_on).is_ok());
+    assert!(validate_minimum_enrollment(Some(12), Some(date(6, 8)), 40, starts_on).is_ok());
+    assert!(validate_minimum_enrollment(Some(12), None, 40, starts_on).is_err());
+    assert!(validate_minimum_enrollment(Some(41), Some(date(6, 8)), 40, starts_on).is_err());
+    assert!(validate_minimum_enrollment(Some(12), Some(date(7, 7)), 40, starts_on).is_err());
+}
+
+async fn run_enrollment_job(app: &TestApp) -> Value {
+    let response = app
+        .admin(Method::POST, "/admin/jobs/enrollment")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["summary"].clone()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn under_enrolled_sessions_are_flagged_then_cancelled_on_confirmation() {
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+    let today = chrono::Utc::now().date_naive();
+    diesel::update(camp_sessions::table.find(seed.session_id))
+        .set((
+            camp_sessions::starts_on.eq(today + Duration::days(30)),
+            camp_sessions::ends_on.eq(today + Duration::days(35)),
+        ))
+        .execute(&mut app.conn())
+        .unwrap();
+    let minimum = |min_enrollment: i32| {
+        app.admin(
+            Method::PUT,
+            &format!("/admin/sessions/{}/minimum_enrollment", seed.session_id),
+        )
+        .json(&json!({
+            "min_enrollment": min_enrollment,
+            "enrollment_cutoff": today + Duration::days(7),
+        }))
+        .send()
+    };
+    // More than the session holds
+    assert_eq!(minimum(11).await.unwrap().status(), 400);
+    assert_eq!(minimum(3).await.unwrap().status(), 200);
+
+    // One alert however often the job runs
+    let summary = run_enrollment_job(&app).await;
+    assert_eq!(summary, json!({ "at_risk": 1, "flagged": 1 }));
+    let summary = run_enrollment_job(&app).await;
+    assert_eq!(summary, json!({ "at_risk": 1, "flagged": 0 }));
+    let alerts: i64 = admin_alerts::table
+        .filter(admin_alerts::kind.eq(UNDER_ENROLLED))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap();
+    assert_eq!(alerts, 1);
+
+    let at_risk: Value = app
+        .admin(Method::GET, "/admin/sessions/at_risk")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let session = &at_risk["sessions"][0];
+    assert_eq!(session["session_id"], seed.session_id.to_string());
+    assert_eq!(session["enrolled"], 1);
+    assert_eq!(session["shortfall"], 2);
+    assert_eq!(session["cutoff_passed"], false);
+
+    // Nothing is cancelled before the cutoff
+    let cancel = || {
+        app.admin(
+            Method::POST,
+            &format!("/admin/sessions/{}/cancel_under_enrolled", seed.session_id),
+        )
+        .send()
+    };
+    assert_eq!(cancel().await.unwrap().status(), 409);
+
+    diesel::update(camp_sessions::table.find(seed.session_id))
+        .set(camp_sessions::enrollment_cutoff.eq(Some(today - Duration::days(1))))
+        .execute(&mut app.conn())
+        .unwrap();
+    assert_eq!(cancel().await.unwrap().status(), 200);
+
+    let mut conn = app.conn();
+    let cancelled_at: Option<NaiveDateTime> = camp_sessions::table
+        .find(seed.session_id)
+        .select(camp_sessions::cancelled_at)
+        .first(&mut conn)
+        .unwrap();
+    assert!(cancelled_at.is_some());
+    let status: String = registrations::table
+        .find(seed.registration_id)
+        .select(registrations::status)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(status, "cancelled");
+    let reason: String = session_cancellations::table
+        .filter(session_cancellations::session_id.eq(seed.session_id))
+        .select(session_cancellations::reason)
+        .first(&mut conn)
+        .unwrap();
+    assert!(reason.starts_with("Minimum enrollment of 3 was not reached"));
+}
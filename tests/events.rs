@@ -0,0 +1,193 @@
+//! Tests for the domain event log, and for the activity feeds it serves against
+//! Postgres.
+mod common;
+
+use camp_registration_lambda::database::models::Registration;
+use camp_registration_lambda::events::{
+    feed_limit, DomainEvent, EventRecorder, REGISTRATION_CONFIRMED,
+};
+use chrono::NaiveDate;
+use common::{payment_intent_event, seed_pending_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+fn registration() -> Registration {
+    let at = NaiveDate::from_ymd_opt(2026, 3, 1)
+        .unwrap()
+        .and_hms_opt(11, 0, 0)
+        .unwrap();
+    Registration {
+        id: Uuid::from_u128(4),
+        guardian_id: Uuid::from_u128(1),
+        camper_id: Uuid::from_u128(2),
+        session_id: Uuid::from_u128(3),
+        status: "confirmed".to_string(),
+        created_at: at,
+        updated_at: at,
+    }
+}
+
+#[test]
+fn registration_events_reach_every_feed() {
+    let occurred_at = registration().updated_at;
+    let event = DomainEvent::new(REGISTRATION_CONFIRMED, "Registration paid and confirmed")
+        .registration(&registration())
+        .payment_intent("pi_123")
+        .details(json!({ "amount": 45_000 }));
+    let row = EventRecorder::system().to_row(event, occurred_at);
+
+    assert_eq!(row.kind, "registration.confirmed");
+    assert_eq!(row.actor_role, "system");
+    assert_eq!(row.actor_id, None);
+    assert_eq!(row.camper_id, Some(Uuid::from_u128(2)));
+    assert_eq!(row.guardian_id, Some(Uuid::from_u128(1)));
+    assert_eq!(row.session_id, Some(Uuid::from_u128(3)));
+    assert_eq!(row.registration_id, Some(Uuid::from_u128(4)));
+    assert_eq!(row.payment_intent_id.as_deref(), Some("pi_123"));
+    assert_eq!(row.details["amount"], 45_000);
+    assert_eq!(row.occurred_at, occurred_at);
+}
+
+#[test]
+fn session_events_only_reach_the_session_feed() {
+    let event = DomainEvent::new("session.cancelled", "Lakeside Week 1 cancelled")
+        .session(Uuid::from_u128(3));
+    assert_eq!(event.session_id, Some(Uuid::from_u128(3)));
+    assert_eq!(event.camper_id, None);
+    assert_eq!(event.guardian_id, None);
+    assert_eq!(event.details, json!({}));
+}
+
+#[test]
+fn feed_pages_are_bounded() {
+    assert_eq!(feed_limit(None), 50);
+    assert_eq!(feed_limit(Some(0)), 1);
+    assert_eq!(feed_limit(Some(20)), 20);
+    assert_eq!(feed_limit(Some(10_000)), 200);
+}
+
+/// The kinds of the events in a feed page, newest first.
+fn kinds(feed: &Value) -> Vec<&str> {
+    feed["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["kind"].as_str().unwrap())
+        .collect()
+}
+
+async fn feed(request: reqwest::RequestBuilder) -> Value {
+    let response = request.send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn registrations_and_payments_appear_in_each_feed() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let week_two = Uuid::new_v4();
+    app.conn()
+        .batch_execute(&format!(
+            "INSERT INTO camp_sessions (id, name, starts_on, ends_on, capacity, price, currency)
+             VALUES ('{week_two}', 'Week 2', '2027-07-12', '2027-07-16', 10, 45000, 'usd');"
+        ))
+        .unwrap();
+
+    // The family signs up for a second week, then pays for the first
+    let created = app
+        .guardian(seed.guardian_id, Method::POST, "/registrations")
+        .json(&json!({ "camper_id": seed.camper_id, "session_id": week_two }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 200);
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_week_one",
+        seed.price,
+        "usd",
+        json!({
+            "quote_id": seed.quote_id.to_string(),
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    let camper = feed(app.guardian(
+        seed.guardian_id,
+        Method::GET,
+        &format!("/campers/{}/activity", seed.camper_id),
+    ))
+    .await;
+    assert_eq!(
+        kinds(&camper),
+        ["registration.confirmed", "registration.created"]
+    );
+    assert_eq!(camper["events"][0]["actor_role"], "system");
+    assert_eq!(camper["events"][0]["payment_intent_id"], "pi_week_one");
+    assert_eq!(camper["events"][1]["actor_role"], "guardian");
+    assert_eq!(
+        camper["events"][1]["actor_id"],
+        seed.guardian_id.to_string()
+    );
+    let guardian = feed(app.guardian(
+        seed.guardian_id,
+        Method::GET,
+        &format!("/guardians/{}/activity", seed.guardian_id),
+    ))
+    .await;
+    assert_eq!(kinds(&guardian), kinds(&camper));
+
+    // Pages follow `next_before`
+    let first_page = feed(app.admin(
+        Method::GET,
+        &format!("/guardians/{}/activity?limit=1", seed.guardian_id),
+    ))
+    .await;
+    assert_eq!(kinds(&first_page), ["registration.confirmed"]);
+    let before = first_page["next_before"].as_i64().unwrap();
+    let second_page = feed(app.admin(
+        Method::GET,
+        &format!(
+            "/guardians/{}/activity?limit=1&before={before}",
+            seed.guardian_id
+        ),
+    ))
+    .await;
+    assert_eq!(kinds(&second_page), ["registration.created"]);
+    assert!(second_page["next_before"].is_null());
+
+    // Each session only sees its own registrations
+    let session = feed(app.admin(
+        Method::GET,
+        &format!("/admin/sessions/{}/activity", seed.session_id),
+    ))
+    .await;
+    assert_eq!(kinds(&session), ["registration.confirmed"]);
+
+    // Another family cannot read the feed
+    let stranger = app
+        .guardian(
+            Uuid::new_v4(),
+            Method::GET,
+            &format!("/campers/{}/activity", seed.camper_id),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stranger.status(), 404);
+
+    // The log is append-only
+    assert!(app
+        .conn()
+        .batch_execute("UPDATE domain_events SET summary = 'rewritten';")
+        .is_err());
+    assert!(app
+        .conn()
+        .batch_execute("DELETE FROM domain_events;")
+        .is_err());
+}
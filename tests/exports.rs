@@ -0,0 +1,62 @@
+//! Export job processing against Postgres: jobs abandoned in `running` by a run that
+//! died are failed instead of being polled forever.
+mod common;
+
+use camp_registration_lambda::database::models::{ExportJob, NewExportJob};
+use camp_registration_lambda::database::schema::export_jobs;
+use common::TestApp;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// A payments export claimed `minutes_ago` and never finished.
+fn running_job(app: &TestApp, minutes_ago: i64) -> Uuid {
+    let id = Uuid::new_v4();
+    let mut conn = app.conn();
+    diesel::insert_into(export_jobs::table)
+        .values(NewExportJob {
+            id,
+            kind: "payments".to_string(),
+            params: json!({ "kind": "payments", "from": "2026-06-01", "to": "2026-06-30" }),
+            requested_by: None,
+        })
+        .execute(&mut conn)
+        .unwrap();
+    let started = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(minutes_ago);
+    diesel::update(export_jobs::table.find(id))
+        .set((
+            export_jobs::status.eq("running"),
+            export_jobs::started_at.eq(Some(started)),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    id
+}
+
+fn job(app: &TestApp, id: Uuid) -> ExportJob {
+    export_jobs::table.find(id).first(&mut app.conn()).unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn stale_running_jobs_are_failed_and_fresh_ones_left_alone() {
+    let app = TestApp::spawn().await;
+    let stale = running_job(&app, 45);
+    let fresh = running_job(&app, 5);
+
+    let response = app
+        .admin(Method::POST, "/admin/jobs/exports")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["summary"]["failed"], 1);
+
+    let stale = job(&app, stale);
+    assert_eq!(stale.status, "failed");
+    assert!(stale.error.is_some());
+    assert!(stale.completed_at.is_some());
+    assert_eq!(job(&app, fresh).status, "running");
+}
@@ -0,0 +1,124 @@
+//! Offline kiosk sync against Postgres: late operations are checked against the
+//! events already recorded on either side of them.
+mod common;
+
+use camp_registration_lambda::database::schema::attendance_events;
+use common::{seed_confirmed_registration, TestApp};
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+struct Camper {
+    registration_id: Uuid,
+    pickup_id: Uuid,
+}
+
+/// A confirmed registration with one authorized pickup.
+async fn camper(app: &TestApp) -> Camper {
+    let registration_id = seed_confirmed_registration(&mut app.conn(), 45_000).registration_id;
+    let pickup: Value = app
+        .admin(
+            Method::POST,
+            &format!("/registrations/{registration_id}/pickups"),
+        )
+        .json(&json!({ "name": "Alex Camper", "relationship": "Parent" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    Camper {
+        registration_id,
+        pickup_id: pickup["id"].as_str().unwrap().parse().unwrap(),
+    }
+}
+
+/// Syncs `(type, timestamp)` operations for the camper and returns their statuses.
+async fn sync(app: &TestApp, camper: &Camper, operations: &[(&str, &str)]) -> Vec<String> {
+    let operations: Vec<Value> = operations
+        .iter()
+        .map(|(kind, timestamp)| {
+            json!({
+                "operation_id": Uuid::new_v4(),
+                "type": kind,
+                "registration_id": camper.registration_id,
+                "timestamp": timestamp,
+                "pickup_id": (*kind == "check_out").then_some(camper.pickup_id),
+            })
+        })
+        .collect();
+    let response = app
+        .admin(Method::POST, "/sync")
+        .json(&json!({ "kiosk_id": "kiosk-1", "operations": operations }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["status"].as_str().unwrap().to_string())
+        .collect()
+}
+
+fn recorded_kinds(app: &TestApp, camper: &Camper) -> Vec<String> {
+    attendance_events::table
+        .filter(attendance_events::registration_id.eq(camper.registration_id))
+        .order(attendance_events::occurred_at.asc())
+        .select(attendance_events::kind)
+        .load(&mut app.conn())
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn earlier_check_in_syncing_after_a_later_one_conflicts() {
+    let app = TestApp::spawn().await;
+    let camper = camper(&app).await;
+
+    let first = sync(&app, &camper, &[("check_in", "2026-07-06T09:30:00Z")]).await;
+    assert_eq!(first, vec!["applied"]);
+
+    // A second kiosk was offline and saw the camper arrive earlier
+    let late = sync(&app, &camper, &[("check_in", "2026-07-06T09:00:00Z")]).await;
+    assert_eq!(late, vec!["conflict"]);
+    assert_eq!(recorded_kinds(&app, &camper), vec!["check_in"]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn check_out_before_a_later_check_out_conflicts() {
+    let app = TestApp::spawn().await;
+    let camper = camper(&app).await;
+
+    let day = sync(
+        &app,
+        &camper,
+        &[
+            ("check_in", "2026-07-06T09:00:00Z"),
+            ("check_out", "2026-07-06T16:00:00Z"),
+        ],
+    )
+    .await;
+    assert_eq!(day, vec!["applied", "applied"]);
+
+    let late = sync(&app, &camper, &[("check_out", "2026-07-06T12:00:00Z")]).await;
+    assert_eq!(late, vec!["conflict"]);
+    assert_eq!(recorded_kinds(&app, &camper), vec!["check_in", "check_out"]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn late_check_out_after_the_last_event_is_applied() {
+    let app = TestApp::spawn().await;
+    let camper = camper(&app).await;
+
+    sync(&app, &camper, &[("check_in", "2026-07-06T09:00:00Z")]).await;
+    let late = sync(&app, &camper, &[("check_out", "2026-07-06T12:00:00Z")]).await;
+    assert_eq!(late, vec!["applied"]);
+    assert_eq!(recorded_kinds(&app, &camper), vec!["check_in", "check_out"]);
+}
@@ -0,0 +1,184 @@
+//! Tests for the medical-data access trail against Postgres: break-glass reads need a
+//! reason, every read is logged with its reader, and the season's compliance report
+//! lists them.
+mod common;
+
+use camp_registration_lambda::database::models::MedicalAccess;
+use camp_registration_lambda::database::schema::medical_access_log;
+use chrono::Datelike;
+use common::{seed_pending_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Reads a camper's medical record with a token for `role`.
+async fn read_as(app: &TestApp, role: &str, camper: Uuid, reason: Option<&str>) -> StatusCode {
+    let token = app.issue_token(role, None, &[]);
+    let mut request = app
+        .http
+        .get(format!("{}/campers/{camper}/medical", app.base_url))
+        .bearer_auth(token);
+    if let Some(reason) = reason {
+        request = request.query(&[("reason", reason)]);
+    }
+    request.send().await.unwrap().status()
+}
+
+fn accesses(app: &TestApp, camper: Uuid) -> Vec<MedicalAccess> {
+    medical_access_log::table
+        .filter(medical_access_log::camper_id.eq(camper))
+        .order(medical_access_log::accessed_at.asc())
+        .load::<MedicalAccess>(&mut app.conn())
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn non_nurse_reads_need_a_reason_and_log_it() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+
+    assert_eq!(
+        read_as(&app, "counselor", seed.camper_id, None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        read_as(&app, "director", seed.camper_id, Some("   ")).await,
+        StatusCode::FORBIDDEN
+    );
+    assert!(
+        accesses(&app, seed.camper_id).is_empty(),
+        "refused reads are not logged as accesses"
+    );
+
+    assert_eq!(
+        read_as(
+            &app,
+            "counselor",
+            seed.camper_id,
+            Some(" Bee sting on the trail ")
+        )
+        .await,
+        StatusCode::OK
+    );
+    let logged = accesses(&app, seed.camper_id);
+    assert_eq!(logged.len(), 1);
+    assert_eq!(logged[0].reader_role, "counselor");
+    assert_eq!(logged[0].reason.as_deref(), Some("Bee sting on the trail"));
+    assert!(logged[0].break_glass);
+    assert!(logged[0].reader_token_id.is_some());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn guardian_and_nurse_reads_are_logged() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+
+    assert_eq!(
+        read_as(&app, "nurse", seed.camper_id, None).await,
+        StatusCode::OK
+    );
+    let response = app
+        .guardian(
+            seed.guardian_id,
+            Method::GET,
+            &format!("/campers/{}/medical", seed.camper_id),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // Another family's guardian cannot read it, and nothing is logged for them
+    let other = seed_pending_registration(&mut app.conn(), 30_000);
+    let response = app
+        .guardian(
+            other.guardian_id,
+            Method::GET,
+            &format!("/campers/{}/medical", seed.camper_id),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let logged = accesses(&app, seed.camper_id);
+    let readers: Vec<(&str, Option<Uuid>, bool)> = logged
+        .iter()
+        .map(|a| (a.reader_role.as_str(), a.reader_subject_id, a.break_glass))
+        .collect();
+    assert_eq!(
+        readers,
+        vec![
+            ("nurse", None, false),
+            ("guardian", Some(seed.guardian_id), false),
+        ]
+    );
+    assert!(logged.iter().all(|a| a.reason.is_none()));
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn season_report_lists_break_glass_reads() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let season = chrono::Utc::now().year();
+
+    assert_eq!(
+        read_as(&app, "nurse", seed.camper_id, None).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        read_as(&app, "director", seed.camper_id, Some("Parent unreachable")).await,
+        StatusCode::OK
+    );
+    // A read from last season stays in last season's report
+    app.conn()
+        .batch_execute(&format!(
+            "INSERT INTO medical_access_log (camper_id, reader_role, reason, break_glass, accessed_at)
+             VALUES ('{}', 'admin', 'Insurance audit', TRUE, '{}-08-01 09:00:00');",
+            seed.camper_id,
+            season - 1
+        ))
+        .unwrap();
+
+    let report: Value = app
+        .admin(
+            Method::GET,
+            &format!("/admin/compliance/medical_access?season={season}"),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["season"], season);
+    assert_eq!(report["total_accesses"], 2);
+    assert_eq!(report["break_glass_accesses"], 1);
+    let break_glass: Vec<&Value> = report["accesses"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|a| a["break_glass"] == true)
+        .collect();
+    assert_eq!(break_glass.len(), 1);
+    assert_eq!(break_glass[0]["reader_role"], "director");
+    assert_eq!(break_glass[0]["reason"], "Parent unreachable");
+
+    let last_season: Value = app
+        .admin(
+            Method::GET,
+            &format!("/admin/compliance/medical_access?season={}", season - 1),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(last_season["total_accesses"], 1);
+    assert_eq!(last_season["accesses"][0]["reason"], "Insurance audit");
+}
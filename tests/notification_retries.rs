@@ -0,0 +1,177 @@
+//! Tests for notification retry backoff, and for the dispatcher's retries and
+//! dead-lettering against Postgres.
+mod common;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use camp_registration_lambda::database::schema::notification_outbox;
+use camp_registration_lambda::notifications::Channel;
+use common::TestApp;
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+#[test]
+fn backoff_doubles_up_to_the_cap() {
+    let policy = Channel::Email.retry_policy();
+    let delays: Vec<Duration> = (1..=8)
+        .map(|attempt| policy.backoff(attempt, 1.0))
+        .collect();
+
+    assert_eq!(delays[0], policy.base_delay);
+    assert_eq!(delays[1], policy.base_delay * 2);
+    assert_eq!(delays[2], policy.base_delay * 4);
+    assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_eq!(delays[7], policy.max_delay);
+}
+
+#[test]
+fn jitter_spreads_the_upper_half() {
+    let policy = Channel::Push.retry_policy();
+    let ceiling = policy.backoff(3, 1.0);
+
+    assert_eq!(policy.backoff(3, 0.0), ceiling / 2);
+    assert!(policy.backoff(3, 0.5) > ceiling / 2);
+    assert!(policy.backoff(3, 0.5) < ceiling);
+    // Out-of-range jitter is clamped
+    assert_eq!(policy.backoff(3, 7.0), ceiling);
+}
+
+#[test]
+fn attempts_stop_at_the_channel_limit() {
+    for channel in [
+        Channel::WebSocket,
+        Channel::Email,
+        Channel::Sms,
+        Channel::Push,
+    ] {
+        let policy = channel.retry_policy();
+        assert!(policy.retries_after(policy.max_attempts - 1));
+        assert!(!policy.retries_after(policy.max_attempts));
+        assert_eq!(Channel::parse(channel.as_str()), Some(channel));
+    }
+}
+
+/// Starts a relay that fails `flaky@` once, rejects `bounced@` and is always down
+/// for everyone else.
+async fn spawn_relay() -> String {
+    let flaky_calls = Arc::new(AtomicUsize::new(0));
+    let router = Router::new()
+        .route(
+            "/send",
+            post(
+                |State(flaky_calls): State<Arc<AtomicUsize>>, Json(message): Json<Value>| async move {
+                    match message["target"].as_str() {
+                        Some("flaky@example.com")
+                            if flaky_calls.fetch_add(1, Ordering::SeqCst) > 0 =>
+                        {
+                            StatusCode::ACCEPTED
+                        }
+                        Some("bounced@example.com") => StatusCode::UNPROCESSABLE_ENTITY,
+                        _ => StatusCode::SERVICE_UNAVAILABLE,
+                    }
+                },
+            ),
+        )
+        .with_state(flaky_calls);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    format!("http://{addr}/send")
+}
+
+/// Queues an email for `target`, as if `attempts` earlier sends had failed.
+fn queue_email(app: &TestApp, target: &str, intent: &str, attempts: i32) {
+    app.conn()
+        .batch_execute(&format!(
+            "INSERT INTO notification_outbox (id, channel, target, template, payload, payment_intent_id, attempts)
+             VALUES ('{}', 'email', '{target}', 'payment_succeeded', '{{}}', '{intent}', {attempts});",
+            uuid::Uuid::new_v4()
+        ))
+        .unwrap();
+}
+
+fn outbox_state(app: &TestApp, intent: &str) -> (String, i32) {
+    notification_outbox::table
+        .filter(notification_outbox::payment_intent_id.eq(intent))
+        .select((notification_outbox::status, notification_outbox::attempts))
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+async fn dispatch(app: &TestApp) -> Value {
+    let response = app
+        .admin(Method::POST, "/admin/jobs/notifications")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["summary"]["sent"].clone()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn failed_sends_are_retried_until_sent_or_dead_lettered() {
+    std::env::set_var("NOTIFICATION_RELAY_URL", spawn_relay().await);
+    let app = TestApp::spawn().await;
+    queue_email(&app, "flaky@example.com", "pi_flaky", 0);
+    queue_email(&app, "bounced@example.com", "pi_bounced", 0);
+    // The relay is down for this message's last allowed attempt
+    let max_attempts = Channel::Email.retry_policy().max_attempts;
+    queue_email(&app, "down@example.com", "pi_down", max_attempts - 1);
+
+    assert_eq!(dispatch(&app).await, 0);
+    assert_eq!(outbox_state(&app, "pi_flaky"), ("pending".to_string(), 1));
+    assert_eq!(
+        outbox_state(&app, "pi_bounced"),
+        ("dead_letter".to_string(), 1)
+    );
+    assert_eq!(
+        outbox_state(&app, "pi_down"),
+        ("dead_letter".to_string(), max_attempts)
+    );
+
+    // Nothing is retried before its backoff has passed
+    assert_eq!(dispatch(&app).await, 0);
+    diesel::update(
+        notification_outbox::table.filter(notification_outbox::payment_intent_id.eq("pi_flaky")),
+    )
+    .set(notification_outbox::next_attempt_at.eq(diesel::dsl::now))
+    .execute(&mut app.conn())
+    .unwrap();
+    assert_eq!(dispatch(&app).await, 1);
+
+    let trace: Value = app
+        .admin(Method::GET, "/admin/payments/pi_flaky/deliveries")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let attempts: Vec<(i64, &str)> = trace["deliveries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| {
+            (
+                d["attempt"].as_i64().unwrap(),
+                d["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(attempts, vec![(1, "failed"), (2, "sent")]);
+    let message = &trace["messages"][0];
+    assert_eq!(message["status"], "sent");
+    assert_eq!(message["attempts"], 2);
+    assert_eq!(message["max_attempts"], max_attempts);
+    assert!(message["next_attempt_at"].is_null());
+    assert_eq!(message["last_error"], Value::Null);
+}
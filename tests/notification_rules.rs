@@ -0,0 +1,175 @@
+//! Tests for date-driven notification rules, and for running them with guardian
+//! opt-outs against Postgres.
+mod common;
+
+use camp_registration_lambda::database::schema::notification_outbox;
+use camp_registration_lambda::notification_rules::{
+    age_on, birthday_in, find_rule, is_birthday, Trigger, RULES,
+};
+use chrono::{Months, NaiveDate, Utc};
+use common::{seed_confirmed_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn birthdays_match_the_day_and_month() {
+    let birthdate = date(2016, 7, 14);
+    assert!(is_birthday(birthdate, date(2026, 7, 14)));
+    assert!(!is_birthday(birthdate, date(2026, 7, 15)));
+    // The day of birth itself is not a birthday
+    assert!(!is_birthday(birthdate, birthdate));
+}
+
+#[test]
+fn leap_day_birthdays_fall_on_the_28th_in_common_years() {
+    let birthdate = date(2016, 2, 29);
+    assert_eq!(birthday_in(birthdate, 2026), date(2026, 2, 28));
+    assert_eq!(birthday_in(birthdate, 2028), date(2028, 2, 29));
+    assert!(is_birthday(birthdate, date(2026, 2, 28)));
+    assert!(!is_birthday(birthdate, date(2028, 2, 28)));
+}
+
+#[test]
+fn ages_count_whole_years() {
+    let birthdate = date(2016, 7, 14);
+    assert_eq!(age_on(birthdate, date(2026, 7, 13)), 9);
+    assert_eq!(age_on(birthdate, date(2026, 7, 14)), 10);
+    assert_eq!(age_on(date(2016, 2, 29), date(2026, 2, 28)), 10);
+}
+
+#[test]
+fn rules_have_unique_names_and_their_own_templates() {
+    for rule in RULES {
+        assert_eq!(find_rule(rule.name).unwrap().template, rule.template);
+    }
+    assert_eq!(
+        find_rule("session_countdown").unwrap().trigger,
+        Trigger::DaysBeforeSession(7)
+    );
+    assert!(find_rule("newsletter").is_none());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn rules_send_once_and_respect_opt_outs() {
+    let app = TestApp::spawn().await;
+    let today = Utc::now().date_naive();
+    let (opted_in, opted_out) = {
+        let mut conn = app.conn();
+        let opted_in = seed_confirmed_registration(&mut conn, 45_000);
+        let opted_out = seed_confirmed_registration(&mut conn, 45_000);
+        // Both campers turn ten today and both sessions start a week from now
+        let birthdate = today.checked_sub_months(Months::new(120)).unwrap();
+        let starts_on = today + chrono::Duration::days(7);
+        conn.batch_execute(&format!(
+            "UPDATE campers SET birthdate = '{birthdate}';
+             UPDATE camp_sessions SET starts_on = '{starts_on}', ends_on = '{}';",
+            starts_on + chrono::Duration::days(4)
+        ))
+        .unwrap();
+        (opted_in, opted_out)
+    };
+
+    let unknown = app
+        .guardian(
+            opted_out.guardian_id,
+            Method::PUT,
+            "/me/notification_preferences",
+        )
+        .json(&json!({ "opted_out": ["newsletter"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+
+    let preferences: Value = app
+        .guardian(
+            opted_out.guardian_id,
+            Method::PUT,
+            "/me/notification_preferences",
+        )
+        .json(&json!({ "opted_out": ["session_countdown"] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let enabled: Vec<(&str, bool)> = preferences["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| {
+            (
+                rule["rule"].as_str().unwrap(),
+                rule["enabled"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        enabled,
+        [("camper_birthday", true), ("session_countdown", false)]
+    );
+
+    let first = run_rules(&app).await;
+    assert_eq!(
+        first["sent"],
+        json!({ "camper_birthday": 2, "session_countdown": 1 })
+    );
+    assert_eq!(first["opted_out"], 1);
+    assert_eq!(first["already_sent"], 0);
+
+    let mut conn = app.conn();
+    let templates = |target: String, conn: &mut PgConnection| {
+        let mut templates: Vec<String> = notification_outbox::table
+            .filter(notification_outbox::target.eq(target))
+            .select(notification_outbox::template)
+            .load(conn)
+            .unwrap();
+        templates.sort();
+        templates
+    };
+    assert_eq!(
+        templates(format!("{}@example.com", opted_in.guardian_id), &mut conn),
+        ["camper_birthday", "session_countdown_one_week"]
+    );
+    assert_eq!(
+        templates(format!("{}@example.com", opted_out.guardian_id), &mut conn),
+        ["camper_birthday"]
+    );
+    let payload: Value = notification_outbox::table
+        .filter(notification_outbox::template.eq("camper_birthday"))
+        .select(notification_outbox::payload)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(payload["age"], 10);
+
+    // A second run the same day finds every occasion already covered
+    let second = run_rules(&app).await;
+    assert_eq!(second["sent"], json!({}));
+    assert_eq!(second["already_sent"], 3);
+    assert_eq!(second["opted_out"], 1);
+    let messages: i64 = notification_outbox::table
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(messages, 3);
+}
+
+async fn run_rules(app: &TestApp) -> Value {
+    let response: Value = app
+        .admin(Method::POST, "/admin/jobs/notification_rules")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    response["summary"]["rules"].clone()
+}
@@ -0,0 +1,165 @@
+//! Property-based tests for the WebSocket client message parser and webhook
+//! verification: arbitrary input must yield a structured error, never a panic.
+mod common;
+
+use camp_registration_lambda::stripe_webhook::{verify_event, WebhookError};
+use camp_registration_lambda::websocket_handler::{
+    parse_client_message, ClientMessage, ClientMessageError, MAX_CLIENT_MESSAGE_BYTES,
+};
+use common::{payment_intent_event, sign_webhook, WEBHOOK_SECRET};
+use proptest::prelude::*;
+use serde_json::{json, Value};
+
+const WS_ERROR_CODES: &[&str] = &[
+    "message_too_large",
+    "binary_not_supported",
+    "invalid_json",
+    "not_an_object",
+    "missing_type",
+    "unknown_type",
+    "missing_field",
+    "invalid_field",
+];
+
+const WEBHOOK_ERROR_CODES: &[&str] = &[
+    "missing_signature",
+    "malformed_signature_header",
+    "invalid_signature",
+    "stale_timestamp",
+    "invalid_encoding",
+    "malformed_payload",
+];
+
+fn arb_json() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<f64>().prop_map(|f| json!(f)),
+        ".{0,40}".prop_map(Value::from),
+        Just(Value::from("subscribe")),
+        "pi_[A-Za-z0-9]{1,24}".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::Array),
+            prop::collection::btree_map(
+                prop_oneof![
+                    Just("type".to_string()),
+                    Just("payment_intent_id".to_string()),
+                    Just("customer_id".to_string()),
+                    Just("customer_email".to_string()),
+                    "[a-z_]{1,12}",
+                ],
+                inner,
+                0..6,
+            )
+            .prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn ws_parser_never_panics_on_arbitrary_text(text in ".{0,512}") {
+        if let Err(e) = parse_client_message(&text) {
+            prop_assert!(WS_ERROR_CODES.contains(&e.code()));
+            let reply: Value = serde_json::from_str(&e.to_message()).unwrap();
+            prop_assert_eq!(&reply["type"], "error");
+            prop_assert_eq!(&reply["code"], e.code());
+        }
+    }
+
+    #[test]
+    fn ws_parser_classifies_arbitrary_json(value in arb_json()) {
+        match parse_client_message(&value.to_string()) {
+            Ok(ClientMessage::Subscribe { payment_intent_id, .. }) => {
+                prop_assert_eq!(&value["type"], "subscribe");
+                prop_assert!(payment_intent_id.starts_with("pi_"));
+            }
+            Err(e) => prop_assert!(WS_ERROR_CODES.contains(&e.code())),
+        }
+    }
+
+    #[test]
+    fn ws_parser_accepts_well_formed_subscriptions(
+        intent in "pi_[A-Za-z0-9]{1,40}",
+        customer in proptest::option::of("[a-z0-9]{1,32}"),
+    ) {
+        let message = json!({
+            "type": "subscribe",
+            "payment_intent_id": intent,
+            "customer_id": customer,
+        });
+        prop_assert_eq!(
+            parse_client_message(&message.to_string()),
+            Ok(ClientMessage::Subscribe {
+                payment_intent_id: intent,
+                customer_id: customer,
+                customer_email: None,
+            })
+        );
+    }
+
+    #[test]
+    fn webhook_rejects_arbitrary_bytes_and_headers(
+        payload in prop::collection::vec(any::<u8>(), 0..512),
+        header in proptest::option::of(".{0,128}"),
+    ) {
+        let e = verify_event(&payload, header.as_deref(), WEBHOOK_SECRET).unwrap_err();
+        prop_assert!(WEBHOOK_ERROR_CODES.contains(&e.code()));
+    }
+
+    #[test]
+    fn webhook_classifies_signed_arbitrary_json(value in arb_json()) {
+        let payload = value.to_string();
+        let header = sign_webhook(&payload, WEBHOOK_SECRET);
+        // Signed payloads only fail when they do not describe a Stripe event
+        if let Err(e) = verify_event(payload.as_bytes(), Some(&header), WEBHOOK_SECRET) {
+            prop_assert_eq!(e.code(), "malformed_payload");
+        }
+    }
+
+    #[test]
+    fn webhook_rejects_tampered_payloads(amount in 1i64..1_000_000, tamper in 1i64..1_000) {
+        let signed = payment_intent_event("payment_intent.succeeded", "pi_fuzz", amount, "usd", json!({}));
+        let header = sign_webhook(&signed, WEBHOOK_SECRET);
+        let tampered = payment_intent_event(
+            "payment_intent.succeeded",
+            "pi_fuzz",
+            amount + tamper,
+            "usd",
+            json!({}),
+        );
+        prop_assert_eq!(
+            verify_event(tampered.as_bytes(), Some(&header), WEBHOOK_SECRET).unwrap_err(),
+            WebhookError::InvalidSignature
+        );
+    }
+}
+
+#[test]
+fn ws_parser_rejects_oversized_messages() {
+    let text = format!(
+        r#"{{"type":"subscribe","payment_intent_id":"pi_{}"}}"#,
+        "a".repeat(MAX_CLIENT_MESSAGE_BYTES)
+    );
+    assert!(matches!(
+        parse_client_message(&text),
+        Err(ClientMessageError::TooLarge { .. })
+    ));
+}
+
+#[test]
+fn webhook_accepts_a_correctly_signed_event() {
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_fuzz",
+        1_000,
+        "usd",
+        json!({}),
+    );
+    let header = sign_webhook(&payload, WEBHOOK_SECRET);
+    let event = verify_event(payload.as_bytes(), Some(&header), WEBHOOK_SECRET).unwrap();
+    assert_eq!(event.type_.to_string(), "payment_intent.succeeded");
+}
@@ -0,0 +1,221 @@
+//! Tests for payment amount limits and velocity checks, and for their enforcement
+//! by the payment sheet against Postgres and stripe-mock.
+mod common;
+
+use camp_registration_lambda::database::models::PaymentLimitOverride;
+use camp_registration_lambda::payment_limits::{LimitViolation, PaymentLimits, RecentActivity};
+use chrono::NaiveDate;
+use common::TestApp;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+fn defaults() -> PaymentLimits {
+    PaymentLimits::from_settings(None, None, None).unwrap()
+}
+
+fn activity(intents_last_hour: i64, amount_last_day: i64) -> RecentActivity {
+    RecentActivity {
+        intents_last_hour,
+        amount_last_day,
+    }
+}
+
+fn limit_override(max_amount: i64) -> PaymentLimitOverride {
+    let at = NaiveDate::from_ymd_opt(2026, 6, 1)
+        .unwrap()
+        .and_hms_opt(10, 0, 0)
+        .unwrap();
+    PaymentLimitOverride {
+        id: Uuid::from_u128(1),
+        customer_email: "morgan@example.com".to_string(),
+        currency: "usd".to_string(),
+        max_amount,
+        reason: "Whole summer in one payment".to_string(),
+        created_by: None,
+        expires_at: at + chrono::Duration::hours(24),
+        created_at: at,
+    }
+}
+
+#[test]
+fn settings_are_parsed_with_defaults() {
+    let limits = defaults();
+    assert_eq!(limits.amounts["usd"].min, 50);
+    assert_eq!(limits.amounts["eur"].max, 1_000_000);
+    assert_eq!(limits.daily_totals["usd"], 2_500_000);
+    assert_eq!(limits.max_intents_per_hour, 10);
+
+    let limits =
+        PaymentLimits::from_settings(Some("USD: 100-500000"), Some("usd:900000"), Some("3"))
+            .unwrap();
+    assert_eq!(limits.amounts["usd"].max, 500_000);
+    assert!(!limits.amounts.contains_key("eur"));
+    assert_eq!(limits.max_intents_per_hour, 3);
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    assert!(PaymentLimits::from_settings(Some("usd:500-100"), None, None).is_err());
+    assert!(PaymentLimits::from_settings(Some("usd"), None, None).is_err());
+    assert!(PaymentLimits::from_settings(None, Some("usd:0"), None).is_err());
+    assert!(PaymentLimits::from_settings(None, None, Some("0")).is_err());
+    // A daily limit below the largest single payment could never be met
+    assert!(PaymentLimits::from_settings(None, Some("usd:500000"), None).is_err());
+}
+
+#[test]
+fn amounts_outside_the_currency_bounds_are_refused() {
+    let limits = defaults();
+    let none = RecentActivity::default();
+    assert!(limits.check("usd", 45_000, &none, None).is_ok());
+    assert_eq!(
+        limits.check("USD", 30, &none, None),
+        Err(LimitViolation::BelowMinimum {
+            currency: "usd".to_string(),
+            min: 50,
+        })
+    );
+    assert_eq!(
+        limits.check("eur", 1_000_001, &none, None),
+        Err(LimitViolation::AboveMaximum {
+            currency: "eur".to_string(),
+            max: 1_000_000,
+        })
+    );
+}
+
+#[test]
+fn velocity_limits_apply_per_customer() {
+    let limits = defaults();
+    assert!(limits.check("usd", 45_000, &activity(9, 0), None).is_ok());
+    assert_eq!(
+        limits.check("usd", 45_000, &activity(10, 0), None),
+        Err(LimitViolation::TooManyAttempts { limit: 10 })
+    );
+    assert!(limits
+        .check("usd", 500_000, &activity(2, 2_000_000), None)
+        .is_ok());
+    assert_eq!(
+        limits.check("usd", 500_001, &activity(2, 2_000_000), None),
+        Err(LimitViolation::DailyLimitExceeded {
+            currency: "usd".to_string(),
+            limit: 2_500_000,
+        })
+    );
+}
+
+#[test]
+fn overrides_raise_the_maximum_and_lift_velocity_checks() {
+    let limits = defaults();
+    let grant = limit_override(1_800_000);
+    assert!(limits
+        .check("usd", 1_800_000, &activity(12, 2_400_000), Some(&grant))
+        .is_ok());
+    assert_eq!(
+        limits.check("usd", 1_800_001, &activity(0, 0), Some(&grant)),
+        Err(LimitViolation::AboveMaximum {
+            currency: "usd".to_string(),
+            max: 1_800_000,
+        })
+    );
+    // The minimum still applies
+    assert!(matches!(
+        limits.check("usd", 10, &activity(0, 0), Some(&grant)),
+        Err(LimitViolation::BelowMinimum { .. })
+    ));
+    // An override below the configured maximum does not lower it
+    assert!(limits
+        .check(
+            "usd",
+            900_000,
+            &activity(0, 0),
+            Some(&limit_override(100_000))
+        )
+        .is_ok());
+}
+
+/// Requests a payment sheet, returning its status and error code.
+async fn payment_sheet(app: &TestApp, email: &str, amount: i64) -> (StatusCode, Option<String>) {
+    let response = app
+        .http
+        .post(format!("{}/payment_sheet", app.base_url))
+        .json(&json!({
+            "customer_name": "Test Guardian",
+            "customer_email": email,
+            "amount": amount,
+            "currency": "usd",
+            "metadata": {},
+        }))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    let body: Value = response.json().await.unwrap();
+    (status, body["error"].as_str().map(str::to_string))
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn payment_sheet_enforces_limits_until_an_override_is_granted() {
+    std::env::set_var("PAYMENT_MAX_INTENTS_PER_HOUR", "2");
+    let app = TestApp::spawn().await;
+    let family = "morgan@example.com";
+
+    assert_eq!(
+        payment_sheet(&app, family, 1_500_000).await,
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Some("AMOUNT_ABOVE_MAXIMUM".to_string())
+        )
+    );
+    // Refused checkouts do not count towards the hourly limit
+    for _ in 0..2 {
+        assert_eq!(
+            payment_sheet(&app, family, 45_000).await,
+            (StatusCode::OK, None)
+        );
+    }
+    assert_eq!(
+        payment_sheet(&app, "MORGAN@example.com", 45_000).await,
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            Some("TOO_MANY_PAYMENT_ATTEMPTS".to_string())
+        )
+    );
+    // Other families are unaffected
+    assert_eq!(
+        payment_sheet(&app, "riley@example.com", 45_000).await,
+        (StatusCode::OK, None)
+    );
+
+    let granted = app
+        .admin(Method::POST, "/admin/payment_limits/overrides")
+        .json(&json!({
+            "customer_email": " Morgan@Example.com ",
+            "currency": "usd",
+            "max_amount": 1_500_000,
+            "reason": "Whole summer in one payment",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(granted.status(), 200);
+    assert_eq!(
+        payment_sheet(&app, family, 1_500_000).await,
+        (StatusCode::OK, None)
+    );
+
+    let limits: Value = app
+        .admin(Method::GET, "/admin/payment_limits")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(limits["limits"]["max_intents_per_hour"], 2);
+    let overrides = limits["overrides"].as_array().unwrap();
+    assert_eq!(overrides.len(), 1);
+    assert_eq!(overrides[0]["customer_email"], family);
+}
@@ -0,0 +1,140 @@
+//! Payment method mix against Postgres: successful charges delivered by webhook are
+//! recorded once and totalled by category over a date range.
+mod common;
+
+use camp_registration_lambda::payment_methods::MethodCategory;
+use chrono::NaiveDate;
+use common::TestApp;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// A `charge.succeeded` event for a charge paid with `method_type` at noon on `day`.
+fn charge_succeeded(charge: &str, method_type: &str, amount: i64, day: NaiveDate) -> String {
+    let created = day.and_hms_opt(12, 0, 0).unwrap().and_utc().timestamp();
+    json!({
+        "id": format!("evt_{}", Uuid::new_v4().simple()),
+        "object": "event",
+        "type": "charge.succeeded",
+        "created": chrono::Utc::now().timestamp(),
+        "livemode": false,
+        "pending_webhooks": 1,
+        "data": {
+            "object": {
+                "id": charge,
+                "object": "charge",
+                "amount": amount,
+                "amount_captured": amount,
+                "amount_refunded": 0,
+                "billing_details": {},
+                "captured": true,
+                "created": created,
+                "currency": "usd",
+                "disputed": false,
+                "livemode": false,
+                "metadata": {},
+                "paid": true,
+                "refunded": false,
+                "status": "succeeded",
+                "payment_intent": format!("pi_{charge}"),
+                "payment_method_details": { "type": method_type },
+            }
+        }
+    })
+    .to_string()
+}
+
+fn june(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2026, 6, day).unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn successful_charges_are_totalled_by_method_category() {
+    let app = TestApp::spawn().await;
+    let charges = [
+        ("ch_card", "card", 30_000, june(10)),
+        ("ch_ach", "us_bank_account", 10_000, june(11)),
+        ("ch_klarna", "klarna", 10_000, june(30)),
+        (
+            "ch_july",
+            "card",
+            5_000,
+            NaiveDate::from_ymd_opt(2026, 7, 1).unwrap(),
+        ),
+    ];
+    for (charge, method_type, amount, day) in charges {
+        let payload = charge_succeeded(charge, method_type, amount, day);
+        assert_eq!(app.post_webhook(&payload).await.status(), 200);
+    }
+    // A second event for the same charge is not counted again
+    let repeated = charge_succeeded("ch_card", "card", 30_000, june(10));
+    assert_eq!(app.post_webhook(&repeated).await.status(), 200);
+
+    let response = app
+        .admin(
+            Method::GET,
+            "/admin/reports/payment_methods?from=2026-06-01&to=2026-06-30",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let report: Value = response.json().await.unwrap();
+    let methods: Vec<(String, i64, i64, f64)> = report["methods"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| {
+            (
+                m["category"].as_str().unwrap().to_string(),
+                m["count"].as_i64().unwrap(),
+                m["amount"].as_i64().unwrap(),
+                m["share_of_amount"].as_f64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        methods,
+        vec![
+            ("card".to_string(), 1, 30_000, 0.6),
+            ("ach".to_string(), 1, 10_000, 0.2),
+            ("bnpl".to_string(), 1, 10_000, 0.2),
+        ]
+    );
+    assert_eq!(
+        report["methods"][1]["method_types"],
+        json!({ "us_bank_account": 10_000 })
+    );
+
+    let backwards = app
+        .admin(
+            Method::GET,
+            "/admin/reports/payment_methods?from=2026-06-30&to=2026-06-01",
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(backwards.status(), 400);
+}
+
+#[test]
+fn method_types_fall_into_reporting_categories() {
+    assert_eq!(
+        MethodCategory::for_type("card_present"),
+        MethodCategory::Card
+    );
+    assert_eq!(MethodCategory::for_type("acss_debit"), MethodCategory::Ach);
+    assert_eq!(
+        MethodCategory::for_type("afterpay_clearpay"),
+        MethodCategory::Bnpl
+    );
+    assert_eq!(
+        MethodCategory::for_type("customer_balance"),
+        MethodCategory::Offline
+    );
+    assert_eq!(
+        MethodCategory::for_type("sepa_debit"),
+        MethodCategory::Other
+    );
+}
@@ -0,0 +1,192 @@
+//! Tests for authorized pickup validation, and for authorized pickups against
+//! Postgres: check-outs must name an adult on the registration's list, and the
+//! session pickup log shows who collected each camper.
+mod common;
+
+use camp_registration_lambda::database::schema::attendance_events;
+use camp_registration_lambda::pickups::AddPickupRequest;
+use common::{seed_confirmed_registration, PendingRegistration, TestApp};
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+async fn add_pickup(app: &TestApp, seed: &PendingRegistration, name: &str) -> Uuid {
+    let pickup: Value = app
+        .admin(
+            Method::POST,
+            &format!("/registrations/{}/pickups", seed.registration_id),
+        )
+        .json(&json!({ "name": name, "relationship": "grandparent" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    pickup["id"].as_str().unwrap().parse().unwrap()
+}
+
+/// Syncs one attendance operation and returns its result.
+async fn sync(
+    app: &TestApp,
+    seed: &PendingRegistration,
+    kind: &str,
+    timestamp: &str,
+    pickup_id: Option<Uuid>,
+) -> Value {
+    let response = app
+        .admin(Method::POST, "/sync")
+        .json(&json!({
+            "kiosk_id": "kiosk-1",
+            "operations": [{
+                "operation_id": Uuid::new_v4(),
+                "type": kind,
+                "registration_id": seed.registration_id,
+                "timestamp": timestamp,
+                "pickup_id": pickup_id,
+            }],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["results"][0].clone()
+}
+
+fn check_outs(app: &TestApp, seed: &PendingRegistration) -> Vec<Option<Uuid>> {
+    attendance_events::table
+        .filter(attendance_events::registration_id.eq(seed.registration_id))
+        .filter(attendance_events::kind.eq("check_out"))
+        .select(attendance_events::picked_up_by)
+        .load(&mut app.conn())
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn check_out_without_an_authorized_pickup_is_rejected() {
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+    let dana = add_pickup(&app, &seed, "Dana Lindqvist").await;
+    sync(&app, &seed, "check_in", "2026-07-06T09:00:00Z", None).await;
+
+    let unnamed = sync(&app, &seed, "check_out", "2026-07-06T16:00:00Z", None).await;
+    assert_eq!(unnamed["status"], "rejected");
+    let stranger = sync(
+        &app,
+        &seed,
+        "check_out",
+        "2026-07-06T16:00:00Z",
+        Some(Uuid::new_v4()),
+    )
+    .await;
+    assert_eq!(stranger["status"], "rejected");
+
+    // A removed adult can no longer collect the camper
+    let removed = app
+        .admin(
+            Method::DELETE,
+            &format!("/registrations/{}/pickups/{dana}", seed.registration_id),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(removed.status(), 204);
+    let after_removal = sync(&app, &seed, "check_out", "2026-07-06T16:00:00Z", Some(dana)).await;
+    assert_eq!(after_removal["status"], "rejected");
+
+    assert!(check_outs(&app, &seed).is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn authorized_check_out_is_logged_with_the_adult() {
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+    let dana = add_pickup(&app, &seed, "Dana Lindqvist").await;
+    sync(&app, &seed, "check_in", "2026-07-06T09:00:00Z", None).await;
+
+    let check_out = sync(&app, &seed, "check_out", "2026-07-06T16:00:00Z", Some(dana)).await;
+    assert_eq!(check_out["status"], "applied");
+    assert_eq!(check_outs(&app, &seed), vec![Some(dana)]);
+
+    let log: Value = app
+        .admin(
+            Method::GET,
+            &format!(
+                "/admin/sessions/{}/pickups?date=2026-07-06",
+                seed.session_id
+            ),
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let pickups = log["pickups"].as_array().unwrap();
+    assert_eq!(pickups.len(), 1);
+    assert_eq!(
+        pickups[0]["registration_id"],
+        seed.registration_id.to_string()
+    );
+    assert_eq!(pickups[0]["picked_up_by"]["name"], "Dana Lindqvist");
+    assert_eq!(pickups[0]["source"], "kiosk");
+}
+
+fn request(name: &str, relationship: &str) -> AddPickupRequest {
+    AddPickupRequest {
+        name: name.to_string(),
+        relationship: relationship.to_string(),
+        photo_url: None,
+        id_note: None,
+    }
+}
+
+#[test]
+fn fields_are_trimmed() {
+    let pickup = AddPickupRequest {
+        photo_url: Some(" https://camp.example.com/pickups/dana.jpg ".to_string()),
+        id_note: Some("   ".to_string()),
+        ..request("  Dana Lindqvist ", " grandparent ")
+    }
+    .validate()
+    .unwrap();
+    assert_eq!(pickup.name, "Dana Lindqvist");
+    assert_eq!(pickup.relationship, "grandparent");
+    assert_eq!(
+        pickup.photo_url.as_deref(),
+        Some("https://camp.example.com/pickups/dana.jpg")
+    );
+    assert_eq!(pickup.id_note, None);
+}
+
+#[test]
+fn names_and_relationships_are_required() {
+    assert!(request(" ", "grandparent").validate().is_err());
+    assert!(request("Dana Lindqvist", "").validate().is_err());
+    assert!(request(&"D".repeat(101), "grandparent").validate().is_err());
+    assert!(request("Dana Lindqvist", &"n".repeat(51))
+        .validate()
+        .is_err());
+}
+
+#[test]
+fn photos_must_be_https_and_notes_short() {
+    let insecure = AddPickupRequest {
+        photo_url: Some("http://camp.example.com/dana.jpg".to_string()),
+        ..request("Dana Lindqvist", "grandparent")
+    };
+    assert_eq!(
+        insecure.validate().unwrap_err(),
+        "photo_url must be an https URL"
+    );
+
+    let long_note = AddPickupRequest {
+        id_note: Some("x".repeat(501)),
+        ..request("Dana Lindqvist", "grandparent")
+    };
+    assert!(long_note.validate().is_err());
+}
@@ -0,0 +1,146 @@
+//! Tests for processing fee settings, gross-up and rounding, and for the fee on
+//! quotes and receipts against Postgres.
+mod common;
+
+use camp_registration_lambda::processing_fees::{
+    format_minor, minor_unit_digits, FeeRounding, ProcessingFees,
+};
+use common::{payment_intent_event, seed_pending_registration, TestApp};
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+
+fn fees(percent: Option<&str>, fixed: Option<&str>) -> ProcessingFees {
+    ProcessingFees::from_settings(percent, fixed, None).unwrap()
+}
+
+#[test]
+fn fees_are_disabled_by_default() {
+    let disabled = fees(None, None);
+    assert!(!disabled.is_enabled());
+    assert_eq!(disabled.fee_for(45_000, "usd"), 0);
+    assert!(disabled.line_item(45_000, "usd").is_none());
+}
+
+#[test]
+fn settings_are_validated() {
+    let parsed = fees(Some(" 2.9 "), Some("usd:30, EUR:25"));
+    assert_eq!(parsed.percent_basis_points, 290);
+    assert_eq!(parsed.fixed.get("eur"), Some(&25));
+    assert_eq!(parsed.rounding, FeeRounding::Up);
+
+    assert!(ProcessingFees::from_settings(Some("10.5"), None, None).is_err());
+    assert!(ProcessingFees::from_settings(Some("2.925"), None, None).is_err());
+    assert!(ProcessingFees::from_settings(Some("-1"), None, None).is_err());
+    assert!(ProcessingFees::from_settings(None, Some("usd:-30"), None).is_err());
+    // Three-decimal currencies are charged in multiples of ten
+    assert!(ProcessingFees::from_settings(None, Some("kwd:105"), None).is_err());
+    assert!(ProcessingFees::from_settings(None, None, Some("down")).is_err());
+    assert_eq!(
+        ProcessingFees::from_settings(None, None, Some("Nearest"))
+            .unwrap()
+            .rounding,
+        FeeRounding::Nearest
+    );
+}
+
+#[test]
+fn fees_gross_up_what_is_due() {
+    let card = fees(Some("2.9"), Some("usd:30"));
+    // (45,000 + 30) / 0.971 = 46,374.87, rounded up
+    assert_eq!(card.fee_for(45_000, "usd"), 1_375);
+    let total = 45_000 + card.fee_for(45_000, "usd");
+    let stripe_fee = (total as f64 * 0.029).round() as i64 + 30;
+    assert!(total - stripe_fee >= 45_000);
+
+    // Nothing due, nothing charged
+    assert_eq!(card.fee_for(0, "usd"), 0);
+    assert_eq!(card.fee_for(-100, "usd"), 0);
+
+    let nearest =
+        ProcessingFees::from_settings(Some("2.9"), Some("usd:30"), Some("nearest")).unwrap();
+    assert_eq!(nearest.fee_for(45_000, "usd"), 1_375);
+    // 3,030 / 0.971 = 3,120.49
+    assert_eq!(nearest.fee_for(3_000, "usd"), 120);
+    assert_eq!(card.fee_for(3_000, "usd"), 121);
+}
+
+#[test]
+fn fees_are_rounded_to_the_currency() {
+    assert_eq!(minor_unit_digits("JPY"), 0);
+    assert_eq!(minor_unit_digits("kwd"), 3);
+    assert_eq!(minor_unit_digits("usd"), 2);
+
+    let percent = fees(Some("3.6"), None);
+    // 5,000 JPY grossed up is 5,186.7 yen
+    assert_eq!(percent.fee_for(5_000, "jpy"), 187);
+    // 10.000 KWD grossed up is 10.373 KWD, charged as 10.380
+    assert_eq!(percent.fee_for(10_000, "kwd"), 380);
+}
+
+#[test]
+fn line_items_name_the_fee() {
+    let card = fees(Some("2.9"), Some("usd:30,jpy:40"));
+    let item = card.line_item(45_000, "usd").unwrap();
+    assert_eq!(item.label, "Processing fee (2.9% + 0.30 USD)");
+    assert_eq!(item.amount, 1_375);
+    assert_eq!(card.label("jpy"), "Processing fee (2.9% + 40 JPY)");
+    assert_eq!(card.label("eur"), "Processing fee (2.9%)");
+    assert_eq!(
+        fees(None, Some("eur:25")).label("eur"),
+        "Processing fee (0.25 EUR)"
+    );
+    assert_eq!(fees(Some("3"), None).label("usd"), "Processing fee (3%)");
+    assert_eq!(format_minor(10_380, "kwd"), "10.380 KWD");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn quotes_and_receipts_carry_the_fee() {
+    std::env::set_var("PROCESSING_FEE_PERCENT", "2.9");
+    std::env::set_var("PROCESSING_FEE_FIXED", "usd:30");
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+
+    let response = app
+        .guardian(seed.guardian_id, Method::POST, "/quote")
+        .json(&json!({ "registration_ids": [seed.registration_id], "currency": "usd" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let quote: Value = response.json().await.unwrap();
+    assert_eq!(quote["subtotal"], 45_000);
+    assert_eq!(quote["processing_fee"], 1_375);
+    assert_eq!(quote["total"], 46_375);
+    assert_eq!(
+        quote["line_items"],
+        json!([
+            { "label": "Camp registration: Week 1", "amount": 45_000 },
+            { "label": "Processing fee (2.9% + 0.30 USD)", "amount": 1_375 },
+        ])
+    );
+
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_with_fee",
+        46_375,
+        "usd",
+        json!({
+            "quote_id": quote["quote_id"],
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    let receipt: Value = app
+        .guardian(seed.guardian_id, Method::GET, "/receipts/pi_with_fee")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(receipt["amount"], 46_375);
+    assert_eq!(receipt["processing_fee"], 1_375);
+    assert_eq!(receipt["line_items"], quote["line_items"]);
+}
@@ -0,0 +1,30 @@
+//! Public availability rate limits against the served router: callers are keyed on the
+//! address API Gateway appended, not on whatever `X-Forwarded-For` they sent.
+mod common;
+
+use common::TestApp;
+
+async fn fetch(app: &TestApp, forwarded_for: &str) -> u16 {
+    app.http
+        .get(format!("{}/public/sessions/availability", app.base_url))
+        .header("x-forwarded-for", forwarded_for)
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn spoofed_first_hops_share_the_gateway_address_limit() {
+    std::env::set_var("PUBLIC_AVAILABILITY_RATE_LIMIT", "2");
+    let app = TestApp::spawn().await;
+
+    assert_eq!(fetch(&app, "198.51.100.1, 203.0.113.7").await, 200);
+    assert_eq!(fetch(&app, "198.51.100.2, 203.0.113.7").await, 200);
+    assert_eq!(fetch(&app, "198.51.100.3, 203.0.113.7").await, 429);
+
+    // A different gateway-observed address has its own allowance
+    assert_eq!(fetch(&app, "198.51.100.3, 203.0.113.8").await, 200);
+}
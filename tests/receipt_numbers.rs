@@ -0,0 +1,147 @@
+//! Tests for receipt numbering settings and formatting, and for receipt numbering
+//! against Postgres: successful payments draw gap-free numbers from the
+//! organization's sequence, once per PaymentIntent, and reading a receipt never
+//! allocates one.
+mod common;
+
+use camp_registration_lambda::database::schema::{receipt_numbers, receipt_sequences};
+use camp_registration_lambda::receipt_numbers::ReceiptNumbering;
+use common::{payment_intent_event, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+
+fn receipt_number(app: &TestApp, intent: &str) -> Option<String> {
+    receipt_numbers::table
+        .find(intent)
+        .select(receipt_numbers::receipt_number)
+        .first(&mut app.conn())
+        .optional()
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn successful_payments_are_numbered_once_in_order() {
+    let app = TestApp::spawn().await;
+    let webhook =
+        |event: &str, intent: &str| payment_intent_event(event, intent, 12_000, "usd", json!({}));
+
+    // Failures never consume a number
+    let failed = webhook("payment_intent.payment_failed", "pi_failed");
+    assert_eq!(app.post_webhook(&failed).await.status(), 200);
+    for intent in ["pi_first", "pi_second"] {
+        let succeeded = webhook("payment_intent.succeeded", intent);
+        assert_eq!(app.post_webhook(&succeeded).await.status(), 200);
+    }
+    // A second success event for the same payment keeps its number
+    let repeated = webhook("payment_intent.succeeded", "pi_first");
+    assert_eq!(app.post_webhook(&repeated).await.status(), 200);
+
+    assert_eq!(receipt_number(&app, "pi_failed"), None);
+    assert_eq!(
+        receipt_number(&app, "pi_first").as_deref(),
+        Some("R-000001")
+    );
+    assert_eq!(
+        receipt_number(&app, "pi_second").as_deref(),
+        Some("R-000002")
+    );
+    let next: i64 = receipt_sequences::table
+        .find("default")
+        .select(receipt_sequences::next_number)
+        .first(&mut app.conn())
+        .unwrap();
+    assert_eq!(next, 3);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn concurrent_allocations_leave_no_gaps() {
+    let app = TestApp::spawn().await;
+    let numbering = ReceiptNumbering::from_settings(Some("pine-lake"), Some("PLC")).unwrap();
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let pool = app.pool.clone();
+            let numbering = numbering.clone();
+            std::thread::spawn(move || {
+                let mut conn = pool.get().unwrap();
+                numbering
+                    .allocate(&mut conn, &format!("pi_concurrent_{i}"), 5_000, "USD")
+                    .unwrap()
+                    .number
+            })
+        })
+        .collect();
+    let mut numbers: Vec<i64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    numbers.sort();
+    assert_eq!(numbers, (1..=8).collect::<Vec<i64>>());
+}
+
+#[test]
+fn numbers_are_printed_with_the_prefix_and_padding() {
+    let numbering = ReceiptNumbering::from_settings(None, None).unwrap();
+    assert_eq!(numbering.organization(), "default");
+    assert_eq!(numbering.format(42), "R-000042");
+    assert_eq!(numbering.format(1_234_567), "R-1234567");
+
+    let numbering = ReceiptNumbering::from_settings(Some("pine-lake"), Some(" PLC ")).unwrap();
+    assert_eq!(numbering.organization(), "pine-lake");
+    assert_eq!(numbering.format(7), "PLC-000007");
+}
+
+#[test]
+fn blank_settings_fall_back_to_defaults() {
+    let numbering = ReceiptNumbering::from_settings(Some(""), Some("  ")).unwrap();
+    assert_eq!(numbering.organization(), "default");
+    assert_eq!(numbering.format(1), "R-000001");
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    assert!(ReceiptNumbering::from_settings(None, Some("R/2026")).is_err());
+    assert!(ReceiptNumbering::from_settings(Some("pine lake"), None).is_err());
+    assert!(ReceiptNumbering::from_settings(None, Some(&"R".repeat(33))).is_err());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn reading_a_receipt_never_allocates_a_number() {
+    let app = TestApp::spawn().await;
+    // A payment recorded without going through the webhook has no number
+    app.conn()
+        .batch_execute(
+            "INSERT INTO payment_events (payment_intent_id, status, amount, currency)
+             VALUES ('pi_unnumbered', 'succeeded', 12000, 'usd');",
+        )
+        .unwrap();
+
+    let response = app
+        .admin(Method::GET, "/receipts/pi_unnumbered")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.text().await.unwrap(), "Receipt not issued yet");
+    assert_eq!(receipt_number(&app, "pi_unnumbered"), None);
+
+    let succeeded = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_numbered",
+        12_000,
+        "usd",
+        json!({}),
+    );
+    assert_eq!(app.post_webhook(&succeeded).await.status(), 200);
+    let receipt: Value = app
+        .admin(Method::GET, "/receipts/pi_numbered")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(receipt["receipt_number"], "R-000001");
+}
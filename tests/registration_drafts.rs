@@ -0,0 +1,145 @@
+//! Tests for registration draft answers and completeness, and for saving, converting
+//! and expiring drafts against Postgres.
+mod common;
+
+use camp_registration_lambda::registration_drafts::{
+    merge_answers, missing_fields, MAX_ANSWERS_BYTES,
+};
+use common::{seed_pending_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+fn changes(value: Value) -> Map<String, Value> {
+    value.as_object().cloned().unwrap()
+}
+
+#[test]
+fn answers_are_merged_key_by_key() {
+    let saved = json!({ "tshirt_size": "youth_s", "swim_level": "beginner" });
+    let merged = merge_answers(
+        &saved,
+        changes(json!({ "tshirt_size": "youth_m", "allergies": "none" })),
+    )
+    .unwrap();
+    assert_eq!(
+        merged,
+        json!({ "tshirt_size": "youth_m", "swim_level": "beginner", "allergies": "none" })
+    );
+
+    // null removes an answer
+    let merged = merge_answers(&merged, changes(json!({ "swim_level": null }))).unwrap();
+    assert_eq!(
+        merged,
+        json!({ "tshirt_size": "youth_m", "allergies": "none" })
+    );
+}
+
+#[test]
+fn oversized_answers_are_rejected() {
+    let essay = "a".repeat(MAX_ANSWERS_BYTES);
+    assert!(merge_answers(&json!({}), changes(json!({ "notes": essay }))).is_err());
+}
+
+#[test]
+fn drafts_need_a_camper_and_a_session() {
+    let id = Uuid::from_u128(1);
+    assert_eq!(missing_fields(None, None), vec!["camper_id", "session_id"]);
+    assert_eq!(missing_fields(Some(id), None), vec!["session_id"]);
+    assert!(missing_fields(Some(id), Some(id)).is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn drafts_are_saved_in_parts_and_converted_once() {
+    let app = TestApp::spawn().await;
+    let (seed, week_two, teens) = {
+        let mut conn = app.conn();
+        let seed = seed_pending_registration(&mut conn, 45_000);
+        let (week_two, teens) = (Uuid::new_v4(), Uuid::new_v4());
+        conn.batch_execute(&format!(
+            "INSERT INTO camp_sessions (id, name, starts_on, ends_on, capacity, price, currency, min_age, max_age)
+                 VALUES ('{week_two}', 'Week 2', '2027-07-12', '2027-07-16', 10, 45000, 'usd', 8, 15),
+                        ('{teens}', 'Teen Trek', '2027-07-19', '2027-07-23', 10, 45000, 'usd', 16, NULL);"
+        ))
+        .unwrap();
+        (seed, week_two, teens)
+    };
+    let guardian = |method: Method, path: &str| app.guardian(seed.guardian_id, method, path);
+
+    let (status, created) = send(guardian(Method::POST, "/registrations/draft").json(&json!({
+        "camper_id": seed.camper_id,
+        "answers": { "tshirt_size": "youth_s", "swim_level": "beginner" },
+    })))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["missing"], json!(["session_id"]));
+    assert_eq!(created["ready_for_checkout"], false);
+    let draft = format!(
+        "/registrations/draft/{}",
+        created["draft"]["id"].as_str().unwrap()
+    );
+
+    let (status, incomplete) = send(guardian(Method::POST, &format!("{draft}/convert"))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(incomplete["message"], "Draft is missing session_id");
+
+    // Sam is 13 when Teen Trek starts
+    let (status, too_young) =
+        send(guardian(Method::PATCH, &draft).json(&json!({ "session_id": teens }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(too_young["error"], "AGE_INELIGIBLE");
+
+    let (status, complete) = send(guardian(Method::PATCH, &draft).json(&json!({
+        "session_id": week_two,
+        "friend_requests": ["Avery"],
+        "answers": { "swim_level": null },
+    })))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(complete["ready_for_checkout"], true);
+    assert_eq!(
+        complete["draft"]["answers"],
+        json!({ "tshirt_size": "youth_s" })
+    );
+
+    let stranger = app
+        .guardian(Uuid::new_v4(), Method::GET, &draft)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stranger.status(), StatusCode::NOT_FOUND);
+    let (_, listed) = send(guardian(Method::GET, "/registrations/draft")).await;
+    assert_eq!(listed["drafts"].as_array().unwrap().len(), 1);
+
+    let (status, converted) = send(guardian(Method::POST, &format!("{draft}/convert"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        converted["registration"]["session_id"],
+        week_two.to_string()
+    );
+    assert_eq!(converted["registration"]["status"], "pending");
+    assert_eq!(converted["friend_requests"], json!(["Avery"]));
+    let (status, _) = send(guardian(Method::POST, &format!("{draft}/convert"))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (_, listed) = send(guardian(Method::GET, "/registrations/draft")).await;
+    assert_eq!(listed["drafts"], json!([]));
+
+    // Stale drafts are deleted, converted ones are kept
+    send(guardian(Method::POST, "/registrations/draft").json(&json!({ "session_id": week_two })))
+        .await;
+    app.conn()
+        .batch_execute("UPDATE registration_drafts SET expires_at = NOW() - INTERVAL '1 day';")
+        .unwrap();
+    let (_, job) = send(app.admin(Method::POST, "/admin/jobs/registration_drafts")).await;
+    assert_eq!(job["summary"]["expired"], 1);
+    let (status, _) = send(guardian(Method::GET, &draft)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn send(request: RequestBuilder) -> (StatusCode, Value) {
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
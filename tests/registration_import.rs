@@ -0,0 +1,197 @@
+//! Tests for reading and checking registration import files, and for importing
+//! them into Postgres.
+mod common;
+
+use camp_registration_lambda::api_error::ErrorCode;
+use camp_registration_lambda::database::schema::{guardians, registrations};
+use camp_registration_lambda::registration_import::{parse_rows, ImportStatus, MAX_IMPORT_ROWS};
+use chrono::NaiveDate;
+use common::{seed_pending_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+use uuid::Uuid;
+
+const SESSION: &str = "00000000-0000-0000-0000-000000003000";
+const HEADER: &str =
+    "guardian_email,guardian_name,camper_first_name,camper_last_name,birthdate,session_id";
+
+#[test]
+fn rows_are_read_by_column_name() {
+    let csv = format!(
+        "Session_ID, Birthdate ,camper_last_name,camper_first_name,guardian_name,guardian_email,status,friend_requests\n\
+         {SESSION},2014-07-14,Lindqvist,Avery,Morgan Lindqvist,parent@example.com,Confirmed,Riley Okafor; Sam Park\n\
+         {SESSION},2016-02-01,Lindqvist,Jordan,Morgan Lindqvist,parent@example.com,,\n"
+    );
+    let parsed = parse_rows(&csv).unwrap();
+    assert_eq!(parsed.total_rows, 2);
+    assert!(parsed.errors.is_empty());
+
+    let first = &parsed.rows[0];
+    assert_eq!(first.row, 2);
+    assert_eq!(first.guardian_email, "parent@example.com");
+    assert_eq!(first.camper_first_name, "Avery");
+    assert_eq!(
+        first.birthdate,
+        NaiveDate::from_ymd_opt(2014, 7, 14).unwrap()
+    );
+    assert_eq!(first.session_id, Uuid::parse_str(SESSION).unwrap());
+    assert_eq!(first.status, ImportStatus::Confirmed);
+    assert_eq!(first.friend_requests, vec!["Riley Okafor", "Sam Park"]);
+
+    let second = &parsed.rows[1];
+    assert_eq!(second.row, 3);
+    assert_eq!(second.status, ImportStatus::Pending);
+    assert!(second.friend_requests.is_empty());
+}
+
+#[test]
+fn every_bad_value_is_reported() {
+    let csv = format!(
+        "{HEADER},status\n\
+         parent.example.com,Morgan Lindqvist,Avery,,07/14/2014,not-a-session,waitlisted\n\
+         parent@example.com,Morgan Lindqvist,Jordan,Lindqvist,2016-02-01,{SESSION},\n"
+    );
+    let parsed = parse_rows(&csv).unwrap();
+    assert_eq!(parsed.total_rows, 2);
+    assert_eq!(parsed.rows.len(), 1);
+    assert_eq!(parsed.rows[0].row, 3);
+
+    let columns: Vec<&str> = parsed
+        .errors
+        .iter()
+        .map(|e| e.column.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        columns,
+        vec![
+            "camper_last_name",
+            "guardian_email",
+            "birthdate",
+            "session_id",
+            "status"
+        ]
+    );
+    assert!(parsed
+        .errors
+        .iter()
+        .all(|e| e.row == 2 && e.code == ErrorCode::InvalidRequest));
+}
+
+#[test]
+fn friend_requests_follow_the_api_rules() {
+    let csv = format!(
+        "{HEADER},friend_requests\n\
+         parent@example.com,Morgan Lindqvist,Avery,Lindqvist,2014-07-14,{SESSION},A;B;C;D\n"
+    );
+    let parsed = parse_rows(&csv).unwrap();
+    assert!(parsed.rows.is_empty());
+    assert_eq!(parsed.errors[0].column.as_deref(), Some("friend_requests"));
+}
+
+#[test]
+fn unusable_files_are_rejected() {
+    let missing = parse_rows("guardian_email,guardian_name\n").unwrap_err();
+    assert_eq!(missing.code, ErrorCode::InvalidRequest);
+    assert_eq!(
+        missing.message,
+        "Missing CSV columns: camper_first_name, camper_last_name, birthdate, session_id"
+    );
+
+    let row = format!("parent@example.com,Morgan Lindqvist,Avery,Lindqvist,2014-07-14,{SESSION}\n");
+    let too_many = format!("{HEADER}\n{}", row.repeat(MAX_IMPORT_ROWS + 1));
+    assert!(parse_rows(&too_many).is_err());
+
+    // A short row is reported, not fatal
+    let short = format!("{HEADER}\nparent@example.com,Morgan Lindqvist\n{row}");
+    let parsed = parse_rows(&short).unwrap();
+    assert_eq!(parsed.rows.len(), 1);
+    assert_eq!(parsed.errors[0].row, 2);
+    assert_eq!(parsed.errors[0].column, None);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn imports_are_checked_then_saved_all_or_nothing() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let session = Uuid::new_v4();
+    app.conn()
+        .batch_execute(&format!(
+            "INSERT INTO camp_sessions (id, name, starts_on, ends_on, capacity, price, currency)
+                 VALUES ('{session}', 'Paper Week', '2027-08-02', '2027-08-06', 3, 45000, 'usd');"
+        ))
+        .unwrap();
+    let rows = [
+        format!(
+            "parent@example.com,Morgan Lindqvist,Avery,Lindqvist,2014-07-14,{session},confirmed"
+        ),
+        format!("parent@example.com,Morgan Lindqvist,Jordan,Lindqvist,2016-02-01,{session},"),
+        // The camper seeded through the API, matched by guardian, name and birthdate
+        format!(
+            "{}@example.com,Test Guardian,Sam,Camper,2014-05-01,{session},",
+            seed.guardian_id
+        ),
+        format!("okafor@example.com,Dana Okafor,Riley,Okafor,2015-03-09,{session},"),
+    ];
+    let file = |rows: &[String]| format!("{HEADER},status\n{}\n", rows.join("\n"));
+
+    // The fourth camper does not fit; a dry run reports it and saves nothing
+    let (status, dry_run) = import(&app, "?dry_run=true", file(&rows)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dry_run["committed"], false);
+    assert_eq!(dry_run["registrations_created"], 3);
+    assert_eq!(dry_run["imported"][0]["registration_id"], Value::Null);
+    assert_eq!(dry_run["errors"][0]["row"], 5);
+    assert_eq!(dry_run["errors"][0]["code"], "SESSION_FULL");
+    let (status, failed) = import(&app, "", file(&rows)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(failed["committed"], false);
+    let mut conn = app.conn();
+    let parents: i64 = guardians::table
+        .filter(guardians::email.eq("parent@example.com"))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(parents, 0);
+
+    let (status, saved) = import(&app, "", file(&rows[..3])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(saved["committed"], true);
+    assert_eq!(saved["guardians_created"], 1);
+    assert_eq!(saved["campers_created"], 2);
+    assert_eq!(
+        saved["imported"][2]["camper_id"],
+        seed.camper_id.to_string()
+    );
+    let mut statuses: Vec<String> = registrations::table
+        .filter(registrations::session_id.eq(session))
+        .select(registrations::status)
+        .load(&mut conn)
+        .unwrap();
+    statuses.sort();
+    assert_eq!(statuses, ["confirmed", "pending", "pending"]);
+
+    // Importing the same file again finds every camper already registered
+    let (status, again) = import(&app, "", file(&rows[..3])).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let codes: Vec<&str> = again["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, ["CONFLICT"; 3]);
+}
+
+async fn import(app: &TestApp, query: &str, csv: String) -> (StatusCode, Value) {
+    let response = app
+        .admin(Method::POST, &format!("/admin/registrations/import{query}"))
+        .header("content-type", "text/csv")
+        .body(csv)
+        .send()
+        .await
+        .unwrap();
+    (response.status(), response.json().await.unwrap())
+}
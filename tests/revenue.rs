@@ -0,0 +1,207 @@
+//! Tests for session revenue allocation and recognition, and for the monthly
+//! report built from payments and refunds in Postgres.
+mod common;
+
+use camp_registration_lambda::revenue::{
+    allocate, month_end, parse_month, summarize, RevenueEntry, RevenueLedger, SessionRef,
+};
+use chrono::{NaiveDate, Utc};
+use common::{payment_intent_event, seed_confirmed_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+fn date(month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+}
+
+fn entry(session: Option<Uuid>, on: NaiveDate, amount: i64) -> RevenueEntry {
+    RevenueEntry {
+        session_id: session,
+        on,
+        amount,
+        currency: "usd".to_string(),
+    }
+}
+
+#[test]
+fn payments_are_split_by_session_price() {
+    let (a, b, c) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+    assert_eq!(
+        allocate(10_000, &[(a, 30_000), (b, 10_000)]),
+        vec![(a, 7500), (b, 2500)]
+    );
+    // The remainder lands on the last session
+    assert_eq!(
+        allocate(100, &[(a, 1), (b, 1), (c, 1)]),
+        vec![(a, 33), (b, 33), (c, 34)]
+    );
+    assert_eq!(allocate(101, &[(a, 0), (b, 0)]), vec![(a, 50), (b, 51)]);
+    assert!(allocate(100, &[]).is_empty());
+}
+
+#[test]
+fn months_are_parsed_and_bounded() {
+    assert_eq!(parse_month("2026-02"), Ok(date(2, 1)));
+    assert!(parse_month("2026-13").is_err());
+    assert!(parse_month("June").is_err());
+    assert_eq!(month_end(date(2, 1)), date(2, 28));
+    assert_eq!(month_end(date(12, 1)), date(12, 31));
+}
+
+#[test]
+fn revenue_is_deferred_until_the_session_starts() {
+    let session = Uuid::from_u128(1);
+    let ledger = RevenueLedger {
+        sessions: vec![SessionRef {
+            id: session,
+            name: "Lakeside Week 1".to_string(),
+            starts_on: date(7, 6),
+        }],
+        entries: vec![
+            entry(Some(session), date(5, 10), 45_000),
+            entry(Some(session), date(6, 2), 45_000),
+            entry(Some(session), date(6, 20), -10_000),
+            entry(Some(session), date(7, 20), -5000),
+            entry(None, date(6, 3), 2500),
+        ],
+    };
+
+    let may = summarize(date(5, 1), &ledger);
+    assert_eq!(may.month, "2026-05");
+    assert_eq!(may.sessions[0].collected_to_date, 45_000);
+    assert_eq!(may.sessions[0].deferred, 45_000);
+    assert_eq!(may.sessions[0].recognized_to_date, 0);
+
+    let june = summarize(date(6, 1), &ledger);
+    assert_eq!(june.sessions[0].collected_in_month, 35_000);
+    assert_eq!(june.sessions[0].deferred, 80_000);
+    assert_eq!(june.totals[0].collected_in_month, 37_500);
+    assert_eq!(june.totals[0].unallocated_in_month, 2500);
+
+    // Everything collected so far is recognized in the month the session starts
+    let july = summarize(date(7, 1), &ledger);
+    let row = &july.sessions[0];
+    assert_eq!(row.collected_in_month, -5000);
+    assert_eq!(row.recognized_in_month, 75_000);
+    assert_eq!(row.recognized_to_date, 75_000);
+    assert_eq!(row.deferred, 0);
+    assert_eq!(july.totals[0].unallocated_in_month, 0);
+
+    // Nothing moves in August, so nothing more is recognized
+    let august = summarize(date(8, 1), &ledger);
+    assert_eq!(august.sessions[0].recognized_in_month, 0);
+    assert_eq!(august.sessions[0].recognized_to_date, 75_000);
+}
+
+#[test]
+fn sessions_without_money_are_left_out() {
+    let session = Uuid::from_u128(1);
+    let ledger = RevenueLedger {
+        sessions: vec![SessionRef {
+            id: session,
+            name: "Lakeside Week 1".to_string(),
+            starts_on: date(7, 6),
+        }],
+        entries: vec![entry(Some(session), date(6, 10), 45_000)],
+    };
+    assert!(summarize(date(5, 1), &ledger).sessions.is_empty());
+    assert!(summarize(date(5, 1), &ledger).totals.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn the_monthly_report_defers_payments_until_sessions_start() {
+    let app = TestApp::spawn().await;
+    let (week_one, week_two) = {
+        let mut conn = app.conn();
+        let week_one = seed_confirmed_registration(&mut conn, 30_000);
+        let week_two = seed_confirmed_registration(&mut conn, 10_000);
+        conn.batch_execute(&format!(
+            "UPDATE camp_sessions SET name = 'Week 2', starts_on = '2027-07-12', ends_on = '2027-07-16'
+                 WHERE id = '{}';",
+            week_two.session_id
+        ))
+        .unwrap();
+        (week_one, week_two)
+    };
+
+    // One payment for both weeks, split 3:1 by session price, and a voucher
+    let family = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_family",
+        20_000,
+        "usd",
+        json!({
+            "registration_ids": format!("{},{}", week_one.registration_id, week_two.registration_id),
+        }),
+    );
+    assert_eq!(app.post_webhook(&family).await.status(), 200);
+    let voucher = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_voucher",
+        2500,
+        "usd",
+        json!({ "purpose": "voucher" }),
+    );
+    assert_eq!(app.post_webhook(&voucher).await.status(), 200);
+    // A partial refund not tied to a cancellation is split like the payment
+    app.conn()
+        .batch_execute(
+            "INSERT INTO refunds (stripe_refund_id, payment_intent_id, amount, currency, status)
+                 VALUES ('re_partial', 'pi_family', 4000, 'usd', 'succeeded');",
+        )
+        .unwrap();
+
+    let this_month = report(&app, &Utc::now().format("%Y-%m").to_string()).await;
+    let rows = this_month["sessions"].as_array().unwrap();
+    let collected: Vec<(&str, i64, i64, i64)> = rows
+        .iter()
+        .map(|row| {
+            (
+                row["session_name"].as_str().unwrap(),
+                row["collected_in_month"].as_i64().unwrap(),
+                row["recognized_to_date"].as_i64().unwrap(),
+                row["deferred"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        collected,
+        [("Week 1", 12_000, 0, 12_000), ("Week 2", 4000, 0, 4000)]
+    );
+    let totals = &this_month["totals"][0];
+    assert_eq!(totals["currency"], "usd");
+    assert_eq!(totals["collected_in_month"], 18_500);
+    assert_eq!(totals["unallocated_in_month"], 2500);
+    assert_eq!(totals["deferred"], 16_000);
+
+    // Both weeks start in July 2027, when everything collected is recognized
+    let july = report(&app, "2027-07").await;
+    let totals = &july["totals"][0];
+    assert_eq!(totals["collected_in_month"], 0);
+    assert_eq!(totals["recognized_in_month"], 16_000);
+    assert_eq!(totals["deferred"], 0);
+    assert_eq!(july["sessions"][0]["recognized_in_month"], 12_000);
+
+    let invalid = app
+        .admin(Method::GET, "/admin/reports/session_revenue?month=June")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+}
+
+async fn report(app: &TestApp, month: &str) -> Value {
+    let response = app
+        .admin(
+            Method::GET,
+            &format!("/admin/reports/session_revenue?month={month}"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
@@ -0,0 +1,147 @@
+//! Tests for the route-policy registry: every route the router serves has a declared
+//! policy, and the middleware refuses method and path pairs that are not declared.
+use axum::{middleware, routing::get, Extension, Router};
+use camp_registration_lambda::route_policy::{
+    enforce_route_policy, RoutePolicyRegistry, ROUTE_POLICIES,
+};
+use reqwest::StatusCode;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+const METHOD_ROUTERS: [(&str, &str); 5] = [
+    ("get", "GET"),
+    ("post", "POST"),
+    ("put", "PUT"),
+    ("patch", "PATCH"),
+    ("delete", "DELETE"),
+];
+
+/// The end of the call whose argument list starts at `open`, an opening parenthesis.
+fn closing_paren(source: &str, open: usize) -> usize {
+    let mut depth = 0;
+    for (i, c) in source[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + i;
+                }
+            }
+            _ => {}
+        }
+    }
+    panic!("unbalanced parentheses after byte {open}");
+}
+
+/// Every `(method, path)` pair `build_router` registers, read from its `.route(...)` calls.
+fn served_routes() -> BTreeSet<(String, String)> {
+    let source = include_str!("../src/lib.rs");
+    let start = source
+        .find("let app = Router::new()")
+        .expect("build_router no longer starts with Router::new()");
+    let end = start
+        + source[start..]
+            .find(".route_layer(")
+            .expect("build_router has no route layers");
+    let routes = &source[start..end];
+
+    let mut served = BTreeSet::new();
+    let mut rest = 0;
+    while let Some(found) = routes[rest..].find(".route(") {
+        let open = rest + found + ".route".len();
+        let close = closing_paren(routes, open);
+        let call = &routes[open + 1..close];
+        let path = call
+            .split('"')
+            .nth(1)
+            .expect("route path is not a string literal");
+        let handlers = &call[call.find(',').expect("route without handlers")..];
+        for (function, method) in METHOD_ROUTERS {
+            let called = format!("{function}(");
+            let mut from = 0;
+            while let Some(at) = handlers[from..].find(&called) {
+                let at = from + at;
+                let preceding = handlers[..at].chars().next_back();
+                if !preceding.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    served.insert((method.to_string(), path.to_string()));
+                }
+                from = at + called.len();
+            }
+        }
+        rest = close;
+    }
+    served
+}
+
+#[test]
+fn every_served_route_has_a_declared_policy() {
+    let served = served_routes();
+    assert!(served.len() > 50, "found only {} routes", served.len());
+    let declared: BTreeSet<(String, String)> = ROUTE_POLICIES
+        .iter()
+        .map(|p| (p.method.to_string(), p.path.to_string()))
+        .collect();
+
+    let undeclared: Vec<_> = served.difference(&declared).collect();
+    assert!(
+        undeclared.is_empty(),
+        "routes without a policy: {undeclared:?}"
+    );
+    let unserved: Vec<_> = declared.difference(&served).collect();
+    assert!(
+        unserved.is_empty(),
+        "policies for unserved routes: {unserved:?}"
+    );
+}
+
+#[test]
+fn policies_are_declared_once() {
+    assert!(RoutePolicyRegistry::load().is_ok());
+}
+
+/// Serves `/hello`, which is declared for GET only, with POST too, and a route that
+/// is not declared at all, behind the policy middleware.
+async fn serve_with_undeclared_routes() -> String {
+    let registry = Arc::new(RoutePolicyRegistry::load().unwrap());
+    let router = Router::new()
+        .route(
+            "/hello",
+            get(|| async { "hello" }).post(|| async { "posted" }),
+        )
+        .route("/undeclared", get(|| async { "undeclared" }))
+        .route_layer(middleware::from_fn(enforce_route_policy))
+        .layer(Extension(registry));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{address}")
+}
+
+#[tokio::test]
+async fn undeclared_routes_are_refused() {
+    let base_url = serve_with_undeclared_routes().await;
+    let http = reqwest::Client::new();
+
+    let response = http.get(format!("{base_url}/hello")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // HEAD is answered by the GET handler, so it follows the GET policy
+    let response = http.head(format!("{base_url}/hello")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for request in [
+        http.post(format!("{base_url}/hello")),
+        http.get(format!("{base_url}/undeclared")),
+        http.head(format!("{base_url}/undeclared")),
+    ] {
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let response = http
+        .get(format!("{base_url}/undeclared"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "Route is not permitted");
+}
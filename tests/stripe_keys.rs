@@ -0,0 +1,195 @@
+//! Tests for per-capability Stripe keys, and for the keys each flow sends to
+//! stripe-mock.
+mod common;
+
+use axum::{body::to_bytes, extract::Request, extract::State, response::IntoResponse, Router};
+use camp_registration_lambda::database::schema::registration_holds;
+use camp_registration_lambda::stripe_keys::{StripeCapability, StripeKeyring};
+use common::{payment_intent_event, seed_pending_registration, TestApp};
+use diesel::prelude::*;
+use lambda_lib::structs::StripeKeys;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+fn secret_keys() -> StripeKeys {
+    StripeKeys {
+        secret_key: "sk_test_secret".to_string(),
+        publishable_key: "pk_test_publishable".to_string(),
+        webhook_secret: "whsec_test".to_string(),
+    }
+}
+
+#[test]
+fn capabilities_without_their_own_key_use_the_secret_key() {
+    let keyring = StripeKeyring::from_settings(None, None, None).unwrap();
+    for capability in StripeCapability::ALL {
+        assert!(!keyring.has_own_key(capability));
+        assert_eq!(keyring.key(capability, &secret_keys()), "sk_test_secret");
+    }
+}
+
+#[test]
+fn each_capability_gets_its_own_key() {
+    let keyring =
+        StripeKeyring::from_settings(Some(" rk_test_payments "), None, Some("rk_test_reports"))
+            .unwrap();
+    let keys = secret_keys();
+    assert_eq!(
+        keyring.key(StripeCapability::Payments, &keys),
+        "rk_test_payments"
+    );
+    assert_eq!(
+        keyring.key(StripeCapability::Refunds, &keys),
+        "sk_test_secret"
+    );
+    assert_eq!(
+        keyring.key(StripeCapability::Reporting, &keys),
+        "rk_test_reports"
+    );
+    // Keys never show up in debug output
+    assert!(!format!("{keyring:?}").contains("rk_test"));
+}
+
+#[test]
+fn invalid_keys_are_rejected() {
+    assert!(StripeKeyring::from_settings(Some("pk_test_publishable"), None, None).is_err());
+    assert!(StripeKeyring::from_settings(None, Some("whsec_abc"), None).is_err());
+    assert!(
+        StripeKeyring::from_settings(Some("rk_test_payments"), Some("rk_live_refunds"), None)
+            .is_err()
+    );
+    assert!(StripeKeyring::from_settings(Some(""), Some("  "), None).is_ok());
+}
+
+/// Stripe API paths requested through the recorder, with the key each request used.
+type KeyLog = Arc<Mutex<Vec<(String, String)>>>;
+
+/// Starts a proxy in front of stripe-mock that records the key of every request and
+/// forwards it with the harness's own key.
+async fn spawn_key_recorder(upstream: String) -> (String, KeyLog) {
+    let log = KeyLog::default();
+    let router = Router::new()
+        .fallback(
+            |State((upstream, log)): State<(String, KeyLog)>, request: Request| async move {
+                let method = request.method().clone();
+                let path = request.uri().path().to_string();
+                let target = format!(
+                    "{upstream}{}",
+                    request.uri().path_and_query().unwrap().as_str()
+                );
+                let key = request
+                    .headers()
+                    .get("authorization")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .unwrap_or_default()
+                    .to_string();
+                let content_type = request.headers().get("content-type").cloned();
+                log.lock().unwrap().push((path, key));
+
+                let body = to_bytes(request.into_body(), usize::MAX).await.unwrap();
+                let mut forwarded = reqwest::Client::new()
+                    .request(method, target)
+                    .bearer_auth("sk_test_123")
+                    .body(body);
+                if let Some(content_type) = content_type {
+                    forwarded = forwarded.header("content-type", content_type);
+                }
+                let response = forwarded.send().await.unwrap();
+                let status = axum::http::StatusCode::from_u16(response.status().as_u16()).unwrap();
+                let body = response.bytes().await.unwrap();
+                (status, [("content-type", "application/json")], body).into_response()
+            },
+        )
+        .with_state((upstream, log.clone()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    (format!("http://{addr}"), log)
+}
+
+/// The keys used for requests to Stripe paths starting with `prefix`.
+fn keys_for(log: &KeyLog, prefix: &str) -> Vec<String> {
+    let log = log.lock().unwrap();
+    let mut keys: Vec<String> = log
+        .iter()
+        .filter(|(path, _)| path.starts_with(prefix))
+        .map(|(_, key)| key.clone())
+        .collect();
+    keys.dedup();
+    keys
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn checkout_and_refunds_use_their_own_restricted_keys() {
+    std::env::set_var("STRIPE_PAYMENTS_KEY", "rk_test_payments");
+    std::env::set_var("STRIPE_REFUNDS_KEY", "rk_test_refunds");
+    let app = TestApp::spawn().await;
+    let (recorder, log) = spawn_key_recorder(std::env::var("STRIPE_API_BASE").unwrap()).await;
+    std::env::set_var("STRIPE_API_BASE", &recorder);
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+
+    let sheet = app
+        .http
+        .post(format!("{}/payment_sheet", app.base_url))
+        .json(&json!({
+            "customer_name": "Test Guardian",
+            "customer_email": "guardian@example.com",
+            "amount": seed.price,
+            "currency": "usd",
+            "metadata": {
+                "quote_id": seed.quote_id.to_string(),
+                "registration_ids": [seed.registration_id.to_string()],
+            },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(sheet.status(), 200);
+    let intent: String = registration_holds::table
+        .filter(registration_holds::registration_id.eq(seed.registration_id))
+        .select(registration_holds::payment_intent_id)
+        .first::<Option<String>>(&mut app.conn())
+        .unwrap()
+        .unwrap();
+    let paid = payment_intent_event(
+        "payment_intent.succeeded",
+        &intent,
+        seed.price,
+        "usd",
+        json!({
+            "quote_id": seed.quote_id.to_string(),
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&paid).await.status(), 200);
+
+    // Cancelling the session refunds the family
+    let cancelled = app
+        .admin(
+            Method::POST,
+            &format!("/admin/sessions/{}/cancel", seed.session_id),
+        )
+        .json(&json!({ "reason": "Lake closed for repairs" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cancelled.status(), 200);
+    let cancelled: Value = cancelled.json().await.unwrap();
+    assert_eq!(cancelled["first_batch"]["refunded"], 1);
+
+    assert_eq!(keys_for(&log, "/v1/customers"), ["rk_test_payments"]);
+    assert_eq!(keys_for(&log, "/v1/payment_intents"), ["rk_test_payments"]);
+    assert_eq!(keys_for(&log, "/v1/refunds"), ["rk_test_refunds"]);
+    // The full-access secret key is never sent
+    assert!(log
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|(_, key)| key.starts_with("rk_test_")));
+}
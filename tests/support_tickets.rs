@@ -0,0 +1,250 @@
+//! Tests for support ticket rules and the requests each ticketing provider sends,
+//! and for opening tickets from failed payments against Postgres.
+mod common;
+
+use axum::{extract::State, routing::post, Json, Router};
+use camp_registration_lambda::database::schema::support_tickets;
+use camp_registration_lambda::support_tickets::{
+    normalize_status, parse_rules, JiraTicketing, SupportTicket, SupportTickets, Ticketing,
+    WebhookTicketing, ZendeskTicketing,
+};
+use common::{payment_intent_event, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+fn ticket(family: &str) -> SupportTicket {
+    let rule = &parse_rules("repeated_payment_failures:payment_failed:2:168").unwrap()[0];
+    SupportTicket::new(
+        rule,
+        family,
+        vec!["pi_1".to_string(), "pi_2".to_string()],
+        2,
+    )
+}
+
+fn body(request: &reqwest::Request) -> Value {
+    serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap()
+}
+
+#[test]
+fn rules_are_parsed() {
+    let rules = parse_rules(
+        " repeated_payment_failures:payment_failed:2:168, stuck:requires_action|processing:3:24 ",
+    )
+    .unwrap();
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules[0].threshold, 2);
+    assert_eq!(rules[0].window_hours, 168);
+    assert_eq!(rules[1].statuses, vec!["requires_action", "processing"]);
+
+    assert!(parse_rules("missing_fields:payment_failed:2").is_err());
+    assert!(parse_rules("zero:payment_failed:0:24").is_err());
+    assert!(parse_rules("no_statuses::2:24").is_err());
+    assert!(parse_rules("bad name:payment_failed:2:24").is_err());
+    assert!(parse_rules("too_long:payment_failed:2:9000").is_err());
+    assert!(parse_rules("").unwrap().is_empty());
+}
+
+#[test]
+fn statuses_match_ignoring_case_and_underscores() {
+    assert_eq!(normalize_status("PaymentFailed"), "paymentfailed");
+    assert_eq!(normalize_status("payment_failed"), "paymentfailed");
+    let rule = &parse_rules("failures:payment_failed:2:168").unwrap()[0];
+    assert!(rule.matches("PaymentFailed"));
+    assert!(rule.matches("PAYMENT-FAILED"));
+    assert!(!rule.matches("succeeded"));
+}
+
+#[test]
+fn tickets_need_a_provider() {
+    let rules = parse_rules("failures:payment_failed:2:168").unwrap();
+    let disabled = SupportTickets::new(None, rules.clone(), 720);
+    assert!(!disabled.applies_to("payment_failed"));
+
+    let enabled = SupportTickets::new(
+        Some(Box::new(WebhookTicketing::new(
+            "https://hooks.example.com/tickets",
+        ))),
+        rules,
+        720,
+    );
+    assert!(enabled.applies_to("payment_failed"));
+    assert!(!enabled.applies_to("succeeded"));
+}
+
+#[test]
+fn webhook_posts_the_ticket() {
+    let provider = WebhookTicketing::new("https://hooks.example.com/tickets");
+    let request = provider
+        .request(&reqwest::Client::new(), &ticket("parent@example.com"))
+        .build()
+        .unwrap();
+    assert_eq!(request.method(), reqwest::Method::POST);
+    assert_eq!(request.url().as_str(), "https://hooks.example.com/tickets");
+    let sent = body(&request);
+    assert_eq!(sent["family"], "parent@example.com");
+    assert_eq!(sent["payment_intent_ids"], json!(["pi_1", "pi_2"]));
+    assert_eq!(sent["matching_events"], 2);
+    assert_eq!(
+        provider.reference(&json!({ "id": "T-17" })),
+        Some("T-17".to_string())
+    );
+    assert_eq!(provider.reference(&json!({})), None);
+}
+
+#[test]
+fn jira_creates_an_issue() {
+    let provider = JiraTicketing::new(
+        "https://camp.atlassian.net/",
+        "ops@example.com",
+        "secret-token",
+        "SUP",
+    );
+    let request = provider
+        .request(&reqwest::Client::new(), &ticket("parent@example.com"))
+        .build()
+        .unwrap();
+    assert_eq!(
+        request.url().as_str(),
+        "https://camp.atlassian.net/rest/api/2/issue"
+    );
+    assert!(request.headers()["authorization"]
+        .to_str()
+        .unwrap()
+        .starts_with("Basic "));
+    let sent = body(&request);
+    assert_eq!(sent["fields"]["project"]["key"], "SUP");
+    assert_eq!(
+        sent["fields"]["summary"],
+        "Payments keep failing for parent@example.com"
+    );
+    assert_eq!(
+        sent["fields"]["labels"],
+        json!(["payment-failure", "repeated_payment_failures"])
+    );
+    assert_eq!(
+        provider.reference(&json!({ "id": "10001", "key": "SUP-42" })),
+        Some("SUP-42".to_string())
+    );
+    // Credentials stay out of logs
+    assert!(!format!("{provider:?}").contains("secret-token"));
+}
+
+#[test]
+fn zendesk_creates_a_ticket_for_the_family() {
+    let provider = ZendeskTicketing::new("camp", "ops@example.com", "secret-token");
+    let request = provider
+        .request(&reqwest::Client::new(), &ticket("parent@example.com"))
+        .build()
+        .unwrap();
+    assert_eq!(
+        request.url().as_str(),
+        "https://camp.zendesk.com/api/v2/tickets.json"
+    );
+    let sent = body(&request);
+    assert_eq!(sent["ticket"]["requester"]["email"], "parent@example.com");
+    assert!(sent["ticket"]["comment"]["body"]
+        .as_str()
+        .unwrap()
+        .contains("pi_1, pi_2"));
+    assert_eq!(
+        provider.reference(&json!({ "ticket": { "id": 35436 } })),
+        Some("35436".to_string())
+    );
+
+    // Stripe customers cannot be requesters
+    let request = provider
+        .request(&reqwest::Client::new(), &ticket("cus_123"))
+        .build()
+        .unwrap();
+    assert!(body(&request)["ticket"].get("requester").is_none());
+    assert!(!format!("{provider:?}").contains("secret-token"));
+}
+
+/// Starts a ticketing webhook that records each ticket and answers with a reference.
+async fn spawn_ticket_desk() -> (String, mpsc::UnboundedReceiver<Value>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let router =
+        Router::new()
+            .route(
+                "/tickets",
+                post(
+                    |State(sender): State<mpsc::UnboundedSender<Value>>,
+                     Json(ticket): Json<Value>| async move {
+                        sender.send(ticket).ok();
+                        Json(json!({ "id": "T-17" }))
+                    },
+                ),
+            )
+            .with_state(sender);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    (format!("http://{addr}/tickets"), receiver)
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn repeated_failures_open_one_ticket_per_family() {
+    let (desk_url, mut tickets) = spawn_ticket_desk().await;
+    std::env::set_var("TICKETING_PROVIDER", "webhook");
+    std::env::set_var("TICKETING_WEBHOOK_URL", &desk_url);
+    let app = TestApp::spawn().await;
+    app.conn()
+        .batch_execute(
+            "INSERT INTO payment_sheet_attempts (customer_email, payment_intent_id, amount, currency)
+                 VALUES ('parent@example.com', 'pi_fail_1', 45000, 'usd'),
+                        ('parent@example.com', 'pi_fail_2', 45000, 'usd'),
+                        ('parent@example.com', 'pi_fail_3', 45000, 'usd');",
+        )
+        .unwrap();
+    let fail = |intent: &str| {
+        payment_intent_event(
+            "payment_intent.payment_failed",
+            intent,
+            45_000,
+            "usd",
+            json!({}),
+        )
+    };
+
+    // One failure is below the threshold
+    assert_eq!(app.post_webhook(&fail("pi_fail_1")).await.status(), 200);
+    assert!(tickets.try_recv().is_err());
+
+    assert_eq!(app.post_webhook(&fail("pi_fail_2")).await.status(), 200);
+    let ticket = tickets.try_recv().expect("a ticket was opened");
+    assert_eq!(ticket["family"], "parent@example.com");
+    assert_eq!(ticket["rule"], "repeated_payment_failures");
+    assert_eq!(
+        ticket["payment_intent_ids"],
+        json!(["pi_fail_1", "pi_fail_2"])
+    );
+    assert_eq!(ticket["matching_events"], 2);
+
+    // The family already has a ticket for the rule
+    assert_eq!(app.post_webhook(&fail("pi_fail_3")).await.status(), 200);
+    assert!(tickets.try_recv().is_err());
+
+    let recorded: Vec<(String, String, Option<String>)> = support_tickets::table
+        .select((
+            support_tickets::family,
+            support_tickets::status,
+            support_tickets::reference,
+        ))
+        .load(&mut app.conn())
+        .unwrap();
+    assert_eq!(
+        recorded,
+        [(
+            "parent@example.com".to_string(),
+            "opened".to_string(),
+            Some("T-17".to_string())
+        )]
+    );
+}
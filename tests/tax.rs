@@ -0,0 +1,232 @@
+//! Tests for tax receipt details and their PDF rendering, and against Postgres for
+//! dependent-care tax summaries, where the year's eligible payments are totalled per
+//! currency net of the refunds issued that year, and for printed receipts.
+mod common;
+
+use camp_registration_lambda::receipt_pdf;
+use camp_registration_lambda::tax::{normalize_ein, BillingAddress};
+use common::{payment_intent_event, seed_confirmed_registration, TestApp};
+use diesel::connection::SimpleConnection;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+fn address(country: &str) -> BillingAddress {
+    BillingAddress {
+        line1: " 418 Birch Hollow Rd ".to_string(),
+        line2: Some("  ".to_string()),
+        city: "Duluth".to_string(),
+        region: "MN".to_string(),
+        postal_code: "55803".to_string(),
+        country: country.to_string(),
+    }
+}
+
+#[test]
+fn eins_are_normalized() {
+    assert_eq!(normalize_ein("41-1234567").as_deref(), Some("41-1234567"));
+    assert_eq!(normalize_ein(" 411234567 ").as_deref(), Some("41-1234567"));
+    assert_eq!(normalize_ein("411-234567"), None);
+    assert_eq!(normalize_ein("41-123-4567"), None);
+    assert_eq!(normalize_ein("41-12345678"), None);
+}
+
+#[test]
+fn billing_addresses_are_normalized() {
+    let normalized = address("us").normalized().unwrap();
+    assert_eq!(normalized.line1, "418 Birch Hollow Rd");
+    assert_eq!(normalized.line2, None);
+    assert_eq!(normalized.country, "US");
+}
+
+#[test]
+fn incomplete_billing_addresses_are_rejected() {
+    assert!(address("USA").normalized().is_err());
+    let mut missing_city = address("US");
+    missing_city.city = " ".to_string();
+    assert_eq!(
+        missing_city.normalized().unwrap_err(),
+        "city is required".to_string()
+    );
+}
+
+#[test]
+fn receipt_pdfs_index_every_object() {
+    let lines: Vec<String> = (0..100).map(|n| format!("Line {n} (of 100)")).collect();
+    let pdf = String::from_utf8(receipt_pdf::render(&lines)).unwrap();
+    assert!(pdf.starts_with("%PDF-1.4\n"));
+    assert!(pdf.ends_with("%%EOF\n"));
+    assert!(pdf.contains("(Line 7 \\(of 100\\)) Tj"));
+    assert!(pdf.contains("/Count 3"));
+
+    let startxref: usize = pdf
+        .rsplit("startxref\n")
+        .next()
+        .and_then(|tail| tail.lines().next())
+        .and_then(|offset| offset.parse().ok())
+        .unwrap();
+    let table: Vec<&str> = pdf[startxref..].lines().collect();
+    assert_eq!(table[0], "xref");
+    let count: usize = table[1].split(' ').nth(1).unwrap().parse().unwrap();
+    // Every entry after the free one points at the start of its object
+    for (number, entry) in table[3..count + 2].iter().enumerate() {
+        let offset: usize = entry[..10].parse().unwrap();
+        assert!(pdf[offset..].starts_with(&format!("{} 0 obj\n", number + 1)));
+    }
+}
+
+#[test]
+fn receipt_pdfs_encode_latin1_text() {
+    let pdf = receipt_pdf::render(&["Zoë Åberg \u{2713}".to_string()]);
+    let pdf = String::from_utf8(pdf).unwrap();
+    assert!(pdf.contains("(Zo\\353 \\305berg ?) Tj"));
+}
+
+/// Records a succeeded payment event at `at` with `metadata`.
+fn record_payment(app: &TestApp, intent: &str, amount: i64, at: &str, metadata: Value) {
+    app.conn()
+        .batch_execute(&format!(
+            "INSERT INTO payment_events (payment_intent_id, status, created_at, amount, currency, metadata)
+             VALUES ('{intent}', 'succeeded', '{at}', {amount}, 'usd', '{metadata}');"
+        ))
+        .unwrap();
+}
+
+fn record_refund(app: &TestApp, intent: &str, amount: i64, at: &str) {
+    app.conn()
+        .batch_execute(&format!(
+            "INSERT INTO refunds (stripe_refund_id, payment_intent_id, amount, currency, status, created_at)
+             VALUES ('re_{}', '{intent}', {amount}, 'usd', 'succeeded', '{at}');",
+            Uuid::new_v4().simple()
+        ))
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn tax_summary_totals_the_years_payments_net_of_refunds() {
+    let app = TestApp::spawn().await;
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+    let other_family = seed_confirmed_registration(&mut app.conn(), 30_000);
+    let quoted = json!({ "quote_id": seed.quote_id.to_string() });
+
+    // The year's payment, recorded twice by a redelivered webhook
+    record_payment(
+        &app,
+        "pi_camp",
+        45_000,
+        "2026-03-02 10:00:00",
+        quoted.clone(),
+    );
+    record_payment(
+        &app,
+        "pi_camp",
+        45_000,
+        "2026-03-02 10:00:05",
+        quoted.clone(),
+    );
+    // Paid directly for the registration, listed after another family's
+    record_payment(
+        &app,
+        "pi_direct",
+        15_000,
+        "2026-06-01 08:00:00",
+        json!({ "registration_ids": format!("{}, {}", other_family.registration_id, seed.registration_id) }),
+    );
+    // Paid the year before, refunded partly this year
+    record_payment(&app, "pi_last_year", 20_000, "2025-11-20 09:00:00", quoted);
+    record_refund(&app, "pi_last_year", 5_000, "2026-01-15 12:00:00");
+    // Not childcare, and not this family's
+    record_payment(
+        &app,
+        "pi_voucher",
+        10_000,
+        "2026-04-01 08:00:00",
+        json!({ "purpose": "voucher", "quote_id": seed.quote_id.to_string() }),
+    );
+    record_payment(
+        &app,
+        "pi_other",
+        30_000,
+        "2026-05-01 08:00:00",
+        json!({ "quote_id": other_family.quote_id.to_string() }),
+    );
+
+    let response = app
+        .guardian(seed.guardian_id, Method::GET, "/me/tax_summary?year=2026")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let summary: Value = response.json().await.unwrap();
+
+    assert_eq!(
+        summary["totals"],
+        json!([{ "currency": "usd", "paid": 60_000, "refunded": 5_000, "eligible": 55_000 }])
+    );
+    let payments = summary["payments"].as_array().unwrap();
+    assert_eq!(payments.len(), 2);
+    assert_eq!(payments[0]["payment_intent_id"], "pi_camp");
+    assert_eq!(payments[0]["campers"], json!(["Sam Camper"]));
+    assert_eq!(payments[1]["payment_intent_id"], "pi_direct");
+    assert_eq!(payments[1]["campers"], json!(["Sam Camper"]));
+    assert_eq!(summary["refunds"][0]["payment_intent_id"], "pi_last_year");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn printed_receipts_carry_the_ein_and_billing_address() {
+    std::env::set_var("ORG_LEGAL_NAME", "Pine Lake Camp Inc.");
+    std::env::set_var("ORG_EIN", "411234567");
+    std::env::set_var("ORG_ADDRESS", "1 Lakeshore Dr, Ely MN 55731");
+    let app = TestApp::spawn().await;
+    for name in ["ORG_LEGAL_NAME", "ORG_EIN", "ORG_ADDRESS"] {
+        std::env::remove_var(name);
+    }
+    let seed = seed_confirmed_registration(&mut app.conn(), 45_000);
+
+    let saved = app
+        .guardian(seed.guardian_id, Method::PUT, "/me/billing_address")
+        .json(&address("us"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(saved.status(), 200);
+    let succeeded = payment_intent_event(
+        "payment_intent.succeeded",
+        "pi_printed",
+        45_000,
+        "usd",
+        json!({ "quote_id": seed.quote_id.to_string() }),
+    );
+    assert_eq!(app.post_webhook(&succeeded).await.status(), 200);
+
+    let response = app
+        .guardian(seed.guardian_id, Method::GET, "/receipts/pi_printed/pdf")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/pdf");
+    let pdf = String::from_utf8(response.bytes().await.unwrap().to_vec()).unwrap();
+    assert!(pdf.starts_with("%PDF-"));
+    for printed in [
+        "(Receipt R-000001) Tj",
+        "(Pine Lake Camp Inc.) Tj",
+        "(EIN 41-1234567) Tj",
+        "(418 Birch Hollow Rd) Tj",
+        "(Duluth, MN 55803) Tj",
+        "(Total paid: 450.00 USD) Tj",
+    ] {
+        assert!(pdf.contains(printed), "receipt is missing {printed}");
+    }
+
+    // Another family's guardian cannot print it
+    let other = seed_confirmed_registration(&mut app.conn(), 30_000);
+    let response = app
+        .guardian(other.guardian_id, Method::GET, "/receipts/pi_printed/pdf")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,256 @@
+//! Tests for when a waitlist offer can still be paid, and for waitlist offers against
+//! Postgres and stripe-mock: lapsed offers are expired by the `waitlist_offers` job
+//! and their seat offered to the next family, and a payment that lands after its
+//! offer expired is refunded.
+mod common;
+
+use camp_registration_lambda::database::models::WaitlistOffer;
+use camp_registration_lambda::database::schema::{
+    admin_alerts, cancellation_refunds, registrations, waitlist_entries, waitlist_offers,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use common::{payment_intent_event, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const PRICE: i64 = 45_000;
+
+/// A one-seat session whose seat is offered to the first family on its waitlist,
+/// with a second family waiting behind them.
+struct Waitlist {
+    offer_id: Uuid,
+    registration_id: Uuid,
+    quote_id: Uuid,
+    next_entry_id: Uuid,
+}
+
+fn seed_waitlist(app: &TestApp, expired_minutes_ago: i64, intent: Option<&str>) -> Waitlist {
+    let (guardian, first_camper, next_camper, session) = (
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+    );
+    let (first_entry, next_entry, registration, hold, offer, quote) = (
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+    );
+    let intent = intent.map_or("NULL".to_string(), |id| format!("'{id}'"));
+    app.conn()
+        .batch_execute(&format!(
+            r#"
+            INSERT INTO guardians (id, email, name)
+                VALUES ('{guardian}', '{guardian}@example.com', 'Test Guardian');
+            INSERT INTO campers (id, guardian_id, first_name, last_name, birthdate)
+                VALUES ('{first_camper}', '{guardian}', 'Sam', 'Camper', '2014-05-01'),
+                       ('{next_camper}', '{guardian}', 'Alex', 'Camper', '2015-03-02');
+            INSERT INTO camp_sessions (id, name, starts_on, ends_on, capacity, price, currency)
+                VALUES ('{session}', 'Week 1', '2027-07-05', '2027-07-09', 1, {PRICE}, 'usd');
+            INSERT INTO waitlist_entries (id, session_id, camper_id, guardian_id, status, created_at)
+                VALUES ('{first_entry}', '{session}', '{first_camper}', '{guardian}', 'offered',
+                        NOW() - INTERVAL '2 days'),
+                       ('{next_entry}', '{session}', '{next_camper}', '{guardian}', 'waiting',
+                        NOW() - INTERVAL '1 day');
+            INSERT INTO registrations (id, guardian_id, camper_id, session_id, status)
+                VALUES ('{registration}', '{guardian}', '{first_camper}', '{session}', 'pending');
+            INSERT INTO registration_holds (id, registration_id, session_id, expires_at)
+                VALUES ('{hold}', '{registration}', '{session}',
+                        NOW() - INTERVAL '{expired_minutes_ago} minutes');
+            INSERT INTO waitlist_offers
+                (id, waitlist_entry_id, registration_id, hold_id, token_hash, expires_at, payment_intent_id)
+                VALUES ('{offer}', '{first_entry}', '{registration}', '{hold}', '{offer}',
+                        NOW() - INTERVAL '{expired_minutes_ago} minutes', {intent});
+            INSERT INTO quotes (id, guardian_id, currency, subtotal, credit_applied, total, line_items, registration_ids)
+                VALUES ('{quote}', '{guardian}', 'usd', {PRICE}, 0, {PRICE}, '[]', ARRAY['{registration}']::UUID[]);
+            "#
+        ))
+        .expect("failed to seed waitlist");
+    Waitlist {
+        offer_id: offer,
+        registration_id: registration,
+        quote_id: quote,
+        next_entry_id: next_entry,
+    }
+}
+
+async fn run_sweep(app: &TestApp) -> Value {
+    let response = app
+        .admin(Method::POST, "/admin/jobs/waitlist_offers")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["summary"]["offers"].clone()
+}
+
+fn offer_status(app: &TestApp, offer: Uuid) -> String {
+    waitlist_offers::table
+        .find(offer)
+        .select(waitlist_offers::status)
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+fn registration_status(app: &TestApp, registration: Uuid) -> String {
+    registrations::table
+        .find(registration)
+        .select(registrations::status)
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+fn minutes_ago(minutes: i64) -> NaiveDateTime {
+    chrono::Utc::now().naive_utc() - chrono::Duration::minutes(minutes)
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn lapsed_offer_expires_and_the_seat_goes_to_the_next_family() {
+    let app = TestApp::spawn().await;
+    let line = seed_waitlist(&app, 5, None);
+
+    let sweep = run_sweep(&app).await;
+    assert_eq!(sweep["expired"], 1);
+    assert_eq!(sweep["offered"], 1);
+
+    assert_eq!(offer_status(&app, line.offer_id), "expired");
+    assert_eq!(registration_status(&app, line.registration_id), "cancelled");
+
+    let mut conn = app.conn();
+    let next_status: String = waitlist_entries::table
+        .find(line.next_entry_id)
+        .select(waitlist_entries::status)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(next_status, "offered");
+    let next_offer = waitlist_offers::table
+        .filter(waitlist_offers::waitlist_entry_id.eq(line.next_entry_id))
+        .first::<WaitlistOffer>(&mut conn)
+        .unwrap();
+    assert_eq!(next_offer.status, "open");
+    assert_eq!(
+        registration_status(&app, next_offer.registration_id),
+        "pending"
+    );
+
+    // A second run finds nothing left to do
+    let again = run_sweep(&app).await;
+    assert_eq!(again["expired"], 0);
+    assert_eq!(again["offered"], 0);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn started_payment_gets_a_grace_period_then_its_intent_is_cancelled() {
+    let app = TestApp::spawn().await;
+    let intent = format!("pi_{}", Uuid::new_v4().simple());
+
+    // Inside the grace period the offer stays open for the payment to settle
+    let line = seed_waitlist(&app, 5, Some(&intent));
+    let sweep = run_sweep(&app).await;
+    assert_eq!(sweep["expired"], 0);
+    assert_eq!(offer_status(&app, line.offer_id), "open");
+
+    diesel::update(waitlist_offers::table.find(line.offer_id))
+        .set(waitlist_offers::expires_at.eq(minutes_ago(45)))
+        .execute(&mut app.conn())
+        .unwrap();
+    let sweep = run_sweep(&app).await;
+    assert_eq!(sweep["expired"], 1);
+    assert_eq!(sweep["intents_cancelled"], 1);
+    assert_eq!(registration_status(&app, line.registration_id), "cancelled");
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn payment_landing_after_expiry_is_refunded_and_flagged() {
+    let app = TestApp::spawn().await;
+    let intent = format!("pi_{}", Uuid::new_v4().simple());
+    let line = seed_waitlist(&app, 45, Some(&intent));
+    run_sweep(&app).await;
+
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &intent,
+        PRICE,
+        "usd",
+        json!({
+            "quote_id": line.quote_id.to_string(),
+            "registration_ids": line.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    // The seat was given away; the registration stays cancelled
+    assert_eq!(registration_status(&app, line.registration_id), "cancelled");
+    let mut conn = app.conn();
+    let refund: (i64, String, String) = cancellation_refunds::table
+        .filter(cancellation_refunds::registration_id.eq(line.registration_id))
+        .select((
+            cancellation_refunds::amount,
+            cancellation_refunds::status,
+            cancellation_refunds::cause,
+        ))
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(
+        refund,
+        (PRICE, "pending".to_string(), "late_payment".to_string())
+    );
+    let alerts: Vec<String> = admin_alerts::table
+        .filter(admin_alerts::payment_intent_id.eq(&intent))
+        .select(admin_alerts::kind)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(alerts, vec!["late_payment".to_string()]);
+}
+
+fn at(hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2026, 6, 1)
+        .unwrap()
+        .and_hms_opt(hour, 0, 0)
+        .unwrap()
+}
+
+fn offer(status: &str) -> WaitlistOffer {
+    WaitlistOffer {
+        id: Uuid::new_v4(),
+        waitlist_entry_id: Uuid::new_v4(),
+        registration_id: Uuid::new_v4(),
+        hold_id: Uuid::new_v4(),
+        token_hash: "hash".to_string(),
+        status: status.to_string(),
+        expires_at: at(12),
+        payment_intent_id: None,
+        created_at: at(0),
+        resolved_at: None,
+    }
+}
+
+#[test]
+fn offers_lapse_with_their_hold() {
+    assert!(offer("open").is_open(at(11)));
+    assert!(!offer("open").is_open(at(12)));
+    assert!(!offer("open").is_open(at(13)));
+}
+
+#[test]
+fn resolved_offers_are_closed() {
+    assert!(!offer("paid").is_open(at(11)));
+    assert!(!offer("expired").is_open(at(11)));
+}
+
+#[test]
+fn token_hashes_are_not_serialized() {
+    let body = serde_json::to_value(offer("open")).unwrap();
+    assert!(body.get("token_hash").is_none());
+    assert_eq!(body["status"], "open");
+}
@@ -0,0 +1,122 @@
+//! Tests for the webhook event type coverage report, and for counting received
+//! events against Postgres.
+mod common;
+
+use camp_registration_lambda::database::models::WebhookEventCount;
+use camp_registration_lambda::webhook_coverage::{coverage, is_handled, HANDLED_EVENT_TYPES};
+use chrono::NaiveDate;
+use common::{payment_intent_event, TestApp};
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+
+fn count(event_type: &str, received: i64, filtered: i64) -> WebhookEventCount {
+    let at = NaiveDate::from_ymd_opt(2026, 6, 1)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+    WebhookEventCount {
+        event_type: event_type.to_string(),
+        received,
+        filtered,
+        first_received_at: at,
+        last_received_at: at,
+    }
+}
+
+#[test]
+fn payment_events_are_handled() {
+    assert!(is_handled("payment_intent.succeeded"));
+    assert!(is_handled("charge.refund.updated"));
+    assert!(!is_handled("customer.updated"));
+}
+
+#[test]
+fn unhandled_types_are_listed_by_volume() {
+    let report = coverage(vec![
+        count("customer.updated", 12, 0),
+        count("payment_intent.succeeded", 140, 0),
+        count("invoice.created", 30, 30),
+        count("charge.succeeded", 120, 0),
+    ]);
+
+    assert_eq!(report.total_received, 302);
+    assert_eq!(report.unhandled_received, 42);
+    let unhandled: Vec<&str> = report
+        .unhandled
+        .iter()
+        .map(|e| e.event_type.as_str())
+        .collect();
+    assert_eq!(unhandled, ["invoice.created", "customer.updated"]);
+    assert_eq!(report.unhandled[0].filtered, 30);
+    assert_eq!(report.handled[0].event_type, "payment_intent.succeeded");
+
+    assert_eq!(report.never_received.len(), HANDLED_EVENT_TYPES.len() - 2);
+    assert!(report.never_received.contains(&"payout.failed".to_string()));
+    assert!(!report
+        .never_received
+        .contains(&"charge.succeeded".to_string()));
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn received_events_are_counted_by_type() {
+    std::env::set_var("STRIPE_WEBHOOK_EVENT_DENYLIST", "invoice.*");
+    let app = TestApp::spawn().await;
+
+    let deliveries = [
+        ("payment_intent.created", 2),
+        ("customer.updated", 3),
+        ("invoice.created", 1),
+    ];
+    for (event_type, times) in deliveries {
+        for n in 0..times {
+            let payload =
+                payment_intent_event(event_type, &format!("pi_cov_{n}"), 45_000, "usd", json!({}));
+            assert_eq!(app.post_webhook(&payload).await.status(), StatusCode::OK);
+        }
+    }
+
+    let report: Value = app
+        .admin(Method::GET, "/admin/webhook_coverage")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["total_received"], 6);
+    assert_eq!(report["unhandled_received"], 4);
+    let counts = |key: &str| -> Vec<(String, i64, i64)> {
+        report[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["event_type"].as_str().unwrap().to_string(),
+                    entry["received"].as_i64().unwrap(),
+                    entry["filtered"].as_i64().unwrap(),
+                )
+            })
+            .collect()
+    };
+    assert_eq!(
+        counts("unhandled"),
+        [
+            ("customer.updated".to_string(), 3, 0),
+            ("invoice.created".to_string(), 1, 1),
+        ]
+    );
+    assert_eq!(
+        counts("handled"),
+        [("payment_intent.created".to_string(), 2, 0)]
+    );
+    let never_received: Vec<&str> = report["never_received"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t.as_str().unwrap())
+        .collect();
+    assert_eq!(never_received.len(), HANDLED_EVENT_TYPES.len() - 1);
+    assert!(!never_received.contains(&"payment_intent.created"));
+}
@@ -0,0 +1,231 @@
+//! Tests for the webhook's response to each class of processing failure, and for
+//! failing to confirm a paid registration in Postgres.
+mod common;
+
+use axum::http::StatusCode;
+use camp_registration_lambda::database::schema::{payment_events, registrations};
+use camp_registration_lambda::stripe_webhook::WebhookError;
+use camp_registration_lambda::webhook_failures::{
+    FailureClass, FailureResponse, Failures, WebhookFailureResponses,
+};
+use common::{payment_intent_event, seed_pending_registration, PendingRegistration, TestApp};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio::sync::Mutex;
+
+/// Held by the Postgres tests: they read `WEBHOOK_FAILURE_RESPONSES` at startup and
+/// compare the process-wide response counters.
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+#[test]
+fn database_failures_are_retried_by_default() {
+    let defaults = WebhookFailureResponses::parse("").unwrap();
+    assert_eq!(defaults, WebhookFailureResponses::default());
+    assert_eq!(
+        defaults.response_for(FailureClass::DatabaseUnavailable),
+        FailureResponse::Retry
+    );
+    assert_eq!(
+        defaults.response_for(FailureClass::Persistence),
+        FailureResponse::Retry
+    );
+    assert_eq!(
+        defaults.response_for(FailureClass::Notification),
+        FailureResponse::Acknowledge
+    );
+}
+
+#[test]
+fn responses_are_configured_per_class() {
+    let configured =
+        WebhookFailureResponses::parse(" Persistence:acknowledge, notification : RETRY ").unwrap();
+    assert_eq!(
+        configured.response_for(FailureClass::Persistence),
+        FailureResponse::Acknowledge
+    );
+    assert_eq!(
+        configured.response_for(FailureClass::Notification),
+        FailureResponse::Retry
+    );
+    assert_eq!(
+        configured.response_for(FailureClass::DatabaseUnavailable),
+        FailureResponse::Retry
+    );
+
+    assert!(WebhookFailureResponses::parse("persistence").is_err());
+    assert!(WebhookFailureResponses::parse("stripe:retry").is_err());
+    assert!(WebhookFailureResponses::parse("persistence:ignore").is_err());
+}
+
+#[test]
+fn any_retried_failure_asks_stripe_to_retry() {
+    let defaults = WebhookFailureResponses::default();
+    assert_eq!(defaults.resolve("evt_1", &Failures::default()), None);
+
+    let mut failures = Failures::default();
+    failures.record(FailureClass::Notification, "Failed to notify", "timeout");
+    assert_eq!(
+        defaults.resolve("evt_1", &failures),
+        Some(FailureResponse::Acknowledge)
+    );
+
+    failures.record(FailureClass::Persistence, "Failed to save", "deadlock");
+    failures.record(
+        FailureClass::Persistence,
+        "Failed to save again",
+        "deadlock",
+    );
+    assert_eq!(
+        failures.classes(),
+        &[FailureClass::Notification, FailureClass::Persistence]
+    );
+    assert_eq!(
+        defaults.resolve("evt_1", &failures),
+        Some(FailureResponse::Retry)
+    );
+
+    let lenient = WebhookFailureResponses::parse("persistence:acknowledge").unwrap();
+    assert_eq!(
+        lenient.resolve("evt_1", &failures),
+        Some(FailureResponse::Acknowledge)
+    );
+}
+
+#[test]
+fn processing_failures_are_answered_with_500() {
+    let error = WebhookError::ProcessingFailed;
+    assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(error.code(), "processing_failed");
+}
+
+/// Seeds a pending registration that Postgres refuses to confirm, and the event
+/// paying for it.
+fn seed_unconfirmable(app: &TestApp, intent: &str) -> (PendingRegistration, String) {
+    let mut conn = app.conn();
+    let seed = seed_pending_registration(&mut conn, 45_000);
+    conn.batch_execute(
+        "CREATE FUNCTION refuse_update() RETURNS trigger AS $$
+             BEGIN RAISE EXCEPTION 'registrations are read-only'; END
+         $$ LANGUAGE plpgsql;
+         CREATE TRIGGER refuse_update BEFORE UPDATE ON registrations
+             FOR EACH ROW EXECUTE FUNCTION refuse_update();",
+    )
+    .unwrap();
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        intent,
+        seed.price,
+        "usd",
+        json!({
+            "quote_id": seed.quote_id.to_string(),
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    (seed, payload)
+}
+
+fn registration_status(app: &TestApp, seed: &PendingRegistration) -> String {
+    registrations::table
+        .find(seed.registration_id)
+        .select(registrations::status)
+        .first(&mut app.conn())
+        .unwrap()
+}
+
+fn payment_event_count(app: &TestApp, intent: &str) -> i64 {
+    payment_events::table
+        .filter(payment_events::payment_intent_id.eq(intent))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap()
+}
+
+/// `webhook_responses_total` by outcome. Counters are shared by every app in the
+/// process, so tests compare them before and after.
+async fn response_outcomes(app: &TestApp) -> BTreeMap<String, u64> {
+    let metrics: Value = app
+        .admin(Method::GET, "/admin/metrics")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    metrics["counters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|counter| counter["name"] == "webhook_responses_total")
+        .map(|counter| {
+            (
+                counter["labels"]["outcome"].as_str().unwrap().to_string(),
+                counter["value"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn failed_confirmations_are_retried_unless_acknowledged() {
+    let _serial = SERIAL.lock().await;
+    // By default Stripe is asked to redeliver, and the redelivery repairs it
+    let app = TestApp::spawn().await;
+    let (seed, payload) = seed_unconfirmable(&app, "pi_retried");
+    let response = app.post_webhook(&payload).await;
+    assert_eq!(response.status(), 500);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "processing_failed");
+    assert_eq!(registration_status(&app, &seed), "pending");
+
+    app.conn()
+        .batch_execute("DROP TRIGGER refuse_update ON registrations;")
+        .unwrap();
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+    assert_eq!(registration_status(&app, &seed), "confirmed");
+    assert_eq!(payment_event_count(&app, "pi_retried"), 1);
+    drop(app);
+
+    // Configured to acknowledge, the failure is left to logs and metrics
+    std::env::set_var("WEBHOOK_FAILURE_RESPONSES", "persistence:acknowledge");
+    let app = TestApp::spawn().await;
+    std::env::remove_var("WEBHOOK_FAILURE_RESPONSES");
+    let (seed, payload) = seed_unconfirmable(&app, "pi_acknowledged");
+    let before = response_outcomes(&app).await;
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+    assert_eq!(registration_status(&app, &seed), "pending");
+    assert_eq!(payment_event_count(&app, "pi_acknowledged"), 1);
+
+    let after = response_outcomes(&app).await;
+    let delta = |outcome: &str| {
+        after.get(outcome).copied().unwrap_or(0) - before.get(outcome).copied().unwrap_or(0)
+    };
+    assert_eq!(delta("acknowledged"), 1);
+    assert_eq!(delta("processed"), 0);
+    assert_eq!(delta("retry"), 0);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn failed_confirmations_roll_back_the_whole_payment() {
+    use camp_registration_lambda::database::schema::receipt_numbers;
+
+    let _serial = SERIAL.lock().await;
+    let app = TestApp::spawn().await;
+    let (seed, payload) = seed_unconfirmable(&app, "pi_rolled_back");
+    assert_eq!(app.post_webhook(&payload).await.status(), 500);
+    assert_eq!(registration_status(&app, &seed), "pending");
+
+    // Neither the event nor its receipt number outlives the failed confirmation
+    assert_eq!(payment_event_count(&app, "pi_rolled_back"), 0);
+    let mut conn = app.conn();
+    let receipts: i64 = receipt_numbers::table
+        .filter(receipt_numbers::payment_intent_id.eq("pi_rolled_back"))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(receipts, 0);
+}
@@ -0,0 +1,257 @@
+//! Webhook → database → WebSocket flow, end to end against Postgres and stripe-mock.
+mod common;
+
+use camp_registration_lambda::database::schema::{
+    admin_alerts, notification_deliveries, payment_events, payment_intent_states,
+    registration_holds, registrations,
+};
+use common::{payment_intent_event, seed_pending_registration, TestApp};
+use diesel::prelude::*;
+use serde_json::json;
+use uuid::Uuid;
+
+fn intent_id() -> String {
+    format!("pi_{}", Uuid::new_v4().simple())
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn succeeded_webhook_confirms_registration_and_notifies_subscriber() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let intent = intent_id();
+    let mut ws = app.subscribe(&intent).await;
+
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &intent,
+        seed.price,
+        "usd",
+        json!({
+            "quote_id": seed.quote_id.to_string(),
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    let response = app.post_webhook(&payload).await;
+    assert_eq!(response.status(), 200);
+
+    let update = ws.expect_type("payment_update").await;
+    assert_eq!(update["payment_intent_id"], intent.as_str());
+    assert_eq!(update["amount"], seed.price);
+    let confirmed = ws.expect_type("registration_confirmed").await;
+    assert_eq!(
+        confirmed["registration_id"],
+        seed.registration_id.to_string()
+    );
+
+    let mut conn = app.conn();
+    let event_statuses: Vec<String> = payment_events::table
+        .filter(payment_events::payment_intent_id.eq(&intent))
+        .select(payment_events::status)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(event_statuses, vec!["succeeded".to_string()]);
+
+    let status: String = registrations::table
+        .find(seed.registration_id)
+        .select(registrations::status)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(status, "confirmed");
+
+    let open_holds: i64 = registration_holds::table
+        .filter(registration_holds::registration_id.eq(seed.registration_id))
+        .filter(registration_holds::released_at.is_null())
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(open_holds, 0);
+
+    let ws_deliveries: Vec<(String, String)> = notification_deliveries::table
+        .filter(notification_deliveries::payment_intent_id.eq(&intent))
+        .filter(notification_deliveries::channel.eq("websocket"))
+        .select((
+            notification_deliveries::template,
+            notification_deliveries::outcome,
+        ))
+        .order(notification_deliveries::created_at)
+        .load(&mut conn)
+        .unwrap();
+    assert!(ws_deliveries.contains(&("payment_update".to_string(), "sent".to_string())));
+    assert!(ws_deliveries.contains(&("registration_confirmed".to_string(), "sent".to_string())));
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn underpaid_webhook_holds_registration_for_review() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let intent = intent_id();
+
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &intent,
+        100,
+        "usd",
+        json!({
+            "quote_id": seed.quote_id.to_string(),
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    let mut conn = app.conn();
+    let status: String = registrations::table
+        .find(seed.registration_id)
+        .select(registrations::status)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(status, "payment_review");
+
+    let alert_kinds: Vec<String> = admin_alerts::table
+        .filter(admin_alerts::payment_intent_id.eq(&intent))
+        .select(admin_alerts::kind)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(alert_kinds, vec!["payment_amount_mismatch".to_string()]);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn redelivered_webhook_is_recorded_once() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+    let intent = intent_id();
+
+    let payload = payment_intent_event(
+        "payment_intent.succeeded",
+        &intent,
+        100,
+        "usd",
+        json!({
+            "quote_id": seed.quote_id.to_string(),
+            "registration_ids": seed.registration_id.to_string(),
+        }),
+    );
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+    assert_eq!(app.post_webhook(&payload).await.status(), 200);
+
+    let mut conn = app.conn();
+    let event_ids: Vec<Option<String>> = payment_events::table
+        .filter(payment_events::payment_intent_id.eq(&intent))
+        .select(payment_events::stripe_event_id)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(event_ids.len(), 1);
+    assert!(event_ids[0].as_deref().unwrap().starts_with("evt_"));
+
+    // The mismatch was flagged by the first delivery only
+    let alerts: i64 = admin_alerts::table
+        .filter(admin_alerts::payment_intent_id.eq(&intent))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(alerts, 1);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn webhook_with_bad_signature_is_rejected_without_side_effects() {
+    let app = TestApp::spawn().await;
+    let intent = intent_id();
+    let payload =
+        payment_intent_event("payment_intent.succeeded", &intent, 1_000, "usd", json!({}));
+
+    let response = app
+        .http
+        .post(format!("{}/webhook", app.base_url))
+        .header(
+            "stripe-signature",
+            common::sign_webhook(&payload, "whsec_wrong"),
+        )
+        .body(payload)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let stored: i64 = payment_events::table
+        .filter(payment_events::payment_intent_id.eq(&intent))
+        .count()
+        .get_result(&mut app.conn())
+        .unwrap();
+    assert_eq!(stored, 0);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn payment_sheet_links_registration_holds_to_the_intent() {
+    let app = TestApp::spawn().await;
+    let seed = seed_pending_registration(&mut app.conn(), 45_000);
+
+    let response = app
+        .http
+        .post(format!("{}/payment_sheet", app.base_url))
+        .json(&json!({
+            "customer_name": "Test Guardian",
+            "customer_email": "guardian@example.com",
+            "amount": seed.price,
+            "currency": "usd",
+            "metadata": {
+                "quote_id": seed.quote_id.to_string(),
+                "registration_ids": [seed.registration_id.to_string()],
+            },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["publishableKey"], "pk_test_123");
+    assert!(body["paymentIntent"].is_string());
+
+    let linked: Option<String> = registration_holds::table
+        .filter(registration_holds::registration_id.eq(seed.registration_id))
+        .select(registration_holds::payment_intent_id)
+        .first(&mut app.conn())
+        .unwrap();
+    assert!(linked.is_some_and(|id| id.starts_with("pi_")));
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn older_webhook_event_does_not_override_a_newer_one() {
+    let app = TestApp::spawn().await;
+    let intent = intent_id();
+
+    let succeeded =
+        payment_intent_event("payment_intent.succeeded", &intent, 1_000, "usd", json!({}));
+    assert_eq!(app.post_webhook(&succeeded).await.status(), 200);
+
+    // Stripe delivers the intent's creation after its success, stamped a minute earlier
+    let mut created: serde_json::Value = serde_json::from_str(&payment_intent_event(
+        "payment_intent.created",
+        &intent,
+        1_000,
+        "usd",
+        json!({}),
+    ))
+    .unwrap();
+    created["created"] = json!(chrono::Utc::now().timestamp() - 60);
+    assert_eq!(app.post_webhook(&created.to_string()).await.status(), 200);
+
+    let mut conn = app.conn();
+    let event_statuses: Vec<String> = payment_events::table
+        .filter(payment_events::payment_intent_id.eq(&intent))
+        .select(payment_events::status)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(event_statuses, vec!["succeeded".to_string()]);
+
+    let last_event_type: String = payment_intent_states::table
+        .find(&intent)
+        .select(payment_intent_states::last_event_type)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(last_event_type, "payment_intent.succeeded");
+}
@@ -0,0 +1,114 @@
+//! Shadow replay against Postgres: webhook deliveries are copied to the shadow
+//! endpoint after the primary handler responds, and disagreements are counted.
+mod common;
+
+use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+use common::{payment_intent_event, sign_webhook, TestApp, WEBHOOK_SECRET};
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// A delivery as the shadow endpoint received it.
+struct Replayed {
+    signature: Option<String>,
+    body: String,
+}
+
+/// Starts a shadow endpoint that records each delivery and fails it.
+async fn spawn_failing_shadow() -> (String, mpsc::UnboundedReceiver<Replayed>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let router = Router::new()
+        .route(
+            "/webhook",
+            post(
+                |State(sender): State<mpsc::UnboundedSender<Replayed>>,
+                 headers: HeaderMap,
+                 body: String| async move {
+                    let signature = headers
+                        .get("stripe-signature")
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string);
+                    sender.send(Replayed { signature, body }).ok();
+                    (StatusCode::INTERNAL_SERVER_ERROR, "shadow failed")
+                },
+            ),
+        )
+        .with_state(sender);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router).await.unwrap();
+    });
+    (format!("http://{addr}/webhook"), receiver)
+}
+
+async fn shadow_outcomes(app: &TestApp) -> Vec<(String, u64)> {
+    let metrics: Value = app
+        .admin(Method::GET, "/admin/metrics")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    metrics["counters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|counter| counter["name"] == "webhook_shadow_total")
+        .map(|counter| {
+            (
+                counter["labels"]["outcome"].as_str().unwrap().to_string(),
+                counter["value"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn deliveries_are_replayed_without_changing_the_response() {
+    let (shadow_url, mut replayed) = spawn_failing_shadow().await;
+    std::env::set_var("WEBHOOK_SHADOW_URL", &shadow_url);
+    let app = TestApp::spawn().await;
+
+    let payload = payment_intent_event(
+        "payment_intent.payment_failed",
+        "pi_shadowed",
+        12_000,
+        "usd",
+        json!({}),
+    );
+    let signature = sign_webhook(&payload, WEBHOOK_SECRET);
+    let response = app
+        .http
+        .post(format!("{}/webhook", app.base_url))
+        .header("stripe-signature", &signature)
+        .header("content-type", "application/json")
+        .body(payload.clone())
+        .send()
+        .await
+        .unwrap();
+    // The shadow's failure never reaches Stripe
+    assert_eq!(response.status(), 200);
+
+    let copy = tokio::time::timeout(Duration::from_secs(10), replayed.recv())
+        .await
+        .expect("shadow was never called")
+        .unwrap();
+    assert_eq!(copy.body, payload);
+    assert_eq!(copy.signature.as_deref(), Some(signature.as_str()));
+
+    // The comparison runs after the reply; wait for it to be counted
+    let mut outcomes = Vec::new();
+    for _ in 0..50 {
+        outcomes = shadow_outcomes(&app).await;
+        if !outcomes.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(outcomes, vec![("mismatch".to_string(), 1)]);
+}
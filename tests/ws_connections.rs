@@ -0,0 +1,159 @@
+//! Tests for the WebSocket connection inspector and admin disconnects.
+mod common;
+
+use camp_registration_lambda::database::models::WebSocketConnection;
+use camp_registration_lambda::database::schema::websocket_connections;
+use camp_registration_lambda::ws_connections::{
+    close_reason, summarize_connections, LiveConnections,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use common::TestApp;
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use reqwest::Method;
+use serde_json::Value;
+use uuid::Uuid;
+
+fn at(hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2026, 6, 1)
+        .unwrap()
+        .and_hms_opt(hour, minute, 0)
+        .unwrap()
+}
+
+fn row(
+    connection_id: &str,
+    payment_intent_id: &str,
+    created_at: NaiveDateTime,
+) -> WebSocketConnection {
+    WebSocketConnection {
+        id: Uuid::new_v4(),
+        payment_intent_id: payment_intent_id.to_string(),
+        connection_id: connection_id.to_string(),
+        created_at,
+        updated_at: created_at,
+        customer_id: None,
+        customer_email: Some("morgan@example.com".to_string()),
+        status: "active".to_string(),
+    }
+}
+
+#[test]
+fn subscriptions_are_grouped_by_connection() {
+    let live = LiveConnections::default();
+    let _close = live.open("conn-b");
+    let rows = vec![
+        row("conn-a", "pi_2", at(9, 30)),
+        row("conn-b", "pi_1", at(9, 10)),
+        row("conn-a", "pi_1", at(9, 20)),
+        row("conn-a", "pi_2", at(9, 40)),
+    ];
+
+    let connections = summarize_connections(rows, at(10, 0), &live);
+    assert_eq!(connections.len(), 2);
+    assert_eq!(connections[0].connection_id, "conn-b");
+    assert!(connections[0].live_here);
+    assert_eq!(connections[1].subscriptions, vec!["pi_1", "pi_2"]);
+    assert_eq!(connections[1].opened_at, at(9, 20));
+    assert_eq!(connections[1].age_seconds, 40 * 60);
+    assert!(!connections[1].live_here);
+}
+
+#[tokio::test]
+async fn disconnect_delivers_the_reason_to_a_live_socket() {
+    let live = LiveConnections::default();
+    let close = live.open("conn-a");
+    assert!(!live.disconnect("conn-b", "Stuck"));
+    assert!(live.disconnect("conn-a", "Stuck"));
+    assert_eq!(close.await.unwrap(), "Stuck");
+    assert!(!live.is_live("conn-a"));
+}
+
+#[test]
+fn close_reasons_fit_a_control_frame() {
+    assert_eq!(close_reason(None), "Disconnected by support");
+    assert_eq!(close_reason(Some("  ")), "Disconnected by support");
+    let long = "é".repeat(100);
+    let reason = close_reason(Some(&long));
+    assert!(reason.len() <= 123);
+    assert_eq!(reason.chars().count(), 61);
+}
+
+async fn list_connections(app: &TestApp) -> Vec<Value> {
+    let body: Value = app
+        .admin(Method::GET, "/admin/ws_connections")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["connections"].as_array().unwrap().clone()
+}
+
+async fn disconnect(app: &TestApp, connection_id: &str) -> reqwest::Response {
+    app.admin(
+        Method::DELETE,
+        &format!("/admin/ws_connections/{connection_id}?reason=Stuck%20client"),
+    )
+    .send()
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn support_can_list_and_kick_live_connections() {
+    let app = TestApp::spawn().await;
+    let mut client = app.subscribe("pi_stuck").await;
+
+    let connections = list_connections(&app).await;
+    assert_eq!(connections.len(), 1);
+    assert_eq!(
+        connections[0]["subscriptions"],
+        serde_json::json!(["pi_stuck"])
+    );
+    assert_eq!(connections[0]["live_here"], true);
+    let connection_id = connections[0]["connection_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = disconnect(&app, &connection_id).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["closed"], true);
+    assert_eq!(body["deactivated"], 1);
+    // Service restart: the client is expected to reconnect
+    assert_eq!(
+        client.expect_close().await,
+        (1012, "Stuck client".to_string())
+    );
+
+    assert!(list_connections(&app).await.is_empty());
+    assert_eq!(disconnect(&app, &connection_id).await.status(), 404);
+}
+
+#[tokio::test]
+#[ignore = "requires Docker"]
+async fn connections_held_elsewhere_are_only_marked_inactive() {
+    let app = TestApp::spawn().await;
+    app.conn()
+        .batch_execute(
+            "INSERT INTO websocket_connections (payment_intent_id, connection_id, status)
+             VALUES ('pi_elsewhere', 'conn-elsewhere', 'active');",
+        )
+        .unwrap();
+
+    let response = disconnect(&app, "conn-elsewhere").await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["closed"], false);
+    assert_eq!(body["deactivated"], 1);
+    let status: String = websocket_connections::table
+        .filter(websocket_connections::connection_id.eq("conn-elsewhere"))
+        .select(websocket_connections::status)
+        .first(&mut app.conn())
+        .unwrap();
+    assert_eq!(status, "inactive");
+}